@@ -0,0 +1,310 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Finds the indices that can't be out of range in the `//gos:nobounds`
+//! functions of a package, run on the package before its code is generated.
+//!
+//! In `for i := range s { ... s[i] ... }` the index is less than the length
+//! of the slice ranged over. It's less than the length of `s` as long as
+//! neither `s` nor `i` changes: they are locals of the function that are not
+//! assigned once declared, incremented, taken the address of, used as the
+//! receiver of a method, or captured by a function literal. The elements of
+//! `s` may change, its length can't.
+
+use goscript_parser::ast::BlockStmt;
+use goscript_parser::ast::{Decl, Expr, FuncDecl, Pragma, Spec, Stmt};
+use goscript_parser::objects::{IdentKey, Objects as AstObjects};
+use goscript_parser::token::Token;
+use goscript_types::{ObjKey as TCObjKey, TypeInfo};
+use std::collections::HashSet;
+
+struct Walker<'a> {
+    ast_objs: &'a AstObjects,
+    ti: &'a TypeInfo,
+    /// the variables declared by the function, outside function literals
+    locals: HashSet<TCObjKey>,
+    /// the variables that may change once declared
+    changed: HashSet<TCObjKey>,
+    /// how many function literals the walk is in
+    lit_depth: usize,
+    /// false while the variables are collected, the loops are looked into
+    /// once they are all known
+    checking: bool,
+    /// the slices and indices of the loops being walked
+    loops: Vec<(TCObjKey, TCObjKey)>,
+    in_range: HashSet<IdentKey>,
+}
+
+/// Returns the identifiers of the indices, in the `//gos:nobounds`
+/// functions, that are always less than the length of the slice indexed
+pub fn in_range_indices(ast_objs: &AstObjects, ti: &TypeInfo) -> HashSet<IdentKey> {
+    let mut in_range = HashSet::new();
+    for d in ti.ast_files.iter().flat_map(|f| f.decls.iter()) {
+        let fdecl = match d {
+            Decl::Func(fkey) => &ast_objs.fdecls[*fkey],
+            _ => continue,
+        };
+        if let (true, Some(body)) = (fdecl.pragmas.contains(&Pragma::NoBounds), &fdecl.body) {
+            let mut w = Walker::new(ast_objs, ti);
+            w.func_decl(fdecl, body);
+            w.checking = true;
+            w.stmts(&body.list);
+            in_range.extend(w.in_range);
+        }
+    }
+    in_range
+}
+
+impl<'a> Walker<'a> {
+    fn new(ast_objs: &'a AstObjects, ti: &'a TypeInfo) -> Walker<'a> {
+        Walker {
+            ast_objs: ast_objs,
+            ti: ti,
+            locals: HashSet::new(),
+            changed: HashSet::new(),
+            lit_depth: 0,
+            checking: false,
+            loops: vec![],
+            in_range: HashSet::new(),
+        }
+    }
+
+    fn func_decl(&mut self, fdecl: &FuncDecl, body: &BlockStmt) {
+        let objs = self.ast_objs;
+        let ftype = &objs.ftypes[fdecl.typ];
+        let fields = fdecl.recv.iter().chain(Some(&ftype.params));
+        for fkey in fields.flat_map(|l| l.list.iter()) {
+            for ikey in objs.fields[*fkey].names.iter() {
+                self.declare(ikey);
+            }
+        }
+        self.stmts(&body.list);
+    }
+
+    fn declare(&mut self, ikey: &IdentKey) {
+        if let Some(Some(okey)) = self.ti.defs.get(ikey) {
+            if self.lit_depth == 0 {
+                self.locals.insert(*okey);
+            }
+        }
+    }
+
+    /// `expr` is assigned to, or may be through a pointer
+    fn change(&mut self, expr: &Expr) {
+        match unparen(expr) {
+            Expr::Ident(ikey) => {
+                if let Some(okey) = self.object(ikey) {
+                    self.changed.insert(okey);
+                }
+            }
+            e => self.expr(e),
+        }
+    }
+
+    fn stmts(&mut self, stmts: &[Stmt]) {
+        for s in stmts.iter() {
+            self.stmt(s);
+        }
+    }
+
+    fn opt_stmt(&mut self, stmt: &Option<Stmt>) {
+        if let Some(s) = stmt {
+            self.stmt(s);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        let objs = self.ast_objs;
+        match stmt {
+            Stmt::Decl(d) => {
+                if let Decl::Gen(gdecl) = d.as_ref() {
+                    for skey in gdecl.specs.iter() {
+                        if let Spec::Value(vs) = &objs.specs[*skey] {
+                            for ikey in vs.names.iter() {
+                                self.declare(ikey);
+                            }
+                            self.exprs(&vs.values);
+                        }
+                    }
+                }
+            }
+            Stmt::Labeled(lkey) => self.stmt(&objs.l_stmts[*lkey].stmt),
+            Stmt::Expr(e) => self.expr(e),
+            Stmt::Send(s) => {
+                self.expr(&s.chan);
+                self.expr(&s.val);
+            }
+            Stmt::IncDec(s) => self.change(&s.expr),
+            Stmt::Assign(akey) => {
+                let astmt = &objs.a_stmts[*akey];
+                for e in astmt.lhs.iter() {
+                    match e {
+                        // the variables redeclared are assigned
+                        Expr::Ident(ikey)
+                            if astmt.token == Token::DEFINE
+                                && matches!(self.ti.defs.get(ikey), Some(Some(_))) =>
+                        {
+                            self.declare(ikey)
+                        }
+                        _ => self.change(e),
+                    }
+                }
+                self.exprs(&astmt.rhs);
+            }
+            Stmt::Go(s) => self.expr(&s.call),
+            Stmt::Defer(s) => self.expr(&s.call),
+            Stmt::Return(s) => self.exprs(&s.results),
+            Stmt::Block(s) => self.stmts(&s.list),
+            Stmt::If(s) => {
+                self.opt_stmt(&s.init);
+                self.expr(&s.cond);
+                self.stmts(&s.body.list);
+                self.opt_stmt(&s.els);
+            }
+            Stmt::Case(s) => {
+                if let Some(l) = &s.list {
+                    self.exprs(l);
+                }
+                self.stmts(&s.body);
+            }
+            Stmt::Switch(s) => {
+                self.opt_stmt(&s.init);
+                if let Some(tag) = &s.tag {
+                    self.expr(tag);
+                }
+                self.stmts(&s.body.list);
+            }
+            Stmt::TypeSwitch(s) => {
+                self.opt_stmt(&s.init);
+                self.stmt(&s.assign);
+                self.stmts(&s.body.list);
+            }
+            Stmt::Comm(s) => {
+                self.opt_stmt(&s.comm);
+                self.stmts(&s.body);
+            }
+            Stmt::Select(s) => self.stmts(&s.body.list),
+            Stmt::For(s) => {
+                self.opt_stmt(&s.init);
+                if let Some(cond) = &s.cond {
+                    self.expr(cond);
+                }
+                self.opt_stmt(&s.post);
+                self.stmts(&s.body.list);
+            }
+            Stmt::Range(s) => {
+                for e in [&s.key, &s.val].iter().filter_map(|e| e.as_ref()) {
+                    match e {
+                        Expr::Ident(ikey) if s.token == Token::DEFINE => self.declare(ikey),
+                        _ => self.change(e),
+                    }
+                }
+                self.expr(&s.expr);
+                let checked = self.checked_loop(&s.token, &s.key, &s.expr);
+                if let Some(l) = checked {
+                    self.loops.push(l);
+                }
+                self.stmts(&s.body.list);
+                if checked.is_some() {
+                    self.loops.pop();
+                }
+            }
+            Stmt::Bad(_) | Stmt::Empty(_) | Stmt::Branch(_) => {}
+        }
+    }
+
+    /// The slice and the index of `for i := range s`, if neither changes
+    fn checked_loop(
+        &self,
+        token: &Token,
+        key: &Option<Expr>,
+        expr: &Expr,
+    ) -> Option<(TCObjKey, TCObjKey)> {
+        if !self.checking || *token != Token::DEFINE {
+            return None;
+        }
+        let (i, s) = match (key, unparen(expr)) {
+            (Some(Expr::Ident(i)), Expr::Ident(s)) => (self.object(i)?, self.object(s)?),
+            _ => return None,
+        };
+        let fixed = |okey| self.locals.contains(okey) && !self.changed.contains(okey);
+        (fixed(&i) && fixed(&s)).then(|| (s, i))
+    }
+
+    fn exprs(&mut self, exprs: &[Expr]) {
+        for e in exprs.iter() {
+            self.expr(e);
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Ident(ikey) => {
+                // captured by the literal
+                if self.lit_depth > 0 {
+                    if let Some(okey) = self.object(ikey) {
+                        self.changed.insert(okey);
+                    }
+                }
+            }
+            Expr::FuncLit(flit) => {
+                self.lit_depth += 1;
+                self.stmts(&flit.body.list);
+                self.lit_depth -= 1;
+            }
+            Expr::CompositeLit(clit) => self.exprs(&clit.elts),
+            Expr::Paren(e) => self.expr(&e.expr),
+            // a method with a pointer receiver takes the address
+            Expr::Selector(e) => self.change(&e.expr),
+            Expr::Index(e) => {
+                if let (Expr::Ident(s), Expr::Ident(i)) = (unparen(&e.expr), &e.index) {
+                    let pair = (self.object(s), self.object(i));
+                    if self.loops.iter().any(|&(s, i)| pair == (Some(s), Some(i))) {
+                        self.in_range.insert(*i);
+                    }
+                }
+                self.expr(&e.expr);
+                self.expr(&e.index);
+            }
+            Expr::Slice(e) => {
+                self.expr(&e.expr);
+                for x in [&e.low, &e.high, &e.max].iter().filter_map(|x| x.as_ref()) {
+                    self.expr(x);
+                }
+            }
+            Expr::TypeAssert(e) => self.expr(&e.expr),
+            Expr::Call(e) => {
+                self.expr(&e.func);
+                self.exprs(&e.args);
+            }
+            Expr::Star(e) => self.expr(&e.expr),
+            Expr::Unary(e) if e.op == Token::AND => self.change(&e.expr),
+            Expr::Unary(e) => self.expr(&e.expr),
+            Expr::Binary(e) => {
+                self.expr(&e.expr_a);
+                self.expr(&e.expr_b);
+            }
+            Expr::KeyValue(e) => {
+                self.expr(&e.key);
+                self.expr(&e.val);
+            }
+            _ => {}
+        }
+    }
+
+    fn object(&self, ikey: &IdentKey) -> Option<TCObjKey> {
+        self.ti
+            .uses
+            .get(ikey)
+            .copied()
+            .or_else(|| self.ti.defs.get(ikey).copied().flatten())
+    }
+}
+
+fn unparen(expr: &Expr) -> &Expr {
+    match expr {
+        Expr::Paren(p) => unparen(&p.expr),
+        _ => expr,
+    }
+}
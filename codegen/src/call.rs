@@ -7,9 +7,13 @@
 /// we need to path them after codegen is all done.
 ///
 ///
+use goscript_parser::ast::{Decl, Pragma};
+use goscript_parser::objects::Objects as AstObjects;
+use goscript_types::{ObjKey as TCObjKey, TypeInfo};
 use goscript_vm::instruction::{Instruction, OpIndex};
 use goscript_vm::metadata::Meta;
 use goscript_vm::value::{key_to_u64, FunctionKey, VMObjects};
+use std::collections::HashSet;
 
 struct CallPoint {
     func: FunctionKey,
@@ -20,11 +24,36 @@ struct CallPoint {
 
 pub struct CallHelper {
     calls: Vec<CallPoint>,
+    // the `//gos:pure` functions of all the packages
+    pure: HashSet<TCObjKey>,
 }
 
 impl CallHelper {
     pub fn new() -> CallHelper {
-        CallHelper { calls: vec![] }
+        CallHelper {
+            calls: vec![],
+            pure: HashSet::new(),
+        }
+    }
+
+    /// Records the `//gos:pure` functions of a package, before the code of
+    /// any package is generated
+    pub fn add_pure_funcs(&mut self, ast_objs: &AstObjects, ti: &TypeInfo) {
+        for decl in ti.ast_files.iter().flat_map(|f| f.decls.iter()) {
+            if let Decl::Func(fkey) = decl {
+                let fdecl = &ast_objs.fdecls[*fkey];
+                if fdecl.recv.is_none() && fdecl.pragmas.contains(&Pragma::Pure) {
+                    if let Some(Some(okey)) = ti.defs.get(&fdecl.name) {
+                        self.pure.insert(*okey);
+                    }
+                }
+            }
+        }
+    }
+
+    #[inline]
+    pub fn is_pure(&self, okey: TCObjKey) -> bool {
+        self.pure.contains(&okey)
     }
 
     pub fn add_call(&mut self, func: FunctionKey, point: usize, meta: Meta, index: OpIndex) {
@@ -3,12 +3,15 @@
 // license that can be found in the LICENSE file.
 
 use slotmap::KeyData;
+use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
 use std::iter::FromIterator;
 
+use super::bounds;
 use super::branch::*;
 use super::call::CallHelper;
 use super::emit::*;
+use super::escape;
 use super::interface::IfaceMapping;
 use super::package::PkgHelper;
 use super::types::{SelectionType, TypeCache, TypeLookup};
@@ -26,8 +29,8 @@ use goscript_parser::position::Pos;
 use goscript_parser::token::Token;
 use goscript_parser::visitor::{walk_decl, walk_expr, walk_stmt, ExprVisitor, StmtVisitor};
 use goscript_types::{
-    identical_ignore_tags, Builtin, ObjKey as TCObjKey, OperandMode, PackageKey as TCPackageKey,
-    TCObjects, Type, TypeInfo, TypeKey as TCTypeKey,
+    identical_ignore_tags, Builtin, ConstValue, ObjKey as TCObjKey, OperandMode,
+    PackageKey as TCPackageKey, TCObjects, Type, TypeInfo, TypeKey as TCTypeKey,
 };
 
 macro_rules! current_func_mut {
@@ -78,6 +81,13 @@ pub struct CodeGen<'a> {
     func_stack: Vec<FunctionKey>,
     func_t_stack: Vec<TCTypeKey>, // for casting return values to interfaces
     blank_ident: IdentKey,
+    // for the names of the functions
+    pkg_name: String,
+    func_lit_count: HashMap<FunctionKey, usize>,
+    // the locals whose addresses don't escape where they are taken
+    local_refs: HashSet<IdentKey>,
+    // the indices proved in range in the //gos:nobounds functions
+    in_range: HashSet<IdentKey>,
 }
 
 impl<'a> CodeGen<'a> {
@@ -109,6 +119,10 @@ impl<'a> CodeGen<'a> {
             func_stack: Vec::new(),
             func_t_stack: Vec::new(),
             blank_ident: bk,
+            pkg_name: String::new(),
+            func_lit_count: HashMap::new(),
+            local_refs: HashSet::new(),
+            in_range: HashSet::new(),
         }
     }
 
@@ -197,13 +211,14 @@ impl<'a> CodeGen<'a> {
         }
         if is_def {
             let tc_obj = self.t.object_def(*ikey);
-            let (index, tc_type, _) = self.add_local_var(tc_obj);
-            let func = current_func_mut!(self);
-            if func.is_ctor() {
-                let pkg_key = func.package;
-                let pkg = &mut self.objects.packages[pkg_key];
-                pkg.add_var_mapping(ident.name.clone(), index.into());
+            if current_func!(self).is_ctor() {
+                // package vars are stored in the package as they are
+                // initialized, as functions called by later initializers
+                // may read them
+                let index = self.pkg_helper.get_member_index(tc_obj, *ikey);
+                return (index, Some(self.t.obj_tc_type(tc_obj)), pos);
             }
+            let (index, tc_type, _) = self.add_local_var(tc_obj);
             (index, Some(tc_type), pos)
         } else {
             let index = self.resolve_var_ident(ikey);
@@ -222,6 +237,9 @@ impl<'a> CodeGen<'a> {
         let ident_key = Some(okey.into());
         let index = func.add_local(ident_key);
         func.add_local_zero(zero_val, meta.value_type(&self.objects.metas));
+        if let Some(var) = self.t.local_var(okey, index.into()) {
+            func.add_local_var(var);
+        }
         (index, tc_type, meta)
     }
 
@@ -273,30 +291,33 @@ impl<'a> CodeGen<'a> {
                     }
                     Expr::Index(ind_expr) => {
                         let obj = &ind_expr.as_ref().expr;
-                        self.visit_expr(obj);
-                        let obj_typ = self.t.expr_value_type(obj);
+                        let obj_typ = self.visit_container_expr(obj);
                         let ind = &ind_expr.as_ref().index;
                         let pos = ind_expr.as_ref().l_brack;
 
                         let mut index_const = None;
                         let mut index_typ = None;
-                        if let Some(const_val) = self.t.try_tc_const_value(ind.id()) {
-                            let (ival, _) = const_val.to_int().int_as_i64();
-                            if let Ok(i) = OpIndex::try_from(ival) {
-                                index_const = Some(i);
+                        let unchecked = self.unchecked_index(obj_typ, ind);
+                        if obj_typ != ValueType::Map && !unchecked {
+                            if let Some(const_val) = self.t.try_tc_const_value(ind.id()) {
+                                if let (ival, true) = const_val.to_int().int_as_i64() {
+                                    if let Ok(i) = OpIndex::try_from(ival) {
+                                        index_const = Some(i);
+                                    }
+                                }
                             }
                         }
                         if index_const.is_none() {
                             self.visit_expr(ind);
-                            index_typ = Some(self.t.expr_value_type(ind));
+                            index_typ = Some(match obj_typ {
+                                ValueType::Map => self.cast_map_key(obj, ind),
+                                _ => self.t.expr_value_type(ind),
+                            });
                         }
+                        let mut info = IndexLhsInfo::new(0, index_const, obj_typ, index_typ);
+                        info.unchecked = unchecked;
                         (
-                            LeftHandSide::IndexExpr(IndexLhsInfo::new(
-                                0,
-                                index_const,
-                                obj_typ,
-                                index_typ,
-                            )), // the true index will be calculated later
+                            LeftHandSide::IndexExpr(info), // the true index will be calculated later
                             Some(self.t.expr_tc_type(expr)),
                             pos,
                         )
@@ -485,7 +506,7 @@ impl<'a> CodeGen<'a> {
             }
             RightHandSide::Range(r) => {
                 // the range statement
-                self.visit_expr(r);
+                self.visit_container_expr(r);
                 let tkv = self.t.expr_range_tc_types(r);
                 let types = [
                     Some(self.t.tc_type_to_value_type(tkv[0])),
@@ -618,10 +639,18 @@ impl<'a> CodeGen<'a> {
             None => {}
         };
 
-        // If this is SHL/SHR,  cast the rhs to uint32
+        // If this is SHL/SHR,  cast the rhs to uint32, FlagA makes the cast
+        // saturate, and panic if the count is negative
         if let Some(t) = op.1 {
             if t != ValueType::Uint32 {
-                current_func_emitter!(self).emit_cast(ValueType::Uint32, t, None, -1, 0, pos);
+                current_func_emitter!(self).emit_cast(
+                    ValueType::Uint32,
+                    t,
+                    Some(ValueType::FlagA),
+                    -1,
+                    0,
+                    pos,
+                );
                 *on_stack_types.last_mut().unwrap() = ValueType::Uint32;
             }
         }
@@ -744,12 +773,109 @@ impl<'a> CodeGen<'a> {
         }
     }
 
+    fn gen_type_switch_body(
+        &mut self,
+        body: &BlockStmt,
+        tag: EntIndex,
+        vars: Option<Vec<(EntIndex, ValueType)>>,
+        pos: Option<usize>,
+    ) {
+        let mut helper = SwitchHelper::new();
+        let mut has_default = false;
+        // the metadata of the case if the clause has exactly one non-nil case
+        let mut clause_metas: Vec<Option<Meta>> = vec![];
+        for (i, stmt) in body.list.iter().enumerate() {
+            helper.add_case_clause();
+            let cc = SwitchHelper::to_case_clause(stmt);
+            let mut single = None;
+            match &cc.list {
+                Some(l) => {
+                    for c in l.iter() {
+                        let pos = Some(stmt.pos(&self.ast_objs));
+                        let nil = self.t.expr_value_type(c) == ValueType::Void;
+                        let meta = (!nil).then(|| {
+                            let t = self.t.expr_tc_type(c);
+                            self.t.tc_type_to_meta(t, self.objects, self.dummy_gcv)
+                        });
+                        if l.len() == 1 {
+                            single = meta;
+                        }
+                        let func = current_func_mut!(self);
+                        let mut emitter = Emitter::new(func);
+                        emitter.emit_load(tag, None, ValueType::Interface, pos);
+                        match meta {
+                            Some(m) => {
+                                let index = func.add_const(None, GosValue::new_metadata(m));
+                                func.emit_code_with_imm(Opcode::TYPE, index.into(), pos);
+                            }
+                            None => {
+                                func.emit_code(Opcode::PUSH_NIL, pos);
+                                func.emit_code_with_type(Opcode::EQL, ValueType::Interface, pos);
+                            }
+                        }
+                        helper.tags.add_case(i, func.next_code_index());
+                        func.emit_code(Opcode::JUMP_IF, pos);
+                    }
+                }
+                None => has_default = true,
+            }
+            clause_metas.push(single);
+        }
+
+        let func = current_func_mut!(self);
+        helper.tags.add_default(func.next_code_index());
+        func.emit_code(Opcode::JUMP, None);
+
+        for (i, stmt) in body.list.iter().enumerate() {
+            let cc = SwitchHelper::to_case_clause(stmt);
+            let func = current_func_mut!(self);
+            let default = cc.list.is_none();
+            if default {
+                helper.tags.patch_default(func, func.next_code_index());
+            } else {
+                helper.tags.patch_case(func, i, func.next_code_index());
+            }
+            if let Some(vars) = &vars {
+                let (var, typ) = vars[i];
+                let mut emitter = Emitter::new(func);
+                emitter.emit_load(tag, None, ValueType::Interface, pos);
+                if let Some(m) = clause_metas[i] {
+                    let index = func.add_const(None, GosValue::new_metadata(m));
+                    func.emit_code_with_flag_imm(Opcode::TYPE_ASSERT, false, index.into(), pos);
+                }
+                let mut emitter = Emitter::new(func);
+                let lhs = LeftHandSide::Primitive(var);
+                emitter.emit_store(&lhs, -1, None, None, typ, pos);
+                emitter.emit_pop(&[typ], pos);
+            }
+            for s in cc.body.iter() {
+                self.visit_stmt(s);
+            }
+            let func = current_func_mut!(self);
+            if default {
+                helper.ends.add_default(func.next_code_index());
+            } else {
+                helper.ends.add_case(i, func.next_code_index());
+            }
+            func.emit_code(Opcode::JUMP, None);
+        }
+        let end = current_func!(self).next_code_index();
+        helper.patch_ends(current_func_mut!(self), end);
+        // jump to the end if there is no default code
+        if !has_default {
+            let func = current_func_mut!(self);
+            helper.tags.patch_default(func, end);
+        }
+    }
+
     fn gen_func_def(
         &mut self,
+        name: String,
         tc_type: TCTypeKey, // Meta,
         fkey: FuncTypeKey,
         recv: Option<FieldList>,
         body: &BlockStmt,
+        pragmas: &[Pragma],
     ) -> FunctionKey {
         let typ = &self.ast_objs.ftypes[fkey];
         let fmeta = self
@@ -763,6 +889,8 @@ impl<'a> CodeGen<'a> {
             FuncFlag::Default,
         );
         let fkey = *f.as_function();
+        self.objects.functions[fkey].name = name;
+        self.objects.functions[fkey].keep_code = pragmas.contains(&Pragma::NoOpt);
         let mut emitter = Emitter::new(&mut self.objects.functions[fkey]);
         if let Some(fl) = &typ.results {
             emitter.add_params(&fl, self.ast_objs, &self.t);
@@ -775,6 +903,15 @@ impl<'a> CodeGen<'a> {
             }
             None => emitter.add_params(&typ.params, self.ast_objs, &self.t),
         };
+        for p in pragmas.iter() {
+            match p {
+                Pragma::Timeout(d) => emitter.emit_budget(*d, Some(body.l_brace)),
+                Pragma::Memoize(cap) => emitter.emit_memoize(*cap, Some(body.l_brace)),
+                // the others are honored above, by the index expressions or
+                // at the call sites
+                Pragma::NoOpt | Pragma::NoBounds | Pragma::Pure => {}
+            }
+        }
         self.func_stack.push(fkey);
         self.func_t_stack.push(tc_type);
         // process function body
@@ -789,6 +926,178 @@ impl<'a> CodeGen<'a> {
         fkey
     }
 
+    /// The name of a method in stack traces, like `main.T.M` or `main.(*T).M`
+    fn method_name(&self, recv: TCTypeKey, method: &str) -> String {
+        let (base, ptr) = match self.tc_objs.types[recv].try_as_pointer() {
+            Some(p) => (p.base(), true),
+            None => (recv, false),
+        };
+        let obj = self.tc_objs.types[base]
+            .try_as_named()
+            .and_then(|n| *n.obj());
+        let (pkg, typ) = match obj.map(|o| &self.tc_objs.lobjs[o]) {
+            Some(o) => (
+                o.pkg().and_then(|p| self.tc_objs.pkgs[p].name().clone()),
+                o.name().as_str(),
+            ),
+            None => (None, "?"),
+        };
+        let pkg = pkg.as_ref().unwrap_or(&self.pkg_name);
+        if ptr {
+            format!("{}.(*{}).{}", pkg, typ, method)
+        } else {
+            format!("{}.{}.{}", pkg, typ, method)
+        }
+    }
+
+    /// Generates a closure of the method selected by `indices` with the receiver bound.
+    /// The receiver is `lhs` or, if it's None, the value on top of the stack.
+    fn gen_bind_method(
+        &mut self,
+        lhs: Option<&Expr>,
+        lhs_meta: Meta,
+        indices: &[usize],
+        stype: &SelectionType,
+        pos: Option<usize>,
+    ) {
+        let lhs_type = lhs_meta.value_type(&self.objects.metas);
+        let index_count = indices.len();
+        let index = indices[index_count - 1] as OpIndex; // the final index
+        let embedded_indices = Vec::from_iter(indices[..index_count - 1].iter().cloned());
+        let lhs_has_embedded = index_count > 1;
+        let final_lhs_meta = match lhs_has_embedded {
+            false => lhs_meta,
+            true => self.get_field_meta(&lhs_meta, &embedded_indices),
+        };
+        let final_lhs_type = final_lhs_meta.value_type(&self.objects.metas);
+        if (final_lhs_type != ValueType::Pointer && final_lhs_type != ValueType::Interface)
+            && *stype == SelectionType::MethodPtrRecv
+        {
+            if !lhs_has_embedded {
+                // the checker only allows this for addressable operands
+                self.gen_ref_expr(lhs.unwrap(), None);
+            } else {
+                if let Some(e) = lhs {
+                    self.visit_expr(e);
+                }
+                current_func_emitter!(self).emit_struct_field_op(
+                    Opcode::REF_STRUCT_FIELD,
+                    &embedded_indices,
+                    lhs_type,
+                    pos,
+                );
+            }
+        } else {
+            if let Some(e) = lhs {
+                self.visit_expr(e);
+            }
+            if lhs_has_embedded {
+                self.gen_load_field(&embedded_indices, lhs_meta, lhs_type, pos);
+            }
+            if final_lhs_type == ValueType::Pointer && *stype == SelectionType::MethodNonPtrRecv {
+                current_func_mut!(self).emit_code_with_type(Opcode::DEREF, lhs_type, pos);
+            }
+        }
+
+        if final_lhs_type == ValueType::Interface {
+            current_func_mut!(self).emit_code_with_type_imm(
+                Opcode::BIND_INTERFACE_METHOD,
+                final_lhs_type,
+                index,
+                pos,
+            );
+        } else {
+            let func = current_func_mut!(self);
+            func.emit_code_with_type(Opcode::BIND_METHOD, final_lhs_type, pos);
+            let point = func.next_code_index();
+            func.emit_raw_inst(0, pos); // placeholder for FunctionKey
+            let fkey = *self.func_stack.last().unwrap();
+            self.call_helper
+                .add_call(fkey, point, final_lhs_meta, index);
+        }
+    }
+
+    /// Generates a method expression like T.M or (*T).M. It's a function with the
+    /// receiver as its first parameter, which binds the method and then calls it.
+    fn gen_method_expr(
+        &mut self,
+        this: &Expr,
+        recv: TCTypeKey,
+        method: &IdentKey,
+        pos: Option<usize>,
+    ) {
+        let tc_type = self.t.node_tc_type(this.id());
+        let fmeta = self
+            .t
+            .tc_type_to_meta(tc_type, &mut self.objects, self.dummy_gcv);
+        let f = GosValue::function_with_meta(
+            self.pkg_key,
+            fmeta,
+            self.objects,
+            self.dummy_gcv,
+            FuncFlag::Default,
+        );
+        let fkey = *f.as_function();
+        self.objects.functions[fkey].name =
+            self.method_name(recv, &self.ast_objs.idents[*method].name);
+        let (params, _) = self.t.sig_params_tc_types(tc_type);
+        let returns = self.t.sig_returns_tc_types(tc_type);
+        let param_types: Vec<ValueType> = params
+            .iter()
+            .map(|&x| self.t.tc_type_to_value_type(x))
+            .collect();
+        let return_types: Vec<ValueType> = returns
+            .iter()
+            .map(|&x| self.t.tc_type_to_value_type(x))
+            .collect();
+        let emitter = Emitter::new(&mut self.objects.functions[fkey]);
+        for _ in 0..(returns.len() + params.len()) {
+            emitter.f.add_local(None);
+        }
+        self.func_stack.push(fkey);
+        self.func_t_stack.push(tc_type);
+
+        let ret_count = returns.len() as OpIndex;
+        let recv_meta = self
+            .t
+            .tc_type_to_meta(recv, &mut self.objects, self.dummy_gcv);
+        current_func_emitter!(self).emit_load(
+            EntIndex::LocalVar(ret_count),
+            None,
+            param_types[0],
+            pos,
+        );
+        let (_, _, indices, stype) = self.t.selection_vtypes_indices_sel_typ(this.id());
+        let indices = indices.clone();
+        self.gen_bind_method(None, recv_meta, &indices, &stype, pos);
+
+        let mut emitter = current_func_emitter!(self);
+        emitter.emit_pre_call(pos);
+        for (i, t) in param_types.iter().enumerate().skip(1) {
+            emitter.emit_load(EntIndex::LocalVar(ret_count + i as OpIndex), None, *t, pos);
+        }
+        // a variadic parameter is passed on as a slice
+        emitter.emit_call(CallStyle::Default, None, pos);
+        for (i, t) in return_types.iter().enumerate() {
+            emitter.emit_store(
+                &LeftHandSide::Primitive(EntIndex::LocalVar(i as OpIndex)),
+                i as OpIndex - ret_count,
+                None,
+                None,
+                *t,
+                pos,
+            );
+        }
+        emitter.emit_pop(&return_types, pos);
+        emitter.emit_return(None, pos);
+        self.func_stack.pop();
+        self.func_t_stack.pop();
+
+        let mut emitter = current_func_emitter!(self);
+        let i = emitter.add_const(None, GosValue::new_function(fkey));
+        emitter.emit_literal(ValueType::Function, None, i.into(), pos);
+    }
+
     fn gen_call(&mut self, func_expr: &Expr, params: &Vec<Expr>, ellipsis: bool, style: CallStyle) {
         let pos = Some(func_expr.pos(&self.ast_objs));
         match *self.t.expr_mode(func_expr) {
@@ -812,8 +1121,31 @@ impl<'a> CodeGen<'a> {
                     Builtin::Ffi => Opcode::FFI,
                     _ => unimplemented!(),
                 };
-                for e in params.iter() {
+                if let (Opcode::LEN | Opcode::CAP, Some(n)) = (opcode, self.array_ptr_len(params)) {
+                    // the length of the array type, the pointer is evaluated
+                    // but not dereferenced, it may be nil
+                    self.visit_expr(&params[0]);
+                    let mut emitter = current_func_emitter!(self);
+                    emitter.emit_pop(&[ValueType::Pointer], pos);
+                    emitter.emit_push_imm(ValueType::Int, n, pos);
+                    return;
+                }
+                for (i, e) in params.iter().enumerate() {
                     self.visit_expr(e);
+                    // the sizes are read as ints whatever their types
+                    if opcode == Opcode::MAKE && i > 0 {
+                        let t = self.t.expr_value_type(e);
+                        if t != ValueType::Int {
+                            current_func_emitter!(self).emit_cast(
+                                ValueType::Int,
+                                t,
+                                None,
+                                -1,
+                                0,
+                                pos,
+                            );
+                        }
+                    }
                 }
                 // some of the built in funcs are not recorded
                 if let Some(t) = self.t.try_expr_tc_type(func_expr) {
@@ -834,15 +1166,29 @@ impl<'a> CodeGen<'a> {
                 };
                 let bf = self.tc_objs.universe().builtins()[&builtin];
                 let param_count = params.len() as OpIndex;
-                let special_case = (opcode == Opcode::APPEND || opcode == Opcode::COPY)
+                // append([]byte, string...) and copy([]byte, string)
+                let special_case = ((opcode == Opcode::APPEND && ellipsis)
+                    || opcode == Opcode::COPY)
                     && param_last_t.map_or(false, |x| x == ValueType::String);
+                let t_elem = match opcode {
+                    Opcode::APPEND | Opcode::COPY => {
+                        let (_, t_elem) = self.t.sliceable_expr_value_types(
+                            &params[0],
+                            self.objects,
+                            self.dummy_gcv,
+                        );
+                        Some(t_elem)
+                    }
+                    _ => None,
+                };
                 let (t_variadic, count) = match special_case {
                     true => (Some(ValueType::FlagC), Some(0)), // special case,
                     false => match bf.variadic {
                         true => match ellipsis {
                             true => (Some(ValueType::FlagB), Some(0)), // do not pack params if there is ellipsis
                             false => (
-                                param_last_t,
+                                // the args are packed as elements, nil or converted to interfaces
+                                t_elem.or(param_last_t),
                                 Some(bf.arg_count as OpIndex - param_count + 1),
                             ),
                         },
@@ -857,14 +1203,7 @@ impl<'a> CodeGen<'a> {
                         (param0t, Some(self.t.tc_type_to_value_type(t)))
                     }
                     Opcode::PANIC => (Some(ValueType::Interface), None),
-                    Opcode::APPEND | Opcode::COPY => {
-                        let (_, t_elem) = self.t.sliceable_expr_value_types(
-                            &params[0],
-                            self.objects,
-                            self.dummy_gcv,
-                        );
-                        (Some(t_elem), None)
-                    }
+                    Opcode::APPEND | Opcode::COPY => (t_elem, None),
                     _ => (param0t, None),
                 };
                 let func = current_func_mut!(self);
@@ -959,7 +1298,9 @@ impl<'a> CodeGen<'a> {
 
                             current_func_emitter!(self).emit_cast(typ_to, typ_from, t2, -1, 0, pos);
                         }
-                        ValueType::Channel => { /* nothing to be done */ }
+                        // function values are the same closures whatever the
+                        // function type is
+                        ValueType::Channel | ValueType::Closure => { /* nothing to be done */ }
                         _ => {
                             dbg!(typ_to);
                             unreachable!()
@@ -1000,19 +1341,91 @@ impl<'a> CodeGen<'a> {
         func.emit_code_with_flag_imm(Opcode::TYPE_ASSERT, comma_ok, index.into(), Some(pos));
     }
 
-    fn gen_index(&mut self, container: &Expr, index: &Expr, t_result: ValueType, comma_ok: bool) {
-        let t1 = self.t.expr_value_type(index);
+    /// The length of the array the argument of len or cap points to, if it
+    /// is a pointer to an array
+    fn array_ptr_len(&self, params: &[Expr]) -> Option<OpIndex> {
+        let t = self.t.underlying_tc(self.t.expr_tc_type(params.first()?));
+        let base = match &self.tc_objs.types[t] {
+            Type::Pointer(detail) => self.t.underlying_tc(detail.base()),
+            _ => return None,
+        };
+        let len = self.tc_objs.types[base].try_as_array()?.len()?;
+        OpIndex::try_from(len).ok()
+    }
+
+    /// Visits the operand of an index, slice or range expression, a pointer
+    /// to an array is dereferenced: p[i] is (*p)[i]. Returns the value type
+    /// of what is indexed.
+    fn visit_container_expr(&mut self, container: &Expr) -> ValueType {
         self.visit_expr(container);
+        let t = self.t.expr_value_type(container);
+        if t != ValueType::Pointer {
+            return t;
+        }
+        let pos = Some(container.pos(self.ast_objs));
+        current_func_mut!(self).emit_code_with_type(Opcode::DEREF, t, pos);
+        ValueType::Array
+    }
+
+    /// Whether a container of type `t` is indexed by `index` without checking
+    /// the length, where a `//gos:nobounds` function ranges over the slice
+    fn unchecked_index(&self, t: ValueType, index: &Expr) -> bool {
+        t == ValueType::Slice && matches!(index, Expr::Ident(i) if self.in_range.contains(i))
+    }
+
+    fn gen_index(&mut self, container: &Expr, index: &Expr, t_result: ValueType, comma_ok: bool) {
+        let t0 = self.visit_container_expr(container);
         let pos = Some(container.pos(&self.ast_objs));
-        if let Some(const_val) = self.t.try_tc_const_value(index.id()) {
-            let (ival, _) = const_val.to_int().int_as_i64();
-            if let Ok(i) = OpIndex::try_from(ival) {
-                current_func_emitter!(self).emit_load_index_imm(i, t_result, comma_ok, pos);
-                return;
+        if self.unchecked_index(t0, index) {
+            self.visit_expr(index);
+            let t1 = self.t.expr_value_type(index);
+            current_func_emitter!(self).emit_load_index_unchecked(t_result, t1, pos);
+            return;
+        }
+        // map keys are not always ints, they are never immediates
+        if t0 != ValueType::Map {
+            if let Some(const_val) = self.t.try_tc_const_value(index.id()) {
+                if let (ival, true) = const_val.to_int().int_as_i64() {
+                    if let Ok(i) = OpIndex::try_from(ival) {
+                        current_func_emitter!(self).emit_load_index_imm(i, t_result, comma_ok, pos);
+                        return;
+                    }
+                }
             }
         }
         self.visit_expr(index);
-        current_func_emitter!(self).emit_load_index(t_result, t1, comma_ok, pos);
+        let t1 = match t0 {
+            ValueType::Map => self.cast_map_key(container, index),
+            _ => self.t.expr_value_type(index),
+        };
+        let zero_meta = match t0 {
+            ValueType::Map => {
+                let meta = self
+                    .t
+                    .map_elem_meta(container, self.objects, self.dummy_gcv);
+                let func = current_func_mut!(self);
+                Some(func.add_const(None, GosValue::new_metadata(meta)).into())
+            }
+            _ => None,
+        };
+        current_func_emitter!(self).emit_load_index(t_result, t1, comma_ok, zero_meta, pos);
+    }
+
+    /// Converts the key on top of the stack to the key type of the map, if
+    /// that is an interface, and returns its type
+    fn cast_map_key(&mut self, map: &Expr, key: &Expr) -> ValueType {
+        let key_type = self.t.map_key_tc(map);
+        let t = self.t.expr_tc_type(key);
+        self.try_cast_to_iface(Some(key_type), t, -1, key.pos(self.ast_objs))
+    }
+
+    /// An interface converted to another one has to be bound again, unless
+    /// the other one is the same or has no methods
+    fn iface_needs_rebinding(&self, to: TCTypeKey, from: TCTypeKey) -> bool {
+        let empty = self.tc_objs.types[self.t.underlying_tc(to)]
+            .try_as_interface()
+            .map_or(true, |x| x.is_empty());
+        !empty && !identical_ignore_tags(to, from, self.tc_objs)
     }
 
     fn try_cast_to_iface(
@@ -1027,7 +1440,13 @@ impl<'a> CodeGen<'a> {
             Some(t0) => match self.t.obj_underlying_value_type(t0) == ValueType::Interface {
                 true => {
                     let vt1 = self.t.obj_underlying_value_type(rhs);
-                    match vt1 != ValueType::Interface && vt1 != ValueType::Void {
+                    let cast = match vt1 {
+                        ValueType::Void => false,
+                        // the methods are bound again, at the indices of t0
+                        ValueType::Interface => self.iface_needs_rebinding(t0, rhs),
+                        _ => true,
+                    };
+                    match cast {
                         true => {
                             let index = self.iface_mapping.get_index(
                                 &(t0, rhs),
@@ -1101,7 +1520,10 @@ impl<'a> CodeGen<'a> {
 
     fn visit_composite_expr(&mut self, expr: &Expr, tctype: TCTypeKey) {
         match expr {
-            Expr::CompositeLit(clit) => self.gen_composite_literal(clit, tctype),
+            // the literal may have its own type, e.g. T{} in []Iface{T{}}
+            Expr::CompositeLit(clit) if clit.typ.is_none() => {
+                self.gen_composite_literal(clit, tctype)
+            }
             _ => self.visit_expr(expr),
         }
         let t = self.t.expr_tc_type(expr);
@@ -1117,6 +1539,13 @@ impl<'a> CodeGen<'a> {
         let typ = &self.tc_objs.types[tctype].underlying_val(&self.tc_objs);
         let meta = meta.underlying(&self.objects.metas);
         let mtype = &self.objects.metas[meta.key].clone();
+        if let Some(val) = self.const_composite_literal(clit, mtype) {
+            let mut emitter = current_func_emitter!(self);
+            let t = val.typ();
+            let i = emitter.add_const(None, val);
+            emitter.emit_literal(t, Some(vt), i.into(), pos);
+            return;
+        }
         match mtype {
             MetadataType::Slice(_) | MetadataType::Array(_, _) => {
                 let elem = match typ {
@@ -1194,6 +1623,88 @@ impl<'a> CodeGen<'a> {
         emitter.emit_literal(ValueType::Metadata, Some(vt), i.into(), pos);
     }
 
+    /// Builds the value of a slice, array or map literal at compile time if all
+    /// of its elements are constants of basic types. Every evaluation of the
+    /// literal then shares the data of the prebuilt value until it's written,
+    /// instead of pushing every element on the stack and building the container
+    /// from them.
+    fn const_composite_literal(
+        &mut self,
+        clit: &CompositeLit,
+        mtype: &MetadataType,
+    ) -> Option<GosValue> {
+        if clit.elts.is_empty() {
+            return None;
+        }
+        let is_basic = |t: ValueType| t.copyable() || t == ValueType::String;
+        match mtype {
+            MetadataType::Slice(m) | MetadataType::Array(m, _) => {
+                let t_elem = m.value_type(&self.objects.metas);
+                if !is_basic(t_elem) {
+                    return None;
+                }
+                let zero_val = m.zero(&self.objects.metas, self.dummy_gcv);
+                let mut vals = vec![];
+                let mut cur_index = 0;
+                for expr in clit.elts.iter() {
+                    let expr = match expr {
+                        Expr::KeyValue(kv) => {
+                            let key = self.t.try_tc_const_value(kv.key.id())?;
+                            cur_index = key.int_as_i64().0 as usize;
+                            &kv.val
+                        }
+                        _ => expr,
+                    };
+                    let val = self.const_elem_value(expr, t_elem)?;
+                    if cur_index >= vals.len() {
+                        vals.resize(cur_index, zero_val.clone());
+                        vals.push(val);
+                    } else {
+                        vals[cur_index] = val;
+                    }
+                    cur_index += 1;
+                }
+                if let MetadataType::Array(_, size) = mtype {
+                    vals.resize(*size, zero_val);
+                }
+                Some(GosValue::array_with_data(vals, t_elem, self.dummy_gcv))
+            }
+            MetadataType::Map(km, vm) => {
+                let (tk, tv) = (
+                    km.value_type(&self.objects.metas),
+                    vm.value_type(&self.objects.metas),
+                );
+                if !is_basic(tk) || !is_basic(tv) {
+                    return None;
+                }
+                let mut entries = vec![];
+                for expr in clit.elts.iter() {
+                    let kv = match expr {
+                        Expr::KeyValue(kv) => kv,
+                        _ => return None,
+                    };
+                    let k = self.const_elem_value(&kv.key, tk)?;
+                    let v = self.const_elem_value(&kv.val, tv)?;
+                    entries.push((k, v));
+                }
+                let zero_val = vm.zero(&self.objects.metas, self.dummy_gcv);
+                let map_val = GosValue::map_with_default_val(zero_val, self.dummy_gcv);
+                let map = map_val.as_map().unwrap();
+                for (k, v) in entries.into_iter() {
+                    map.0.insert(k, v);
+                }
+                Some(map_val)
+            }
+            _ => None,
+        }
+    }
+
+    fn const_elem_value(&mut self, expr: &Expr, t: ValueType) -> Option<GosValue> {
+        self.t.try_tc_const_value(expr.id())?;
+        let val = self.t.const_value(expr.id());
+        (val.typ() == t).then(|| val)
+    }
+
     fn gen_ref_expr(&mut self, expr: &Expr, whole_expr: Option<&Expr>) {
         let pos = Some(expr.pos(&self.ast_objs));
         match expr {
@@ -1204,11 +1715,20 @@ impl<'a> CodeGen<'a> {
                         let meta = self.t.node_meta(expr.id(), self.objects, self.dummy_gcv);
                         let t = meta.value_type(&self.objects.metas);
                         let entity_key = use_ident_unique_key!(self, *ikey);
+                        let kept = self.local_refs.contains(ikey);
                         let func = current_func_mut!(self);
                         let ind = *func.entity_index(&entity_key).unwrap();
                         let desc =
                             ValueDesc::new(*self.func_stack.last().unwrap(), ind.into(), t, false);
-                        if !func.is_ctor() {
+                        if !func.is_ctor() && kept {
+                            // a pointer to the stack, no upvalue to set up and close
+                            func.emit_inst(
+                                Opcode::REF_LOCAL,
+                                [Some(t), None, None],
+                                Some(ind.into()),
+                                pos,
+                            );
+                        } else if !func.is_ctor() {
                             let index = func.try_add_upvalue(&entity_key, desc);
                             func.emit_inst(
                                 Opcode::REF_UPVALUE,
@@ -1246,7 +1766,7 @@ impl<'a> CodeGen<'a> {
                     self.t
                         .sliceable_expr_value_types(&iexpr.expr, self.objects, self.dummy_gcv);
                 let t1 = self.t.expr_value_type(&iexpr.index);
-                self.visit_expr(&iexpr.expr);
+                self.visit_container_expr(&iexpr.expr);
                 self.visit_expr(&iexpr.index);
                 let pos = Some(iexpr.index.pos(&self.ast_objs));
                 current_func_mut!(self).emit_inst(
@@ -1364,6 +1884,29 @@ impl<'a> CodeGen<'a> {
         }
     }
 
+    /// Whether the expression is a call of a `//gos:pure` function with
+    /// arguments that can't panic, so it can be left out when its results
+    /// are unused
+    fn is_unused_pure_call(&self, expr: &Expr) -> bool {
+        let call = match expr {
+            Expr::Call(call) => call,
+            _ => return false,
+        };
+        let ident = match &call.func {
+            Expr::Ident(ikey) => *ikey,
+            Expr::Selector(sel) if self.t.try_pkg_key(&sel.expr).is_some() => sel.sel,
+            _ => return false,
+        };
+        let pure = self
+            .t
+            .try_object_use(ident)
+            .is_some_and(|okey| self.call_helper.is_pure(okey));
+        pure && call.args.iter().all(|arg| match arg {
+            Expr::Ident(_) | Expr::BasicLit(_) => true,
+            _ => matches!(self.t.try_expr_mode(arg), Some(OperandMode::Constant(_))),
+        })
+    }
+
     fn swallow_value(&mut self, expr: &Expr) {
         let val_types = self.t.expr_value_types(expr);
         current_func_emitter!(self).emit_pop(&val_types, Some(expr.pos(&self.ast_objs)));
@@ -1398,6 +1941,8 @@ impl<'a> CodeGen<'a> {
             FuncFlag::PkgCtor,
         );
         let fkey = *f.as_function();
+        self.pkg_name = self.tc_objs.pkgs[tcpkg].name().clone().unwrap_or_default();
+        self.objects.functions[fkey].name = format!("{}.init", self.pkg_name);
         // the 0th member is the constructor
         self.objects.packages[pkey].add_member(
             String::new(),
@@ -1406,6 +1951,8 @@ impl<'a> CodeGen<'a> {
         );
         self.pkg_key = pkey;
         self.func_stack.push(fkey);
+        self.local_refs = escape::local_refs(self.ast_objs, self.tc_objs, self.t.type_info());
+        self.in_range = bounds::in_range_indices(self.ast_objs, self.t.type_info());
 
         let (names, vars) = self.pkg_helper.sort_var_decls(files, self.t.type_info());
         self.add_pkg_var_member(pkey, &names);
@@ -1464,7 +2011,11 @@ impl<'a> ExprVisitor for CodeGen<'a> {
     /// Add function as a const and then generate a closure of it
     fn visit_expr_func_lit(&mut self, this: &Expr, flit: &FuncLit) {
         let tc_type = self.t.node_tc_type(this.id());
-        let fkey = self.gen_func_def(tc_type, flit.typ, None, &flit.body);
+        let parent = *self.func_stack.last().unwrap();
+        let count = self.func_lit_count.entry(parent).or_insert(0);
+        *count += 1;
+        let name = format!("{}.func{}", self.objects.functions[parent].name, count);
+        let fkey = self.gen_func_def(name, tc_type, flit.typ, None, &flit.body, &[]);
         let mut emitter = current_func_emitter!(self);
         let i = emitter.add_const(None, GosValue::new_function(fkey));
         let pos = Some(flit.body.l_brace);
@@ -1495,63 +2046,18 @@ impl<'a> ExprVisitor for CodeGen<'a> {
             return;
         }
 
+        if let Some(recv) = self.t.method_expr_recv(this.id()) {
+            self.gen_method_expr(this, recv, ident, pos);
+            return;
+        }
+
         let lhs_meta = self.t.node_meta(expr.id(), self.objects, self.dummy_gcv);
         let lhs_type = lhs_meta.value_type(&self.objects.metas);
         let (_, _, indices, stype) = self.t.selection_vtypes_indices_sel_typ(this.id());
         let indices = indices.clone();
         match &stype {
             SelectionType::MethodNonPtrRecv | SelectionType::MethodPtrRecv => {
-                let index_count = indices.len();
-                let index = indices[index_count - 1] as OpIndex; // the final index
-                let embedded_indices = Vec::from_iter(indices[..index_count - 1].iter().cloned());
-                let lhs_has_embedded = index_count > 1;
-                let final_lhs_meta = match lhs_has_embedded {
-                    false => lhs_meta,
-                    true => self.get_field_meta(&lhs_meta, &embedded_indices),
-                };
-                let final_lhs_type = final_lhs_meta.value_type(&self.objects.metas);
-                if (final_lhs_type != ValueType::Pointer && final_lhs_type != ValueType::Interface)
-                    && stype == SelectionType::MethodPtrRecv
-                {
-                    if !lhs_has_embedded {
-                        self.gen_ref_expr(expr, None);
-                    } else {
-                        self.visit_expr(expr);
-                        current_func_emitter!(self).emit_struct_field_op(
-                            Opcode::REF_STRUCT_FIELD,
-                            &embedded_indices,
-                            lhs_type,
-                            pos,
-                        );
-                    }
-                } else {
-                    self.visit_expr(expr);
-                    if lhs_has_embedded {
-                        self.gen_load_field(&embedded_indices, lhs_meta, lhs_type, pos);
-                    }
-                    if final_lhs_type == ValueType::Pointer
-                        && stype == SelectionType::MethodNonPtrRecv
-                    {
-                        current_func_mut!(self).emit_code_with_type(Opcode::DEREF, lhs_type, pos);
-                    }
-                }
-
-                if final_lhs_type == ValueType::Interface {
-                    current_func_mut!(self).emit_code_with_type_imm(
-                        Opcode::BIND_INTERFACE_METHOD,
-                        final_lhs_type,
-                        index,
-                        pos,
-                    );
-                } else {
-                    let func = current_func_mut!(self);
-                    func.emit_code_with_type(Opcode::BIND_METHOD, final_lhs_type, pos);
-                    let point = func.next_code_index();
-                    func.emit_raw_inst(0, pos); // placeholder for FunctionKey
-                    let fkey = *self.func_stack.last().unwrap();
-                    self.call_helper
-                        .add_call(fkey, point, final_lhs_meta, index);
-                }
+                self.gen_bind_method(Some(expr), lhs_meta, &indices, &stype, pos);
             }
             SelectionType::NonMethod => {
                 self.visit_expr(expr);
@@ -1573,7 +2079,7 @@ impl<'a> ExprVisitor for CodeGen<'a> {
         high: &Option<Expr>,
         max: &Option<Expr>,
     ) -> Self::Result {
-        self.visit_expr(expr);
+        self.visit_container_expr(expr);
         let (t0, t1) = self
             .t
             .sliceable_expr_value_types(expr, self.objects, self.dummy_gcv);
@@ -1582,12 +2088,25 @@ impl<'a> ExprVisitor for CodeGen<'a> {
             None => current_func_emitter!(self).emit_push_imm(ValueType::Int, 0, pos),
             Some(e) => self.visit_expr(e),
         }
-        match high {
-            None => current_func_emitter!(self).emit_push_imm(ValueType::Int, -1, pos),
-            Some(e) => self.visit_expr(e),
-        }
+        // a missing high index is a placeholder flagged with FlagA, so that
+        // a negative index is not taken for it
+        let t2 = match high {
+            None => {
+                current_func_emitter!(self).emit_push_imm(ValueType::Int, -1, pos);
+                Some(ValueType::FlagA)
+            }
+            Some(e) => {
+                self.visit_expr(e);
+                None
+            }
+        };
         match max {
-            None => current_func_mut!(self).emit_code_with_type2(Opcode::SLICE, t0, Some(t1), pos),
+            None => current_func_mut!(self).emit_inst(
+                Opcode::SLICE,
+                [Some(t0), Some(t1), t2],
+                None,
+                pos,
+            ),
             Some(e) => {
                 self.visit_expr(e);
                 current_func_mut!(self).emit_code_with_type2(Opcode::SLICE_FULL, t0, Some(t1), pos);
@@ -1779,7 +2298,22 @@ impl<'a> StmtVisitor for CodeGen<'a> {
         }
         let tc_type = self.t.obj_def_tc_type(decl.name);
         let stmt = decl.body.as_ref().unwrap();
-        let fkey = self.gen_func_def(tc_type, decl.typ, decl.recv.clone(), stmt);
+        let ident = &self.ast_objs.idents[decl.name].name;
+        let name = match &decl.recv {
+            Some(recv) => {
+                let field = &self.ast_objs.fields[recv.list[0]];
+                self.method_name(self.t.expr_tc_type(&field.typ), ident)
+            }
+            None => format!("{}.{}", self.pkg_name, ident),
+        };
+        let fkey = self.gen_func_def(
+            name,
+            tc_type,
+            decl.typ,
+            decl.recv.clone(),
+            stmt,
+            &decl.pragmas,
+        );
         let cls = GosValue::new_closure_static(fkey, &self.objects.functions);
         // this is a struct method
         if let Some(self_ident) = &decl.recv {
@@ -1806,7 +2340,11 @@ impl<'a> StmtVisitor for CodeGen<'a> {
         let offset = current_func!(self).code().len();
         let entity = self.t.object_def(stmt.label).into();
         let is_breakable = match &stmt.stmt {
-            Stmt::For(_) | Stmt::Range(_) | Stmt::Select(_) | Stmt::Switch(_) => true,
+            Stmt::For(_)
+            | Stmt::Range(_)
+            | Stmt::Select(_)
+            | Stmt::Switch(_)
+            | Stmt::TypeSwitch(_) => true,
             _ => false,
         };
         self.branch_helper.add_label(entity, offset, is_breakable);
@@ -1928,8 +2466,14 @@ impl<'a> StmtVisitor for CodeGen<'a> {
     }
 
     fn visit_stmt_block(&mut self, bstmt: &BlockStmt) {
-        for stmt in bstmt.list.iter() {
+        for (i, stmt) in bstmt.list.iter().enumerate() {
             self.visit_stmt(stmt);
+            // what follows a return or a jump can only be reached by a goto,
+            // and a goto can't jump into a block
+            let rest = &bstmt.list[i + 1..];
+            if is_terminating_stmt(stmt) && !rest.iter().any(|s| matches!(s, Stmt::Labeled(_))) {
+                break;
+            }
         }
     }
 
@@ -1937,6 +2481,15 @@ impl<'a> StmtVisitor for CodeGen<'a> {
         if let Some(init) = &ifstmt.init {
             self.visit_stmt(init);
         }
+        // generate only the arm that's taken if the condition is constant
+        if let Some(ConstValue::Bool(taken)) = self.t.try_tc_const_value(ifstmt.cond.id()) {
+            if *taken {
+                self.visit_stmt_block(&ifstmt.body);
+            } else if let Some(els) = &ifstmt.els {
+                self.visit_stmt(els);
+            }
+            return;
+        }
         self.visit_expr(&ifstmt.cond);
         let func = current_func_mut!(self);
         func.emit_code(Opcode::JUMP_IF_NOT, Some(ifstmt.if_pos));
@@ -1996,6 +2549,8 @@ impl<'a> StmtVisitor for CodeGen<'a> {
     }
 
     fn visit_stmt_type_switch(&mut self, tstmt: &TypeSwitchStmt) {
+        self.branch_helper.enter_block(false);
+
         if let Some(init) = &tstmt.init {
             self.visit_stmt(init);
         }
@@ -2013,29 +2568,39 @@ impl<'a> StmtVisitor for CodeGen<'a> {
             _ => unreachable!(),
         };
 
-        if let Some(_) = ident_expr {
-            let inst_data: Vec<(ValueType, OpIndex)> = tstmt
+        // the value being switched on is kept in a hidden local
+        let tc_type = self.t.expr_tc_type(v);
+        let meta = self
+            .t
+            .tc_type_to_meta(tc_type, self.objects, self.dummy_gcv);
+        let zero_val = meta.zero(&self.objects.metas, self.dummy_gcv);
+        let func = current_func_mut!(self);
+        let tag = func.add_local(None);
+        func.add_local_zero(zero_val, ValueType::Interface);
+        self.visit_expr(v);
+        let mut emitter = current_func_emitter!(self);
+        let lhs = LeftHandSide::Primitive(tag);
+        emitter.emit_store(&lhs, -1, None, None, ValueType::Interface, pos);
+        emitter.emit_pop(&[ValueType::Interface], pos);
+
+        // a new variable per clause, it has the type of the case if there is only one,
+        // and the type of the tag otherwise
+        let vars: Option<Vec<(EntIndex, ValueType)>> = ident_expr.map(|_| {
+            tstmt
                 .body
                 .list
                 .iter()
                 .map(|stmt| {
                     let tc_obj = self.t.object_implicit(&stmt.id());
                     let (index, _, meta) = self.add_local_var(tc_obj);
-                    (meta.value_type(&self.objects.metas), index.into())
+                    (index, meta.value_type(&self.objects.metas))
                 })
-                .collect();
-            self.visit_expr(v);
-            let func = current_func_mut!(self);
-            func.emit_code_with_imm(Opcode::TYPE, inst_data.len() as OpIndex, pos);
-            for data in inst_data.into_iter() {
-                func.emit_inst(Opcode::VOID, [Some(data.0), None, None], Some(data.1), pos)
-            }
-        } else {
-            self.visit_expr(v);
-            current_func_mut!(self).emit_code(Opcode::TYPE, pos);
-        }
+                .collect()
+        });
+        self.gen_type_switch_body(&*tstmt.body, tag, vars, pos);
 
-        self.gen_switch_body(&*tstmt.body, ValueType::Metadata);
+        self.branch_helper
+            .leave_block(current_func_mut!(self), None);
     }
 
     fn visit_stmt_comm(&mut self, _cclause: &CommClause) {
@@ -2076,6 +2641,13 @@ impl<'a> StmtVisitor for CodeGen<'a> {
             .iter()
             .map(|s| SelectHelper::to_comm_clause(s))
             .collect();
+        if comms.is_empty() {
+            // select {} blocks forever, it's a SELECT without any cases
+            current_func_mut!(self).emit_code_with_imm(Opcode::SELECT, 0, Some(sstmt.select));
+            self.branch_helper
+                .leave_block(current_func_mut!(self), None);
+            return;
+        }
         for c in comms.iter() {
             let (typ, pos) = match &c.comm {
                 Some(comm) => match comm {
@@ -2214,6 +2786,9 @@ impl<'a> StmtVisitor for CodeGen<'a> {
     }
 
     fn visit_expr_stmt(&mut self, e: &Expr) {
+        if self.is_unused_pure_call(e) {
+            return;
+        }
         self.visit_expr(e);
         self.swallow_value(e);
     }
@@ -2228,3 +2803,13 @@ impl<'a> StmtVisitor for CodeGen<'a> {
         unreachable!();
     }
 }
+
+/// Whether the statements following stmt in the same block are unreachable,
+/// unless they are jumped to
+fn is_terminating_stmt(stmt: &Stmt) -> bool {
+    match stmt {
+        Stmt::Return(_) => true,
+        Stmt::Branch(b) => matches!(b.token, Token::BREAK | Token::CONTINUE | Token::GOTO),
+        _ => false,
+    }
+}
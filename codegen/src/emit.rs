@@ -11,6 +11,7 @@ use goscript_vm::objects::{key_to_u64, EntIndex, FunctionVal};
 use goscript_vm::value::*;
 use slotmap::KeyData;
 use std::convert::TryFrom;
+use std::time::Duration;
 
 #[derive(Clone, Copy, Debug)]
 pub enum CallStyle {
@@ -25,6 +26,7 @@ pub struct IndexLhsInfo {
     pub imm_index: Option<OpIndex>, // for IMM instructions
     pub t1: ValueType,
     pub t2: Option<ValueType>, // for non-IMM instructions
+    pub unchecked: bool,       // for //gos:nobounds
 }
 
 impl IndexLhsInfo {
@@ -39,6 +41,7 @@ impl IndexLhsInfo {
             imm_index: imm_index,
             t1: t1,
             t2: t2,
+            unchecked: false,
         }
     }
 
@@ -117,7 +120,10 @@ impl<'a> Emitter<'a> {
                         .iter()
                         .map(|n| {
                             let key = t_lookup.object_def(*n);
-                            self.f.add_local(Some(key.into()));
+                            let index = self.f.add_local(Some(key.into()));
+                            if let Some(var) = t_lookup.local_var(key, index.into()) {
+                                self.f.add_local_var(var);
+                            }
                         })
                         .count()
                 }
@@ -247,7 +253,10 @@ impl<'a> Emitter<'a> {
                 ),
 
                 None => (
-                    Opcode::STORE_INDEX,
+                    match info.unchecked {
+                        true => Opcode::STORE_INDEX_UNCHECKED,
+                        false => Opcode::STORE_INDEX,
+                    },
                     info.index as i32,
                     Some(info.t1),
                     info.t2,
@@ -391,19 +400,44 @@ impl<'a> Emitter<'a> {
         }
     }
 
+    /// `zero_meta` is the const of the element metadata when indexing a map,
+    /// which gives the value read from a nil map
     pub fn emit_load_index(
         &mut self,
         typ: ValueType,
         index_type: ValueType,
         comma_ok: bool,
+        zero_meta: Option<OpIndex>,
         pos: Option<usize>,
     ) {
-        let mut inst =
-            Instruction::new(Opcode::LOAD_INDEX, Some(typ), Some(index_type), None, None);
+        let mut inst = Instruction::new(
+            Opcode::LOAD_INDEX,
+            Some(typ),
+            Some(index_type),
+            None,
+            zero_meta,
+        );
         inst.set_t2_with_index(if comma_ok { 1 } else { 0 });
         self.f.push_inst_pos(inst, pos);
     }
 
+    /// LOAD_INDEX of a slice element by an index proved in range, for `//gos:nobounds`
+    pub fn emit_load_index_unchecked(
+        &mut self,
+        typ: ValueType,
+        index_type: ValueType,
+        pos: Option<usize>,
+    ) {
+        let inst = Instruction::new(
+            Opcode::LOAD_INDEX_UNCHECKED,
+            Some(typ),
+            Some(index_type),
+            None,
+            None,
+        );
+        self.f.push_inst_pos(inst, pos);
+    }
+
     pub fn emit_load_index_imm(
         &mut self,
         imm: OpIndex,
@@ -430,6 +464,21 @@ impl<'a> Emitter<'a> {
         );
     }
 
+    /// Emits the prologue that starts the time budget of each call
+    pub fn emit_budget(&mut self, budget: Duration, pos: Option<usize>) {
+        let nanos = GosValue::new_int64(budget.as_nanos().min(i64::MAX as u128) as i64);
+        let i = self.add_const(None, nanos);
+        self.f
+            .emit_inst(Opcode::BUDGET, [None, None, None], Some(i.into()), pos);
+    }
+
+    /// Emits the prologue that returns the cached results of a call, if any
+    pub fn emit_memoize(&mut self, capacity: usize, pos: Option<usize>) {
+        let imm = capacity.min(OpIndex::MAX as usize) as OpIndex;
+        self.f
+            .emit_inst(Opcode::MEMOIZE, [None, None, None], Some(imm), pos);
+    }
+
     pub fn emit_pre_call(&mut self, pos: Option<usize>) {
         self.f
             .emit_inst(Opcode::PRE_CALL, [None, None, None], None, pos);
@@ -8,7 +8,9 @@ use super::call::CallHelper;
 use super::codegen::CodeGen;
 use super::emit::{CallStyle, Emitter};
 use super::interface::IfaceMapping;
+use super::optimize;
 use super::package::{PkgHelper, PkgVarPairs};
+use super::shake;
 use super::types::TypeCache;
 use goscript_parser::ast::Ident;
 use goscript_parser::errors::ErrorList;
@@ -21,7 +23,7 @@ use goscript_vm::instruction::*;
 use goscript_vm::null_key;
 use goscript_vm::value::*;
 use goscript_vm::vm::ByteCode;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::pin::Pin;
 
 pub struct EntryGen<'a> {
@@ -50,39 +52,6 @@ impl<'a> EntryGen<'a> {
         }
     }
 
-    // generate the entry function for ByteCode
-    fn gen_entry_func(
-        &mut self,
-        pkg: PackageKey,
-        index: OpIndex,
-        main_ident: IdentKey,
-        pairs: &mut PkgVarPairs,
-    ) -> FunctionKey {
-        // import the 0th pkg and call the main function of the pkg
-        let fmeta = self.objects.s_meta.default_sig;
-        let f = GosValue::function_with_meta(
-            null_key!(),
-            fmeta.clone(),
-            &mut self.objects,
-            &self.dummy_gcv,
-            FuncFlag::Default,
-        );
-        let fkey = *f.as_function();
-        let func = &mut self.objects.functions[fkey];
-        let mut emitter = Emitter::new(func);
-        emitter.emit_import(index, pkg, None);
-        emitter.emit_load(
-            EntIndex::PackageMember(pkg, main_ident.into()),
-            Some((pairs, fkey)),
-            ValueType::Function,
-            None,
-        );
-        emitter.emit_pre_call(None);
-        emitter.emit_call(CallStyle::Default, None, None);
-        emitter.emit_return(None, None);
-        *f.as_function()
-    }
-
     pub fn gen(
         mut self,
         checker_result: &HashMap<TCPackageKey, TypeInfo>,
@@ -104,6 +73,9 @@ impl<'a> EntryGen<'a> {
         let mut type_cache: TypeCache = HashMap::new();
         let mut pkg_pairs = PkgVarPairs::new();
         let mut call_helper = CallHelper::new();
+        for ti in checker_result.values() {
+            call_helper.add_pure_funcs(self.ast_objs, ti);
+        }
         let mut branch_helper = BranchHelper::new();
         for (i, (tcpkg, ti)) in checker_result.iter().enumerate() {
             let mut pkg_helper = PkgHelper::new(
@@ -130,30 +102,175 @@ impl<'a> EntryGen<'a> {
             cgen.gen_with_files(&ti.ast_files, *tcpkg, i as OpIndex);
         }
         let index = main_pkg_idx.unwrap();
-        let entry = self.gen_entry_func(
+        let entry = gen_entry_func(
+            &mut self.objects,
+            &self.dummy_gcv,
             self.packages[index as usize],
             index,
-            main_ident,
-            &mut pkg_pairs,
+            Some((main_ident, &mut pkg_pairs)),
         );
         pkg_pairs.patch_index(self.ast_objs, &mut self.objects);
         call_helper.patch_call(&mut self.objects);
         branch_helper.patch_go_tos(&mut self.objects.functions);
-        ByteCode::new(
-            self.objects,
-            self.packages,
-            self.iface_mapping.result(),
-            entry,
-        )
+        let mut ifaces = self.iface_mapping.result();
+        shake::remove_unreachable(&mut self.objects, &self.packages, &mut ifaces, entry, &[]);
+        for (_, func) in self
+            .objects
+            .functions
+            .iter_mut()
+            .filter(|(_, f)| !f.keep_code)
+        {
+            optimize::collapse_jump_chains(func);
+        }
+        ByteCode::new(self.objects, self.packages, ifaces, entry)
+    }
+}
+
+// generate a function importing the pkg at index, and calling its main
+// function if any
+fn gen_entry_func(
+    objects: &mut VMObjects,
+    dummy_gcv: &GcoVec,
+    pkg: PackageKey,
+    index: OpIndex,
+    main: Option<(IdentKey, &mut PkgVarPairs)>,
+) -> FunctionKey {
+    let fmeta = objects.s_meta.default_sig;
+    let f = GosValue::function_with_meta(null_key!(), fmeta, objects, dummy_gcv, FuncFlag::Default);
+    let fkey = *f.as_function();
+    let func = &mut objects.functions[fkey];
+    let mut emitter = Emitter::new(func);
+    emitter.emit_import(index, pkg, None);
+    if let Some((main_ident, pairs)) = main {
+        emitter.emit_load(
+            EntIndex::PackageMember(pkg, main_ident.into()),
+            Some((pairs, fkey)),
+            ValueType::Function,
+            None,
+        );
+        emitter.emit_pre_call(None);
+        emitter.emit_call(CallStyle::Default, None, None);
+    }
+    emitter.emit_return(None, None);
+    fkey
+}
+
+/// Generates the code of packages checked one after the other into the same
+/// ByteCode, for a REPL, where the code of each snippet is added to the code
+/// that already ran, and the values of its packages are kept.
+pub struct IncrementalGen {
+    dummy_gcv: GcoVec,
+    type_cache: TypeCache,
+    iface_mapping: IfaceMapping,
+    // the packages generated so far, by their index in the ByteCode
+    pkg_indices: HashMap<TCPackageKey, OpIndex>,
+    blank_ident: IdentKey,
+}
+
+impl IncrementalGen {
+    pub fn new(ast_objs: &mut AstObjects) -> IncrementalGen {
+        IncrementalGen {
+            dummy_gcv: GcoVec::new(),
+            type_cache: HashMap::new(),
+            iface_mapping: IfaceMapping::new(),
+            pkg_indices: HashMap::new(),
+            blank_ident: ast_objs.idents.insert(Ident::blank(0)),
+        }
+    }
+
+    /// Returns the code without any package the packages are added to
+    pub fn empty_code() -> ByteCode {
+        ByteCode::new(Box::pin(VMObjects::new()), vec![], vec![], null_key!())
+    }
+
+    /// Adds the code of the packages of `results` that are not in `code`
+    /// yet, and makes an entry importing `pkg` the entry of `code`.
+    /// Importing runs the initialization of the new packages. Returns the
+    /// vm package of `pkg`.
+    pub fn gen(
+        &mut self,
+        code: &mut ByteCode,
+        ast_objs: &AstObjects,
+        tc_objs: &TCObjects,
+        results: &HashMap<TCPackageKey, TypeInfo>,
+        pkg: TCPackageKey,
+    ) -> PackageKey {
+        let new_pkgs: Vec<(TCPackageKey, &TypeInfo)> = results
+            .iter()
+            .filter(|(k, _)| !self.pkg_indices.contains_key(k))
+            .map(|(k, ti)| (*k, ti))
+            .collect();
+        for (tcpkg, _) in new_pkgs.iter() {
+            let pkey = code.objects.packages.insert(PackageVal::new());
+            code.packages.push(pkey);
+            self.pkg_indices
+                .insert(*tcpkg, (code.packages.len() - 1) as OpIndex);
+        }
+        let old_funcs: HashSet<FunctionKey> = code.objects.functions.keys().collect();
+        let iface_count = self.iface_mapping.len();
+        let mut pkg_pairs = PkgVarPairs::new();
+        let mut call_helper = CallHelper::new();
+        for ti in results.values() {
+            call_helper.add_pure_funcs(ast_objs, ti);
+        }
+        let mut branch_helper = BranchHelper::new();
+        for (tcpkg, ti) in new_pkgs.iter() {
+            let index = self.pkg_indices[tcpkg];
+            let mut pkg_helper = PkgHelper::new(
+                ast_objs,
+                tc_objs,
+                &self.pkg_indices,
+                &code.packages,
+                &mut pkg_pairs,
+            );
+            let mut cgen = CodeGen::new(
+                &mut code.objects,
+                ast_objs,
+                tc_objs,
+                &mut self.dummy_gcv,
+                ti,
+                &mut self.type_cache,
+                &mut self.iface_mapping,
+                &mut call_helper,
+                &mut branch_helper,
+                &mut pkg_helper,
+                code.packages[index as usize],
+                self.blank_ident,
+            );
+            cgen.gen_with_files(&ti.ast_files, *tcpkg, index);
+        }
+        let index = self.pkg_indices[&pkg];
+        code.entry = gen_entry_func(
+            &mut code.objects,
+            &self.dummy_gcv,
+            code.packages[index as usize],
+            index,
+            None,
+        );
+        pkg_pairs.patch_index(ast_objs, &mut code.objects);
+        call_helper.patch_call(&mut code.objects);
+        branch_helper.patch_go_tos(&mut code.objects.functions);
+        for (_, func) in code
+            .objects
+            .functions
+            .iter_mut()
+            .filter(|(k, f)| !f.keep_code && !old_funcs.contains(k))
+        {
+            optimize::collapse_jump_chains(func);
+        }
+        code.add_ifaces(self.iface_mapping.since(iface_count));
+        code.packages[index as usize]
     }
 }
 
+/// Returns the code together with the warnings, or all the errors and
+/// warnings if there are errors.
 pub fn parse_check_gen<S: SourceRead>(
     path: &str,
     tconfig: &TraceConfig,
     reader: &S,
     fset: &mut FileSet,
-) -> Result<ByteCode, ErrorList> {
+) -> Result<(ByteCode, ErrorList), ErrorList> {
     let asto = &mut AstObjects::new();
     let tco = &mut goscript_types::TCObjects::new();
     let results = &mut HashMap::new();
@@ -165,12 +282,12 @@ pub fn parse_check_gen<S: SourceRead>(
     );
     let key = goscript_types::ImportKey::new(path, "./");
     let main_pkg = importer.import(&key);
-    if el.len() > 0 {
+    if el.has_errors() {
         Err(el)
     } else {
         let blank_ident = asto.idents.insert(Ident::blank(0));
         let main_ident = asto.idents.insert(Ident::with_str(0, "main"));
         let gen = EntryGen::new(asto, tco, blank_ident);
-        Ok(gen.gen(results, main_pkg.unwrap(), main_ident))
+        Ok((gen.gen(results, main_pkg.unwrap(), main_ident), el))
     }
 }
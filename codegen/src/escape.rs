@@ -0,0 +1,480 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Finds the addresses of local variables that can not outlive the frame
+//! of the function taking them, run on a package before its code is
+//! generated.
+//!
+//! Taking the address of a local makes it an upvalue of its own function,
+//! which sets up the upvalue of each call and closes it on return. A pointer
+//! that is only dereferenced, compared or passed to a function that does
+//! no more with it can point to the stack instead, nothing is left to
+//! close. Such a pointer is either passed straight to the function, or kept
+//! in a local only used that way.
+//!
+//! Only the functions and methods declared in the package are looked into,
+//! and only one level deep: a parameter passed on to another call escapes.
+
+use goscript_parser::ast::BlockStmt;
+use goscript_parser::ast::{Decl, Expr, FuncDecl, Node, Spec, Stmt};
+use goscript_parser::objects::{FuncTypeKey, IdentKey, Objects as AstObjects};
+use goscript_parser::token::Token;
+use goscript_types::{ObjKey as TCObjKey, SelectionKind, TCObjects, Type, TypeInfo};
+use std::collections::{HashMap, HashSet};
+
+/// The parameters of a function or method that no pointer escapes through
+struct Params {
+    /// for a pointer receiver, whether it does not escape
+    recv: Option<bool>,
+    params: Vec<bool>,
+}
+
+/// Where the address of a local goes
+enum Site {
+    /// to a function keeping it in its frame
+    Kept,
+    /// into a local of the same function, kept if the local is
+    Local(TCObjKey),
+}
+
+struct Walker<'a> {
+    ast_objs: &'a AstObjects,
+    tc_objs: &'a TCObjects,
+    ti: &'a TypeInfo,
+    /// None while the parameters are summarized, every call escapes then
+    funcs: Option<&'a HashMap<TCObjKey, Params>>,
+    /// the variables declared by the functions being walked, innermost last
+    frames: Vec<HashSet<TCObjKey>>,
+    /// the variables with a use that may let their value escape
+    leaked: HashSet<TCObjKey>,
+    sites: Vec<(IdentKey, Site)>,
+}
+
+/// Returns the identifiers of the locals whose addresses, taken where they
+/// are, never outlive the frame of their function
+pub fn local_refs(ast_objs: &AstObjects, tc_objs: &TCObjects, ti: &TypeInfo) -> HashSet<IdentKey> {
+    let decls: Vec<&FuncDecl> = ti
+        .ast_files
+        .iter()
+        .flat_map(|f| f.decls.iter())
+        .filter_map(|d| match d {
+            Decl::Func(fkey) => Some(&ast_objs.fdecls[*fkey]),
+            _ => None,
+        })
+        .collect();
+
+    let mut funcs = HashMap::new();
+    for fdecl in decls.iter() {
+        let (okey, body) = match (ti.defs.get(&fdecl.name), &fdecl.body) {
+            (Some(Some(okey)), Some(body)) => (*okey, body),
+            _ => continue,
+        };
+        let mut w = Walker::new(ast_objs, tc_objs, ti, None);
+        w.func_decl(fdecl, body);
+        let defined = |ikey: &IdentKey| ti.defs.get(ikey).copied().flatten();
+        let kept = |ikey: Option<&IdentKey>| {
+            ikey.and_then(defined)
+                .map_or(true, |okey| !w.leaked.contains(&okey))
+        };
+        let recv = fdecl.recv.as_ref().and_then(|recv| {
+            let field = &ast_objs.fields[recv.list[0]];
+            matches!(field.typ, Expr::Star(_)).then(|| kept(field.names.first()))
+        });
+        let ftype = &ast_objs.ftypes[fdecl.typ];
+        let mut params = vec![];
+        for fkey in ftype.params.list.iter() {
+            let field = &ast_objs.fields[*fkey];
+            // the variadic arguments are put in a slice
+            let variadic = matches!(field.typ, Expr::Ellipsis(_));
+            if field.names.is_empty() {
+                params.push(!variadic);
+            }
+            for ikey in field.names.iter() {
+                params.push(!variadic && kept(Some(ikey)));
+            }
+        }
+        funcs.insert(
+            okey,
+            Params {
+                recv: recv,
+                params: params,
+            },
+        );
+    }
+
+    let mut w = Walker::new(ast_objs, tc_objs, ti, Some(&funcs));
+    for fdecl in decls.iter() {
+        if let Some(body) = &fdecl.body {
+            w.func_decl(fdecl, body);
+        }
+    }
+    let leaked = &w.leaked;
+    w.sites
+        .iter()
+        .filter(|(_, site)| match site {
+            Site::Kept => true,
+            Site::Local(okey) => !leaked.contains(okey),
+        })
+        .map(|(ikey, _)| *ikey)
+        .collect()
+}
+
+impl<'a> Walker<'a> {
+    fn new(
+        ast_objs: &'a AstObjects,
+        tc_objs: &'a TCObjects,
+        ti: &'a TypeInfo,
+        funcs: Option<&'a HashMap<TCObjKey, Params>>,
+    ) -> Walker<'a> {
+        Walker {
+            ast_objs: ast_objs,
+            tc_objs: tc_objs,
+            ti: ti,
+            funcs: funcs,
+            frames: vec![],
+            leaked: HashSet::new(),
+            sites: vec![],
+        }
+    }
+
+    fn func_decl(&mut self, fdecl: &FuncDecl, body: &BlockStmt) {
+        let mut frame = HashSet::new();
+        if let Some(recv) = &fdecl.recv {
+            for fkey in recv.list.iter() {
+                frame.extend(self.defined(&self.ast_objs.fields[*fkey].names));
+            }
+        }
+        self.func(frame, fdecl.typ, body);
+    }
+
+    fn func(&mut self, mut frame: HashSet<TCObjKey>, typ: FuncTypeKey, body: &BlockStmt) {
+        let ftype = &self.ast_objs.ftypes[typ];
+        for fkey in ftype.params.list.iter() {
+            frame.extend(self.defined(&self.ast_objs.fields[*fkey].names));
+        }
+        if let Some(results) = &ftype.results {
+            // a bare return returns them
+            for fkey in results.list.iter() {
+                let names = self.defined(&self.ast_objs.fields[*fkey].names);
+                self.leaked.extend(names);
+            }
+        }
+        self.frames.push(frame);
+        self.stmts(&body.list);
+        self.frames.pop();
+    }
+
+    fn defined(&self, idents: &[IdentKey]) -> Vec<TCObjKey> {
+        idents
+            .iter()
+            .filter_map(|i| self.ti.defs.get(i).copied().flatten())
+            .collect()
+    }
+
+    fn declare(&mut self, ikey: &IdentKey) {
+        if let Some(Some(okey)) = self.ti.defs.get(ikey) {
+            self.frames.last_mut().unwrap().insert(*okey);
+        }
+    }
+
+    fn stmts(&mut self, stmts: &[Stmt]) {
+        for s in stmts.iter() {
+            self.stmt(s);
+        }
+    }
+
+    fn opt_stmt(&mut self, stmt: &Option<Stmt>) {
+        if let Some(s) = stmt {
+            self.stmt(s);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        let objs = self.ast_objs;
+        match stmt {
+            Stmt::Decl(d) => {
+                if let Decl::Gen(gdecl) = d.as_ref() {
+                    for skey in gdecl.specs.iter() {
+                        if let Spec::Value(vs) = &objs.specs[*skey] {
+                            for ikey in vs.names.iter() {
+                                self.declare(ikey);
+                            }
+                            if vs.names.len() == vs.values.len() {
+                                for (ikey, val) in vs.names.iter().zip(vs.values.iter()) {
+                                    self.assigned(Some(ikey), val);
+                                }
+                            } else {
+                                self.exprs(&vs.values);
+                            }
+                        }
+                    }
+                }
+            }
+            Stmt::Labeled(lkey) => self.stmt(&objs.l_stmts[*lkey].stmt),
+            Stmt::Expr(e) => self.expr(e),
+            Stmt::Send(s) => {
+                self.expr(&s.chan);
+                self.expr(&s.val);
+            }
+            Stmt::IncDec(s) => self.expr(&s.expr),
+            Stmt::Assign(akey) => {
+                let astmt = &objs.a_stmts[*akey];
+                for e in astmt.lhs.iter() {
+                    match e {
+                        Expr::Ident(ikey) if astmt.token == Token::DEFINE => self.declare(ikey),
+                        Expr::Ident(_) if astmt.token == Token::ASSIGN => {}
+                        _ => self.expr(e),
+                    }
+                }
+                let simple = astmt.token == Token::ASSIGN || astmt.token == Token::DEFINE;
+                if simple && astmt.lhs.len() == astmt.rhs.len() {
+                    for (l, r) in astmt.lhs.iter().zip(astmt.rhs.iter()) {
+                        match l {
+                            Expr::Ident(ikey) => self.assigned(Some(ikey), r),
+                            _ => self.assigned(None, r),
+                        }
+                    }
+                } else {
+                    self.exprs(&astmt.rhs);
+                }
+            }
+            // the arguments of the calls outlive the statements
+            Stmt::Go(s) => self.escaping_call(&s.call),
+            Stmt::Defer(s) => self.escaping_call(&s.call),
+            Stmt::Return(s) => self.exprs(&s.results),
+            Stmt::Block(s) => self.stmts(&s.list),
+            Stmt::If(s) => {
+                self.opt_stmt(&s.init);
+                self.expr(&s.cond);
+                self.stmts(&s.body.list);
+                self.opt_stmt(&s.els);
+            }
+            Stmt::Case(s) => {
+                if let Some(l) = &s.list {
+                    self.exprs(l);
+                }
+                self.stmts(&s.body);
+            }
+            Stmt::Switch(s) => {
+                self.opt_stmt(&s.init);
+                if let Some(tag) = &s.tag {
+                    self.expr(tag);
+                }
+                self.stmts(&s.body.list);
+            }
+            Stmt::TypeSwitch(s) => {
+                self.opt_stmt(&s.init);
+                self.stmt(&s.assign);
+                self.stmts(&s.body.list);
+            }
+            Stmt::Comm(s) => {
+                self.opt_stmt(&s.comm);
+                self.stmts(&s.body);
+            }
+            Stmt::Select(s) => self.stmts(&s.body.list),
+            Stmt::For(s) => {
+                self.opt_stmt(&s.init);
+                if let Some(cond) = &s.cond {
+                    self.expr(cond);
+                }
+                self.opt_stmt(&s.post);
+                self.stmts(&s.body.list);
+            }
+            Stmt::Range(s) => {
+                for e in [&s.key, &s.val].iter().filter_map(|e| e.as_ref()) {
+                    match e {
+                        Expr::Ident(ikey) if s.token == Token::DEFINE => self.declare(ikey),
+                        Expr::Ident(_) => {}
+                        _ => self.expr(e),
+                    }
+                }
+                self.expr(&s.expr);
+                self.stmts(&s.body.list);
+            }
+            Stmt::Bad(_) | Stmt::Empty(_) | Stmt::Branch(_) => {}
+        }
+    }
+
+    /// `val` assigned to the local `ikey`, or to something else if None
+    fn assigned(&mut self, ikey: Option<&IdentKey>, val: &Expr) {
+        let target = ikey
+            .and_then(|i| self.object(i))
+            .filter(|okey| self.frames.last().unwrap().contains(okey));
+        match (local_ref(val), target) {
+            (Some(x), Some(okey)) => self.site(&x, Site::Local(okey)),
+            _ => self.expr(val),
+        }
+    }
+
+    fn exprs(&mut self, exprs: &[Expr]) {
+        for e in exprs.iter() {
+            self.expr(e);
+        }
+    }
+
+    /// Walks an expression whose value may escape
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Ident(ikey) => {
+                if let Some(okey) = self.object(ikey) {
+                    self.leaked.insert(okey);
+                }
+            }
+            Expr::FuncLit(flit) => self.func(HashSet::new(), flit.typ, &flit.body),
+            Expr::CompositeLit(clit) => self.exprs(&clit.elts),
+            Expr::Paren(e) => self.expr(&e.expr),
+            Expr::Selector(e) => match self.ti.selections.get(&e.id()).map(|s| s.kind()) {
+                Some(SelectionKind::FieldVal) => self.deref(&e.expr),
+                // a method value keeps the receiver
+                Some(SelectionKind::MethodVal) => self.expr(&e.expr),
+                // qualified identifiers and method expressions
+                _ => {}
+            },
+            Expr::Index(e) => {
+                self.deref(&e.expr);
+                self.expr(&e.index);
+            }
+            Expr::Slice(e) => {
+                self.expr(&e.expr);
+                for x in [&e.low, &e.high, &e.max].iter().filter_map(|x| x.as_ref()) {
+                    self.expr(x);
+                }
+            }
+            Expr::TypeAssert(e) => self.expr(&e.expr),
+            Expr::Call(e) => self.call(&e.func, &e.args, e.ellipsis.is_some()),
+            Expr::Star(e) => self.deref(&e.expr),
+            Expr::Unary(e) => self.expr(&e.expr),
+            Expr::Binary(e) => {
+                if e.op == Token::EQL || e.op == Token::NEQ {
+                    self.deref(&e.expr_a);
+                    self.deref(&e.expr_b);
+                } else {
+                    self.expr(&e.expr_a);
+                    self.expr(&e.expr_b);
+                }
+            }
+            Expr::KeyValue(e) => {
+                self.expr(&e.key);
+                self.expr(&e.val);
+            }
+            _ => {}
+        }
+    }
+
+    /// Walks an expression whose value, if it's a variable, is only read
+    /// through or compared
+    fn deref(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Ident(ikey) => self.used(ikey),
+            _ => self.expr(expr),
+        }
+    }
+
+    /// A variable used without its value escaping, unless it's captured by
+    /// a function literal
+    fn used(&mut self, ikey: &IdentKey) {
+        if let Some(okey) = self.object(ikey) {
+            let (inner, outer) = self.frames.split_last().unwrap();
+            if !inner.contains(&okey) && outer.iter().any(|f| f.contains(&okey)) {
+                self.leaked.insert(okey);
+            }
+        }
+    }
+
+    fn call(&mut self, func: &Expr, args: &[Expr], ellipsis: bool) {
+        let funcs = match self.funcs {
+            Some(funcs) if !ellipsis => funcs,
+            _ => return self.escaping_call_parts(func, args),
+        };
+        let params = match func {
+            Expr::Ident(ikey) => self.object(ikey).and_then(|okey| funcs.get(&okey)),
+            Expr::Selector(sexpr) => match self.ti.selections.get(&sexpr.id()) {
+                Some(sel) if matches!(sel.kind(), SelectionKind::MethodVal) => {
+                    let params = funcs.get(&sel.obj());
+                    self.receiver(&sexpr.expr, sel.indices().len(), params);
+                    params
+                }
+                _ => {
+                    self.expr(func);
+                    None
+                }
+            },
+            _ => {
+                self.expr(func);
+                None
+            }
+        };
+        for (i, arg) in args.iter().enumerate() {
+            let kept = params.map_or(false, |p| p.params.get(i) == Some(&true));
+            match (kept, arg, local_ref(arg)) {
+                (true, _, Some(x)) => self.site(&x, Site::Kept),
+                (true, Expr::Ident(ikey), _) => self.used(ikey),
+                _ => self.expr(arg),
+            }
+        }
+    }
+
+    /// The receiver `recv` of a method call, `depth` is the number of
+    /// fields and the method selected
+    fn receiver(&mut self, recv: &Expr, depth: usize, params: Option<&Params>) {
+        let ikey = match recv {
+            Expr::Ident(ikey) => ikey,
+            _ => return self.expr(recv),
+        };
+        let recv_ptr = self.ti.types.get(&recv.id()).map_or(false, |tv| {
+            let typ = match &self.tc_objs.types[tv.typ] {
+                Type::Named(n) => n.underlying(),
+                _ => tv.typ,
+            };
+            matches!(self.tc_objs.types[typ], Type::Pointer(_))
+        });
+        match params.map(|p| p.recv) {
+            // the address of the receiver is taken for the call
+            Some(Some(true)) if depth == 1 && !recv_ptr => self.site(ikey, Site::Kept),
+            Some(Some(true)) if depth == 1 => self.used(ikey),
+            // a value receiver gets a copy
+            Some(None) => self.used(ikey),
+            _ => self.expr(recv),
+        }
+    }
+
+    fn escaping_call(&mut self, call: &Expr) {
+        match call {
+            Expr::Call(e) => self.escaping_call_parts(&e.func, &e.args),
+            _ => self.expr(call),
+        }
+    }
+
+    fn escaping_call_parts(&mut self, func: &Expr, args: &[Expr]) {
+        self.expr(func);
+        self.exprs(args);
+    }
+
+    /// The address of `x` taken, its value can be read through the pointer
+    fn site(&mut self, x: &IdentKey, site: Site) {
+        if let Some(okey) = self.object(x) {
+            self.leaked.insert(okey);
+        }
+        self.sites.push((*x, site));
+    }
+
+    fn object(&self, ikey: &IdentKey) -> Option<TCObjKey> {
+        self.ti
+            .uses
+            .get(ikey)
+            .copied()
+            .or_else(|| self.ti.defs.get(ikey).copied().flatten())
+    }
+}
+
+/// The local `x` of `&x`
+fn local_ref(expr: &Expr) -> Option<IdentKey> {
+    match expr {
+        Expr::Unary(e) if e.op == Token::AND => match &e.expr {
+            Expr::Ident(ikey) => Some(*ikey),
+            _ => None,
+        },
+        _ => None,
+    }
+}
@@ -27,6 +27,15 @@ impl IfaceMapping {
         self.ifaces
     }
 
+    pub fn len(&self) -> usize {
+        self.ifaces.len()
+    }
+
+    /// The bindings added after the first `start` ones
+    pub fn since(&self, start: usize) -> Vec<(Meta, Vec<IfaceBinding>)> {
+        self.ifaces[start..].to_vec()
+    }
+
     pub fn get_index(
         &mut self,
         i_s: &(TCTypeKey, TCTypeKey),
@@ -52,6 +61,11 @@ impl IfaceMapping {
     ) -> (Meta, Vec<IfaceBinding>) {
         let iface = lookup.tc_type_to_meta(i_s.0, objs, dummy_gcv);
         let struct_ = lookup.tc_type_to_meta(i_s.1, objs, dummy_gcv);
+        if struct_.value_type(&objs.metas) == ValueType::Interface {
+            // from one interface to another, the methods of the dynamic type
+            // are looked up at runtime
+            return (iface, vec![]);
+        }
         let fields: Vec<&String> = match &objs.metas[iface.underlying(&objs.metas).key] {
             MetadataType::Interface(m) => m.all().iter().map(|x| &x.name).collect(),
             _ => unreachable!(),
@@ -2,14 +2,20 @@
 // Use of this source code is governed by a BSD-style
 // license that can be found in the LICENSE file.
 
+mod bounds;
 mod branch;
 mod call;
 mod emit;
+mod escape;
 mod interface;
+mod optimize;
 mod package;
+mod shake;
 mod types;
 
 pub mod codegen;
 pub mod entry;
-pub use entry::parse_check_gen;
-pub use goscript_types::{FsReader, SourceRead, TraceConfig};
+pub use entry::{parse_check_gen, IncrementalGen};
+pub use goscript_types::{
+    FsReader, IdentInfo, Location, MapReader, SourceRead, TraceConfig, Workspace,
+};
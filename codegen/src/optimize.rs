@@ -0,0 +1,47 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Passes over the generated code of a function, run once all the jumps
+//! have been patched.
+
+use goscript_vm::instruction::*;
+use goscript_vm::objects::FunctionVal;
+
+// the number of jumps followed to find the final target, chains longer than
+// this are most likely loops like `for {}`
+const MAX_JUMP_CHAIN: usize = 8;
+
+/// Retargets the jumps that land on an unconditional JUMP to where that one
+/// goes, so that `if` arms ending in loops and `continue`s take one jump
+/// instead of two.
+///
+/// The raw data in the code (keys and indices after some instructions)
+/// always decodes as VOID, so it's never mistaken for a jump.
+pub fn collapse_jump_chains(func: &mut FunctionVal) {
+    for i in 0..func.code().len() {
+        let inst = func.code()[i];
+        match inst.op() {
+            Opcode::JUMP | Opcode::JUMP_IF | Opcode::JUMP_IF_NOT => {}
+            _ => continue,
+        }
+        let mut target = jump_target(i, &inst);
+        for _ in 0..MAX_JUMP_CHAIN {
+            match func.code().get(target) {
+                Some(next) if next.op() == Opcode::JUMP && target != i => {
+                    target = jump_target(target, next);
+                }
+                _ => break,
+            }
+        }
+        let imm = target as OpIndex - (i as OpIndex + 1);
+        if imm != inst.imm() {
+            func.instruction_mut(i).set_imm(imm);
+        }
+    }
+}
+
+#[inline]
+fn jump_target(i: usize, inst: &Instruction) -> usize {
+    (i as OpIndex + 1 + inst.imm()) as usize
+}
@@ -128,8 +128,7 @@ impl<'a> PkgHelper<'a> {
         let mut orders = HashMap::new();
         for (i, init) in ti.init_order.iter().enumerate() {
             for okey in init.lhs.iter() {
-                let name = self.tc_objs.lobjs[*okey].name();
-                orders.insert(name, i);
+                orders.insert(*okey, i);
             }
         }
 
@@ -145,8 +144,9 @@ impl<'a> PkgHelper<'a> {
                                 match spec {
                                     Spec::Value(v) => {
                                         names.extend(v.names.iter());
-                                        let name = &self.ast_objs.idents[v.names[0]].name;
-                                        if let Some(order) = orders.get(name) {
+                                        // by object, as there may be many blank vars
+                                        let obj = ti.defs[&v.names[0]];
+                                        if let Some(order) = obj.and_then(|o| orders.get(&o)) {
                                             decls.push((v.clone(), order));
                                         }
                                     }
@@ -0,0 +1,181 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Removes the functions that can not be called from the entry function,
+//! run once all the calls and package members have been patched.
+//!
+//! A function is reachable from another one if it's one of its consts
+//! (function literals, method expressions), a package member it loads or
+//! takes the address of, a method it binds, or the constructor or an init
+//! function of a package it imports. The methods of a named type are
+//! reachable if the type is: interface calls and type assertions find them
+//! through the metadata of the dynamic type at runtime, and a value can only
+//! get into an interface with a type the live code refers to, directly or
+//! through the types of fields, elements and signatures.
+
+use goscript_vm::instruction::*;
+use goscript_vm::metadata::{Meta, MetadataType};
+use goscript_vm::objects::{u64_to_key, ClosureObj, IfaceBinding, MetadataKey};
+use goscript_vm::value::*;
+use std::collections::HashSet;
+
+/// Removes the unreachable functions from `objs`, together with their
+/// consts, and resets the package members and the methods that held them.
+/// The bindings of `ifaces` only the removed functions convert with are
+/// emptied, their indices stay valid.
+/// `keep` are the other functions the host may call, besides `entry`.
+/// Returns the number of removed functions.
+pub fn remove_unreachable(
+    objs: &mut VMObjects,
+    packages: &[PackageKey],
+    ifaces: &mut [(Meta, Vec<IfaceBinding>)],
+    entry: FunctionKey,
+    keep: &[FunctionKey],
+) -> usize {
+    let mut live: HashSet<FunctionKey> = HashSet::new();
+    let mut types: HashSet<MetadataKey> = HashSet::new();
+    let mut live_ifaces: HashSet<usize> = HashSet::new();
+    let mut queue: Vec<FunctionKey> = vec![entry];
+    queue.extend_from_slice(keep);
+    let mut metas: Vec<Meta> = vec![];
+
+    while !queue.is_empty() || !metas.is_empty() {
+        while let Some(fkey) = queue.pop() {
+            if !live.insert(fkey) {
+                continue;
+            }
+            let func = &objs.functions[fkey];
+            metas.push(func.meta);
+            // the consts of a constructor hold all the types of its package
+            let ctor = func.is_ctor();
+            for c in func.consts.iter() {
+                queue.extend(func_key(c));
+                if !ctor {
+                    metas.extend(meta_of(c));
+                }
+            }
+            let code = func.code();
+            let mut i = 0;
+            while i < code.len() {
+                let inst = code[i];
+                i += 1;
+                match inst.op() {
+                    Opcode::LOAD_PKG_FIELD | Opcode::REF_PKG_MEMBER | Opcode::STORE_PKG_FIELD => {
+                        let pkg = &objs.packages[u64_to_key::<PackageKey>(code[i].get_u64())];
+                        let index = match inst.op() {
+                            Opcode::STORE_PKG_FIELD => inst.imm824().1,
+                            _ => inst.imm(),
+                        };
+                        let member = pkg.member(index);
+                        queue.extend(func_key(&member));
+                        metas.extend(meta_of(&member));
+                        i += 1;
+                    }
+                    Opcode::LOAD_PKG_INIT => {
+                        let pkg = &objs.packages[u64_to_key::<PackageKey>(code[i].get_u64())];
+                        let mut index = 0;
+                        while let Some(f) = pkg.init_func(index) {
+                            queue.extend(func_key(f));
+                            index += 1;
+                        }
+                        i += 1;
+                    }
+                    Opcode::BIND_METHOD => {
+                        queue.push(u64_to_key(code[i].get_u64()));
+                        i += 1;
+                    }
+                    Opcode::CAST if inst.t0() == ValueType::Interface => {
+                        let index = inst.imm824().1 as usize;
+                        if live_ifaces.insert(index) {
+                            let (meta, bindings) = &ifaces[index];
+                            metas.push(*meta);
+                            for b in bindings.iter() {
+                                if let IfaceBinding::Struct(m, _) = b {
+                                    queue.extend(m.borrow().func);
+                                }
+                            }
+                        }
+                    }
+                    // the raw data after other instructions decodes as VOID
+                    _ => {}
+                }
+            }
+        }
+
+        while let Some(meta) = metas.pop() {
+            if !types.insert(meta.key) {
+                continue;
+            }
+            match &objs.metas[meta.key] {
+                MetadataType::Array(m, _) | MetadataType::Slice(m) => metas.push(*m),
+                MetadataType::Channel(_, m) => metas.push(*m),
+                MetadataType::Map(k, v) => metas.extend([*k, *v]),
+                MetadataType::Struct(fields, _) | MetadataType::Interface(fields) => {
+                    metas.extend(fields.all().iter().map(|f| f.meta))
+                }
+                MetadataType::Signature(s) => {
+                    metas.extend(s.recv.iter().chain(s.params.iter()).chain(s.results.iter()));
+                    metas.extend(s.variadic.iter().flat_map(|(a, b)| [*a, *b]));
+                }
+                MetadataType::Named(methods, underlying, _) => {
+                    metas.push(*underlying);
+                    queue.extend(methods.members.iter().filter_map(|m| m.borrow().func));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    for &pkey in packages.iter() {
+        let pkg = &objs.packages[pkey];
+        for index in 0..pkg.member_count() as OpIndex {
+            let dead = func_key(&pkg.member(index)).map_or(false, |f| !live.contains(&f));
+            if dead {
+                *pkg.member_mut(index) = GosValue::new_nil(ValueType::Closure);
+            }
+        }
+    }
+    for (_, mt) in objs.metas.iter() {
+        if let MetadataType::Named(methods, _, _) = mt {
+            for m in methods.members.iter() {
+                let dead = m.borrow().func.map_or(false, |f| !live.contains(&f));
+                if dead {
+                    m.borrow_mut().func = None;
+                }
+            }
+        }
+    }
+    for (i, (_, bindings)) in ifaces.iter_mut().enumerate() {
+        if !live_ifaces.contains(&i) {
+            bindings.clear();
+        }
+    }
+    let dead: Vec<FunctionKey> = objs
+        .functions
+        .keys()
+        .filter(|k| !live.contains(k))
+        .collect();
+    for k in dead.iter() {
+        objs.functions.remove(*k);
+    }
+    dead.len()
+}
+
+fn func_key(val: &GosValue) -> Option<FunctionKey> {
+    match val.typ() {
+        ValueType::Function => Some(*val.as_function()),
+        ValueType::Closure => match val.as_closure() {
+            Some((ClosureObj::Gos(c), _)) => Some(c.func),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn meta_of(val: &GosValue) -> Option<Meta> {
+    match val.typ() {
+        ValueType::Metadata => Some(*val.as_metadata()),
+        _ => None,
+    }
+}
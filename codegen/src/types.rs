@@ -7,8 +7,8 @@ use goscript_parser::ast::Node;
 use goscript_parser::ast::{Expr, NodeId};
 use goscript_parser::objects::IdentKey;
 use goscript_types::{
-    BasicType, ChanDir, ConstValue, EntityType, ObjKey as TCObjKey, OperandMode,
-    PackageKey as TCPackageKey, TCObjects, Type, TypeInfo, TypeKey as TCTypeKey,
+    identical, BasicType, ChanDir, ConstValue, EntityType, ObjKey as TCObjKey, OperandMode,
+    PackageKey as TCPackageKey, SelectionKind, TCObjects, Type, TypeInfo, TypeKey as TCTypeKey,
 };
 use goscript_vm::gc::GcoVec;
 use goscript_vm::instruction::ValueType;
@@ -75,7 +75,8 @@ impl<'a> TypeLookup<'a> {
     pub fn expr_value_types(&self, e: &Expr) -> Vec<ValueType> {
         let typ_val = self.ti.types.get(&e.id()).unwrap();
         let tcts = match typ_val.mode {
-            OperandMode::Value => {
+            // e.g. a receive used as a statement: <-ch
+            OperandMode::Value | OperandMode::CommaOk => {
                 let typ = self.tc_objs.types[typ_val.typ].underlying_val(self.tc_objs);
                 match typ {
                     Type::Tuple(tp) => tp
@@ -169,7 +170,8 @@ impl<'a> TypeLookup<'a> {
         let typ = self.tc_objs.types[tc_type].underlying().unwrap_or(tc_type);
         let meta = self.tc_type_to_meta(typ, vm_objs, dummy_gcv);
         let metas = &vm_objs.metas;
-        match &metas[meta.key] {
+        // the base of a pointer to an array may be a named type
+        match &metas[meta.underlying(metas).key] {
             MetadataType::Array(m, _) => (ValueType::Array, m.value_type(&metas)),
             MetadataType::Slice(m) => (ValueType::Slice, m.value_type(&metas)),
             MetadataType::Str(_) => (ValueType::String, ValueType::Uint8),
@@ -177,6 +179,32 @@ impl<'a> TypeLookup<'a> {
         }
     }
 
+    /// The metadata of the element type of a map expression
+    pub fn map_elem_meta(
+        &mut self,
+        e: &Expr,
+        vm_objs: &mut VMObjects,
+        dummy_gcv: &mut GcoVec,
+    ) -> Meta {
+        let tc_type = self.expr_tc_type(&e);
+        let typ = self.tc_objs.types[tc_type].underlying().unwrap_or(tc_type);
+        let elem = match &self.tc_objs.types[typ] {
+            Type::Map(detail) => detail.elem(),
+            _ => unreachable!(),
+        };
+        self.tc_type_to_meta(elem, vm_objs, dummy_gcv)
+    }
+
+    /// The key type of a map expression
+    pub fn map_key_tc(&self, e: &Expr) -> TCTypeKey {
+        let tc_type = self.expr_tc_type(&e);
+        let typ = self.tc_objs.types[tc_type].underlying().unwrap_or(tc_type);
+        match &self.tc_objs.types[typ] {
+            Type::Map(detail) => detail.key(),
+            _ => unreachable!(),
+        }
+    }
+
     pub fn node_meta(
         &mut self,
         id: NodeId,
@@ -197,6 +225,11 @@ impl<'a> TypeLookup<'a> {
         self.ti.uses[&ikey]
     }
 
+    #[inline]
+    pub fn try_object_use(&self, ikey: IdentKey) -> Option<TCObjKey> {
+        self.ti.uses.get(&ikey).copied()
+    }
+
     #[inline]
     pub fn object_def(&self, ikey: IdentKey) -> TCObjKey {
         self.ti.defs[&ikey].unwrap()
@@ -223,6 +256,27 @@ impl<'a> TypeLookup<'a> {
     }
 
     #[inline]
+    /// The debug info of the local `okey` at `index`, None for `_`
+    pub fn local_var(&self, okey: TCObjKey, index: OpIndex) -> Option<LocalVar> {
+        let obj = &self.tc_objs.lobjs[okey];
+        if obj.name() == "_" {
+            return None;
+        }
+        let start = match *obj.scope_pos() {
+            0 => obj.pos(),
+            p => p,
+        };
+        let end = obj
+            .parent()
+            .map_or(usize::MAX, |s| self.tc_objs.scopes[s].end());
+        Some(LocalVar {
+            name: obj.name().clone(),
+            index: index,
+            start: start,
+            end: end,
+        })
+    }
+
     pub fn obj_tc_type(&self, okey: TCObjKey) -> TCTypeKey {
         let obj = &self.tc_objs.lobjs[okey];
         obj.typ().unwrap()
@@ -281,6 +335,17 @@ impl<'a> TypeLookup<'a> {
         (t0, t1, &sel.indices(), sel_typ)
     }
 
+    /// returns the receiver type if the selector is a method expression, like T.M
+    pub fn method_expr_recv(&self, id: NodeId) -> Option<TCTypeKey> {
+        self.ti
+            .selections
+            .get(&id)
+            .and_then(|sel| match sel.kind() {
+                SelectionKind::MethodExpr => sel.recv(),
+                _ => None,
+            })
+    }
+
     pub fn tc_type_to_meta(
         &mut self,
         typ: TCTypeKey,
@@ -288,12 +353,34 @@ impl<'a> TypeLookup<'a> {
         dummy_gcv: &mut GcoVec,
     ) -> Meta {
         if !self.types_cache.contains_key(&typ) {
-            let val = self.tc_type_to_meta_impl(typ, vm_objs, dummy_gcv);
+            let val = match self.identical_cached(typ) {
+                Some(meta) => meta,
+                None => self.tc_type_to_meta_impl(typ, vm_objs, dummy_gcv),
+            };
             self.types_cache.insert(typ, val);
         }
         self.types_cache.get(&typ).unwrap().clone()
     }
 
+    /// Unnamed composite types written more than once are the same type,
+    /// they share the metadata so that interfaces holding their values have
+    /// the same dynamic type
+    fn identical_cached(&self, typ: TCTypeKey) -> Option<Meta> {
+        let composite = |t: TCTypeKey| match &self.tc_objs.types[t] {
+            Type::Struct(_) | Type::Array(_) | Type::Slice(_) | Type::Map(_) | Type::Chan(_) => {
+                true
+            }
+            _ => false,
+        };
+        if !composite(typ) {
+            return None;
+        }
+        self.types_cache
+            .iter()
+            .find(|(&k, _)| composite(k) && identical(k, typ, self.tc_objs))
+            .map(|(_, &meta)| meta)
+    }
+
     pub fn sig_params_tc_types(&mut self, func: TCTypeKey) -> (Vec<TCTypeKey>, Option<TCTypeKey>) {
         let typ = &self.tc_objs.types[func].underlying_val(self.tc_objs);
         let sig = typ.try_as_signature().unwrap();
@@ -339,7 +426,8 @@ impl<'a> TypeLookup<'a> {
                     vm_objs.s_meta.mint32
                 }
                 BasicType::Int64 => vm_objs.s_meta.mint64,
-                BasicType::Uint | BasicType::Uintptr => vm_objs.s_meta.muint,
+                BasicType::Uint => vm_objs.s_meta.muint,
+                BasicType::Uintptr => vm_objs.s_meta.muint_ptr,
                 BasicType::Uint8 | BasicType::Byte => vm_objs.s_meta.muint8,
                 BasicType::Uint16 => vm_objs.s_meta.muint16,
                 BasicType::Uint32 => vm_objs.s_meta.muint32,
@@ -426,7 +514,7 @@ impl<'a> TypeLookup<'a> {
                 let (cr, ci, _) = val.to_complex().complex_as_complex64();
                 (GosValue::new_complex64(cr, ci), ValueType::Complex64)
             }
-            BasicType::Complex128 => {
+            BasicType::Complex128 | BasicType::UntypedComplex => {
                 let (cr, ci, _) = val.to_complex().complex_as_complex128();
                 (GosValue::new_complex128(cr, ci), ValueType::Complex128)
             }
@@ -444,6 +532,20 @@ impl<'a> TypeLookup<'a> {
         }
     }
 
+    /// qualified name of a named type as the runtime reports it, e.g. "main.Point"
+    fn named_type_name(&self, obj: &Option<TCObjKey>) -> String {
+        match obj {
+            Some(okey) => {
+                let lobj = &self.tc_objs.lobjs[*okey];
+                match lobj.pkg().and_then(|p| self.tc_objs.pkgs[p].name().clone()) {
+                    Some(pkg) => format!("{}.{}", pkg, lobj.name()),
+                    None => lobj.name().clone(),
+                }
+            }
+            None => String::new(),
+        }
+    }
+
     // get vm_type from tc_type
     fn tc_type_to_meta_impl(
         &mut self,
@@ -454,7 +556,7 @@ impl<'a> TypeLookup<'a> {
         match &self.tc_objs.types[typ] {
             Type::Basic(_) => self.basic_type_meta(typ, vm_objs).unwrap(),
             Type::Array(detail) => {
-                let elem = self.tc_type_to_meta_impl(detail.elem(), vm_objs, dummy_gcv);
+                let elem = self.tc_type_to_meta(detail.elem(), vm_objs, dummy_gcv);
                 Meta::new_array(elem, detail.len().unwrap() as usize, &mut vm_objs.metas)
             }
             Type::Slice(detail) => {
@@ -467,12 +569,15 @@ impl<'a> TypeLookup<'a> {
                 Meta::new_map(ktype, vtype, &mut vm_objs.metas)
             }
             Type::Struct(detail) => {
-                let fields = self.build_fields(detail.fields(), vm_objs, dummy_gcv);
+                let tags: Vec<Option<String>> = (0..detail.fields().len())
+                    .map(|i| detail.tag(i).cloned())
+                    .collect();
+                let fields = self.build_fields(detail.fields(), &tags, vm_objs, dummy_gcv);
                 Meta::new_struct(fields, vm_objs, dummy_gcv)
             }
             Type::Interface(detail) => {
                 let methods = detail.all_methods();
-                let fields = self.build_fields(methods.as_ref().unwrap(), vm_objs, dummy_gcv);
+                let fields = self.build_fields(methods.as_ref().unwrap(), &[], vm_objs, dummy_gcv);
                 Meta::new_interface(fields, &mut vm_objs.metas)
             }
             Type::Chan(detail) => {
@@ -527,7 +632,8 @@ impl<'a> TypeLookup<'a> {
             }
             Type::Named(detail) => {
                 // generate a Named with dummy underlying to avoid recursion
-                let md = Meta::new_named(vm_objs.s_meta.mint, &mut vm_objs.metas);
+                let name = self.named_type_name(detail.obj());
+                let md = Meta::new_named(name, vm_objs.s_meta.mint, &mut vm_objs.metas);
                 for key in detail.methods().iter() {
                     let mobj = &self.tc_objs.lobjs[*key];
                     md.add_method(
@@ -608,12 +714,18 @@ impl<'a> TypeLookup<'a> {
         let typ = self.tc_objs.types[typ].underlying().unwrap_or(typ);
         match &self.tc_objs.types[typ] {
             Type::Basic(detail) => match detail.typ() {
-                BasicType::Str | BasicType::UntypedString => [typ, t_int, t_int],
+                BasicType::Str | BasicType::UntypedString => {
+                    [typ, t_int, *self.tc_objs.universe().rune()]
+                }
                 _ => unreachable!(),
             },
             Type::Slice(detail) => [typ, t_int, detail.elem()],
             Type::Array(detail) => [typ, t_int, detail.elem()],
             Type::Map(detail) => [typ, detail.key(), detail.elem()],
+            // a channel yields only one value, the second is a placeholder
+            Type::Chan(detail) => [typ, detail.elem(), detail.elem()],
+            // a pointer to an array ranges over the array
+            Type::Pointer(detail) => self.range_tc_types(detail.base()),
             _ => {
                 dbg!(&self.tc_objs.types[typ]);
                 unreachable!()
@@ -635,6 +747,7 @@ impl<'a> TypeLookup<'a> {
     fn build_fields(
         &mut self,
         fields: &Vec<TCObjKey>,
+        tags: &[Option<String>],
         vm_objs: &mut VMObjects,
         dummy_gcv: &mut GcoVec,
     ) -> Fields {
@@ -651,6 +764,7 @@ impl<'a> TypeLookup<'a> {
                 name: field.name().clone(),
                 exported: is_exported,
                 embedded: is_embedded,
+                tag: tags.get(i).cloned().flatten(),
             });
             map.insert(field.name().clone(), vec![i]);
             if is_embedded {
@@ -6,7 +6,7 @@ use criterion::{criterion_group, criterion_main, Criterion};
 
 extern crate goscript_engine as engine;
 
-fn run(path: &str, trace: bool) -> Result<(), engine::ErrorList> {
+fn run(path: &str, trace: bool) -> Result<engine::RunResult, engine::ErrorList> {
     let mut cfg = engine::run_fs::Config::default();
     cfg.working_dir = Some("./");
     cfg.base_dir = Some("./std/");
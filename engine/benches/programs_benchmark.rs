@@ -0,0 +1,44 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Measures how fast the VM runs some of the test programs. The programs are
+//! compiled outside of the measured loop, so only the interpreter is timed.
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+
+extern crate goscript_codegen as cg;
+extern crate goscript_engine as engine;
+extern crate goscript_parser as fe;
+
+const PROGRAMS: &[(&str, &str)] = &[
+    ("leetcode5", "./tests/demo/leetcode5.gos"),
+    ("linked", "./tests/demo/linked.gos"),
+    ("gc", "./tests/group1/gc.gos"),
+    ("closure", "./tests/group1/closure4.gos"),
+    ("strings", "./tests/std/strings.gos"),
+    ("path", "./tests/std/path.gos"),
+];
+
+pub fn criterion_benchmark(c: &mut Criterion) {
+    let engine = engine::Engine::new();
+    let reader = cg::FsReader::new(Some("./"), Some("../std/"), None);
+    let mut group = c.benchmark_group("vm");
+    for (name, path) in PROGRAMS.iter() {
+        group.bench_function(*name, |b| {
+            b.iter_batched(
+                || {
+                    let mut fs = fe::FileSet::new();
+                    let code = engine.compile(&reader, path, &mut fs).unwrap();
+                    (code, fs)
+                },
+                |(code, fs)| engine.run_code(code, Some(&fs)),
+                BatchSize::PerIteration,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
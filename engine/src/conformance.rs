@@ -0,0 +1,387 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Conformance of the engine to the Go spec, feature by feature. Each
+//! feature is a small program under `engine/conformance`, asserting what
+//! the spec says about a section. A feature passes if its program runs to
+//! the end, fails if it panics or the compiler gets it wrong, and is
+//! unsupported if the compiler rejects or crashes on a valid program.
+
+extern crate goscript_parser as fe;
+extern crate goscript_types as types;
+extern crate goscript_vm as vm;
+
+use super::doc::{json_field, json_list};
+use super::engine::Engine;
+use super::util::panic_msg;
+use std::fmt::Write;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use types::SourceRead;
+use vm::vm::RunResult;
+
+/// A program checking a feature described in a section of the spec
+#[derive(Debug, Clone, Copy)]
+pub struct SpecFeature {
+    pub section: &'static str,
+    pub name: &'static str,
+    pub file: &'static str,
+    pub source: &'static str,
+}
+
+macro_rules! feature {
+    ($section:expr, $name:expr, $file:expr) => {
+        SpecFeature {
+            section: $section,
+            name: $name,
+            file: $file,
+            source: include_str!(concat!("../conformance/", $file)),
+        }
+    };
+}
+
+/// The features checked, in the order of the sections of the spec
+pub static CORPUS: &[SpecFeature] = &[
+    feature!(
+        "Lexical elements",
+        "Integer literals",
+        "integer_literals.gos"
+    ),
+    feature!(
+        "Lexical elements",
+        "Floating-point literals",
+        "float_literals.gos"
+    ),
+    feature!("Lexical elements", "Rune literals", "rune_literals.gos"),
+    feature!("Lexical elements", "String literals", "string_literals.gos"),
+    feature!("Constants", "Constants", "constants.gos"),
+    feature!("Types", "Array types", "array_types.gos"),
+    feature!("Types", "Slice types", "slice_types.gos"),
+    feature!("Types", "Struct types", "struct_types.gos"),
+    feature!("Types", "Pointer types", "pointer_types.gos"),
+    feature!("Types", "Function types", "function_types.gos"),
+    feature!("Types", "Interface types", "interface_types.gos"),
+    feature!("Types", "Map types", "map_types.gos"),
+    feature!("Types", "Channel types", "channel_types.gos"),
+    feature!("Types", "Type parameters", "type_parameters.gos"),
+    feature!("Declarations and scope", "Iota", "iota.gos"),
+    feature!(
+        "Declarations and scope",
+        "Type declarations",
+        "type_declarations.gos"
+    ),
+    feature!(
+        "Declarations and scope",
+        "Short variable declarations",
+        "short_var_decls.gos"
+    ),
+    feature!(
+        "Declarations and scope",
+        "Method declarations",
+        "method_declarations.gos"
+    ),
+    feature!(
+        "Expressions",
+        "Composite literals",
+        "composite_literals.gos"
+    ),
+    feature!("Expressions", "Function literals", "function_literals.gos"),
+    feature!("Expressions", "Slice expressions", "slice_expressions.gos"),
+    feature!("Expressions", "Type assertions", "type_assertions.gos"),
+    feature!(
+        "Expressions",
+        "Passing arguments to ... parameters",
+        "variadic_calls.gos"
+    ),
+    feature!(
+        "Expressions",
+        "Arithmetic operators",
+        "arithmetic_operators.gos"
+    ),
+    feature!(
+        "Expressions",
+        "Comparison operators",
+        "comparison_operators.gos"
+    ),
+    feature!("Expressions", "Conversions", "conversions.gos"),
+    feature!("Expressions", "Order of evaluation", "evaluation_order.gos"),
+    feature!("Statements", "Switch statements", "switch_statements.gos"),
+    feature!("Statements", "For statements", "for_statements.gos"),
+    feature!("Statements", "Labeled statements", "labeled_statements.gos"),
+    feature!("Statements", "Go statements", "go_statements.gos"),
+    feature!("Statements", "Select statements", "select_statements.gos"),
+    feature!("Statements", "Defer statements", "defer_statements.gos"),
+    feature!(
+        "Built-in functions",
+        "Appending to and copying slices",
+        "append_copy.gos"
+    ),
+    feature!(
+        "Built-in functions",
+        "Manipulating complex numbers",
+        "complex_numbers.gos"
+    ),
+    feature!("Built-in functions", "Handling panics", "panic_recover.gos"),
+    feature!(
+        "Program initialization and execution",
+        "Package initialization",
+        "package_init.gos"
+    ),
+    feature!("Run-time panics", "Run-time panics", "runtime_panics.gos"),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Pass,
+    /// the program compiled but did not do what the spec says, with why
+    Fail(String),
+    /// the program was rejected or crashed the compiler, with the first error
+    Unsupported(String),
+}
+
+impl Outcome {
+    pub fn status(&self) -> &'static str {
+        match self {
+            Outcome::Pass => "pass",
+            Outcome::Fail(_) => "fail",
+            Outcome::Unsupported(_) => "unsupported",
+        }
+    }
+
+    pub fn detail(&self) -> &str {
+        match self {
+            Outcome::Pass => "",
+            Outcome::Fail(s) | Outcome::Unsupported(s) => s,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FeatureResult {
+    pub feature: SpecFeature,
+    pub outcome: Outcome,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ConformanceReport {
+    pub results: Vec<FeatureResult>,
+}
+
+impl ConformanceReport {
+    pub fn passed(&self) -> usize {
+        self.count("pass")
+    }
+
+    pub fn failed(&self) -> usize {
+        self.count("fail")
+    }
+
+    pub fn unsupported(&self) -> usize {
+        self.count("unsupported")
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Outcome> {
+        self.results
+            .iter()
+            .find(|r| r.feature.name == name)
+            .map(|r| &r.outcome)
+    }
+
+    /// The report as a JSON object, with the counts and a list of the
+    /// features with their section, status and what went wrong
+    pub fn to_json(&self) -> String {
+        let mut s = String::new();
+        write!(
+            s,
+            "{{\"total\":{},\"passed\":{},\"failed\":{},\"unsupported\":{},\"features\":",
+            self.results.len(),
+            self.passed(),
+            self.failed(),
+            self.unsupported()
+        )
+        .unwrap();
+        json_list(&mut s, &self.results, FeatureResult::write_json);
+        s.push('}');
+        s
+    }
+
+    fn count(&self, status: &str) -> usize {
+        self.results
+            .iter()
+            .filter(|r| r.outcome.status() == status)
+            .count()
+    }
+}
+
+impl FeatureResult {
+    fn write_json(&self, s: &mut String) {
+        s.push('{');
+        json_field(s, "section", self.feature.section);
+        s.push(',');
+        json_field(s, "name", self.feature.name);
+        s.push(',');
+        json_field(s, "file", self.feature.file);
+        s.push(',');
+        json_field(s, "status", self.outcome.status());
+        s.push(',');
+        json_field(s, "detail", self.outcome.detail());
+        s.push('}');
+    }
+}
+
+/// The directory the programs of the features seem to be in, the imports
+/// of the programs are resolved by the reader passed to `run`
+const DIR: &str = "__conformance__";
+
+/// Runs every feature of `CORPUS` with `engine`
+pub fn run<S: SourceRead>(engine: &Engine, reader: &S) -> ConformanceReport {
+    ConformanceReport {
+        results: CORPUS
+            .iter()
+            .map(|f| FeatureResult {
+                feature: *f,
+                outcome: run_feature(engine, reader, f),
+            })
+            .collect(),
+    }
+}
+
+fn run_feature<S: SourceRead>(engine: &Engine, reader: &S, feature: &SpecFeature) -> Outcome {
+    let reader = WithFeature {
+        inner: reader,
+        path: Path::new(DIR).join(feature.file),
+        source: feature.source,
+    };
+    let path = format!("./{}/{}", DIR, feature.file);
+    let mut fs = fe::FileSet::new();
+    let compiled =
+        panic::catch_unwind(AssertUnwindSafe(|| engine.compile(&reader, &path, &mut fs)));
+    let code = match compiled {
+        Ok(Ok(code)) => code,
+        Ok(Err(el)) => {
+            let errors = el.borrow();
+            // asserts of constants are checked by the type checker
+            return match errors
+                .iter()
+                .find(|e| !e.msg.starts_with("assert(") || !e.msg.ends_with(" failed"))
+            {
+                Some(e) => Outcome::Unsupported(error_msg(e)),
+                None => Outcome::Fail(error_msg(&errors[0])),
+            };
+        }
+        Err(p) => return Outcome::Unsupported(format!("compiler panicked: {}", panic_msg(&p))),
+    };
+    match panic::catch_unwind(AssertUnwindSafe(|| engine.run_code(code, Some(&fs)))) {
+        Ok(RunResult::Done) => Outcome::Pass,
+        Ok(RunResult::Exit(code)) => Outcome::Fail(format!("exit status {}", code)),
+        Ok(RunResult::Panic(p)) => Outcome::Fail(format!("panic: {}", p.message)),
+        Ok(RunResult::LimitExceeded(limit)) => Outcome::Fail(limit.to_string()),
+        Ok(RunResult::Deadlock(_)) => {
+            Outcome::Fail("all goroutines are asleep - deadlock!".to_owned())
+        }
+        Err(p) => match panic_msg(&p) {
+            "ASSERT" => Outcome::Fail("assertion failed".to_owned()),
+            msg => Outcome::Fail(format!("vm panicked: {}", msg)),
+        },
+    }
+}
+
+/// The error without the path of the file, which the report already has
+fn error_msg(e: &fe::errors::Error) -> String {
+    format!("{}:{}: {}", e.pos.line, e.pos.column, e.msg)
+}
+
+/// The reader of a program, with the source of a feature added
+struct WithFeature<'a, S> {
+    inner: &'a S,
+    path: PathBuf,
+    source: &'static str,
+}
+
+impl<'a, S: SourceRead> SourceRead for WithFeature<'a, S> {
+    fn working_dir(&self) -> io::Result<PathBuf> {
+        self.inner.working_dir()
+    }
+
+    fn base_dir(&self) -> Option<&str> {
+        self.inner.base_dir()
+    }
+
+    fn read_file(&self, path: &Path) -> io::Result<String> {
+        if path.ends_with(&self.path) {
+            Ok(self.source.to_owned())
+        } else {
+            self.inner.read_file(path)
+        }
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        self.inner.read_dir(path)
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        path.ends_with(&self.path) || self.inner.is_file(path)
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        self.inner.is_dir(path)
+    }
+
+    fn canonicalize_path(&self, path: &PathBuf) -> io::Result<PathBuf> {
+        if path.ends_with(&self.path) {
+            Ok(path.clone())
+        } else {
+            self.inner.canonicalize_path(path)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use goscript_codegen::MapReader;
+
+    #[test]
+    fn test_conformance_report() {
+        let engine = Engine::new();
+        let report = engine.conformance_report(&MapReader::new(Some("std/")));
+        assert_eq!(report.results.len(), CORPUS.len());
+        assert_eq!(
+            report.passed() + report.failed() + report.unsupported(),
+            CORPUS.len()
+        );
+        // the known gaps, every other feature must pass
+        let gaps = [
+            ("Integer literals", "fail"),
+            ("Floating-point literals", "unsupported"),
+            ("String literals", "fail"),
+            ("Type parameters", "unsupported"),
+            ("Composite literals", "unsupported"),
+            ("Manipulating complex numbers", "fail"),
+            ("Run-time panics", "fail"),
+        ];
+        assert!(gaps.iter().all(|(name, _)| report.get(name).is_some()));
+        for r in report.results.iter() {
+            let want = gaps
+                .iter()
+                .find(|(name, _)| *name == r.feature.name)
+                .map_or("pass", |(_, status)| status);
+            assert_eq!(
+                r.outcome.status(),
+                want,
+                "{}: {}",
+                r.feature.name,
+                r.outcome.detail()
+            );
+        }
+
+        let json = report.to_json();
+        assert!(json.starts_with(&format!("{{\"total\":{},", CORPUS.len())));
+        assert!(json.contains(
+            "{\"section\":\"Statements\",\"name\":\"Defer statements\",\
+            \"file\":\"defer_statements.gos\",\"status\":\"pass\",\"detail\":\"\"}"
+        ));
+    }
+}
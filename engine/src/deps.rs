@@ -0,0 +1,310 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! The import graph of a program, for build tools and bundlers that need
+//! to know which files a program is made of before compiling it.
+
+extern crate goscript_parser as fe;
+extern crate goscript_types as types;
+
+use fe::ast::Spec;
+use fe::errors::ErrorList;
+use fe::objects::Objects as AstObjects;
+use std::collections::{HashMap, VecDeque};
+use types::{ImportKey, SourceRead};
+
+/// A package in the graph, `imports` are the import paths of its
+/// resolved imports, in the order they first appear in its files.
+#[derive(Debug)]
+pub struct PackageNode {
+    pub path: String,
+    pub dir: String,
+    pub files: Vec<String>,
+    pub imports: Vec<String>,
+}
+
+/// An import that could not be resolved to a package with source files.
+#[derive(Debug)]
+pub struct UnresolvedImport {
+    /// Import path of the package containing the import
+    pub importer: String,
+    pub path: String,
+    /// Position of the import spec
+    pub pos: String,
+    pub reason: String,
+}
+
+#[derive(Debug)]
+pub struct DependencyGraph {
+    pub root: String,
+    /// All reachable packages, dependencies before the packages importing
+    /// them, except within cycles.
+    pub packages: Vec<PackageNode>,
+    pub unresolved: Vec<UnresolvedImport>,
+    /// Each cycle is a group of packages that import each other, by import
+    /// path.
+    pub cycles: Vec<Vec<String>>,
+}
+
+impl DependencyGraph {
+    /// Resolves the imports of the package at `path` and of everything it
+    /// imports, the files are parsed but not type-checked. Fails only if a
+    /// file can not be parsed.
+    pub fn new<S: SourceRead>(reader: &S, path: &str) -> Result<DependencyGraph, ErrorList> {
+        let fs = &mut fe::FileSet::new();
+        let asto = &mut AstObjects::new();
+        let el = ErrorList::new();
+
+        let mut nodes: Vec<PackageNode> = vec![];
+        // packages are told apart by their directories, as different local
+        // import paths can refer to the same one
+        let mut indices: HashMap<String, usize> = HashMap::new();
+        let mut unresolved = vec![];
+        let mut root = None;
+        // (index of the importer, import key, position of the import spec)
+        let mut queue = VecDeque::new();
+        queue.push_back((None, ImportKey::new(path, "./"), String::new()));
+        while let Some((importer, key, pos)) = queue.pop_front() {
+            if key.path == "unsafe" {
+                continue;
+            }
+            let resolved = types::resolve_import(reader, &key).and_then(|(pb, import_path)| {
+                let dir = pb.to_string_lossy().to_string();
+                if let Some(&i) = indices.get(&dir) {
+                    return Ok((nodes[i].path.clone(), None));
+                }
+                match types::read_content(pb.as_path(), reader) {
+                    Ok(contents) => Ok((import_path, Some((dir, contents)))),
+                    Err(e) => Err(format!("failed to read from path: {}, {}", dir, e)),
+                }
+            });
+            let (import_path, new_pkg) = match resolved {
+                Ok(r) => r,
+                Err(e) => {
+                    unresolved.push(UnresolvedImport {
+                        importer: importer
+                            .map(|i: usize| nodes[i].path.clone())
+                            .unwrap_or_default(),
+                        path: key.path,
+                        pos: pos,
+                        reason: e,
+                    });
+                    continue;
+                }
+            };
+            if let Some(i) = importer {
+                if !nodes[i].imports.contains(&import_path) {
+                    nodes[i].imports.push(import_path.clone());
+                }
+            }
+            let (dir, contents) = match new_pkg {
+                Some(x) => x,
+                None => continue,
+            };
+            let mut node = PackageNode {
+                path: import_path.clone(),
+                dir: dir.clone(),
+                files: vec![],
+                imports: vec![],
+            };
+            for (full_name, content) in contents.into_iter() {
+                let mut pfile =
+                    fs.add_file(full_name.clone(), Some(fs.base()), content.chars().count());
+                let afile = fe::Parser::new(asto, &mut pfile, &el, &content, false).parse_file();
+                let afile = match afile {
+                    Some(f) => f,
+                    None => return Err(el),
+                };
+                let dir = file_dir(&full_name);
+                for skey in afile.imports.iter() {
+                    if let Spec::Import(ispec) = &asto.specs[*skey] {
+                        let lit = ispec.path.token.get_literal();
+                        let ipath = lit.trim_matches(|c| c == '"' || c == '`');
+                        let ipos = fs.position(ispec.path.pos).unwrap().to_string();
+                        let ikey = ImportKey::new(ipath, &dir);
+                        queue.push_back((Some(nodes.len()), ikey, ipos));
+                    }
+                }
+                node.files.push(full_name);
+            }
+            if root.is_none() {
+                root = Some(import_path.clone());
+            }
+            indices.insert(dir, nodes.len());
+            nodes.push(node);
+        }
+
+        let by_path = nodes
+            .iter()
+            .enumerate()
+            .map(|(i, x)| (x.path.clone(), i))
+            .collect();
+        let (order, cycles) = sort_packages(&nodes, &by_path);
+        let mut slots: Vec<Option<PackageNode>> = nodes.into_iter().map(Some).collect();
+        let packages = order
+            .into_iter()
+            .map(|i| slots[i].take().unwrap())
+            .collect();
+        Ok(DependencyGraph {
+            root: root.unwrap_or_default(),
+            packages: packages,
+            unresolved: unresolved,
+            cycles: cycles,
+        })
+    }
+
+    pub fn package(&self, path: &str) -> Option<&PackageNode> {
+        self.packages.iter().find(|x| x.path == path)
+    }
+
+    /// Returns the files of all the packages, in the order of `packages`.
+    pub fn files(&self) -> Vec<&str> {
+        self.packages
+            .iter()
+            .flat_map(|x| x.files.iter().map(|f| f.as_str()))
+            .collect()
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.unresolved.is_empty() && self.cycles.is_empty()
+    }
+}
+
+/// Returns the package indices in post order and the strongly connected
+/// components with more than one package, or one that imports itself
+/// (Tarjan's algorithm).
+fn sort_packages(
+    nodes: &[PackageNode],
+    indices: &HashMap<String, usize>,
+) -> (Vec<usize>, Vec<Vec<String>>) {
+    struct State<'a> {
+        nodes: &'a [PackageNode],
+        indices: &'a HashMap<String, usize>,
+        index: Vec<Option<usize>>,
+        low: Vec<usize>,
+        on_stack: Vec<bool>,
+        stack: Vec<usize>,
+        next: usize,
+        order: Vec<usize>,
+        cycles: Vec<Vec<String>>,
+    }
+
+    fn visit(s: &mut State, v: usize) {
+        s.index[v] = Some(s.next);
+        s.low[v] = s.next;
+        s.next += 1;
+        s.stack.push(v);
+        s.on_stack[v] = true;
+        for imp in s.nodes[v].imports.iter() {
+            let w = s.indices[imp];
+            match s.index[w] {
+                None => {
+                    visit(s, w);
+                    s.low[v] = s.low[v].min(s.low[w]);
+                }
+                Some(i) if s.on_stack[w] => s.low[v] = s.low[v].min(i),
+                _ => {}
+            }
+        }
+        if Some(s.low[v]) == s.index[v] {
+            let mut comp = vec![];
+            loop {
+                let w = s.stack.pop().unwrap();
+                s.on_stack[w] = false;
+                comp.push(w);
+                if w == v {
+                    break;
+                }
+            }
+            let self_import = s.nodes[v].imports.contains(&s.nodes[v].path);
+            if comp.len() > 1 || self_import {
+                comp.reverse();
+                s.cycles
+                    .push(comp.iter().map(|&i| s.nodes[i].path.clone()).collect());
+            }
+            s.order.extend(comp);
+        }
+    }
+
+    let count = nodes.len();
+    let mut s = State {
+        nodes: nodes,
+        indices: indices,
+        index: vec![None; count],
+        low: vec![0; count],
+        on_stack: vec![false; count],
+        stack: vec![],
+        next: 0,
+        order: vec![],
+        cycles: vec![],
+    };
+    for v in 0..count {
+        if s.index[v].is_none() {
+            visit(&mut s, v);
+        }
+    }
+    (s.order, s.cycles)
+}
+
+/// Same as the checker's, local imports are relative to the directory of
+/// the importing file.
+fn file_dir(path: &str) -> String {
+    if let Some((i, _)) = path.rmatch_indices(&['/', '\\'][..]).next() {
+        if i > 0 {
+            return path[0..i].to_owned();
+        }
+    }
+    ".".to_owned()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use goscript_codegen::FsReader;
+
+    #[test]
+    fn test_dependency_graph() {
+        let source = r#"
+    package main
+
+    import (
+        "fmt"
+        "strings"
+        "unsafe"
+        "no/such/pkg"
+    )
+
+    func main() {
+        fmt.Println(strings.ToUpper("a"), unsafe.Sizeof(1))
+    }
+    "#;
+        let reader = FsReader::new(Some("./"), Some("../std/"), Some(source));
+        let graph = DependencyGraph::new(&reader, FsReader::temp_file_path()).unwrap();
+        let root = graph.package(&graph.root).unwrap();
+        assert_eq!(root.imports, vec!["fmt", "strings"]);
+        assert_eq!(root.files.len(), 1);
+        // the root comes after everything it imports
+        assert_eq!(graph.packages.last().unwrap().path, graph.root);
+        let pos = |p: &str| graph.packages.iter().position(|x| x.path == p);
+        assert!(pos("fmt").is_some() && pos("fmt") < pos(&graph.root));
+        assert!(graph.package("fmt").unwrap().files.len() > 0);
+
+        assert_eq!(graph.unresolved.len(), 1);
+        assert_eq!(graph.unresolved[0].path, "no/such/pkg");
+        assert_eq!(graph.unresolved[0].importer, graph.root);
+        assert!(graph.cycles.is_empty());
+        assert!(!graph.is_complete());
+    }
+
+    #[test]
+    fn test_import_cycle() {
+        let reader = FsReader::new(Some("./"), Some("../std/"), None);
+        let graph = DependencyGraph::new(&reader, "./tests/deps/main.gos").unwrap();
+        assert_eq!(graph.packages.len(), 3);
+        assert_eq!(graph.cycles, vec![vec!["./a", "../b"]]);
+        // "../a" in b is the same package as "./a" in main
+        assert_eq!(graph.package("../b").unwrap().imports, vec!["./a"]);
+        assert!(graph.unresolved.is_empty());
+    }
+}
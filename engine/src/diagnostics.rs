@@ -0,0 +1,188 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! The errors and warnings of a compilation as structured diagnostics, for
+//! editors and CI tools.
+
+extern crate goscript_parser as fe;
+extern crate goscript_types as types;
+
+use super::doc::{json_field, json_list};
+use super::engine::Engine;
+use fe::errors::Diagnostic;
+use fe::FilePos;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::path::Path;
+use types::SourceRead;
+
+/// Compiles the program at `path` and returns its errors, or its warnings if
+/// it compiles, with the spans covering the tokens they are reported at.
+pub(crate) fn collect<S: SourceRead>(engine: &Engine, reader: &S, path: &str) -> Vec<Diagnostic> {
+    let mut fs = fe::FileSet::new();
+    let el = match engine.compile(reader, path, &mut fs) {
+        Ok(_) => engine.warnings(),
+        Err(el) => el,
+    };
+    let mut diags = el.diagnostics();
+    let mut sources: HashMap<String, Option<Vec<char>>> = HashMap::new();
+    for d in diags.iter_mut() {
+        if d.file.is_empty() {
+            continue;
+        }
+        let src = sources
+            .entry(d.file.clone())
+            .or_insert_with(|| read_source(reader, &d.file));
+        if let Some(src) = src {
+            let len = token_len(src, d.start.offset);
+            d.end = FilePos {
+                filename: d.start.filename.clone(),
+                offset: d.start.offset + len,
+                line: d.start.line,
+                column: d.start.column + len,
+            };
+        }
+    }
+    diags
+}
+
+/// The diagnostics as a JSON list, positions are 1-based lines and columns
+/// in chars
+pub fn to_json(diags: &[Diagnostic]) -> String {
+    let mut s = String::new();
+    json_list(&mut s, diags, write_json);
+    s
+}
+
+fn write_json(d: &Diagnostic, s: &mut String) {
+    s.push('{');
+    json_field(s, "severity", d.severity.as_str());
+    s.push(',');
+    json_field(s, "code", d.code);
+    s.push(',');
+    json_field(s, "file", &d.file);
+    s.push_str(",\"start\":");
+    write_pos(&d.start, s);
+    s.push_str(",\"end\":");
+    write_pos(&d.end, s);
+    s.push(',');
+    json_field(s, "message", &d.message);
+    s.push_str(",\"related\":");
+    json_list(s, &d.related, |(pos, msg), s| {
+        s.push_str("{\"pos\":");
+        write_pos(pos, s);
+        s.push(',');
+        json_field(s, "message", msg);
+        s.push('}');
+    });
+    s.push('}');
+}
+
+fn write_pos(pos: &FilePos, s: &mut String) {
+    write!(s, "{{\"line\":{},\"column\":{}}}", pos.line, pos.column).unwrap();
+}
+
+/// Reads the file a diagnostic is in, its name may be relative to the
+/// working directory of the reader
+fn read_source<S: SourceRead>(reader: &S, file: &str) -> Option<Vec<char>> {
+    let path = Path::new(file);
+    reader
+        .read_file(path)
+        .or_else(|e| match reader.working_dir() {
+            Ok(wd) if path.is_relative() => reader.read_file(&wd.join(path)),
+            _ => Err(e),
+        })
+        .ok()
+        .map(|s| s.chars().collect())
+}
+
+/// The length of the token starting at `offset`: a name or a number, a
+/// string literal on one line or else a single char
+fn token_len(src: &[char], offset: usize) -> usize {
+    let rest = match src.get(offset..) {
+        Some(r) if !r.is_empty() => r,
+        _ => return 0,
+    };
+    let word = |c: &char| c.is_alphanumeric() || *c == '_';
+    if word(&rest[0]) {
+        return rest.iter().take_while(|c| word(c)).count();
+    }
+    if let '"' | '`' | '\'' = rest[0] {
+        let quote = rest[0];
+        let mut escaped = false;
+        for (i, c) in rest.iter().enumerate().skip(1) {
+            match c {
+                '\n' => break,
+                '\\' if quote != '`' => escaped = !escaped,
+                c if *c == quote && !escaped => return i + 1,
+                _ => escaped = false,
+            }
+        }
+    }
+    1
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use fe::errors::Severity;
+    use goscript_codegen::FsReader;
+
+    #[test]
+    fn test_diagnostics() {
+        let source = r#"
+package main
+
+import "fmt"
+
+type T int
+
+func (T) m() {}
+
+func main() {
+    count := 1
+    fmt.Println(undefinedName)
+    var s string = 1
+    _ = s
+}
+
+func (T) m() {}
+"#;
+        let engine = Engine::new();
+        let reader = FsReader::new(Some("./"), Some("../std/"), Some(source));
+        let diags = collect(&engine, &reader, FsReader::temp_file_path());
+        let msgs: Vec<&str> = diags.iter().map(|d| &d.message[..]).collect();
+        assert_eq!(
+            msgs,
+            [
+                "declared and not used: count",
+                "undeclared name: undefinedName",
+                "cannot convert 1 (untyped int constant 1) to string",
+                "method T.m already declared",
+            ]
+        );
+        let d = &diags[1];
+        assert_eq!(d.severity, Severity::Error);
+        assert_eq!(d.code, "type");
+        assert!(d.file.ends_with(FsReader::temp_file_path()));
+        assert_eq!((d.start.line, d.start.column), (12, 17));
+        assert_eq!((d.end.line, d.end.column), (12, 30));
+        assert_eq!(diags[2].end.column - diags[2].start.column, 1);
+        assert_eq!(diags[3].related.len(), 1);
+        assert_eq!(diags[3].related[0].0.line, 8);
+
+        let json = to_json(&diags);
+        assert!(json.starts_with("[{\"severity\":\"error\",\"code\":\"type\",\"file\":"));
+        assert!(json.contains("\"start\":{\"line\":12,\"column\":17},\"end\":{\"line\":12,\"column\":30},\"message\":\"undeclared name: undefinedName\",\"related\":[]"));
+        assert!(json.contains("\"related\":[{\"pos\":{\"line\":8,\"column\":10},\"message\":\"other declaration of m\"}]"));
+
+        let src: Vec<char> = "x := \"a\\\"b\" + `c\\` + 'd'".chars().collect();
+        assert_eq!(token_len(&src, 0), 1);
+        assert_eq!(token_len(&src, 2), 1);
+        assert_eq!(token_len(&src, 5), 6);
+        assert_eq!(token_len(&src, 14), 4);
+        assert_eq!(token_len(&src, 21), 3);
+        assert_eq!(token_len(&src, 24), 0);
+    }
+}
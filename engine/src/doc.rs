@@ -0,0 +1,485 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Documentation of the exported API of a package, like `go doc -all`, for
+//! hosts that show the API of their scripts to their users.
+
+extern crate goscript_parser as fe;
+extern crate goscript_types as types;
+
+use fe::ast::{self, Decl, Expr, FuncDecl, Spec};
+use fe::errors::ErrorList;
+use fe::objects::Objects as AstObjects;
+use fe::Token;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt::Write;
+use types::{SourceRead, TCObjects, TypeInfo};
+
+#[derive(Debug, Default)]
+pub struct PackageDoc {
+    pub name: String,
+    pub path: String,
+    pub doc: String,
+    pub consts: Vec<ValueDoc>,
+    pub vars: Vec<ValueDoc>,
+    pub funcs: Vec<FuncDoc>,
+    pub types: Vec<TypeDoc>,
+}
+
+/// An exported constant or variable, `decl` is the whole spec declaring it.
+#[derive(Debug)]
+pub struct ValueDoc {
+    pub name: String,
+    pub typ: String,
+    pub decl: String,
+    pub doc: String,
+}
+
+/// An exported function or method, `decl` is its signature.
+#[derive(Debug)]
+pub struct FuncDoc {
+    pub name: String,
+    pub decl: String,
+    pub doc: String,
+}
+
+/// An exported type with its exported methods.
+#[derive(Debug)]
+pub struct TypeDoc {
+    pub name: String,
+    pub decl: String,
+    pub doc: String,
+    pub methods: Vec<FuncDoc>,
+}
+
+impl PackageDoc {
+    /// Parses and type-checks the package at `path`, and collects the
+    /// exported symbols with their declarations in Go syntax and their doc
+    /// comments.
+    pub fn new<S: SourceRead>(reader: &S, path: &str) -> Result<PackageDoc, ErrorList> {
+        let cfg = types::TraceConfig {
+            trace_parser: false,
+            trace_checker: false,
+        };
+        let fs = &mut fe::FileSet::new();
+        let asto = &mut AstObjects::new();
+        let tco = &mut TCObjects::new();
+        let results = &mut HashMap::new();
+        let pkgs = &mut HashMap::new();
+        let el = ErrorList::new();
+        let key = types::ImportKey::new(path, "./");
+        let pkg =
+            types::Importer::new(&cfg, reader, fs, pkgs, results, asto, tco, &el, 0).import(&key);
+        if el.has_errors() {
+            return Err(el);
+        }
+        let pkg = pkg.unwrap();
+        // names of the package itself are not qualified, like in Go source
+        let own = tco.pkgs[pkg].path().clone();
+        tco.fmt_qualifier = Box::new(move |p| {
+            if *p.path() == own {
+                Cow::Borrowed("")
+            } else {
+                Cow::Owned(p.name().clone().unwrap_or_default())
+            }
+        });
+        let builder = DocBuilder {
+            asto: asto,
+            tco: tco,
+            info: &results[&pkg],
+        };
+        Ok(builder.build(tco.pkgs[pkg].path()))
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut s = String::new();
+        s.push('{');
+        json_field(&mut s, "name", &self.name);
+        s.push(',');
+        json_field(&mut s, "path", &self.path);
+        s.push(',');
+        json_field(&mut s, "doc", &self.doc);
+        s.push_str(",\"consts\":");
+        json_list(&mut s, &self.consts, ValueDoc::write_json);
+        s.push_str(",\"vars\":");
+        json_list(&mut s, &self.vars, ValueDoc::write_json);
+        s.push_str(",\"funcs\":");
+        json_list(&mut s, &self.funcs, FuncDoc::write_json);
+        s.push_str(",\"types\":");
+        json_list(&mut s, &self.types, TypeDoc::write_json);
+        s.push('}');
+        s
+    }
+
+    /// Returns the documentation in the format of `go doc -all`
+    pub fn to_text(&self) -> String {
+        let mut s = format!("package {} // import \"{}\"\n\n", self.name, self.path);
+        if !self.doc.is_empty() {
+            s.push_str(&self.doc);
+            s.push_str("\n\n");
+        }
+        text_section(&mut s, "CONSTANTS", value_items(&self.consts));
+        text_section(&mut s, "VARIABLES", value_items(&self.vars));
+        let funcs = self.funcs.iter().map(|f| (&f.decl[..], &f.doc[..]));
+        text_section(&mut s, "FUNCTIONS", funcs.collect());
+        if !self.types.is_empty() {
+            s.push_str("TYPES\n\n");
+            for t in self.types.iter() {
+                text_item(&mut s, &t.decl, &t.doc);
+                for m in t.methods.iter() {
+                    text_item(&mut s, &m.decl, &m.doc);
+                }
+            }
+        }
+        s
+    }
+}
+
+impl ValueDoc {
+    fn write_json(&self, s: &mut String) {
+        s.push('{');
+        json_field(s, "name", &self.name);
+        s.push(',');
+        json_field(s, "type", &self.typ);
+        s.push(',');
+        json_field(s, "decl", &self.decl);
+        s.push(',');
+        json_field(s, "doc", &self.doc);
+        s.push('}');
+    }
+}
+
+impl FuncDoc {
+    fn write_json(&self, s: &mut String) {
+        s.push('{');
+        json_field(s, "name", &self.name);
+        s.push(',');
+        json_field(s, "decl", &self.decl);
+        s.push(',');
+        json_field(s, "doc", &self.doc);
+        s.push('}');
+    }
+}
+
+impl TypeDoc {
+    fn write_json(&self, s: &mut String) {
+        s.push('{');
+        json_field(s, "name", &self.name);
+        s.push(',');
+        json_field(s, "decl", &self.decl);
+        s.push(',');
+        json_field(s, "doc", &self.doc);
+        s.push_str(",\"methods\":");
+        json_list(s, &self.methods, FuncDoc::write_json);
+        s.push('}');
+    }
+}
+
+struct DocBuilder<'a> {
+    asto: &'a AstObjects,
+    tco: &'a TCObjects,
+    info: &'a TypeInfo,
+}
+
+impl<'a> DocBuilder<'a> {
+    fn build(&self, path: &str) -> PackageDoc {
+        let mut pdoc = PackageDoc {
+            path: path.to_owned(),
+            ..Default::default()
+        };
+        let mut methods: HashMap<String, Vec<FuncDoc>> = HashMap::new();
+        for file in self.info.ast_files.iter() {
+            pdoc.name = self.asto.idents[file.name].name.clone();
+            if let (true, Some(doc)) = (pdoc.doc.is_empty(), &file.doc) {
+                pdoc.doc = doc.clone();
+            }
+            for decl in file.decls.iter() {
+                match decl {
+                    Decl::Gen(gdecl) => self.gen_decl(gdecl, &mut pdoc),
+                    Decl::Func(key) => {
+                        let fdecl = &self.asto.fdecls[*key];
+                        if !self.asto.idents[fdecl.name].is_exported() {
+                            continue;
+                        }
+                        match &fdecl.recv {
+                            Some(recv) => {
+                                let field = &self.asto.fields[recv.list[0]];
+                                if let Some(base) = self.base_type_name(&field.typ) {
+                                    let m = self.func_doc(fdecl);
+                                    methods.entry(base).or_default().push(m);
+                                }
+                            }
+                            None => pdoc.funcs.push(self.func_doc(fdecl)),
+                        }
+                    }
+                    Decl::Bad(_) => {}
+                }
+            }
+        }
+        for t in pdoc.types.iter_mut() {
+            if let Some(mut m) = methods.remove(&t.name) {
+                m.sort_by(|a, b| a.name.cmp(&b.name));
+                t.methods = m;
+            }
+        }
+        pdoc.funcs.sort_by(|a, b| a.name.cmp(&b.name));
+        pdoc.types.sort_by(|a, b| a.name.cmp(&b.name));
+        pdoc
+    }
+
+    fn gen_decl(&self, gdecl: &ast::GenDecl, pdoc: &mut PackageDoc) {
+        for skey in gdecl.specs.iter() {
+            match &self.asto.specs[*skey] {
+                Spec::Value(spec) => {
+                    let doc = spec.doc.as_ref().or(gdecl.doc.as_ref());
+                    let decl = self.value_decl(&gdecl.token, spec);
+                    for ident in spec.names.iter() {
+                        let name = &self.asto.idents[*ident];
+                        if !name.is_exported() {
+                            continue;
+                        }
+                        let typ = self.info.defs.get(ident).copied().flatten();
+                        let typ = typ.and_then(|o| self.tco.lobjs[o].typ());
+                        let val = ValueDoc {
+                            name: name.name.clone(),
+                            typ: typ.map_or(String::new(), |t| self.type_str(t)),
+                            decl: decl.clone(),
+                            doc: doc.cloned().unwrap_or_default(),
+                        };
+                        match gdecl.token {
+                            Token::CONST => pdoc.consts.push(val),
+                            _ => pdoc.vars.push(val),
+                        }
+                    }
+                }
+                Spec::Type(spec) => {
+                    let name = &self.asto.idents[spec.name];
+                    if !name.is_exported() {
+                        continue;
+                    }
+                    let assign = if spec.assign > 0 { "= " } else { "" };
+                    let doc = spec.doc.as_ref().or(gdecl.doc.as_ref());
+                    pdoc.types.push(TypeDoc {
+                        name: name.name.clone(),
+                        decl: format!("type {} {}{}", name.name, assign, self.expr_str(&spec.typ)),
+                        doc: doc.cloned().unwrap_or_default(),
+                        methods: vec![],
+                    });
+                }
+                Spec::Import(_) => {}
+            }
+        }
+    }
+
+    fn value_decl(&self, token: &Token, spec: &ast::ValueSpec) -> String {
+        let mut s = token.text().to_owned();
+        for (i, ident) in spec.names.iter().enumerate() {
+            s.push_str(if i > 0 { ", " } else { " " });
+            s.push_str(&self.asto.idents[*ident].name);
+        }
+        if let Some(t) = &spec.typ {
+            write!(s, " {}", self.expr_str(t)).unwrap();
+        }
+        for (i, v) in spec.values.iter().enumerate() {
+            s.push_str(if i > 0 { ", " } else { " = " });
+            s.push_str(&self.expr_str(v));
+        }
+        s
+    }
+
+    fn func_doc(&self, fdecl: &FuncDecl) -> FuncDoc {
+        let name = &self.asto.idents[fdecl.name].name;
+        let mut decl = "func ".to_owned();
+        if let Some(recv) = &fdecl.recv {
+            let field = &self.asto.fields[recv.list[0]];
+            decl.push('(');
+            for ident in field.names.iter() {
+                write!(decl, "{} ", self.asto.idents[*ident].name).unwrap();
+            }
+            write!(decl, "{}) ", self.expr_str(&field.typ)).unwrap();
+        }
+        // the type is rendered as "func(...)"
+        let sig = self.expr_str(&Expr::Func(fdecl.typ));
+        write!(decl, "{}{}", name, &sig["func".len()..]).unwrap();
+        FuncDoc {
+            name: name.clone(),
+            decl: decl,
+            doc: fdecl.doc.clone().unwrap_or_default(),
+        }
+    }
+
+    /// Returns the name of the receiver type if it is exported
+    fn base_type_name(&self, expr: &Expr) -> Option<String> {
+        match expr {
+            Expr::Ident(i) => {
+                let ident = &self.asto.idents[*i];
+                ident.is_exported().then(|| ident.name.clone())
+            }
+            Expr::Star(s) => self.base_type_name(&s.expr),
+            Expr::Paren(p) => self.base_type_name(&p.expr),
+            _ => None,
+        }
+    }
+
+    fn expr_str(&self, expr: &Expr) -> String {
+        types::Displayer::new(expr, Some(self.asto), None).to_string()
+    }
+
+    fn type_str(&self, t: types::TypeKey) -> String {
+        types::Displayer::new(&t, None, Some(self.tco)).to_string()
+    }
+}
+
+fn text_section(s: &mut String, title: &str, items: Vec<(&str, &str)>) {
+    if items.is_empty() {
+        return;
+    }
+    s.push_str(title);
+    s.push_str("\n\n");
+    for (decl, doc) in items.into_iter() {
+        text_item(s, decl, doc);
+    }
+}
+
+fn value_items(vals: &[ValueDoc]) -> Vec<(&str, &str)> {
+    let mut items: Vec<(&str, &str)> = vec![];
+    for val in vals.iter() {
+        // the names declared together share one entry
+        if items.last().map(|x| x.0) != Some(&val.decl[..]) {
+            items.push((&val.decl, &val.doc));
+        }
+    }
+    items
+}
+
+fn text_item(s: &mut String, decl: &str, doc: &str) {
+    s.push_str(decl);
+    s.push('\n');
+    for line in doc.lines() {
+        if !line.is_empty() {
+            s.push_str("    ");
+        }
+        s.push_str(line);
+        s.push('\n');
+    }
+    s.push('\n');
+}
+
+pub(crate) fn json_list<T>(s: &mut String, items: &[T], f: fn(&T, &mut String)) {
+    s.push('[');
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            s.push(',');
+        }
+        f(item, s);
+    }
+    s.push(']');
+}
+
+pub(crate) fn json_field(s: &mut String, key: &str, val: &str) {
+    json_str(s, key);
+    s.push(':');
+    json_str(s, val);
+}
+
+fn json_str(s: &mut String, val: &str) {
+    s.push('"');
+    for c in val.chars() {
+        match c {
+            '"' => s.push_str("\\\""),
+            '\\' => s.push_str("\\\\"),
+            '\n' => s.push_str("\\n"),
+            '\r' => s.push_str("\\r"),
+            '\t' => s.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(s, "\\u{:04x}", c as u32).unwrap(),
+            c => s.push(c),
+        }
+    }
+    s.push('"');
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use goscript_codegen::FsReader;
+
+    #[test]
+    fn test_package_doc() {
+        let source = r#"
+// Package main is a "demo".
+package main
+
+import "time"
+
+// Limits of the game
+const (
+    // MaxPlayers is the most players in a room
+    MaxPlayers, MinPlayers = 8, 2
+    hidden = 1
+)
+
+// Timeout is how long a turn lasts.
+var Timeout = 3 * time.Second
+
+// Player is in a room.
+type Player struct {
+    Name  string
+    score int
+}
+
+// Score returns the score.
+func (p *Player) Score() int { return p.score }
+
+func (p *Player) reset() {}
+
+// Join adds players,
+// it fails if the room is full.
+func Join(room string, names ...string) (n int, err error) { return }
+
+func Watch(f func(int) bool, done <-chan struct{}) {}
+
+func main() {}
+"#;
+        let reader = FsReader::new(Some("./"), Some("../std/"), Some(source));
+        let doc = PackageDoc::new(&reader, FsReader::temp_file_path()).unwrap();
+        assert_eq!(doc.name, "main");
+        assert_eq!(doc.doc, "Package main is a \"demo\".");
+        let names: Vec<&str> = doc.consts.iter().map(|c| &c.name[..]).collect();
+        assert_eq!(names, ["MaxPlayers", "MinPlayers"]);
+        assert_eq!(doc.consts[1].typ, "untyped int");
+        assert_eq!(doc.consts[1].decl, "const MaxPlayers, MinPlayers = 8, 2");
+        assert_eq!(
+            doc.consts[1].doc,
+            "MaxPlayers is the most players in a room"
+        );
+        assert_eq!(doc.vars[0].typ, "time.Duration");
+        assert_eq!(doc.vars[0].decl, "var Timeout = 3 * time.Second");
+        assert_eq!(doc.funcs.len(), 2);
+        assert_eq!(
+            doc.funcs[0].decl,
+            "func Join(room string, names ...string) (n int, err error)"
+        );
+        assert_eq!(
+            doc.funcs[0].doc,
+            "Join adds players,\nit fails if the room is full."
+        );
+        assert_eq!(
+            doc.funcs[1].decl,
+            "func Watch(f func(int) bool, done <-chan struct{})"
+        );
+        let player = &doc.types[0];
+        assert_eq!(player.decl, "type Player struct{Name string; score int}");
+        assert_eq!(player.methods.len(), 1);
+        assert_eq!(player.methods[0].decl, "func (p *Player) Score() int");
+
+        let text = doc.to_text();
+        assert!(text.contains("TYPES\n\ntype Player struct{Name string; score int}\n    Player is in a room.\n\nfunc (p *Player) Score() int\n    Score returns the score.\n"));
+        assert_eq!(text.matches("const MaxPlayers").count(), 1);
+        let json = doc.to_json();
+        assert!(json.starts_with("{\"name\":\"main\","));
+        assert!(json.contains("\"doc\":\"Package main is a \\\"demo\\\".\""));
+        assert!(json.contains("\"methods\":[{\"name\":\"Score\","));
+    }
+}
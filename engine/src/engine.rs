@@ -5,10 +5,12 @@
 pub use cg::SourceRead;
 use vm::ffi::FfiStatics;
 
-use super::ffi::Ffi;
+use super::ffi::{Ffi, FfiModule};
+use super::std::{host, kv, sql, Capabilities, StdSelection};
 use std::any::Any;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::cell::{Ref, RefMut};
+use std::collections::{BTreeMap, HashMap};
 use std::io;
 use std::rc::Rc;
 
@@ -24,6 +26,33 @@ pub struct StaticData {
     pub std_out: Option<Box<dyn io::Write>>,
 
     pub std_err: Option<Box<dyn io::Write>>,
+
+    pub sql_drivers: HashMap<String, Rc<dyn sql::Driver>>,
+
+    pub kv_store: Option<Rc<dyn kv::Store>>,
+
+    pub event_bus: host::EventBus,
+
+    /// os.Args of the running program
+    pub args: Vec<String>,
+
+    /// The environment variables, copied from the process when they are
+    /// first used if not set by the embedder
+    pub env: Option<BTreeMap<String, String>>,
+
+    /// The std modules the engine registered
+    pub std: StdSelection,
+
+    /// What the scripts are allowed to do with the registered modules
+    pub capabilities: Capabilities,
+}
+
+impl StaticData {
+    /// Returns the environment variables, copying them from the process
+    /// the first time if the embedder didn't set them
+    pub fn env_mut(&mut self) -> &mut BTreeMap<String, String> {
+        self.env.get_or_insert_with(|| std::env::vars().collect())
+    }
 }
 
 #[derive(Default, Clone)]
@@ -60,18 +89,67 @@ impl FfiStatics for Statics {
 pub struct Engine {
     ffi: vm::ffi::FfiFactory,
     statics: Statics,
+    gc_config: Cell<vm::gc::GcConfig>,
+    gc_stats: Cell<vm::gc::GcStats>,
+    exit_code: Cell<Option<i32>>,
+    program_name: RefCell<Option<String>>,
+    warnings: RefCell<fe::errors::ErrorList>,
+    step_observer: RefCell<Option<Rc<dyn vm::step::StepObserver>>>,
+    cast_audit: Cell<bool>,
+    limits: Cell<vm::limits::Limits>,
+    cast_report: RefCell<Vec<vm::audit::CastRecord>>,
+    alloc_profile: Cell<bool>,
+    alloc_report: RefCell<vm::profile::AllocReport>,
 }
 
 impl Engine {
+    /// Returns an engine with all the std modules
     pub fn new() -> Engine {
+        Engine::with_std(StdSelection::all()).unwrap()
+    }
+
+    /// Returns an engine with only the selected std modules, or an error if
+    /// a selected module needs one that is not selected. Which modules are
+    /// active is reported by `std_selection`.
+    pub fn with_std(selection: StdSelection) -> Result<Engine, String> {
+        selection.validate()?;
         let statics = Statics::default();
+        statics.borrow_data_mut().std = selection.clone();
         let ffi = vm::ffi::FfiFactory::new(Box::new(statics.clone()));
         let mut e = Engine {
             ffi: ffi,
             statics: statics,
+            gc_config: Cell::new(vm::gc::GcConfig::default()),
+            gc_stats: Cell::new(vm::gc::GcStats::default()),
+            exit_code: Cell::new(None),
+            program_name: RefCell::new(None),
+            warnings: RefCell::new(fe::errors::ErrorList::new()),
+            step_observer: RefCell::new(None),
+            cast_audit: Cell::new(false),
+            limits: Cell::new(vm::limits::Limits::default()),
+            cast_report: RefCell::new(vec![]),
+            alloc_profile: Cell::new(false),
+            alloc_report: RefCell::new(vm::profile::AllocReport::default()),
         };
-        crate::std::register(&mut e);
-        e
+        crate::std::register(&mut e, &selection);
+        Ok(e)
+    }
+
+    /// Returns the std modules registered by the engine
+    pub fn std_selection(&self) -> StdSelection {
+        self.statics.borrow_data().std.clone()
+    }
+
+    /// Sets what the scripts are allowed to do, they can do everything the
+    /// registered std modules offer if not set. The calls needing a
+    /// capability that is not granted return an error.
+    pub fn set_capabilities(&self, capabilities: Capabilities) {
+        self.statics.borrow_data_mut().capabilities = capabilities;
+    }
+
+    /// Returns the capabilities granted to the scripts
+    pub fn capabilities(&self) -> Capabilities {
+        self.statics.borrow_data().capabilities.clone()
     }
 
     pub fn set_std_io(
@@ -86,24 +164,1537 @@ impl Engine {
         borrow.std_err = std_err;
     }
 
-    pub fn register_extension(&mut self, name: &'static str, proto: Rc<dyn Ffi>) {
+    /// Registers a driver for the `sql` std package, scripts refer to it by
+    /// the name in `sql.Open`.
+    pub fn register_sql_driver(&self, name: &str, driver: Rc<dyn sql::Driver>) {
+        self.statics
+            .borrow_data_mut()
+            .sql_drivers
+            .insert(name.to_owned(), driver);
+    }
+
+    /// Sets the store behind the `kv` std package, it is a `kv::MemStore`
+    /// if not set.
+    pub fn set_kv_store(&self, store: Rc<dyn kv::Store>) {
+        self.statics.borrow_data_mut().kv_store = Some(store);
+    }
+
+    /// Returns the bus behind the `host` std package, for the embedder to
+    /// exchange events with the scripts.
+    pub fn event_bus(&self) -> host::EventBus {
+        self.statics.borrow_data().event_bus.clone()
+    }
+
+    /// Sets when the garbage collector is triggered in the following runs
+    pub fn set_gc_config(&self, config: vm::gc::GcConfig) {
+        self.gc_config.set(config);
+    }
+
+    /// Turns the teaching mode on, `observer` is told about every
+    /// instruction the next runs execute, or off with None. It slows the
+    /// programs down a lot.
+    pub fn set_step_observer(&self, observer: Option<Rc<dyn vm::step::StepObserver>>) {
+        *self.step_observer.borrow_mut() = observer;
+    }
+
+    /// Turns the conversion audit of the following runs on or off, see
+    /// `cast_report`
+    pub fn set_cast_audit(&self, enabled: bool) {
+        self.cast_audit.set(enabled);
+    }
+
+    /// Turns the allocation profile of the following runs on or off, see
+    /// `alloc_report`
+    pub fn set_alloc_profile(&self, enabled: bool) {
+        self.alloc_profile.set(enabled);
+    }
+
+    /// Sets the limits of the following runs, which end with
+    /// `RunResult::LimitExceeded` if one of them is exceeded
+    pub fn set_limits(&self, limits: vm::limits::Limits) {
+        self.limits.set(limits);
+    }
+
+    /// Returns the conversions the last run executed with the audit on,
+    /// with how many times each ran, the most frequent first
+    pub fn cast_report(&self) -> Vec<vm::audit::CastRecord> {
+        self.cast_report.borrow().clone()
+    }
+
+    /// Returns the objects the last run allocated with the profile on, by
+    /// kind and by the instructions allocating them, the most frequent first
+    pub fn alloc_report(&self) -> vm::profile::AllocReport {
+        self.alloc_report.borrow().clone()
+    }
+
+    /// Returns the code passed to os.Exit in the last run, None if the
+    /// program ended otherwise
+    pub fn exit_code(&self) -> Option<i32> {
+        self.exit_code.get()
+    }
+
+    /// Sets the environment variables the scripts see, replacing all of
+    /// them. It is the environment of the process if not set. Changes made
+    /// by scripts with os.Setenv stay in the engine.
+    pub fn set_env(&self, vars: &[(&str, &str)]) {
+        self.statics.borrow_data_mut().env = Some(
+            vars.iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        );
+    }
+
+    /// Returns the garbage collection statistics of the last run
+    pub fn gc_stats(&self) -> vm::gc::GcStats {
+        self.gc_stats.get()
+    }
+
+    /// Sets os.Args[0] of the following runs, it is the path of the program
+    /// if not set.
+    pub fn set_program_name(&self, name: &str) {
+        *self.program_name.borrow_mut() = Some(name.to_owned());
+    }
+
+    /// Returns the warnings of the last compilation, like values assigned
+    /// to error variables that are never checked
+    pub fn warnings(&self) -> fe::errors::ErrorList {
+        self.warnings.borrow().clone()
+    }
+
+    /// Panics if the name is taken
+    pub fn register_extension(&mut self, name: &str, proto: Rc<dyn Ffi>) {
         self.ffi.register(name, proto);
     }
 
+    /// Registers an extension that is only created when a program calls
+    /// `ffi` with its name, once per engine. Panics if the name is taken.
+    pub fn register_lazy_extension<F>(&mut self, name: &str, new: F)
+    where
+        F: Fn() -> Rc<dyn Ffi> + 'static,
+    {
+        self.ffi.register_lazy(name, new);
+    }
+
+    /// Registers the extensions of the module with the prefix `name`, a
+    /// program gets the one named `x` with `ffi(T, "name.x")`. Nothing is
+    /// registered if one of the names is taken.
+    pub fn register_module(&mut self, name: &str, module: FfiModule) -> Result<(), String> {
+        self.ffi.register_module(name, module)
+    }
+
+    /// Compiles and runs the program at `path`, the error list has the
+    /// compile errors, how the run ended is returned otherwise.
     pub fn run<S: SourceRead>(
         &self,
         trace_parser: bool,
         trace_checker: bool,
         reader: &S,
         path: &str,
-    ) -> Result<(), fe::errors::ErrorList> {
+    ) -> Result<vm::vm::RunResult, fe::errors::ErrorList> {
+        self.run_with_args(trace_parser, trace_checker, reader, path, &[])
+    }
+
+    /// Runs the program at `path` like `go run path args...`, the program
+    /// sees `args` in os.Args after the program name.
+    pub fn run_with_args<S: SourceRead>(
+        &self,
+        trace_parser: bool,
+        trace_checker: bool,
+        reader: &S,
+        path: &str,
+        args: &[&str],
+    ) -> Result<vm::vm::RunResult, fe::errors::ErrorList> {
+        self.set_args(path, args);
         let cfg = types::TraceConfig {
             trace_parser: trace_parser,
             trace_checker: trace_checker,
         };
         let mut fs = fe::FileSet::new();
-        let code = cg::entry::parse_check_gen(path, &cfg, reader, &mut fs)?;
-        let vm = vm::vm::GosVM::new(code, &self.ffi, Some(&fs));
-        Ok(vm.run())
+        let code = self.compile_impl(path, &cfg, reader, &mut fs)?;
+        self.check_ffi_deps(&code).map_err(|e| {
+            let el = fe::errors::ErrorList::new();
+            el.add(None, e, false, false);
+            el
+        })?;
+        Ok(self.run_code(code, Some(&fs)))
+    }
+
+    /// Parses, type-checks and generates the code of the program at `path`,
+    /// without running it. The source files are added to `fs`.
+    pub fn compile<S: SourceRead>(
+        &self,
+        reader: &S,
+        path: &str,
+        fs: &mut fe::FileSet,
+    ) -> Result<vm::vm::ByteCode, fe::errors::ErrorList> {
+        let cfg = types::TraceConfig {
+            trace_parser: false,
+            trace_checker: false,
+        };
+        self.compile_impl(path, &cfg, reader, fs)
+    }
+
+    fn compile_impl<S: SourceRead>(
+        &self,
+        path: &str,
+        cfg: &types::TraceConfig,
+        reader: &S,
+        fs: &mut fe::FileSet,
+    ) -> Result<vm::vm::ByteCode, fe::errors::ErrorList> {
+        match cg::entry::parse_check_gen(path, cfg, reader, fs) {
+            Ok((code, warnings)) => {
+                *self.warnings.borrow_mut() = warnings;
+                Ok(code)
+            }
+            Err(el) => {
+                *self.warnings.borrow_mut() = fe::errors::ErrorList::new();
+                Err(el)
+            }
+        }
+    }
+
+    /// Compiles the program at `path` and returns its errors, or its warnings
+    /// if it compiles, as diagnostics with the spans of the tokens they are
+    /// reported at. `diagnostics_to_json` publishes them.
+    pub fn diagnostics<S: SourceRead>(
+        &self,
+        reader: &S,
+        path: &str,
+    ) -> Vec<fe::errors::Diagnostic> {
+        super::diagnostics::collect(self, reader, path)
+    }
+
+    /// Returns the documentation of the exported API of the package at
+    /// `path`, the package is type-checked but not compiled.
+    pub fn doc<S: SourceRead>(
+        &self,
+        reader: &S,
+        path: &str,
+    ) -> Result<super::doc::PackageDoc, fe::errors::ErrorList> {
+        super::doc::PackageDoc::new(reader, path)
+    }
+
+    /// Returns the packages the program at `path` is made of with their
+    /// files, the imports that can not be resolved and the import cycles.
+    pub fn dependency_graph<S: SourceRead>(
+        &self,
+        reader: &S,
+        path: &str,
+    ) -> Result<super::deps::DependencyGraph, fe::errors::ErrorList> {
+        super::deps::DependencyGraph::new(reader, path)
+    }
+
+    /// Returns the edits renaming the identifier at `line` and `column` in
+    /// `file` to `new_name` in the program at `path`. It fails if the new
+    /// name conflicts with another declaration or changes what a reference
+    /// refers to.
+    pub fn rename<S: SourceRead>(
+        &self,
+        reader: &S,
+        path: &str,
+        file: &str,
+        line: usize,
+        column: usize,
+        new_name: &str,
+    ) -> Result<Vec<super::rename::TextEdit>, fe::errors::ErrorList> {
+        super::rename::rename(reader, path, file, line, column, new_name)
+    }
+
+    /// Runs the programs checking the features of the Go spec, section by
+    /// section, imports are resolved by `reader`. The report tells which
+    /// features pass, fail or are unsupported, `to_json` publishes it.
+    pub fn conformance_report<S: SourceRead>(
+        &self,
+        reader: &S,
+    ) -> super::conformance::ConformanceReport {
+        super::conformance::run(self, reader)
+    }
+
+    /// Starts a REPL session, in which lines of Go are evaluated one after
+    /// the other and see what the ones before them declared. Imports are
+    /// resolved by `reader`.
+    pub fn repl<S: SourceRead>(&self, reader: S) -> super::repl::Repl<'_, S> {
+        super::repl::Repl::new(self, reader)
+    }
+
+    /// Checks that every FFI object `code` creates is registered, by the std
+    /// library or with `register_extension`. FFI names that are not
+    /// constants can't be checked.
+    pub fn check_ffi_deps(&self, code: &vm::vm::ByteCode) -> Result<(), String> {
+        self.ffi.check_deps(&code.ffi_deps())
+    }
+
+    /// Sets os.Args for code run by `run_code`
+    pub fn set_args(&self, path: &str, args: &[&str]) {
+        let name = self.program_name.borrow().clone();
+        let mut all = vec![name.unwrap_or_else(|| path.to_owned())];
+        all.extend(args.iter().map(|a| a.to_string()));
+        self.statics.borrow_data_mut().args = all;
+    }
+
+    /// Runs code generated by `compile`, `fs` is used to print the
+    /// positions of panics.
+    pub fn run_code(&self, code: vm::vm::ByteCode, fs: Option<&fe::FileSet>) -> vm::vm::RunResult {
+        let mut vm = vm::vm::GosVM::new(code, &self.ffi, fs);
+        self.run_vm(&mut vm)
+    }
+
+    pub(crate) fn ffi(&self) -> &vm::ffi::FfiFactory {
+        &self.ffi
+    }
+
+    /// Runs `vm` with the settings of the engine, and keeps what the run
+    /// reports
+    pub(crate) fn run_vm(&self, vm: &mut vm::vm::GosVM) -> vm::vm::RunResult {
+        vm.set_gc_config(self.gc_config.get());
+        if let Some(observer) = self.step_observer.borrow().clone() {
+            vm.set_step_observer(observer);
+        }
+        if self.cast_audit.get() {
+            vm.audit_casts();
+        }
+        if self.alloc_profile.get() {
+            vm.profile_allocs();
+        }
+        vm.set_limits(self.limits.get());
+        let result = vm.run();
+        *self.cast_report.borrow_mut() = vm.cast_report();
+        *self.alloc_report.borrow_mut() = vm.alloc_report();
+        self.exit_code.set(match result {
+            vm::vm::RunResult::Exit(code) => Some(code),
+            _ => None,
+        });
+        self.gc_stats.set(vm.gc_stats());
+        result
+    }
+
+    /// Compiles the program at `path` into a bytecode image, with the
+    /// debug info for panics to be reported with positions.
+    pub fn compile_image<S: SourceRead>(
+        &self,
+        reader: &S,
+        path: &str,
+        signer: Option<&dyn vm::image::ImageSigner>,
+    ) -> Result<Vec<u8>, fe::errors::ErrorList> {
+        let mut fs = fe::FileSet::new();
+        let code = self.compile(reader, path, &mut fs)?;
+        vm::image::write_image(&code, Some(&fs), signer).map_err(|e| {
+            let el = fe::errors::ErrorList::new();
+            el.add(None, e, false, false);
+            el
+        })
+    }
+
+    /// Loads a bytecode image written by `compile_image` and runs it,
+    /// fails without running anything if the image can't be loaded or
+    /// creates FFI objects that are not registered. A tampered image can
+    /// panic the host, see `vm::image::read_image`: only signed images,
+    /// checked with `verifier`, or trusted ones are safe to run.
+    pub fn run_image(
+        &self,
+        image: &[u8],
+        verifier: Option<&dyn vm::image::ImageVerifier>,
+    ) -> Result<vm::vm::RunResult, String> {
+        let (code, fs) = vm::image::read_image(image, verifier)?;
+        self.check_ffi_deps(&code)?;
+        Ok(self.run_code(code, fs.as_ref()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use goscript_codegen::FsReader;
+
+    #[test]
+    fn test_gc_stats() {
+        let source = r#"
+    package main
+
+    type Node struct {
+        next *Node
+        data []int
+    }
+
+    func main() {
+        for i := 0; i < 1000; i++ {
+            a := &Node{data: make([]int, 4)}
+            b := &Node{next: a}
+            a.next = b
+        }
+    }
+    "#;
+        let engine = Engine::new();
+        engine.set_gc_config(vm::gc::GcConfig {
+            min_objects: 64,
+            ..Default::default()
+        });
+        let reader = FsReader::new(Some("./"), Some("../std/"), Some(source));
+        let result = engine.run(false, false, &reader, FsReader::temp_file_path());
+        assert!(result.is_ok());
+        let stats = engine.gc_stats();
+        // with the low threshold it collects many times while running
+        assert!(stats.collections > 1);
+        // every cycle is freed
+        assert!(stats.freed >= 2000);
+        assert!(stats.live < 64);
+    }
+
+    #[test]
+    fn test_unreachable_funcs_removed() {
+        let source = r#"
+    package main
+
+    import "strings"
+
+    type T struct{}
+
+    func (T) Method() int { return used() }
+
+    func used() int { return 1 }
+
+    type Namer interface{ Name() string }
+
+    type V struct{}
+
+    func (V) Name() string { return "v" }
+
+    type W struct{}
+
+    func (W) Name() string { return "w" }
+
+    func unused() int {
+        var n Namer = W{}
+        _ = n
+        return strings.Index("ab", "b")
+    }
+
+    func main() {
+        f := func() int { return used() }
+        assert(f() == 1)
+        var n Namer = V{}
+        assert(n.Name() == "v")
+    }
+    "#;
+        let engine = Engine::new();
+        let reader = FsReader::new(Some("./"), Some("../std/"), Some(source));
+        let mut fs = fe::FileSet::new();
+        let code = engine
+            .compile(&reader, FsReader::temp_file_path(), &mut fs)
+            .unwrap();
+        let objs = &code.objects;
+        let member = |name: &str| {
+            code.packages.iter().find_map(|&p| {
+                let pkg = &objs.packages[p];
+                let i = *pkg.get_member_index(name)?;
+                pkg.get_member_index("unused")?;
+                Some(pkg.member(i).clone())
+            })
+        };
+        let is_live = |name: &str| {
+            let v = member(name).unwrap();
+            match v.as_closure() {
+                Some((vm::objects::ClosureObj::Gos(c), _)) => objs.functions.contains_key(c.func),
+                _ => false,
+            }
+        };
+        assert!(is_live("main"));
+        assert!(is_live("used"));
+        assert!(!is_live("unused"));
+        // the methods of the types only dead code converts to interfaces go too
+        let has_func = |name: &str| objs.functions.values().any(|f| f.name == name);
+        assert!(has_func("main.V.Name"));
+        assert!(!has_func("main.W.Name"));
+        assert!(!has_func("main.T.Method"));
+        engine.run_code(code, Some(&fs));
+    }
+
+    #[derive(Clone)]
+    struct SharedBuf(Rc<RefCell<Vec<u8>>>);
+
+    impl io::Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    const IMAGE_SOURCE: &str = r#"
+    package main
+
+    import (
+        "errors"
+        "fmt"
+        "strings"
+    )
+
+    type Shape interface {
+        Area() float64
+    }
+
+    type Rect struct {
+        w, h float64
+        tags [2]string
+    }
+
+    func (r Rect) Area() float64 { return r.w * r.h }
+
+    type Counter int
+
+    func (c *Counter) Inc() { *c++ }
+
+    var total = 10
+
+    func init() {
+        total += 5
+    }
+
+    func main() {
+        var shapes []Shape
+        shapes = append(shapes, Rect{w: 2, h: 3}, &Rect{w: 1, h: 1})
+        sum := 0.0
+        for _, s := range shapes {
+            sum += s.Area()
+        }
+        var c Counter
+        inc := c.Inc
+        inc()
+        add := func(x int) int { return x + int(c) + total }
+        m := map[string]int{"x": 1, "y": 2}
+        m["a"] = add(1)
+        err := errors.New("boom")
+        fmt.Println(sum, m["a"], m["y"], strings.ToUpper("ok"), err, 1+2i)
+    }
+    "#;
+
+    #[test]
+    fn test_ffi_deps() {
+        use std::future::Future;
+        use std::pin::Pin;
+        use vm::value::{GosValue, RuntimeResult};
+
+        struct Greeter;
+
+        impl Ffi for Greeter {
+            fn call(
+                &self,
+                _: &mut vm::ffi::FfiCallCtx,
+                _: Vec<GosValue>,
+            ) -> Pin<Box<dyn Future<Output = RuntimeResult<Vec<GosValue>>> + '_>> {
+                Box::pin(async { Ok(vec![GosValue::with_str("hi")]) })
+            }
+        }
+
+        let source = r#"
+    package main
+
+    import "fmt"
+
+    type greeter interface {
+        hello() string
+    }
+
+    func main() {
+        g := ffi(greeter, "greeter")
+        name := "greeter"
+        g2 := ffi(greeter, name)
+        fmt.Println(g.hello(), g2.hello())
+    }
+    "#;
+        let mut engine = Engine::new();
+        engine.set_std_io(None, Some(Box::new(io::sink())), None);
+        let reader = FsReader::new(Some("./"), Some("../std/"), Some(source));
+        let path = FsReader::temp_file_path();
+        let mut fs = fe::FileSet::new();
+        let code = engine.compile(&reader, path, &mut fs).unwrap();
+        let deps = code.ffi_deps();
+        assert!(deps.names.contains(&"fmt".to_owned()));
+        assert!(deps.names.contains(&"greeter".to_owned()));
+        assert_eq!(deps.dynamic, 1);
+        let err = engine.check_ffi_deps(&code).unwrap_err();
+        assert_eq!(err, "FFI named greeter not found");
+        // nothing is run
+        let el = engine.run(false, false, &reader, path).unwrap_err();
+        assert!(el.to_string().contains("FFI named greeter not found"));
+
+        let image = engine.compile_image(&reader, path, None).unwrap();
+        assert!(engine.run_image(&image, None).is_err());
+        engine.register_extension("greeter", Rc::new(Greeter));
+        assert!(engine.check_ffi_deps(&code).is_ok());
+        assert!(engine.run_image(&image, None).is_ok());
+    }
+
+    #[test]
+    #[cfg(all(feature = "fs", feature = "net", unix))]
+    fn test_std_selection() {
+        use crate::std::StdModule;
+
+        let err = Engine::with_std(StdSelection::none().with(StdModule::Net)).err();
+        assert_eq!(err.unwrap(), "std module net needs the core module");
+        assert_eq!(Engine::new().std_selection(), StdSelection::all());
+
+        let selection = StdSelection::all()
+            .without(StdModule::Fs)
+            .without(StdModule::Net);
+        let engine = Engine::with_std(selection.clone()).unwrap();
+        assert_eq!(engine.std_selection(), selection);
+        assert_eq!(
+            engine.std_selection().to_string(),
+            "core, time, ratelimit, sql, kv, host"
+        );
+
+        let source = r#"
+    package main
+
+    import (
+        "fmt"
+        "os"
+    )
+
+    func main() {
+        _, err := os.Open("test.go")
+        fmt.Println(err)
+    }
+    "#;
+        let out = SharedBuf(Rc::new(RefCell::new(vec![])));
+        engine.set_std_io(None, Some(Box::new(out.clone())), None);
+        let reader = FsReader::new(Some("./"), Some("../std/"), Some(source));
+        let path = FsReader::temp_file_path();
+        engine.run(false, false, &reader, path).unwrap();
+        let printed = String::from_utf8(out.0.borrow().clone()).unwrap();
+        assert!(printed.contains("file system access is not enabled"));
+
+        let source = r#"
+    package main
+
+    import "time"
+
+    func main() {
+        time.Sleep(time.Millisecond)
+    }
+    "#;
+        let engine = Engine::with_std(StdSelection::core()).unwrap();
+        let reader = FsReader::new(Some("./"), Some("../std/"), Some(source));
+        let el = engine.run(false, false, &reader, path).unwrap_err();
+        assert!(el.to_string().contains("FFI named time not found"));
+    }
+
+    #[test]
+    #[cfg(all(feature = "fs", feature = "net", unix))]
+    fn test_capabilities() {
+        use crate::std::Capability;
+
+        let path = std::env::temp_dir().join(format!("goscript_caps_{}.txt", std::process::id()));
+        let path = path.to_string_lossy().replace('\\', "/");
+        let caps = Capabilities::all()
+            .without(Capability::FsWrite)
+            .without(Capability::Net)
+            .without(Capability::Env);
+        assert_eq!(caps.to_string(), "fs_read");
+        let engine = Engine::new();
+        assert_eq!(engine.capabilities(), Capabilities::all());
+        engine.set_capabilities(caps);
+        engine.set_env(&[("HOME", "/home/gopher")]);
+
+        let source = r#"
+    package main
+
+    import (
+        "errors"
+        "net"
+        "os"
+        "strings"
+    )
+
+    func main() {
+        f, err := os.Open("Cargo.toml")
+        assert(err == nil && f != nil)
+        _, err = os.Open("no_such_file")
+        assert(err != nil && !errors.Is(err, os.ErrPermission))
+
+        _, err = os.Create("PATH")
+        assert(errors.Is(err, os.ErrPermission))
+        assert(err.Error() == "open PATH: permission denied: writing files is not allowed")
+        _, err = os.OpenFile("Cargo.toml", os.O_RDWR, 0)
+        assert(errors.Is(err, os.ErrPermission))
+
+        assert(os.Getenv("HOME") == "" && len(os.Environ()) == 0)
+        err = os.Setenv("GREETING", "hi")
+        assert(errors.Is(err, os.ErrPermission))
+        assert(strings.HasPrefix(err.Error(), "setenv: permission denied"))
+        assert(errors.Is(os.Unsetenv("HOME"), os.ErrPermission))
+
+        _, err = net.Dial("tcp", "127.0.0.1:1")
+        assert(strings.Contains(err.Error(), "network access is not allowed"))
+        _, err = net.Listen("tcp", "127.0.0.1:0")
+        assert(strings.Contains(err.Error(), "network access is not allowed"))
+    }
+    "#;
+        let source = source.replace("PATH", &path);
+        let reader = FsReader::new(Some("./"), Some("../std/"), Some(&source));
+        let temp = FsReader::temp_file_path();
+        let result = engine.run(false, false, &reader, temp).unwrap();
+        assert_eq!(result, vm::vm::RunResult::Done);
+        assert!(!std::path::Path::new(&path).exists());
+
+        let source = r#"
+    package main
+
+    import "os"
+
+    func write(s string) {
+        f, err := os.Create("PATH")
+        assert(err == nil)
+        n, err := f.Write([]byte(s))
+        assert(err == nil && n == len(s))
+    }
+
+    func main() {
+        write("hello world")
+        // Create truncates the file
+        write("hi")
+        f, err := os.Open("PATH")
+        assert(err == nil)
+        b := make([]byte, 32)
+        n, _ := f.Read(b)
+        assert(string(b[:n]) == "hi")
+
+        assert(os.Getenv("HOME") == "/home/gopher")
+        assert(os.Setenv("GREETING", "hi") == nil)
+    }
+    "#;
+        engine.set_capabilities(Capabilities::all());
+        let source = source.replace("PATH", &path);
+        let reader = FsReader::new(Some("./"), Some("../std/"), Some(&source));
+        let result = engine.run(false, false, &reader, temp).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(result, vm::vm::RunResult::Done);
+    }
+
+    /// Gives the input a few bytes a read, like a pipe
+    struct Trickle(io::Cursor<Vec<u8>>);
+
+    impl io::Read for Trickle {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = buf.len().min(3);
+            self.0.read(&mut buf[..n])
+        }
+    }
+
+    #[test]
+    fn test_std_in_lines() {
+        let source = r#"
+    package main
+
+    import (
+        "bufio"
+        "os"
+        "strings"
+    )
+
+    func main() {
+        in := bufio.NewScanner(os.Stdin)
+        out := bufio.NewWriter(os.Stdout)
+        defer out.Flush()
+        n := 0
+        for in.Scan() {
+            n++
+            out.WriteString(strings.ToUpper(in.Text()))
+            out.WriteByte('\n')
+        }
+        assert(in.Err() == nil && n == 3)
+    }
+    "#;
+        let engine = Engine::new();
+        let input = Trickle(io::Cursor::new(
+            b"first\nsecond line\r\nno newline".to_vec(),
+        ));
+        let out = SharedBuf(Rc::new(RefCell::new(vec![])));
+        engine.set_std_io(Some(Box::new(input)), Some(Box::new(out.clone())), None);
+        let reader = FsReader::new(Some("./"), Some("../std/"), Some(source));
+        let result = engine.run(false, false, &reader, FsReader::temp_file_path());
+        assert!(matches!(result, Ok(vm::vm::RunResult::Done)));
+        let printed = String::from_utf8(out.0.borrow().clone()).unwrap();
+        assert_eq!(printed, "FIRST\nSECOND LINE\nNO NEWLINE\n");
+    }
+
+    #[test]
+    fn test_run_result() {
+        let source = r#"
+    package main
+
+    import "os"
+
+    var code = 0
+
+    func check(n int) {
+        if n > 2 {
+            panic("too big")
+        }
+    }
+
+    func main() {
+        for i := 0; i < 5; i++ {
+            check(i)
+        }
+        os.Exit(code)
+    }
+    "#;
+        let engine = Engine::new();
+        let reader = FsReader::new(Some("./"), Some("../std/"), Some(source));
+        let path = FsReader::temp_file_path();
+        match engine.run(false, false, &reader, path).unwrap() {
+            vm::vm::RunResult::Panic(info) => {
+                assert_eq!(info.message, "too big");
+                assert_eq!(info.stack.len(), 2);
+                assert_eq!(info.stack[0].function, "main.check");
+                assert_eq!(info.stack[0].line, 10);
+                assert_eq!(info.stack[1].function, "main.main");
+                assert_eq!(info.stack[1].line, 16);
+            }
+            r => panic!("unexpected {:?}", r),
+        }
+        assert_eq!(engine.exit_code(), None);
+
+        let source = source.replace("n > 2", "n > 5");
+        let reader = FsReader::new(Some("./"), Some("../std/"), Some(&source));
+        let result = engine.run(false, false, &reader, path).unwrap();
+        assert_eq!(result, vm::vm::RunResult::Exit(0));
+        assert_eq!(engine.exit_code(), Some(0));
+
+        let source = source.replace("os.Exit(code)", "_ = os.Args");
+        let reader = FsReader::new(Some("./"), Some("../std/"), Some(&source));
+        let result = engine.run(false, false, &reader, path).unwrap();
+        assert_eq!(result, vm::vm::RunResult::Done);
+        assert_eq!(result.code(), 0);
+    }
+
+    #[test]
+    fn test_panic_locals() {
+        let source = r#"
+    package main
+
+    func check(n int, name string) int {
+        total := n * 2
+        if total > 4 {
+            s := []int{1, 2}
+            _ = s
+            panic("too big")
+        }
+        later := 1
+        return total + later
+    }
+
+    func main() {
+        x := 42
+        for i := 0; i < 5; i++ {
+            check(i, "a")
+        }
+        _ = x
+    }
+    "#;
+        let engine = Engine::new();
+        let reader = FsReader::new(Some("./"), Some("../std/"), Some(source));
+        let path = FsReader::temp_file_path();
+        let locals = |result| match result {
+            vm::vm::RunResult::Panic(info) => {
+                let frames: Vec<Vec<String>> = info
+                    .stack
+                    .iter()
+                    .map(|f| {
+                        f.locals
+                            .iter()
+                            .map(|(n, v)| format!("{} = {}", n, v))
+                            .collect()
+                    })
+                    .collect();
+                frames
+            }
+            r => panic!("unexpected {:?}", r),
+        };
+        let expected = vec![
+            vec!["n = 3", "name = a", "total = 6", "s = [1 2]"],
+            vec!["x = 42", "i = 3"],
+        ];
+        let result = engine.run(false, false, &reader, path).unwrap();
+        if let vm::vm::RunResult::Panic(info) = &result {
+            assert!(info.to_string().contains("\n\t\ttotal = 6\n"));
+        }
+        assert_eq!(locals(result), expected);
+
+        // the names are kept in images, and go with the debug info
+        let image = engine.compile_image(&reader, path, None).unwrap();
+        assert_eq!(locals(engine.run_image(&image, None).unwrap()), expected);
+        let code = vm::image::read_image_without_debug(&image, None).unwrap();
+        let result = engine.run_code(code, None);
+        assert_eq!(locals(result), vec![Vec::<String>::new(); 2]);
+    }
+
+    #[test]
+    fn test_nil_map_panic() {
+        let source = r#"
+    package main
+
+    type counter struct {
+        hits map[string]int
+    }
+
+    func main() {
+        var c counter
+        n, ok := c.hits["a"]
+        c.hits["a"] = n + 1
+        _ = ok
+    }
+    "#;
+        let engine = Engine::new();
+        let reader = FsReader::new(Some("./"), Some("../std/"), Some(source));
+        let path = FsReader::temp_file_path();
+        match engine.run(false, false, &reader, path).unwrap() {
+            vm::vm::RunResult::Panic(info) => {
+                assert_eq!(info.message, "assignment to entry in nil map");
+                assert_eq!(info.stack.len(), 1);
+                assert_eq!(info.stack[0].function, "main.main");
+                assert_eq!(info.stack[0].line, 11);
+            }
+            r => panic!("unexpected {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_deadlock() {
+        let source = r#"
+    package main
+
+    import (
+        "sync"
+        "time"
+    )
+
+    func main() {
+        var mu sync.Mutex
+        mu.Lock()
+        c := make(chan int)
+        go func() {
+            mu.Lock()
+        }()
+        go func() {
+            time.Sleep(time.Millisecond)
+            c <- 1
+        }()
+        <-c
+        select {
+        case <-c:
+        }
+    }
+    "#;
+        let engine = Engine::new();
+        let reader = FsReader::new(Some("./"), Some("../std/"), Some(source));
+        let path = FsReader::temp_file_path();
+        match engine.run(false, false, &reader, path).unwrap() {
+            vm::vm::RunResult::Deadlock(goroutines) => {
+                assert_eq!(goroutines.len(), 2);
+                assert_eq!(goroutines[0].id, 1);
+                assert_eq!(goroutines[0].wait, "select");
+                assert_eq!(goroutines[0].stack[0].function, "main.main");
+                assert_eq!(goroutines[0].stack[0].line, 22);
+                assert_eq!(goroutines[1].wait, "lock");
+                assert_eq!(goroutines[1].stack[0].function, "sync.(*Mutex).Lock");
+                assert_eq!(goroutines[1].stack[1].function, "main.main.func1");
+                assert_eq!(goroutines[1].stack[1].line, 14);
+            }
+            r => panic!("unexpected {:?}", r),
+        }
+
+        // the locked goroutine is woken when the select times out
+        let source = source
+            .replace(
+                "mu.Lock()\n        }()",
+                "mu.Lock()\n            c <- 2\n        }()",
+            )
+            .replace(
+                "case <-c:",
+                "case <-time.After(time.Millisecond):\n            mu.Unlock()\n            <-c",
+            );
+        let reader = FsReader::new(Some("./"), Some("../std/"), Some(&source));
+        let result = engine.run(false, false, &reader, path).unwrap();
+        assert_eq!(result, vm::vm::RunResult::Done);
+    }
+
+    #[test]
+    fn test_step_observer() {
+        let source = r#"
+    package main
+
+    func add(a, b int) int {
+        return a + b
+    }
+
+    func main() {
+        x := add(1, 2)
+        assert(x == 3)
+    }
+    "#;
+        #[derive(Default)]
+        struct Recorder {
+            steps: RefCell<Vec<vm::step::Step>>,
+        }
+        impl vm::step::StepObserver for Recorder {
+            fn on_step(&self, step: &vm::step::Step) {
+                self.steps.borrow_mut().push(step.clone());
+            }
+        }
+        let engine = Engine::new();
+        let recorder = Rc::new(Recorder::default());
+        engine.set_step_observer(Some(recorder.clone()));
+        let reader = FsReader::new(Some("./"), Some("../std/"), Some(source));
+        let result = engine.run(false, false, &reader, FsReader::temp_file_path());
+        assert_eq!(result.unwrap(), vm::vm::RunResult::Done);
+
+        let steps = recorder.steps.borrow();
+        let add = steps
+            .iter()
+            .position(|s| s.instruction.op == vm::instruction::Opcode::ADD)
+            .unwrap();
+        assert_eq!(steps[add].instruction.to_string(), "ADD Int");
+        assert_eq!(steps[add].stack.popped, 2);
+        assert_eq!(steps[add].stack.pushed, vec!["3"]);
+        let frames = &steps[add].frames;
+        assert_eq!(frames.last().unwrap().pos.as_ref().unwrap().line, 5);
+        let names: Vec<&str> = frames
+            .last()
+            .unwrap()
+            .locals
+            .iter()
+            .map(|(n, _)| &n[..])
+            .collect();
+        assert_eq!(names, ["a", "b"]);
+        let a = frames.last().unwrap().locals[0].1;
+        // main calling add
+        let caller = &frames[frames.len() - 2];
+        assert_eq!(caller.pos.as_ref().unwrap().line, 9);
+
+        // the stack can be rebuilt from the diffs
+        let mut stack = vec![];
+        for s in steps[..=add].iter() {
+            s.stack.apply(&mut stack);
+        }
+        assert_eq!(stack.last().unwrap(), "3");
+        assert_eq!(stack[a], "1");
+
+        engine.set_step_observer(None);
+        let count = steps.len();
+        drop(steps);
+        engine
+            .run(false, false, &reader, FsReader::temp_file_path())
+            .unwrap();
+        assert_eq!(recorder.steps.borrow().len(), count);
+    }
+
+    #[test]
+    fn test_cast_audit() {
+        let source = r#"
+    package main
+
+    func isInt(v interface{}) bool {
+        _, ok := v.(int)
+        return ok
+    }
+
+    func main() {
+        var sum float64
+        ints := 0
+        for i := 0; i < 10; i++ {
+            sum += float64(i)
+            if isInt(i) {
+                ints++
+            }
+        }
+        assert(sum == 45 && ints == 10)
+    }
+    "#;
+        let engine = Engine::new();
+        engine.set_cast_audit(true);
+        let reader = FsReader::new(Some("./"), Some("../std/"), Some(source));
+        let result = engine.run(false, false, &reader, FsReader::temp_file_path());
+        assert_eq!(result.unwrap(), vm::vm::RunResult::Done);
+
+        let report = engine.cast_report();
+        let found: Vec<_> = report
+            .iter()
+            .map(|r| {
+                let site = r.site.as_ref().unwrap();
+                (
+                    r.kind,
+                    r.from,
+                    r.to,
+                    site.function.as_str(),
+                    site.line,
+                    r.count,
+                )
+            })
+            .collect();
+        use vm::audit::CastKind;
+        use vm::instruction::ValueType;
+        assert_eq!(
+            found,
+            vec![
+                (
+                    CastKind::Numeric,
+                    ValueType::Int,
+                    ValueType::Float64,
+                    "main.main",
+                    13,
+                    10
+                ),
+                (
+                    CastKind::Boxing,
+                    ValueType::Int,
+                    ValueType::Interface,
+                    "main.main",
+                    14,
+                    10
+                ),
+            ]
+        );
+
+        engine.set_cast_audit(false);
+        engine
+            .run(false, false, &reader, FsReader::temp_file_path())
+            .unwrap();
+        assert!(engine.cast_report().is_empty());
+    }
+
+    #[test]
+    fn test_alloc_profile() {
+        let source = r#"
+    package main
+
+    type P struct {
+        x, y int
+    }
+
+    func main() {
+        total := 0
+        for i := 0; i < 10; i++ {
+            p := &P{i, i}
+            s := make([]int, 2)
+            f := func() int { return p.x + len(s) }
+            total += f()
+        }
+        m := map[int]int{1: 1}
+        assert(total == 65 && len(m) == 1)
+    }
+    "#;
+        let engine = Engine::new();
+        engine.set_alloc_profile(true);
+        let reader = FsReader::new(Some("./"), Some("../std/"), Some(source));
+        let result = engine.run(false, false, &reader, FsReader::temp_file_path());
+        assert_eq!(result.unwrap(), vm::vm::RunResult::Done);
+
+        use vm::profile::AllocKind;
+        let report = engine.alloc_report();
+        let in_main = |kind: AllocKind, line: usize| {
+            report
+                .sites
+                .iter()
+                .filter(|r| r.kind == kind)
+                .filter(|r| {
+                    r.site
+                        .as_ref()
+                        .map_or(false, |s| s.function == "main.main" && s.line == line)
+                })
+                .map(|r| r.count)
+                .sum::<u64>()
+        };
+        assert_eq!(in_main(AllocKind::Struct, 11), 10);
+        assert_eq!(in_main(AllocKind::Array, 12), 10);
+        assert_eq!(in_main(AllocKind::Closure, 13), 10);
+        assert_eq!(in_main(AllocKind::Map, 16), 1);
+        for (kind, count) in report.kinds.iter() {
+            let sites: u64 = report
+                .sites
+                .iter()
+                .filter(|r| r.kind == *kind)
+                .map(|r| r.count)
+                .sum();
+            assert_eq!(sites, *count);
+        }
+        assert!(report.sites.windows(2).all(|w| w[0].count >= w[1].count));
+
+        engine.set_alloc_profile(false);
+        engine
+            .run(false, false, &reader, FsReader::temp_file_path())
+            .unwrap();
+        assert!(engine.alloc_report().sites.is_empty());
+    }
+
+    #[test]
+    fn test_pragmas() {
+        let source = r#"
+    package main
+
+    //gos:pure
+    func sq(n int) int {
+        return n * n
+    }
+
+    //gos:noopt
+    func inc(n int) int {
+        return n + 1 // @inc
+    }
+
+    func inc2(n int) int {
+        return n + 1 // @inc2
+    }
+
+    //gos:noopt
+    func odds(n int) (c int) { // @odds
+        i := 0
+        for i < n {
+            i++
+            if i%2 == 0 {
+                c++
+            } else {
+                c += 0
+            }
+        }
+        return
+    }
+
+    func odds2(n int) (c int) { // @odds2
+        i := 0
+        for i < n {
+            i++
+            if i%2 == 0 {
+                c++
+            } else {
+                c += 0
+            }
+        }
+        return
+    }
+
+    //gos:nobounds
+    func sum(s []int) (t int) { // @sum
+        for i := range s {
+            t += s[i] // @load
+        }
+        return
+    }
+
+    //gos:nobounds
+    func double(s []int) {
+        for i := range s {
+            s[i] = s[i] * 2 // @store
+            s[i] += 1 // @add
+        }
+    }
+
+    //gos:nobounds
+    func peek(s []int, i int) int {
+        return s[i] // @peek
+    }
+
+    //gos:nobounds
+    func shrink(s []int) (t int) {
+        for i := range s {
+            s = s[:0]
+            t += s[i] // @shrink
+        }
+        return
+    }
+
+    //gos:nobounds
+    func captured(s []int) (t int) {
+        clear := func() { s = s[:0] }
+        for i := range s {
+            clear()
+            t += s[i] // @captured
+        }
+        return
+    }
+
+    func panics(f func()) (msg interface{}) {
+        defer func() {
+            msg = recover()
+        }()
+        f()
+        return
+    }
+
+    func main() {
+        sq(3) // @unused
+        n := 2
+        sq(n) // @unused2
+        assert(sq(n) == 4) // @used
+        assert(inc(1) == inc2(1))
+        assert(odds(10) == odds2(10))
+
+        s := []int{1, 2, 3, 4}
+        double(s)
+        assert(sum(s) == 24)
+        // only the indices proved in range are not checked
+        assert(peek(s, 3) == 9)
+        assert(panics(func() { peek(s[:1], 3) }) != nil)
+        assert(panics(func() { shrink(s) }) != nil)
+        assert(panics(func() { captured(s) }) != nil)
+    }
+    "#;
+        #[derive(Default)]
+        struct Recorder {
+            ops: RefCell<Vec<(usize, vm::instruction::Opcode)>>,
+        }
+        impl vm::step::StepObserver for Recorder {
+            fn on_step(&self, step: &vm::step::Step) {
+                let line = step
+                    .frames
+                    .last()
+                    .unwrap()
+                    .pos
+                    .as_ref()
+                    .map_or(0, |p| p.line);
+                self.ops.borrow_mut().push((line, step.instruction.op));
+            }
+        }
+        let engine = Engine::new();
+        let recorder = Rc::new(Recorder::default());
+        engine.set_step_observer(Some(recorder.clone()));
+        let reader = FsReader::new(Some("./"), Some("../std/"), Some(source));
+        let result = engine.run(false, false, &reader, FsReader::temp_file_path());
+        assert_eq!(result.unwrap(), vm::vm::RunResult::Done);
+
+        use vm::instruction::Opcode;
+        let ops = recorder.ops.borrow();
+        // the line of the source ending with the marker comment
+        let line = |marker: &str| {
+            let suffix = format!("// {}", marker);
+            source.lines().position(|l| l.ends_with(&suffix)).unwrap() + 1
+        };
+        let ran = |marker, op| ops.contains(&(line(marker), op));
+        // the calls whose results are unused are left out
+        assert!(!ran("@unused", Opcode::CALL));
+        assert!(!ran("@unused2", Opcode::CALL));
+        assert!(ran("@used", Opcode::CALL));
+        // the constant is added by a superinstruction, except in inc
+        assert!(!ran("@inc", Opcode::PUSH_IMM_ADD));
+        assert!(ran("@inc2", Opcode::PUSH_IMM_ADD));
+        // the jumps to the loop's jump back are retargeted, except in odds
+        let jumps = |from: &str, to: &str| {
+            let lines = line(from)..line(to);
+            ops.iter()
+                .filter(|(l, op)| lines.contains(l) && *op == Opcode::JUMP)
+                .count()
+        };
+        assert_eq!(jumps("@odds", "@odds2"), 15);
+        assert_eq!(jumps("@odds2", "@sum"), 10);
+        // the slice is indexed without checking the length by the key of a
+        // range loop over it, as long as neither changes
+        assert!(ran("@load", Opcode::LOAD_INDEX_UNCHECKED));
+        assert!(ran("@store", Opcode::STORE_INDEX_UNCHECKED));
+        assert!(ran("@add", Opcode::STORE_INDEX_UNCHECKED));
+        for marker in ["@peek", "@shrink", "@captured"] {
+            assert!(!ran(marker, Opcode::LOAD_INDEX_UNCHECKED));
+        }
+    }
+
+    #[test]
+    fn test_local_refs() {
+        let source = r#"
+    package main
+
+    type T struct{ a int }
+
+    func (t *T) Inc() { t.a++ }
+
+    func (t *T) Self() *T { return t }
+
+    func set(p *int, v int) { *p = v }
+
+    var kept *int
+
+    func main() {
+        var n, m int
+        set(&n, 1)
+        p := &m
+        *p = 2
+        var t T
+        t.Inc()
+        kept = &n
+        q := &m
+        _ = t.Self()
+        kept = q
+        assert(n == 1 && m == 2 && t.a == 1 && q == p)
+    }
+    "#;
+        #[derive(Default)]
+        struct Recorder {
+            ops: RefCell<Vec<(usize, vm::instruction::Opcode)>>,
+        }
+        impl vm::step::StepObserver for Recorder {
+            fn on_step(&self, step: &vm::step::Step) {
+                let line = step
+                    .frames
+                    .last()
+                    .unwrap()
+                    .pos
+                    .as_ref()
+                    .map_or(0, |p| p.line);
+                self.ops.borrow_mut().push((line, step.instruction.op));
+            }
+        }
+        let engine = Engine::new();
+        let recorder = Rc::new(Recorder::default());
+        engine.set_step_observer(Some(recorder.clone()));
+        let reader = FsReader::new(Some("./"), Some("../std/"), Some(source));
+        let result = engine.run(false, false, &reader, FsReader::temp_file_path());
+        assert_eq!(result.unwrap(), vm::vm::RunResult::Done);
+
+        // the pointers kept in main point to its stack
+        use vm::instruction::Opcode;
+        let ops = recorder.ops.borrow();
+        let refs: Vec<(usize, Opcode)> = ops
+            .iter()
+            .filter(|(_, op)| *op == Opcode::REF_LOCAL || *op == Opcode::REF_UPVALUE)
+            .copied()
+            .collect();
+        assert_eq!(
+            refs,
+            vec![
+                (16, Opcode::REF_LOCAL),
+                (17, Opcode::REF_LOCAL),
+                (20, Opcode::REF_LOCAL),
+                (21, Opcode::REF_UPVALUE),
+                (22, Opcode::REF_UPVALUE),
+                (23, Opcode::REF_UPVALUE),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_bytecode_image() {
+        let engine = Engine::new();
+        let out = SharedBuf(Rc::new(RefCell::new(vec![])));
+        engine.set_std_io(None, Some(Box::new(out.clone())), None);
+        let reader = FsReader::new(Some("./"), Some("../std/"), Some(IMAGE_SOURCE));
+        let image = engine
+            .compile_image(&reader, FsReader::temp_file_path(), None)
+            .unwrap();
+        engine.run_image(&image, None).unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&out.0.borrow()),
+            "7 17 2 OK boom (1+2i)\n"
+        );
+
+        let mut tampered = image.clone();
+        let i = tampered.len() / 2;
+        tampered[i] ^= 1;
+        let err = engine.run_image(&tampered, None).unwrap_err();
+        assert_eq!(err, "bytecode image checksum mismatch");
+
+        let mut newer = image.clone();
+        newer[4] += 1;
+        let err = engine.run_image(&newer, None).unwrap_err();
+        let expected = format!(
+            "bytecode image version {} is not supported",
+            vm::image::FORMAT_VERSION + 1
+        );
+        assert!(err.starts_with(&expected));
+
+        let err = engine
+            .run_image(&image[..image.len() - 8], None)
+            .unwrap_err();
+        assert_eq!(err, "bytecode image is truncated");
+    }
+
+    #[test]
+    fn test_signed_bytecode_image() {
+        // xors the payload with a key, stands for a real signature scheme
+        struct Key(u8);
+
+        impl vm::image::ImageSigner for Key {
+            fn sign(&self, payload: &[u8]) -> Vec<u8> {
+                vec![payload.iter().fold(self.0, |a, b| a ^ b)]
+            }
+        }
+
+        impl vm::image::ImageVerifier for Key {
+            fn verify(&self, payload: &[u8], signature: &[u8]) -> bool {
+                vm::image::ImageSigner::sign(self, payload) == signature
+            }
+        }
+
+        let engine = Engine::new();
+        engine.set_std_io(None, Some(Box::new(io::sink())), None);
+        let reader = FsReader::new(Some("./"), Some("../std/"), Some(IMAGE_SOURCE));
+        let path = FsReader::temp_file_path();
+        let signed = engine.compile_image(&reader, path, Some(&Key(7))).unwrap();
+        assert!(engine.run_image(&signed, Some(&Key(7))).is_ok());
+        let err = engine.run_image(&signed, Some(&Key(8))).unwrap_err();
+        assert_eq!(err, "bytecode image signature rejected");
+
+        let unsigned = engine.compile_image(&reader, path, None).unwrap();
+        assert!(engine.run_image(&unsigned, Some(&Key(7))).is_err());
+        // the signature is not checked without a verifier
+        assert!(engine.run_image(&signed, None).is_ok());
+    }
+
+    #[test]
+    fn test_stripped_debug_info() {
+        let source = r#"
+    package main
+
+    func check(n int) {
+        if n > 2 {
+            panic("too big")
+        }
+    }
+
+    func main() {
+        check(3)
+    }
+    "#;
+        let engine = Engine::new();
+        let reader = FsReader::new(Some("./"), Some("../std/"), Some(source));
+        let path = FsReader::temp_file_path();
+        let lines = |result| match result {
+            vm::vm::RunResult::Panic(info) => {
+                let frames: Vec<(String, usize)> = info
+                    .stack
+                    .iter()
+                    .map(|f| (f.function.clone(), f.line))
+                    .collect();
+                frames
+            }
+            r => panic!("unexpected {:?}", r),
+        };
+        let expected = |l0, l1| vec![("main.check".to_owned(), l0), ("main.main".to_owned(), l1)];
+
+        let mut fs = fe::FileSet::new();
+        let mut code = engine.compile(&reader, path, &mut fs).unwrap();
+        assert!(!code.strip_debug().is_empty());
+        assert!(code.objects.functions.values().all(|f| f.pos().is_empty()));
+        let image = vm::image::write_image(&code, Some(&fs), None).unwrap();
+        // the stack has the names of the functions only
+        assert_eq!(lines(engine.run_code(code, Some(&fs))), expected(0, 0));
+        let (code, _) = vm::image::read_image(&image, None).unwrap();
+        assert_eq!(lines(engine.run_code(code, Some(&fs))), expected(0, 0));
+
+        let mut code = engine.compile(&reader, path, &mut fs).unwrap();
+        let stripped = code.strip_debug();
+        code.attach_debug(stripped);
+        assert_eq!(lines(engine.run_code(code, Some(&fs))), expected(6, 11));
+
+        // the debug section is loaded only when asked for
+        let image = engine.compile_image(&reader, path, None).unwrap();
+        let code = vm::image::read_image_without_debug(&image, None).unwrap();
+        assert_eq!(lines(engine.run_code(code, None)), expected(0, 0));
+        let mut code = vm::image::read_image_without_debug(&image, None).unwrap();
+        let fs = vm::image::read_image_debug(&image, &mut code).unwrap();
+        assert!(fs.is_some());
+        assert_eq!(lines(engine.run_code(code, fs.as_ref())), expected(6, 11));
     }
 }
@@ -3,5 +3,395 @@
 // license that can be found in the LICENSE file.
 
 pub use goscript_pmacro::*;
-pub use goscript_vm::ffi::{Ffi, FfiCallCtx};
+pub use goscript_vm::bind::GosBind;
+pub use goscript_vm::ffi::{Ffi, FfiCallCtx, FfiModule, FromGos, GoError, IntoGos};
 pub use goscript_vm::value::RuntimeResult;
+
+#[cfg(test)]
+mod test {
+    extern crate self as goscript_engine;
+    use super::*;
+    use crate::engine::Engine;
+    use goscript_codegen::FsReader;
+    use goscript_vm::value::GosValue;
+    use goscript_vm::vm::RunResult;
+    use std::cell::Cell;
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::rc::Rc;
+
+    #[derive(Ffi)]
+    pub struct TypedFfi {}
+
+    #[ffi_impl(rename = "typed")]
+    impl TypedFfi {
+        fn ffi_open(&self, path: &str, flags: i64) -> (isize, GoError) {
+            match path.is_empty() {
+                true => (-1, GoError::new("empty path")),
+                false => (path.len() as isize + flags as isize, GoError::nil()),
+            }
+        }
+
+        fn ffi_repeat(&self, data: &[u8], n: u8) -> Vec<String> {
+            vec![String::from_utf8_lossy(data).to_string(); n as usize]
+        }
+
+        fn ffi_div(&self, a: i64, b: i64) -> RuntimeResult<i64> {
+            a.checked_div(b)
+                .ok_or_else(|| "division by zero".to_owned())
+        }
+
+        fn ffi_echo(&self, ctx: &FfiCallCtx, v: GosValue, ok: bool) -> (GosValue, GoError) {
+            match ok {
+                true => (v, GoError::nil()),
+                false => (v, GoError::new(ctx.func_name)),
+            }
+        }
+
+        fn ffi_nothing(&self) {}
+    }
+
+    #[derive(Ffi)]
+    pub struct CallbackFfi {}
+
+    #[ffi_impl(rename = "callback")]
+    impl CallbackFfi {
+        fn ffi_fold(&self, ctx: &FfiCallCtx, n: isize, f: GosValue) -> RuntimeResult<isize> {
+            let mut acc = 0;
+            for i in 0..n {
+                let args = vec![GosValue::new_int(acc), GosValue::new_int(i)];
+                acc = isize::from_gos(&ctx.call_closure(&f, args)?[0])?;
+            }
+            Ok(acc)
+        }
+
+        fn ffi_later(&self, ctx: &FfiCallCtx, f: GosValue, v: isize) -> RuntimeResult<()> {
+            ctx.spawn_closure(&f, vec![GosValue::new_int(v)])
+        }
+    }
+
+    #[derive(GosBind, Debug, PartialEq)]
+    pub struct Limits {
+        max_retries: u8,
+        #[gos(name = "Hosts")]
+        servers: Vec<String>,
+    }
+
+    #[derive(GosBind, Debug, PartialEq)]
+    pub struct Settings {
+        name: String,
+        debug: bool,
+        ratio: f64,
+        limits: Limits,
+        ports: Vec<i64>,
+    }
+
+    fn settings() -> Settings {
+        Settings {
+            name: "app".to_owned(),
+            debug: true,
+            ratio: 0.5,
+            limits: Limits {
+                max_retries: 3,
+                servers: vec!["a".to_owned(), "b".to_owned()],
+            },
+            ports: vec![80, 443],
+        }
+    }
+
+    #[derive(Ffi)]
+    pub struct BindFfi {}
+
+    #[ffi_impl(rename = "bind")]
+    impl BindFfi {
+        fn ffi_load(&self, ctx: &mut FfiCallCtx, target: GosValue) -> RuntimeResult<()> {
+            ctx.bind_into(&target, &settings())
+        }
+
+        fn ffi_check(&self, ctx: &FfiCallCtx, v: GosValue) -> RuntimeResult<bool> {
+            let mut want = settings();
+            want.limits.max_retries = 4;
+            want.ports.push(8080);
+            Ok(ctx.bind_from::<Settings>(&v)? == want)
+        }
+    }
+
+    fn run(source: &str) -> RunResult {
+        let mut engine = Engine::new();
+        engine.register_extension(TypedFfi::ffi__id(), TypedFfi::ffi__new());
+        engine.register_extension(CallbackFfi::ffi__id(), CallbackFfi::ffi__new());
+        engine.register_extension(BindFfi::ffi__id(), BindFfi::ffi__new());
+        let reader = FsReader::new(Some("./"), Some("../std/"), Some(source));
+        engine
+            .run(false, false, &reader, FsReader::temp_file_path())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_typed_ffi() {
+        let source = r#"
+    package main
+
+    type typed interface {
+        open(path string, flags int64) (int, error)
+        repeat(data []byte, n uint8) []string
+        div(a, b int64) int64
+        echo(v interface{}, ok bool) (interface{}, error)
+        nothing()
+    }
+
+    func main() {
+        t := ffi(typed, "typed")
+        fd, err := t.open("a.txt", 10)
+        assert(fd == 15 && err == nil)
+        fd, err = t.open("", 0)
+        assert(fd == -1 && err.Error() == "empty path")
+        parts := t.repeat([]byte("ab"), 3)
+        assert(len(parts) == 3 && parts[2] == "ab")
+        assert(len(t.repeat(nil, 1)) == 1)
+        assert(t.div(7, 2) == 3)
+        v, err := t.echo("x", true)
+        assert(v == "x" && err == nil)
+        _, err = t.echo(1, false)
+        assert(err.Error() == "echo")
+        t.nothing()
+        t.div(1, 0)
+    }
+    "#;
+        match run(source) {
+            RunResult::Panic(info) => assert_eq!(info.message, "division by zero"),
+            r => panic!("unexpected {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_typed_ffi_mismatch() {
+        let source = r#"
+    package main
+
+    type typed interface {
+        open(path []byte, flags int64) (int, error)
+    }
+
+    func main() {
+        t := ffi(typed, "typed")
+        t.open([]byte("a"), 1)
+    }
+    "#;
+        match run(source) {
+            RunResult::Panic(info) => {
+                assert_eq!(
+                    info.message,
+                    "open: argument 0: expected string, found Slice"
+                )
+            }
+            r => panic!("unexpected {:?}", r),
+        }
+
+        let source = r#"
+    package main
+
+    type typed interface {
+        div(a, b uint64) int64
+    }
+
+    func main() {
+        t := ffi(typed, "typed")
+        t.div(1<<63, 1)
+    }
+    "#;
+        match run(source) {
+            RunResult::Panic(info) => {
+                assert_eq!(
+                    info.message,
+                    "div: argument 0: 9223372036854775808 out of range of i64"
+                )
+            }
+            r => panic!("unexpected {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_gos_bind() {
+        let source = r#"
+    package main
+
+    type bind interface {
+        load(target interface{})
+        check(v interface{}) bool
+    }
+
+    type Limits struct {
+        MaxRetries int
+        Hosts      []string
+    }
+
+    type Settings struct {
+        Name   string
+        Debug  bool
+        Ratio  float32
+        Limits Limits
+        Ports  []int
+        Extra  int
+    }
+
+    func main() {
+        b := ffi(bind, "bind")
+        s := Settings{Extra: 5}
+        b.load(&s)
+        assert(s.Name == "app" && s.Debug && s.Ratio == 0.5 && s.Extra == 0)
+        assert(s.Limits.MaxRetries == 3 && len(s.Limits.Hosts) == 2 && s.Limits.Hosts[1] == "b")
+        assert(len(s.Ports) == 2 && s.Ports[1] == 443)
+        s.Limits.MaxRetries = 4
+        s.Ports = append(s.Ports, 8080)
+        assert(b.check(s))
+        s.Name = "other"
+        assert(!b.check(s))
+    }
+    "#;
+        assert_eq!(run(source).code(), 0);
+
+        let source = r#"
+    package main
+
+    type bind interface {
+        check(v interface{}) bool
+    }
+
+    type Limits struct {
+        MaxRetries int
+    }
+
+    type Settings struct {
+        Name   string
+        Debug  bool
+        Ratio  float64
+        Limits Limits
+        Ports  []int
+    }
+
+    func main() {
+        b := ffi(bind, "bind")
+        b.check(Settings{Limits: Limits{MaxRetries: 300}})
+    }
+    "#;
+        match run(source) {
+            RunResult::Panic(info) => {
+                assert_eq!(
+                    info.message,
+                    "Settings.limits: Limits.max_retries: 300 out of range of u8"
+                )
+            }
+            r => panic!("unexpected {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_call_closure() {
+        let source = r#"
+    package main
+
+    type callback interface {
+        fold(n int, f func(acc, i int) int) int
+        later(f func(int), v int)
+    }
+
+    type adder struct{ k int }
+
+    func (a *adder) add(acc, i int) int { return acc + i*a.k }
+
+    func main() {
+        c := ffi(callback, "callback")
+        calls := 0
+        sum := c.fold(5, func(acc, i int) int {
+            calls++
+            return acc + i
+        })
+        assert(sum == 10 && calls == 5)
+        a := &adder{2}
+        assert(c.fold(3, a.add) == 6)
+        nested := c.fold(2, func(acc, i int) int {
+            return acc + c.fold(i+1, func(acc, j int) int { return acc + j })
+        })
+        assert(nested == 1)
+
+        // the other goroutines run while a callback blocks
+        ch := make(chan int)
+        go func() { ch <- 7 }()
+        assert(c.fold(1, func(acc, i int) int { return <-ch }) == 7)
+        done := make(chan int)
+        c.later(func(v int) { done <- v }, 3)
+        assert(<-done == 3)
+
+        defer func() {
+            assert(recover() == "boom")
+            assert(calls == 6)
+        }()
+        c.fold(1, func(acc, i int) int {
+            calls++
+            panic("boom")
+        })
+    }
+    "#;
+        assert_eq!(run(source).code(), 0);
+    }
+
+    #[test]
+    fn test_ffi_modules() {
+        let source = r#"
+    package main
+
+    type typed interface {
+        div(a, b int64) int64
+    }
+
+    func main() {
+        a := ffi(typed, "mylib.math")
+        b := ffi(typed, "mylib.math")
+        c := ffi(typed, "mylib.sub.math")
+        assert(a.div(6, 3) == 2 && b.div(1, 1) == 1 && c.div(0, 1) == 0)
+    }
+    "#;
+        let created = Rc::new(Cell::new(0));
+        let (c0, c1) = (created.clone(), created.clone());
+        let new = move |c: &Rc<Cell<i32>>| {
+            c.set(c.get() + 1);
+            TypedFfi::ffi__new()
+        };
+        let new2 = new;
+        let module = FfiModule::new()
+            .with_lazy("math", move || new(&c0))
+            .with_module("sub", FfiModule::new().with_lazy("math", move || new2(&c1)))
+            .with_lazy("unused", || panic!("never created"));
+        let mut engine = Engine::new();
+        engine.register_module("mylib", module).unwrap();
+
+        let err =
+            engine.register_module("mylib", FfiModule::new().with("math", TypedFfi::ffi__new()));
+        assert_eq!(
+            err.unwrap_err(),
+            "FFI named mylib.math is already registered"
+        );
+        let dup = FfiModule::new()
+            .with("a", TypedFfi::ffi__new())
+            .with("b", TypedFfi::ffi__new())
+            .with("a", TypedFfi::ffi__new());
+        let err = engine.register_module("other", dup);
+        assert_eq!(err.unwrap_err(), "FFI named other.a is already registered");
+        // nothing is registered when a name is taken
+        let err = engine.register_module("other", FfiModule::new().with("b", TypedFfi::ffi__new()));
+        assert!(err.is_ok());
+
+        let reader = FsReader::new(Some("./"), Some("../std/"), Some(source));
+        let path = FsReader::temp_file_path();
+        let mut fs = goscript_parser::FileSet::new();
+        let code = engine.compile(&reader, path, &mut fs).unwrap();
+        assert!(engine.check_ffi_deps(&code).is_ok());
+        assert_eq!(created.get(), 0);
+        assert_eq!(engine.run(false, false, &reader, path).unwrap().code(), 0);
+        // created once for each name
+        assert_eq!(created.get(), 2);
+        assert_eq!(engine.run(false, false, &reader, path).unwrap().code(), 0);
+        assert_eq!(created.get(), 2);
+    }
+}
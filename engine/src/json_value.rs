@@ -0,0 +1,158 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Converts Goscript values to and from `serde_json::Value`, for FFI code
+//! exchanging structured data with the scripts without marshaling it by
+//! hand. The rules are the ones of the encoding/json std package: structs
+//! are objects keyed by their exported field names or json tags, byte
+//! slices are base64 strings, map keys are sorted and so on.
+
+use crate::ffi::FfiCallCtx;
+use crate::std::json::{self, Json};
+use goscript_vm::value::{GosValue, RuntimeResult};
+use serde_json::{Map, Number, Value};
+
+/// Converts the value in the interface `iface`, which is how an FFI
+/// function gets an argument of any type, like json.Marshal would.
+pub fn to_json(ctx: &FfiCallCtx, iface: &GosValue) -> RuntimeResult<Value> {
+    json::encode_iface(ctx, iface).and_then(into_value)
+}
+
+/// Converts `value` to an `interface{}` holding what json.Unmarshal stores
+/// in one: nil, bool, float64, string, []interface{} or
+/// map[string]interface{}.
+pub fn from_json(ctx: &mut FfiCallCtx, value: &Value) -> RuntimeResult<GosValue> {
+    json::decode_any(ctx, &from_value(value))
+}
+
+/// Stores `value` where the pointer in the interface `target` points to,
+/// like json.Unmarshal. Structs and maps are updated, not replaced, and on
+/// a type mismatch the rest is still stored before the error is returned.
+pub fn from_json_into(ctx: &mut FfiCallCtx, value: &Value, target: &GosValue) -> RuntimeResult<()> {
+    json::decode_into(ctx, &from_value(value), target)
+}
+
+fn into_value(j: Json) -> RuntimeResult<Value> {
+    Ok(match j {
+        Json::Null => Value::Null,
+        Json::Bool(b) => Value::Bool(b),
+        Json::Number(n) => Value::Number(
+            n.parse::<Number>()
+                .map_err(|e| format!("json: unsupported value: {}: {}", n, e))?,
+        ),
+        Json::Str(s) => Value::String(s),
+        Json::Array(items) => Value::Array(
+            items
+                .into_iter()
+                .map(into_value)
+                .collect::<RuntimeResult<Vec<Value>>>()?,
+        ),
+        Json::Object(members) => Value::Object(
+            members
+                .into_iter()
+                .map(|(k, v)| Ok((k, into_value(v)?)))
+                .collect::<RuntimeResult<Map<String, Value>>>()?,
+        ),
+    })
+}
+
+fn from_value(value: &Value) -> Json {
+    match value {
+        Value::Null => Json::Null,
+        Value::Bool(b) => Json::Bool(*b),
+        Value::Number(n) => Json::Number(n.to_string()),
+        Value::String(s) => Json::Str(s.clone()),
+        Value::Array(items) => Json::Array(items.iter().map(from_value).collect()),
+        Value::Object(members) => Json::Object(
+            members
+                .iter()
+                .map(|(k, v)| (k.clone(), from_value(v)))
+                .collect(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    extern crate self as goscript_engine;
+    use super::*;
+    use crate::engine::Engine;
+    use crate::ffi::*;
+    use goscript_codegen::FsReader;
+    use goscript_vm::vm::RunResult;
+    use serde_json::json;
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::rc::Rc;
+
+    #[derive(Ffi)]
+    pub struct BridgeFfi {}
+
+    #[ffi_impl(rename = "bridge")]
+    impl BridgeFfi {
+        fn ffi_send(&self, ctx: &FfiCallCtx, v: GosValue) -> RuntimeResult<String> {
+            to_json(ctx, &v).map(|j| j.to_string())
+        }
+
+        fn ffi_config(&self, ctx: &mut FfiCallCtx) -> RuntimeResult<GosValue> {
+            from_json(
+                ctx,
+                &json!({"name": "app", "ports": [80, 443], "debug": true}),
+            )
+        }
+
+        fn ffi_load(&self, ctx: &mut FfiCallCtx, target: GosValue) -> GoError {
+            let value = json!({"Name": "bob", "tags": ["a", "b"], "Age": "old"});
+            match from_json_into(ctx, &value, &target) {
+                Ok(()) => GoError::nil(),
+                Err(e) => GoError::new(&e),
+            }
+        }
+    }
+
+    #[test]
+    fn test_json_value() {
+        let source = r#"
+    package main
+
+    type bridge interface {
+        send(v interface{}) string
+        config() interface{}
+        load(target interface{}) error
+    }
+
+    type Person struct {
+        Name   string
+        Age    int
+        Tags   []string `json:"tags"`
+        secret int
+        Data   []byte `json:",omitempty"`
+    }
+
+    func main() {
+        b := ffi(bridge, "bridge")
+        s := b.send(Person{Name: "amy", Age: 30, Tags: []string{"x"}, secret: 1})
+        assert(s == `{"Age":30,"Name":"amy","tags":["x"]}`)
+        assert(b.send(map[string]float64{"pi": 3.5}) == `{"pi":3.5}`)
+        assert(b.send([]byte("hi")) == `"aGk="`)
+        assert(b.send(nil) == "null")
+
+        c := b.config().(map[string]interface{})
+        assert(c["name"] == "app" && c["debug"].(bool))
+        ports := c["ports"].([]interface{})
+        assert(len(ports) == 2 && ports[1] == 443.0)
+
+        p := Person{Age: 7}
+        err := b.load(&p)
+        assert(err.Error() == "json: cannot unmarshal string into Go struct field Person.Age of type int")
+        assert(p.Name == "bob" && p.Age == 7 && len(p.Tags) == 2 && p.Tags[1] == "b")
+    }
+    "#;
+        let mut engine = Engine::new();
+        engine.register_extension(BridgeFfi::ffi__id(), BridgeFfi::ffi__new());
+        let reader = FsReader::new(Some("./"), Some("../std/"), Some(source));
+        let result = engine.run(false, false, &reader, FsReader::temp_file_path());
+        assert_eq!(result.unwrap(), RunResult::Done);
+    }
+}
@@ -2,14 +2,41 @@
 // Use of this source code is governed by a BSD-style
 // license that can be found in the LICENSE file.
 
+mod conformance;
+mod deps;
+mod diagnostics;
+mod doc;
 mod engine;
+#[cfg(feature = "serde_json")]
+pub mod json_value;
+#[cfg(feature = "threads")]
+mod pool;
+mod rename;
+mod repl;
 pub mod run_fs;
+pub mod run_mem;
 #[cfg(feature = "run_zip")]
 pub mod run_zip;
 mod std;
+mod util;
 
 #[macro_use]
-mod ffi;
+pub mod ffi;
 
+pub use self::std::{host, kv, sql, Capabilities, Capability, StdModule, StdSelection};
+pub use conformance::{ConformanceReport, FeatureResult, Outcome, SpecFeature};
+pub use deps::{DependencyGraph, PackageNode, UnresolvedImport};
+pub use diagnostics::to_json as diagnostics_to_json;
+pub use doc::{FuncDoc, PackageDoc, TypeDoc, ValueDoc};
 pub use engine::*;
-pub use goscript_parser::errors::ErrorList;
+pub use goscript_parser::errors::{Diagnostic, ErrorList, Severity};
+pub use goscript_vm::audit::{CastKind, CastRecord};
+pub use goscript_vm::gc::{GcConfig, GcStats};
+pub use goscript_vm::limits::{Limit, Limits};
+pub use goscript_vm::profile::{AllocKind, AllocRecord, AllocReport};
+pub use goscript_vm::step::{DecodedInstruction, StackDiff, Step, StepFrame, StepObserver};
+pub use goscript_vm::vm::{BlockedGoroutine, PanicInfo, RunResult, StackFrame};
+#[cfg(feature = "threads")]
+pub use pool::{Pool, Task};
+pub use rename::TextEdit;
+pub use repl::{Evaluation, Repl};
@@ -0,0 +1,236 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Runs programs on a pool of threads, to use several cores.
+//!
+//! The values of a VM are not `Send`, so the goroutines of one program
+//! always share a thread. What runs in parallel are the programs: each
+//! worker runs bytecode images, which are plain bytes, one after the
+//! other, every one in an engine of its own.
+
+use crate::engine::Engine;
+use crate::util::panic_msg;
+use goscript_vm::vm::RunResult;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+type NewEngine = dyn Fn() -> Engine + Send + Sync;
+
+struct Job {
+    image: Arc<[u8]>,
+    result: mpsc::Sender<Result<RunResult, String>>,
+}
+
+/// A pool of threads running images written by `Engine::compile_image`
+pub struct Pool {
+    jobs: Option<mpsc::Sender<Job>>,
+    workers: Vec<thread::JoinHandle<()>>,
+}
+
+impl Pool {
+    /// Returns a pool of `threads` workers, with the engines of `Engine::new`
+    pub fn new(threads: usize) -> Pool {
+        Pool::with_engine(threads, Engine::new)
+    }
+
+    /// Returns a pool of `threads` workers, creating the engine of each job
+    /// with `new_engine`, where the extensions, std io and other settings of
+    /// the engine can be set up.
+    pub fn with_engine<F>(threads: usize, new_engine: F) -> Pool
+    where
+        F: Fn() -> Engine + Send + Sync + 'static,
+    {
+        let new_engine: Arc<NewEngine> = Arc::new(new_engine);
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let workers = (0..threads.max(1))
+            .map(|i| {
+                let receiver = receiver.clone();
+                let new_engine = new_engine.clone();
+                thread::Builder::new()
+                    .name(format!("goscript-pool-{}", i))
+                    .spawn(move || Pool::work(&receiver, &*new_engine))
+                    .expect("failed to spawn a pool thread")
+            })
+            .collect();
+        Pool {
+            jobs: Some(sender),
+            workers: workers,
+        }
+    }
+
+    /// Runs `image` on the first idle worker, or returns an error if no
+    /// worker is left, which happens only if creating the engines panics.
+    pub fn spawn(&self, image: Arc<[u8]>) -> Result<Task, String> {
+        let (sender, receiver) = mpsc::channel();
+        let job = Job {
+            image: image,
+            result: sender,
+        };
+        // the jobs are taken only when the pool is dropped
+        self.jobs
+            .as_ref()
+            .unwrap()
+            .send(job)
+            .map_err(|_| "the pool has no workers left".to_owned())?;
+        Ok(Task { result: receiver })
+    }
+
+    fn work(receiver: &Mutex<mpsc::Receiver<Job>>, new_engine: &NewEngine) {
+        loop {
+            // the lock is released before running the job
+            let job = match receiver.lock().unwrap_or_else(|e| e.into_inner()).recv() {
+                Ok(job) => job,
+                Err(_) => break,
+            };
+            // the env, exit code and statics a program changes stay in its
+            // engine, each job gets a new one
+            let engine = new_engine();
+            // a failed assert panics, the engine goes with the job
+            let result = match panic::catch_unwind(AssertUnwindSafe(|| {
+                engine.run_image(&job.image, None)
+            })) {
+                Ok(result) => result,
+                Err(p) => Err(format!("the program panicked: {}", panic_msg(&p))),
+            };
+            // the task may have been dropped
+            let _ = job.result.send(result);
+        }
+    }
+}
+
+impl Drop for Pool {
+    /// Waits for the jobs spawned to finish
+    fn drop(&mut self) {
+        drop(self.jobs.take());
+        for w in self.workers.drain(..) {
+            let _ = w.join();
+        }
+    }
+}
+
+/// A program spawned on a `Pool`
+pub struct Task {
+    result: mpsc::Receiver<Result<RunResult, String>>,
+}
+
+impl Task {
+    /// Waits for the program to end and returns how it ended, an error if
+    /// the image could not be run or the worker crashed.
+    pub fn join(self) -> Result<RunResult, String> {
+        self.result
+            .recv()
+            .unwrap_or_else(|_| Err("the worker running the program crashed".to_owned()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use goscript_codegen::FsReader;
+
+    #[test]
+    fn test_pool() {
+        let source = r#"
+    package main
+
+    import "os"
+
+    func fib(n int) int {
+        if n < 2 {
+            return n
+        }
+        return fib(n-1) + fib(n-2)
+    }
+
+    func main() {
+        assert(fib(18) == 2584)
+        if len(os.Args) > 5 {
+            panic("unreachable")
+        }
+        os.Exit(3)
+    }
+    "#;
+        let reader = FsReader::new(Some("./"), Some("../std/"), Some(source));
+        let image: Arc<[u8]> = Engine::new()
+            .compile_image(&reader, FsReader::temp_file_path(), None)
+            .unwrap()
+            .into();
+        let pool = Pool::new(3);
+        let tasks: Vec<Task> = (0..6).map(|_| pool.spawn(image.clone()).unwrap()).collect();
+        for t in tasks {
+            assert_eq!(t.join(), Ok(RunResult::Exit(3)));
+        }
+        let bad = pool.spawn(Arc::from(&b"not an image"[..])).unwrap();
+        assert!(bad.join().is_err());
+    }
+
+    #[test]
+    fn test_pool_failed_assert() {
+        let source = r#"
+    package main
+
+    func check(n int) {
+        assert(n > 2)
+    }
+
+    func main() {
+        check(1)
+    }
+    "#;
+        let reader = FsReader::new(Some("./"), Some("../std/"), Some(source));
+        let engine = Engine::new();
+        let path = FsReader::temp_file_path();
+        let failing: Arc<[u8]> = engine.compile_image(&reader, path, None).unwrap().into();
+        let reader = FsReader::new(
+            Some("./"),
+            Some("../std/"),
+            Some("package main\nfunc main() {}"),
+        );
+        let fine: Arc<[u8]> = engine.compile_image(&reader, path, None).unwrap().into();
+
+        // more failures than workers, the workers keep running
+        let pool = Pool::new(2);
+        for _ in 0..4 {
+            let err = pool.spawn(failing.clone()).unwrap().join().unwrap_err();
+            assert_eq!(err, "the program panicked: ASSERT");
+        }
+        let task = pool.spawn(fine).unwrap();
+        assert_eq!(task.join(), Ok(RunResult::Done));
+    }
+
+    #[test]
+    fn test_pool_job_isolation() {
+        let source = r#"
+    package main
+
+    import "os"
+
+    func main() {
+        if os.Getenv("POOL_JOB") != "" {
+            os.Exit(1)
+        }
+        assert(os.Setenv("POOL_JOB", "1") == nil)
+        os.Exit(2)
+    }
+    "#;
+        let reader = FsReader::new(Some("./"), Some("../std/"), Some(source));
+        let image: Arc<[u8]> = Engine::new()
+            .compile_image(&reader, FsReader::temp_file_path(), None)
+            .unwrap()
+            .into();
+        let pool = Pool::with_engine(1, || {
+            let engine = Engine::new();
+            engine.set_env(&[]);
+            engine
+        });
+        // the second job runs on the same worker and doesn't see the first
+        for _ in 0..2 {
+            let task = pool.spawn(image.clone()).unwrap();
+            assert_eq!(task.join(), Ok(RunResult::Exit(2)));
+        }
+    }
+}
@@ -0,0 +1,647 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Renaming of identifiers, like `gorename`, for editors and script
+//! maintainers. The program is type-checked and the declaration and every
+//! reference of the object an identifier denotes get the new name, unless
+//! that would change the meaning of the program.
+//!
+//! Renaming a method does not check whether the method is needed for its
+//! type to implement an interface.
+
+extern crate goscript_parser as fe;
+extern crate goscript_types as types;
+
+use fe::ast::{self, Node};
+use fe::errors::ErrorList;
+use fe::objects::{IdentKey, Objects as AstObjects};
+use fe::{FilePos, FileSet, Pos};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use types::{
+    lookup_field_or_method, LookupResult, ObjKey, PackageKey, ScopeKey, SourceRead, TCObjects,
+    TypeInfo, TypeKey,
+};
+
+/// A replacement of the text of a single line, the columns are in chars
+/// and start at 1, like the ones of the positions in error messages.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextEdit {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    /// the number of chars replaced, 0 for an insertion
+    pub len: usize,
+    pub new_text: String,
+}
+
+/// Type-checks the program at `path`, and returns the edits renaming the
+/// identifier at `line` and `column` in `file` to `new_name`, in all the
+/// files of the program. The edits are sorted by file and position.
+pub fn rename<S: SourceRead>(
+    reader: &S,
+    path: &str,
+    file: &str,
+    line: usize,
+    column: usize,
+    new_name: &str,
+) -> Result<Vec<TextEdit>, ErrorList> {
+    let cfg = types::TraceConfig {
+        trace_parser: false,
+        trace_checker: false,
+    };
+    let fs = &mut FileSet::new();
+    let asto = &mut AstObjects::new();
+    let tco = &mut TCObjects::new();
+    let results = &mut HashMap::new();
+    let pkgs = &mut HashMap::new();
+    let el = ErrorList::new();
+    let key = types::ImportKey::new(path, "./");
+    let pkg = types::Importer::new(&cfg, reader, fs, pkgs, results, asto, tco, &el, 0).import(&key);
+    if pkg.is_err() || el.has_errors() {
+        return Err(el);
+    }
+    let renamer = Renamer {
+        fs: fs,
+        asto: asto,
+        tco: tco,
+        infos: results,
+        pkgs: pkgs,
+    };
+    renamer
+        .rename(file, line, column, new_name)
+        .map_err(|(pos, msg)| {
+            el.add(pos, msg, false, false);
+            el
+        })
+}
+
+type RenameResult<T> = Result<T, (Option<FilePos>, String)>;
+
+/// An identifier referring to an object to be renamed
+struct Ref {
+    ident: IdentKey,
+    pkg: PackageKey,
+    is_def: bool,
+}
+
+struct Renamer<'a> {
+    fs: &'a FileSet,
+    asto: &'a AstObjects,
+    tco: &'a TCObjects,
+    infos: &'a HashMap<PackageKey, TypeInfo>,
+    pkgs: &'a HashMap<String, PackageKey>,
+}
+
+impl<'a> Renamer<'a> {
+    fn rename(
+        &self,
+        file: &str,
+        line: usize,
+        column: usize,
+        new_name: &str,
+    ) -> RenameResult<Vec<TextEdit>> {
+        let (ident, okey) = self.object_at(file, line, column)?;
+        let obj = &self.tco.lobjs[okey];
+        let err_pos = self.fs.position(self.asto.idents[ident].pos);
+        let error = |msg: String| Err((err_pos.clone(), msg));
+        if !is_identifier(new_name) {
+            return error(format!("{} is not a valid identifier", new_name));
+        }
+        if new_name == obj.name() {
+            return error(format!("{} is already named {}", obj.name(), new_name));
+        }
+        let pkg = match obj.pkg() {
+            Some(p) => p,
+            None => return error(format!("cannot rename the predeclared {}", obj.name())),
+        };
+        if !self.in_workspace(pkg) {
+            return error(format!(
+                "cannot rename {}, it is declared in package {} which is not part of the workspace",
+                obj.name(),
+                self.tco.pkgs[pkg].path()
+            ));
+        }
+
+        let targets = self.targets(okey);
+        let refs = self.refs(&targets);
+        for &t in targets.iter() {
+            self.check_object(t, pkg, new_name, &refs)?;
+        }
+        if obj.exported() && !ast::is_exported(new_name) {
+            if let Some(r) = refs.iter().find(|r| r.pkg != pkg) {
+                return Err((
+                    self.ident_pos(r.ident),
+                    format!(
+                        "renaming {} to {} would make it unexported, it is used in package {}",
+                        obj.name(),
+                        new_name,
+                        self.tco.pkgs[r.pkg].path()
+                    ),
+                ));
+            }
+        }
+
+        let mut edits: Vec<TextEdit> = refs
+            .iter()
+            .map(|r| {
+                let ident = &self.asto.idents[r.ident];
+                let pos = self.fs.position(ident.pos).unwrap();
+                TextEdit {
+                    file: pos.filename.to_string(),
+                    line: pos.line,
+                    column: pos.column,
+                    len: ident.name.chars().count(),
+                    new_text: new_name.to_owned(),
+                }
+            })
+            .collect();
+        // an import without a name gets one
+        if obj.entity_type().is_pkg_name() && !refs.iter().any(|r| r.is_def) {
+            if let Some(p) = self.import_path_pos(okey, pkg) {
+                let pos = self.fs.position(p).unwrap();
+                edits.push(TextEdit {
+                    file: pos.filename.to_string(),
+                    line: pos.line,
+                    column: pos.column,
+                    len: 0,
+                    new_text: format!("{} ", new_name),
+                });
+            }
+        }
+        edits.sort_by(|a, b| (&a.file, a.line, a.column).cmp(&(&b.file, b.line, b.column)));
+        edits.dedup();
+        Ok(edits)
+    }
+
+    /// Finds the identifier at the position and the object it denotes
+    fn object_at(
+        &self,
+        file: &str,
+        line: usize,
+        column: usize,
+    ) -> RenameResult<(IdentKey, ObjKey)> {
+        let file_path = Path::new(file.trim_start_matches("./"));
+        let found = self
+            .asto
+            .idents
+            .iter()
+            .find(|(_, ident)| match self.fs.position(ident.pos) {
+                Some(p) => {
+                    Path::new(&*p.filename).ends_with(file_path)
+                        && p.line == line
+                        && p.column <= column
+                        && column < p.column + ident.name.chars().count()
+                }
+                None => false,
+            });
+        let (ikey, ident) = match found {
+            Some(f) => f,
+            None => {
+                return Err((
+                    None,
+                    format!("no identifier at {}:{}:{}", file, line, column),
+                ))
+            }
+        };
+        for info in self.infos.values() {
+            if let Some(o) = info.uses.get(&ikey) {
+                return Ok((ikey, *o));
+            }
+            if let Some(Some(o)) = info.defs.get(&ikey) {
+                return Ok((ikey, *o));
+            }
+        }
+        Err((
+            self.ident_pos(ikey),
+            format!(
+                "{} does not denote an object that can be renamed",
+                ident.name
+            ),
+        ))
+    }
+
+    /// The packages loaded from the working directory, not from the base
+    /// directory of the library
+    fn in_workspace(&self, pkg: PackageKey) -> bool {
+        let path = self.tco.pkgs[pkg].path();
+        path.starts_with('.') || path.starts_with('/')
+    }
+
+    /// The objects renamed together: a type with the embedded fields named
+    /// after it
+    fn targets(&self, okey: ObjKey) -> Vec<ObjKey> {
+        let type_name = match self.embedded_type(okey) {
+            Some(t) => t,
+            None if self.tco.lobjs[okey].entity_type().is_type_name() => okey,
+            None => return vec![okey],
+        };
+        let mut targets = vec![type_name];
+        for info in self.infos.values() {
+            for (ikey, o) in info.defs.iter() {
+                if let Some(o) = o {
+                    if info.uses.get(ikey) == Some(&type_name) && self.is_embedded(*o) {
+                        targets.push(*o);
+                    }
+                }
+            }
+        }
+        targets
+    }
+
+    fn is_embedded(&self, okey: ObjKey) -> bool {
+        let obj = &self.tco.lobjs[okey];
+        obj.entity_type().is_var() && obj.var_embedded()
+    }
+
+    /// The type name an embedded field is named after
+    fn embedded_type(&self, okey: ObjKey) -> Option<ObjKey> {
+        if !self.is_embedded(okey) {
+            return None;
+        }
+        self.infos.values().find_map(|info| {
+            info.defs
+                .iter()
+                .find(|(_, o)| **o == Some(okey))
+                .and_then(|(ikey, _)| info.uses.get(ikey).copied())
+        })
+    }
+
+    fn refs(&self, targets: &[ObjKey]) -> Vec<Ref> {
+        let mut refs = vec![];
+        let mut seen = HashSet::new();
+        for (pkg, info) in self.infos.iter() {
+            for (ikey, o) in info.defs.iter() {
+                if o.is_some_and(|o| targets.contains(&o)) && seen.insert(*ikey) {
+                    refs.push(Ref {
+                        ident: *ikey,
+                        pkg: *pkg,
+                        is_def: true,
+                    });
+                }
+            }
+            for (ikey, o) in info.uses.iter() {
+                if targets.contains(o) && seen.insert(*ikey) {
+                    refs.push(Ref {
+                        ident: *ikey,
+                        pkg: *pkg,
+                        is_def: false,
+                    });
+                }
+            }
+        }
+        refs
+    }
+
+    /// Checks that renaming `okey` to `new_name` in package `pkg` neither
+    /// conflicts with another declaration nor changes what an identifier
+    /// refers to.
+    fn check_object(
+        &self,
+        okey: ObjKey,
+        pkg: PackageKey,
+        new_name: &str,
+        refs: &[Ref],
+    ) -> RenameResult<()> {
+        let obj = &self.tco.lobjs[okey];
+        let old_name = obj.name();
+        let obj_pos = self.fs.position(obj.pos());
+        let pkg_scope = *self.tco.pkgs[pkg].scope();
+        let scope = match obj.parent() {
+            Some(s) => s,
+            None => return self.check_member(okey, new_name),
+        };
+        let conflict = |other: ObjKey| {
+            Err((
+                obj_pos.clone(),
+                format!(
+                    "renaming {} to {} conflicts with the declaration at {}",
+                    old_name,
+                    new_name,
+                    self.pos_str(self.tco.lobjs[other].pos())
+                ),
+            ))
+        };
+        if let Some(other) = self.tco.scopes[scope].lookup(new_name) {
+            return conflict(*other);
+        }
+        if obj.entity_type().is_label() {
+            return Ok(());
+        }
+        // package level declarations and imports share the names
+        if scope == pkg_scope {
+            for file_scope in self.tco.scopes[pkg_scope].children().iter() {
+                if let Some(other) = self.tco.scopes[*file_scope].lookup(new_name) {
+                    return conflict(*other);
+                }
+            }
+            let is_main = self.tco.pkgs[pkg].name().as_deref() == Some("main");
+            for special in ["init", "main"] {
+                if (special == "init" || is_main) && (old_name == special || new_name == special) {
+                    return Err((
+                        obj_pos,
+                        format!("cannot rename {} to {}", old_name, new_name),
+                    ));
+                }
+            }
+        } else if obj.entity_type().is_pkg_name() {
+            if let Some(other) = self.tco.scopes[pkg_scope].lookup(new_name) {
+                return conflict(*other);
+            }
+        }
+
+        // a declaration between a reference and the object would hide it
+        for r in refs.iter().filter(|r| !r.is_def && r.pkg == pkg) {
+            let pos = self.asto.idents[r.ident].pos;
+            let mut s = self.innermost(pkg_scope, pos);
+            while s != scope {
+                if let Some(other) = self.visible(s, new_name, pos) {
+                    return Err((
+                        self.ident_pos(r.ident),
+                        format!(
+                            "renaming {} to {} would make this reference refer to the declaration at {}",
+                            old_name,
+                            new_name,
+                            self.pos_str(self.tco.lobjs[other].pos())
+                        ),
+                    ));
+                }
+                match self.tco.scopes[s].parent() {
+                    Some(p) => s = *p,
+                    None => break,
+                }
+            }
+        }
+
+        // the renamed object would hide the objects named new_name declared
+        // outside of its scope from the references inside
+        let info = &self.infos[&pkg];
+        for (ikey, other) in info.uses.iter() {
+            let other_obj = &self.tco.lobjs[*other];
+            if other_obj.name() != new_name {
+                continue;
+            }
+            match other_obj.parent() {
+                // fields, methods and qualified identifiers are selected
+                None => continue,
+                Some(p) if self.is_other_pkg_scope(p, pkg) => continue,
+                _ => {}
+            }
+            let pos = self.asto.idents[*ikey].pos;
+            let mut s = self.innermost(pkg_scope, pos);
+            loop {
+                if self.tco.scopes[s].lookup(new_name) == Some(other) {
+                    break;
+                }
+                if s == scope {
+                    if *obj.scope_pos() <= pos {
+                        return Err((
+                            self.ident_pos(*ikey),
+                            format!(
+                                "renaming {} to {} would make this reference to {} refer to it",
+                                old_name, new_name, new_name
+                            ),
+                        ));
+                    }
+                    break;
+                }
+                match self.tco.scopes[s].parent() {
+                    Some(p) => s = *p,
+                    None => break,
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks renaming a field or a method, which must stay unique among
+    /// the fields and methods of its type
+    fn check_member(&self, okey: ObjKey, new_name: &str) -> RenameResult<()> {
+        let obj = &self.tco.lobjs[okey];
+        let obj_pos = self.fs.position(obj.pos());
+        let typ = if obj.entity_type().is_func() {
+            let sig = self.tco.types[obj.typ().unwrap()]
+                .try_as_signature()
+                .unwrap();
+            let recv = self.tco.lobjs[sig.recv().unwrap()].typ().unwrap();
+            let base = match self.tco.types[recv].try_as_pointer() {
+                Some(p) => p.base(),
+                None => recv,
+            };
+            let underlying = self.tco.types[base].underlying().unwrap_or(base);
+            if self.tco.types[underlying].try_as_interface().is_some() {
+                return Err((
+                    obj_pos,
+                    format!("cannot rename the interface method {}", obj.name()),
+                ));
+            }
+            Some(base)
+        } else {
+            self.struct_of(okey)
+        };
+        if let Some(t) = typ {
+            let found = match lookup_field_or_method(t, true, obj.pkg(), new_name, self.tco) {
+                LookupResult::Entry(other, _, _) => Some(self.pos_str(self.tco.lobjs[other].pos())),
+                LookupResult::NotFound => None,
+                _ => Some("an embedded type".to_owned()),
+            };
+            if let Some(at) = found {
+                return Err((
+                    obj_pos,
+                    format!(
+                        "renaming {} to {} conflicts with the field or method at {}",
+                        obj.name(),
+                        new_name,
+                        at
+                    ),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// The named type of the struct `field` belongs to, or the struct
+    /// itself if it has no name
+    fn struct_of(&self, field: ObjKey) -> Option<TypeKey> {
+        let (skey, _) = self.tco.types.iter().find(|(_, t)| {
+            t.try_as_struct()
+                .is_some_and(|s| s.fields().contains(&field))
+        })?;
+        let named = self
+            .tco
+            .types
+            .iter()
+            .find(|(_, t)| t.try_as_named().is_some_and(|n| n.underlying() == skey));
+        Some(named.map_or(skey, |(k, _)| k))
+    }
+
+    fn is_other_pkg_scope(&self, scope: ScopeKey, pkg: PackageKey) -> bool {
+        self.pkgs
+            .values()
+            .any(|p| *p != pkg && *self.tco.pkgs[*p].scope() == scope)
+    }
+
+    /// The innermost scope under `skey` containing `pos`
+    fn innermost(&self, skey: ScopeKey, pos: Pos) -> ScopeKey {
+        let mut s = skey;
+        'outer: loop {
+            for c in self.tco.scopes[s].children().iter() {
+                if self.tco.scopes[*c].contains(pos) {
+                    s = *c;
+                    continue 'outer;
+                }
+            }
+            return s;
+        }
+    }
+
+    /// The object named `name` in scope `skey` if it's declared before
+    /// `pos`
+    fn visible(&self, skey: ScopeKey, name: &str, pos: Pos) -> Option<ObjKey> {
+        self.tco.scopes[skey]
+            .lookup(name)
+            .filter(|o| *self.tco.lobjs[**o].scope_pos() <= pos)
+            .copied()
+    }
+
+    /// The position of the path of the import declaring the package name
+    fn import_path_pos(&self, okey: ObjKey, pkg: PackageKey) -> Option<Pos> {
+        let info = &self.infos[&pkg];
+        for f in info.ast_files.iter() {
+            for skey in f.imports.iter() {
+                let spec = &self.asto.specs[*skey];
+                if info.implicits.get(&spec.id()) == Some(&okey) {
+                    if let ast::Spec::Import(is) = spec {
+                        return Some(is.path.pos);
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    fn ident_pos(&self, ikey: IdentKey) -> Option<FilePos> {
+        self.fs.position(self.asto.idents[ikey].pos)
+    }
+
+    fn pos_str(&self, pos: Pos) -> String {
+        self.fs
+            .position(pos)
+            .map_or("<unknown position>".to_owned(), |p| p.to_string())
+    }
+}
+
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) if c == '_' || c.is_alphabetic() => {}
+        _ => return false,
+    }
+    s != "_"
+        && chars.all(|c| c == '_' || c.is_alphanumeric())
+        && fe::Token::ident_token(s.to_owned()).is_literal()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use goscript_codegen::FsReader;
+
+    #[test]
+    fn test_rename() {
+        let source = r#"
+package main
+
+import "fmt"
+
+type point struct{ x, y int }
+
+func (p point) sum() int { return p.x + p.y }
+
+func main() {
+	count := 1
+	for i := 0; i < 3; i++ {
+		total := count + i
+		fmt.Println(total)
+	}
+	p := point{x: count}
+	fmt.Println(p.sum())
+}
+"#;
+        let reader = FsReader::new(Some("./"), Some("../std/"), Some(source));
+        let path = FsReader::temp_file_path();
+        let edits = |line, column, new_name| {
+            rename(&reader, path, path, line, column, new_name).map(|edits| {
+                edits
+                    .into_iter()
+                    .map(|e| (e.line, e.column, e.len, e.new_text))
+                    .collect::<Vec<_>>()
+            })
+        };
+        let edit = |line, column, len, text: &str| (line, column, len, text.to_owned());
+
+        assert_eq!(
+            edits(13, 14, "n").unwrap(),
+            vec![
+                edit(11, 2, 5, "n"),
+                edit(13, 12, 5, "n"),
+                edit(16, 16, 5, "n")
+            ]
+        );
+        assert_eq!(
+            edits(6, 20, "z").unwrap(),
+            vec![
+                edit(6, 20, 1, "z"),
+                edit(8, 37, 1, "z"),
+                edit(16, 13, 1, "z")
+            ]
+        );
+        // the import gets a name
+        assert_eq!(
+            edits(14, 3, "f").unwrap(),
+            vec![
+                edit(4, 8, 0, "f "),
+                edit(14, 3, 3, "f"),
+                edit(17, 2, 3, "f")
+            ]
+        );
+        // `count := count + i` still refers to the outer count
+        assert_eq!(edits(13, 3, "count").unwrap().len(), 2);
+
+        let err = |line, column, new_name| {
+            edits(line, column, new_name).unwrap_err().borrow()[0]
+                .msg
+                .clone()
+        };
+        assert!(err(11, 2, "p").contains("conflicts with the declaration"));
+        assert!(err(11, 2, "i").contains("would make this reference refer to"));
+        assert!(err(6, 20, "y").contains("conflicts with the field or method"));
+        assert!(err(8, 16, "x").contains("conflicts with the field or method"));
+        assert!(err(14, 7, "println").contains("not part of the workspace"));
+        assert!(err(13, 3, "fmt").contains("would make this reference to fmt refer to it"));
+        assert!(err(11, 2, "for").contains("not a valid identifier"));
+        assert!(err(10, 6, "start").contains("cannot rename main"));
+    }
+
+    #[test]
+    fn test_rename_across_packages() {
+        let reader = FsReader::new(Some("./"), Some("../std/"), None);
+        let path = "./tests/rename/main.gos";
+        let edits = rename(&reader, path, "shapes/shapes.gos", 7, 6, "Size").unwrap();
+        let files: Vec<(&str, usize)> = edits
+            .iter()
+            .map(|e| (e.file.rsplit('/').next().unwrap(), e.line))
+            .collect();
+        assert_eq!(files, vec![("main.gos", 7), ("shapes.gos", 7)]);
+        // the field is set in main
+        let el = rename(&reader, path, "main.gos", 6, 19, "w").unwrap_err();
+        assert!(el.borrow()[0].msg.contains("would make it unexported"));
+        assert_eq!(
+            rename(&reader, path, "main.gos", 6, 19, "Width")
+                .unwrap()
+                .len(),
+            3
+        );
+    }
+}
@@ -0,0 +1,348 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! A REPL session, which evaluates lines of Go one after the other in the
+//! same VM, so that what a line declares is there for the following ones.
+//!
+//! Each statement of a line is checked as a package nested in the one of
+//! the statement before it, its code is added to the code that already ran
+//! and its package is initialized, which runs it:
+//!
+//! - declarations stay as they are, and so do imports, which are not
+//!   errors if they are not used
+//! - `a, b := f()` declares the package variables `a` and `b`
+//! - an expression is assigned to package variables, which are printed
+//! - the other statements run in an `init` function
+//!
+//! A line is either declarations or statements, and methods are declared
+//! on the line declaring their type. Goroutines started by a statement stop
+//! when it is done.
+
+extern crate goscript_codegen as cg;
+extern crate goscript_parser as fe;
+extern crate goscript_types as types;
+extern crate goscript_vm as vm;
+
+use crate::engine::Engine;
+use fe::ast::{Decl, Expr, Node, Stmt};
+use fe::errors::ErrorList;
+use fe::objects::Objects as AstObjects;
+use fe::{FileSet, Parser, Token};
+use std::collections::HashMap;
+use types::{OperandMode, PackageKey as TCPackageKey, SourceRead, TCObjects, TypeInfo};
+use vm::gc::GcoVec;
+use vm::value::PackageKey;
+use vm::vm::{ByteCode, GosVM, RunResult};
+
+// the path of the packages of the statements, it is the same for all of them
+// so that they can refer to the unexported names of each other
+const PKG_PATH: &str = "main";
+
+// the prefix of the package variables expression values are assigned to
+const VALUE_PREFIX: &str = "_gosRepl";
+
+/// What a line evaluated to
+#[derive(Debug, PartialEq)]
+pub struct Evaluation {
+    /// the values of the expressions of the line, one string per expression
+    /// with its values formatted like fmt.Println does
+    pub values: Vec<String>,
+    /// how the last statement that ran ended, the ones after it are not run
+    /// if it is not `RunResult::Done`
+    pub result: RunResult,
+}
+
+// a statement turned into the source of a package
+enum Unit {
+    Source(String),
+    // an expression, with its source
+    Expr(String),
+    // a call, which may have no results
+    Call(String),
+}
+
+pub struct Repl<'a, S: SourceRead> {
+    engine: &'a Engine,
+    reader: S,
+    fs: FileSet,
+    ast_objs: AstObjects,
+    tc_objs: TCObjects,
+    // the imported packages by directory
+    pkgs: HashMap<String, TCPackageKey>,
+    results: HashMap<TCPackageKey, TypeInfo>,
+    gen: cg::IncrementalGen,
+    // taken by the VM while a statement runs
+    code: Option<(ByteCode, GcoVec)>,
+    // the package of the last statement that ran, the next ones are nested
+    // in it
+    last: Option<TCPackageKey>,
+    count: usize,
+}
+
+impl<'a, S: SourceRead> Repl<'a, S> {
+    /// Returns a session running on `engine`, imports are resolved by
+    /// `reader`
+    pub fn new(engine: &'a Engine, reader: S) -> Repl<'a, S> {
+        let mut ast_objs = AstObjects::new();
+        let gen = cg::IncrementalGen::new(&mut ast_objs);
+        Repl {
+            engine: engine,
+            reader: reader,
+            fs: FileSet::new(),
+            ast_objs: ast_objs,
+            tc_objs: TCObjects::new(),
+            pkgs: HashMap::new(),
+            results: HashMap::new(),
+            gen: gen,
+            code: Some((cg::IncrementalGen::empty_code(), GcoVec::new())),
+            last: None,
+            count: 0,
+        }
+    }
+
+    /// Evaluates the statements of `line` in order, and returns the values
+    /// of its expressions. The errors of a statement that does not compile
+    /// are returned, the statements before it have run.
+    pub fn eval_line(&mut self, line: &str) -> Result<Evaluation, ErrorList> {
+        let mut values = vec![];
+        for unit in self.split(line)? {
+            let el = ErrorList::new();
+            let (pkg, count) = match unit {
+                Unit::Source(src) => (self.check(&src, &el), 0),
+                Unit::Expr(expr) => (self.check(&value_source(&expr, 1), &el), 1),
+                Unit::Call(call) => {
+                    let probe = ErrorList::new();
+                    let src = format!("package main; func init() {{ {}\n}}", call);
+                    match self.check(&src, &probe).map(|p| (p, self.call_results(p))) {
+                        Some((pkg, 0)) => (Some(pkg), 0),
+                        other => {
+                            let count = other.map_or(1, |(_, n)| n);
+                            if let Some((pkg, _)) = other {
+                                self.results.remove(&pkg);
+                            }
+                            (self.check(&value_source(&call, count), &el), count)
+                        }
+                    }
+                }
+            };
+            let pkg = pkg.ok_or(el)?;
+            let (vm_pkg, result) = self.run(pkg);
+            if result != RunResult::Done {
+                return Ok(Evaluation {
+                    values: values,
+                    result: result,
+                });
+            }
+            self.last = Some(pkg);
+            if count > 0 {
+                values.push(self.format_values(vm_pkg, count));
+            }
+        }
+        Ok(Evaluation {
+            values: values,
+            result: RunResult::Done,
+        })
+    }
+
+    // turns the statements of a line into the sources of their packages
+    fn split(&mut self, line: &str) -> Result<Vec<Unit>, ErrorList> {
+        let decls = format!("package main; {}", line);
+        let decl_errors = ErrorList::new();
+        if self.parse(&decls, &decl_errors).is_some() {
+            return Ok(vec![Unit::Source(decls)]);
+        }
+        let body = format!("package main; func init() {{ {}\n}}", line);
+        let errors = ErrorList::new();
+        let (file, base) = match self.parse(&body, &errors) {
+            Some(parsed) => parsed,
+            None if is_decl(line) => return Err(decl_errors),
+            None => return Err(errors),
+        };
+        let stmts = match file.decls.first() {
+            Some(Decl::Func(f)) => self.ast_objs.fdecls[*f].body.clone().unwrap(),
+            _ => unreachable!(),
+        };
+        let chars: Vec<char> = body.chars().collect();
+        let text =
+            |from: usize, to: usize| -> String { chars[from - base..to - base].iter().collect() };
+        let mut units = vec![];
+        for stmt in stmts.list.iter() {
+            let (from, to) = (stmt.pos(&self.ast_objs), stmt.end(&self.ast_objs));
+            units.push(match stmt {
+                Stmt::Empty(_) => continue,
+                Stmt::Assign(key) if self.ast_objs.a_stmts[*key].token == Token::DEFINE => {
+                    let tok = self.ast_objs.a_stmts[*key].token_pos;
+                    Unit::Source(format!(
+                        "package main; var {} = {}",
+                        text(from, tok),
+                        text(tok + 2, to)
+                    ))
+                }
+                Stmt::Decl(_) => Unit::Source(format!("package main; {}", text(from, to))),
+                Stmt::Expr(e) if matches!(e.as_ref(), Expr::Call(_)) => Unit::Call(text(from, to)),
+                Stmt::Expr(_) => Unit::Expr(text(from, to)),
+                _ => Unit::Source(format!(
+                    "package main; func init() {{ {}\n}}",
+                    text(from, to)
+                )),
+            });
+        }
+        Ok(units)
+    }
+
+    fn parse(&mut self, source: &str, el: &ErrorList) -> Option<(fe::ast::File, usize)> {
+        self.count += 1;
+        let base = self.fs.base();
+        let name = format!("repl_{}", self.count);
+        let pfile = self.fs.add_file(name, Some(base), source.chars().count());
+        let file = Parser::new(&mut self.ast_objs, pfile, el, source, false).parse_file();
+        file.filter(|_| !el.has_errors()).map(|f| (f, base))
+    }
+
+    // checks the source of a statement as a package nested in the one of
+    // the last statement, what the check added is removed if it fails
+    fn check(&mut self, source: &str, el: &ErrorList) -> Option<TCPackageKey> {
+        let (file, _) = self.parse(source, el)?;
+        let old_results: Vec<TCPackageKey> = self.results.keys().copied().collect();
+        let old_pkgs: Vec<String> = self.pkgs.keys().cloned().collect();
+        let cfg = types::TraceConfig {
+            trace_parser: false,
+            trace_checker: false,
+        };
+        let result = types::Importer::new(
+            &cfg,
+            &self.reader,
+            &mut self.fs,
+            &mut self.pkgs,
+            &mut self.results,
+            &mut self.ast_objs,
+            &mut self.tc_objs,
+            el,
+            0,
+        )
+        .check_nested(PKG_PATH, vec![file], self.last);
+        match result {
+            Ok(pkg) if !el.has_errors() => Some(pkg),
+            _ => {
+                self.results.retain(|k, _| old_results.contains(k));
+                self.pkgs.retain(|k, _| old_pkgs.contains(k));
+                None
+            }
+        }
+    }
+
+    // the number of results of the call of the `init` function of `pkg`
+    fn call_results(&self, pkg: TCPackageKey) -> usize {
+        let ti = &self.results[&pkg];
+        let call = match ti.ast_files[0].decls.last() {
+            Some(Decl::Func(f)) => match &self.ast_objs.fdecls[*f].body.as_ref().unwrap().list[0] {
+                Stmt::Expr(e) => e.id(),
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        };
+        match ti.types.get(&call) {
+            None => 0,
+            Some(tv) if tv.mode == OperandMode::NoValue => 0,
+            Some(tv) => self.tc_objs.types[tv.typ]
+                .try_as_tuple()
+                .map_or(1, |t| t.vars().len()),
+        }
+    }
+
+    // adds the code of `pkg` and runs its initialization
+    fn run(&mut self, pkg: TCPackageKey) -> (PackageKey, RunResult) {
+        let (mut code, gcv) = self.code.take().unwrap();
+        let vm_pkg = self
+            .gen
+            .gen(&mut code, &self.ast_objs, &self.tc_objs, &self.results, pkg);
+        let mut vm = GosVM::with_gcv(code, gcv, self.engine.ffi(), Some(&self.fs));
+        let result = self.engine.run_vm(&mut vm);
+        self.code = Some(vm.into_parts());
+        (vm_pkg, result)
+    }
+
+    fn format_values(&self, pkg: PackageKey, count: usize) -> String {
+        let (code, _) = self.code.as_ref().unwrap();
+        let pkg = &code.objects.packages[pkg];
+        let vals: Vec<_> = (0..count)
+            .map(|i| {
+                let index = pkg.get_member_index(&format!("{}{}", VALUE_PREFIX, i));
+                pkg.member(*index.unwrap()).clone()
+            })
+            .collect();
+        vm::format::sprint_values(&code.objects, &vals)
+    }
+}
+
+// the source of a package assigning the `count` values of `expr` to
+// package variables
+fn value_source(expr: &str, count: usize) -> String {
+    let vars: Vec<String> = (0..count)
+        .map(|i| format!("{}{}", VALUE_PREFIX, i))
+        .collect();
+    format!(
+        "package main; var {} interface{{}} = {}",
+        vars.join(", "),
+        expr
+    )
+}
+
+// tells if a line that doesn't parse is meant to be a declaration, to
+// report the errors of parsing it as one
+fn is_decl(line: &str) -> bool {
+    let mut words = line.split_whitespace();
+    match words.next() {
+        Some("import") | Some("type") | Some("const") | Some("var") => true,
+        Some("func") => words.next().map_or(false, |w| !w.starts_with('(')),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use goscript_codegen::FsReader;
+
+    #[test]
+    fn test_eval_line() {
+        let engine = Engine::new();
+        let reader = FsReader::new(Some("./"), Some("../std/"), None);
+        let mut repl = engine.repl(reader);
+        let mut eval = |line: &str| repl.eval_line(line).unwrap().values;
+
+        assert!(eval("import \"strings\"").is_empty());
+        assert!(eval("x := 40").is_empty());
+        assert_eq!(eval("x + 2"), vec!["42"]);
+        assert!(eval("x++; x++").is_empty());
+        assert_eq!(eval("x"), vec!["42"]);
+        assert!(eval("func div(a, b int) (int, int) { return a / b, a % b }").is_empty());
+        assert_eq!(eval("div(x, 5)"), vec!["8 2"]);
+        assert_eq!(
+            eval("strings.Repeat(\"ab\", 2); len(\"abc\")"),
+            vec!["abab", "3"]
+        );
+        assert!(eval("type point struct { x, y int }").is_empty());
+        assert_eq!(eval("p := point{1, 2}; p.y = x; p"), vec!["{1 42}"]);
+        assert!(eval("m := map[string]int{}").is_empty());
+        assert!(eval("func add(k string) { m[k]++ }").is_empty());
+        assert!(eval("add(\"a\"); add(\"a\")").is_empty());
+        assert_eq!(eval("m[\"a\"]"), vec!["2"]);
+        // a name declared again shadows the one before
+        assert!(eval("x := \"x\"").is_empty());
+        assert_eq!(eval("x + x"), vec!["xx"]);
+
+        let el = repl.eval_line("y + 1").unwrap_err();
+        assert!(el.to_string().contains("undeclared name: y"), "{}", el);
+        let el = repl.eval_line("func f() {").unwrap_err();
+        assert!(el.has_errors());
+        // a panic ends the line, the session goes on
+        let result = repl.eval_line("var s []int; s[1] = 0; x = \"y\"").unwrap();
+        match result.result {
+            RunResult::Panic(info) => assert!(info.message.contains("index out of range")),
+            r => panic!("unexpected {:?}", r),
+        }
+        assert_eq!(repl.eval_line("x").unwrap().values, vec!["x"]);
+    }
+}
@@ -4,7 +4,7 @@
 
 /// run_fs runs an engine with a file system.
 use crate::engine::Engine;
-use crate::ErrorList;
+use crate::{Capabilities, ErrorList, Limits, RunResult};
 use goscript_codegen::FsReader;
 
 #[derive(Default)]
@@ -23,19 +23,51 @@ pub struct Config<'a> {
     pub std_out: Option<Box<dyn std::io::Write>>,
     /// custom std err
     pub std_err: Option<Box<dyn std::io::Write>>,
+    /// os.Args[0], the path of the program if not set
+    pub program_name: Option<&'a str>,
+    /// environment variables, the ones of the process if not set
+    pub env: Option<&'a [(&'a str, &'a str)]>,
+    /// the limits of the run, for untrusted scripts
+    pub limits: Limits,
+    /// what the scripts are allowed to do, everything if not set
+    pub capabilities: Capabilities,
 }
 
-pub fn run(config: Config, path: &str) -> Result<(), ErrorList> {
-    run_fs_impl(config, None, path)
+pub fn run(config: Config, path: &str) -> Result<RunResult, ErrorList> {
+    run_fs_impl(config, None, path, &[])
 }
 
-pub fn run_string(config: Config, source: &str) -> Result<(), ErrorList> {
-    run_fs_impl(config, Some(source), FsReader::temp_file_path())
+/// Runs the program at `path` with `args` in os.Args, like `go run path args...`
+pub fn run_with_args(config: Config, path: &str, args: &[&str]) -> Result<RunResult, ErrorList> {
+    run_fs_impl(config, None, path, args)
 }
 
-fn run_fs_impl(config: Config, temp_source: Option<&str>, path: &str) -> Result<(), ErrorList> {
+pub fn run_string(config: Config, source: &str) -> Result<RunResult, ErrorList> {
+    run_fs_impl(config, Some(source), FsReader::temp_file_path(), &[])
+}
+
+fn run_fs_impl(
+    config: Config,
+    temp_source: Option<&str>,
+    path: &str,
+    args: &[&str],
+) -> Result<RunResult, ErrorList> {
     let engine = Engine::new();
     engine.set_std_io(config.std_in, config.std_out, config.std_err);
+    engine.set_limits(config.limits);
+    engine.set_capabilities(config.capabilities);
+    if let Some(name) = config.program_name {
+        engine.set_program_name(name);
+    }
+    if let Some(env) = config.env {
+        engine.set_env(env);
+    }
     let reader = FsReader::new(config.working_dir, config.base_dir, temp_source);
-    engine.run(config.trace_parser, config.trace_checker, &reader, path)
+    engine.run_with_args(
+        config.trace_parser,
+        config.trace_checker,
+        &reader,
+        path,
+        args,
+    )
 }
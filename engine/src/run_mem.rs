@@ -0,0 +1,178 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+/// run_mem runs an engine with the sources in memory, without touching the
+/// file system, so it also works where there is none, like in a browser
+/// with the engine built for wasm32-unknown-unknown. The scripts can't open
+/// files either, the Fs std module is left out.
+use crate::engine::Engine;
+use crate::{Capabilities, ErrorList, Limits, RunResult, StdModule, StdSelection};
+use goscript_codegen::MapReader;
+
+/// The path `run_string` puts the source at
+pub const MAIN_FILE: &str = "main.gos";
+
+#[derive(Default)]
+pub struct Config<'a> {
+    /// print debug info in parser
+    pub trace_parser: bool,
+    /// print debug info in checker
+    pub trace_checker: bool,
+    /// custom std in
+    pub std_in: Option<Box<dyn std::io::Read>>,
+    /// custom std out
+    pub std_out: Option<Box<dyn std::io::Write>>,
+    /// custom std err
+    pub std_err: Option<Box<dyn std::io::Write>>,
+    /// os.Args[0], the path of the program if not set
+    pub program_name: Option<&'a str>,
+    /// environment variables, there are none if not set
+    pub env: Option<&'a [(&'a str, &'a str)]>,
+    /// the limits of the run, for untrusted scripts
+    pub limits: Limits,
+    /// what the scripts are allowed to do, everything if not set
+    pub capabilities: Capabilities,
+}
+
+/// Runs the program at `path` of `reader`, a file or a directory
+pub fn run(config: Config, reader: &MapReader, path: &str) -> Result<RunResult, ErrorList> {
+    let engine = Engine::with_std(StdSelection::all().without(StdModule::Fs)).unwrap();
+    engine.set_std_io(config.std_in, config.std_out, config.std_err);
+    engine.set_limits(config.limits);
+    engine.set_capabilities(config.capabilities);
+    if let Some(name) = config.program_name {
+        engine.set_program_name(name);
+    }
+    engine.set_env(config.env.unwrap_or(&[]));
+    engine.run(config.trace_parser, config.trace_checker, reader, path)
+}
+
+/// Runs `source` as the file `MAIN_FILE` of `reader`, which usually has the
+/// std packages under its base directory
+pub fn run_string(
+    config: Config,
+    reader: &MapReader,
+    source: &str,
+) -> Result<RunResult, ErrorList> {
+    let mut reader = reader.clone();
+    reader.add_file(MAIN_FILE, source.to_owned());
+    run(config, &reader, &format!("./{}", MAIN_FILE))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::path::Path;
+
+    /// Adds the files under `dir` of the file system, at `prefix` of the map
+    fn add_dir(reader: &mut MapReader, dir: &Path, prefix: &str) {
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            let name = format!("{}/{}", prefix, path.file_name().unwrap().to_string_lossy());
+            if path.is_dir() {
+                add_dir(reader, &path, &name);
+            } else {
+                reader.add_file(&name, std::fs::read_to_string(&path).unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn test_run_string() {
+        let mut reader = MapReader::new(Some("std/"));
+        add_dir(&mut reader, Path::new("../std"), "std");
+        reader.add_file(
+            "./util/../util/util.gos",
+            "package util\n\nfunc Double(n int) int { return n * 2 }\n".to_owned(),
+        );
+        assert!(reader.paths().any(|p| p == Path::new("util/util.gos")));
+
+        let source = r#"
+    package main
+
+    import (
+        "os"
+        "strings"
+        "./util"
+    )
+
+    func main() {
+        assert(util.Double(21) == 42)
+        assert(strings.ToUpper("wasm") == "WASM")
+        assert(len(os.Environ()) == 0)
+        _, err := os.Open("../std/fmt/fmt.gos")
+        assert(err != nil)
+    }
+    "#;
+        let result = run_string(Config::default(), &reader, source);
+        assert_eq!(result.unwrap(), RunResult::Done);
+        // the source is not added to the reader
+        assert!(reader.paths().all(|p| p != Path::new(MAIN_FILE)));
+
+        let result = run_string(
+            Config::default(),
+            &reader,
+            "package main\nimport \"./nosuch\"",
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_multi_package() {
+        let files = [
+            (
+                "app/main.gos",
+                r#"
+    package main
+
+    import (
+        "../shapes"
+        "../shapes/area"
+    )
+
+    func main() {
+        r := shapes.Rect{W: 2, H: 3}
+        var total area.Measure = area.Of(r) + area.Of(shapes.Square(2))
+        assert(total == 10)
+    }
+    "#,
+            ),
+            (
+                "shapes/shapes.gos",
+                r#"
+    package shapes
+
+    type Rect struct {
+        W, H int
+    }
+
+    func Square(n int) Rect {
+        return Rect{n, n}
+    }
+    "#,
+            ),
+            (
+                // the same package as "../shapes" of app/main.gos
+                "shapes/area/area.gos",
+                r#"
+    package area
+
+    import "../../shapes"
+
+    type Measure int
+
+    func Of(r shapes.Rect) Measure {
+        return Measure(r.W * r.H)
+    }
+    "#,
+            ),
+        ];
+        let reader: MapReader = files
+            .iter()
+            .map(|(path, source)| (path, source.to_string()))
+            .collect();
+        let result = run(Config::default(), &reader, "./app");
+        assert_eq!(result.unwrap(), RunResult::Done);
+    }
+}
@@ -5,6 +5,7 @@
 /// run_zip runs an engine with a zip file.
 use crate::Engine;
 use crate::ErrorList;
+use crate::RunResult;
 use crate::SourceRead;
 use std::cell::RefCell;
 use std::io;
@@ -32,11 +33,11 @@ pub struct Config<'a> {
     pub std_err: Option<Box<dyn std::io::Write>>,
 }
 
-pub fn run(archive: &[u8], config: Config, path: &str) -> Result<(), ErrorList> {
+pub fn run(archive: &[u8], config: Config, path: &str) -> Result<RunResult, ErrorList> {
     run_zip_impl(archive, config, None, path)
 }
 
-pub fn run_string(archive: &[u8], config: Config, source: &str) -> Result<(), ErrorList> {
+pub fn run_string(archive: &[u8], config: Config, source: &str) -> Result<RunResult, ErrorList> {
     run_zip_impl(archive, config, Some(source), ZipReader::temp_file_path())
 }
 
@@ -45,7 +46,7 @@ fn run_zip_impl(
     config: Config,
     temp_source: Option<&str>,
     path: &str,
-) -> Result<(), ErrorList> {
+) -> Result<RunResult, ErrorList> {
     let engine = Engine::new();
     engine.set_std_io(config.std_in, config.std_out, config.std_err);
     match ZipReader::new(archive, config.working_dir, config.base_dir, temp_source) {
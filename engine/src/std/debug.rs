@@ -0,0 +1,25 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+extern crate self as goscript_engine;
+use crate::ffi::*;
+use goscript_vm::value::GosValue;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+
+#[derive(Ffi)]
+pub struct DebugFfi {}
+
+#[ffi_impl(rename = "runtime.debug")]
+impl DebugFfi {
+    fn ffi_stack(&self, ctx: &FfiCallCtx, _args: Vec<GosValue>) -> GosValue {
+        let mut trace = String::new();
+        for frame in ctx.stack_trace() {
+            trace.push_str(&frame.to_string());
+            trace.push('\n');
+        }
+        GosValue::with_str(&trace)
+    }
+}
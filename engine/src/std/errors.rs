@@ -0,0 +1,35 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+extern crate self as goscript_engine;
+use crate::ffi::*;
+use goscript_vm::value::{GosValue, InterfaceObj};
+use goscript_vm::vm::type_assert;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+
+#[derive(Ffi)]
+pub struct ErrorsFfi {}
+
+#[ffi_impl]
+impl ErrorsFfi {
+    /// Sets the value target points to to err and returns true, if err can
+    /// be assigned to it.
+    fn ffi_assign(&self, ctx: &mut FfiCallCtx, args: Vec<GosValue>) -> RuntimeResult<GosValue> {
+        let (ptr, meta) = match args[1].as_interface().map(|x| x as &InterfaceObj) {
+            Some(InterfaceObj::Gos(v, Some((meta, _)))) if meta.ptr_depth > 0 && !v.is_nil() => {
+                (v.as_some_pointer()?.clone(), meta.unptr_to())
+            }
+            _ => return Err("errors: target must be a non-nil pointer".to_owned()),
+        };
+        match type_assert(&args[0], &meta, ctx.vm_objs, ctx.gcv)? {
+            Some(v) => {
+                ptr.set_pointee(&v, ctx.stack, &ctx.vm_objs.packages, ctx.gcv)?;
+                Ok(GosValue::new_bool(true))
+            }
+            None => Ok(GosValue::new_bool(false)),
+        }
+    }
+}
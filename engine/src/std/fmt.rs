@@ -0,0 +1,85 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+extern crate self as goscript_engine;
+use crate::ffi::*;
+use goscript_vm::format::{MethodText, Operand, ValueFormatter};
+use goscript_vm::instruction::ValueType;
+use goscript_vm::objects::StrUtil;
+use goscript_vm::value::{GosElem, GosValue};
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+
+#[derive(Ffi)]
+pub struct FmtFfi {}
+
+#[ffi_impl]
+impl FmtFfi {
+    fn ffi_sprint(&self, ctx: &FfiCallCtx, args: Vec<GosValue>) -> RuntimeResult<GosValue> {
+        let operands = FmtFfi::operands(&args[0], &args[1])?;
+        let s = ValueFormatter::new(ctx.vm_objs, ctx.stack).sprint(&operands);
+        Ok(GosValue::with_str(&s))
+    }
+
+    fn ffi_sprintln(&self, ctx: &FfiCallCtx, args: Vec<GosValue>) -> RuntimeResult<GosValue> {
+        let operands = FmtFfi::operands(&args[0], &args[1])?;
+        let s = ValueFormatter::new(ctx.vm_objs, ctx.stack).sprintln(&operands);
+        Ok(GosValue::with_str(&s))
+    }
+
+    fn ffi_sprintf(&self, ctx: &FfiCallCtx, args: Vec<GosValue>) -> RuntimeResult<Vec<GosValue>> {
+        let format = StrUtil::as_str(args[0].as_string()).to_string();
+        let operands = FmtFfi::operands(&args[1], &args[2])?;
+        let (s, wrapped) = ValueFormatter::new(ctx.vm_objs, ctx.stack).sprintf(&format, &operands);
+        let wrapped = wrapped.map_or(-1, |x| x as isize);
+        Ok(vec![GosValue::with_str(&s), GosValue::new_int(wrapped)])
+    }
+
+    fn ffi_elems(&self, ctx: &FfiCallCtx, args: Vec<GosValue>) -> GosValue {
+        match ValueFormatter::new(ctx.vm_objs, ctx.stack).elems(&args[0], *args[1].as_bool()) {
+            Some(elems) => GosValue::slice_with_data(elems, ValueType::Interface, ctx.gcv),
+            None => GosValue::new_slice_nil(ValueType::Interface),
+        }
+    }
+
+    /// Pairs the operands with the texts returned by their Error() or String() methods
+    fn operands(args: &GosValue, texts: &GosValue) -> RuntimeResult<Vec<Operand>> {
+        let args = FmtFfi::interfaces(args)?;
+        let texts = FmtFfi::interfaces(texts)?;
+        args.into_iter()
+            .zip(texts.iter())
+            .map(|(arg, text)| Ok(Operand::new(arg, FmtFfi::method_text(text)?)))
+            .collect()
+    }
+
+    /// A text is a string, or a slice of the texts of the nested values
+    fn method_text(text: &GosValue) -> RuntimeResult<MethodText> {
+        if text.is_nil() {
+            return Ok(MethodText::None);
+        }
+        match text.iface_underlying()? {
+            Some(x) if x.typ() == ValueType::String => {
+                Ok(MethodText::Text(StrUtil::as_str(x.as_string()).to_string()))
+            }
+            Some(x) => Ok(MethodText::Nested(
+                FmtFfi::interfaces(&x)?
+                    .iter()
+                    .map(FmtFfi::method_text)
+                    .collect::<RuntimeResult<Vec<MethodText>>>()?,
+            )),
+            None => Ok(MethodText::None),
+        }
+    }
+
+    fn interfaces(slice: &GosValue) -> RuntimeResult<Vec<GosValue>> {
+        match slice.is_nil() {
+            true => Ok(vec![]),
+            false => Ok(slice
+                .as_some_slice::<GosElem>()?
+                .0
+                .get_vec(ValueType::Interface)),
+        }
+    }
+}
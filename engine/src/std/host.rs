@@ -0,0 +1,292 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! The backend of the `host` std package, a pub/sub bus between the embedder
+//! and the scripts. The embedder gets the bus with `Engine::event_bus`,
+//! events it publishes show up on the channels returned by `host.Subscribe`,
+//! and events published by scripts with `host.Publish` are passed to the
+//! handler set with `EventBus::set_handler`.
+
+extern crate self as goscript_engine;
+use crate::engine::Statics;
+use crate::ffi::*;
+use goscript_vm::gc::GcoVec;
+use goscript_vm::metadata::Meta;
+use goscript_vm::objects::{InterfaceObj, StructObj};
+use goscript_vm::value::*;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+
+/// The payload of an event, it is an `interface{}` on the Go side holding
+/// an int, float64, bool, string or nil.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Nil,
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Str(String),
+}
+
+impl Value {
+    fn from_go(val: &GosValue) -> RuntimeResult<Value> {
+        if val.is_nil() {
+            return Ok(Value::Nil);
+        }
+        let v = match val.iface_underlying()? {
+            Some(v) => v,
+            None => return Err("host: unsupported event data type".to_owned()),
+        };
+        Ok(match v.typ() {
+            ValueType::Bool => Value::Bool(*v.as_bool()),
+            ValueType::Int => Value::Int(*v.as_int() as i64),
+            ValueType::Int8 => Value::Int(*v.as_int8() as i64),
+            ValueType::Int16 => Value::Int(*v.as_int16() as i64),
+            ValueType::Int32 => Value::Int(*v.as_int32() as i64),
+            ValueType::Int64 => Value::Int(*v.as_int64()),
+            ValueType::Uint => Value::Int(*v.as_uint() as i64),
+            ValueType::Uint8 => Value::Int(*v.as_uint8() as i64),
+            ValueType::Uint16 => Value::Int(*v.as_uint16() as i64),
+            ValueType::Uint32 => Value::Int(*v.as_uint32() as i64),
+            ValueType::Uint64 => Value::Int(*v.as_uint64() as i64),
+            ValueType::Float32 => Value::Float(v.as_float32().into_inner() as f64),
+            ValueType::Float64 => Value::Float(v.as_float64().into_inner()),
+            ValueType::String => Value::Str(StrUtil::as_str(v.as_string()).to_string()),
+            t => return Err(format!("host: unsupported event data type {:?}", t)),
+        })
+    }
+
+    fn to_go(&self, metas: &EventMetas) -> GosValue {
+        let (v, meta) = match self {
+            Value::Nil => return GosValue::new_nil(ValueType::Interface),
+            Value::Int(i) => (GosValue::new_int(*i as isize), metas.int),
+            Value::Float(f) => (GosValue::new_float64((*f).into()), metas.float),
+            Value::Bool(b) => (GosValue::new_bool(*b), metas.bool),
+            Value::Str(s) => (GosValue::with_str(s), metas.str),
+        };
+        GosValue::new_interface(InterfaceObj::with_value(v, Some((meta, vec![]))))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Event {
+    pub topic: String,
+    pub data: Value,
+}
+
+/// The metas needed to box event data into interfaces, they are copied from
+/// the VM the first time a script subscribes.
+#[derive(Clone, Copy)]
+struct EventMetas {
+    int: Meta,
+    float: Meta,
+    bool: Meta,
+    str: Meta,
+}
+
+#[derive(Default)]
+struct BusState {
+    // the subscribed Gos channels of each topic
+    subs: HashMap<String, Vec<GosValue>>,
+    metas: Option<EventMetas>,
+    handler: Option<Rc<dyn Fn(&EventBus, &Event)>>,
+    // event structs only hold strings and scalars, they never form cycles
+    gcv: Option<GcoVec>,
+}
+
+/// The host side of the `host` package. It's a cheap handle, clones share
+/// the same subscriptions and handler.
+#[derive(Clone, Default)]
+pub struct EventBus {
+    state: Rc<RefCell<BusState>>,
+}
+
+impl EventBus {
+    /// Publishes an event to the scripts subscribed to topic, and returns how
+    /// many subscriptions received it. Events to topics with no subscriptions
+    /// are dropped, so is the event for a subscription whose buffer is full.
+    pub fn publish(&self, topic: &str, data: Value) -> usize {
+        let mut state = self.state.borrow_mut();
+        let state = &mut *state;
+        let (metas, subs) = match (state.metas, state.subs.get_mut(topic)) {
+            (Some(m), Some(s)) => (m, s),
+            _ => return 0,
+        };
+        let gcv = state.gcv.get_or_insert_with(GcoVec::new);
+        let mut count = 0;
+        subs.retain(|chan| {
+            let event = StructObj::new(vec![GosValue::with_str(topic), data.to_go(&metas)]);
+            match chan
+                .as_channel()
+                .unwrap()
+                .chan
+                .try_send(GosValue::new_struct(event, gcv))
+            {
+                Ok(()) => {
+                    count += 1;
+                    true
+                }
+                Err(e) => !e.is_closed(),
+            }
+        });
+        count
+    }
+
+    /// Sets the handler for the events published by scripts. It's called
+    /// synchronously inside `host.Publish`, and may publish events itself.
+    pub fn set_handler(&self, handler: impl Fn(&EventBus, &Event) + 'static) {
+        self.state.borrow_mut().handler = Some(Rc::new(handler));
+    }
+
+    /// Returns the number of live subscriptions to topic.
+    pub fn subscriber_count(&self, topic: &str) -> usize {
+        self.state.borrow().subs.get(topic).map_or(0, |s| s.len())
+    }
+
+    fn subscribe(&self, topic: String, chan: GosValue, metas: EventMetas) {
+        let mut state = self.state.borrow_mut();
+        state.metas.get_or_insert(metas);
+        state.subs.entry(topic).or_default().push(chan);
+    }
+
+    fn unsubscribe(&self, chan: &GosValue) -> RuntimeResult<()> {
+        for subs in self.state.borrow_mut().subs.values_mut() {
+            subs.retain(|c| c != chan);
+        }
+        chan.as_some_channel()?.chan.close();
+        Ok(())
+    }
+
+    fn dispatch(&self, event: Event) {
+        // the handler may publish, so the state must not be borrowed when it runs
+        let handler = self.state.borrow().handler.clone();
+        if let Some(h) = handler {
+            h(self, &event);
+        }
+    }
+}
+
+#[derive(Ffi)]
+pub struct HostFfi {}
+
+#[ffi_impl(rename = "host")]
+impl HostFfi {
+    fn ffi_subscribe(&self, ctx: &FfiCallCtx, args: Vec<GosValue>) -> RuntimeResult<()> {
+        let topic = StrUtil::as_str(args[0].as_string()).to_string();
+        args[1].as_some_channel()?;
+        let s_meta = &ctx.vm_objs.s_meta;
+        let metas = EventMetas {
+            int: s_meta.mint,
+            float: s_meta.mfloat64,
+            bool: s_meta.mbool,
+            str: s_meta.mstr,
+        };
+        HostFfi::bus(ctx).subscribe(topic, args[1].clone(), metas);
+        Ok(())
+    }
+
+    fn ffi_unsubscribe(&self, ctx: &FfiCallCtx, args: Vec<GosValue>) -> RuntimeResult<()> {
+        HostFfi::bus(ctx).unsubscribe(&args[0])
+    }
+
+    fn ffi_publish(&self, ctx: &FfiCallCtx, args: Vec<GosValue>) -> GosValue {
+        let topic = StrUtil::as_str(args[0].as_string()).to_string();
+        let msg = match Value::from_go(&args[1]) {
+            Ok(data) => {
+                HostFfi::bus(ctx).dispatch(Event { topic, data });
+                "".to_owned()
+            }
+            Err(e) => e,
+        };
+        GosValue::with_str(&msg)
+    }
+
+    fn bus(ctx: &FfiCallCtx) -> EventBus {
+        Statics::downcast_borrow_data(ctx.statics).event_bus.clone()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::engine::Engine;
+    use goscript_codegen::FsReader;
+
+    #[test]
+    fn test_event_bus() {
+        let source = r#"
+    package main
+    import "host"
+
+    func main() {
+        ch := host.Subscribe("pong")
+        for i := 0; i < 3; i++ {
+            assert(host.Publish("ping", i) == nil)
+            ev := <-ch
+            assert(ev.Topic == "pong")
+            assert(ev.Data.(int) == i+1)
+        }
+
+        host.Publish("echo", "hi")
+        ev := <-ch
+        s, ok := ev.Data.(string)
+        assert(ok && s == "hi")
+        host.Publish("echo", 1.5)
+        ev = <-ch
+        assert(ev.Data.(float64) == 1.5)
+        host.Publish("echo", true)
+        isTrue := false
+        switch v := (<-ch).Data.(type) {
+        case bool:
+            isTrue = v
+        }
+        assert(isTrue)
+        host.Publish("echo", nil)
+        ev = <-ch
+        assert(ev.Data == nil)
+
+        assert(host.Publish("echo", []int{1}) != nil)
+
+        host.Unsubscribe(ch)
+        _, ok = <-ch
+        assert(!ok)
+        host.Publish("done", "bye")
+    }
+    "#;
+        let engine = Engine::new();
+        let bus = engine.event_bus();
+        let received = Rc::new(RefCell::new(vec![]));
+        let r = received.clone();
+        bus.set_handler(move |bus, ev| {
+            r.borrow_mut().push(ev.clone());
+            match (&ev.topic[..], &ev.data) {
+                ("ping", Value::Int(i)) => {
+                    assert_eq!(bus.publish("pong", Value::Int(i + 1)), 1);
+                }
+                ("echo", d) => {
+                    bus.publish("pong", d.clone());
+                }
+                _ => {}
+            }
+        });
+        // nobody has subscribed yet
+        assert_eq!(bus.publish("pong", Value::Nil), 0);
+        let reader = FsReader::new(Some("./"), Some("../std/"), Some(source));
+        let result = engine.run(false, false, &reader, FsReader::temp_file_path());
+        assert!(result.is_ok());
+        let received = received.borrow();
+        assert_eq!(received.len(), 8);
+        assert_eq!(
+            received.last(),
+            Some(&Event {
+                topic: "done".to_owned(),
+                data: Value::Str("bye".to_owned())
+            })
+        );
+        assert_eq!(bus.subscriber_count("pong"), 0);
+    }
+}
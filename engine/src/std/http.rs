@@ -0,0 +1,387 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! The client side of HTTP/1.1 for net/http, over the non-blocking sockets
+//! of the net package. Only plain `http://` urls are supported, and each
+//! request has a connection of its own, closed after the response.
+
+extern crate self as goscript_engine;
+use super::kv::bytes_to_slice;
+use super::net::{check_net, TcpConn};
+use crate::ffi::*;
+use goscript_vm::timer::IoWaits;
+use goscript_vm::value::*;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::rc::Rc;
+
+#[derive(Ffi)]
+pub struct HttpFfi {}
+
+#[ffi_impl(rename = "http")]
+impl HttpFfi {
+    fn ffi_round_trip(
+        &self,
+        ctx: &mut FfiCallCtx,
+        args: Vec<GosValue>,
+    ) -> Pin<Box<dyn Future<Output = RuntimeResult<Vec<GosValue>>> + '_>> {
+        let allowed = check_net(ctx);
+        let io = ctx.timers.io().clone();
+        Box::pin(async move {
+            let method = StrUtil::as_str(args[0].as_string()).to_string();
+            let url = StrUtil::as_str(args[1].as_string()).to_string();
+            let header = StrUtil::as_str(args[2].as_string()).to_string();
+            let body = match args[3].as_slice::<Elem8>() {
+                Some(s) => unsafe { s.0.as_raw_slice::<u8>() }.to_vec(),
+                None => vec![],
+            };
+            let r = match allowed {
+                Ok(()) => round_trip(&io, &method, &url, &header, &body).await,
+                Err(e) => Err(e),
+            };
+            let (mut vals, msg) = match r {
+                Ok(resp) => (
+                    vec![
+                        GosValue::new_int(resp.code as isize),
+                        GosValue::with_str(&resp.status),
+                        GosValue::with_str(&resp.proto),
+                        GosValue::with_str(&resp.header),
+                        bytes_to_slice(resp.body),
+                    ],
+                    "".to_owned(),
+                ),
+                Err(e) => (
+                    vec![
+                        GosValue::new_int(0),
+                        GosValue::with_str(""),
+                        GosValue::with_str(""),
+                        GosValue::with_str(""),
+                        GosValue::new_slice_nil(ValueType::Uint8),
+                    ],
+                    e.to_string(),
+                ),
+            };
+            vals.push(GosValue::with_str(&msg));
+            Ok(vals)
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Response {
+    code: u16,
+    /// the code and the reason, like "200 OK"
+    status: String,
+    proto: String,
+    /// the header lines as they were sent, separated by "\r\n"
+    header: String,
+    body: Vec<u8>,
+}
+
+/// Sends a request, `header` is a block of "Key: value\r\n" lines, and reads
+/// the whole response
+async fn round_trip(
+    io: &IoWaits,
+    method: &str,
+    url: &str,
+    header: &str,
+    body: &[u8],
+) -> io::Result<Response> {
+    let (authority, address, path) = parse_url(url)?;
+    let conn = TcpConn::dial(io, "tcp".to_owned(), address).await?;
+    let mut req = format!(
+        "{} {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: Go-http-client/1.1\r\n{}",
+        method, path, authority, header
+    );
+    if !body.is_empty() || method == "POST" || method == "PUT" {
+        req.push_str(&format!("Content-Length: {}\r\n", body.len()));
+    }
+    req.push_str("Connection: close\r\n\r\n");
+    let mut data = req.into_bytes();
+    data.extend_from_slice(body);
+    conn.write_all(&data).await?;
+
+    // the server closes the connection after the response
+    data.clear();
+    let mut buf = [0u8; 4096];
+    loop {
+        match conn.read(&mut buf).await? {
+            0 => break,
+            n => data.extend_from_slice(&buf[..n]),
+        }
+    }
+    let _ = conn.close();
+    parse_response(&data, method == "HEAD")
+}
+
+/// Splits an url into the authority for the Host header, the address to
+/// dial and the path with the query
+fn parse_url(url: &str) -> io::Result<(String, String, String)> {
+    let rest = match url.split_once("://") {
+        Some(("http", rest)) => rest,
+        Some((scheme, _)) => return Err(http_err(&unsupported_scheme(scheme))),
+        None => return Err(http_err(&unsupported_scheme(""))),
+    };
+    let rest = rest.split('#').next().unwrap_or("");
+    let (authority, path) = match rest.find(['/', '?']) {
+        Some(i) if rest[i..].starts_with('?') => (&rest[..i], format!("/{}", &rest[i..])),
+        Some(i) => (&rest[..i], rest[i..].to_owned()),
+        None => (rest, "/".to_owned()),
+    };
+    if authority.is_empty() {
+        return Err(http_err("http: no Host in request URL"));
+    }
+    // a colon after the closing bracket of an IPv6 host starts the port
+    let address = match authority.rfind(':') {
+        Some(i) if !authority[i..].contains(']') => authority.to_owned(),
+        _ => format!("{}:80", authority),
+    };
+    Ok((authority.to_owned(), address, path))
+}
+
+fn unsupported_scheme(scheme: &str) -> String {
+    format!("unsupported protocol scheme \"{}\"", scheme)
+}
+
+fn parse_response(data: &[u8], head: bool) -> io::Result<Response> {
+    let end = match data.windows(4).position(|w| w == b"\r\n\r\n") {
+        Some(i) => i,
+        None => return Err(io::Error::from(io::ErrorKind::UnexpectedEof)),
+    };
+    let text = String::from_utf8_lossy(&data[..end]);
+    let (status_line, header) = text.split_once("\r\n").unwrap_or((&text, ""));
+    let (proto, status) = status_line.split_once(' ').unwrap_or((status_line, ""));
+    let code = match status.get(..3).map(|s| s.parse::<u16>()) {
+        Some(Ok(code)) if proto.starts_with("HTTP/") => code,
+        _ => {
+            return Err(http_err(&format!(
+                "malformed HTTP response \"{}\"",
+                status_line
+            )))
+        }
+    };
+
+    let mut chunked = false;
+    let mut length = None;
+    for line in header.split("\r\n") {
+        if let Some((key, value)) = line.split_once(':') {
+            let value = value.trim();
+            if key.eq_ignore_ascii_case("Transfer-Encoding") {
+                chunked = value.eq_ignore_ascii_case("chunked");
+            } else if key.eq_ignore_ascii_case("Content-Length") {
+                length = value.parse::<usize>().ok();
+            }
+        }
+    }
+    let rest = &data[end + 4..];
+    let body = if head || code / 100 == 1 || code == 204 || code == 304 {
+        vec![]
+    } else if chunked {
+        decode_chunked(rest)?
+    } else {
+        match length {
+            Some(n) if n > rest.len() => return Err(io::Error::from(io::ErrorKind::UnexpectedEof)),
+            Some(n) => rest[..n].to_vec(),
+            None => rest.to_vec(),
+        }
+    };
+    Ok(Response {
+        code,
+        status: status.to_owned(),
+        proto: proto.to_owned(),
+        header: header.to_owned(),
+        body,
+    })
+}
+
+fn decode_chunked(mut data: &[u8]) -> io::Result<Vec<u8>> {
+    let bad = || http_err("malformed chunked encoding");
+    let mut body = vec![];
+    loop {
+        let line_end = data.windows(2).position(|w| w == b"\r\n").ok_or_else(bad)?;
+        let line = String::from_utf8_lossy(&data[..line_end]);
+        // chunk extensions after a ';' are ignored
+        let size = line.split(';').next().unwrap_or("").trim();
+        let size = usize::from_str_radix(size, 16).map_err(|_| bad())?;
+        data = &data[line_end + 2..];
+        if size == 0 {
+            // the trailer is dropped
+            return Ok(body);
+        }
+        if data.len() < size + 2 || &data[size..size + 2] != b"\r\n" {
+            return Err(bad());
+        }
+        body.extend_from_slice(&data[..size]);
+        data = &data[size + 2..];
+    }
+}
+
+fn http_err(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use goscript_vm::vm::RunResult;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[test]
+    fn test_parse() {
+        let (authority, address, path) = parse_url("http://example.com?q=1#top").unwrap();
+        assert_eq!(
+            (&*authority, &*address, &*path),
+            ("example.com", "example.com:80", "/?q=1")
+        );
+        let (_, address, path) = parse_url("http://[::1]:8080/a/b").unwrap();
+        assert_eq!((&*address, &*path), ("[::1]:8080", "/a/b"));
+        let (_, address, _) = parse_url("http://[::1]/").unwrap();
+        assert_eq!(address, "[::1]:80");
+        let err = parse_url("https://example.com").unwrap_err();
+        assert_eq!(err.to_string(), "unsupported protocol scheme \"https\"");
+        assert!(parse_url("example.com").is_err());
+
+        let data = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\nX-A: 1\r\n\r\n\
+            5;ext=1\r\nhello\r\n7\r\n, world\r\n0\r\nTrailer: x\r\n\r\n";
+        let resp = parse_response(data, false).unwrap();
+        assert_eq!(resp.code, 200);
+        assert_eq!(resp.status, "200 OK");
+        assert_eq!(resp.proto, "HTTP/1.1");
+        assert_eq!(resp.header, "Transfer-Encoding: chunked\r\nX-A: 1");
+        assert_eq!(resp.body, b"hello, world");
+
+        let data = b"HTTP/1.0 404 Not Found\r\nContent-Length: 3\r\n\r\nabcdef";
+        let resp = parse_response(data, false).unwrap();
+        assert_eq!((resp.code, &*resp.body), (404, &b"abc"[..]));
+        assert!(parse_response(data, true).unwrap().body.is_empty());
+        let short = b"HTTP/1.1 200 OK\r\nContent-Length: 9\r\n\r\nabc";
+        assert!(parse_response(short, false).is_err());
+        assert!(parse_response(b"SSH-2.0\r\n\r\n", false).is_err());
+    }
+
+    /// Answers `n` requests: /hello, a chunked /chunked, /redirect to
+    /// /hello, and /echo with the method, the content type and the body
+    fn serve(listener: TcpListener, n: usize) {
+        for stream in listener.incoming().take(n) {
+            let mut stream = stream.unwrap();
+            let mut r = BufReader::new(stream.try_clone().unwrap());
+            let mut line = String::new();
+            r.read_line(&mut line).unwrap();
+            let mut parts = line.split(' ');
+            let (method, path) = (parts.next().unwrap(), parts.next().unwrap());
+            let mut length = 0;
+            let mut content_type = String::new();
+            loop {
+                let mut h = String::new();
+                r.read_line(&mut h).unwrap();
+                if h == "\r\n" {
+                    break;
+                }
+                let (k, v) = h.split_once(':').unwrap();
+                match k {
+                    "Content-Length" => length = v.trim().parse().unwrap(),
+                    "Content-Type" => content_type = v.trim().to_owned(),
+                    _ => {}
+                }
+            }
+            let mut body = vec![0; length];
+            r.read_exact(&mut body).unwrap();
+            let resp = match path {
+                "/hello" => {
+                    "HTTP/1.1 200 OK\r\nContent-Length: 5\r\nX-Test: a\r\nx-test: b\r\n\r\nhello"
+                        .to_owned()
+                }
+                "/chunked" => "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+                    3\r\nabc\r\n3\r\ndef\r\n0\r\n\r\n"
+                    .to_owned(),
+                "/redirect" => "HTTP/1.1 302 Found\r\nLocation: /hello\r\n\r\n".to_owned(),
+                "/echo" => {
+                    let text = format!(
+                        "{} {} {}",
+                        method,
+                        content_type,
+                        String::from_utf8_lossy(&body)
+                    );
+                    format!("HTTP/1.1 201 Created\r\n\r\n{}", text)
+                }
+                _ => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n".to_owned(),
+            };
+            stream.write_all(resp.as_bytes()).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_http_client() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || serve(listener, 6));
+        let source = r#"
+    package main
+
+    import (
+        "errors"
+        "io"
+        "net/http"
+        "strings"
+    )
+
+    func readAll(r io.Reader) string {
+        s := ""
+        buf := make([]byte, 2)
+        for {
+            n, err := r.Read(buf)
+            s += string(buf[:n])
+            if err == io.EOF {
+                return s
+            }
+            assert(err == nil)
+        }
+    }
+
+    func main() {
+        base := "http://ADDR"
+        resp, err := http.Get(base + "/hello")
+        assert(err == nil)
+        assert(resp.StatusCode == http.StatusOK && resp.Status == "200 OK")
+        assert(resp.Proto == "HTTP/1.1" && resp.ContentLength == 5)
+        assert(resp.Header.Get("x-test") == "a" && len(resp.Header["X-Test"]) == 2)
+        assert(readAll(resp.Body) == "hello")
+        assert(resp.Body.Close() == nil)
+        _, err = resp.Body.Read(make([]byte, 1))
+        assert(err != nil)
+
+        resp, err = http.Get(base + "/chunked")
+        assert(err == nil && resp.ContentLength == -1)
+        assert(readAll(resp.Body) == "abcdef")
+
+        resp, err = http.Get(base + "/redirect")
+        assert(err == nil && resp.StatusCode == 200)
+        assert(resp.Request.URL == base + "/hello")
+
+        resp, err = http.Post(base + "/echo", "text/plain", strings.NewReader("ping"))
+        assert(err == nil && resp.StatusCode == http.StatusCreated)
+        assert(readAll(resp.Body) == "POST text/plain ping")
+
+        resp, err = http.Get(base + "/missing")
+        assert(err == nil && resp.StatusCode == http.StatusNotFound)
+
+        _, err = http.Get("https://ADDR/")
+        assert(err != nil)
+        assert(err.Error() == "Get \"https://ADDR/\": unsupported protocol scheme \"https\"")
+        var uerr *http.Error
+        assert(errors.As(err, &uerr) && uerr.Op == "Get")
+    }
+    "#
+        .replace("ADDR", &addr.to_string());
+        let mut cfg = crate::run_fs::Config::default();
+        cfg.working_dir = Some("./");
+        cfg.base_dir = Some("../std/");
+        let result = crate::run_fs::run_string(cfg, &source);
+        assert_eq!(result.unwrap(), RunResult::Done);
+        server.join().unwrap();
+    }
+}
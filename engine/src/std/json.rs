@@ -0,0 +1,1209 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! The backend of the `encoding/json` std package. Values are walked with
+//! their metadata, the same way `reflect` sees them, and encoded with the
+//! field names of structs. Methods like `MarshalJSON` are not called.
+
+extern crate self as goscript_engine;
+use super::kv::bytes_to_slice;
+use crate::ffi::*;
+use goscript_vm::format::ValueFormatter;
+use goscript_vm::metadata::{Meta, MetadataType};
+use goscript_vm::objects::{MetadataKey, MetadataObjs};
+use goscript_vm::value::*;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+
+/// Nesting limit of both the parser and the encoder, the encoder reports a
+/// cycle when it's reached
+const MAX_DEPTH: usize = 1000;
+
+#[derive(Ffi)]
+pub struct JsonFfi {}
+
+#[ffi_impl]
+impl JsonFfi {
+    fn ffi_marshal(&self, ctx: &FfiCallCtx, args: Vec<GosValue>) -> Vec<GosValue> {
+        let layout = match *args[1].as_bool() {
+            true => Some((str_of(&args[2]), str_of(&args[3]))),
+            false => None,
+        };
+        let mut enc = Encoder { ctx: ctx, depth: 0 };
+        let result = enc.encode_iface(&args[0]).map(|j| {
+            let mut out = String::new();
+            j.write(&mut out, &layout, 0);
+            out
+        });
+        match result {
+            Ok(s) => vec![
+                bytes_to_slice(s.into_bytes()),
+                GosValue::new_nil(ValueType::Interface),
+            ],
+            Err(e) => vec![GosValue::new_slice_nil(ValueType::Uint8), ctx.new_error(&e)],
+        }
+    }
+
+    fn ffi_unmarshal(&self, ctx: &mut FfiCallCtx, args: Vec<GosValue>) -> GosValue {
+        let result = JsonFfi::unmarshal(ctx, &args[0], &args[1]);
+        ctx.result_to_error(&result)
+    }
+
+    fn ffi_valid(&self, args: Vec<GosValue>) -> GosValue {
+        GosValue::new_bool(Parser::parse(&bytes_of(&args[0])).is_ok())
+    }
+}
+
+impl JsonFfi {
+    fn unmarshal(ctx: &mut FfiCallCtx, data: &GosValue, target: &GosValue) -> RuntimeResult<()> {
+        let (ptr, meta) = JsonFfi::target_pointer(ctx, target)?;
+        // the input is checked as a whole before anything is stored
+        let json = Parser::parse(&bytes_of(data))?;
+        JsonFfi::decode_pointee(ctx, &json, ptr, meta)
+    }
+
+    /// Returns the pointer in the interface target and the type it points to
+    fn target_pointer(ctx: &FfiCallCtx, target: &GosValue) -> RuntimeResult<(PointerObj, Meta)> {
+        let objs = ctx.vm_objs;
+        // an untyped nil argument is not an interface value
+        let iface = match target.typ() {
+            ValueType::Interface => target.as_interface(),
+            _ => None,
+        };
+        let (ptr, meta) = match iface.map(|x| x as &InterfaceObj) {
+            Some(InterfaceObj::Gos(v, Some((meta, _)))) => {
+                let t = ValueFormatter::new(objs, ctx.stack).type_string(meta);
+                if meta.ptr_depth == 0 {
+                    return Err(format!("json: Unmarshal(non-pointer {})", t));
+                } else if v.is_nil() {
+                    return Err(format!("json: Unmarshal(nil {})", t));
+                }
+                (v.as_some_pointer()?.clone(), meta.unptr_to())
+            }
+            _ => return Err("json: Unmarshal(nil)".to_owned()),
+        };
+        Ok((ptr, meta))
+    }
+
+    fn decode_pointee(
+        ctx: &mut FfiCallCtx,
+        json: &Json,
+        ptr: PointerObj,
+        meta: Meta,
+    ) -> RuntimeResult<()> {
+        let objs = ctx.vm_objs;
+        let cur = ptr.deref(ctx.stack, &objs.packages)?;
+        let mut dec = Decoder::new(ctx);
+        let val = dec.decode(json, meta, Some(cur))?;
+        let error = dec.error.take();
+        if !updated_in_place(&val) {
+            ptr.set_pointee(&val, ctx.stack, &objs.packages, ctx.gcv)?;
+        }
+        match error {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(feature = "serde_json")]
+/// Encodes the value in the interface iface like Marshal
+pub(crate) fn encode_iface(ctx: &FfiCallCtx, iface: &GosValue) -> RuntimeResult<Json> {
+    Encoder { ctx: ctx, depth: 0 }.encode_iface(iface)
+}
+
+#[cfg(feature = "serde_json")]
+/// Decodes j into what the pointer in the interface target points to, like
+/// Unmarshal
+pub(crate) fn decode_into(ctx: &mut FfiCallCtx, j: &Json, target: &GosValue) -> RuntimeResult<()> {
+    let (ptr, meta) = JsonFfi::target_pointer(ctx, target)?;
+    JsonFfi::decode_pointee(ctx, j, ptr, meta)
+}
+
+#[cfg(feature = "serde_json")]
+/// Decodes j into an empty interface, like Unmarshal
+pub(crate) fn decode_any(ctx: &mut FfiCallCtx, j: &Json) -> RuntimeResult<GosValue> {
+    let mut dec = Decoder::new(ctx);
+    let val = dec.decode_any(j)?;
+    match dec.error.take() {
+        Some(e) => Err(e),
+        None => Ok(val),
+    }
+}
+
+/// A parsed JSON value, numbers keep their literal text so that integers
+/// are not rounded through f64
+#[derive(Debug)]
+pub(crate) enum Json {
+    Null,
+    Bool(bool),
+    Number(String),
+    Str(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// Describes the value in type errors, like Go's `UnmarshalTypeError`
+    fn describe(&self) -> String {
+        match self {
+            Json::Null => "null".to_owned(),
+            Json::Bool(_) => "bool".to_owned(),
+            Json::Number(n) => format!("number {}", n),
+            Json::Str(_) => "string".to_owned(),
+            Json::Array(_) => "array".to_owned(),
+            Json::Object(_) => "object".to_owned(),
+        }
+    }
+
+    /// Writes compact JSON if layout is None, or JSON indented like Go's
+    /// `MarshalIndent` with layout's (prefix, indent)
+    fn write(&self, out: &mut String, layout: &Option<(String, String)>, level: usize) {
+        let newline = |out: &mut String, level: usize| {
+            if let Some((prefix, indent)) = layout {
+                out.push('\n');
+                out.push_str(prefix);
+                for _ in 0..level {
+                    out.push_str(indent);
+                }
+            }
+        };
+        match self {
+            Json::Null => out.push_str("null"),
+            Json::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+            Json::Number(n) => out.push_str(n),
+            Json::Str(s) => write_string(out, s),
+            Json::Array(items) => {
+                out.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    newline(out, level + 1);
+                    item.write(out, layout, level + 1);
+                }
+                if !items.is_empty() {
+                    newline(out, level);
+                }
+                out.push(']');
+            }
+            Json::Object(members) => {
+                out.push('{');
+                for (i, (key, val)) in members.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    newline(out, level + 1);
+                    write_string(out, key);
+                    out.push(':');
+                    if layout.is_some() {
+                        out.push(' ');
+                    }
+                    val.write(out, layout, level + 1);
+                }
+                if !members.is_empty() {
+                    newline(out, level);
+                }
+                out.push('}');
+            }
+        }
+    }
+}
+
+/// Quotes s the way Go does, with <, > and & escaped so that the output
+/// can be embedded in HTML
+fn write_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\u{8}' => out.push_str("\\b"),
+            '\u{c}' => out.push_str("\\f"),
+            '<' | '>' | '&' | '\u{2028}' | '\u{2029}' => {
+                out.push_str(&format!("\\u{:04x}", c as u32))
+            }
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+struct Parser<'a> {
+    data: &'a [u8],
+    pos: usize,
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    /// Parses a whole JSON document, errors read like Go's `SyntaxError`
+    fn parse(data: &'a [u8]) -> RuntimeResult<Json> {
+        let mut p = Parser {
+            data: data,
+            pos: 0,
+            depth: 0,
+        };
+        p.skip_space();
+        let val = p.value()?;
+        p.skip_space();
+        match p.peek() {
+            Some(c) => Err(invalid_char(c, "after top-level value")),
+            None => Ok(val),
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.data.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> RuntimeResult<u8> {
+        let c = self.peek().ok_or_else(unexpected_end)?;
+        self.pos += 1;
+        Ok(c)
+    }
+
+    fn skip_space(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn value(&mut self) -> RuntimeResult<Json> {
+        match self.peek() {
+            None => Err(unexpected_end()),
+            Some(b'{') | Some(b'[') => {
+                self.depth += 1;
+                if self.depth > MAX_DEPTH {
+                    return Err("exceeded max depth".to_owned());
+                }
+                let val = match self.peek() {
+                    Some(b'{') => self.object(),
+                    _ => self.array(),
+                };
+                self.depth -= 1;
+                val
+            }
+            Some(b'"') => self.string().map(Json::Str),
+            Some(b't') => self.literal("true").map(|_| Json::Bool(true)),
+            Some(b'f') => self.literal("false").map(|_| Json::Bool(false)),
+            Some(b'n') => self.literal("null").map(|_| Json::Null),
+            Some(b'-') | Some(b'0'..=b'9') => self.number().map(Json::Number),
+            Some(c) => Err(invalid_char(c, "looking for beginning of value")),
+        }
+    }
+
+    fn object(&mut self) -> RuntimeResult<Json> {
+        self.pos += 1;
+        let mut members = vec![];
+        self.skip_space();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(Json::Object(members));
+        }
+        loop {
+            match self.peek() {
+                Some(b'"') => {}
+                Some(c) => {
+                    return Err(invalid_char(
+                        c,
+                        "looking for beginning of object key string",
+                    ))
+                }
+                None => return Err(unexpected_end()),
+            }
+            let key = self.string()?;
+            self.skip_space();
+            match self.next()? {
+                b':' => {}
+                c => return Err(invalid_char(c, "after object key")),
+            }
+            self.skip_space();
+            members.push((key, self.value()?));
+            self.skip_space();
+            match self.next()? {
+                b',' => self.skip_space(),
+                b'}' => return Ok(Json::Object(members)),
+                c => return Err(invalid_char(c, "after object key:value pair")),
+            }
+        }
+    }
+
+    fn array(&mut self) -> RuntimeResult<Json> {
+        self.pos += 1;
+        let mut items = vec![];
+        self.skip_space();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(Json::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            self.skip_space();
+            match self.next()? {
+                b',' => self.skip_space(),
+                b']' => return Ok(Json::Array(items)),
+                c => return Err(invalid_char(c, "after array element")),
+            }
+        }
+    }
+
+    fn literal(&mut self, word: &str) -> RuntimeResult<()> {
+        for &expected in word.as_bytes() {
+            let c = self.next()?;
+            if c != expected {
+                return Err(format!(
+                    "invalid character {} in literal {} (expecting {})",
+                    quote_char(c),
+                    word,
+                    quote_char(expected)
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn number(&mut self) -> RuntimeResult<String> {
+        let start = self.pos;
+        if self.peek() == Some(b'-') {
+            self.pos += 1;
+        }
+        match self.next()? {
+            b'0' => {}
+            b'1'..=b'9' => self.digits(),
+            c => return Err(invalid_char(c, "in numeric literal")),
+        }
+        if self.peek() == Some(b'.') {
+            self.pos += 1;
+            match self.next()? {
+                b'0'..=b'9' => self.digits(),
+                c => return Err(invalid_char(c, "after decimal point in numeric literal")),
+            }
+        }
+        if let Some(b'e' | b'E') = self.peek() {
+            self.pos += 1;
+            if let Some(b'+' | b'-') = self.peek() {
+                self.pos += 1;
+            }
+            match self.next()? {
+                b'0'..=b'9' => self.digits(),
+                c => return Err(invalid_char(c, "in exponent of numeric literal")),
+            }
+        }
+        Ok(String::from_utf8_lossy(&self.data[start..self.pos]).into_owned())
+    }
+
+    fn digits(&mut self) {
+        while let Some(b'0'..=b'9') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    /// Invalid UTF-8 and unpaired surrogates are replaced with U+FFFD
+    fn string(&mut self) -> RuntimeResult<String> {
+        self.pos += 1;
+        let mut buf: Vec<u8> = vec![];
+        loop {
+            match self.next()? {
+                b'"' => return Ok(String::from_utf8_lossy(&buf).into_owned()),
+                b'\\' => match self.next()? {
+                    c @ (b'"' | b'\\' | b'/') => buf.push(c),
+                    b'b' => buf.push(8),
+                    b'f' => buf.push(12),
+                    b'n' => buf.push(b'\n'),
+                    b'r' => buf.push(b'\r'),
+                    b't' => buf.push(b'\t'),
+                    b'u' => {
+                        let mut code = self.hex4()?;
+                        if (0xd800..0xdc00).contains(&code)
+                            && self.data[self.pos..].starts_with(b"\\u")
+                        {
+                            let save = self.pos;
+                            self.pos += 2;
+                            let low = self.hex4()?;
+                            if (0xdc00..0xe000).contains(&low) {
+                                code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+                            } else {
+                                self.pos = save;
+                            }
+                        }
+                        let c = char::from_u32(code).unwrap_or('\u{fffd}');
+                        buf.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                    }
+                    c => return Err(invalid_char(c, "in string escape code")),
+                },
+                c if c < 0x20 => return Err(invalid_char(c, "in string literal")),
+                c => buf.push(c),
+            }
+        }
+    }
+
+    fn hex4(&mut self) -> RuntimeResult<u32> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let c = self.next()?;
+            let d = (c as char)
+                .to_digit(16)
+                .ok_or_else(|| invalid_char(c, "in \\u hexadecimal character escape"))?;
+            code = code * 16 + d;
+        }
+        Ok(code)
+    }
+}
+
+fn unexpected_end() -> String {
+    "unexpected end of JSON input".to_owned()
+}
+
+fn invalid_char(c: u8, context: &str) -> String {
+    format!("invalid character {} {}", quote_char(c), context)
+}
+
+/// Same as Go's `quoteChar` in encoding/json
+fn quote_char(c: u8) -> String {
+    match c {
+        b'\'' => "'\\''".to_owned(),
+        b'"' => "'\"'".to_owned(),
+        b'\\' => "'\\\\'".to_owned(),
+        b'\n' => "'\\n'".to_owned(),
+        b'\r' => "'\\r'".to_owned(),
+        b'\t' => "'\\t'".to_owned(),
+        c if c < 0x20 || c == 0x7f => format!("'\\x{:02x}'", c),
+        c => format!("'{}'", c as char),
+    }
+}
+
+/// A struct field as JSON sees it, the fields of embedded structs are
+/// promoted
+struct JsonField {
+    /// The key, from the json tag or the field name
+    name: String,
+    /// Whether the name comes from a json tag
+    tagged: bool,
+    omit_empty: bool,
+    /// Field indices, from the outermost struct to the struct that has it
+    path: Vec<usize>,
+    meta: Meta,
+}
+
+/// Returns the fields of the struct type meta that are encoded, in the order
+/// of declaration. Like in Go, a promoted field is hidden by a field with the
+/// same name at a shallower depth, and dropped if there are more of them at
+/// the same depth, unless exactly one of those is named by a json tag.
+///
+/// A field tagged `json:"-"` is skipped, `json:"name,omitempty"` renames it
+/// and omits it when encoding an empty value. An embedded struct with a
+/// tagged name is encoded as a field instead of being flattened.
+fn json_fields(meta: Meta, metas: &MetadataObjs) -> Vec<JsonField> {
+    fn collect(
+        meta: Meta,
+        metas: &MetadataObjs,
+        path: &mut Vec<usize>,
+        visiting: &mut Vec<MetadataKey>,
+        out: &mut Vec<JsonField>,
+    ) {
+        let fields = metas[meta.underlying(metas).key].as_struct().0.all();
+        for (i, f) in fields.iter().enumerate() {
+            let tag = f.lookup_tag("json");
+            if tag.as_deref() == Some("-") {
+                continue;
+            }
+            let (tag_name, options) = match tag.as_deref().map(|t| t.split_once(',')) {
+                Some(Some((name, options))) => (name, options),
+                _ => (tag.as_deref().unwrap_or(""), ""),
+            };
+            let tag_name = is_valid_tag_name(tag_name).then_some(tag_name);
+            path.push(i);
+            let embedded = match f.meta.ptr_depth {
+                0 => f.meta,
+                _ => f.meta.unptr_to(),
+            };
+            let is_struct = embedded.ptr_depth == 0
+                && embedded.underlying(metas).value_type(metas) == ValueType::Struct;
+            if f.embedded && is_struct && tag_name.is_none() {
+                if !visiting.contains(&embedded.key) {
+                    visiting.push(embedded.key);
+                    collect(embedded, metas, path, visiting, out);
+                    visiting.pop();
+                }
+            } else if f.exported {
+                out.push(JsonField {
+                    name: tag_name.unwrap_or(&f.name).to_owned(),
+                    tagged: tag_name.is_some(),
+                    omit_empty: options.split(',').any(|o| o == "omitempty"),
+                    path: path.clone(),
+                    meta: f.meta,
+                });
+            }
+            path.pop();
+        }
+    }
+
+    let mut all = vec![];
+    collect(meta, metas, &mut vec![], &mut vec![meta.key], &mut all);
+    let visible: Vec<bool> = all
+        .iter()
+        .map(|f| {
+            let min = all
+                .iter()
+                .filter(|x| x.name == f.name)
+                .map(|x| x.path.len())
+                .min()
+                .unwrap();
+            let rivals: Vec<&JsonField> = all
+                .iter()
+                .filter(|x| x.name == f.name && x.path.len() == min)
+                .collect();
+            f.path.len() == min
+                && (rivals.len() == 1
+                    || (f.tagged && rivals.iter().filter(|x| x.tagged).count() == 1))
+        })
+        .collect();
+    all.into_iter()
+        .zip(visible)
+        .filter_map(|(f, v)| v.then_some(f))
+        .collect()
+}
+
+/// Reports whether name can be used as a key from a json tag, the same
+/// characters as Go allows
+fn is_valid_tag_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == ' ' || "!#$%&()*+-./:;<=>?@[]^_{|}~".contains(c))
+}
+
+/// Reports whether val is empty as omitempty means it: false, 0, a nil
+/// pointer or interface, and an empty array, slice, map or string
+fn is_empty_value(val: &GosValue) -> bool {
+    match val.typ() {
+        ValueType::Array | ValueType::Slice | ValueType::Map | ValueType::String => val.len() == 0,
+        ValueType::Float32 => val.as_float32().into_inner() == 0.0,
+        ValueType::Float64 => val.as_float64().into_inner() == 0.0,
+        ValueType::Pointer | ValueType::Interface | ValueType::UnsafePtr => val.is_nil(),
+        t if t.copyable() => *val.as_uint() == 0,
+        _ => false,
+    }
+}
+
+struct Encoder<'a, 'b> {
+    ctx: &'a FfiCallCtx<'b>,
+    depth: usize,
+}
+
+impl<'a, 'b> Encoder<'a, 'b> {
+    fn encode_iface(&mut self, iface: &GosValue) -> RuntimeResult<Json> {
+        if iface.is_nil() {
+            return Ok(Json::Null);
+        }
+        match iface.as_interface().map(|x| x as &InterfaceObj) {
+            Some(InterfaceObj::Gos(v, Some((meta, _)))) => self.encode(v, *meta),
+            Some(InterfaceObj::Gos(v, None)) => match self.basic_meta(v.typ()) {
+                Some(meta) => self.encode(v, meta),
+                None => Err(format!("json: unsupported type: {:?}", v.typ())),
+            },
+            _ => Err("json: unsupported type: ffi object".to_owned()),
+        }
+    }
+
+    fn encode(&mut self, val: &GosValue, meta: Meta) -> RuntimeResult<Json> {
+        self.depth += 1;
+        let result = match self.depth > MAX_DEPTH {
+            true => Err(format!(
+                "json: unsupported value: encountered a cycle via {}",
+                self.type_string(&meta)
+            )),
+            false => self.encode_value(val, meta),
+        };
+        self.depth -= 1;
+        result
+    }
+
+    fn encode_value(&mut self, val: &GosValue, meta: Meta) -> RuntimeResult<Json> {
+        let objs = self.ctx.vm_objs;
+        let metas = &objs.metas;
+        if meta.ptr_depth > 0 {
+            if val.is_nil() {
+                return Ok(Json::Null);
+            }
+            let pointee = val
+                .as_some_pointer()?
+                .deref(self.ctx.stack, &objs.packages)?;
+            return self.encode(&pointee, meta.unptr_to());
+        }
+        let umeta = meta.underlying(metas);
+        match &metas[umeta.key] {
+            MetadataType::Bool => Ok(Json::Bool(*val.as_bool())),
+            _ if is_integer(val.typ()) => Ok(Json::Number(integer_string(val))),
+            MetadataType::Float32 => {
+                format_float(Into::<f32>::into(*val.as_float32()) as f64, 32).map(Json::Number)
+            }
+            MetadataType::Float64 => {
+                format_float(val.as_float64().into_inner(), 64).map(Json::Number)
+            }
+            MetadataType::Str(_) => Ok(Json::Str(str_of(val))),
+            MetadataType::Slice(elem) => {
+                if val.is_nil() {
+                    Ok(Json::Null)
+                } else if is_byte(elem, metas) {
+                    Ok(Json::Str(base64_encode(&bytes_of(val))))
+                } else {
+                    let items = (0..val.len())
+                        .map(|i| {
+                            let v = val.dispatcher_a_s().slice_get(val, i)?;
+                            self.encode(&v, *elem)
+                        })
+                        .collect::<RuntimeResult<Vec<Json>>>()?;
+                    Ok(Json::Array(items))
+                }
+            }
+            MetadataType::Array(elem, _) => {
+                let items = (0..val.len())
+                    .map(|i| {
+                        let v = val.dispatcher_a_s().array_get(val, i)?;
+                        self.encode(&v, *elem)
+                    })
+                    .collect::<RuntimeResult<Vec<Json>>>()?;
+                Ok(Json::Array(items))
+            }
+            MetadataType::Map(kmeta, vmeta) => {
+                if val.is_nil() {
+                    return Ok(Json::Null);
+                }
+                let key_of: fn(&GosValue) -> String = match kmeta.value_type(metas) {
+                    ValueType::String => str_of,
+                    t if is_integer(t) => integer_string,
+                    _ => {
+                        return Err(format!(
+                            "json: unsupported type: {}",
+                            self.type_string(&meta)
+                        ))
+                    }
+                };
+                let entries: Vec<(GosValue, GosValue)> = val
+                    .as_map()
+                    .unwrap()
+                    .0
+                    .borrow_data()
+                    .iter()
+                    .map(|(k, v)| (k.clone(), v.clone()))
+                    .collect();
+                let mut members = entries
+                    .iter()
+                    .map(|(k, v)| Ok((key_of(k), self.encode(v, *vmeta)?)))
+                    .collect::<RuntimeResult<Vec<(String, Json)>>>()?;
+                members.sort_by(|a, b| a.0.cmp(&b.0));
+                Ok(Json::Object(members))
+            }
+            MetadataType::Struct(_, _) => {
+                let mut members = vec![];
+                for f in json_fields(umeta, metas).iter() {
+                    if let Some(v) = self.field_value(val, &f.path)? {
+                        if f.omit_empty && is_empty_value(&v) {
+                            continue;
+                        }
+                        members.push((f.name.clone(), self.encode(&v, f.meta)?));
+                    }
+                }
+                Ok(Json::Object(members))
+            }
+            MetadataType::Interface(_) => self.encode_iface(val),
+            _ => Err(format!(
+                "json: unsupported type: {}",
+                self.type_string(&meta)
+            )),
+        }
+    }
+
+    /// Returns None if the field is promoted through a nil embedded pointer
+    fn field_value(&self, val: &GosValue, path: &[usize]) -> RuntimeResult<Option<GosValue>> {
+        let mut cur = val.clone();
+        for (n, &i) in path.iter().enumerate() {
+            let v = cur.as_struct().0.borrow_fields()[i].clone();
+            if n + 1 == path.len() {
+                return Ok(Some(v));
+            }
+            cur = match v.typ() {
+                ValueType::Pointer if v.is_nil() => return Ok(None),
+                ValueType::Pointer => v
+                    .as_some_pointer()?
+                    .deref(self.ctx.stack, &self.ctx.vm_objs.packages)?,
+                _ => v,
+            };
+        }
+        Ok(None)
+    }
+
+    /// Metadata of values put in interfaces by FFI code, which have none
+    fn basic_meta(&self, t: ValueType) -> Option<Meta> {
+        let s = &self.ctx.vm_objs.s_meta;
+        match t {
+            ValueType::Bool => Some(s.mbool),
+            ValueType::Int => Some(s.mint),
+            ValueType::Int64 => Some(s.mint64),
+            ValueType::Uint => Some(s.muint),
+            ValueType::Uint64 => Some(s.muint64),
+            ValueType::Float64 => Some(s.mfloat64),
+            ValueType::String => Some(s.mstr),
+            _ => None,
+        }
+    }
+
+    fn type_string(&self, meta: &Meta) -> String {
+        ValueFormatter::new(self.ctx.vm_objs, self.ctx.stack).type_string(meta)
+    }
+}
+
+struct Decoder<'a, 'b> {
+    ctx: &'a mut FfiCallCtx<'b>,
+    /// The first type error, decoding goes on after it like in Go
+    error: Option<String>,
+    /// Type name of the struct of the field being decoded, for errors
+    struct_name: String,
+    /// Names of the fields being decoded, from the outermost struct
+    field_path: Vec<String>,
+}
+
+impl<'a, 'b> Decoder<'a, 'b> {
+    fn new(ctx: &'a mut FfiCallCtx<'b>) -> Decoder<'a, 'b> {
+        Decoder {
+            ctx: ctx,
+            error: None,
+            struct_name: String::new(),
+            field_path: vec![],
+        }
+    }
+
+    /// Decodes j into a value of type meta. If cur is given, structs and
+    /// arrays are updated in place and returned, maps get the new entries
+    /// and pointers set the values they point to, like in Go.
+    fn decode(&mut self, j: &Json, meta: Meta, cur: Option<GosValue>) -> RuntimeResult<GosValue> {
+        let objs = self.ctx.vm_objs;
+        let metas = &objs.metas;
+        let gcv = self.ctx.gcv;
+        let cur = match cur {
+            Some(v) => v,
+            None => meta.zero(metas, gcv),
+        };
+        if meta.ptr_depth > 0 {
+            if let Json::Null = j {
+                return Ok(meta.zero(metas, gcv));
+            }
+            if cur.is_nil() {
+                let val = self.decode(j, meta.unptr_to(), None)?;
+                return Ok(GosValue::new_pointer(PointerObj::new_closed_up_value(&val)));
+            }
+            let ptr = cur.as_some_pointer()?;
+            let pointee = ptr.deref(self.ctx.stack, &objs.packages)?;
+            let val = self.decode(j, meta.unptr_to(), Some(pointee))?;
+            if !updated_in_place(&val) {
+                ptr.set_pointee(&val, self.ctx.stack, &objs.packages, gcv)?;
+            }
+            return Ok(cur);
+        }
+
+        let mt = &metas[meta.underlying(metas).key];
+        let t = meta.value_type(metas);
+        match (mt, j) {
+            (
+                MetadataType::Slice(_) | MetadataType::Map(_, _) | MetadataType::Interface(_),
+                Json::Null,
+            ) => Ok(meta.zero(metas, gcv)),
+            // null is a no-op for other types
+            (_, Json::Null) => Ok(cur),
+            (MetadataType::Bool, Json::Bool(b)) => Ok(GosValue::new_bool(*b)),
+            (MetadataType::Str(_), Json::Str(s)) => Ok(GosValue::with_str(s)),
+            (
+                MetadataType::Int
+                | MetadataType::Int8
+                | MetadataType::Int16
+                | MetadataType::Int32
+                | MetadataType::Int64
+                | MetadataType::Uint
+                | MetadataType::UintPtr
+                | MetadataType::Uint8
+                | MetadataType::Uint16
+                | MetadataType::Uint32
+                | MetadataType::Uint64
+                | MetadataType::Float32
+                | MetadataType::Float64,
+                Json::Number(n),
+            ) => match parse_number(n, t) {
+                Some(v) => Ok(v),
+                None => Ok(self.type_error(j, meta, cur)),
+            },
+            (MetadataType::Slice(elem), Json::Str(s)) if is_byte(elem, metas) => {
+                Ok(bytes_to_slice(base64_decode(s)?))
+            }
+            (MetadataType::Slice(elem), Json::Array(items)) => {
+                let vals = items
+                    .iter()
+                    .map(|x| self.decode(x, *elem, None))
+                    .collect::<RuntimeResult<Vec<GosValue>>>()?;
+                Ok(GosValue::slice_with_data(vals, elem.value_type(metas), gcv))
+            }
+            (MetadataType::Array(elem, size), Json::Array(items)) => {
+                let dispatcher = cur.dispatcher_a_s();
+                for i in 0..*size {
+                    let val = match items.get(i) {
+                        Some(x) => {
+                            let old = dispatcher.array_get(&cur, i)?;
+                            self.decode(x, *elem, Some(old))?
+                        }
+                        None => elem.zero(metas, gcv),
+                    };
+                    dispatcher.array_set(&cur, &val, i)?;
+                }
+                Ok(cur)
+            }
+            (MetadataType::Map(kmeta, vmeta), Json::Object(members)) => {
+                let kt = kmeta.value_type(metas);
+                if kt != ValueType::String && !is_integer(kt) {
+                    return Ok(self.type_error(j, meta, cur));
+                }
+                let map = match cur.is_nil() {
+                    true => GosValue::map_with_default_val(vmeta.zero(metas, gcv), gcv),
+                    false => cur,
+                };
+                for (key, val) in members.iter() {
+                    let k = match kt {
+                        ValueType::String => GosValue::with_str(key),
+                        _ => match parse_number(key, kt) {
+                            Some(k) => k,
+                            None => {
+                                let j = Json::Number(key.clone());
+                                self.type_error(&j, *kmeta, GosValue::new_nil(kt));
+                                continue;
+                            }
+                        },
+                    };
+                    let v = self.decode(val, *vmeta, None)?;
+                    map.as_map().unwrap().0.insert(k, v);
+                }
+                Ok(map)
+            }
+            (MetadataType::Struct(_, _), Json::Object(members)) => {
+                let fields = json_fields(meta, metas);
+                for (key, val) in members.iter() {
+                    let f = fields.iter().find(|f| &f.name == key).or_else(|| {
+                        fields
+                            .iter()
+                            .find(|f| f.name.to_lowercase() == key.to_lowercase())
+                    });
+                    if let Some(f) = f {
+                        self.decode_field(&cur, meta, f, val)?;
+                    }
+                }
+                Ok(cur)
+            }
+            (MetadataType::Interface(fields), _) => {
+                // a non-nil pointer in the interface is decoded into
+                if let Some(InterfaceObj::Gos(v, Some((m, _)))) =
+                    cur.as_interface().map(|x| x as &InterfaceObj)
+                {
+                    if m.ptr_depth > 0 && !v.is_nil() {
+                        self.decode(j, *m, Some(v.clone()))?;
+                        return Ok(cur);
+                    }
+                }
+                match fields.all().is_empty() {
+                    true => self.decode_any(j),
+                    false => Ok(self.type_error(j, meta, cur)),
+                }
+            }
+            _ => Ok(self.type_error(j, meta, cur)),
+        }
+    }
+
+    fn decode_field(
+        &mut self,
+        s: &GosValue,
+        smeta: Meta,
+        f: &JsonField,
+        j: &Json,
+    ) -> RuntimeResult<()> {
+        let objs = self.ctx.vm_objs;
+        let metas = &objs.metas;
+        let (last, embedded) = f.path.split_last().unwrap();
+        // find or allocate the embedded struct that has the field
+        let mut target = s.clone();
+        let mut tmeta = smeta;
+        for &i in embedded.iter() {
+            let fmeta = metas[tmeta.underlying(metas).key].as_struct().0.all()[i].meta;
+            let v = target.as_struct().0.borrow_fields()[i].clone();
+            if fmeta.ptr_depth == 0 {
+                target = v;
+                tmeta = fmeta;
+                continue;
+            }
+            tmeta = fmeta.unptr_to();
+            target = match v.is_nil() {
+                true => {
+                    let zero = tmeta.zero(metas, self.ctx.gcv);
+                    let ptr = GosValue::new_pointer(PointerObj::new_closed_up_value(&zero));
+                    target.as_struct().0.borrow_fields_mut()[i] = ptr;
+                    zero
+                }
+                false => v.as_some_pointer()?.deref(self.ctx.stack, &objs.packages)?,
+            };
+        }
+
+        let cur = target.as_struct().0.borrow_fields()[*last].clone();
+        let struct_name = std::mem::replace(&mut self.struct_name, type_name(&tmeta, metas));
+        self.field_path.push(f.name.clone());
+        let val = self.decode(j, f.meta, Some(cur));
+        self.field_path.pop();
+        self.struct_name = struct_name;
+        target.as_struct().0.borrow_fields_mut()[*last] = val?;
+        Ok(())
+    }
+
+    /// Decodes into an empty interface, with the types Go uses:
+    /// bool, float64, string, []interface{} and map[string]interface{}
+    fn decode_any(&mut self, j: &Json) -> RuntimeResult<GosValue> {
+        let s = &self.ctx.vm_objs.s_meta;
+        let gcv = self.ctx.gcv;
+        let (val, meta) = match j {
+            Json::Null => return Ok(GosValue::new_nil(ValueType::Interface)),
+            Json::Bool(b) => (GosValue::new_bool(*b), s.mbool),
+            Json::Number(n) => match parse_number(n, ValueType::Float64) {
+                Some(v) => (v, s.mfloat64),
+                None => {
+                    let nil = GosValue::new_nil(ValueType::Interface);
+                    return Ok(self.type_error(j, s.mfloat64, nil));
+                }
+            },
+            Json::Str(x) => (GosValue::with_str(x), s.mstr),
+            Json::Array(items) => {
+                let vals = items
+                    .iter()
+                    .map(|x| self.decode_any(x))
+                    .collect::<RuntimeResult<Vec<GosValue>>>()?;
+                let slice = GosValue::slice_with_data(vals, ValueType::Interface, gcv);
+                (slice, s.empty_iface_slice)
+            }
+            Json::Object(members) => {
+                let map =
+                    GosValue::map_with_default_val(GosValue::new_nil(ValueType::Interface), gcv);
+                for (key, val) in members.iter() {
+                    let v = self.decode_any(val)?;
+                    map.as_map().unwrap().0.insert(GosValue::with_str(key), v);
+                }
+                (map, s.empty_iface_map)
+            }
+        };
+        Ok(GosValue::new_interface(InterfaceObj::with_value(
+            val,
+            Some((meta, vec![])),
+        )))
+    }
+
+    /// Records the error if it's the first one, and returns cur to be kept
+    fn type_error(&mut self, j: &Json, meta: Meta, cur: GosValue) -> GosValue {
+        if self.error.is_none() {
+            let t = ValueFormatter::new(self.ctx.vm_objs, self.ctx.stack).type_string(&meta);
+            self.error = Some(match self.field_path.is_empty() {
+                true => format!(
+                    "json: cannot unmarshal {} into Go value of type {}",
+                    j.describe(),
+                    t
+                ),
+                false => format!(
+                    "json: cannot unmarshal {} into Go struct field {}.{} of type {}",
+                    j.describe(),
+                    self.struct_name,
+                    self.field_path.join("."),
+                    t
+                ),
+            });
+        }
+        cur
+    }
+}
+
+/// Structs and arrays are decoded into in place, storing them again would
+/// store a copy.
+fn updated_in_place(val: &GosValue) -> bool {
+    matches!(val.typ(), ValueType::Struct | ValueType::Array)
+}
+
+/// Parses a JSON number into a value of the numeric type t, None if it's
+/// out of range or not an integer for integer types
+fn parse_number(n: &str, t: ValueType) -> Option<GosValue> {
+    let (min, max) = match t {
+        ValueType::Float32 => {
+            let f = n.parse::<f32>().ok().filter(|f| f.is_finite())?;
+            return Some(GosValue::new_float32(f.into()));
+        }
+        ValueType::Float64 => {
+            let f = n.parse::<f64>().ok().filter(|f| f.is_finite())?;
+            return Some(GosValue::new_float64(f.into()));
+        }
+        ValueType::Uint | ValueType::UintPtr => (0, usize::MAX as i128),
+        ValueType::Uint8 => (0, u8::MAX as i128),
+        ValueType::Uint16 => (0, u16::MAX as i128),
+        ValueType::Uint32 => (0, u32::MAX as i128),
+        ValueType::Uint64 => (0, u64::MAX as i128),
+        ValueType::Int => (isize::MIN as i128, isize::MAX as i128),
+        ValueType::Int8 => (i8::MIN as i128, i8::MAX as i128),
+        ValueType::Int16 => (i16::MIN as i128, i16::MAX as i128),
+        ValueType::Int32 => (i32::MIN as i128, i32::MAX as i128),
+        ValueType::Int64 => (i64::MIN as i128, i64::MAX as i128),
+        _ => return None,
+    };
+    let i = n.parse::<i128>().ok().filter(|i| (min..=max).contains(i))?;
+    let mut val = match min {
+        0 => GosValue::new_uint64(i as u64),
+        _ => GosValue::new_int64(i as i64),
+    };
+    val.cast_copyable(val.typ(), t);
+    Some(val)
+}
+
+/// Formats floats like Go's encoding/json, the exponent form is used for
+/// very small and very large numbers only
+fn format_float(f: f64, bits: u32) -> RuntimeResult<String> {
+    if !f.is_finite() {
+        let s = match f {
+            f if f.is_nan() => "NaN",
+            f if f > 0.0 => "+Inf",
+            _ => "-Inf",
+        };
+        return Err(format!("json: unsupported value: {}", s));
+    }
+    let abs = f.abs();
+    let exp = abs != 0.0 && !(1e-6..1e21).contains(&abs);
+    let s = match (bits, exp) {
+        (32, false) => (f as f32).to_string(),
+        (32, true) => format!("{:e}", f as f32),
+        (_, false) => f.to_string(),
+        (_, true) => format!("{:e}", f),
+    };
+    Ok(match s.find('e') {
+        Some(i) if !s[i + 1..].starts_with('-') => format!("{}e+{}", &s[..i], &s[i + 1..]),
+        _ => s,
+    })
+}
+
+const BASE64: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64_encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len() * 4 / 3 + 4);
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            match i <= chunk.len() {
+                true => out.push(BASE64[(n >> (18 - 6 * i) & 63) as usize] as char),
+                false => out.push('='),
+            }
+        }
+    }
+    out
+}
+
+/// Decodes standard base64 with padding, newlines are ignored like in Go
+fn base64_decode(s: &str) -> RuntimeResult<Vec<u8>> {
+    let input: Vec<(usize, u8)> = s
+        .bytes()
+        .enumerate()
+        .filter(|(_, c)| *c != b'\r' && *c != b'\n')
+        .collect();
+    let illegal = |i: usize| format!("illegal base64 data at input byte {}", i);
+    if input.len() & 3 != 0 {
+        return Err(illegal(input.last().map_or(0, |x| x.0 + 1) & !3));
+    }
+    let mut out = Vec::with_capacity(input.len() / 4 * 3);
+    for (ci, chunk) in input.chunks(4).enumerate() {
+        let last = ci + 1 == input.len() / 4;
+        let mut n = 0;
+        let mut count = 0;
+        for (k, &(i, c)) in chunk.iter().enumerate() {
+            if c == b'=' {
+                let padding_ok = last && k >= 2 && chunk[k..].iter().all(|x| x.1 == b'=');
+                if !padding_ok {
+                    return Err(illegal(i));
+                }
+                break;
+            }
+            let d = BASE64
+                .iter()
+                .position(|&x| x == c)
+                .ok_or_else(|| illegal(i))?;
+            n |= (d as u32) << (18 - 6 * k);
+            count += 1;
+        }
+        out.extend_from_slice(&n.to_be_bytes()[1..count]);
+    }
+    Ok(out)
+}
+
+fn is_byte(meta: &Meta, metas: &MetadataObjs) -> bool {
+    meta.ptr_depth == 0 && meta.underlying(metas).value_type(metas) == ValueType::Uint8
+}
+
+fn type_name(meta: &Meta, metas: &MetadataObjs) -> String {
+    match &metas[meta.key] {
+        MetadataType::Named(_, _, name) => match name.rfind('.') {
+            Some(i) => name[i + 1..].to_owned(),
+            None => name.clone(),
+        },
+        _ => String::new(),
+    }
+}
+
+fn is_integer(t: ValueType) -> bool {
+    matches!(
+        t,
+        ValueType::Int
+            | ValueType::Int8
+            | ValueType::Int16
+            | ValueType::Int32
+            | ValueType::Int64
+            | ValueType::Uint
+            | ValueType::UintPtr
+            | ValueType::Uint8
+            | ValueType::Uint16
+            | ValueType::Uint32
+            | ValueType::Uint64
+    )
+}
+
+fn integer_string(val: &GosValue) -> String {
+    match val.typ() {
+        ValueType::Int => val.as_int().to_string(),
+        ValueType::Int8 => val.as_int8().to_string(),
+        ValueType::Int16 => val.as_int16().to_string(),
+        ValueType::Int32 => val.as_int32().to_string(),
+        ValueType::Int64 => val.as_int64().to_string(),
+        ValueType::Uint => val.as_uint().to_string(),
+        ValueType::UintPtr => val.as_uint_ptr().to_string(),
+        ValueType::Uint8 => val.as_uint8().to_string(),
+        ValueType::Uint16 => val.as_uint16().to_string(),
+        ValueType::Uint32 => val.as_uint32().to_string(),
+        _ => val.as_uint64().to_string(),
+    }
+}
+
+/// Invalid UTF-8 is replaced with U+FFFD, as Go does when encoding
+fn str_of(val: &GosValue) -> String {
+    let bytes = unsafe { val.as_string().as_raw_slice::<u8>() };
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+fn bytes_of(val: &GosValue) -> Vec<u8> {
+    match val.as_slice::<Elem8>() {
+        Some(s) => unsafe { s.0.as_raw_slice::<u8>() }.to_vec(),
+        None => vec![],
+    }
+}
@@ -0,0 +1,236 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! The backend of the `kv` std package. Scripts get persistent state through
+//! a `Store` set by the embedder with `Engine::set_kv_store`, it defaults to
+//! an in-memory `MemStore` that lives as long as the engine.
+
+extern crate self as goscript_engine;
+use crate::engine::Statics;
+use crate::ffi::*;
+use goscript_vm::value::*;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+
+/// Store is the interface that must be implemented by a key-value store.
+pub trait Store {
+    /// get returns the value of the key, or None if it doesn't exist.
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, String>;
+
+    fn set(&self, key: &str, value: &[u8]) -> Result<(), String>;
+
+    /// delete removes the key, it is not an error if the key doesn't exist.
+    fn delete(&self, key: &str) -> Result<(), String>;
+
+    /// list returns the keys starting with prefix in ascending order.
+    fn list(&self, prefix: &str) -> Result<Vec<String>, String>;
+}
+
+/// MemStore is the default Store, it keeps everything in memory.
+#[derive(Default)]
+pub struct MemStore {
+    data: RefCell<BTreeMap<String, Vec<u8>>>,
+}
+
+impl Store for MemStore {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, String> {
+        Ok(self.data.borrow().get(key).cloned())
+    }
+
+    fn set(&self, key: &str, value: &[u8]) -> Result<(), String> {
+        self.data
+            .borrow_mut()
+            .insert(key.to_owned(), value.to_vec());
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> Result<(), String> {
+        self.data.borrow_mut().remove(key);
+        Ok(())
+    }
+
+    fn list(&self, prefix: &str) -> Result<Vec<String>, String> {
+        Ok(self
+            .data
+            .borrow()
+            .range(prefix.to_owned()..)
+            .map(|(k, _)| k)
+            .take_while(|k| k.starts_with(prefix))
+            .cloned()
+            .collect())
+    }
+}
+
+#[derive(Ffi)]
+pub struct KvFfi {}
+
+#[ffi_impl(rename = "kv")]
+impl KvFfi {
+    fn ffi_get(&self, ctx: &FfiCallCtx, args: Vec<GosValue>) -> Vec<GosValue> {
+        let key = StrUtil::as_str(args[0].as_string());
+        let result = KvFfi::store(ctx).get(&key);
+        let (val, ok) = match &result {
+            Ok(Some(v)) => (bytes_to_slice(v.clone()), true),
+            _ => (GosValue::new_slice_nil(ValueType::Uint8), false),
+        };
+        vec![val, GosValue::new_bool(ok), ctx.result_to_error(&result)]
+    }
+
+    fn ffi_set(&self, ctx: &FfiCallCtx, key: &str, value: &[u8]) -> GoError {
+        GoError::from_result(&KvFfi::store(ctx).set(key, value))
+    }
+
+    fn ffi_delete(&self, ctx: &FfiCallCtx, key: &str) -> GoError {
+        GoError::from_result(&KvFfi::store(ctx).delete(key))
+    }
+
+    fn ffi_list(&self, ctx: &FfiCallCtx, prefix: &str) -> (Vec<String>, GoError) {
+        let result = KvFfi::store(ctx).list(prefix);
+        let err = GoError::from_result(&result);
+        (result.unwrap_or_default(), err)
+    }
+
+    /// Returns the store set by the embedder, or a MemStore if there isn't one.
+    /// The borrow of the statics is released before the store is used.
+    fn store(ctx: &FfiCallCtx) -> Rc<dyn Store> {
+        Statics::downcast_borrow_data_mut(ctx.statics)
+            .kv_store
+            .get_or_insert_with(|| Rc::new(MemStore::default()))
+            .clone()
+    }
+}
+
+pub(crate) fn bytes_to_slice(data: Vec<u8>) -> GosValue {
+    let buf: Vec<Elem8> = unsafe { std::mem::transmute(data) };
+    let arr = GosValue::new_non_gc_array(ArrayObj::with_raw_data(buf), ValueType::Uint8);
+    GosValue::slice_array(arr, 0, -1, ValueType::Uint8).unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::engine::Engine;
+    use goscript_codegen::FsReader;
+
+    #[test]
+    fn test_mem_store() {
+        let store = MemStore::default();
+        store.set("a/1", b"x").unwrap();
+        store.set("a/2", b"y").unwrap();
+        store.set("b", b"z").unwrap();
+        assert_eq!(store.list("a/").unwrap(), vec!["a/1", "a/2"]);
+        assert_eq!(store.list("").unwrap().len(), 3);
+        store.delete("a/1").unwrap();
+        store.delete("nosuchkey").unwrap();
+        assert_eq!(store.get("a/1").unwrap(), None);
+        assert_eq!(store.get("b").unwrap(), Some(b"z".to_vec()));
+    }
+
+    #[test]
+    fn test_kv_store() {
+        let source = r#"
+    package main
+    import "kv"
+
+    func main() {
+        _, err := kv.Get("missing")
+        assert(err == kv.ErrNotFound)
+
+        assert(kv.Set("user/1", []byte("alice")) == nil)
+        assert(kv.Set("user/2", []byte("bob")) == nil)
+        assert(kv.Set("config", []byte{}) == nil)
+        v, err := kv.Get("user/1")
+        assert(err == nil && string(v) == "alice")
+        v, err = kv.Get("config")
+        assert(err == nil && len(v) == 0)
+
+        keys, err := kv.List("user/")
+        assert(err == nil && len(keys) == 2)
+        assert(keys[0] == "user/1" && keys[1] == "user/2")
+
+        assert(kv.Delete("user/1") == nil)
+        _, err = kv.Get("user/1")
+        assert(err == kv.ErrNotFound)
+        keys, _ = kv.List("")
+        assert(len(keys) == 2)
+
+        kv.Set("done", []byte("yes"))
+    }
+    "#;
+        let engine = Engine::new();
+        let store = Rc::new(MemStore::default());
+        engine.set_kv_store(store.clone());
+        let reader = FsReader::new(Some("./"), Some("../std/"), Some(source));
+        let result = engine.run(false, false, &reader, FsReader::temp_file_path());
+        assert!(result.is_ok());
+        // the last write shows the script ran to the end
+        assert_eq!(store.get("done").unwrap(), Some(b"yes".to_vec()));
+        assert_eq!(store.get("user/2").unwrap(), Some(b"bob".to_vec()));
+    }
+
+    struct BrokenStore;
+
+    impl Store for BrokenStore {
+        fn get(&self, _: &str) -> Result<Option<Vec<u8>>, String> {
+            Err("disk on fire".to_owned())
+        }
+
+        fn set(&self, _: &str, _: &[u8]) -> Result<(), String> {
+            Err("read-only".to_owned())
+        }
+
+        fn delete(&self, _: &str) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn list(&self, _: &str) -> Result<Vec<String>, String> {
+            Err("disk on fire".to_owned())
+        }
+    }
+
+    #[test]
+    fn test_kv_store_errors() {
+        let source = r#"
+    package main
+    import (
+        "errors"
+        "fmt"
+        "kv"
+    )
+
+    type temporary interface {
+        Temporary() bool
+    }
+
+    func main() {
+        err := kv.Set("a", []byte("x"))
+        assert(err != nil && err.Error() == "read-only")
+        assert(fmt.Sprint(err) == "read-only")
+        assert(errors.Is(err, err) && !errors.Is(err, kv.ErrNotFound))
+        var e error
+        assert(errors.As(err, &e) && e == err)
+        _, ok := err.(temporary)
+        assert(!ok)
+
+        wrapped := fmt.Errorf("set a: %w", err)
+        assert(wrapped.Error() == "set a: read-only")
+        assert(errors.Is(wrapped, err) && errors.Unwrap(wrapped) == err)
+
+        _, err = kv.Get("a")
+        assert(err.Error() == "disk on fire")
+        keys, err := kv.List("")
+        assert(keys == nil && err.Error() == "disk on fire")
+        assert(kv.Delete("a") == nil)
+    }
+    "#;
+        let engine = Engine::new();
+        engine.set_kv_store(Rc::new(BrokenStore));
+        let reader = FsReader::new(Some("./"), Some("../std/"), Some(source));
+        let result = engine.run(false, false, &reader, FsReader::temp_file_path());
+        assert!(result.is_ok());
+    }
+}
@@ -0,0 +1,139 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+extern crate self as goscript_engine;
+use crate::ffi::*;
+use goscript_vm::value::GosValue;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+
+/// The math functions that have an f64 method in Rust, which gives the
+/// results Go specifies, special cases included, faster than interpreting
+/// the Go implementations.
+#[derive(Ffi)]
+pub struct MathFfi {}
+
+#[ffi_impl]
+impl MathFfi {
+    fn ffi_sqrt(&self, x: f64) -> f64 {
+        x.sqrt()
+    }
+
+    fn ffi_floor(&self, x: f64) -> f64 {
+        x.floor()
+    }
+
+    fn ffi_ceil(&self, x: f64) -> f64 {
+        x.ceil()
+    }
+
+    fn ffi_trunc(&self, x: f64) -> f64 {
+        x.trunc()
+    }
+
+    fn ffi_round(&self, x: f64) -> f64 {
+        x.round()
+    }
+
+    fn ffi_abs(&self, x: f64) -> f64 {
+        x.abs()
+    }
+
+    fn ffi_log(&self, x: f64) -> f64 {
+        x.ln()
+    }
+
+    fn ffi_log10(&self, x: f64) -> f64 {
+        x.log10()
+    }
+
+    fn ffi_log2(&self, x: f64) -> f64 {
+        x.log2()
+    }
+
+    fn ffi_log1p(&self, x: f64) -> f64 {
+        x.ln_1p()
+    }
+
+    fn ffi_exp(&self, x: f64) -> f64 {
+        x.exp()
+    }
+
+    fn ffi_exp2(&self, x: f64) -> f64 {
+        x.exp2()
+    }
+
+    fn ffi_expm1(&self, x: f64) -> f64 {
+        x.exp_m1()
+    }
+
+    fn ffi_sin(&self, x: f64) -> f64 {
+        x.sin()
+    }
+
+    fn ffi_cos(&self, x: f64) -> f64 {
+        x.cos()
+    }
+
+    fn ffi_tan(&self, x: f64) -> f64 {
+        x.tan()
+    }
+
+    fn ffi_asin(&self, x: f64) -> f64 {
+        x.asin()
+    }
+
+    fn ffi_acos(&self, x: f64) -> f64 {
+        x.acos()
+    }
+
+    fn ffi_atan(&self, x: f64) -> f64 {
+        x.atan()
+    }
+
+    fn ffi_sinh(&self, x: f64) -> f64 {
+        x.sinh()
+    }
+
+    fn ffi_cosh(&self, x: f64) -> f64 {
+        x.cosh()
+    }
+
+    fn ffi_tanh(&self, x: f64) -> f64 {
+        x.tanh()
+    }
+
+    fn ffi_asinh(&self, x: f64) -> f64 {
+        x.asinh()
+    }
+
+    fn ffi_acosh(&self, x: f64) -> f64 {
+        x.acosh()
+    }
+
+    fn ffi_atanh(&self, x: f64) -> f64 {
+        x.atanh()
+    }
+
+    fn ffi_cbrt(&self, x: f64) -> f64 {
+        x.cbrt()
+    }
+
+    fn ffi_pow(&self, x: f64, y: f64) -> f64 {
+        x.powf(y)
+    }
+
+    fn ffi_atan2(&self, x: f64, y: f64) -> f64 {
+        x.atan2(y)
+    }
+
+    fn ffi_hypot(&self, x: f64, y: f64) -> f64 {
+        x.hypot(y)
+    }
+
+    fn ffi_mod(&self, x: f64, y: f64) -> f64 {
+        x % y
+    }
+}
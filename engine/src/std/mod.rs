@@ -3,18 +3,347 @@
 // license that can be found in the LICENSE file.
 
 mod bits;
+mod debug;
+mod errors;
+mod fmt;
 mod fmt2;
+pub mod host;
+#[cfg(all(feature = "net", unix))]
+mod http;
 mod io;
+pub(crate) mod json;
+pub mod kv;
+mod math;
+#[cfg(all(feature = "net", unix))]
+mod net;
 mod os;
+mod rand;
+mod ratelimit;
+#[cfg(all(feature = "net", unix))]
+mod reactor;
 mod reflect;
+mod sort;
+pub mod sql;
+mod strings;
 mod sync;
+mod time;
+mod utf8;
+#[cfg(all(feature = "net", unix))]
+mod websocket;
 
-pub(crate) fn register(engine: &mut crate::engine::Engine) {
-    fmt2::Fmt2Ffi::register(engine);
-    bits::BitsFfi::register(engine);
-    sync::MutexFfi::register(engine);
-    sync::RWMutexFfi::register(engine);
-    reflect::ReflectFfi::register(engine);
-    io::IoFfi::register(engine);
-    os::FileFfi::register(engine);
+use std::collections::BTreeSet;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::io::{Error, ErrorKind, Result as IoResult};
+
+/// A group of std packages that are backed by FFI. Leaving one out of the
+/// `StdSelection` of an engine takes the capability away from the scripts:
+/// programs importing its packages fail the FFI check before they run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum StdModule {
+    /// fmt, errors, math, math/rand, reflect, sort, strings, sync,
+    /// unicode/utf8, encoding/json, runtime/debug, and the standard streams
+    /// and arguments of os. With the `crypto_rand` feature, crypto/rand too.
+    /// The other modules need it.
+    Core,
+    /// Opening files with os, without it os.Open and os.OpenFile fail with
+    /// a permission error. Needs the `fs` feature.
+    Fs,
+    /// net, net/http and websocket. Needs the `net` feature.
+    Net,
+    /// time, which reads the clocks and sleeps
+    Time,
+    /// ratelimit
+    RateLimit,
+    /// sql, with the drivers registered by the embedder
+    Sql,
+    /// kv
+    Kv,
+    /// host, the event bus shared with the embedder
+    Host,
+}
+
+impl StdModule {
+    pub const ALL: [StdModule; 8] = [
+        StdModule::Core,
+        StdModule::Fs,
+        StdModule::Net,
+        StdModule::Time,
+        StdModule::RateLimit,
+        StdModule::Sql,
+        StdModule::Kv,
+        StdModule::Host,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            StdModule::Core => "core",
+            StdModule::Fs => "fs",
+            StdModule::Net => "net",
+            StdModule::Time => "time",
+            StdModule::RateLimit => "ratelimit",
+            StdModule::Sql => "sql",
+            StdModule::Kv => "kv",
+            StdModule::Host => "host",
+        }
+    }
+
+    /// Whether the module is built, the ones needing the file system or the
+    /// network are left out of builds without the `fs` or `net` feature, and
+    /// the network one of builds for targets other than unix
+    pub fn is_available(&self) -> bool {
+        match self {
+            StdModule::Fs => cfg!(feature = "fs"),
+            StdModule::Net => cfg!(all(feature = "net", unix)),
+            _ => true,
+        }
+    }
+}
+
+impl Display for StdModule {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        f.write_str(self.name())
+    }
+}
+
+/// The std modules an engine registers, see `Engine::with_std`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StdSelection {
+    modules: BTreeSet<StdModule>,
+}
+
+impl StdSelection {
+    /// Every std module that is built, what `Engine::new` registers
+    pub fn all() -> StdSelection {
+        StdSelection {
+            modules: StdModule::ALL
+                .into_iter()
+                .filter(|m| m.is_available())
+                .collect(),
+        }
+    }
+
+    /// Only the core module: no files, no network, no clocks and nothing
+    /// shared with the embedder
+    pub fn core() -> StdSelection {
+        StdSelection::none().with(StdModule::Core)
+    }
+
+    /// No std module, scripts can only use the extensions registered by the
+    /// embedder and the packages written purely in Go
+    pub fn none() -> StdSelection {
+        StdSelection {
+            modules: BTreeSet::new(),
+        }
+    }
+
+    pub fn with(mut self, module: StdModule) -> StdSelection {
+        self.modules.insert(module);
+        self
+    }
+
+    pub fn without(mut self, module: StdModule) -> StdSelection {
+        self.modules.remove(&module);
+        self
+    }
+
+    pub fn contains(&self, module: StdModule) -> bool {
+        self.modules.contains(&module)
+    }
+
+    /// The selected modules in the order of `StdModule::ALL`
+    pub fn modules(&self) -> impl Iterator<Item = StdModule> + '_ {
+        self.modules.iter().copied()
+    }
+
+    /// Checks that the selected modules are built, and that the modules they
+    /// need are selected too
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(m) = self.modules().find(|m| !m.is_available()) {
+            return Err(format!(
+                "std module {} is not built, it needs the {} feature",
+                m, m
+            ));
+        }
+        match self.modules().find(|m| *m != StdModule::Core) {
+            Some(m) if !self.contains(StdModule::Core) => {
+                Err(format!("std module {} needs the core module", m))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Default for StdSelection {
+    fn default() -> StdSelection {
+        StdSelection::all()
+    }
+}
+
+impl Display for StdSelection {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        let names: Vec<&str> = self.modules().map(|m| m.name()).collect();
+        f.write_str(&names.join(", "))
+    }
+}
+
+/// Something scripts may do outside of the engine. Unlike a module left out
+/// of the `StdSelection`, a capability that is not granted doesn't stop a
+/// program from loading: the calls needing it fail with an error matching
+/// os.ErrPermission, which the script can handle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Capability {
+    /// Opening files for reading
+    FsRead,
+    /// Opening files for writing, creating or truncating them
+    FsWrite,
+    /// Dialing and listening with net, net/http and websocket
+    Net,
+    /// Reading and changing the environment variables with os
+    Env,
+}
+
+impl Capability {
+    pub const ALL: [Capability; 4] = [
+        Capability::FsRead,
+        Capability::FsWrite,
+        Capability::Net,
+        Capability::Env,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Capability::FsRead => "fs_read",
+            Capability::FsWrite => "fs_write",
+            Capability::Net => "net",
+            Capability::Env => "env",
+        }
+    }
+
+    /// The error of the calls denied for the lack of the capability
+    pub(crate) fn denied(&self) -> Error {
+        let what = match self {
+            Capability::FsRead => "reading files",
+            Capability::FsWrite => "writing files",
+            Capability::Net => "network access",
+            Capability::Env => "environment access",
+        };
+        Error::new(
+            ErrorKind::PermissionDenied,
+            format!("permission denied: {} is not allowed", what),
+        )
+    }
+}
+
+impl Display for Capability {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        f.write_str(self.name())
+    }
+}
+
+/// The capabilities granted to the scripts of an engine, see
+/// `Engine::set_capabilities`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capabilities {
+    granted: BTreeSet<Capability>,
+}
+
+impl Capabilities {
+    /// Every capability, what an engine grants if not told otherwise
+    pub fn all() -> Capabilities {
+        Capabilities {
+            granted: Capability::ALL.into_iter().collect(),
+        }
+    }
+
+    /// No capability, the scripts can only compute and talk to the embedder
+    pub fn none() -> Capabilities {
+        Capabilities {
+            granted: BTreeSet::new(),
+        }
+    }
+
+    pub fn with(mut self, cap: Capability) -> Capabilities {
+        self.granted.insert(cap);
+        self
+    }
+
+    pub fn without(mut self, cap: Capability) -> Capabilities {
+        self.granted.remove(&cap);
+        self
+    }
+
+    pub fn contains(&self, cap: Capability) -> bool {
+        self.granted.contains(&cap)
+    }
+
+    /// Returns the error of the calls needing `cap` if it is not granted
+    pub fn check(&self, cap: Capability) -> IoResult<()> {
+        match self.contains(cap) {
+            true => Ok(()),
+            false => Err(cap.denied()),
+        }
+    }
+
+    /// The granted capabilities in the order of `Capability::ALL`
+    pub fn iter(&self) -> impl Iterator<Item = Capability> + '_ {
+        self.granted.iter().copied()
+    }
+}
+
+impl Default for Capabilities {
+    fn default() -> Capabilities {
+        Capabilities::all()
+    }
+}
+
+impl Display for Capabilities {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        let names: Vec<&str> = self.iter().map(|c| c.name()).collect();
+        f.write_str(&names.join(", "))
+    }
+}
+
+pub(crate) fn register(engine: &mut crate::engine::Engine, selection: &StdSelection) {
+    if selection.contains(StdModule::Core) {
+        fmt::FmtFfi::register(engine);
+        fmt2::Fmt2Ffi::register(engine);
+        bits::BitsFfi::register(engine);
+        debug::DebugFfi::register(engine);
+        math::MathFfi::register(engine);
+        rand::RandFfi::register(engine);
+        #[cfg(feature = "crypto_rand")]
+        rand::CryptoRandFfi::register(engine);
+        errors::ErrorsFfi::register(engine);
+        sort::SortFfi::register(engine);
+        strings::ReplacerFfi::register(engine);
+        utf8::Utf8Ffi::register(engine);
+        sync::MutexFfi::register(engine);
+        sync::RWMutexFfi::register(engine);
+        reflect::ReflectFfi::register(engine);
+        io::IoFfi::register(engine);
+        json::JsonFfi::register(engine);
+        os::FileFfi::register(engine);
+        os::ProcFfi::register(engine);
+    }
+    #[cfg(all(feature = "net", unix))]
+    if selection.contains(StdModule::Net) {
+        net::NetFfi::register(engine);
+        http::HttpFfi::register(engine);
+        websocket::WebSocketFfi::register(engine);
+    }
+    if selection.contains(StdModule::Sql) {
+        sql::SqlFfi::register(engine);
+    }
+    if selection.contains(StdModule::Kv) {
+        kv::KvFfi::register(engine);
+    }
+    if selection.contains(StdModule::Host) {
+        host::HostFfi::register(engine);
+    }
+    if selection.contains(StdModule::Time) {
+        time::TimeFfi::register(engine);
+    }
+    if selection.contains(StdModule::RateLimit) {
+        ratelimit::RateLimitFfi::register(engine);
+    }
 }
@@ -0,0 +1,486 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! TCP sockets for the net package. The sockets are non-blocking, a
+//! goroutine waiting on one is parked until the reactor finds it ready, so
+//! the other goroutines keep running. Resolving and connecting can't be done
+//! without blocking, so dialing happens on a thread of its own.
+
+extern crate self as goscript_engine;
+use super::reactor::{self, Interest};
+use super::Capability;
+use crate::engine::Statics;
+use crate::ffi::*;
+use goscript_vm::timer::IoWaits;
+use goscript_vm::value::*;
+use std::any::Any;
+use std::cell::RefCell;
+use std::future::Future;
+use std::io;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::pin::Pin;
+use std::rc::Rc;
+
+const ERR_CLOSED: &str = "use of closed network connection";
+
+#[derive(Ffi)]
+pub struct NetFfi {}
+
+#[ffi_impl(rename = "net")]
+impl NetFfi {
+    fn ffi_dial(
+        &self,
+        ctx: &mut FfiCallCtx,
+        args: Vec<GosValue>,
+    ) -> Pin<Box<dyn Future<Output = RuntimeResult<Vec<GosValue>>> + '_>> {
+        let allowed = check_net(ctx);
+        let io = ctx.timers.io().clone();
+        Box::pin(async move {
+            let network = StrUtil::as_str(args[0].as_string()).to_string();
+            let address = StrUtil::as_str(args[1].as_string()).to_string();
+            let r = match allowed {
+                Ok(()) => TcpConn::dial(&io, network, address).await,
+                Err(e) => Err(e),
+            };
+            Ok(NetFfi::result_to_go(r, |opt| match opt {
+                Some(c) => vec![GosValue::new_unsafe_ptr(c)],
+                None => vec![GosValue::new_nil(ValueType::UnsafePtr)],
+            }))
+        })
+    }
+
+    fn ffi_listen(&self, ctx: &FfiCallCtx, args: Vec<GosValue>) -> Vec<GosValue> {
+        let network = StrUtil::as_str(args[0].as_string());
+        let address = StrUtil::as_str(args[1].as_string());
+        let r = check_net(ctx)
+            .and_then(|_| TcpListenerObj::listen(ctx.timers.io(), &network, &address));
+        NetFfi::result_to_go(r, |opt| match opt {
+            Some(l) => vec![GosValue::new_unsafe_ptr(l)],
+            None => vec![GosValue::new_nil(ValueType::UnsafePtr)],
+        })
+    }
+
+    async fn ffi_accept(&self, args: Vec<GosValue>) -> RuntimeResult<Vec<GosValue>> {
+        let l = args[0]
+            .as_some_unsafe_ptr()?
+            .downcast_ref::<TcpListenerObj>()?;
+        let r = l.accept().await;
+        Ok(NetFfi::result_to_go(r, |opt| match opt {
+            Some(c) => vec![GosValue::new_unsafe_ptr(c)],
+            None => vec![GosValue::new_nil(ValueType::UnsafePtr)],
+        }))
+    }
+
+    async fn ffi_read(&self, args: Vec<GosValue>) -> RuntimeResult<Vec<GosValue>> {
+        let c = args[0].as_some_unsafe_ptr()?.downcast_ref::<TcpConn>()?;
+        let slice = &args[1].as_some_slice::<Elem8>()?.0;
+        // other goroutines may use the slice while this one waits
+        let mut buf = vec![0; slice.len()];
+        let r = c.read(&mut buf).await;
+        if let Ok(n) = r {
+            let mut dst = unsafe { slice.as_raw_slice_mut::<u8>() };
+            dst[..n].copy_from_slice(&buf[..n]);
+        }
+        Ok(NetFfi::result_to_go(r, |opt| {
+            vec![GosValue::new_int(opt.unwrap_or(0) as isize)]
+        }))
+    }
+
+    async fn ffi_write(&self, args: Vec<GosValue>) -> RuntimeResult<Vec<GosValue>> {
+        let c = args[0].as_some_unsafe_ptr()?.downcast_ref::<TcpConn>()?;
+        let data = match args[1].as_slice::<Elem8>() {
+            Some(s) => unsafe { s.0.as_raw_slice::<u8>() }.to_vec(),
+            None => vec![],
+        };
+        let r = c.write_all(&data).await;
+        Ok(NetFfi::result_to_go(r, |opt| {
+            vec![GosValue::new_int(opt.unwrap_or(0) as isize)]
+        }))
+    }
+
+    fn ffi_close(&self, args: Vec<GosValue>) -> RuntimeResult<GosValue> {
+        let c = args[0].as_some_unsafe_ptr()?.downcast_ref::<TcpConn>()?;
+        Ok(NetFfi::err_to_go(c.close()))
+    }
+
+    fn ffi_close_listener(&self, args: Vec<GosValue>) -> RuntimeResult<GosValue> {
+        let l = args[0]
+            .as_some_unsafe_ptr()?
+            .downcast_ref::<TcpListenerObj>()?;
+        Ok(NetFfi::err_to_go(l.close()))
+    }
+
+    fn ffi_addrs(&self, args: Vec<GosValue>) -> RuntimeResult<Vec<GosValue>> {
+        let c = args[0].as_some_unsafe_ptr()?.downcast_ref::<TcpConn>()?;
+        Ok(vec![
+            GosValue::with_str(&c.local.to_string()),
+            GosValue::with_str(&c.remote.to_string()),
+        ])
+    }
+
+    fn ffi_listener_addr(&self, args: Vec<GosValue>) -> RuntimeResult<GosValue> {
+        let l = args[0]
+            .as_some_unsafe_ptr()?
+            .downcast_ref::<TcpListenerObj>()?;
+        Ok(GosValue::with_str(&l.addr.to_string()))
+    }
+
+    fn result_to_go<T, F>(result: io::Result<T>, f: F) -> Vec<GosValue>
+    where
+        F: Fn(Option<T>) -> Vec<GosValue>,
+    {
+        let (mut vals, msg) = match result {
+            Ok(v) => (f(Some(v)), "".to_owned()),
+            Err(e) => (f(None), e.to_string()),
+        };
+        vals.push(GosValue::with_str(&msg));
+        vals
+    }
+
+    fn err_to_go(result: io::Result<()>) -> GosValue {
+        match result {
+            Ok(()) => GosValue::with_str(""),
+            Err(e) => GosValue::with_str(&e.to_string()),
+        }
+    }
+}
+
+/// Returns the error of dialing and listening if the engine doesn't grant
+/// the network capability
+pub(crate) fn check_net(ctx: &FfiCallCtx) -> io::Result<()> {
+    Statics::downcast_borrow_data(ctx.statics)
+        .capabilities
+        .check(Capability::Net)
+}
+
+#[derive(UnsafePtr)]
+pub struct TcpConn {
+    /// taken by close, which releases the socket right away
+    stream: RefCell<Option<TcpStream>>,
+    fd: RawFd,
+    io: IoWaits,
+    local: SocketAddr,
+    remote: SocketAddr,
+}
+
+impl TcpConn {
+    fn new(io: &IoWaits, stream: TcpStream) -> io::Result<TcpConn> {
+        stream.set_nonblocking(true)?;
+        Ok(TcpConn {
+            fd: stream.as_raw_fd(),
+            io: io.clone(),
+            local: stream.local_addr()?,
+            remote: stream.peer_addr()?,
+            stream: RefCell::new(Some(stream)),
+        })
+    }
+
+    pub(super) async fn dial(
+        io: &IoWaits,
+        network: String,
+        address: String,
+    ) -> io::Result<TcpConn> {
+        let stream = reactor::unblock(io, move || {
+            let addrs = resolve(&network, &address, "127.0.0.1", "[::1]")?;
+            let mut last = io::Error::new(io::ErrorKind::InvalidInput, "no suitable address");
+            for addr in addrs {
+                match TcpStream::connect(addr) {
+                    Ok(s) => return Ok(s),
+                    Err(e) => last = e,
+                }
+            }
+            Err(last)
+        })
+        .await??;
+        TcpConn::new(io, stream)
+    }
+
+    pub(super) async fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let r = match &*self.stream.borrow() {
+                Some(s) => (&*s).read(buf),
+                None => return Err(closed_err()),
+            };
+            match r {
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    reactor::ready(&self.io, self.fd, Interest::Read).await?
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                r => return r,
+            }
+        }
+    }
+
+    pub(super) async fn write_all(&self, buf: &[u8]) -> io::Result<usize> {
+        let mut written = 0;
+        while written < buf.len() {
+            let r = match &*self.stream.borrow() {
+                Some(s) => (&*s).write(&buf[written..]),
+                None => return Err(closed_err()),
+            };
+            match r {
+                Ok(0) => return Err(io::Error::from(io::ErrorKind::WriteZero)),
+                Ok(n) => written += n,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    reactor::ready(&self.io, self.fd, Interest::Write).await?
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(written)
+    }
+
+    pub(super) fn close(&self) -> io::Result<()> {
+        let stream = self.stream.borrow_mut().take();
+        reactor::wake_all(self.fd);
+        match stream {
+            Some(s) => s
+                .shutdown(std::net::Shutdown::Both)
+                .or_else(|e| match e.kind() {
+                    io::ErrorKind::NotConnected => Ok(()),
+                    _ => Err(e),
+                }),
+            None => Err(closed_err()),
+        }
+    }
+}
+
+#[derive(UnsafePtr)]
+pub struct TcpListenerObj {
+    listener: RefCell<Option<TcpListener>>,
+    fd: RawFd,
+    io: IoWaits,
+    addr: SocketAddr,
+}
+
+impl TcpListenerObj {
+    fn listen(io: &IoWaits, network: &str, address: &str) -> io::Result<TcpListenerObj> {
+        let mut last = io::Error::new(io::ErrorKind::InvalidInput, "no suitable address");
+        for addr in resolve(network, address, "0.0.0.0", "[::]")? {
+            match TcpListener::bind(addr) {
+                Ok(l) => {
+                    l.set_nonblocking(true)?;
+                    return Ok(TcpListenerObj {
+                        addr: l.local_addr()?,
+                        fd: l.as_raw_fd(),
+                        io: io.clone(),
+                        listener: RefCell::new(Some(l)),
+                    });
+                }
+                Err(e) => last = e,
+            }
+        }
+        Err(last)
+    }
+
+    async fn accept(&self) -> io::Result<TcpConn> {
+        loop {
+            let r = match &*self.listener.borrow() {
+                Some(l) => l.accept(),
+                None => return Err(closed_err()),
+            };
+            match r {
+                Ok((s, _)) => return TcpConn::new(&self.io, s),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    reactor::ready(&self.io, self.fd, Interest::Read).await?
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn close(&self) -> io::Result<()> {
+        let listener = self.listener.borrow_mut().take();
+        reactor::wake_all(self.fd);
+        match listener {
+            Some(_) => Ok(()),
+            None => Err(closed_err()),
+        }
+    }
+}
+
+fn closed_err() -> io::Error {
+    io::Error::new(io::ErrorKind::NotConnected, ERR_CLOSED)
+}
+
+/// Resolves `address` to the addresses of `network`, "tcp", "tcp4" or
+/// "tcp6". Without a host, like ":80", the host is `v4_host` or `v6_host`.
+fn resolve(
+    network: &str,
+    address: &str,
+    v4_host: &str,
+    v6_host: &str,
+) -> io::Result<Vec<SocketAddr>> {
+    let address = match address.strip_prefix(':') {
+        Some(port) if network == "tcp6" => format!("{}:{}", v6_host, port),
+        Some(port) => format!("{}:{}", v4_host, port),
+        None => address.to_owned(),
+    };
+    let addrs: Vec<SocketAddr> = address
+        .to_socket_addrs()?
+        .filter(|a| match network {
+            "tcp4" => a.is_ipv4(),
+            "tcp6" => a.is_ipv6(),
+            _ => true,
+        })
+        .collect();
+    Ok(addrs)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_resolve() {
+        let addrs = resolve("tcp", ":8080", "0.0.0.0", "[::]").unwrap();
+        assert_eq!(addrs, vec!["0.0.0.0:8080".parse().unwrap()]);
+        let addrs = resolve("tcp6", ":1", "0.0.0.0", "[::]").unwrap();
+        assert_eq!(addrs, vec!["[::]:1".parse().unwrap()]);
+        assert!(resolve("tcp6", "127.0.0.1:1", "", "").unwrap().is_empty());
+        assert!(resolve("tcp", "no port", "", "").is_err());
+    }
+
+    #[test]
+    fn test_tcp_echo() {
+        let source = r#"
+    package main
+
+    import (
+        "bufio"
+        "errors"
+        "io"
+        "net"
+        "strings"
+    )
+
+    // serve answers each line with its upper case, until the client closes
+    func serve(l net.Listener, done chan bool) {
+        c, err := l.Accept()
+        assert(err == nil)
+        r := bufio.NewReader(c)
+        for {
+            line, err := r.ReadString('\n')
+            if err == io.EOF {
+                break
+            }
+            assert(err == nil)
+            c.Write([]byte(strings.ToUpper(line)))
+        }
+        assert(c.Close() == nil)
+        done <- true
+    }
+
+    func main() {
+        l, err := net.Listen("tcp", "127.0.0.1:0")
+        assert(err == nil)
+        addr := l.Addr().String()
+        assert(strings.HasPrefix(addr, "127.0.0.1:") && addr != "127.0.0.1:0")
+        assert(l.Addr().Network() == "tcp")
+        done := make(chan bool)
+        go serve(l, done)
+
+        c, err := net.Dial("tcp", addr)
+        assert(err == nil)
+        assert(c.RemoteAddr().String() == addr)
+        r := bufio.NewReader(c)
+        for _, s := range []string{"hello\n", "goscript\n"} {
+            n, err := c.Write([]byte(s))
+            assert(n == len(s) && err == nil)
+            line, err := r.ReadString('\n')
+            assert(err == nil && line == strings.ToUpper(s))
+        }
+        assert(c.Close() == nil)
+        <-done
+        _, err = c.Write([]byte("x"))
+        assert(errors.Is(err, net.ErrClosed))
+        assert(c.Close() != nil)
+
+        assert(l.Close() == nil)
+        _, err = l.Accept()
+        assert(errors.Is(err, net.ErrClosed))
+        _, err = net.Dial("tcp", addr)
+        assert(err != nil && strings.HasPrefix(err.Error(), "dial tcp " + addr + ": "))
+        _, err = net.Dial("udp", addr)
+        assert(err != nil && err.Error() == "dial udp: unknown network udp")
+    }
+    "#;
+        let mut cfg = crate::run_fs::Config::default();
+        cfg.working_dir = Some("./");
+        cfg.base_dir = Some("../std/");
+        let result = crate::run_fs::run_string(cfg, source);
+        assert_eq!(result.unwrap(), goscript_vm::vm::RunResult::Done);
+    }
+
+    /// The CPU time used by the thread
+    fn thread_cpu_time() -> std::time::Duration {
+        let mut ts = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        unsafe { libc::clock_gettime(libc::CLOCK_THREAD_CPUTIME_ID, &mut ts) };
+        std::time::Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32)
+    }
+
+    #[test]
+    fn test_parked_io() {
+        let l = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = l.local_addr().unwrap();
+        // a peer that takes its time to answer
+        let peer = std::thread::spawn(move || {
+            let (mut s, _) = l.accept().unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(1000));
+            s.write_all(b"late\n").unwrap();
+        });
+        let source = format!(
+            r#"
+    package main
+
+    import (
+        "bufio"
+        "errors"
+        "net"
+        "time"
+    )
+
+    func main() {{
+        c, err := net.Dial("tcp", "{}")
+        assert(err == nil)
+        line, err := bufio.NewReader(c).ReadString('\n')
+        assert(err == nil && line == "late\n")
+
+        // closing the listener wakes the goroutine blocked in Accept
+        l, err := net.Listen("tcp", "127.0.0.1:0")
+        assert(err == nil)
+        done := make(chan error)
+        go func() {{
+            _, err := l.Accept()
+            done <- err
+        }}()
+        time.Sleep(10 * time.Millisecond)
+        assert(l.Close() == nil)
+        assert(errors.Is(<-done, net.ErrClosed))
+    }}
+    "#,
+            addr
+        );
+        let engine = crate::Engine::new();
+        let reader = goscript_codegen::FsReader::new(Some("./"), Some("../std/"), Some(&source));
+        let image = engine
+            .compile_image(&reader, goscript_codegen::FsReader::temp_file_path(), None)
+            .unwrap();
+        let start = thread_cpu_time();
+        let result = engine.run_image(&image, None);
+        let used = thread_cpu_time() - start;
+        assert_eq!(result.unwrap(), goscript_vm::vm::RunResult::Done);
+        peer.join().unwrap();
+        // the VM thread sleeps while the goroutines wait on the sockets, so it
+        // burns well under the peer's delay even with a debug build's startup
+        assert!(used < std::time::Duration::from_millis(500), "{:?}", used);
+    }
+}
@@ -3,6 +3,7 @@
 // license that can be found in the LICENSE file.
 
 extern crate self as goscript_engine;
+use super::{Capability, StdModule};
 use crate::engine::Statics;
 use crate::ffi::*;
 use goscript_vm::value::*;
@@ -26,6 +27,78 @@ const O_CREATE: usize = 0x00040;
 const O_EXCL: usize = 0x00080;
 const O_TRUNC: usize = 0x00200;
 
+#[derive(Ffi)]
+pub struct ProcFfi {}
+
+#[ffi_impl(rename = "os.proc")]
+impl ProcFfi {
+    fn ffi_args(&self, ctx: &FfiCallCtx, _args: Vec<GosValue>) -> GosValue {
+        let args = Statics::downcast_borrow_data(ctx.statics)
+            .args
+            .iter()
+            .map(|a| GosValue::with_str(a))
+            .collect();
+        GosValue::slice_with_data(args, ValueType::String, ctx.gcv)
+    }
+
+    /// Without the Env capability the environment looks empty
+    fn ffi_lookup_env(&self, ctx: &FfiCallCtx, args: Vec<GosValue>) -> Vec<GosValue> {
+        let key = StrUtil::as_str(args[0].as_string());
+        let mut statics = Statics::downcast_borrow_data_mut(ctx.statics);
+        let found = match statics.capabilities.contains(Capability::Env) {
+            true => statics.env_mut().get(&*key),
+            false => None,
+        };
+        match found {
+            Some(v) => vec![GosValue::with_str(v), GosValue::new_bool(true)],
+            None => vec![GosValue::with_str(""), GosValue::new_bool(false)],
+        }
+    }
+
+    fn ffi_set_env(&self, ctx: &FfiCallCtx, args: Vec<GosValue>) -> Vec<GosValue> {
+        let key = StrUtil::as_str(args[0].as_string()).to_string();
+        let value = StrUtil::as_str(args[1].as_string()).to_string();
+        let mut statics = Statics::downcast_borrow_data_mut(ctx.statics);
+        let r = statics.capabilities.check(Capability::Env).map(|_| {
+            statics.env_mut().insert(key, value);
+        });
+        FileFfi::error_to_go(r)
+    }
+
+    fn ffi_unset_env(&self, ctx: &FfiCallCtx, args: Vec<GosValue>) -> Vec<GosValue> {
+        let key = StrUtil::as_str(args[0].as_string());
+        let mut statics = Statics::downcast_borrow_data_mut(ctx.statics);
+        let r = statics.capabilities.check(Capability::Env).map(|_| {
+            statics.env_mut().remove(&*key);
+        });
+        FileFfi::error_to_go(r)
+    }
+
+    fn ffi_clear_env(&self, ctx: &FfiCallCtx, _args: Vec<GosValue>) {
+        let mut statics = Statics::downcast_borrow_data_mut(ctx.statics);
+        if statics.capabilities.contains(Capability::Env) {
+            statics.env_mut().clear();
+        }
+    }
+
+    fn ffi_environ(&self, ctx: &FfiCallCtx, _args: Vec<GosValue>) -> GosValue {
+        let mut statics = Statics::downcast_borrow_data_mut(ctx.statics);
+        let vars = match statics.capabilities.contains(Capability::Env) {
+            true => statics
+                .env_mut()
+                .iter()
+                .map(|(k, v)| GosValue::with_str(&format!("{}={}", k, v)))
+                .collect(),
+            false => vec![],
+        };
+        GosValue::slice_with_data(vars, ValueType::String, ctx.gcv)
+    }
+
+    fn ffi_exit(&self, ctx: &FfiCallCtx, args: Vec<GosValue>) {
+        ctx.exit(*args[0].as_int() as i32);
+    }
+}
+
 #[derive(Ffi)]
 pub struct FileFfi {}
 
@@ -40,9 +113,39 @@ impl FileFfi {
         }
     }
 
-    fn ffi_open(&self, args: Vec<GosValue>) -> Vec<GosValue> {
+    fn ffi_open(&self, ctx: &FfiCallCtx, args: Vec<GosValue>) -> Vec<GosValue> {
         let path = StrUtil::as_str(args[0].as_string());
+        if !StdModule::Fs.is_available()
+            || !Statics::downcast_borrow_data(ctx.statics)
+                .std
+                .contains(StdModule::Fs)
+        {
+            let err = io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "file system access is not enabled",
+            );
+            return FileFfi::result_to_go(Err::<(), _>(err), |_| {
+                GosValue::new_nil(ValueType::UnsafePtr)
+            });
+        }
         let flags = *args[1].as_int() as usize;
+        let reads = flags & O_RDWR != O_WRONLY;
+        let writes = flags & (O_WRONLY | O_RDWR | O_APPEND | O_CREATE | O_TRUNC) != 0;
+        let allowed = {
+            let caps = &Statics::downcast_borrow_data(ctx.statics).capabilities;
+            match (reads, writes) {
+                (true, true) => caps
+                    .check(Capability::FsRead)
+                    .and(caps.check(Capability::FsWrite)),
+                (true, false) => caps.check(Capability::FsRead),
+                _ => caps.check(Capability::FsWrite),
+            }
+        };
+        if let Err(err) = allowed {
+            return FileFfi::result_to_go(Err::<(), _>(err), |_| {
+                GosValue::new_nil(ValueType::UnsafePtr)
+            });
+        }
         let mut options = fs::OpenOptions::new();
         match flags & O_RDWR {
             O_RDONLY => options.read(true),
@@ -51,7 +154,7 @@ impl FileFfi {
             _ => unreachable!(),
         };
         options.append((flags & O_APPEND) != 0);
-        options.append((flags & O_TRUNC) != 0);
+        options.truncate((flags & O_TRUNC) != 0);
         match (((flags & O_CREATE) != 0), ((flags & O_EXCL) != 0)) {
             (true, false) => options.create(true),
             (true, true) => options.create_new(true),
@@ -105,6 +208,17 @@ impl FileFfi {
         }))
     }
 
+    /// Returns the kind and the message of the error, empty if none
+    fn error_to_go(result: io::Result<()>) -> Vec<GosValue> {
+        match result {
+            Ok(()) => vec![GosValue::new_int(0), GosValue::with_str("")],
+            Err(e) => vec![
+                GosValue::new_int(e.kind() as isize),
+                GosValue::with_str(&e.to_string()),
+            ],
+        }
+    }
+
     fn result_to_go<T, F>(result: io::Result<T>, f: F) -> Vec<GosValue>
     where
         F: Fn(Option<T>) -> GosValue,
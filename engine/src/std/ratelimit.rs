@@ -0,0 +1,145 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! The backend of the `ratelimit` std package, token buckets and the
+//! randomization of retry delays.
+
+extern crate self as goscript_engine;
+use crate::ffi::*;
+use goscript_vm::clock::Instant;
+use goscript_vm::rng;
+use goscript_vm::value::*;
+use rand::Rng;
+use std::any::Any;
+use std::cell::Cell;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+
+#[derive(Ffi)]
+pub struct RateLimitFfi {}
+
+#[ffi_impl(rename = "ratelimit")]
+impl RateLimitFfi {
+    fn ffi_new_limiter(&self, args: Vec<GosValue>) -> GosValue {
+        let rate = args[0].as_float64().into_inner();
+        let burst = *args[1].as_int() as f64;
+        GosValue::new_unsafe_ptr(TokenBucket {
+            rate: Cell::new(rate),
+            burst: Cell::new(burst),
+            tokens: Cell::new(burst),
+            last: Cell::new(Instant::now()),
+        })
+    }
+
+    fn ffi_set_limit(&self, args: Vec<GosValue>) -> RuntimeResult<()> {
+        let tb = args[0]
+            .as_some_unsafe_ptr()?
+            .downcast_ref::<TokenBucket>()?;
+        tb.advance();
+        tb.rate.set(args[1].as_float64().into_inner());
+        Ok(())
+    }
+
+    fn ffi_set_burst(&self, args: Vec<GosValue>) -> RuntimeResult<()> {
+        let tb = args[0]
+            .as_some_unsafe_ptr()?
+            .downcast_ref::<TokenBucket>()?;
+        tb.advance();
+        let burst = *args[1].as_int() as f64;
+        tb.burst.set(burst);
+        tb.tokens.set(tb.tokens.get().min(burst));
+        Ok(())
+    }
+
+    fn ffi_tokens(&self, args: Vec<GosValue>) -> RuntimeResult<GosValue> {
+        let tb = args[0]
+            .as_some_unsafe_ptr()?
+            .downcast_ref::<TokenBucket>()?;
+        tb.advance();
+        Ok(GosValue::new_float64(tb.tokens.get().into()))
+    }
+
+    fn ffi_allow(&self, args: Vec<GosValue>) -> RuntimeResult<GosValue> {
+        let tb = args[0]
+            .as_some_unsafe_ptr()?
+            .downcast_ref::<TokenBucket>()?;
+        let n = *args[1].as_int() as f64;
+        tb.advance();
+        let ok = tb.tokens.get() >= n;
+        if ok {
+            tb.tokens.set(tb.tokens.get() - n);
+        }
+        Ok(GosValue::new_bool(ok))
+    }
+
+    /// Takes n tokens, going into debt if there are not enough, and returns
+    /// how long the caller has to wait before the debt is paid off.
+    /// Fails if n is more than the burst size, such a wait would never end.
+    fn ffi_reserve(&self, args: Vec<GosValue>) -> RuntimeResult<Vec<GosValue>> {
+        let tb = args[0]
+            .as_some_unsafe_ptr()?
+            .downcast_ref::<TokenBucket>()?;
+        let n = *args[1].as_int() as f64;
+        tb.advance();
+        let rate = tb.rate.get();
+        if n > tb.burst.get() || (rate <= 0.0 && tb.tokens.get() < n) {
+            return Ok(vec![GosValue::new_int64(0), GosValue::new_bool(false)]);
+        }
+        let tokens = tb.tokens.get() - n;
+        tb.tokens.set(tokens);
+        let wait = match tokens < 0.0 {
+            true => (-tokens / rate * 1e9) as i64,
+            false => 0,
+        };
+        Ok(vec![GosValue::new_int64(wait), GosValue::new_bool(true)])
+    }
+
+    /// Gives back the tokens of a reservation that won't be used
+    fn ffi_cancel(&self, args: Vec<GosValue>) -> RuntimeResult<()> {
+        let tb = args[0]
+            .as_some_unsafe_ptr()?
+            .downcast_ref::<TokenBucket>()?;
+        let n = *args[1].as_int() as f64;
+        tb.advance();
+        tb.tokens.set((tb.tokens.get() + n).min(tb.burst.get()));
+        Ok(())
+    }
+
+    /// Returns d randomized by up to +/- factor of itself
+    fn ffi_jitter(&self, args: Vec<GosValue>) -> GosValue {
+        let d = *args[0].as_int64();
+        let factor = args[1].as_float64().into_inner().clamp(0.0, 1.0);
+        if factor == 0.0 || d <= 0 {
+            return GosValue::new_int64(d);
+        }
+        let r = rng::with_rng(|rng| rng.gen_range(-factor..=factor));
+        GosValue::new_int64((d as f64 * (1.0 + r)) as i64)
+    }
+}
+
+struct TokenBucket {
+    // tokens per second
+    rate: Cell<f64>,
+    burst: Cell<f64>,
+    tokens: Cell<f64>,
+    last: Cell<Instant>,
+}
+
+impl TokenBucket {
+    /// Adds the tokens accumulated since the last call
+    fn advance(&self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last.get()).as_secs_f64();
+        self.last.set(now);
+        let tokens = self.tokens.get() + elapsed * self.rate.get();
+        self.tokens.set(tokens.min(self.burst.get()));
+    }
+}
+
+impl UnsafePtr for TokenBucket {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
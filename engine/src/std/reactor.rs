@@ -0,0 +1,279 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Readiness of the sockets of the network packages. A goroutine that can't
+//! read or write a non-blocking socket registers it here and is parked: a
+//! thread of the reactor polls the registered sockets and wakes the
+//! goroutines whose sockets are ready, or closed. Work that can only block,
+//! like resolving a name and connecting, runs on a thread of its own which
+//! wakes the goroutine when done.
+
+use goscript_vm::timer::{IoWait, IoWaits};
+use std::future::Future;
+use std::io::{self, Read, Write};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::net::UnixStream;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+
+#[derive(Clone, Copy)]
+pub(crate) enum Interest {
+    Read,
+    Write,
+}
+
+/// A goroutine waiting on a socket
+struct Entry {
+    id: u64,
+    fd: RawFd,
+    events: libc::c_short,
+    waker: Waker,
+}
+
+#[derive(Default)]
+struct Entries {
+    list: Vec<Entry>,
+    next_id: u64,
+}
+
+struct Reactor {
+    entries: Arc<Mutex<Entries>>,
+    /// makes the poll start over with the current entries
+    notify: UnixStream,
+}
+
+static REACTOR: OnceLock<io::Result<Reactor>> = OnceLock::new();
+
+fn reactor() -> io::Result<&'static Reactor> {
+    REACTOR
+        .get_or_init(Reactor::start)
+        .as_ref()
+        .map_err(|e| io::Error::new(e.kind(), e.to_string()))
+}
+
+impl Reactor {
+    fn start() -> io::Result<Reactor> {
+        let (notified, notify) = UnixStream::pair()?;
+        notified.set_nonblocking(true)?;
+        notify.set_nonblocking(true)?;
+        let entries = Arc::new(Mutex::new(Entries::default()));
+        let polled = entries.clone();
+        thread::Builder::new()
+            .name("goscript-reactor".to_owned())
+            .spawn(move || Reactor::poll_loop(&polled, notified))?;
+        Ok(Reactor { entries, notify })
+    }
+
+    fn lock(entries: &Mutex<Entries>) -> MutexGuard<'_, Entries> {
+        entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn poll_loop(entries: &Mutex<Entries>, mut notified: UnixStream) {
+        let mut fds = vec![];
+        let mut ids = vec![];
+        loop {
+            fds.clear();
+            ids.clear();
+            fds.push(pollfd(notified.as_raw_fd(), libc::POLLIN));
+            for e in Reactor::lock(entries).list.iter() {
+                fds.push(pollfd(e.fd, e.events));
+                ids.push(e.id);
+            }
+            let n = unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, -1) };
+            if n < 0 {
+                // EINTR, the entries are polled again
+                continue;
+            }
+            if fds[0].revents != 0 {
+                let mut buf = [0u8; 64];
+                while matches!(notified.read(&mut buf), Ok(n) if n > 0) {}
+            }
+            // closed sockets report POLLNVAL, errors and hangups are reported
+            // without being asked for, the goroutines find out by retrying
+            let ready: Vec<u64> = fds[1..]
+                .iter()
+                .zip(ids.iter())
+                .filter(|(fd, _)| fd.revents != 0)
+                .map(|(_, id)| *id)
+                .collect();
+            if !ready.is_empty() {
+                let wakers = Reactor::take(entries, |e| ready.contains(&e.id));
+                wakers.into_iter().for_each(Waker::wake);
+            }
+        }
+    }
+
+    /// Removes the entries `f` picks, returns their wakers
+    fn take<F: Fn(&Entry) -> bool>(entries: &Mutex<Entries>, f: F) -> Vec<Waker> {
+        let mut entries = Reactor::lock(entries);
+        let mut wakers = vec![];
+        entries.list.retain(|e| {
+            if f(e) {
+                wakers.push(e.waker.clone());
+                false
+            } else {
+                true
+            }
+        });
+        wakers
+    }
+
+    fn register(&self, fd: RawFd, interest: Interest, waker: Waker) -> u64 {
+        let events = match interest {
+            Interest::Read => libc::POLLIN,
+            Interest::Write => libc::POLLOUT,
+        };
+        let id = {
+            let mut entries = Reactor::lock(&self.entries);
+            let id = entries.next_id;
+            entries.next_id += 1;
+            entries.list.push(Entry {
+                id,
+                fd,
+                events,
+                waker,
+            });
+            id
+        };
+        self.notify();
+        id
+    }
+
+    fn deregister(&self, id: u64) {
+        Reactor::take(&self.entries, |e| e.id == id);
+    }
+
+    fn notify(&self) {
+        // a full buffer means a notification is pending already
+        let _ = (&self.notify).write(&[1]);
+    }
+}
+
+fn pollfd(fd: RawFd, events: libc::c_short) -> libc::pollfd {
+    libc::pollfd {
+        fd,
+        events,
+        revents: 0,
+    }
+}
+
+/// Returns a future that's ready once `fd` is ready for `interest`, or
+/// closed. It may also be ready without either, so the operation that
+/// would have blocked is to be retried.
+pub(crate) fn ready(io: &IoWaits, fd: RawFd, interest: Interest) -> Ready {
+    Ready {
+        io: io.clone(),
+        fd,
+        interest,
+        registered: None,
+    }
+}
+
+/// Wakes the goroutines waiting on `fd`, for them to see it's closed.
+/// Closing a socket doesn't end a poll it's part of.
+pub(crate) fn wake_all(fd: RawFd) {
+    if let Ok(r) = reactor() {
+        let wakers = Reactor::take(&r.entries, |e| e.fd == fd);
+        wakers.into_iter().for_each(Waker::wake);
+    }
+}
+
+pub(crate) struct Ready {
+    io: IoWaits,
+    fd: RawFd,
+    interest: Interest,
+    registered: Option<(u64, IoWait)>,
+}
+
+impl Future for Ready {
+    type Output = io::Result<()>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if self.registered.is_some() {
+            return Poll::Ready(Ok(()));
+        }
+        let r = match reactor() {
+            Ok(r) => r,
+            Err(e) => return Poll::Ready(Err(e)),
+        };
+        let wait = self.io.wait(cx.waker());
+        let id = r.register(self.fd, self.interest, wait.waker().clone());
+        self.registered = Some((id, wait));
+        Poll::Pending
+    }
+}
+
+impl Drop for Ready {
+    fn drop(&mut self) {
+        if let (Some((id, _)), Ok(r)) = (&self.registered, reactor()) {
+            r.deregister(*id);
+        }
+    }
+}
+
+/// Runs `f` on a thread of its own, the returned future is ready with its
+/// result
+pub(crate) fn unblock<T, F>(io: &IoWaits, f: F) -> Unblock<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    let shared = Arc::new(Mutex::new(Unblocked {
+        result: None,
+        waker: None,
+    }));
+    let done = shared.clone();
+    let spawned = thread::Builder::new().spawn(move || {
+        let r = panic::catch_unwind(AssertUnwindSafe(f))
+            .map_err(|_| io::Error::new(io::ErrorKind::Other, "the blocking thread panicked"));
+        let waker = {
+            let mut done = done.lock().unwrap_or_else(|e| e.into_inner());
+            done.result = Some(r);
+            done.waker.take()
+        };
+        if let Some(w) = waker {
+            w.wake();
+        }
+    });
+    Unblock {
+        io: io.clone(),
+        shared,
+        spawned: spawned.map(|_| ()),
+        wait: None,
+    }
+}
+
+struct Unblocked<T> {
+    result: Option<io::Result<T>>,
+    waker: Option<Waker>,
+}
+
+pub(crate) struct Unblock<T> {
+    io: IoWaits,
+    shared: Arc<Mutex<Unblocked<T>>>,
+    spawned: io::Result<()>,
+    wait: Option<IoWait>,
+}
+
+impl<T> Future for Unblock<T> {
+    type Output = io::Result<T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<T>> {
+        if let Err(e) = &self.spawned {
+            return Poll::Ready(Err(io::Error::new(e.kind(), e.to_string())));
+        }
+        let wait = self.io.wait(cx.waker());
+        let mut shared = self.shared.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(r) = shared.result.take() {
+            return Poll::Ready(r);
+        }
+        shared.waker = Some(wait.waker().clone());
+        drop(shared);
+        self.wait = Some(wait);
+        Poll::Pending
+    }
+}
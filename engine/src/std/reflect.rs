@@ -4,8 +4,9 @@
 
 extern crate self as goscript_engine;
 use crate::ffi::*;
+use goscript_vm::format::ValueFormatter;
 use goscript_vm::instruction::ValueType;
-use goscript_vm::metadata::{Meta, MetadataType};
+use goscript_vm::metadata::{FieldInfo, Meta, MetadataType};
 use goscript_vm::objects::*;
 use goscript_vm::value::{GosValue, InterfaceObj, PointerObj, UnsafePtr};
 use std::any::Any;
@@ -56,6 +57,11 @@ fn val_to_std_val(val: &GosValue) -> RuntimeResult<&StdValue> {
     val.as_some_unsafe_ptr()?.downcast_ref::<StdValue>()
 }
 
+#[inline]
+fn val_to_std_type(val: &GosValue) -> RuntimeResult<&StdType> {
+    val.as_some_unsafe_ptr()?.downcast_ref::<StdType>()
+}
+
 #[inline]
 fn val_to_map_iter(val: &GosValue) -> RuntimeResult<&StdMapIter> {
     val.as_some_unsafe_ptr()?.downcast_ref::<StdMapIter>()
@@ -157,6 +163,51 @@ impl ReflectFfi {
         val_to_std_val(&args[0])?.len(ctx)
     }
 
+    fn ffi_iface(&self, ctx: &FfiCallCtx, args: Vec<GosValue>) -> RuntimeResult<GosValue> {
+        val_to_std_val(&args[0])?.interface(ctx)
+    }
+
+    fn ffi_can_interface(&self, args: Vec<GosValue>) -> RuntimeResult<GosValue> {
+        Ok(GosValue::new_bool(
+            val_to_std_val(&args[0])?.can_interface(),
+        ))
+    }
+
+    fn ffi_type_string(&self, ctx: &FfiCallCtx, args: Vec<GosValue>) -> RuntimeResult<GosValue> {
+        let t = val_to_std_type(&args[0])?;
+        let s = ValueFormatter::new(ctx.vm_objs, ctx.stack).type_string(&t.meta);
+        Ok(GosValue::with_str(&s))
+    }
+
+    fn ffi_type_name(&self, args: Vec<GosValue>) -> RuntimeResult<GosValue> {
+        Ok(GosValue::with_str(&val_to_std_type(&args[0])?.name()))
+    }
+
+    fn ffi_type_elem(&self, ctx: &FfiCallCtx, args: Vec<GosValue>) -> RuntimeResult<Vec<GosValue>> {
+        val_to_std_type(&args[0])?.elem(ctx)
+    }
+
+    fn ffi_type_key(&self, ctx: &FfiCallCtx, args: Vec<GosValue>) -> RuntimeResult<Vec<GosValue>> {
+        val_to_std_type(&args[0])?.key(ctx)
+    }
+
+    fn ffi_type_len(&self, ctx: &FfiCallCtx, args: Vec<GosValue>) -> RuntimeResult<GosValue> {
+        val_to_std_type(&args[0])?.len(ctx)
+    }
+
+    fn ffi_type_num_field(&self, ctx: &FfiCallCtx, args: Vec<GosValue>) -> RuntimeResult<GosValue> {
+        let t = val_to_std_type(&args[0])?;
+        Ok(GosValue::new_int(t.fields(ctx)?.len() as isize))
+    }
+
+    fn ffi_type_field(
+        &self,
+        ctx: &FfiCallCtx,
+        args: Vec<GosValue>,
+    ) -> RuntimeResult<Vec<GosValue>> {
+        val_to_std_type(&args[0])?.field(ctx, *args[1].as_int() as usize)
+    }
+
     fn ffi_map_range_init(&self, ctx: &FfiCallCtx, args: Vec<GosValue>) -> RuntimeResult<GosValue> {
         StdMapIter::map_range(ctx, val_to_std_val(&args[0])?)
     }
@@ -422,6 +473,37 @@ impl StdValue {
         Ok(GosValue::new_int(val.len() as isize))
     }
 
+    fn can_interface(&self) -> bool {
+        match self {
+            Self::Pointer(_, _, Some(exported)) => *exported,
+            _ => true,
+        }
+    }
+
+    fn interface(&self, ctx: &FfiCallCtx) -> RuntimeResult<GosValue> {
+        if !self.can_interface() {
+            return Err(
+                "reflect: cannot return value obtained from unexported field or method".to_owned(),
+            );
+        }
+        let val = self.val(ctx)?;
+        match self.meta() {
+            // values of interface types are returned as they are
+            Some(m)
+                if m.underlying(&ctx.vm_objs.metas)
+                    .value_type(&ctx.vm_objs.metas)
+                    == ValueType::Interface =>
+            {
+                Ok(val)
+            }
+            Some(m) => Ok(GosValue::new_interface(InterfaceObj::with_value(
+                val,
+                Some((*m, vec![])),
+            ))),
+            None => Ok(GosValue::empty_iface_with_val(val)),
+        }
+    }
+
     fn can_addr(&self) -> bool {
         match self {
             Self::Value(_, _) => false,
@@ -572,44 +654,154 @@ impl StdType {
 
     fn type_of(val: &StdValue, ctx: &FfiCallCtx) -> (GosValue, GosValue) {
         let m = val.meta().unwrap().clone();
+        StdType::wrap(m, ctx)
+    }
+
+    /// Returns the type and its kind as reflectType expects them
+    fn wrap(m: Meta, ctx: &FfiCallCtx) -> (GosValue, GosValue) {
         let typ = StdType::new(m, &ctx.vm_objs.metas);
-        let kind = match m
-            .underlying(&ctx.vm_objs.metas)
-            .value_type(&ctx.vm_objs.metas)
-        {
-            ValueType::Bool => GosKind::Bool,
-            ValueType::Int => GosKind::Int,
-            ValueType::Int8 => GosKind::Int8,
-            ValueType::Int16 => GosKind::Int16,
-            ValueType::Int32 => GosKind::Int32,
-            ValueType::Int64 => GosKind::Int64,
-            ValueType::Uint => GosKind::Uint,
-            ValueType::UintPtr => GosKind::UintPtr,
-            ValueType::Uint8 => GosKind::Uint8,
-            ValueType::Uint16 => GosKind::Uint16,
-            ValueType::Uint32 => GosKind::Uint32,
-            ValueType::Uint64 => GosKind::Uint64,
-            ValueType::Float32 => GosKind::Float32,
-            ValueType::Float64 => GosKind::Float64,
-            ValueType::Complex64 => GosKind::Complex64,
-            ValueType::Complex128 => GosKind::Complex128,
-            ValueType::Array => GosKind::Array,
-            ValueType::Channel => GosKind::Chan,
-            ValueType::Closure => GosKind::Func,
-            ValueType::Interface => GosKind::Interface,
-            ValueType::Map => GosKind::Map,
-            ValueType::Pointer => GosKind::Ptr,
-            ValueType::UnsafePtr => GosKind::UnsafePtr,
-            ValueType::Slice => GosKind::Slice,
-            ValueType::String => GosKind::String,
-            ValueType::Struct => GosKind::Struct,
-            _ => GosKind::Invalid,
-        };
+        let kind = kind_of(&m, &ctx.vm_objs.metas);
         (
             GosValue::new_unsafe_ptr(typ),
             GosValue::new_uint(kind as usize),
         )
     }
+
+    /// Returns the name of a defined type without the package
+    fn name(&self) -> String {
+        if self.meta.ptr_depth > 0 {
+            return String::new();
+        }
+        match &meta_objs(self.mobjs)[self.meta.key] {
+            MetadataType::Named(_, _, name) => match name.rfind('.') {
+                Some(i) => name[i + 1..].to_owned(),
+                None => name.clone(),
+            },
+            MetadataType::Bool => "bool".to_owned(),
+            MetadataType::Str(_) => "string".to_owned(),
+            MetadataType::UnsafePtr => "Pointer".to_owned(),
+            mt => match kind_of(&self.meta, meta_objs(self.mobjs)) {
+                GosKind::Int
+                | GosKind::Int8
+                | GosKind::Int16
+                | GosKind::Int32
+                | GosKind::Int64
+                | GosKind::Uint
+                | GosKind::Uint8
+                | GosKind::Uint16
+                | GosKind::Uint32
+                | GosKind::Uint64
+                | GosKind::UintPtr
+                | GosKind::Float32
+                | GosKind::Float64
+                | GosKind::Complex64
+                | GosKind::Complex128 => format!("{:?}", mt).to_lowercase(),
+                _ => String::new(),
+            },
+        }
+    }
+
+    fn underlying<'a>(&self, ctx: &'a FfiCallCtx) -> (Meta, &'a MetadataType) {
+        let metas = &ctx.vm_objs.metas;
+        if self.meta.ptr_depth > 0 {
+            return (self.meta, &metas[self.meta.key]);
+        }
+        let u = self.meta.underlying(metas);
+        (u, &metas[u.key])
+    }
+
+    fn elem(&self, ctx: &FfiCallCtx) -> RuntimeResult<Vec<GosValue>> {
+        let elem = match self.underlying(ctx) {
+            (m, _) if m.ptr_depth > 0 => m.unptr_to(),
+            (_, MetadataType::Array(m, _))
+            | (_, MetadataType::Slice(m))
+            | (_, MetadataType::Map(_, m))
+            | (_, MetadataType::Channel(_, m)) => *m,
+            _ => return Err("reflect: Elem of invalid type".to_owned()),
+        };
+        let (t, k) = StdType::wrap(elem, ctx);
+        Ok(vec![t, k])
+    }
+
+    fn key(&self, ctx: &FfiCallCtx) -> RuntimeResult<Vec<GosValue>> {
+        match self.underlying(ctx) {
+            (m, MetadataType::Map(k, _)) if m.ptr_depth == 0 => {
+                let (t, k) = StdType::wrap(*k, ctx);
+                Ok(vec![t, k])
+            }
+            _ => Err("reflect: Key of non-map type".to_owned()),
+        }
+    }
+
+    fn len(&self, ctx: &FfiCallCtx) -> RuntimeResult<GosValue> {
+        match self.underlying(ctx) {
+            (m, MetadataType::Array(_, size)) if m.ptr_depth == 0 => {
+                Ok(GosValue::new_int(*size as isize))
+            }
+            _ => Err("reflect: Len of non-array type".to_owned()),
+        }
+    }
+
+    fn fields<'a>(&self, ctx: &'a FfiCallCtx) -> RuntimeResult<&'a [FieldInfo]> {
+        match self.underlying(ctx) {
+            (m, MetadataType::Struct(fields, _)) if m.ptr_depth == 0 => Ok(fields.all()),
+            _ => Err("reflect: NumField of non-struct type".to_owned()),
+        }
+    }
+
+    /// Returns the name, type, kind, exported, embedded and tag of the i'th field
+    fn field(&self, ctx: &FfiCallCtx, i: usize) -> RuntimeResult<Vec<GosValue>> {
+        let fields = self.fields(ctx)?;
+        if fields.len() <= i {
+            return Err("reflect: Field index out of bounds".to_owned());
+        }
+        let f = &fields[i];
+        let (t, k) = StdType::wrap(f.meta, ctx);
+        Ok(vec![
+            GosValue::with_str(&f.name),
+            t,
+            k,
+            GosValue::new_bool(f.exported),
+            GosValue::new_bool(f.embedded),
+            GosValue::with_str(f.tag.as_deref().unwrap_or("")),
+        ])
+    }
+}
+
+fn kind_of(m: &Meta, metas: &MetadataObjs) -> GosKind {
+    // underlying() of a pointer to a named type drops the pointer
+    if m.ptr_depth > 0 {
+        return GosKind::Ptr;
+    }
+    match m.underlying(metas).value_type(metas) {
+        ValueType::Bool => GosKind::Bool,
+        ValueType::Int => GosKind::Int,
+        ValueType::Int8 => GosKind::Int8,
+        ValueType::Int16 => GosKind::Int16,
+        ValueType::Int32 => GosKind::Int32,
+        ValueType::Int64 => GosKind::Int64,
+        ValueType::Uint => GosKind::Uint,
+        ValueType::UintPtr => GosKind::UintPtr,
+        ValueType::Uint8 => GosKind::Uint8,
+        ValueType::Uint16 => GosKind::Uint16,
+        ValueType::Uint32 => GosKind::Uint32,
+        ValueType::Uint64 => GosKind::Uint64,
+        ValueType::Float32 => GosKind::Float32,
+        ValueType::Float64 => GosKind::Float64,
+        ValueType::Complex64 => GosKind::Complex64,
+        ValueType::Complex128 => GosKind::Complex128,
+        ValueType::Array => GosKind::Array,
+        ValueType::Channel => GosKind::Chan,
+        ValueType::Closure => GosKind::Func,
+        ValueType::Interface => GosKind::Interface,
+        ValueType::Map => GosKind::Map,
+        ValueType::Pointer => GosKind::Ptr,
+        ValueType::UnsafePtr => GosKind::UnsafePtr,
+        ValueType::Slice => GosKind::Slice,
+        ValueType::String => GosKind::String,
+        ValueType::Struct => GosKind::Struct,
+        _ => GosKind::Invalid,
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -622,7 +814,7 @@ struct StdMapIterInner {
 struct StdMapIter {
     inner: RefCell<StdMapIterInner>,
     key_meta: Meta,
-    //val_meta: Meta,
+    val_meta: Meta,
 }
 
 impl UnsafePtr for StdMapIter {
@@ -638,14 +830,14 @@ impl StdMapIter {
         let iter: GosHashMapIter<'static> = unsafe { mem::transmute(mref.iter()) };
         let metas = &ctx.vm_objs.metas;
         let map_meta = metas[v.meta().unwrap().underlying(metas).key].as_map();
-        let (k, _) = (map_meta.0.clone(), map_meta.1.clone());
+        let (k, v) = (map_meta.0.clone(), map_meta.1.clone());
         let smi = StdMapIter {
             inner: RefCell::new(StdMapIterInner {
                 iter: iter,
                 item: None,
             }),
             key_meta: k,
-            //val_meta: v,
+            val_meta: v,
         };
         Ok(GosValue::new_unsafe_ptr(smi))
     }
@@ -669,6 +861,6 @@ impl StdMapIter {
             Some(kv) => Ok(kv.1.clone()),
             None => Err("reflect.MapIter: Next not called or iter exhausted".to_owned()),
         }
-        .map(|x| wrap_std_val(x, Some(self.key_meta)))
+        .map(|x| wrap_std_val(x, Some(self.val_meta)))
     }
 }
@@ -0,0 +1,171 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! The backend of the `sort` std package. Slices of ints, floats and strings
+//! are sorted in Rust, the others by calling their less function back: the
+//! order is found first, then the elements are swapped into it, so that
+//! less always sees the elements where they were.
+
+extern crate self as goscript_engine;
+use crate::ffi::*;
+use goscript_vm::value::*;
+use std::cmp::Ordering;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+
+#[derive(Ffi)]
+pub struct SortFfi {}
+
+#[ffi_impl(rename = "sort")]
+impl SortFfi {
+    fn ffi_sort(
+        &self,
+        ctx: &FfiCallCtx,
+        n: isize,
+        less: GosValue,
+        swap: GosValue,
+    ) -> RuntimeResult<()> {
+        let order = merge_sort(n.max(0) as usize, |i, j| call_less(ctx, &less, i, j))?;
+        permute(&order, |i, j| {
+            let args = vec![GosValue::new_int(i as isize), GosValue::new_int(j as isize)];
+            ctx.call_closure(&swap, args).map(|_| ())
+        })
+    }
+
+    fn ffi_sort_slice(
+        &self,
+        ctx: &FfiCallCtx,
+        slice: GosValue,
+        n: isize,
+        less: GosValue,
+    ) -> RuntimeResult<()> {
+        let iface = slice.as_some_interface()?;
+        let slice = match iface.underlying_value() {
+            Some(v) if v.typ() == ValueType::Slice => v,
+            _ => return Err("sort: not a slice".to_owned()),
+        };
+        let order = merge_sort(n.max(0) as usize, |i, j| call_less(ctx, &less, i, j))?;
+        let dispatcher = slice.dispatcher_a_s();
+        permute(&order, |i, j| dispatcher.slice_swap(slice, i, j))
+    }
+
+    fn ffi_ints(&self, a: GosValue) {
+        if let Some(s) = a.as_slice::<Elem64>() {
+            unsafe { s.0.as_raw_slice_mut::<isize>() }.sort_unstable();
+        }
+    }
+
+    fn ffi_float64s(&self, a: GosValue) {
+        if let Some(s) = a.as_slice::<Elem64>() {
+            // NaNs are less than the other values
+            unsafe { s.0.as_raw_slice_mut::<f64>() }.sort_by(|x, y| {
+                match (x.is_nan(), y.is_nan()) {
+                    (true, true) => Ordering::Equal,
+                    (true, false) => Ordering::Less,
+                    (false, true) => Ordering::Greater,
+                    (false, false) => x.partial_cmp(y).unwrap(),
+                }
+            });
+        }
+    }
+
+    fn ffi_strings(&self, a: GosValue) {
+        if let Some(s) = a.as_slice::<GosElem>() {
+            s.0.as_rust_slice_mut().sort_by(|x, y| {
+                StrUtil::as_str(x.borrow().as_string())
+                    .cmp(&StrUtil::as_str(y.borrow().as_string()))
+            });
+        }
+    }
+}
+
+fn call_less(ctx: &FfiCallCtx, less: &GosValue, i: usize, j: usize) -> RuntimeResult<bool> {
+    let args = vec![GosValue::new_int(i as isize), GosValue::new_int(j as isize)];
+    bool::from_gos(&ctx.call_closure(less, args)?[0])
+}
+
+/// Returns the indexes 0..n in the order of less, keeping the order of equal
+/// elements. Unlike the sorts of std, it stops at the first error of less and
+/// doesn't panic if less is not a strict weak order.
+fn merge_sort<F>(n: usize, mut less: F) -> RuntimeResult<Vec<usize>>
+where
+    F: FnMut(usize, usize) -> RuntimeResult<bool>,
+{
+    let mut order: Vec<usize> = (0..n).collect();
+    let mut merged = order.clone();
+    let mut width = 1;
+    while width < n {
+        for lo in (0..n).step_by(2 * width) {
+            let mid = (lo + width).min(n);
+            let hi = (lo + 2 * width).min(n);
+            // the runs are in order already
+            if mid == hi || !less(order[mid], order[mid - 1])? {
+                merged[lo..hi].copy_from_slice(&order[lo..hi]);
+                continue;
+            }
+            let (mut i, mut j) = (lo, mid);
+            for m in merged[lo..hi].iter_mut() {
+                if i < mid && (j == hi || !less(order[j], order[i])?) {
+                    *m = order[i];
+                    i += 1;
+                } else {
+                    *m = order[j];
+                    j += 1;
+                }
+            }
+        }
+        std::mem::swap(&mut order, &mut merged);
+        width *= 2;
+    }
+    Ok(order)
+}
+
+/// Moves the element at order[k] to k for every k, with at most one swap
+/// per element
+fn permute<F>(order: &[usize], mut swap: F) -> RuntimeResult<()>
+where
+    F: FnMut(usize, usize) -> RuntimeResult<()>,
+{
+    let mut done = vec![false; order.len()];
+    for start in 0..order.len() {
+        let mut k = start;
+        while !done[k] {
+            done[k] = true;
+            if order[k] == start {
+                break;
+            }
+            swap(k, order[k])?;
+            k = order[k];
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_merge_sort() {
+        let v = [5, 3, 8, 3, 1, 9, 2, 5, 7];
+        let order = merge_sort(v.len(), |i, j| Ok(v[i] < v[j])).unwrap();
+        assert_eq!(order, vec![4, 6, 1, 3, 0, 7, 8, 2, 5]);
+        // not a strict weak order
+        assert!(merge_sort(20, |i, j| Ok((i + j) % 3 == 0)).is_ok());
+        assert!(merge_sort(4, |i, _| match i {
+            2 => Err("stop".to_owned()),
+            _ => Ok(false),
+        })
+        .is_err());
+
+        let mut sorted = v.to_vec();
+        permute(&order, |i, j| {
+            sorted.swap(i, j);
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(sorted, vec![1, 2, 3, 3, 5, 5, 7, 8, 9]);
+    }
+}
@@ -0,0 +1,485 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! The backend of the `sql` std package. The embedder implements `Driver`
+//! for whatever database it wants to expose and registers it with
+//! `Engine::register_sql_driver`, scripts then use it by name in `sql.Open`.
+
+extern crate self as goscript_engine;
+use crate::engine::Statics;
+use crate::ffi::*;
+use goscript_vm::value::*;
+use std::any::Any;
+use std::cell::RefCell;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+
+/// A column value or a query argument
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Text(String),
+    Bytes(Vec<u8>),
+}
+
+impl Value {
+    /// the kind number used by the Go side of the package
+    fn kind(&self) -> isize {
+        match self {
+            Value::Null => 0,
+            Value::Int(_) => 1,
+            Value::Float(_) => 2,
+            Value::Bool(_) => 3,
+            Value::Text(_) => 4,
+            Value::Bytes(_) => 5,
+        }
+    }
+
+    fn from_go(val: &GosValue) -> RuntimeResult<Value> {
+        if val.is_nil() {
+            return Ok(Value::Null);
+        }
+        let v = match val.iface_underlying()? {
+            Some(v) => v,
+            None => return Err("sql: unsupported argument type".to_owned()),
+        };
+        Ok(match v.typ() {
+            ValueType::Bool => Value::Bool(*v.as_bool()),
+            ValueType::Int => Value::Int(*v.as_int() as i64),
+            ValueType::Int8 => Value::Int(*v.as_int8() as i64),
+            ValueType::Int16 => Value::Int(*v.as_int16() as i64),
+            ValueType::Int32 => Value::Int(*v.as_int32() as i64),
+            ValueType::Int64 => Value::Int(*v.as_int64()),
+            ValueType::Uint => Value::Int(uint_to_i64(*v.as_uint() as u64)?),
+            ValueType::Uint8 => Value::Int(*v.as_uint8() as i64),
+            ValueType::Uint16 => Value::Int(*v.as_uint16() as i64),
+            ValueType::Uint32 => Value::Int(*v.as_uint32() as i64),
+            ValueType::Uint64 => Value::Int(uint_to_i64(*v.as_uint64())?),
+            ValueType::Float32 => Value::Float(v.as_float32().into_inner() as f64),
+            ValueType::Float64 => Value::Float(v.as_float64().into_inner()),
+            ValueType::String => Value::Text(StrUtil::as_str(v.as_string()).to_string()),
+            ValueType::Slice if v.t_elem() == ValueType::Uint8 => match v.as_slice::<Elem8>() {
+                Some(s) => Value::Bytes(unsafe { s.0.as_raw_slice::<u8>() }.to_vec()),
+                None => Value::Null,
+            },
+            t => return Err(format!("sql: unsupported argument type {:?}", t)),
+        })
+    }
+}
+
+/// Converts an unsigned argument, the ones that don't fit in an int64 are
+/// rejected like database/sql does
+fn uint_to_i64(v: u64) -> RuntimeResult<i64> {
+    i64::try_from(v)
+        .map_err(|_| "sql: uint64 values with high bit set are not supported".to_owned())
+}
+
+/// The result of a successful Exec
+#[derive(Clone, Debug, Default)]
+pub struct ExecResult {
+    pub last_insert_id: i64,
+    pub rows_affected: i64,
+}
+
+/// Driver is the interface that must be implemented by a database driver.
+pub trait Driver {
+    /// open returns a new connection to the database,
+    /// the name is a string in a driver-specific format.
+    fn open(&self, name: &str) -> Result<Box<dyn Conn>, String>;
+}
+
+/// Conn is a connection to a database.
+pub trait Conn {
+    fn query(&self, query: &str, args: &[Value]) -> Result<Box<dyn Rows>, String>;
+
+    fn exec(&self, query: &str, args: &[Value]) -> Result<ExecResult, String>;
+
+    fn close(&self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// Rows is an iterator over an executed query's results.
+pub trait Rows {
+    fn columns(&self) -> Vec<String>;
+
+    /// next returns the next row, or None if there are no more rows.
+    fn next(&mut self) -> Result<Option<Vec<Value>>, String>;
+}
+
+#[derive(Ffi)]
+pub struct SqlFfi {}
+
+#[ffi_impl(rename = "sql")]
+impl SqlFfi {
+    fn ffi_open(&self, ctx: &FfiCallCtx, args: Vec<GosValue>) -> Vec<GosValue> {
+        let driver = StrUtil::as_str(args[0].as_string());
+        let name = StrUtil::as_str(args[1].as_string());
+        let d = Statics::downcast_borrow_data(ctx.statics)
+            .sql_drivers
+            .get(&*driver)
+            .cloned();
+        let result = match d {
+            Some(d) => d.open(&name).map(|c| {
+                GosValue::new_unsafe_ptr(ConnHandle {
+                    conn: c,
+                    closed: RefCell::new(false),
+                })
+            }),
+            None => Err(format!(
+                "sql: unknown driver \"{}\" (forgotten register?)",
+                driver
+            )),
+        };
+        SqlFfi::result_to_go(result, |v| {
+            vec![v.unwrap_or(GosValue::new_nil(ValueType::UnsafePtr))]
+        })
+    }
+
+    fn ffi_close(&self, args: Vec<GosValue>) -> RuntimeResult<GosValue> {
+        let conn = args[0].as_some_unsafe_ptr()?.downcast_ref::<ConnHandle>()?;
+        let result = match conn.closed.replace(true) {
+            false => conn.conn.close(),
+            true => Ok(()),
+        };
+        Ok(SqlFfi::result_to_go(result, |_| vec![]).pop().unwrap())
+    }
+
+    fn ffi_exec(&self, args: Vec<GosValue>) -> RuntimeResult<Vec<GosValue>> {
+        let conn = args[0].as_some_unsafe_ptr()?.downcast_ref::<ConnHandle>()?;
+        let query = StrUtil::as_str(args[1].as_string());
+        let result = SqlFfi::args_from_go(&args[2]).and_then(|a| conn.checked()?.exec(&query, &a));
+        Ok(SqlFfi::result_to_go(result, |r| {
+            let r = r.unwrap_or_default();
+            vec![
+                GosValue::new_int64(r.last_insert_id),
+                GosValue::new_int64(r.rows_affected),
+            ]
+        }))
+    }
+
+    fn ffi_query(&self, args: Vec<GosValue>) -> RuntimeResult<Vec<GosValue>> {
+        let conn = args[0].as_some_unsafe_ptr()?.downcast_ref::<ConnHandle>()?;
+        let query = StrUtil::as_str(args[1].as_string());
+        let result = SqlFfi::args_from_go(&args[2])
+            .and_then(|a| conn.checked()?.query(&query, &a))
+            .map(|rows| {
+                GosValue::new_unsafe_ptr(RowsHandle {
+                    columns: rows.columns(),
+                    rows: RefCell::new(Some(rows)),
+                    current: RefCell::new(vec![]),
+                })
+            });
+        Ok(SqlFfi::result_to_go(result, |v| {
+            vec![v.unwrap_or(GosValue::new_nil(ValueType::UnsafePtr))]
+        }))
+    }
+
+    fn ffi_column_count(&self, args: Vec<GosValue>) -> RuntimeResult<GosValue> {
+        let rows = args[0].as_some_unsafe_ptr()?.downcast_ref::<RowsHandle>()?;
+        Ok(GosValue::new_int(rows.columns.len() as isize))
+    }
+
+    fn ffi_column_name(&self, args: Vec<GosValue>) -> RuntimeResult<GosValue> {
+        let rows = args[0].as_some_unsafe_ptr()?.downcast_ref::<RowsHandle>()?;
+        let name = rows.columns.get(*args[1].as_int() as usize);
+        Ok(GosValue::with_str(name.map_or("", |x| x)))
+    }
+
+    fn ffi_next(&self, args: Vec<GosValue>) -> RuntimeResult<Vec<GosValue>> {
+        let rows = args[0].as_some_unsafe_ptr()?.downcast_ref::<RowsHandle>()?;
+        let next = match rows.rows.borrow_mut().as_mut() {
+            Some(r) => r.next(),
+            None => Ok(None),
+        };
+        let result = next.map(|row| match row {
+            Some(vals) => {
+                *rows.current.borrow_mut() = vals;
+                true
+            }
+            None => {
+                rows.close();
+                false
+            }
+        });
+        Ok(SqlFfi::result_to_go(result, |ok| {
+            vec![GosValue::new_bool(ok.unwrap_or(false))]
+        }))
+    }
+
+    fn ffi_close_rows(&self, args: Vec<GosValue>) -> RuntimeResult<()> {
+        let rows = args[0].as_some_unsafe_ptr()?.downcast_ref::<RowsHandle>()?;
+        rows.close();
+        Ok(())
+    }
+
+    fn ffi_column_kind(&self, args: Vec<GosValue>) -> RuntimeResult<GosValue> {
+        let rows = args[0].as_some_unsafe_ptr()?.downcast_ref::<RowsHandle>()?;
+        rows.with_column(&args[1], |v| GosValue::new_int(v.kind()))
+    }
+
+    fn ffi_column_int(&self, args: Vec<GosValue>) -> RuntimeResult<GosValue> {
+        let rows = args[0].as_some_unsafe_ptr()?.downcast_ref::<RowsHandle>()?;
+        rows.with_column(&args[1], |v| match v {
+            Value::Int(i) => GosValue::new_int64(*i),
+            _ => GosValue::new_int64(0),
+        })
+    }
+
+    fn ffi_column_float(&self, args: Vec<GosValue>) -> RuntimeResult<GosValue> {
+        let rows = args[0].as_some_unsafe_ptr()?.downcast_ref::<RowsHandle>()?;
+        rows.with_column(&args[1], |v| match v {
+            Value::Float(f) => GosValue::new_float64((*f).into()),
+            _ => GosValue::new_float64(0.0.into()),
+        })
+    }
+
+    fn ffi_column_bool(&self, args: Vec<GosValue>) -> RuntimeResult<GosValue> {
+        let rows = args[0].as_some_unsafe_ptr()?.downcast_ref::<RowsHandle>()?;
+        rows.with_column(&args[1], |v| GosValue::new_bool(*v == Value::Bool(true)))
+    }
+
+    fn ffi_column_string(&self, args: Vec<GosValue>) -> RuntimeResult<GosValue> {
+        let rows = args[0].as_some_unsafe_ptr()?.downcast_ref::<RowsHandle>()?;
+        rows.with_column(&args[1], |v| match v {
+            Value::Text(s) => GosValue::with_str(s),
+            Value::Bytes(b) => GosValue::with_str(&String::from_utf8_lossy(b)),
+            _ => GosValue::with_str(""),
+        })
+    }
+
+    fn ffi_column_bytes(&self, args: Vec<GosValue>) -> RuntimeResult<GosValue> {
+        let rows = args[0].as_some_unsafe_ptr()?.downcast_ref::<RowsHandle>()?;
+        rows.with_column(&args[1], |v| {
+            let bytes = match v {
+                Value::Text(s) => s.as_bytes().to_vec(),
+                Value::Bytes(b) => b.clone(),
+                _ => return GosValue::new_slice_nil(ValueType::Uint8),
+            };
+            let buf: Vec<Elem8> = unsafe { std::mem::transmute(bytes) };
+            let arr = GosValue::new_non_gc_array(ArrayObj::with_raw_data(buf), ValueType::Uint8);
+            GosValue::slice_array(arr, 0, -1, ValueType::Uint8).unwrap()
+        })
+    }
+
+    fn args_from_go(arg: &GosValue) -> Result<Vec<Value>, String> {
+        match arg.as_slice::<GosElem>() {
+            Some(s) => {
+                s.0.get_vec(ValueType::Interface)
+                    .iter()
+                    .map(|x| Value::from_go(x))
+                    .collect()
+            }
+            None => Ok(vec![]),
+        }
+    }
+
+    fn result_to_go<T, F>(result: Result<T, String>, f: F) -> Vec<GosValue>
+    where
+        F: Fn(Option<T>) -> Vec<GosValue>,
+    {
+        let (mut vals, msg) = match result {
+            Ok(v) => (f(Some(v)), "".to_owned()),
+            Err(e) => (f(None), e),
+        };
+        vals.push(GosValue::with_str(&msg));
+        vals
+    }
+}
+
+#[derive(UnsafePtr)]
+struct ConnHandle {
+    conn: Box<dyn Conn>,
+    closed: RefCell<bool>,
+}
+
+impl ConnHandle {
+    fn checked(&self) -> Result<&dyn Conn, String> {
+        match *self.closed.borrow() {
+            false => Ok(self.conn.as_ref()),
+            true => Err("sql: database is closed".to_owned()),
+        }
+    }
+}
+
+#[derive(UnsafePtr)]
+struct RowsHandle {
+    columns: Vec<String>,
+    rows: RefCell<Option<Box<dyn Rows>>>,
+    current: RefCell<Vec<Value>>,
+}
+
+impl RowsHandle {
+    fn close(&self) {
+        *self.rows.borrow_mut() = None;
+    }
+
+    fn with_column<F>(&self, index: &GosValue, f: F) -> RuntimeResult<GosValue>
+    where
+        F: Fn(&Value) -> GosValue,
+    {
+        let i = *index.as_int() as usize;
+        match self.current.borrow().get(i) {
+            Some(v) => Ok(f(v)),
+            None => Err(format!("sql: column index {} out of range", i)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::engine::Engine;
+    use goscript_codegen::FsReader;
+    use std::cell::Cell;
+
+    /// MemDriver is a toy driver with one table, "SELECT" lists the table
+    /// and "INSERT" appends the arguments as a new row.
+    struct MemDriver {
+        table: Rc<RefCell<Vec<Vec<Value>>>>,
+    }
+
+    struct MemConn {
+        table: Rc<RefCell<Vec<Vec<Value>>>>,
+        inserts: Cell<i64>,
+    }
+
+    struct MemRows {
+        rows: std::vec::IntoIter<Vec<Value>>,
+    }
+
+    impl Driver for MemDriver {
+        fn open(&self, name: &str) -> Result<Box<dyn Conn>, String> {
+            match name {
+                "mem" => Ok(Box::new(MemConn {
+                    table: self.table.clone(),
+                    inserts: Cell::new(0),
+                })),
+                _ => Err(format!("no database named {}", name)),
+            }
+        }
+    }
+
+    impl Conn for MemConn {
+        fn query(&self, query: &str, _: &[Value]) -> Result<Box<dyn Rows>, String> {
+            match query {
+                "SELECT" => Ok(Box::new(MemRows {
+                    rows: self.table.borrow().clone().into_iter(),
+                })),
+                _ => Err(format!("bad query: {}", query)),
+            }
+        }
+
+        fn exec(&self, query: &str, args: &[Value]) -> Result<ExecResult, String> {
+            match query {
+                "INSERT" => {
+                    self.table.borrow_mut().push(args.to_vec());
+                    self.inserts.set(self.inserts.get() + 1);
+                    Ok(ExecResult {
+                        last_insert_id: self.table.borrow().len() as i64,
+                        rows_affected: 1,
+                    })
+                }
+                _ => Err(format!("bad exec: {}", query)),
+            }
+        }
+    }
+
+    impl Rows for MemRows {
+        fn columns(&self) -> Vec<String> {
+            vec!["id".to_owned(), "name".to_owned(), "score".to_owned()]
+        }
+
+        fn next(&mut self) -> Result<Option<Vec<Value>>, String> {
+            Ok(self.rows.next())
+        }
+    }
+
+    #[test]
+    fn test_sql_driver() {
+        let source = r#"
+    package main
+    import "sql"
+
+    func main() {
+        _, err := sql.Open("nosuchdriver", "")
+        assert(err != nil)
+        _, err = sql.Open("mem", "nosuchdb")
+        assert(err != nil)
+
+        db, err := sql.Open("mem", "mem")
+        assert(err == nil)
+        r, err := db.Exec("INSERT", 3, "carol", 2.5)
+        assert(err == nil)
+        id, _ := r.LastInsertId()
+        assert(id == 2)
+        n, _ := r.RowsAffected()
+        assert(n == 1)
+        _, err = db.Exec("DROP")
+        assert(err != nil)
+        _, err = db.Exec("INSERT", uint64(1<<63), "eve", 0.0)
+        assert(err != nil && err.Error() == "sql: uint64 values with high bit set are not supported")
+
+        rows, err := db.Query("SELECT")
+        assert(err == nil)
+        cols, _ := rows.Columns()
+        assert(len(cols) == 3 && cols[1] == "name")
+        count := 0
+        total := 0.0
+        for rows.Next() {
+            var id int
+            var name string
+            var score float64
+            err = rows.Scan(&id, &name, &score)
+            assert(err == nil)
+            count++
+            total += score
+        }
+        assert(rows.Err() == nil)
+        assert(count == 2)
+        assert(total == 4.0)
+
+        var first int
+        var name string
+        var score interface{}
+        err = db.QueryRow("SELECT").Scan(&first, &name, &score)
+        assert(err == nil)
+        assert(first == 1 && name == "bob")
+        f, ok := score.(float64)
+        assert(ok && f == 1.5)
+
+        err = db.QueryRow("SELECT").Scan(&first)
+        assert(err != nil)
+
+        _, err = db.Exec("INSERT", 4, "dave", 0.5)
+        assert(err == nil)
+        assert(db.Close() == nil)
+        _, err = db.Query("SELECT")
+        assert(err != nil)
+    }
+    "#;
+        let engine = Engine::new();
+        let table = Rc::new(RefCell::new(vec![vec![
+            Value::Int(1),
+            Value::Text("bob".to_owned()),
+            Value::Float(1.5),
+        ]]));
+        engine.register_sql_driver(
+            "mem",
+            Rc::new(MemDriver {
+                table: table.clone(),
+            }),
+        );
+        let reader = FsReader::new(Some("./"), Some("../std/"), Some(source));
+        let result = engine.run(false, false, &reader, FsReader::temp_file_path());
+        assert_eq!(result.unwrap(), goscript_vm::vm::RunResult::Done);
+        // the last insert shows the script ran to the end
+        assert_eq!(table.borrow().len(), 3);
+        assert_eq!(table.borrow()[1][1], Value::Text("carol".to_owned()));
+    }
+}
@@ -0,0 +1,129 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! The backend of strings.Replacer, the old strings are found all at once by
+//! an Aho-Corasick automaton built when the Replacer is created.
+
+extern crate self as goscript_engine;
+use crate::ffi::*;
+use aho_corasick::{AhoCorasick, Anchored, Input, MatchKind, StartKind};
+use goscript_vm::value::*;
+use std::any::Any;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+
+#[derive(Ffi)]
+pub struct ReplacerFfi {}
+
+#[ffi_impl(rename = "strings.replacer")]
+impl ReplacerFfi {
+    fn ffi_new_replacer(&self, oldnew: Vec<String>) -> RuntimeResult<GosValue> {
+        Ok(GosValue::new_unsafe_ptr(Replacer::new(&oldnew)?))
+    }
+
+    fn ffi_replace(&self, r: GosValue, s: GosValue) -> RuntimeResult<GosValue> {
+        let r = r.as_some_unsafe_ptr()?.downcast_ref::<Replacer>()?;
+        let replaced = r.replace(StrUtil::as_str(s.as_string()).as_bytes());
+        Ok(match replaced {
+            Some(bytes) => GosValue::new_string(StrUtil::with_bytes(bytes)),
+            None => s,
+        })
+    }
+}
+
+struct Replacer {
+    // finds the old string that starts first, the first one in the argument
+    // order if several start at the same byte
+    finder: AhoCorasick,
+    new: Vec<String>,
+    // if some old strings are empty, the others and their indexes in new:
+    // only they are tried where an empty one has just matched
+    non_empty: Option<(AhoCorasick, Vec<usize>)>,
+}
+
+impl Replacer {
+    fn new(oldnew: &[String]) -> RuntimeResult<Replacer> {
+        let (old, new): (Vec<&str>, Vec<String>) = oldnew
+            .chunks(2)
+            .map(|pair| (pair[0].as_str(), pair[1].clone()))
+            .unzip();
+        let build = |patterns: &[&str]| {
+            AhoCorasick::builder()
+                .match_kind(MatchKind::LeftmostFirst)
+                .start_kind(StartKind::Both)
+                .build(patterns)
+                .map_err(|e| e.to_string())
+        };
+        let non_empty = match old.iter().any(|o| o.is_empty()) {
+            true => {
+                let index: Vec<usize> = (0..old.len()).filter(|&i| !old[i].is_empty()).collect();
+                let patterns: Vec<&str> = index.iter().map(|&i| old[i]).collect();
+                Some((build(&patterns)?, index))
+            }
+            false => None,
+        };
+        Ok(Replacer {
+            finder: build(&old)?,
+            new: new,
+            non_empty: non_empty,
+        })
+    }
+
+    /// Replaces the old strings in s like Go's generic replacer does, None if
+    /// there are none
+    fn replace(&self, s: &[u8]) -> Option<Vec<u8>> {
+        let mut out = Vec::with_capacity(s.len());
+        let mut last = 0;
+        let mut replace = |start: usize, end: usize, new: usize| {
+            out.extend_from_slice(&s[last..start]);
+            out.extend_from_slice(self.new[new].as_bytes());
+            last = end;
+        };
+        match &self.non_empty {
+            None => {
+                let mut found = false;
+                for m in self.finder.find_iter(s) {
+                    replace(m.start(), m.end(), m.pattern().as_usize());
+                    found = true;
+                }
+                if !found {
+                    return None;
+                }
+            }
+            Some((non_empty, index)) => {
+                // an empty old string matches at every byte, including the
+                // ones inside UTF-8 sequences
+                let find = |ac: &AhoCorasick, i: usize| {
+                    ac.find(Input::new(s).range(i..).anchored(Anchored::Yes))
+                        .map(|m| (m.pattern().as_usize(), m.len()))
+                };
+                let mut i = 0;
+                let mut prev_empty = false;
+                while i <= s.len() {
+                    let found = match prev_empty {
+                        false => find(&self.finder, i),
+                        true => find(non_empty, i).map(|(p, len)| (index[p], len)),
+                    };
+                    prev_empty = matches!(found, Some((_, 0)));
+                    match found {
+                        Some((p, len)) => {
+                            replace(i, i + len, p);
+                            i += len;
+                        }
+                        None => i += 1,
+                    }
+                }
+            }
+        }
+        out.extend_from_slice(&s[last..]);
+        Some(out)
+    }
+}
+
+impl UnsafePtr for Replacer {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
@@ -12,8 +12,10 @@ use goscript_vm::value::{GosValue, RuntimeResult, UnsafePtr};
 use std::any::Any;
 use std::cell::{Cell, RefCell};
 use std::future::Future;
+use std::mem;
 use std::pin::Pin;
 use std::rc::Rc;
+use std::task::{Poll, Waker};
 use std::vec;
 
 macro_rules! create_mutex {
@@ -64,9 +66,25 @@ impl MutexFfi {
     }
 }
 
+/// The fibers waiting for a lock, woken when it's released
+#[derive(Clone, Default)]
+struct Waiters(Rc<RefCell<Vec<Waker>>>);
+
+impl Waiters {
+    fn wait(&self, waker: &Waker) {
+        self.0.borrow_mut().push(waker.clone());
+    }
+
+    fn wake_all(&self) {
+        let wakers = mem::take(&mut *self.0.borrow_mut());
+        wakers.into_iter().for_each(|w| w.wake());
+    }
+}
+
 #[derive(Clone)]
 struct Mutex {
     locked: Rc<Cell<bool>>,
+    waiters: Waiters,
 }
 
 impl UnsafePtr for Mutex {
@@ -79,14 +97,21 @@ impl Mutex {
     fn new() -> Mutex {
         Mutex {
             locked: Rc::new(Cell::new(false)),
+            waiters: Waiters::default(),
         }
     }
 
     async fn lock(self) -> RuntimeResult<Vec<GosValue>> {
         //dbg!("lock called");
-        while self.locked.get() {
-            future::yield_now().await;
-        }
+        future::poll_fn(|cx| {
+            if self.locked.get() {
+                self.waiters.wait(cx.waker());
+                Poll::Pending
+            } else {
+                Poll::Ready(())
+            }
+        })
+        .await;
         self.locked.set(true);
         Ok(vec![])
     }
@@ -97,6 +122,7 @@ impl Mutex {
             Err("sync: unlock of unlocked mutex".to_owned())
         } else {
             self.locked.set(false);
+            self.waiters.wake_all();
             Ok(vec![])
         }
     }
@@ -199,6 +225,7 @@ impl RWMutexData {
 #[derive(Clone)]
 pub struct RWMutex {
     data: Rc<RefCell<RWMutexData>>,
+    waiters: Waiters,
 }
 
 impl UnsafePtr for RWMutex {
@@ -211,18 +238,24 @@ impl RWMutex {
     fn new() -> RWMutex {
         RWMutex {
             data: Rc::new(RefCell::new(RWMutexData::new())),
+            waiters: Waiters::default(),
         }
     }
 
-    async fn r_lock(self) -> RuntimeResult<Vec<GosValue>> {
-        loop {
-            let can_read = self.data.borrow().can_read();
-            if !can_read {
-                future::yield_now().await;
+    async fn wait_until(&self, ready: fn(&RWMutexData) -> bool) {
+        future::poll_fn(|cx| {
+            if ready(&self.data.borrow()) {
+                Poll::Ready(())
             } else {
-                break;
+                self.waiters.wait(cx.waker());
+                Poll::Pending
             }
-        }
+        })
+        .await
+    }
+
+    async fn r_lock(self) -> RuntimeResult<Vec<GosValue>> {
+        self.wait_until(RWMutexData::can_read).await;
         self.data.borrow_mut().inc_reader_num();
         Ok(vec![])
     }
@@ -232,20 +265,14 @@ impl RWMutex {
         if num < 0 {
             Err("sync: unmatched rUnlock call".to_owned())
         } else {
+            self.waiters.wake_all();
             Ok(vec![])
         }
     }
 
     async fn w_lock(self) -> RuntimeResult<Vec<GosValue>> {
         self.data.borrow_mut().inc_writer_num();
-        loop {
-            let can_write = self.data.borrow().can_write();
-            if !can_write {
-                future::yield_now().await;
-            } else {
-                break;
-            }
-        }
+        self.wait_until(RWMutexData::can_write).await;
         let mut d = self.data.borrow_mut();
         d.dec_writer_num();
         d.set_writer_active(true);
@@ -257,6 +284,7 @@ impl RWMutex {
         if !was_active {
             Err("sync: unmatched wUnlock call".to_owned())
         } else {
+            self.waiters.wake_all();
             Ok(vec![])
         }
     }
@@ -0,0 +1,66 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! The clocks of the time package, and sleeping on the VM's timers.
+//!
+//! Timers and tickers are implemented in Go on top of sleep_until, each one
+//! runs on a goroutine that's parked on the VM's timer queue between firings.
+
+extern crate self as goscript_engine;
+use crate::ffi::*;
+use goscript_vm::clock::{self, Instant};
+use goscript_vm::value::{GosValue, RuntimeResult};
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::time::Duration;
+
+#[derive(Ffi)]
+pub struct TimeFfi {}
+
+#[ffi_impl(rename = "time")]
+impl TimeFfi {
+    fn ffi_now(&self, ctx: &FfiCallCtx, _args: Vec<GosValue>) -> Vec<GosValue> {
+        let since = clock::unix_now();
+        vec![
+            GosValue::new_int64(since.as_secs() as i64),
+            GosValue::new_int32(since.subsec_nanos() as i32),
+            GosValue::new_int64(ctx.timers.now_nanos()),
+        ]
+    }
+
+    fn ffi_runtime_nano(&self, ctx: &FfiCallCtx, _args: Vec<GosValue>) -> GosValue {
+        GosValue::new_int64(ctx.timers.now_nanos())
+    }
+
+    fn ffi_sleep(
+        &self,
+        ctx: &mut FfiCallCtx,
+        args: Vec<GosValue>,
+    ) -> Pin<Box<dyn Future<Output = RuntimeResult<Vec<GosValue>>> + '_>> {
+        let d = (*args[0].as_int64()).max(0) as u64;
+        let sleep = ctx
+            .timers
+            .sleep_until(Instant::now() + Duration::from_nanos(d));
+        Box::pin(async move {
+            sleep.await;
+            Ok(vec![])
+        })
+    }
+
+    /// Sleeps until the monotonic time `when`, as returned by runtime_nano
+    fn ffi_sleep_until(
+        &self,
+        ctx: &mut FfiCallCtx,
+        args: Vec<GosValue>,
+    ) -> Pin<Box<dyn Future<Output = RuntimeResult<Vec<GosValue>>> + '_>> {
+        let sleep = ctx
+            .timers
+            .sleep_until(ctx.timers.instant_of(*args[0].as_int64()));
+        Box::pin(async move {
+            sleep.await;
+            Ok(vec![])
+        })
+    }
+}
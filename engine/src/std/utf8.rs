@@ -0,0 +1,47 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! The hot functions of unicode/utf8, the strings are read as the bytes
+//! they hold, so invalid UTF-8 is seen the way Go sees it.
+
+extern crate self as goscript_engine;
+use crate::ffi::*;
+use goscript_vm::value::*;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+
+#[derive(Ffi)]
+pub struct Utf8Ffi {}
+
+#[ffi_impl(rename = "unicode.utf8")]
+impl Utf8Ffi {
+    fn ffi_decode_rune(&self, p: &[u8]) -> (i32, isize) {
+        match p.is_empty() {
+            true => (char::REPLACEMENT_CHARACTER as i32, 0),
+            false => {
+                let (r, size) = StrUtil::decode_rune(p);
+                (r as i32, size as isize)
+            }
+        }
+    }
+
+    fn ffi_rune_len(&self, r: i32) -> isize {
+        match r {
+            // surrogates are not chars
+            0..=0x10FFFF => char::from_u32(r as u32).map_or(-1, |c| c.len_utf8() as isize),
+            _ => -1,
+        }
+    }
+
+    fn ffi_rune_count_in_string(&self, s: GosValue) -> isize {
+        let bytes = unsafe { s.as_string().as_raw_slice::<u8>() };
+        StringEnumIter::new(&bytes).count() as isize
+    }
+
+    fn ffi_valid_string(&self, s: GosValue) -> bool {
+        let bytes = unsafe { s.as_string().as_raw_slice::<u8>() };
+        std::str::from_utf8(&bytes).is_ok()
+    }
+}
@@ -0,0 +1,640 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! A minimal RFC 6455 client, only plain `ws://` urls are supported.
+//! It runs on the sockets of the net package, a goroutine waiting on the
+//! connection is parked without blocking the VM.
+
+extern crate self as goscript_engine;
+use super::net::{check_net, TcpConn};
+use crate::ffi::*;
+use goscript_vm::timer::IoWaits;
+use goscript_vm::value::*;
+use std::any::Any;
+use std::cell::Cell;
+use std::collections::hash_map::RandomState;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::pin::Pin;
+use std::rc::Rc;
+
+const WS_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+// The largest message read, the lengths in frame headers are not trusted
+// further, the same limit as golang.org/x/net/websocket
+const MAX_MESSAGE_SIZE: usize = 32 << 20;
+// The largest handshake response header read
+const MAX_HEADER_SIZE: usize = 8 << 10;
+// Control frames can't be fragmented, and carry 125 bytes at most
+const MAX_CONTROL_SIZE: u64 = 125;
+
+// Message types, the same values as the opcodes in the frame header
+const CONTINUATION_FRAME: u8 = 0;
+const TEXT_MESSAGE: u8 = 1;
+const BINARY_MESSAGE: u8 = 2;
+const CLOSE_MESSAGE: u8 = 8;
+const PING_MESSAGE: u8 = 9;
+const PONG_MESSAGE: u8 = 10;
+
+#[derive(Ffi)]
+pub struct WebSocketFfi {}
+
+#[ffi_impl(rename = "websocket")]
+impl WebSocketFfi {
+    fn ffi_dial(
+        &self,
+        ctx: &mut FfiCallCtx,
+        args: Vec<GosValue>,
+    ) -> Pin<Box<dyn Future<Output = RuntimeResult<Vec<GosValue>>> + '_>> {
+        let allowed = check_net(ctx);
+        let io = ctx.timers.io().clone();
+        Box::pin(async move {
+            let url = StrUtil::as_str(args[0].as_string()).to_string();
+            let r = match allowed {
+                Ok(()) => WsConn::dial(&io, &url).await,
+                Err(e) => Err(e),
+            };
+            Ok(WebSocketFfi::result_to_go(r, |opt| match opt {
+                Some(c) => vec![GosValue::new_unsafe_ptr(c)],
+                None => vec![GosValue::new_nil(ValueType::UnsafePtr)],
+            }))
+        })
+    }
+
+    async fn ffi_read_message(&self, args: Vec<GosValue>) -> RuntimeResult<Vec<GosValue>> {
+        let conn = args[0].as_some_unsafe_ptr()?.downcast_ref::<WsConn>()?;
+        let r = conn.read_message().await;
+        Ok(WebSocketFfi::result_to_go(r, |opt| match opt {
+            Some((typ, data)) => vec![GosValue::new_int(typ as isize), bytes_to_slice(data)],
+            None => vec![
+                GosValue::new_int(-1),
+                GosValue::new_slice_nil(ValueType::Uint8),
+            ],
+        }))
+    }
+
+    async fn ffi_write_message(&self, args: Vec<GosValue>) -> RuntimeResult<Vec<GosValue>> {
+        let conn = args[0].as_some_unsafe_ptr()?.downcast_ref::<WsConn>()?;
+        let typ = *args[1].as_int() as u8;
+        let data = match args[2].as_slice::<Elem8>() {
+            Some(s) => unsafe { s.0.as_raw_slice::<u8>() }.to_vec(),
+            None => vec![],
+        };
+        let r = conn.write_message(typ, &data).await;
+        Ok(WebSocketFfi::result_to_go(r, |_| vec![]))
+    }
+
+    async fn ffi_close(&self, args: Vec<GosValue>) -> RuntimeResult<Vec<GosValue>> {
+        let conn = args[0].as_some_unsafe_ptr()?.downcast_ref::<WsConn>()?;
+        let r = conn.close().await;
+        Ok(WebSocketFfi::result_to_go(r, |_| vec![]))
+    }
+
+    fn result_to_go<T, F>(result: io::Result<T>, f: F) -> Vec<GosValue>
+    where
+        F: Fn(Option<T>) -> Vec<GosValue>,
+    {
+        let (mut vals, msg) = match result {
+            Ok(v) => (f(Some(v)), "".to_owned()),
+            Err(e) => (f(None), e.to_string()),
+        };
+        vals.push(GosValue::with_str(&msg));
+        vals
+    }
+}
+
+#[derive(UnsafePtr)]
+pub struct WsConn {
+    conn: TcpConn,
+    closed: Cell<bool>,
+}
+
+impl WsConn {
+    async fn dial(io: &IoWaits, url: &str) -> io::Result<WsConn> {
+        let (host, path) = parse_url(url)?;
+        let conn = WsConn {
+            conn: TcpConn::dial(io, "tcp".to_owned(), host.clone()).await?,
+            closed: Cell::new(false),
+        };
+        conn.handshake(&host, &path).await?;
+        Ok(conn)
+    }
+
+    async fn handshake(&self, host: &str, path: &str) -> io::Result<()> {
+        let key = base64_encode(&random_bytes(16));
+        let req = format!(
+            "GET {} HTTP/1.1\r\nHost: {}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+            Sec-WebSocket-Key: {}\r\nSec-WebSocket-Version: 13\r\n\r\n",
+            path, host, key
+        );
+        self.write_all(req.as_bytes()).await?;
+
+        // read the response header byte by byte, so that nothing after it is consumed
+        let mut header = vec![];
+        while !header.ends_with(b"\r\n\r\n") {
+            if header.len() == MAX_HEADER_SIZE {
+                return Err(ws_err("bad handshake: header too long"));
+            }
+            let mut b = [0u8; 1];
+            self.read_exact(&mut b).await?;
+            header.push(b[0]);
+        }
+        let header = String::from_utf8_lossy(&header);
+        let mut lines = header.split("\r\n");
+        let status = lines.next().unwrap_or("");
+        if status.split(' ').nth(1) != Some("101") {
+            return Err(ws_err(&format!("bad handshake: {}", status)));
+        }
+        let expected = base64_encode(&sha1(format!("{}{}", key, WS_GUID).as_bytes()));
+        let accepted = lines.any(|l| match l.split_once(':') {
+            Some((k, v)) => {
+                k.trim().eq_ignore_ascii_case("sec-websocket-accept") && v.trim() == expected
+            }
+            None => false,
+        });
+        if !accepted {
+            return Err(ws_err("bad handshake: invalid Sec-WebSocket-Accept"));
+        }
+        Ok(())
+    }
+
+    async fn read_message(&self) -> io::Result<(u8, Vec<u8>)> {
+        let mut typ = CONTINUATION_FRAME;
+        let mut message = vec![];
+        loop {
+            let (fin, opcode, payload) = self.read_frame(MAX_MESSAGE_SIZE - message.len()).await?;
+            match opcode {
+                PING_MESSAGE => self.write_frame(PONG_MESSAGE, &payload).await?,
+                PONG_MESSAGE => {}
+                CLOSE_MESSAGE => {
+                    if !self.closed.get() {
+                        self.closed.set(true);
+                        // echo the status code back, as required by the protocol
+                        self.write_frame(CLOSE_MESSAGE, &payload[..payload.len().min(2)])
+                            .await?;
+                    }
+                    return Err(ws_err("websocket: close received"));
+                }
+                TEXT_MESSAGE | BINARY_MESSAGE | CONTINUATION_FRAME => {
+                    // only continuation frames follow the first fragment of a message
+                    match (typ, opcode) {
+                        (CONTINUATION_FRAME, CONTINUATION_FRAME) => {
+                            return Err(ws_err("websocket: continuation frame without a message"))
+                        }
+                        (CONTINUATION_FRAME, _) => typ = opcode,
+                        (_, CONTINUATION_FRAME) => {}
+                        _ => return Err(ws_err("websocket: new message inside a fragmented one")),
+                    }
+                    message.extend_from_slice(&payload);
+                    if fin {
+                        return Ok((typ, message));
+                    }
+                }
+                _ => return Err(ws_err(&format!("websocket: unknown opcode {}", opcode))),
+            }
+        }
+    }
+
+    async fn write_message(&self, typ: u8, data: &[u8]) -> io::Result<()> {
+        if self.closed.get() {
+            return Err(ws_err("websocket: use of closed connection"));
+        }
+        match typ {
+            TEXT_MESSAGE | BINARY_MESSAGE | PING_MESSAGE | PONG_MESSAGE => {
+                self.write_frame(typ, data).await
+            }
+            CLOSE_MESSAGE => {
+                self.closed.set(true);
+                self.write_frame(typ, data).await
+            }
+            _ => Err(ws_err(&format!("websocket: bad message type {}", typ))),
+        }
+    }
+
+    async fn close(&self) -> io::Result<()> {
+        let mut result = Ok(());
+        if !self.closed.get() {
+            self.closed.set(true);
+            // 1000: normal closure
+            result = self
+                .write_frame(CLOSE_MESSAGE, &1000u16.to_be_bytes())
+                .await;
+        }
+        // the connection is closed even if the close frame can't be sent
+        let closed = self.conn.close();
+        result.and(closed)
+    }
+
+    /// Reads a frame, its payload can't be longer than `limit`
+    async fn read_frame(&self, limit: usize) -> io::Result<(bool, u8, Vec<u8>)> {
+        let mut head = [0u8; 2];
+        self.read_exact(&mut head).await?;
+        let fin = head[0] & 0x80 != 0;
+        let opcode = head[0] & 0x0f;
+        // no extension is negotiated, so the reserved bits are never set,
+        // and frames sent by a server are never masked
+        if head[0] & 0x70 != 0 {
+            return Err(ws_err("websocket: reserved bits set"));
+        }
+        if head[1] & 0x80 != 0 {
+            return Err(ws_err("websocket: masked frame from the server"));
+        }
+        let len = match head[1] & 0x7f {
+            126 => {
+                let mut ext = [0u8; 2];
+                self.read_exact(&mut ext).await?;
+                u16::from_be_bytes(ext) as u64
+            }
+            127 => {
+                let mut ext = [0u8; 8];
+                self.read_exact(&mut ext).await?;
+                u64::from_be_bytes(ext)
+            }
+            l => l as u64,
+        };
+        if opcode & 0x08 != 0 && (len > MAX_CONTROL_SIZE || !fin) {
+            return Err(ws_err("websocket: invalid control frame"));
+        }
+        if opcode & 0x08 == 0 && len > limit as u64 {
+            return Err(ws_err("websocket: read limit exceeded"));
+        }
+        let mut payload = vec![0u8; len as usize];
+        self.read_exact(&mut payload).await?;
+        Ok((fin, opcode, payload))
+    }
+
+    async fn write_frame(&self, opcode: u8, data: &[u8]) -> io::Result<()> {
+        let mut frame = Vec::with_capacity(data.len() + 14);
+        frame.push(0x80 | opcode);
+        // frames sent by a client must always be masked
+        match data.len() {
+            l if l < 126 => frame.push(0x80 | l as u8),
+            l if l <= u16::MAX as usize => {
+                frame.push(0x80 | 126);
+                frame.extend_from_slice(&(l as u16).to_be_bytes());
+            }
+            l => {
+                frame.push(0x80 | 127);
+                frame.extend_from_slice(&(l as u64).to_be_bytes());
+            }
+        }
+        let mut mask = [0u8; 4];
+        mask.copy_from_slice(&random_bytes(4));
+        frame.extend_from_slice(&mask);
+        let begin = frame.len();
+        frame.extend_from_slice(data);
+        apply_mask(&mut frame[begin..], mask);
+        self.write_all(&frame).await
+    }
+
+    async fn read_exact(&self, buf: &mut [u8]) -> io::Result<()> {
+        let mut filled = 0;
+        while filled < buf.len() {
+            match self.conn.read(&mut buf[filled..]).await? {
+                0 => return Err(io::Error::from(io::ErrorKind::UnexpectedEof)),
+                n => filled += n,
+            }
+        }
+        Ok(())
+    }
+
+    async fn write_all(&self, buf: &[u8]) -> io::Result<()> {
+        self.conn.write_all(buf).await.map(|_| ())
+    }
+}
+
+fn ws_err(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, msg)
+}
+
+/// parse_url splits "ws://host:port/path" into ("host:port", "/path")
+fn parse_url(url: &str) -> io::Result<(String, String)> {
+    let rest = match url.strip_prefix("ws://") {
+        Some(r) => r,
+        None => return Err(ws_err(&format!("websocket: unsupported url: {}", url))),
+    };
+    let (host, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, "/"),
+    };
+    let host = if host.contains(':') {
+        host.to_owned()
+    } else {
+        format!("{}:80", host)
+    };
+    Ok((host, path.to_owned()))
+}
+
+fn bytes_to_slice(data: Vec<u8>) -> GosValue {
+    let buf: Vec<Elem8> = unsafe { std::mem::transmute(data) };
+    let arr = GosValue::new_non_gc_array(ArrayObj::with_raw_data(buf), ValueType::Uint8);
+    GosValue::slice_array(arr, 0, -1, ValueType::Uint8).unwrap()
+}
+
+fn apply_mask(data: &mut [u8], mask: [u8; 4]) {
+    for (i, b) in data.iter_mut().enumerate() {
+        *b ^= mask[i % 4];
+    }
+}
+
+/// random_bytes uses the randomly seeded std hasher, the keys and masks only
+/// have to be unpredictable, not cryptographically strong.
+fn random_bytes(n: usize) -> Vec<u8> {
+    let mut result = Vec::with_capacity(n + 8);
+    while result.len() < n {
+        let mut h = RandomState::new().build_hasher();
+        h.write_usize(result.len());
+        result.extend_from_slice(&h.finish().to_le_bytes());
+    }
+    result.truncate(n);
+    result
+}
+
+fn base64_encode(data: &[u8]) -> String {
+    const TABLE: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut s = String::with_capacity((data.len() + 2) / 3 * 4);
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                s.push(TABLE[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                s.push('=');
+            }
+        }
+    }
+    s
+}
+
+/// SHA-1 is only used to verify Sec-WebSocket-Accept in the handshake
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+    let mut msg = data.to_vec();
+    msg.push(0x80);
+    while msg.len() % 64 != 56 {
+        msg.push(0);
+    }
+    msg.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+    for block in msg.chunks(64) {
+        let mut w = [0u32; 80];
+        for i in 0..16 {
+            w[i] = u32::from_be_bytes([
+                block[i * 4],
+                block[i * 4 + 1],
+                block[i * 4 + 2],
+                block[i * 4 + 3],
+            ]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+        let (mut a, mut b, mut c, mut d, mut e) = (h[0], h[1], h[2], h[3], h[4]);
+        for (i, wi) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*wi);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        h[0] = h[0].wrapping_add(a);
+        h[1] = h[1].wrapping_add(b);
+        h[2] = h[2].wrapping_add(c);
+        h[3] = h[3].wrapping_add(d);
+        h[4] = h[4].wrapping_add(e);
+    }
+    let mut result = [0u8; 20];
+    for (i, v) in h.iter().enumerate() {
+        result[i * 4..i * 4 + 4].copy_from_slice(&v.to_be_bytes());
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+
+    #[test]
+    fn test_handshake_helpers() {
+        // the example in RFC 6455 section 1.3
+        let key = "dGhlIHNhbXBsZSBub25jZQ==";
+        let accept = base64_encode(&sha1(format!("{}{}", key, WS_GUID).as_bytes()));
+        assert_eq!(accept, "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+        assert_eq!(base64_encode(b"ab"), "YWI=");
+        assert_eq!(
+            parse_url("ws://localhost/chat").unwrap(),
+            ("localhost:80".to_owned(), "/chat".to_owned())
+        );
+        assert!(parse_url("wss://localhost").is_err());
+    }
+
+    /// Accepts a connection and answers its handshake
+    fn accept(listener: &TcpListener) -> TcpStream {
+        let (mut s, _) = listener.accept().unwrap();
+        let mut header = vec![];
+        while !header.ends_with(b"\r\n\r\n") {
+            let mut b = [0u8; 1];
+            s.read_exact(&mut b).unwrap();
+            header.push(b[0]);
+        }
+        let header = String::from_utf8(header).unwrap();
+        let key = header
+            .split("\r\n")
+            .find_map(|l| l.strip_prefix("Sec-WebSocket-Key: "))
+            .unwrap();
+        let accept = base64_encode(&sha1(format!("{}{}", key, WS_GUID).as_bytes()));
+        write!(
+            s,
+            "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n\
+            Connection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+            accept
+        )
+        .unwrap();
+        s
+    }
+
+    /// serve_echo accepts one connection and echoes every data frame back
+    fn serve_echo(listener: TcpListener) {
+        let mut s = accept(&listener);
+        loop {
+            let mut head = [0u8; 2];
+            s.read_exact(&mut head).unwrap();
+            let opcode = head[0] & 0x0f;
+            let len = (head[1] & 0x7f) as usize;
+            let mut mask = [0u8; 4];
+            s.read_exact(&mut mask).unwrap();
+            let mut payload = vec![0u8; len];
+            s.read_exact(&mut payload).unwrap();
+            apply_mask(&mut payload, mask);
+            if opcode == CLOSE_MESSAGE {
+                break;
+            }
+            // server frames are not masked
+            s.write_all(&[0x80 | opcode, len as u8]).unwrap();
+            s.write_all(&payload).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_websocket_echo() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || serve_echo(listener));
+
+        let source = format!(
+            r#"
+    package main
+    import "websocket"
+
+    func main() {{
+        c, err := websocket.Dial("ws://{}/echo")
+        assert(err == nil)
+        err = c.WriteMessage(websocket.TextMessage, []byte("hello"))
+        assert(err == nil)
+        typ, data, err := c.ReadMessage()
+        assert(err == nil)
+        assert(typ == websocket.TextMessage)
+        assert(string(data) == "hello")
+        err = c.WriteMessage(websocket.BinaryMessage, []byte{{1, 2, 3}})
+        assert(err == nil)
+        typ, data, err = c.ReadMessage()
+        assert(typ == websocket.BinaryMessage)
+        assert(len(data) == 3 && data[2] == 3)
+        assert(c.Close() == nil)
+        assert(c.WriteMessage(websocket.TextMessage, []byte("x")) != nil)
+    }}
+    "#,
+            addr
+        );
+        let mut cfg = crate::run_fs::Config::default();
+        cfg.working_dir = Some("./");
+        cfg.base_dir = Some("../std/");
+        let result = crate::run_fs::run_string(cfg, &source);
+        assert_eq!(result.unwrap(), goscript_vm::vm::RunResult::Done);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_read_limit() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            // a frame claiming 2^62 bytes
+            let mut s1 = accept(&listener);
+            s1.write_all(&[0x82, 127, 0x40, 0, 0, 0, 0, 0, 0, 0])
+                .unwrap();
+            // a fragment, then one that makes the message too large
+            let mut s2 = accept(&listener);
+            s2.write_all(&[0x02, 16]).unwrap();
+            s2.write_all(&[0; 16]).unwrap();
+            s2.write_all(&[0x80, 127]).unwrap();
+            s2.write_all(&(MAX_MESSAGE_SIZE as u64 - 8).to_be_bytes())
+                .unwrap();
+            let mut rest = vec![];
+            let _ = s1.read_to_end(&mut rest);
+            let _ = s2.read_to_end(&mut rest);
+        });
+
+        let source = format!(
+            r#"
+    package main
+    import "websocket"
+
+    func main() {{
+        for i := 0; i < 2; i++ {{
+            c, err := websocket.Dial("ws://{}/")
+            assert(err == nil)
+            _, _, err = c.ReadMessage()
+            assert(err != nil && err.Error() == "websocket: read limit exceeded")
+            c.Close()
+        }}
+    }}
+    "#,
+            addr
+        );
+        let mut cfg = crate::run_fs::Config::default();
+        cfg.working_dir = Some("./");
+        cfg.base_dir = Some("../std/");
+        let result = crate::run_fs::run_string(cfg, &source);
+        assert_eq!(result.unwrap(), goscript_vm::vm::RunResult::Done);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_protocol_errors() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let frames: Vec<&'static [u8]> = vec![
+            // a masked frame
+            &[0x81, 0x81, 1, 2, 3, 4, b'a' ^ 1],
+            // RSV1 set
+            &[0xc1, 1, b'a'],
+            // a text frame inside a fragmented binary message
+            &[0x02, 1, b'a', 0x81, 1, b'b'],
+            // a continuation frame with no message to continue
+            &[0x80, 1, b'a'],
+        ];
+        let n = frames.len();
+        let server = thread::spawn(move || {
+            for f in frames {
+                let mut s = accept(&listener);
+                s.write_all(f).unwrap();
+                // returns once the client has closed the connection
+                let mut rest = vec![];
+                let _ = s.read_to_end(&mut rest);
+            }
+        });
+
+        let source = format!(
+            r#"
+    package main
+    import "websocket"
+
+    func main() {{
+        errs := []string{{
+            "websocket: masked frame from the server",
+            "websocket: reserved bits set",
+            "websocket: new message inside a fragmented one",
+            "websocket: continuation frame without a message",
+        }}
+        assert(len(errs) == {})
+        for _, want := range errs {{
+            c, err := websocket.Dial("ws://{}/")
+            assert(err == nil)
+            _, _, err = c.ReadMessage()
+            assert(err != nil && err.Error() == want)
+            c.Close()
+        }}
+    }}
+    "#,
+            n, addr
+        );
+        let mut cfg = crate::run_fs::Config::default();
+        cfg.working_dir = Some("./");
+        cfg.base_dir = Some("../std/");
+        let result = crate::run_fs::run_string(cfg, &source);
+        assert_eq!(result.unwrap(), goscript_vm::vm::RunResult::Done);
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,19 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Helpers shared by the modules of the engine.
+
+use std::any::Any;
+
+/// The message of a Rust panic caught with `catch_unwind`, like a failed
+/// assert of a program or a crash of the compiler
+pub(crate) fn panic_msg(p: &Box<dyn Any + Send>) -> &str {
+    if let Some(s) = p.downcast_ref::<&str>() {
+        s
+    } else if let Some(s) = p.downcast_ref::<String>() {
+        s
+    } else {
+        "unknown"
+    }
+}
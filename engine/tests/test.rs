@@ -38,7 +38,7 @@ impl Write for WriteBuf {
     }
 }
 
-fn run(path: &str, trace: bool) -> Result<(), engine::ErrorList> {
+fn run(path: &str, trace: bool) -> Result<engine::RunResult, engine::ErrorList> {
     let mut cfg = engine::run_fs::Config::default();
     cfg.working_dir = Some("./");
     cfg.base_dir = Some("../std/");
@@ -52,7 +52,7 @@ fn run(path: &str, trace: bool) -> Result<(), engine::ErrorList> {
     result
 }
 
-fn run_string(source: &str, trace: bool) -> Result<(), engine::ErrorList> {
+fn run_string(source: &str, trace: bool) -> Result<engine::RunResult, engine::ErrorList> {
     let mut cfg = engine::run_fs::Config::default();
     cfg.working_dir = Some("./");
     cfg.base_dir = Some("../std/");
@@ -67,7 +67,7 @@ fn run_string(source: &str, trace: bool) -> Result<(), engine::ErrorList> {
 }
 
 #[cfg(feature = "run_zip")]
-fn run_zip_and_string(file: &str, source: &str, trace: bool) -> Result<(), engine::ErrorList> {
+fn run_zip_and_string(file: &str, source: &str, trace: bool) -> Result<engine::RunResult, engine::ErrorList> {
     let mut cfg = engine::run_zip::Config::default();
     cfg.base_dir = Some("std/");
     cfg.trace_parser = trace;
@@ -93,7 +93,7 @@ fn test_source() {
     }
     "#;
     let result = run_string(source, false);
-    assert!(result.is_ok());
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
 }
 
 #[test]
@@ -109,43 +109,47 @@ fn test_zip() {
     }
     "#;
     let result = run_zip_and_string("./tests/std.zip", source, false);
-    assert!(result.is_ok());
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
 }
 
 #[test]
 fn test_g2case0() {
     let result = run("./tests/group2/case0.gos", true);
-    assert!(result.is_ok());
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
 }
 
 #[test]
 fn test_g2case1() {
     let result = run("./tests/group2/case1.gos", true);
-    assert!(result.is_ok());
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
 }
 
 #[test]
 fn test_g2case2() {
     let result = run("./tests/group2/case2.gos", true);
-    assert!(result.is_ok());
+    // the program writes to a nil map
+    match result {
+        Ok(engine::RunResult::Panic(p)) => assert_eq!(p.message, "assignment to entry in nil map"),
+        _ => panic!("case2.gos should panic"),
+    }
 }
 
 #[test]
 fn test_g2case3() {
     let result = run("./tests/group2/case3.gos", true);
-    assert!(result.is_ok());
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
 }
 
 #[test]
 fn test_g2nil() {
     let result = run("./tests/group2/nil.gos", true);
-    assert!(result.is_ok());
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
 }
 
 #[test]
 fn test_g2display() {
     let result = run("./tests/group2/display.gos", true);
-    assert!(result.is_ok());
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
 }
 
 #[test]
@@ -153,223 +157,587 @@ fn test_unicode0() {
     time_test!();
 
     let result = run("./tests/group2/unicode0.gos", true);
-    assert!(result.is_ok());
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
 }
 
 #[test]
 fn test_sync_mutex() {
     let result = run("./tests/group2/sync_mutex.gos", true);
-    assert!(result.is_ok());
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
 }
 
 #[test]
 fn test_sync_rwmutex() {
     let result = run("./tests/group2/sync_rwmutex.gos", true);
-    assert!(result.is_ok());
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
 }
 
 #[test]
 fn test_g1case1() {
     let result = run("./tests/group1/case1.gos", true);
-    assert!(result.is_ok());
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
 }
 
 #[test]
 fn test_closure1() {
     let result = run("./tests/group1/closure1.gos", true);
-    assert!(result.is_ok());
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
 }
 
 #[test]
 fn test_closure2() {
     let result = run("./tests/group1/closure2.gos", true);
-    assert!(result.is_ok());
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
 }
 
 #[test]
 fn test_closure3() {
     let result = run("./tests/group1/closure3.gos", true);
-    assert!(result.is_ok());
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
 }
 
 #[test]
 fn test_closure4() {
     let result = run("./tests/group1/closure4.gos", true);
-    assert!(result.is_ok());
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
 }
 
 #[test]
 fn test_func1() {
     let result = run("./tests/group1/func1.gos", true);
-    assert!(result.is_ok());
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
+}
+
+#[test]
+fn test_method() {
+    let result = run("./tests/group1/method.gos", false);
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
+}
+
+#[test]
+fn test_method2() {
+    let result = run("./tests/group1/method2.gos", false);
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
+}
+
+#[test]
+fn test_alias() {
+    let result = run("./tests/group1/alias.gos", false);
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
+}
+
+#[test]
+fn test_method_alias() {
+    let result = run("./tests/group1/method_alias.gos", false);
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
+}
+
+#[test]
+fn test_embed() {
+    let result = run("./tests/group1/embed.gos", false);
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
+}
+
+#[test]
+fn test_compare() {
+    let result = run("./tests/group1/compare.gos", false);
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
+}
+
+#[test]
+fn test_arith() {
+    let result = run("./tests/group1/arith.gos", false);
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
+}
+
+#[test]
+fn test_method_not_addressable() {
+    let source = r#"
+    package main
+
+    type T struct{ n int }
+
+    func (t *T) Set(n int) { t.n = n }
+
+    func get() [2]T { return [2]T{} }
+
+    func main() {
+        m := map[string]T{}
+        m["a"].Set(1)
+        f := m["a"].Set
+        get()[0].Set(1)
+        T{}.Set(1)
+        g := T.Set
+        _, _ = f, g
+    }
+    "#;
+    let el = run_string(source, false).unwrap_err();
+    el.sort();
+    let msgs: Vec<(usize, String)> = el
+        .borrow()
+        .iter()
+        .map(|e| (e.pos.line, e.msg.clone()))
+        .collect();
+    let ptr_call = "cannot call pointer method Set on ./temp_file_in_memory_for_testing_and_you_can_only_have_one.gos.T";
+    assert_eq!(msgs.len(), 5);
+    for line in 12..16 {
+        assert_eq!(msgs[line - 12], (line, ptr_call.to_owned()));
+    }
+    assert!(msgs[4].1.starts_with("invalid method expression T.Set (needs pointer receiver"));
+}
+
+#[test]
+fn test_missing_method() {
+    let source = r#"
+    package main
+
+    type I interface {
+        Get() int
+        Set(int)
+    }
+
+    type Field struct{ Get int }
+    type Ptr struct{}
+    type Wrong struct{}
+    type Case struct{}
+
+    func (p *Ptr) Get() int     { return 0 }
+    func (p *Ptr) Set(int)      {}
+    func (w Wrong) Get() int    { return 0 }
+    func (w Wrong) Set(string)  {}
+    func (c Case) Get() int     { return 0 }
+    func (c Case) set(int)      {}
+
+    func main() {
+        var i I = Field{}
+        i = Ptr{}
+        i = Wrong{}
+        i = Case{}
+        _ = i.(Wrong)
+    }
+    "#;
+    let el = run_string(source, false).unwrap_err();
+    el.sort();
+    let msgs: Vec<String> = el
+        .borrow()
+        .iter()
+        .map(|e| e.msg.replace("./temp_file_in_memory_for_testing_and_you_can_only_have_one.gos.", ""))
+        .collect();
+    assert_eq!(
+        msgs,
+        vec![
+            "cannot use (Field literal) (value of type Field) as I value in variable declaration: \
+             Field does not implement I (Field.Get is a field, not a method)",
+            "cannot use (Ptr literal) (value of type Ptr) as I value in assignment: \
+             Ptr does not implement I (method Get has pointer receiver)",
+            "cannot use (Wrong literal) (value of type Wrong) as I value in assignment: \
+             Wrong does not implement I (wrong type for method Set)\n\t\thave Set(string)\n\t\twant Set(int)",
+            "cannot use (Case literal) (value of type Case) as I value in assignment: \
+             Case does not implement I (missing method Set)\n\t\thave set(int)\n\t\twant Set(int)",
+            "impossible type assertion: i.(Wrong)\n\t\
+             Wrong does not implement I (wrong type for method Set)\n\t\thave Set(string)\n\t\twant Set(int)",
+        ]
+    );
+}
+
+#[test]
+fn test_func_conv() {
+    let result = run("./tests/group1/func_conv.gos", false);
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
 }
 
 #[test]
 fn test_blankid() {
     let result = run("./tests/group1/blankid.gos", true);
-    assert!(result.is_ok());
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
 }
 
 #[test]
 fn test_declare() {
     let result = run("./tests/group1/declare.gos", true);
-    assert!(result.is_ok());
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
 }
 
 #[test]
 fn test_slice1() {
     let result = run("./tests/group1/slice1.gos", true);
-    assert!(result.is_ok());
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
+}
+
+#[test]
+fn test_bounds() {
+    let result = run("./tests/group1/bounds.gos", false);
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
 }
 
 #[test]
 fn test_map1() {
     let result = run("./tests/group1/map1.gos", true);
-    assert!(result.is_ok());
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
 }
 
 #[test]
 fn test_pkg() {
     let result = run("./tests/group1/pkg.gos", true);
-    assert!(result.is_ok());
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
 }
 
 #[test]
 fn test_type1() {
     let result = run("./tests/group1/type1.gos", true);
-    assert!(result.is_ok());
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
 }
 
 #[test]
 fn test_pointer() {
     let result = run("./tests/group1/pointer.gos", true);
-    assert!(result.is_ok());
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
+}
+
+#[test]
+fn test_escape() {
+    let result = run("./tests/group1/escape.gos", true);
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
 }
 
 #[test]
 fn test_operations() {
     let result = run("./tests/group1/operations.gos", true);
-    assert!(result.is_ok());
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
 }
 
 #[test]
 fn test_if() {
     let result = run("./tests/group1/if.gos", true);
-    assert!(result.is_ok());
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
 }
 
 #[test]
 fn test_for() {
     let result = run("./tests/group1/for.gos", true);
-    assert!(result.is_ok());
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
 }
 
 #[test]
 fn test_interface1() {
     let result = run("./tests/group1/interface1.gos", true);
-    assert!(result.is_ok());
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
 }
 
 #[test]
 fn test_interface2() {
     let result = run("./tests/group1/interface2.gos", true);
-    assert!(result.is_ok());
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
+}
+
+#[test]
+fn test_interface3() {
+    let result = run("./tests/group1/interface3.gos", true);
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
 }
 
 #[test]
 fn test_initorder() {
     let result = run("./tests/group1/initorder.gos", true);
-    assert!(result.is_ok());
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
+}
+
+#[test]
+fn test_pkg_init() {
+    let result = run("./tests/pkginit/main.gos", false);
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
 }
 
 #[test]
 fn test_switch() {
     let result = run("./tests/group1/switch.gos", true);
-    assert!(result.is_ok());
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
 }
 
 #[test]
 fn test_typeswitch() {
     let result = run("./tests/group1/typeswitch.gos", true);
-    assert!(result.is_ok());
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
+}
+
+#[test]
+fn test_typeswitch2() {
+    let result = run("./tests/group1/typeswitch2.gos", false);
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
 }
 
 #[test]
 fn test_basictypes() {
     let result = run("./tests/group1/basictypes.gos", true);
-    assert!(result.is_ok());
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
 }
 
 #[test]
 fn test_composite() {
     let result = run("./tests/group1/composite.gos", true);
-    assert!(result.is_ok());
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
 }
 
 #[test]
 fn test_array() {
     let result = run("./tests/group1/array.gos", true);
-    assert!(result.is_ok());
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
 }
 
 #[test]
 fn test_conversion() {
     let result = run("./tests/group1/conversion.gos", true);
-    assert!(result.is_ok());
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
+}
+
+#[test]
+fn test_conversion2() {
+    let result = run("./tests/group1/conversion2.gos", false);
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
+}
+
+#[test]
+fn test_constant() {
+    let result = run("./tests/group1/constant.gos", false);
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
 }
 
 #[test]
 fn test_async() {
     let result = run("./tests/group1/async.gos", true);
-    assert!(result.is_ok());
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
 }
 
 #[test]
 fn test_channel() {
     let result = run("./tests/group1/channel.gos", true);
-    assert!(result.is_ok());
+    assert_eq!(result.unwrap(), engine::RunResult::Done);
 }
 
 #[test]
 fn test_defer() {
     let result = run("./tests/group1/defer.gos", true);
-    assert!(result.is_ok());
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
 }
 
 #[test]
 fn test_goto() {
     let result = run("./tests/group1/goto.gos", true);
-    assert!(result.is_ok());
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
+}
+
+#[test]
+fn test_label() {
+    let result = run("./tests/group1/label.gos", false);
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
 }
 
 #[test]
 fn test_recover() {
     let result = run("./tests/group1/recover.gos", true);
-    assert!(result.is_ok());
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
+}
+
+#[test]
+fn test_budget() {
+    let result = run("./tests/group1/budget.gos", false);
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
+}
+
+#[test]
+fn test_memoize() {
+    let result = run("./tests/group1/memoize.gos", false);
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
+}
+
+#[test]
+fn test_fold() {
+    let result = run("./tests/group1/fold.gos", false);
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
+}
+
+#[test]
+fn test_fold_jump_chains() {
+    use goscript_vm::instruction::Opcode;
+    let engine = engine::Engine::new();
+    let reader = goscript_codegen::FsReader::new(Some("./"), Some("../std/"), None);
+    let mut fs = goscript_parser::FileSet::new();
+    let code = engine
+        .compile(&reader, "./tests/group1/fold.gos", &mut fs)
+        .unwrap();
+    // no jump lands on an unconditional jump, unless it's a loop to itself
+    for (_, func) in code.objects.functions.iter() {
+        let code = func.code();
+        for (i, inst) in code.iter().enumerate() {
+            if let Opcode::JUMP | Opcode::JUMP_IF | Opcode::JUMP_IF_NOT = inst.op() {
+                let target = (i as i32 + 1 + inst.imm()) as usize;
+                assert!(target == i || code[target].op() != Opcode::JUMP);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_vet_warnings() {
+    let engine = engine::Engine::new();
+    let reader = goscript_codegen::FsReader::new(Some("./"), Some("../std/"), None);
+    let mut fs = goscript_parser::FileSet::new();
+    let code = engine
+        .compile(&reader, "./tests/group1/vet_err.gos", &mut fs)
+        .unwrap();
+    let warnings = engine.warnings();
+    warnings.sort();
+    let msgs: Vec<(usize, String)> = warnings
+        .borrow()
+        .iter()
+        .map(|w| (w.pos.line, w.msg.clone()))
+        .collect();
+    assert_eq!(
+        msgs,
+        vec![
+            (
+                17,
+                "declaration of \"err\" shadows declaration at line 13".to_owned()
+            ),
+            (28, "this value of err is never used".to_owned()),
+        ]
+    );
+    engine.run_code(code, Some(&fs));
+}
+
+#[test]
+fn test_os_args() {
+    let mut cfg = engine::run_fs::Config::default();
+    cfg.working_dir = Some("./");
+    cfg.base_dir = Some("../std/");
+    cfg.program_name = Some("greet");
+    cfg.env = Some(&[]);
+    let args = ["-v", "-name", "gopher", "a b", ""];
+    let result = engine::run_fs::run_with_args(cfg, "./tests/group1/os_args.gos", &args);
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
+}
+
+#[test]
+fn test_os_env_exit() {
+    let engine = engine::Engine::new();
+    engine.set_env(&[("APP_MODE", "test"), ("EMPTY", "")]);
+    let out = WriteBuf::new();
+    engine.set_std_io(None, Some(Box::new(out.clone())), None);
+    let reader = goscript_codegen::FsReader::new(Some("./"), Some("../std/"), None);
+    let result = engine.run(false, false, &reader, "./tests/group1/os_env.gos");
+    assert_eq!(result.unwrap(), engine::RunResult::Exit(3));
+    assert_eq!(engine.exit_code(), Some(3));
+
+    let result = engine.run(false, false, &reader, "./tests/group1/range_chan.gos");
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
+    assert_eq!(engine.exit_code(), None);
+}
+
+#[test]
+fn test_limits() {
+    let source = r#"
+    package main
+
+    import "time"
+
+    type node struct {
+        id int
+    }
+
+    func main() {
+        var nodes []*node
+        for i := 0; ; i++ {
+            switch mode {
+            case "spin":
+            case "sleep":
+                time.Sleep(time.Hour)
+            case "grow":
+                nodes = append(nodes, &node{i})
+            }
+        }
+    }
+    "#;
+    let run_with = |mode: &str, limits: engine::Limits| {
+        let mut cfg = engine::run_fs::Config::default();
+        cfg.working_dir = Some("./");
+        cfg.base_dir = Some("../std/");
+        cfg.limits = limits;
+        let source = format!("{}\nconst mode = \"{}\"\n", source, mode);
+        engine::run_fs::run_string(cfg, &source).unwrap()
+    };
+    let limits = engine::Limits {
+        max_instructions: Some(100_000),
+        ..Default::default()
+    };
+    let result = run_with("spin", limits);
+    assert_eq!(result, engine::RunResult::LimitExceeded(engine::Limit::Instructions(100_000)));
+
+    let max_time = std::time::Duration::from_millis(50);
+    let limits = engine::Limits {
+        max_time: Some(max_time),
+        ..Default::default()
+    };
+    let start = std::time::Instant::now();
+    let result = run_with("sleep", limits);
+    assert_eq!(result, engine::RunResult::LimitExceeded(engine::Limit::Time(max_time)));
+    assert!(start.elapsed() < std::time::Duration::from_secs(5));
+    let result = run_with("spin", limits);
+    assert_eq!(result, engine::RunResult::LimitExceeded(engine::Limit::Time(max_time)));
+
+    let limits = engine::Limits {
+        max_objects: Some(10_000),
+        ..Default::default()
+    };
+    let result = run_with("grow", limits);
+    assert_eq!(result, engine::RunResult::LimitExceeded(engine::Limit::Objects(10_000)));
+}
+
+#[test]
+fn test_debug_stack() {
+    let result = run("./tests/group1/debug_stack.gos", false);
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
+}
+
+#[test]
+fn test_range_chan() {
+    let result = run("./tests/group1/range_chan.gos", false);
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
+}
+
+#[test]
+fn test_nil_composite() {
+    let result = run("./tests/group1/nil_composite.gos", false);
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
 }
 
 #[test]
 fn test_select() {
     let result = run("./tests/group1/select.gos", true);
-    assert!(result.is_ok());
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
+}
+
+#[test]
+fn test_select_cases() {
+    let result = run("./tests/group1/select2.gos", false);
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
 }
 
 #[test]
 fn test_init_func() {
     let result = run("./tests/group1/init_func.gos", true);
-    assert!(result.is_ok());
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
 }
 
 #[test]
 fn test_complex() {
     let result = run("./tests/group1/complex.gos", true);
-    assert!(result.is_ok());
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
 }
 
 #[test]
 fn test_linked() {
     let result = run("./tests/demo/linked.gos", true);
-    assert!(result.is_ok());
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
 }
 
 #[test]
@@ -377,7 +745,7 @@ fn test_leetcode5() {
     time_test!();
 
     let result = run("./tests/demo/leetcode5.gos", true);
-    assert!(result.is_ok());
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
 }
 
 #[test]
@@ -385,7 +753,7 @@ fn test_fibonacci() {
     time_test!();
 
     let result = run("./tests/demo/fibonacci.gos", true);
-    assert!(result.is_ok());
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
 }
 
 #[test]
@@ -393,7 +761,7 @@ fn test_issue8() {
     time_test!();
 
     let result = run("./tests/issues/issue8.gos", true);
-    assert!(result.is_ok());
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
 }
 
 #[test]
@@ -401,7 +769,7 @@ fn test_std_math() {
     time_test!();
 
     let result = run("./tests/std/math.gos", false);
-    assert!(result.is_ok());
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
 }
 
 #[test]
@@ -409,7 +777,7 @@ fn test_std_strconv() {
     time_test!();
 
     let result = run("./tests/std/strconv.gos", false);
-    assert!(result.is_ok());
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
 }
 
 #[test]
@@ -417,7 +785,7 @@ fn test_std_reflect() {
     time_test!();
 
     let result = run("./tests/std/reflect.gos", false);
-    assert!(result.is_ok());
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
 }
 
 #[test]
@@ -425,7 +793,39 @@ fn test_std_time() {
     time_test!();
 
     let result = run("./tests/std/time.gos", false);
-    assert!(result.is_ok());
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
+}
+
+#[test]
+fn test_std_timer() {
+    time_test!();
+
+    let result = run("./tests/std/timer.gos", false);
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
+}
+
+#[test]
+fn test_std_ratelimit() {
+    time_test!();
+
+    let result = run("./tests/std/ratelimit.gos", false);
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
+}
+
+#[test]
+fn test_std_errors() {
+    time_test!();
+
+    let result = run("./tests/std/errors.gos", false);
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
+}
+
+#[test]
+fn test_std_json() {
+    time_test!();
+
+    let result = run("./tests/std/json.gos", false);
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
 }
 
 #[test]
@@ -433,7 +833,7 @@ fn test_std_sort() {
     time_test!();
 
     let result = run("./tests/std/sort.gos", false);
-    assert!(result.is_ok());
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
 }
 
 #[test]
@@ -441,7 +841,7 @@ fn test_std_path() {
     time_test!();
 
     let result = run("./tests/std/path.gos", false);
-    assert!(result.is_ok());
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
 }
 
 #[test]
@@ -449,7 +849,31 @@ fn test_std_bytes() {
     time_test!();
 
     let result = run("./tests/std/bytes.gos", false);
-    assert!(result.is_ok());
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
+}
+
+#[test]
+fn test_std_bufio() {
+    time_test!();
+
+    let result = run("./tests/std/bufio.gos", false);
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
+}
+
+#[test]
+fn test_std_rand() {
+    time_test!();
+
+    let result = run("./tests/std/rand.gos", false);
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
+}
+
+#[test]
+fn test_std_utf8() {
+    time_test!();
+
+    let result = run("./tests/std/utf8.gos", false);
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
 }
 
 #[test]
@@ -457,7 +881,7 @@ fn test_std_strings() {
     time_test!();
 
     let result = run("./tests/std/strings.gos", false);
-    assert!(result.is_ok());
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
 }
 
 #[test]
@@ -465,7 +889,7 @@ fn test_std_fmt() {
     time_test!();
 
     let result = run("./tests/std/fmt.gos", false);
-    assert!(result.is_ok());
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
 }
 
 #[test]
@@ -473,5 +897,11 @@ fn test_std_temp() {
     time_test!();
 
     let result = run("./tests/std/temp.gos", true);
-    assert!(result.is_ok());
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
+}
+
+#[test]
+fn test_gc() {
+    let result = run("./tests/group1/gc.gos", false);
+    assert!(matches!(result, Ok(engine::RunResult::Done)));
 }
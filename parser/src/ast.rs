@@ -18,6 +18,7 @@ use super::token;
 use std::collections::HashMap;
 use std::hash::Hash;
 use std::rc::Rc;
+use std::time::Duration;
 
 /// NodeId can be used as key of HashMaps
 #[derive(PartialEq, Eq, Hash, Clone, Debug)]
@@ -495,6 +496,7 @@ pub struct File {
     pub scope: ScopeKey,
     pub imports: Vec<SpecKey>, //ImportSpec
     pub unresolved: Vec<IdentKey>,
+    pub doc: Option<String>,
 }
 
 impl Node for File {
@@ -851,6 +853,7 @@ pub struct ValueSpec {
     pub names: Vec<IdentKey>,
     pub typ: Option<Expr>,
     pub values: Vec<Expr>,
+    pub doc: Option<String>,
 }
 
 // A TypeSpec node represents a type declaration (TypeSpec production).
@@ -859,6 +862,7 @@ pub struct TypeSpec {
     pub name: IdentKey,
     pub assign: position::Pos,
     pub typ: Expr,
+    pub doc: Option<String>,
 }
 
 #[derive(Debug)]
@@ -884,6 +888,7 @@ pub struct GenDecl {
     pub l_paran: Option<position::Pos>,
     pub specs: Vec<SpecKey>,
     pub r_paren: Option<position::Pos>,
+    pub doc: Option<String>,
 }
 
 // A FuncDecl node represents a function declaration.
@@ -893,6 +898,8 @@ pub struct FuncDecl {
     pub name: IdentKey,
     pub typ: FuncTypeKey,
     pub body: Option<Rc<BlockStmt>>,
+    pub pragmas: Vec<Pragma>,
+    pub doc: Option<String>,
 }
 
 impl FuncDecl {
@@ -901,6 +908,27 @@ impl FuncDecl {
     }
 }
 
+// A Pragma is a `//gos:` directive in the comments right before a function
+// declaration, like `//gos:timeout 50ms`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Pragma {
+    // Each call of the function must return within the duration, or it panics
+    Timeout(Duration),
+    // The results of the function are cached by its arguments, in an LRU of
+    // the given capacity
+    Memoize(usize),
+    // The code of the function is kept as generated, no instructions are
+    // fused or jumps retargeted in it
+    NoOpt,
+    // The slice elements indexed in the function by the key of a range loop
+    // over the same slice are not checked against the length of the slice,
+    // when neither the slice nor the key changes in the function
+    NoBounds,
+    // The function has no side effects, which the type checker verifies, so
+    // calls whose results are unused can be left out
+    Pure,
+}
+
 #[derive(Debug)]
 pub struct BadStmt {
     pub from: position::Pos,
@@ -0,0 +1,1707 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Serializes a parsed file to JSON and back, so that tools written in other
+//! languages can work on the AST.
+//!
+//! Every node is a JSON object with a "kind", named after the Rust type of
+//! the node (e.g. "BinaryExpr", "IfStmt"), an "id" and the fields of that
+//! type under their Rust names. Positions are the `Pos` values of the parser,
+//! they are offsets in the FileSet; the optional "source" object of the file
+//! carries what is needed to turn them into lines and columns.
+//!
+//! The ids are numbered in depth first order starting with 0 for the file.
+//! `Encoder::ids` and `Decoder::ids` map them to the `NodeId`s of the AST,
+//! decoding accepts nodes without an id.
+//!
+//! Resolution data (scopes and the entities of identifiers) is not part of
+//! the format, the decoded file has an empty scope.
+
+use super::ast::*;
+use super::objects::*;
+use super::position;
+use super::scope::Scope;
+use super::token::Token;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::rc::Rc;
+use std::time::Duration;
+
+/// Encodes `file` to JSON, the `source` file is included if provided.
+pub fn to_json(
+    file: &File,
+    objs: &Objects,
+    source: Option<&position::File>,
+    pretty: bool,
+) -> String {
+    Encoder::new(objs).encode_file(file, source, pretty)
+}
+
+/// Decodes a file encoded by `to_json`, the nodes are added to `objs` and
+/// the source file, if any, to `fs`.
+pub fn from_json(
+    json: &str,
+    objs: &mut Objects,
+    fs: &mut position::FileSet,
+) -> Result<File, String> {
+    Decoder::new(objs, fs).decode_file(json)
+}
+
+pub struct Encoder<'a> {
+    objs: &'a Objects,
+    ids: HashMap<NodeId, usize>,
+}
+
+impl<'a> Encoder<'a> {
+    pub fn new(objs: &'a Objects) -> Encoder<'a> {
+        Encoder {
+            objs: objs,
+            ids: HashMap::new(),
+        }
+    }
+
+    /// The ids given to the nodes encoded so far.
+    pub fn ids(&self) -> &HashMap<NodeId, usize> {
+        &self.ids
+    }
+
+    pub fn encode_file(
+        &mut self,
+        file: &File,
+        source: Option<&position::File>,
+        pretty: bool,
+    ) -> String {
+        let mut o = self.node("File", file.id());
+        o.push(("package".to_owned(), Json::pos(file.package)));
+        o.push(("name".to_owned(), self.ident(file.name)));
+        let decls = file.decls.iter().map(|d| self.decl(d)).collect();
+        o.push(("decls".to_owned(), Json::Array(decls)));
+        let unresolved = file
+            .unresolved
+            .iter()
+            .map(|i| Json::pos(self.id(NodeId::IdentExpr(*i))))
+            .collect();
+        o.push(("unresolved".to_owned(), Json::Array(unresolved)));
+        o.push(("doc".to_owned(), Json::opt_str(&file.doc)));
+        if let Some(f) = source {
+            let lines = f.lines().iter().map(|l| Json::pos(*l)).collect();
+            let src = vec![
+                ("name".to_owned(), Json::Str(f.name().to_owned())),
+                ("base".to_owned(), Json::pos(f.base())),
+                ("size".to_owned(), Json::pos(f.size())),
+                ("lines".to_owned(), Json::Array(lines)),
+            ];
+            o.push(("source".to_owned(), Json::Object(src)));
+        }
+        let mut out = String::new();
+        Json::Object(o).write(&mut out, if pretty { Some(0) } else { None });
+        out
+    }
+
+    fn id(&mut self, id: NodeId) -> usize {
+        let next = self.ids.len();
+        *self.ids.entry(id).or_insert(next)
+    }
+
+    fn node(&mut self, kind: &str, id: NodeId) -> Vec<(String, Json)> {
+        let id = self.id(id);
+        vec![
+            ("kind".to_owned(), Json::Str(kind.to_owned())),
+            ("id".to_owned(), Json::pos(id)),
+        ]
+    }
+
+    fn ident(&mut self, key: IdentKey) -> Json {
+        let ident = &self.objs.idents[key];
+        let mut o = self.node("Ident", NodeId::IdentExpr(key));
+        o.push(("pos".to_owned(), Json::pos(ident.pos)));
+        o.push(("name".to_owned(), Json::Str(ident.name.clone())));
+        Json::Object(o)
+    }
+
+    fn idents(&mut self, keys: &[IdentKey]) -> Json {
+        Json::Array(keys.iter().map(|k| self.ident(*k)).collect())
+    }
+
+    fn opt_ident(&mut self, key: &Option<IdentKey>) -> Json {
+        key.map_or(Json::Null, |k| self.ident(k))
+    }
+
+    fn basic_lit(&mut self, lit: &BasicLit, id: NodeId) -> Json {
+        let mut o = self.node("BasicLit", id);
+        o.push(("pos".to_owned(), Json::pos(lit.pos)));
+        o.push(("token".to_owned(), Json::Str(lit.token.text().to_owned())));
+        match &lit.token {
+            Token::INT(l) | Token::FLOAT(l) | Token::IMAG(l) => {
+                o.push(("lit".to_owned(), Json::Str(l.as_str().clone())));
+            }
+            Token::CHAR(l) => {
+                let (lit, c) = l.as_str_char();
+                o.push(("lit".to_owned(), Json::Str(lit.clone())));
+                o.push(("value".to_owned(), Json::Str(c.to_string())));
+            }
+            Token::STRING(l) => {
+                o.push(("lit".to_owned(), Json::Str(l.as_str().clone())));
+                let value = l
+                    .unquoted_str()
+                    .map_or(Json::Null, |s| Json::Str(s.clone()));
+                o.push(("value".to_owned(), value));
+            }
+            _ => {}
+        }
+        Json::Object(o)
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Json {
+        let o = match expr {
+            Expr::Bad(e) => {
+                let mut o = self.node("BadExpr", expr.id());
+                o.push(("from".to_owned(), Json::pos(e.from)));
+                o.push(("to".to_owned(), Json::pos(e.to)));
+                o
+            }
+            Expr::Ident(i) => return self.ident(*i),
+            Expr::Ellipsis(e) => {
+                let mut o = self.node("Ellipsis", expr.id());
+                o.push(("pos".to_owned(), Json::pos(e.pos)));
+                o.push(("elt".to_owned(), self.opt_expr(&e.elt)));
+                o
+            }
+            Expr::BasicLit(e) => return self.basic_lit(e, expr.id()),
+            Expr::FuncLit(e) => {
+                let mut o = self.node("FuncLit", expr.id());
+                o.push((
+                    "typ".to_owned(),
+                    self.func_type(e.typ, NodeId::FuncType(e.typ)),
+                ));
+                o.push(("body".to_owned(), self.block(&e.body)));
+                o
+            }
+            Expr::CompositeLit(e) => {
+                let mut o = self.node("CompositeLit", expr.id());
+                o.push(("typ".to_owned(), self.opt_expr(&e.typ)));
+                o.push(("l_brace".to_owned(), Json::pos(e.l_brace)));
+                o.push(("elts".to_owned(), self.exprs(&e.elts)));
+                o.push(("r_brace".to_owned(), Json::pos(e.r_brace)));
+                o.push(("incomplete".to_owned(), Json::Bool(e.incomplete)));
+                o
+            }
+            Expr::Paren(e) => {
+                let mut o = self.node("ParenExpr", expr.id());
+                o.push(("l_paren".to_owned(), Json::pos(e.l_paren)));
+                o.push(("expr".to_owned(), self.expr(&e.expr)));
+                o.push(("r_paren".to_owned(), Json::pos(e.r_paren)));
+                o
+            }
+            Expr::Selector(e) => {
+                let mut o = self.node("SelectorExpr", expr.id());
+                o.push(("expr".to_owned(), self.expr(&e.expr)));
+                o.push(("sel".to_owned(), self.ident(e.sel)));
+                o
+            }
+            Expr::Index(e) => {
+                let mut o = self.node("IndexExpr", expr.id());
+                o.push(("expr".to_owned(), self.expr(&e.expr)));
+                o.push(("l_brack".to_owned(), Json::pos(e.l_brack)));
+                o.push(("index".to_owned(), self.expr(&e.index)));
+                o.push(("r_brack".to_owned(), Json::pos(e.r_brack)));
+                o
+            }
+            Expr::Slice(e) => {
+                let mut o = self.node("SliceExpr", expr.id());
+                o.push(("expr".to_owned(), self.expr(&e.expr)));
+                o.push(("l_brack".to_owned(), Json::pos(e.l_brack)));
+                o.push(("low".to_owned(), self.opt_expr(&e.low)));
+                o.push(("high".to_owned(), self.opt_expr(&e.high)));
+                o.push(("max".to_owned(), self.opt_expr(&e.max)));
+                o.push(("slice3".to_owned(), Json::Bool(e.slice3)));
+                o.push(("r_brack".to_owned(), Json::pos(e.r_brack)));
+                o
+            }
+            Expr::TypeAssert(e) => {
+                let mut o = self.node("TypeAssertExpr", expr.id());
+                o.push(("expr".to_owned(), self.expr(&e.expr)));
+                o.push(("l_paren".to_owned(), Json::pos(e.l_paren)));
+                o.push(("typ".to_owned(), self.opt_expr(&e.typ)));
+                o.push(("r_paren".to_owned(), Json::pos(e.r_paren)));
+                o
+            }
+            Expr::Call(e) => {
+                let mut o = self.node("CallExpr", expr.id());
+                o.push(("func".to_owned(), self.expr(&e.func)));
+                o.push(("l_paren".to_owned(), Json::pos(e.l_paren)));
+                o.push(("args".to_owned(), self.exprs(&e.args)));
+                o.push(("ellipsis".to_owned(), Json::opt_pos(e.ellipsis)));
+                o.push(("r_paren".to_owned(), Json::pos(e.r_paren)));
+                o
+            }
+            Expr::Star(e) => {
+                let mut o = self.node("StarExpr", expr.id());
+                o.push(("star".to_owned(), Json::pos(e.star)));
+                o.push(("expr".to_owned(), self.expr(&e.expr)));
+                o
+            }
+            Expr::Unary(e) => {
+                let mut o = self.node("UnaryExpr", expr.id());
+                o.push(("op_pos".to_owned(), Json::pos(e.op_pos)));
+                o.push(("op".to_owned(), Json::token(&e.op)));
+                o.push(("expr".to_owned(), self.expr(&e.expr)));
+                o
+            }
+            Expr::Binary(e) => {
+                let mut o = self.node("BinaryExpr", expr.id());
+                o.push(("expr_a".to_owned(), self.expr(&e.expr_a)));
+                o.push(("op_pos".to_owned(), Json::pos(e.op_pos)));
+                o.push(("op".to_owned(), Json::token(&e.op)));
+                o.push(("expr_b".to_owned(), self.expr(&e.expr_b)));
+                o
+            }
+            Expr::KeyValue(e) => {
+                let mut o = self.node("KeyValueExpr", expr.id());
+                o.push(("key".to_owned(), self.expr(&e.key)));
+                o.push(("colon".to_owned(), Json::pos(e.colon)));
+                o.push(("val".to_owned(), self.expr(&e.val)));
+                o
+            }
+            Expr::Array(e) => {
+                let mut o = self.node("ArrayType", expr.id());
+                o.push(("l_brack".to_owned(), Json::pos(e.l_brack)));
+                o.push(("len".to_owned(), self.opt_expr(&e.len)));
+                o.push(("elt".to_owned(), self.expr(&e.elt)));
+                o
+            }
+            Expr::Struct(e) => {
+                let mut o = self.node("StructType", expr.id());
+                o.push(("struct_pos".to_owned(), Json::pos(e.struct_pos)));
+                o.push(("fields".to_owned(), self.field_list(&e.fields)));
+                o.push(("incomplete".to_owned(), Json::Bool(e.incomplete)));
+                o
+            }
+            Expr::Func(f) => return self.func_type(*f, expr.id()),
+            Expr::Interface(e) => {
+                let mut o = self.node("InterfaceType", expr.id());
+                o.push(("interface".to_owned(), Json::pos(e.interface)));
+                o.push(("methods".to_owned(), self.field_list(&e.methods)));
+                o.push(("incomplete".to_owned(), Json::Bool(e.incomplete)));
+                o
+            }
+            Expr::Map(e) => {
+                let mut o = self.node("MapType", expr.id());
+                o.push(("map".to_owned(), Json::pos(e.map)));
+                o.push(("key".to_owned(), self.expr(&e.key)));
+                o.push(("val".to_owned(), self.expr(&e.val)));
+                o
+            }
+            Expr::Chan(e) => {
+                let mut o = self.node("ChanType", expr.id());
+                o.push(("begin".to_owned(), Json::pos(e.begin)));
+                o.push(("arrow".to_owned(), Json::pos(e.arrow)));
+                let dir = match e.dir {
+                    ChanDir::Send => "send",
+                    ChanDir::Recv => "recv",
+                    ChanDir::SendRecv => "send_recv",
+                };
+                o.push(("dir".to_owned(), Json::Str(dir.to_owned())));
+                o.push(("val".to_owned(), self.expr(&e.val)));
+                o
+            }
+        };
+        Json::Object(o)
+    }
+
+    fn opt_expr(&mut self, expr: &Option<Expr>) -> Json {
+        expr.as_ref().map_or(Json::Null, |e| self.expr(e))
+    }
+
+    fn exprs(&mut self, exprs: &[Expr]) -> Json {
+        Json::Array(exprs.iter().map(|e| self.expr(e)).collect())
+    }
+
+    fn func_type(&mut self, key: FuncTypeKey, id: NodeId) -> Json {
+        let ft = &self.objs.ftypes[key];
+        let mut o = self.node("FuncType", id);
+        o.push(("func".to_owned(), Json::opt_pos(ft.func)));
+        o.push(("params".to_owned(), self.field_list(&ft.params)));
+        let results = ft
+            .results
+            .as_ref()
+            .map_or(Json::Null, |r| self.field_list(r));
+        o.push(("results".to_owned(), results));
+        Json::Object(o)
+    }
+
+    fn field_list(&mut self, fl: &FieldList) -> Json {
+        let list = fl.list.iter().map(|f| self.field(*f)).collect();
+        Json::Object(vec![
+            ("openning".to_owned(), Json::opt_pos(fl.openning)),
+            ("list".to_owned(), Json::Array(list)),
+            ("closing".to_owned(), Json::opt_pos(fl.closing)),
+        ])
+    }
+
+    fn field(&mut self, key: FieldKey) -> Json {
+        let field = &self.objs.fields[key];
+        let mut o = self.node("Field", NodeId::Field(key));
+        o.push(("names".to_owned(), self.idents(&field.names)));
+        o.push(("typ".to_owned(), self.expr(&field.typ)));
+        o.push(("tag".to_owned(), self.opt_expr(&field.tag)));
+        Json::Object(o)
+    }
+
+    fn block(&mut self, block: &BlockStmt) -> Json {
+        let id = NodeId::Address(block as *const BlockStmt as usize);
+        let mut o = self.node("BlockStmt", id);
+        o.push(("l_brace".to_owned(), Json::pos(block.l_brace)));
+        o.push(("list".to_owned(), self.stmts(&block.list)));
+        o.push(("r_brace".to_owned(), Json::pos(block.r_brace)));
+        Json::Object(o)
+    }
+
+    fn stmts(&mut self, stmts: &[Stmt]) -> Json {
+        Json::Array(stmts.iter().map(|s| self.stmt(s)).collect())
+    }
+
+    fn opt_stmt(&mut self, stmt: &Option<Stmt>) -> Json {
+        stmt.as_ref().map_or(Json::Null, |s| self.stmt(s))
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) -> Json {
+        let o = match stmt {
+            Stmt::Bad(s) => {
+                let mut o = self.node("BadStmt", stmt.id());
+                o.push(("from".to_owned(), Json::pos(s.from)));
+                o.push(("to".to_owned(), Json::pos(s.to)));
+                o
+            }
+            Stmt::Decl(d) => {
+                let mut o = self.node("DeclStmt", stmt.id());
+                o.push(("decl".to_owned(), self.decl(d)));
+                o
+            }
+            Stmt::Empty(s) => {
+                let mut o = self.node("EmptyStmt", stmt.id());
+                o.push(("semi".to_owned(), Json::pos(s.semi)));
+                o.push(("implicit".to_owned(), Json::Bool(s.implicit)));
+                o
+            }
+            Stmt::Labeled(key) => {
+                let s = &self.objs.l_stmts[*key];
+                let mut o = self.node("LabeledStmt", stmt.id());
+                o.push(("label".to_owned(), self.ident(s.label)));
+                o.push(("colon".to_owned(), Json::pos(s.colon)));
+                o.push(("stmt".to_owned(), self.stmt(&s.stmt)));
+                o
+            }
+            Stmt::Expr(e) => {
+                let mut o = self.node("ExprStmt", stmt.id());
+                o.push(("expr".to_owned(), self.expr(e)));
+                o
+            }
+            Stmt::Send(s) => {
+                let mut o = self.node("SendStmt", stmt.id());
+                o.push(("chan".to_owned(), self.expr(&s.chan)));
+                o.push(("arrow".to_owned(), Json::pos(s.arrow)));
+                o.push(("val".to_owned(), self.expr(&s.val)));
+                o
+            }
+            Stmt::IncDec(s) => {
+                let mut o = self.node("IncDecStmt", stmt.id());
+                o.push(("expr".to_owned(), self.expr(&s.expr)));
+                o.push(("token_pos".to_owned(), Json::pos(s.token_pos)));
+                o.push(("token".to_owned(), Json::token(&s.token)));
+                o
+            }
+            Stmt::Assign(key) => {
+                let s = &self.objs.a_stmts[*key];
+                let mut o = self.node("AssignStmt", stmt.id());
+                o.push(("lhs".to_owned(), self.exprs(&s.lhs)));
+                o.push(("token_pos".to_owned(), Json::pos(s.token_pos)));
+                o.push(("token".to_owned(), Json::token(&s.token)));
+                o.push(("rhs".to_owned(), self.exprs(&s.rhs)));
+                o
+            }
+            Stmt::Go(s) => {
+                let mut o = self.node("GoStmt", stmt.id());
+                o.push(("go".to_owned(), Json::pos(s.go)));
+                o.push(("call".to_owned(), self.expr(&s.call)));
+                o
+            }
+            Stmt::Defer(s) => {
+                let mut o = self.node("DeferStmt", stmt.id());
+                o.push(("defer".to_owned(), Json::pos(s.defer)));
+                o.push(("call".to_owned(), self.expr(&s.call)));
+                o
+            }
+            Stmt::Return(s) => {
+                let mut o = self.node("ReturnStmt", stmt.id());
+                o.push(("ret".to_owned(), Json::pos(s.ret)));
+                o.push(("results".to_owned(), self.exprs(&s.results)));
+                o
+            }
+            Stmt::Branch(s) => {
+                let mut o = self.node("BranchStmt", stmt.id());
+                o.push(("token_pos".to_owned(), Json::pos(s.token_pos)));
+                o.push(("token".to_owned(), Json::token(&s.token)));
+                o.push(("label".to_owned(), self.opt_ident(&s.label)));
+                o
+            }
+            Stmt::Block(s) => return self.block(s),
+            Stmt::If(s) => {
+                let mut o = self.node("IfStmt", stmt.id());
+                o.push(("if_pos".to_owned(), Json::pos(s.if_pos)));
+                o.push(("init".to_owned(), self.opt_stmt(&s.init)));
+                o.push(("cond".to_owned(), self.expr(&s.cond)));
+                o.push(("body".to_owned(), self.block(&s.body)));
+                o.push(("els".to_owned(), self.opt_stmt(&s.els)));
+                o
+            }
+            Stmt::Case(s) => {
+                let mut o = self.node("CaseClause", stmt.id());
+                o.push(("case".to_owned(), Json::pos(s.case)));
+                let list = s.list.as_ref().map_or(Json::Null, |l| self.exprs(l));
+                o.push(("list".to_owned(), list));
+                o.push(("colon".to_owned(), Json::pos(s.colon)));
+                o.push(("body".to_owned(), self.stmts(&s.body)));
+                o
+            }
+            Stmt::Switch(s) => {
+                let mut o = self.node("SwitchStmt", stmt.id());
+                o.push(("switch".to_owned(), Json::pos(s.switch)));
+                o.push(("init".to_owned(), self.opt_stmt(&s.init)));
+                o.push(("tag".to_owned(), self.opt_expr(&s.tag)));
+                o.push(("body".to_owned(), self.block(&s.body)));
+                o
+            }
+            Stmt::TypeSwitch(s) => {
+                let mut o = self.node("TypeSwitchStmt", stmt.id());
+                o.push(("switch".to_owned(), Json::pos(s.switch)));
+                o.push(("init".to_owned(), self.opt_stmt(&s.init)));
+                o.push(("assign".to_owned(), self.stmt(&s.assign)));
+                o.push(("body".to_owned(), self.block(&s.body)));
+                o
+            }
+            Stmt::Comm(s) => {
+                let mut o = self.node("CommClause", stmt.id());
+                o.push(("case".to_owned(), Json::pos(s.case)));
+                o.push(("comm".to_owned(), self.opt_stmt(&s.comm)));
+                o.push(("colon".to_owned(), Json::pos(s.colon)));
+                o.push(("body".to_owned(), self.stmts(&s.body)));
+                o
+            }
+            Stmt::Select(s) => {
+                let mut o = self.node("SelectStmt", stmt.id());
+                o.push(("select".to_owned(), Json::pos(s.select)));
+                o.push(("body".to_owned(), self.block(&s.body)));
+                o
+            }
+            Stmt::For(s) => {
+                let mut o = self.node("ForStmt", stmt.id());
+                o.push(("for_pos".to_owned(), Json::pos(s.for_pos)));
+                o.push(("init".to_owned(), self.opt_stmt(&s.init)));
+                o.push(("cond".to_owned(), self.opt_expr(&s.cond)));
+                o.push(("post".to_owned(), self.opt_stmt(&s.post)));
+                o.push(("body".to_owned(), self.block(&s.body)));
+                o
+            }
+            Stmt::Range(s) => {
+                let mut o = self.node("RangeStmt", stmt.id());
+                o.push(("for_pos".to_owned(), Json::pos(s.for_pos)));
+                o.push(("key".to_owned(), self.opt_expr(&s.key)));
+                o.push(("val".to_owned(), self.opt_expr(&s.val)));
+                o.push(("token_pos".to_owned(), Json::pos(s.token_pos)));
+                o.push(("token".to_owned(), Json::token(&s.token)));
+                o.push(("expr".to_owned(), self.expr(&s.expr)));
+                o.push(("body".to_owned(), self.block(&s.body)));
+                o
+            }
+        };
+        Json::Object(o)
+    }
+
+    fn spec(&mut self, key: SpecKey) -> Json {
+        let spec = &self.objs.specs[key];
+        let o = match spec {
+            Spec::Import(s) => {
+                let mut o = self.node("ImportSpec", spec.id());
+                o.push(("name".to_owned(), self.opt_ident(&s.name)));
+                let path_id = NodeId::Address(&s.path as *const BasicLit as usize);
+                o.push(("path".to_owned(), self.basic_lit(&s.path, path_id)));
+                o.push(("end_pos".to_owned(), Json::opt_pos(s.end_pos)));
+                o
+            }
+            Spec::Value(s) => {
+                let mut o = self.node("ValueSpec", spec.id());
+                o.push(("names".to_owned(), self.idents(&s.names)));
+                o.push(("typ".to_owned(), self.opt_expr(&s.typ)));
+                o.push(("values".to_owned(), self.exprs(&s.values)));
+                o.push(("doc".to_owned(), Json::opt_str(&s.doc)));
+                o
+            }
+            Spec::Type(s) => {
+                let mut o = self.node("TypeSpec", spec.id());
+                o.push(("name".to_owned(), self.ident(s.name)));
+                o.push(("assign".to_owned(), Json::pos(s.assign)));
+                o.push(("typ".to_owned(), self.expr(&s.typ)));
+                o.push(("doc".to_owned(), Json::opt_str(&s.doc)));
+                o
+            }
+        };
+        Json::Object(o)
+    }
+
+    fn decl(&mut self, decl: &Decl) -> Json {
+        let o = match decl {
+            Decl::Bad(d) => {
+                let mut o = self.node("BadDecl", decl.id());
+                o.push(("from".to_owned(), Json::pos(d.from)));
+                o.push(("to".to_owned(), Json::pos(d.to)));
+                o
+            }
+            Decl::Gen(d) => {
+                let mut o = self.node("GenDecl", decl.id());
+                o.push(("token_pos".to_owned(), Json::pos(d.token_pos)));
+                o.push(("token".to_owned(), Json::token(&d.token)));
+                o.push(("l_paran".to_owned(), Json::opt_pos(d.l_paran)));
+                let specs = d.specs.iter().map(|s| self.spec(*s)).collect();
+                o.push(("specs".to_owned(), Json::Array(specs)));
+                o.push(("r_paren".to_owned(), Json::opt_pos(d.r_paren)));
+                o.push(("doc".to_owned(), Json::opt_str(&d.doc)));
+                o
+            }
+            Decl::Func(key) => {
+                let fd = &self.objs.fdecls[*key];
+                let mut o = self.node("FuncDecl", decl.id());
+                let recv = fd.recv.as_ref().map_or(Json::Null, |r| self.field_list(r));
+                o.push(("recv".to_owned(), recv));
+                o.push(("name".to_owned(), self.ident(fd.name)));
+                o.push((
+                    "typ".to_owned(),
+                    self.func_type(fd.typ, NodeId::FuncType(fd.typ)),
+                ));
+                let body = fd.body.as_ref().map_or(Json::Null, |b| self.block(b));
+                o.push(("body".to_owned(), body));
+                let pragmas = fd.pragmas.iter().map(pragma_to_json).collect();
+                o.push(("pragmas".to_owned(), Json::Array(pragmas)));
+                o.push(("doc".to_owned(), Json::opt_str(&fd.doc)));
+                o
+            }
+        };
+        Json::Object(o)
+    }
+}
+
+fn pragma_to_json(p: &Pragma) -> Json {
+    match p {
+        Pragma::Timeout(d) => Json::Object(vec![
+            ("kind".to_owned(), Json::Str("timeout".to_owned())),
+            ("nanos".to_owned(), Json::Num(d.as_nanos() as f64)),
+        ]),
+        Pragma::Memoize(n) => Json::Object(vec![
+            ("kind".to_owned(), Json::Str("memoize".to_owned())),
+            ("capacity".to_owned(), Json::pos(*n)),
+        ]),
+        Pragma::NoOpt => flag_pragma("noopt"),
+        Pragma::NoBounds => flag_pragma("nobounds"),
+        Pragma::Pure => flag_pragma("pure"),
+    }
+}
+
+fn flag_pragma(kind: &str) -> Json {
+    Json::Object(vec![("kind".to_owned(), Json::Str(kind.to_owned()))])
+}
+
+pub struct Decoder<'a> {
+    objs: &'a mut Objects,
+    fs: &'a mut position::FileSet,
+    ids: HashMap<usize, NodeId>,
+    idents: HashMap<usize, IdentKey>,
+    imports: Vec<SpecKey>,
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(objs: &'a mut Objects, fs: &'a mut position::FileSet) -> Decoder<'a> {
+        Decoder {
+            objs: objs,
+            fs: fs,
+            ids: HashMap::new(),
+            idents: HashMap::new(),
+            imports: vec![],
+        }
+    }
+
+    /// The nodes decoded so far, by the id they have in the JSON.
+    pub fn ids(&self) -> &HashMap<usize, NodeId> {
+        &self.ids
+    }
+
+    pub fn decode_file(&mut self, json: &str) -> Result<File, String> {
+        let json = Json::parse(json)?;
+        let o = Obj::new(&json, "File")?;
+        if let Some(src) = o.opt("source") {
+            self.source(&Obj::new(src, "")?)?;
+        }
+        let name = self.ident(o.get("name")?)?;
+        let decls = o
+            .arr("decls")?
+            .iter()
+            .map(|d| self.decl(d))
+            .collect::<Result<Vec<Decl>, String>>()?;
+        let unresolved = match o.opt("unresolved") {
+            Some(u) => u
+                .as_array("unresolved")?
+                .iter()
+                .map(|id| {
+                    let id = id.as_pos("unresolved")?;
+                    self.idents
+                        .get(&id)
+                        .copied()
+                        .ok_or_else(|| format!("ast json: no identifier has id {}", id))
+                })
+                .collect::<Result<Vec<IdentKey>, String>>()?,
+            None => vec![],
+        };
+        let file = File {
+            package: o.pos("package")?,
+            name: name,
+            decls: decls,
+            scope: self.objs.scopes.insert(Scope::new(None)),
+            imports: std::mem::take(&mut self.imports),
+            unresolved: unresolved,
+            doc: o.opt_str("doc")?,
+        };
+        self.record(&o, file.id())?;
+        Ok(file)
+    }
+
+    fn source(&mut self, o: &Obj) -> Result<(), String> {
+        let base = o.pos("base")?;
+        if base < self.fs.base() {
+            return Err(format!(
+                "ast json: source base {} overlaps the files already in the set",
+                base
+            ));
+        }
+        let lines = o
+            .arr("lines")?
+            .iter()
+            .map(|l| l.as_pos("lines"))
+            .collect::<Result<Vec<usize>, String>>()?;
+        let f = self
+            .fs
+            .add_file(o.str("name")?.to_owned(), Some(base), o.pos("size")?);
+        match f.set_lines(lines) {
+            true => Ok(()),
+            false => Err("ast json: invalid source lines".to_owned()),
+        }
+    }
+
+    fn record(&mut self, o: &Obj, id: NodeId) -> Result<(), String> {
+        if let Some(n) = o.opt("id") {
+            let n = n.as_pos("id")?;
+            self.ids.entry(n).or_insert(id);
+        }
+        Ok(())
+    }
+
+    fn ident(&mut self, j: &Json) -> Result<IdentKey, String> {
+        let o = Obj::new(j, "Ident")?;
+        let id = match o.opt("id") {
+            Some(n) => Some(n.as_pos("id")?),
+            None => None,
+        };
+        // the same identifier can be referred to more than once
+        if let Some(key) = id.and_then(|n| self.idents.get(&n)) {
+            return Ok(*key);
+        }
+        let key = self.objs.idents.insert(Ident {
+            pos: o.pos("pos")?,
+            name: o.str("name")?.to_owned(),
+            entity: IdentEntity::NoEntity,
+        });
+        if let Some(n) = id {
+            self.idents.insert(n, key);
+        }
+        self.record(&o, NodeId::IdentExpr(key))?;
+        Ok(key)
+    }
+
+    fn idents(&mut self, j: &Json, what: &str) -> Result<Vec<IdentKey>, String> {
+        j.as_array(what)?.iter().map(|i| self.ident(i)).collect()
+    }
+
+    fn opt_ident(&mut self, j: Option<&Json>) -> Result<Option<IdentKey>, String> {
+        j.map(|i| self.ident(i)).transpose()
+    }
+
+    fn basic_lit(&mut self, o: &Obj) -> Result<BasicLit, String> {
+        let lit = || o.str("lit").map(|s| s.to_owned());
+        let token = match o.str("token")? {
+            "INT" => Token::INT(lit()?.into()),
+            "FLOAT" => Token::FLOAT(lit()?.into()),
+            "IMAG" => Token::IMAG(lit()?.into()),
+            "CHAR" => {
+                let c = o.str("value")?.chars().next();
+                let c = c.ok_or_else(|| "ast json: empty value of CHAR".to_owned())?;
+                Token::CHAR((lit()?, c).into())
+            }
+            "STRING" => match o.opt_str("value")? {
+                Some(v) => Token::STRING((lit()?, v).into()),
+                None => Token::STRING(lit()?.into()),
+            },
+            t => return Err(format!("ast json: {} is not a literal token", t)),
+        };
+        Ok(BasicLit {
+            pos: o.pos("pos")?,
+            token: token,
+        })
+    }
+
+    fn opt_expr(&mut self, j: Option<&Json>) -> Result<Option<Expr>, String> {
+        j.map(|e| self.expr(e)).transpose()
+    }
+
+    fn exprs(&mut self, j: &Json, what: &str) -> Result<Vec<Expr>, String> {
+        j.as_array(what)?.iter().map(|e| self.expr(e)).collect()
+    }
+
+    fn expr(&mut self, j: &Json) -> Result<Expr, String> {
+        let o = Obj::new(j, "")?;
+        let expr = match o.kind {
+            "BadExpr" => Expr::Bad(Rc::new(BadExpr {
+                from: o.pos("from")?,
+                to: o.pos("to")?,
+            })),
+            "Ident" => return Ok(Expr::Ident(self.ident(j)?)),
+            "Ellipsis" => Expr::Ellipsis(Rc::new(Ellipsis {
+                pos: o.pos("pos")?,
+                elt: self.opt_expr(o.opt("elt"))?,
+            })),
+            "BasicLit" => Expr::BasicLit(Rc::new(self.basic_lit(&o)?)),
+            "FuncLit" => {
+                let typ = self.func_type(o.get("typ")?)?;
+                self.record(&Obj::new(o.get("typ")?, "")?, NodeId::FuncType(typ))?;
+                Expr::FuncLit(Rc::new(FuncLit {
+                    typ: typ,
+                    body: self.block(o.get("body")?)?,
+                }))
+            }
+            "CompositeLit" => Expr::CompositeLit(Rc::new(CompositeLit {
+                typ: self.opt_expr(o.opt("typ"))?,
+                l_brace: o.pos("l_brace")?,
+                elts: self.exprs(o.get("elts")?, "elts")?,
+                r_brace: o.pos("r_brace")?,
+                incomplete: o.bool("incomplete")?,
+            })),
+            "ParenExpr" => Expr::Paren(Rc::new(ParenExpr {
+                l_paren: o.pos("l_paren")?,
+                expr: self.expr(o.get("expr")?)?,
+                r_paren: o.pos("r_paren")?,
+            })),
+            "SelectorExpr" => Expr::Selector(Rc::new(SelectorExpr {
+                expr: self.expr(o.get("expr")?)?,
+                sel: self.ident(o.get("sel")?)?,
+            })),
+            "IndexExpr" => Expr::Index(Rc::new(IndexExpr {
+                expr: self.expr(o.get("expr")?)?,
+                l_brack: o.pos("l_brack")?,
+                index: self.expr(o.get("index")?)?,
+                r_brack: o.pos("r_brack")?,
+            })),
+            "SliceExpr" => Expr::Slice(Rc::new(SliceExpr {
+                expr: self.expr(o.get("expr")?)?,
+                l_brack: o.pos("l_brack")?,
+                low: self.opt_expr(o.opt("low"))?,
+                high: self.opt_expr(o.opt("high"))?,
+                max: self.opt_expr(o.opt("max"))?,
+                slice3: o.bool("slice3")?,
+                r_brack: o.pos("r_brack")?,
+            })),
+            "TypeAssertExpr" => Expr::TypeAssert(Rc::new(TypeAssertExpr {
+                expr: self.expr(o.get("expr")?)?,
+                l_paren: o.pos("l_paren")?,
+                typ: self.opt_expr(o.opt("typ"))?,
+                r_paren: o.pos("r_paren")?,
+            })),
+            "CallExpr" => Expr::Call(Rc::new(CallExpr {
+                func: self.expr(o.get("func")?)?,
+                l_paren: o.pos("l_paren")?,
+                args: self.exprs(o.get("args")?, "args")?,
+                ellipsis: o.opt_pos("ellipsis")?,
+                r_paren: o.pos("r_paren")?,
+            })),
+            "StarExpr" => Expr::Star(Rc::new(StarExpr {
+                star: o.pos("star")?,
+                expr: self.expr(o.get("expr")?)?,
+            })),
+            "UnaryExpr" => Expr::Unary(Rc::new(UnaryExpr {
+                op_pos: o.pos("op_pos")?,
+                op: o.token("op")?,
+                expr: self.expr(o.get("expr")?)?,
+            })),
+            "BinaryExpr" => Expr::Binary(Rc::new(BinaryExpr {
+                expr_a: self.expr(o.get("expr_a")?)?,
+                op_pos: o.pos("op_pos")?,
+                op: o.token("op")?,
+                expr_b: self.expr(o.get("expr_b")?)?,
+            })),
+            "KeyValueExpr" => Expr::KeyValue(Rc::new(KeyValueExpr {
+                key: self.expr(o.get("key")?)?,
+                colon: o.pos("colon")?,
+                val: self.expr(o.get("val")?)?,
+            })),
+            "ArrayType" => Expr::Array(Rc::new(ArrayType {
+                l_brack: o.pos("l_brack")?,
+                len: self.opt_expr(o.opt("len"))?,
+                elt: self.expr(o.get("elt")?)?,
+            })),
+            "StructType" => Expr::Struct(Rc::new(StructType {
+                struct_pos: o.pos("struct_pos")?,
+                fields: self.field_list(o.get("fields")?)?,
+                incomplete: o.bool("incomplete")?,
+            })),
+            "FuncType" => Expr::Func(self.func_type(j)?),
+            "InterfaceType" => Expr::Interface(Rc::new(InterfaceType {
+                interface: o.pos("interface")?,
+                methods: self.field_list(o.get("methods")?)?,
+                incomplete: o.bool("incomplete")?,
+            })),
+            "MapType" => Expr::Map(Rc::new(MapType {
+                map: o.pos("map")?,
+                key: self.expr(o.get("key")?)?,
+                val: self.expr(o.get("val")?)?,
+            })),
+            "ChanType" => Expr::Chan(Rc::new(ChanType {
+                begin: o.pos("begin")?,
+                arrow: o.pos("arrow")?,
+                dir: match o.str("dir")? {
+                    "send" => ChanDir::Send,
+                    "recv" => ChanDir::Recv,
+                    "send_recv" => ChanDir::SendRecv,
+                    d => return Err(format!("ast json: unknown channel direction {}", d)),
+                },
+                val: self.expr(o.get("val")?)?,
+            })),
+            k => return Err(format!("ast json: {} is not an expression", k)),
+        };
+        self.record(&o, expr.id())?;
+        Ok(expr)
+    }
+
+    fn func_type(&mut self, j: &Json) -> Result<FuncTypeKey, String> {
+        let o = Obj::new(j, "FuncType")?;
+        let results = match o.opt("results") {
+            Some(r) => Some(self.field_list(r)?),
+            None => None,
+        };
+        let ft = FuncType {
+            func: o.opt_pos("func")?,
+            params: self.field_list(o.get("params")?)?,
+            results: results,
+        };
+        Ok(self.objs.ftypes.insert(ft))
+    }
+
+    fn field_list(&mut self, j: &Json) -> Result<FieldList, String> {
+        let o = Obj::new(j, "")?;
+        let list = o
+            .arr("list")?
+            .iter()
+            .map(|f| self.field(f))
+            .collect::<Result<Vec<FieldKey>, String>>()?;
+        Ok(FieldList {
+            openning: o.opt_pos("openning")?,
+            list: list,
+            closing: o.opt_pos("closing")?,
+        })
+    }
+
+    fn field(&mut self, j: &Json) -> Result<FieldKey, String> {
+        let o = Obj::new(j, "Field")?;
+        let field = Field {
+            names: self.idents(o.get("names")?, "names")?,
+            typ: self.expr(o.get("typ")?)?,
+            tag: self.opt_expr(o.opt("tag"))?,
+        };
+        let key = self.objs.fields.insert(field);
+        self.record(&o, NodeId::Field(key))?;
+        Ok(key)
+    }
+
+    fn block(&mut self, j: &Json) -> Result<Rc<BlockStmt>, String> {
+        let o = Obj::new(j, "BlockStmt")?;
+        let block = Rc::new(BlockStmt {
+            l_brace: o.pos("l_brace")?,
+            list: self.stmts(o.get("list")?, "list")?,
+            r_brace: o.pos("r_brace")?,
+        });
+        self.record(&o, NodeId::Address(&*block as *const BlockStmt as usize))?;
+        Ok(block)
+    }
+
+    fn stmts(&mut self, j: &Json, what: &str) -> Result<Vec<Stmt>, String> {
+        j.as_array(what)?.iter().map(|s| self.stmt(s)).collect()
+    }
+
+    fn opt_stmt(&mut self, j: Option<&Json>) -> Result<Option<Stmt>, String> {
+        j.map(|s| self.stmt(s)).transpose()
+    }
+
+    fn stmt(&mut self, j: &Json) -> Result<Stmt, String> {
+        let o = Obj::new(j, "")?;
+        let stmt = match o.kind {
+            "BadStmt" => Stmt::Bad(Rc::new(BadStmt {
+                from: o.pos("from")?,
+                to: o.pos("to")?,
+            })),
+            "DeclStmt" => Stmt::Decl(Rc::new(self.decl(o.get("decl")?)?)),
+            "EmptyStmt" => Stmt::Empty(Rc::new(EmptyStmt {
+                semi: o.pos("semi")?,
+                implicit: o.bool("implicit")?,
+            })),
+            "LabeledStmt" => {
+                let label = self.ident(o.get("label")?)?;
+                let stmt = self.stmt(o.get("stmt")?)?;
+                Stmt::Labeled(LabeledStmt::arena_new(
+                    self.objs,
+                    label,
+                    o.pos("colon")?,
+                    stmt,
+                ))
+            }
+            "ExprStmt" => Stmt::Expr(Box::new(self.expr(o.get("expr")?)?)),
+            "SendStmt" => Stmt::Send(Rc::new(SendStmt {
+                chan: self.expr(o.get("chan")?)?,
+                arrow: o.pos("arrow")?,
+                val: self.expr(o.get("val")?)?,
+            })),
+            "IncDecStmt" => Stmt::IncDec(Rc::new(IncDecStmt {
+                expr: self.expr(o.get("expr")?)?,
+                token_pos: o.pos("token_pos")?,
+                token: o.token("token")?,
+            })),
+            "AssignStmt" => {
+                let lhs = self.exprs(o.get("lhs")?, "lhs")?;
+                let rhs = self.exprs(o.get("rhs")?, "rhs")?;
+                Stmt::new_assign(self.objs, lhs, o.pos("token_pos")?, o.token("token")?, rhs)
+            }
+            "GoStmt" => Stmt::Go(Rc::new(GoStmt {
+                go: o.pos("go")?,
+                call: self.expr(o.get("call")?)?,
+            })),
+            "DeferStmt" => Stmt::Defer(Rc::new(DeferStmt {
+                defer: o.pos("defer")?,
+                call: self.expr(o.get("call")?)?,
+            })),
+            "ReturnStmt" => Stmt::Return(Rc::new(ReturnStmt {
+                ret: o.pos("ret")?,
+                results: self.exprs(o.get("results")?, "results")?,
+            })),
+            "BranchStmt" => Stmt::Branch(Rc::new(BranchStmt {
+                token_pos: o.pos("token_pos")?,
+                token: o.token("token")?,
+                label: self.opt_ident(o.opt("label"))?,
+            })),
+            "BlockStmt" => return Ok(Stmt::Block(self.block(j)?)),
+            "IfStmt" => Stmt::If(Rc::new(IfStmt {
+                if_pos: o.pos("if_pos")?,
+                init: self.opt_stmt(o.opt("init"))?,
+                cond: self.expr(o.get("cond")?)?,
+                body: self.block(o.get("body")?)?,
+                els: self.opt_stmt(o.opt("els"))?,
+            })),
+            "CaseClause" => {
+                let list = match o.opt("list") {
+                    Some(l) => Some(self.exprs(l, "list")?),
+                    None => None,
+                };
+                Stmt::Case(Rc::new(CaseClause {
+                    case: o.pos("case")?,
+                    list: list,
+                    colon: o.pos("colon")?,
+                    body: self.stmts(o.get("body")?, "body")?,
+                }))
+            }
+            "SwitchStmt" => Stmt::Switch(Rc::new(SwitchStmt {
+                switch: o.pos("switch")?,
+                init: self.opt_stmt(o.opt("init"))?,
+                tag: self.opt_expr(o.opt("tag"))?,
+                body: self.block(o.get("body")?)?,
+            })),
+            "TypeSwitchStmt" => Stmt::TypeSwitch(Rc::new(TypeSwitchStmt {
+                switch: o.pos("switch")?,
+                init: self.opt_stmt(o.opt("init"))?,
+                assign: self.stmt(o.get("assign")?)?,
+                body: self.block(o.get("body")?)?,
+            })),
+            "CommClause" => Stmt::Comm(Rc::new(CommClause {
+                case: o.pos("case")?,
+                comm: self.opt_stmt(o.opt("comm"))?,
+                colon: o.pos("colon")?,
+                body: self.stmts(o.get("body")?, "body")?,
+            })),
+            "SelectStmt" => Stmt::Select(Rc::new(SelectStmt {
+                select: o.pos("select")?,
+                body: self.block(o.get("body")?)?,
+            })),
+            "ForStmt" => Stmt::For(Rc::new(ForStmt {
+                for_pos: o.pos("for_pos")?,
+                init: self.opt_stmt(o.opt("init"))?,
+                cond: self.opt_expr(o.opt("cond"))?,
+                post: self.opt_stmt(o.opt("post"))?,
+                body: self.block(o.get("body")?)?,
+            })),
+            "RangeStmt" => Stmt::Range(Rc::new(RangeStmt {
+                for_pos: o.pos("for_pos")?,
+                key: self.opt_expr(o.opt("key"))?,
+                val: self.opt_expr(o.opt("val"))?,
+                token_pos: o.pos("token_pos")?,
+                token: o.token("token")?,
+                expr: self.expr(o.get("expr")?)?,
+                body: self.block(o.get("body")?)?,
+            })),
+            k => return Err(format!("ast json: {} is not a statement", k)),
+        };
+        self.record(&o, stmt.id())?;
+        Ok(stmt)
+    }
+
+    fn spec(&mut self, j: &Json) -> Result<SpecKey, String> {
+        let o = Obj::new(j, "")?;
+        let spec = match o.kind {
+            "ImportSpec" => {
+                let path = Obj::new(o.get("path")?, "BasicLit")?;
+                let spec = Rc::new(ImportSpec {
+                    name: self.opt_ident(o.opt("name"))?,
+                    path: self.basic_lit(&path)?,
+                    end_pos: o.opt_pos("end_pos")?,
+                });
+                self.record(
+                    &path,
+                    NodeId::Address(&spec.path as *const BasicLit as usize),
+                )?;
+                Spec::Import(spec)
+            }
+            "ValueSpec" => Spec::Value(Rc::new(ValueSpec {
+                names: self.idents(o.get("names")?, "names")?,
+                typ: self.opt_expr(o.opt("typ"))?,
+                values: self.exprs(o.get("values")?, "values")?,
+                doc: o.opt_str("doc")?,
+            })),
+            "TypeSpec" => Spec::Type(Rc::new(TypeSpec {
+                name: self.ident(o.get("name")?)?,
+                assign: o.pos("assign")?,
+                typ: self.expr(o.get("typ")?)?,
+                doc: o.opt_str("doc")?,
+            })),
+            k => return Err(format!("ast json: {} is not a spec", k)),
+        };
+        self.record(&o, spec.id())?;
+        let is_import = matches!(spec, Spec::Import(_));
+        let key = self.objs.specs.insert(spec);
+        if is_import {
+            self.imports.push(key);
+        }
+        Ok(key)
+    }
+
+    fn decl(&mut self, j: &Json) -> Result<Decl, String> {
+        let o = Obj::new(j, "")?;
+        let decl = match o.kind {
+            "BadDecl" => Decl::Bad(Rc::new(BadDecl {
+                from: o.pos("from")?,
+                to: o.pos("to")?,
+            })),
+            "GenDecl" => {
+                let specs = o
+                    .arr("specs")?
+                    .iter()
+                    .map(|s| self.spec(s))
+                    .collect::<Result<Vec<SpecKey>, String>>()?;
+                Decl::Gen(Rc::new(GenDecl {
+                    token_pos: o.pos("token_pos")?,
+                    token: o.token("token")?,
+                    l_paran: o.opt_pos("l_paran")?,
+                    specs: specs,
+                    r_paren: o.opt_pos("r_paren")?,
+                    doc: o.opt_str("doc")?,
+                }))
+            }
+            "FuncDecl" => {
+                let recv = match o.opt("recv") {
+                    Some(r) => Some(self.field_list(r)?),
+                    None => None,
+                };
+                let name = self.ident(o.get("name")?)?;
+                let typ = self.func_type(o.get("typ")?)?;
+                self.record(&Obj::new(o.get("typ")?, "")?, NodeId::FuncType(typ))?;
+                let body = match o.opt("body") {
+                    Some(b) => Some(self.block(b)?),
+                    None => None,
+                };
+                let pragmas = match o.opt("pragmas") {
+                    Some(p) => p
+                        .as_array("pragmas")?
+                        .iter()
+                        .map(pragma_from_json)
+                        .collect::<Result<Vec<Pragma>, String>>()?,
+                    None => vec![],
+                };
+                Decl::Func(self.objs.fdecls.insert(FuncDecl {
+                    recv: recv,
+                    name: name,
+                    typ: typ,
+                    body: body,
+                    pragmas: pragmas,
+                    doc: o.opt_str("doc")?,
+                }))
+            }
+            k => return Err(format!("ast json: {} is not a declaration", k)),
+        };
+        self.record(&o, decl.id())?;
+        Ok(decl)
+    }
+}
+
+fn pragma_from_json(j: &Json) -> Result<Pragma, String> {
+    let o = Obj::new(j, "")?;
+    match o.kind {
+        "timeout" => Ok(Pragma::Timeout(
+            Duration::from_nanos(o.pos("nanos")? as u64),
+        )),
+        "memoize" => Ok(Pragma::Memoize(o.pos("capacity")?)),
+        "noopt" => Ok(Pragma::NoOpt),
+        "nobounds" => Ok(Pragma::NoBounds),
+        "pure" => Ok(Pragma::Pure),
+        k => Err(format!("ast json: unknown pragma {}", k)),
+    }
+}
+
+/// The tokens without data, which are stored as their text.
+const TEXT_TOKENS: &[Token] = &[
+    Token::NONE,
+    Token::ADD,
+    Token::SUB,
+    Token::MUL,
+    Token::QUO,
+    Token::REM,
+    Token::AND,
+    Token::OR,
+    Token::XOR,
+    Token::SHL,
+    Token::SHR,
+    Token::AND_NOT,
+    Token::ADD_ASSIGN,
+    Token::SUB_ASSIGN,
+    Token::MUL_ASSIGN,
+    Token::QUO_ASSIGN,
+    Token::REM_ASSIGN,
+    Token::AND_ASSIGN,
+    Token::OR_ASSIGN,
+    Token::XOR_ASSIGN,
+    Token::SHL_ASSIGN,
+    Token::SHR_ASSIGN,
+    Token::AND_NOT_ASSIGN,
+    Token::LAND,
+    Token::LOR,
+    Token::ARROW,
+    Token::INC,
+    Token::DEC,
+    Token::EQL,
+    Token::LSS,
+    Token::GTR,
+    Token::ASSIGN,
+    Token::NOT,
+    Token::NEQ,
+    Token::LEQ,
+    Token::GEQ,
+    Token::DEFINE,
+    Token::ELLIPSIS,
+];
+
+fn token_from_text(text: &str) -> Result<Token, String> {
+    match Token::ident_token(text.to_owned()) {
+        Token::IDENT(_) => TEXT_TOKENS
+            .iter()
+            .find(|t| t.text() == text)
+            .cloned()
+            .ok_or_else(|| format!("ast json: unknown token {}", text)),
+        keyword => Ok(keyword),
+    }
+}
+
+/// A JSON object that is an AST node, or a part of one.
+struct Obj<'j> {
+    kind: &'j str,
+    fields: &'j [(String, Json)],
+}
+
+impl<'j> Obj<'j> {
+    /// Checks `j` is an object of the expected kind, if `kind` is not empty.
+    fn new(j: &'j Json, kind: &str) -> Result<Obj<'j>, String> {
+        let fields = match j {
+            Json::Object(fields) => fields,
+            _ => return Err(format!("ast json: expected an object for {}", kind)),
+        };
+        let mut o = Obj {
+            kind: "",
+            fields: fields,
+        };
+        if let Some(k) = o.opt("kind") {
+            o.kind = k.as_str("kind")?;
+        }
+        if !kind.is_empty() && o.kind != kind {
+            return Err(format!("ast json: expected {}, found '{}'", kind, o.kind));
+        }
+        Ok(o)
+    }
+
+    /// Returns the field, None if it's missing or null.
+    fn opt(&self, key: &str) -> Option<&'j Json> {
+        self.fields
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v)
+            .filter(|v| !matches!(v, Json::Null))
+    }
+
+    fn get(&self, key: &str) -> Result<&'j Json, String> {
+        self.opt(key)
+            .ok_or_else(|| format!("ast json: {} has no field '{}'", self.kind, key))
+    }
+
+    fn pos(&self, key: &str) -> Result<usize, String> {
+        self.get(key)?.as_pos(key)
+    }
+
+    fn opt_pos(&self, key: &str) -> Result<Option<usize>, String> {
+        self.opt(key).map(|p| p.as_pos(key)).transpose()
+    }
+
+    fn str(&self, key: &str) -> Result<&'j str, String> {
+        self.get(key)?.as_str(key)
+    }
+
+    fn opt_str(&self, key: &str) -> Result<Option<String>, String> {
+        self.opt(key)
+            .map(|s| s.as_str(key).map(|s| s.to_owned()))
+            .transpose()
+    }
+
+    fn bool(&self, key: &str) -> Result<bool, String> {
+        match self.opt(key) {
+            Some(Json::Bool(b)) => Ok(*b),
+            None => Ok(false),
+            _ => Err(format!("ast json: '{}' is not a bool", key)),
+        }
+    }
+
+    fn arr(&self, key: &str) -> Result<&'j [Json], String> {
+        self.get(key)?.as_array(key)
+    }
+
+    fn token(&self, key: &str) -> Result<Token, String> {
+        token_from_text(self.str(key)?)
+    }
+}
+
+/// The JSON values, objects keep the order of their fields.
+#[derive(Debug, Clone, PartialEq)]
+enum Json {
+    Null,
+    Bool(bool),
+    Num(f64),
+    Str(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    fn pos(p: usize) -> Json {
+        Json::Num(p as f64)
+    }
+
+    fn opt_pos(p: Option<usize>) -> Json {
+        p.map_or(Json::Null, Json::pos)
+    }
+
+    fn opt_str(s: &Option<String>) -> Json {
+        s.as_ref().map_or(Json::Null, |s| Json::Str(s.clone()))
+    }
+
+    fn token(t: &Token) -> Json {
+        Json::Str(t.text().to_owned())
+    }
+
+    fn as_pos(&self, what: &str) -> Result<usize, String> {
+        match self {
+            Json::Num(n) if *n >= 0.0 && n.fract() == 0.0 && *n <= usize::MAX as f64 => {
+                Ok(*n as usize)
+            }
+            _ => Err(format!(
+                "ast json: '{}' is not a non-negative integer",
+                what
+            )),
+        }
+    }
+
+    fn as_str(&self, what: &str) -> Result<&str, String> {
+        match self {
+            Json::Str(s) => Ok(s),
+            _ => Err(format!("ast json: '{}' is not a string", what)),
+        }
+    }
+
+    fn as_array(&self, what: &str) -> Result<&[Json], String> {
+        match self {
+            Json::Array(a) => Ok(a),
+            _ => Err(format!("ast json: '{}' is not an array", what)),
+        }
+    }
+
+    /// Writes the value, indented by `indent` levels if it is not None.
+    fn write(&self, out: &mut String, indent: Option<usize>) {
+        let newline = |out: &mut String, level: usize| {
+            out.push('\n');
+            for _ in 0..level {
+                out.push_str("  ");
+            }
+        };
+        match self {
+            Json::Null => out.push_str("null"),
+            Json::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+            Json::Num(n) => {
+                if n.fract() == 0.0 && n.abs() < 1e15 {
+                    write!(out, "{}", *n as i64).unwrap();
+                } else {
+                    write!(out, "{}", n).unwrap();
+                }
+            }
+            Json::Str(s) => write_str(out, s),
+            Json::Array(a) if a.is_empty() => out.push_str("[]"),
+            Json::Array(a) => {
+                out.push('[');
+                for (i, v) in a.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    if let Some(level) = indent {
+                        newline(out, level + 1);
+                    }
+                    v.write(out, indent.map(|l| l + 1));
+                }
+                if let Some(level) = indent {
+                    newline(out, level);
+                }
+                out.push(']');
+            }
+            Json::Object(o) if o.is_empty() => out.push_str("{}"),
+            Json::Object(o) => {
+                out.push('{');
+                for (i, (k, v)) in o.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    if let Some(level) = indent {
+                        newline(out, level + 1);
+                    }
+                    write_str(out, k);
+                    out.push(':');
+                    if indent.is_some() {
+                        out.push(' ');
+                    }
+                    v.write(out, indent.map(|l| l + 1));
+                }
+                if let Some(level) = indent {
+                    newline(out, level);
+                }
+                out.push('}');
+            }
+        }
+    }
+
+    fn parse(s: &str) -> Result<Json, String> {
+        let mut p = JsonParser {
+            src: s.as_bytes(),
+            pos: 0,
+        };
+        let v = p.value()?;
+        p.skip_ws();
+        if p.pos < p.src.len() {
+            return Err(p.error("unexpected trailing characters"));
+        }
+        Ok(v)
+    }
+}
+
+fn write_str(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+struct JsonParser<'s> {
+    src: &'s [u8],
+    pos: usize,
+}
+
+impl<'s> JsonParser<'s> {
+    fn error(&self, msg: &str) -> String {
+        format!("json: {} at offset {}", msg, self.pos)
+    }
+
+    fn skip_ws(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.src.get(self.pos) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, lit: &str) -> Result<(), String> {
+        if self.src[self.pos..].starts_with(lit.as_bytes()) {
+            self.pos += lit.len();
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected {}", lit)))
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.skip_ws();
+        match self.src.get(self.pos) {
+            Some(b'n') => self.expect("null").map(|_| Json::Null),
+            Some(b't') => self.expect("true").map(|_| Json::Bool(true)),
+            Some(b'f') => self.expect("false").map(|_| Json::Bool(false)),
+            Some(b'"') => self.string().map(Json::Str),
+            Some(b'[') => {
+                self.pos += 1;
+                let mut items = vec![];
+                self.skip_ws();
+                if self.src.get(self.pos) == Some(&b']') {
+                    self.pos += 1;
+                    return Ok(Json::Array(items));
+                }
+                loop {
+                    items.push(self.value()?);
+                    self.skip_ws();
+                    match self.src.get(self.pos) {
+                        Some(b',') => self.pos += 1,
+                        Some(b']') => {
+                            self.pos += 1;
+                            return Ok(Json::Array(items));
+                        }
+                        _ => return Err(self.error("expected ',' or ']'")),
+                    }
+                }
+            }
+            Some(b'{') => {
+                self.pos += 1;
+                let mut fields = vec![];
+                self.skip_ws();
+                if self.src.get(self.pos) == Some(&b'}') {
+                    self.pos += 1;
+                    return Ok(Json::Object(fields));
+                }
+                loop {
+                    self.skip_ws();
+                    if self.src.get(self.pos) != Some(&b'"') {
+                        return Err(self.error("expected a field name"));
+                    }
+                    let key = self.string()?;
+                    self.skip_ws();
+                    self.expect(":")?;
+                    fields.push((key, self.value()?));
+                    self.skip_ws();
+                    match self.src.get(self.pos) {
+                        Some(b',') => self.pos += 1,
+                        Some(b'}') => {
+                            self.pos += 1;
+                            return Ok(Json::Object(fields));
+                        }
+                        _ => return Err(self.error("expected ',' or '}'")),
+                    }
+                }
+            }
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            Some(_) => Err(self.error("unexpected character")),
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, String> {
+        let start = self.pos;
+        while let Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9') = self.src.get(self.pos) {
+            self.pos += 1;
+        }
+        let text = std::str::from_utf8(&self.src[start..self.pos]).unwrap();
+        text.parse::<f64>()
+            .map(Json::Num)
+            .map_err(|_| self.error(&format!("invalid number {}", text)))
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let digits = self
+            .src
+            .get(self.pos..self.pos + 4)
+            .and_then(|d| std::str::from_utf8(d).ok())
+            .and_then(|d| u32::from_str_radix(d, 16).ok())
+            .ok_or_else(|| self.error("invalid unicode escape"))?;
+        self.pos += 4;
+        Ok(digits)
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.pos += 1;
+        let mut bytes = vec![];
+        loop {
+            let b = match self.src.get(self.pos) {
+                Some(b) => *b,
+                None => return Err(self.error("unterminated string")),
+            };
+            self.pos += 1;
+            match b {
+                b'"' => break,
+                b'\\' => {
+                    let e = self.src.get(self.pos).copied();
+                    self.pos += 1;
+                    let c = match e {
+                        Some(b'"') => '"',
+                        Some(b'\\') => '\\',
+                        Some(b'/') => '/',
+                        Some(b'b') => '\u{8}',
+                        Some(b'f') => '\u{c}',
+                        Some(b'n') => '\n',
+                        Some(b'r') => '\r',
+                        Some(b't') => '\t',
+                        Some(b'u') => {
+                            let mut code = self.hex4()?;
+                            if (0xd800..0xdc00).contains(&code)
+                                && self.src[self.pos..].starts_with(b"\\u")
+                            {
+                                self.pos += 2;
+                                let low = self.hex4()?;
+                                code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+                            }
+                            char::from_u32(code)
+                                .ok_or_else(|| self.error("invalid unicode escape"))?
+                        }
+                        _ => return Err(self.error("invalid escape")),
+                    };
+                    let mut buf = [0; 4];
+                    bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                }
+                b => bytes.push(b),
+            }
+        }
+        String::from_utf8(bytes).map_err(|_| self.error("invalid utf-8 in string"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::errors::ErrorList;
+
+    const SRC: &str = r#"package main
+
+import (
+    "fmt"
+    m "math"
+)
+
+type Point struct {
+    X, Y int `json:"x"`
+}
+
+//gos:memoize 8
+//gos:pure
+func (p *Point) Dist(o Point) (d float64) {
+    dx := float64(p.X - o.X)
+    d = m.Sqrt(dx*dx + 1.5)
+    return
+}
+
+func main() {
+    var s = []int{1, 2, 3}
+    ch := make(chan<- string, 1)
+    ch <- "a\tb"
+    for i, v := range s[1:2:3] {
+        switch {
+        case i > 0 && v != 'x':
+            fmt.Println(-v)
+        default:
+            i++
+        }
+    }
+outer:
+    for {
+        select {
+        case <-time.After(1):
+            break outer
+        }
+    }
+    f := func(xs ...int) interface{} { return map[string]int{"a": len(xs)} }
+    if v, ok := f().(map[string]int); ok {
+        go fmt.Println(v, undefined)
+    }
+    defer f(s...)
+}
+"#;
+
+    #[test]
+    fn test_round_trip() {
+        let mut fs = position::FileSet::new();
+        let mut objs = Objects::new();
+        let el = ErrorList::new();
+        let (_, file) = crate::parse_file(&mut objs, &mut fs, &el, "a.gos", SRC, false);
+        assert_eq!(el.len(), 0);
+        let file = file.unwrap();
+        let source = fs.iter().next();
+        let json = to_json(&file, &objs, source, false);
+        let pretty = to_json(&file, &objs, source, true);
+        assert!(Json::parse(&pretty).unwrap() == Json::parse(&json).unwrap());
+
+        let mut fs2 = position::FileSet::new();
+        let mut objs2 = Objects::new();
+        let mut dec = Decoder::new(&mut objs2, &mut fs2);
+        let file2 = dec.decode_file(&json).unwrap();
+        assert_eq!(dec.ids()[&0], file2.id());
+        assert_eq!(file2.imports.len(), 2);
+        assert_eq!(file2.unresolved.len(), file.unresolved.len());
+        assert_eq!(to_json(&file2, &objs2, fs2.iter().next(), false), json);
+
+        let pos = file.decls[1].pos(&objs);
+        let (p, p2) = (fs.position(pos).unwrap(), fs2.position(pos).unwrap());
+        assert_eq!((p.line, p.column), (p2.line, p2.column));
+        assert_eq!(p.filename, p2.filename);
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut fs = position::FileSet::new();
+        let mut objs = Objects::new();
+        let errs = [
+            (
+                "{\"kind\": \"File\"",
+                "json: expected ',' or '}' at offset 15",
+            ),
+            ("[]", "ast json: expected an object for File"),
+            (
+                "{\"kind\": \"File\", \"package\": 0, \"decls\": []}",
+                "ast json: File has no field 'name'",
+            ),
+        ];
+        for (json, err) in errs.iter() {
+            assert_eq!(from_json(json, &mut objs, &mut fs).unwrap_err(), *err);
+        }
+    }
+}
@@ -20,17 +20,87 @@ pub struct Error {
     pub msg: String,
     pub soft: bool,
     pub by_parser: bool, // reported by parser (not type checker)
+    pub warning: bool,   // doesn't stop the compilation
     order: usize,        // display order
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let p = if self.by_parser { "[Parser]" } else { "[TC]" };
+        let p = if self.by_parser {
+            "[Parser]"
+        } else if self.warning {
+            "[Warning]"
+        } else {
+            "[TC]"
+        };
         write!(f, "{} {}  {}\n", p, self.pos, self.msg)?;
         Ok(())
     }
 }
 
+/// How bad a diagnostic is, warnings don't stop the compilation
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+}
+
+/// An error of an ErrorList in a structured form, for editors and other tools.
+/// Errors are reported at a position, so `end` is the same as `start` unless
+/// the span is widened by someone who has the source.
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// What reported it: "syntax" for the parser, "type" for the type checker,
+    /// "vet" for warnings and "build" for errors without a position
+    pub code: &'static str,
+    /// Empty if the diagnostic has no position
+    pub file: String,
+    pub start: FilePos,
+    pub end: FilePos,
+    pub message: String,
+    /// The follow-up lines of the message, like the other declaration of
+    /// a redeclared name
+    pub related: Vec<(FilePos, String)>,
+}
+
+impl Diagnostic {
+    fn new(e: &Error) -> Diagnostic {
+        let (severity, code) = if e.warning {
+            (Severity::Warning, "vet")
+        } else if e.by_parser {
+            (Severity::Error, "syntax")
+        } else if e.pos.is_valid() {
+            (Severity::Error, "type")
+        } else {
+            (Severity::Error, "build")
+        };
+        let file = if e.pos.is_valid() {
+            e.pos.filename.to_string()
+        } else {
+            String::new()
+        };
+        Diagnostic {
+            severity: severity,
+            code: code,
+            file: file,
+            start: e.pos.clone(),
+            end: e.pos.clone(),
+            message: e.msg.clone(),
+            related: vec![],
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct ErrorList {
     errors: Rc<RefCell<Vec<Error>>>,
@@ -54,6 +124,21 @@ impl ErrorList {
     }
 
     pub fn add(&self, p: Option<FilePos>, msg: String, soft: bool, by_parser: bool) {
+        self.add_impl(p, msg, soft, by_parser, false);
+    }
+
+    pub fn add_warning(&self, p: Option<FilePos>, msg: String) {
+        self.add_impl(p, msg, true, false, true);
+    }
+
+    fn add_impl(
+        &self,
+        p: Option<FilePos>,
+        msg: String,
+        soft: bool,
+        by_parser: bool,
+        warning: bool,
+    ) {
         let fp = p.unwrap_or(FilePos::null());
         let order = if msg.starts_with('\t') {
             self.errors
@@ -72,6 +157,7 @@ impl ErrorList {
             msg: msg,
             soft: soft,
             by_parser: by_parser,
+            warning: warning,
             order: order,
         });
     }
@@ -80,6 +166,11 @@ impl ErrorList {
         self.errors.borrow().len()
     }
 
+    /// Returns true if there are errors other than warnings
+    pub fn has_errors(&self) -> bool {
+        self.errors.borrow().iter().any(|e| !e.warning)
+    }
+
     pub fn sort(&self) {
         self.errors.borrow_mut().sort_by_key(|e| e.order);
     }
@@ -87,6 +178,23 @@ impl ErrorList {
     pub fn borrow(&self) -> Ref<Vec<Error>> {
         self.errors.borrow()
     }
+
+    /// Returns the errors in display order as diagnostics, with the follow-up
+    /// lines of a message in its `related`
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        let mut errors: Vec<&Error> = vec![];
+        let borrowed = self.errors.borrow();
+        errors.extend(borrowed.iter());
+        errors.sort_by_key(|e| e.order);
+        let mut result: Vec<Diagnostic> = vec![];
+        for e in errors.into_iter() {
+            match (e.msg.strip_prefix('\t'), result.last_mut()) {
+                (Some(msg), Some(last)) => last.related.push((e.pos.clone(), msg.to_owned())),
+                _ => result.push(Diagnostic::new(e)),
+            }
+        }
+        result
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -112,6 +220,11 @@ impl<'a> FilePosErrors<'a> {
         self.add(pos, s.to_string(), soft);
     }
 
+    pub fn add_warning(&self, pos: Pos, msg: String) {
+        let p = self.file.position(pos);
+        self.elist.add_warning(Some(p), msg);
+    }
+
     pub fn parser_add(&self, pos: Pos, msg: String) {
         let p = self.file.position(pos);
         self.elist.add(Some(p), msg, false, true);
@@ -4,6 +4,7 @@
 
 #![allow(dead_code)]
 pub mod ast;
+pub mod ast_json;
 pub mod errors;
 pub mod objects;
 mod parser;
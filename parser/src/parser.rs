@@ -19,6 +19,10 @@ use super::scope::*;
 use super::ast::*;
 use super::objects::*;
 use std::rc::Rc;
+use std::time::Duration;
+
+// The capacity of the cache of a `//gos:memoize` without one
+const DEFAULT_MEMO_CAPACITY: usize = 1024;
 
 // Parsing modes for parseSimpleStmt.
 #[derive(PartialEq, Eq)]
@@ -52,6 +56,11 @@ pub struct Parser<'a> {
 
     label_scope: Option<ScopeKey>,
     target_stack: Vec<Vec<IdentKey>>,
+
+    // the //gos: comments right before the current token
+    pragmas: Vec<(position::Pos, String)>,
+    // the comment group ending on the line right above the current token
+    lead_comment: Option<String>,
 }
 
 impl<'a> Parser<'a> {
@@ -79,6 +88,8 @@ impl<'a> Parser<'a> {
             imports: vec![],
             label_scope:None,
             target_stack: vec![],
+            pragmas: vec![],
+            lead_comment: None,
         };
         p.next(); // get the first token ready
         p
@@ -298,16 +309,44 @@ impl<'a> Parser<'a> {
 
     fn next(&mut self) {
         // Get next token and skip comments
+        let mut pragmas = vec![];
+        // the last comment group and the line it ends on
+        let mut group: Vec<String> = vec![];
+        let mut group_end = 0;
         loop {
             let (token, pos) = self.scanner.scan();
-            match token {
-                Token::COMMENT(_) => { // Skip comment
+            match &token {
+                Token::COMMENT(lit) => { // Skip comment, but keep the pragmas
+                    let text: &String = lit.as_ref();
+                    if text.starts_with("//gos:") {
+                        pragmas.push((pos, text.trim_end().to_owned()));
+                    }
+                    let prev_line = match self.token {
+                        Token::NONE => 0,
+                        _ => self.file().line(self.pos),
+                    };
+                    let line = self.file().line(pos);
+                    if line == prev_line || (!group.is_empty() && line > group_end + 1) {
+                        // a comment trailing the previous token, or a new group
+                        group.clear();
+                    }
+                    if line != prev_line {
+                        group.push(text.clone());
+                        group_end = line + text.trim_end().matches('\n').count();
+                    }
                     self.print_trace(pos, &format!("{}", token));
                 },
                 _ => {
                     self.print_trace(pos, &format!("next: {}", token));
+                    self.lead_comment = if !group.is_empty()
+                        && group_end + 1 == self.file().line(pos) {
+                        comment_text(&group)
+                    } else {
+                        None
+                    };
                     self.token = token;
                     self.pos = pos;
+                    self.pragmas = pragmas;
                     break; 
                 },
             }
@@ -2311,6 +2350,7 @@ impl<'a> Parser<'a> {
     fn parse_value_spec<'p, 'k>(self_: &'p mut Parser<'a>, keyword: &'k Token, iota: isize) -> SpecKey {
         self_.trace_begin(&format!("{}{}", keyword.text(), "Spec"));
 
+        let doc = self_.lead_comment.take();
         let pos = self_.pos;
         let idents = self_.parse_ident_list();
         let typ = self_.try_type();
@@ -2342,7 +2382,7 @@ impl<'a> Parser<'a> {
 	    // the end of the innermost containing block.
 	    // (Global identifiers are resolved in a separate phase after parsing.)
         let spec =  specs_mut!(self_).insert(Spec::Value(Rc::new(ValueSpec{
-            names: idents, typ: typ, values: values})));
+            names: idents, typ: typ, values: values, doc: doc})));
         let kind = if let Token::VAR = keyword {
                 EntityKind::Var
             } else {EntityKind::Con};
@@ -2359,6 +2399,7 @@ impl<'a> Parser<'a> {
     fn parse_type_spec(&mut self, _: &Token, _: isize) -> SpecKey {
         self.trace_begin("TypeSpec");
 
+        let doc = self.lead_comment.take();
         let ident = self.parse_ident();
         // Go spec: The scope of a type identifier declared inside a function begins
 	    // at the identifier in the TypeSpec and ends at the end of the innermost
@@ -2366,7 +2407,7 @@ impl<'a> Parser<'a> {
 	    // (Global identifiers are resolved in a separate phase after parsing.)
         let placeholder = Expr::new_bad(0, 0);
         let spec_val = Spec::Type(Rc::new(TypeSpec{
-            name: ident, assign: 0, typ: placeholder
+            name: ident, assign: 0, typ: placeholder, doc: doc
         }));
         let index = specs_mut!(self).insert(spec_val);
         let scope = self.top_scope.unwrap();
@@ -2390,6 +2431,7 @@ impl<'a> Parser<'a> {
         f: fn (&mut Parser<'a>, &Token, isize) -> SpecKey) -> Decl {
         self.trace_begin(&format!("GenDecl({})", keyword.text()));
 
+        let doc = self.lead_comment.take();
         let pos = self.expect(keyword);
         let (lparen, rparen, list) = if self.token == Token::LPAREN {
             let lp = self.pos;
@@ -2413,13 +2455,48 @@ impl<'a> Parser<'a> {
             token: keyword.clone(),
             l_paran: lparen,
             specs: list,
-            r_paren: rparen
+            r_paren: rparen,
+            doc: doc,
         }))
     }
 
+    fn parse_pragmas(&mut self) -> Vec<Pragma> {
+        let mut pragmas = vec![];
+        for (pos, text) in std::mem::take(&mut self.pragmas).into_iter() {
+            let mut fields = text["//gos:".len()..].split_whitespace();
+            match (fields.next(), fields.next(), fields.next()) {
+                (Some("timeout"), Some(d), None) => match parse_duration(d) {
+                    Some(d) if d > Duration::ZERO => pragmas.push(Pragma::Timeout(d)),
+                    _ => self.error(pos, format!("invalid duration in {}", text)),
+                },
+                (Some("timeout"), _, _) => self.error_str(pos, "usage: //gos:timeout duration"),
+                (Some("memoize"), None, _) => pragmas.push(Pragma::Memoize(DEFAULT_MEMO_CAPACITY)),
+                (Some("memoize"), Some(n), None) => match n.parse::<usize>() {
+                    Ok(n) if n > 0 => pragmas.push(Pragma::Memoize(n)),
+                    _ => self.error(pos, format!("invalid capacity in {}", text)),
+                },
+                (Some("memoize"), _, _) => self.error_str(pos, "usage: //gos:memoize [capacity]"),
+                (Some("noopt"), None, _) => pragmas.push(Pragma::NoOpt),
+                (Some("nobounds"), None, _) => pragmas.push(Pragma::NoBounds),
+                (Some("pure"), None, _) => pragmas.push(Pragma::Pure),
+                (Some(name @ ("noopt" | "nobounds" | "pure")), _, _) => {
+                    self.error(pos, format!("usage: //gos:{}", name))
+                }
+                (Some("noinline"), _, _) => self.error_str(
+                    pos,
+                    "//gos:noinline is not supported, calls are never inlined",
+                ),
+                _ => self.error(pos, format!("unknown pragma {}", text)),
+            }
+        }
+        pragmas
+    }
+
     fn parse_func_decl(&mut self) -> Decl {
         self.trace_begin("FunctionDecl");
 
+        let doc = self.lead_comment.take();
+        let pragmas = self.parse_pragmas();
         let pos = self.expect(&Token::FUNC);
         let scope = new_scope!(self, self.top_scope);
         let recv = if self.token == Token::LPAREN {
@@ -2447,6 +2524,8 @@ impl<'a> Parser<'a> {
             name: ident,
             typ: typ,
             body: body,
+            pragmas: pragmas,
+            doc: doc,
         });
         if recv_is_none {
             // Go spec: The scope of an identifier denoting a constant, type,
@@ -2507,6 +2586,7 @@ impl<'a> Parser<'a> {
         self.trace_begin("File");
         
         let err_count = self.errors.len();
+        let doc = self.lead_comment.take();
         let pos = self.expect(&Token::PACKAGE);
         // Go spec: The package clause is not a declaration;
 	    // the package name does not appear in any scope.
@@ -2560,10 +2640,63 @@ impl<'a> Parser<'a> {
             scope: self.pkg_scope.unwrap(),
             imports: self.imports.clone(),
             unresolved: self.unresolved.clone(),
+            doc: doc,
         })
     }
 }
 
+/// Returns the text of a comment group with the comment markers and the
+/// //gos: pragmas removed, like Go's CommentGroup.Text.
+fn comment_text(group: &[String]) -> Option<String> {
+    let mut lines = vec![];
+    for c in group.iter() {
+        if c.starts_with("//gos:") {
+            continue;
+        } else if let Some(line) = c.strip_prefix("//") {
+            let line = line.strip_prefix(' ').unwrap_or(line);
+            lines.push(line.trim_end());
+        } else {
+            let body = c.trim_start_matches("/*").trim_end_matches("*/");
+            let body = body.strip_prefix(' ').unwrap_or(body);
+            lines.extend(body.lines().map(|l| l.trim_end()));
+        }
+    }
+    let start = lines.iter().position(|l| !l.is_empty())?;
+    let end = lines.iter().rposition(|l| !l.is_empty()).unwrap();
+    Some(lines[start..=end].join("\n"))
+}
+
+/// Parses a duration like "300ms" or "1m30s", in the format of Go's
+/// time.ParseDuration without the sign.
+fn parse_duration(s: &str) -> Option<Duration> {
+    let mut rest = s;
+    let mut nanos = 0f64;
+    if rest.is_empty() {
+        return None;
+    }
+    while !rest.is_empty() {
+        let num_len = rest
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(rest.len());
+        let num: f64 = rest[..num_len].parse().ok()?;
+        rest = &rest[num_len..];
+        let unit_len = rest
+            .find(|c: char| c.is_ascii_digit() || c == '.')
+            .unwrap_or(rest.len());
+        let unit = match &rest[..unit_len] {
+            "ns" => 1e0,
+            "us" | "µs" => 1e3,
+            "ms" => 1e6,
+            "s" => 1e9,
+            "m" => 60e9,
+            "h" => 3600e9,
+            _ => return None,
+        };
+        rest = &rest[unit_len..];
+        nanos += num * unit;
+    }
+    Some(Duration::from_nanos(nanos as u64))
+}
 
 #[cfg(test)]
 mod test {
@@ -2588,4 +2721,128 @@ mod test {
         p.pkg_scope = p.top_scope;
         p.parse_decl(Token::is_decl_start);
     }
+
+    #[test]
+    fn test_pragmas() {
+        let mut fs = position::FileSet::new();
+        let f = fs.add_file("testfile2.gs".to_owned(), None, 1000);
+
+        let s1 = r###"
+        // hook is called every frame
+        //gos:timeout 16ms
+        func hook() {}
+        //gos:nosuch
+        func other() {}
+        //gos:memoize
+        func fib(n int) int { return n }
+        //gos:memoize 64
+        func fact(n int) int { return n }
+        //gos:memoize 0
+        func bad(n int) int { return n }
+        //gos:pure
+        //gos:noopt
+        func sq(n int) int { return n * n }
+        //gos:pure 1
+        func bad2() {}
+        //gos:nobounds
+        func get(s []int) int { return s[0] }
+        //gos:noopt 1
+        func bad3() {}
+        //gos:noinline
+        func bad4() {}
+        "###;
+        let o = &mut Objects::new();
+        let el = &mut ErrorList::new();
+        let mut p = Parser::new(o, f, el, s1, false);
+        p.open_scope();
+        p.pkg_scope = p.top_scope;
+        let pragmas = |p: &Parser, d: Decl| match d {
+            Decl::Func(k) => p.objects.fdecls[k].pragmas.clone(),
+            _ => unreachable!(),
+        };
+        let d = p.parse_decl(Token::is_decl_start);
+        assert_eq!(pragmas(&p, d), vec![Pragma::Timeout(Duration::from_millis(16))]);
+        let d = p.parse_decl(Token::is_decl_start);
+        assert!(pragmas(&p, d).is_empty());
+        let d = p.parse_decl(Token::is_decl_start);
+        assert_eq!(pragmas(&p, d), vec![Pragma::Memoize(DEFAULT_MEMO_CAPACITY)]);
+        let d = p.parse_decl(Token::is_decl_start);
+        assert_eq!(pragmas(&p, d), vec![Pragma::Memoize(64)]);
+        let d = p.parse_decl(Token::is_decl_start);
+        assert!(pragmas(&p, d).is_empty());
+        let d = p.parse_decl(Token::is_decl_start);
+        assert_eq!(pragmas(&p, d), vec![Pragma::Pure, Pragma::NoOpt]);
+        let d = p.parse_decl(Token::is_decl_start);
+        assert!(pragmas(&p, d).is_empty());
+        let d = p.parse_decl(Token::is_decl_start);
+        assert_eq!(pragmas(&p, d), vec![Pragma::NoBounds]);
+        let d = p.parse_decl(Token::is_decl_start);
+        assert!(pragmas(&p, d).is_empty());
+        let d = p.parse_decl(Token::is_decl_start);
+        assert!(pragmas(&p, d).is_empty());
+        assert_eq!(el.len(), 5);
+    }
+
+    #[test]
+    fn test_doc_comments() {
+        let mut fs = position::FileSet::new();
+        let f = fs.add_file("testfile3.gs".to_owned(), None, 1000);
+
+        let s1 = r###"
+        // Package p is documented.
+        package p
+
+        // Max is the limit.
+        //gos:ignored
+        const Max = 10 // not a doc
+
+        var (
+            /* A is the first, and
+               spans two lines. */
+            A = 1
+            B = 2
+        )
+
+        // detached
+
+        func f() {}
+        "###;
+        let o = &mut Objects::new();
+        let el = &mut ErrorList::new();
+        let mut p = Parser::new(o, f, el, s1, false);
+        let file = p.parse_file().unwrap();
+        assert_eq!(file.doc.as_deref(), Some("Package p is documented."));
+        let gen = |d: &Decl| match d {
+            Decl::Gen(g) => g.clone(),
+            _ => unreachable!(),
+        };
+        let spec_doc = |p: &Parser, k: SpecKey| match &p.objects.specs[k] {
+            Spec::Value(v) => v.doc.clone(),
+            _ => unreachable!(),
+        };
+        let max = gen(&file.decls[0]);
+        assert_eq!(max.doc.as_deref(), Some("Max is the limit."));
+        assert_eq!(spec_doc(&p, max.specs[0]), None);
+        let vars = gen(&file.decls[1]);
+        assert_eq!(vars.doc, None);
+        assert_eq!(
+            spec_doc(&p, vars.specs[0]).as_deref(),
+            Some("A is the first, and\n               spans two lines.")
+        );
+        assert_eq!(spec_doc(&p, vars.specs[1]), None);
+        match &file.decls[2] {
+            Decl::Func(k) => assert_eq!(p.objects.fdecls[*k].doc, None),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("50ms"), Some(Duration::from_millis(50)));
+        assert_eq!(parse_duration("1m30s"), Some(Duration::from_secs(90)));
+        assert_eq!(parse_duration("1.5s"), Some(Duration::from_millis(1500)));
+        assert_eq!(parse_duration("10"), None);
+        assert_eq!(parse_duration("5days"), None);
+        assert_eq!(parse_duration(""), None);
+    }
 } 
\ No newline at end of file
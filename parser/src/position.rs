@@ -120,10 +120,15 @@ impl File {
             .collect();
     }
 
+    pub fn lines(&self) -> &[usize] {
+        &self.lines
+    }
+
+    /// Sets the line offsets, they must be increasing and within the file.
     pub fn set_lines(&mut self, lines: Vec<usize>) -> bool {
         let size = self.size;
-        for (i, &offset) in self.lines.iter().enumerate() {
-            if (i == 0 && size <= offset) || offset < lines[i - 1] {
+        for (i, &offset) in lines.iter().enumerate() {
+            if (i > 0 && offset <= lines[i - 1]) || size <= offset {
                 return false;
             }
         }
@@ -162,6 +167,15 @@ impl File {
         self.base() + offset
     }
 
+    /// Returns the line of p, the same as position(p).line but faster
+    pub fn line(&self, p: Pos) -> usize {
+        if p < self.base || p > self.base + self.size {
+            panic!("illegal Pos value");
+        }
+        let offset = p - self.base;
+        self.lines.partition_point(|&line| line <= offset)
+    }
+
     pub fn position(&self, p: Pos) -> FilePos {
         if p < self.base || p > self.base + self.size {
             panic!("illegal Pos value");
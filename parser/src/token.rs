@@ -464,6 +464,15 @@ impl TokenData {
         }
     }
 
+    /// Returns the unquoted value of a string literal, None if the data
+    /// holds no such value.
+    pub fn unquoted_str(&self) -> Option<&String> {
+        match self.0.as_ref() {
+            RawTokenData::StrStr(_, s) => Some(s),
+            _ => None,
+        }
+    }
+
     pub fn as_str_char(&self) -> (&String, &char) {
         match self.0.as_ref() {
             RawTokenData::StrChar(s, c) => (s, c),
@@ -54,9 +54,16 @@ pub fn ffi_impl_implement(
                 } else {
                     ffi_name.strip_prefix(FFI_FUNC_PREFIX).map(|x| {
                         let wrapper_name = format!("{}{}", WRAPPER_FUNC_PREFIX, x);
-                        let m = gen_wrapper_method(&method, &wrapper_name);
-                        output_block.items.push(ImplItem::Method(m));
-                        (wrapper_name, get_arg_types(&method.sig))
+                        if is_typed(&method.sig) {
+                            let m = gen_typed_wrapper_method(method, &wrapper_name);
+                            let arg_types = get_arg_types(&m.sig);
+                            output_block.items.push(ImplItem::Method(m));
+                            (wrapper_name, arg_types)
+                        } else {
+                            let m = gen_wrapper_method(&method, &wrapper_name);
+                            output_block.items.push(ImplItem::Method(m));
+                            (wrapper_name, get_arg_types(&method.sig))
+                        }
                     })
                 }
             }
@@ -150,7 +157,7 @@ fn gen_new_method(type_name: &Ident, new_method: &Option<ImplItemMethod>) -> Imp
 fn gen_register_method() -> ImplItemMethod {
     parse_quote! {
         pub fn register(engine: &mut goscript_engine::Engine) {
-            engine.register_extension(Self::ffi__id(), Self::ffi__new());
+            engine.register_lazy_extension(Self::ffi__id(), Self::ffi__new);
         }
     }
 }
@@ -249,6 +256,145 @@ fn gen_wrapper_method(m: &ImplItemMethod, name: &str) -> ImplItemMethod {
     wrapper
 }
 
+/// A FFI function is typed if it takes Rust values rather than a
+/// `Vec<GosValue>`, or returns values that are not `GosValue`s, like
+/// `fn ffi_open(&self, path: &str, flags: i64) -> (File, GoError)`.
+/// The wrapper checks the arguments and converts them with `FromGos`,
+/// the results are converted with `IntoGos`.
+fn is_typed(sig: &Signature) -> bool {
+    let typed_arg = sig.inputs.iter().any(|x| match x {
+        FnArg::Typed(pt) => !is_ctx_type(&pt.ty) && !is_args_type(&pt.ty),
+        _ => false,
+    });
+    typed_arg || !is_untyped_return(&sig.output)
+}
+
+fn is_ctx_type(t: &Type) -> bool {
+    get_type_name(t).is_some_and(|seg| seg.ident == "FfiCallCtx")
+}
+
+/// `Vec<GosValue>`, the arguments of an untyped function, other `Vec`s are
+/// typed parameters like `Vec<String>`
+fn is_args_type(t: &Type) -> bool {
+    get_type_name(t).is_some_and(|seg| {
+        seg.ident == "Vec"
+            && get_type_name(&get_type_arg_type(&seg.arguments))
+                .is_some_and(|seg| seg.ident == "GosValue")
+    })
+}
+
+fn is_untyped_return(rt: &ReturnType) -> bool {
+    let is_values = |t: &Type| match get_type_name(t) {
+        Some(seg) => seg.ident == "Vec" || seg.ident == "GosValue",
+        None => false,
+    };
+    match rt {
+        ReturnType::Default => true,
+        ReturnType::Type(_, t) => match get_type_name(t) {
+            Some(seg) if seg.ident == "RuntimeResult" => {
+                let inner = get_type_arg_type(&seg.arguments);
+                is_values(&inner) || matches!(&inner, Type::Tuple(tt) if tt.elems.is_empty())
+            }
+            Some(seg) if seg.ident == "Pin" => true,
+            _ => is_values(t),
+        },
+    }
+}
+
+fn gen_typed_wrapper_method(m: &ImplItemMethod, name: &str) -> ImplItemMethod {
+    if m.sig.asyncness.is_some() {
+        panic!("async func can only take Vec<GosValue> and return RuntimeResult<Vec<GosValue>>>");
+    }
+    let callee = &m.sig.ident;
+    let mut count = 0usize;
+    let mut convs: Vec<Stmt> = vec![];
+    let mut call_args: Punctuated<Expr, Token![,]> = Punctuated::new();
+    for arg in m.sig.inputs.iter() {
+        let ty = match arg {
+            FnArg::Typed(pt) => &pt.ty,
+            FnArg::Receiver(_) => continue,
+        };
+        if is_ctx_type(ty) {
+            call_args.push(parse_quote! { ctx });
+            continue;
+        }
+        let ident = Ident::new(&format!("arg{}", count), Span::call_site());
+        // borrowed parameters are converted to owned values and passed by reference
+        let (owned, by_ref): (Type, bool) = match &**ty {
+            Type::Reference(tr) => match &*tr.elem {
+                Type::Path(tp) if tp.path.is_ident("str") => (parse_quote! { String }, true),
+                Type::Slice(ts) if ts.elem.to_token_stream().to_string() == "u8" => {
+                    (parse_quote! { Vec<u8> }, true)
+                }
+                _ => panic!("unsupported parameter type of typed ffi function"),
+            },
+            t => (t.clone(), false),
+        };
+        convs.push(parse_quote! {
+            let #ident: #owned = goscript_vm::ffi::arg_from_gos(ctx, &args, #count)?;
+        });
+        call_args.push(match by_ref {
+            true => parse_quote! { &#ident },
+            false => parse_quote! { #ident },
+        });
+        count += 1;
+    }
+
+    let (is_result, ret) = match &m.sig.output {
+        ReturnType::Default => (false, None),
+        ReturnType::Type(_, t) => match get_type_name(t) {
+            Some(seg) if seg.ident == "RuntimeResult" => {
+                (true, Some(get_type_arg_type(&seg.arguments)))
+            }
+            _ => (false, Some((**t).clone())),
+        },
+    };
+    let call: Expr = match is_result {
+        true => parse_quote! { self.#callee(#call_args)? },
+        false => parse_quote! { self.#callee(#call_args) },
+    };
+    let body: Vec<Stmt> = match ret {
+        Some(Type::Tuple(tt)) if !tt.elems.is_empty() => {
+            let results: Punctuated<Expr, Token![,]> = (0..tt.elems.len())
+                .map(|i| -> Expr {
+                    let index = syn::Index::from(i);
+                    parse_quote! { goscript_vm::ffi::IntoGos::into_gos(re.#index, ctx) }
+                })
+                .collect();
+            parse_quote! {
+                let re = #call;
+                Ok(vec![#results])
+            }
+        }
+        Some(Type::Tuple(_)) | None => parse_quote! {
+            #call;
+            Ok(vec![])
+        },
+        Some(_) => parse_quote! {
+            let re = #call;
+            Ok(vec![goscript_vm::ffi::IntoGos::into_gos(re, ctx)])
+        },
+    };
+
+    let mut wrapper: ImplItemMethod = parse_quote! {
+        fn dummy(
+            &self,
+            ctx: &mut FfiCallCtx,
+            args: Vec<GosValue>,
+        ) -> Pin<Box<dyn Future<Output = goscript_vm::value::RuntimeResult<Vec<GosValue>>> + '_>> {
+            let re = (|| -> goscript_vm::value::RuntimeResult<Vec<GosValue>> {
+                goscript_vm::ffi::check_arg_count(ctx, &args, #count)?;
+                #(#convs)*
+                #(#body)*
+            })();
+            Box::pin(async move { re })
+        }
+    };
+    wrapper.sig.ident = Ident::new(name, Span::call_site());
+    wrapper.attrs = m.attrs.clone();
+    wrapper
+}
+
 fn get_return_type_attributes(rt: &ReturnType, return_elem_name: &str) -> (bool, FfiReturnType) {
     match rt {
         ReturnType::Default => (false, FfiReturnType::ZeroVal),
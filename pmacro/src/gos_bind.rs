@@ -0,0 +1,98 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+use quote::quote;
+use syn::ext::IdentExt;
+use syn::{parse_macro_input, Data, DeriveInput, Field, Fields, Lit, Meta, NestedMeta};
+
+/// Implements `goscript_vm::bind::GosBind` for a struct with named fields,
+/// binding each field to the Go field of the same name, ignoring case and
+/// underscores, or to the one named by `#[gos(name = "...")]`.
+pub fn derive_gos_bind_implement(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let fields = match &input.data {
+        Data::Struct(s) => match &s.fields {
+            Fields::Named(f) => f.named.iter().collect::<Vec<&Field>>(),
+            _ => {
+                return syn::Error::new_spanned(
+                    name,
+                    "GosBind can only be derived for structs with named fields",
+                )
+                .to_compile_error()
+                .into()
+            }
+        },
+        _ => {
+            return syn::Error::new_spanned(name, "GosBind can only be derived for structs")
+                .to_compile_error()
+                .into()
+        }
+    };
+    let idents: Vec<_> = fields.iter().map(|f| f.ident.clone().unwrap()).collect();
+    let field_names: Vec<String> = idents.iter().map(|i| i.unraw().to_string()).collect();
+    let go_names = match fields
+        .iter()
+        .map(|f| go_name(f))
+        .collect::<syn::Result<Vec<_>>>()
+    {
+        Ok(names) => names.into_iter().map(|n| match n {
+            Some(n) => quote! { Some(#n) },
+            None => quote! { None },
+        }),
+        Err(e) => return e.to_compile_error().into(),
+    };
+    let go_names: Vec<_> = go_names.collect();
+    let name_str = name.to_string();
+
+    let expanded = quote! {
+        impl #impl_generics goscript_vm::bind::GosBind for #name #ty_generics #where_clause {
+            fn from_gos_meta(
+                v: &goscript_vm::value::GosValue,
+                meta: goscript_vm::metadata::Meta,
+                ctx: &goscript_vm::ffi::FfiCallCtx,
+            ) -> goscript_vm::value::RuntimeResult<Self> {
+                let s = goscript_vm::bind::BoundStruct::new(v, meta, ctx, #name_str)?;
+                Ok(#name {
+                    #( #idents: s.get(#field_names, #go_names)?, )*
+                })
+            }
+
+            fn to_gos_meta(
+                &self,
+                meta: goscript_vm::metadata::Meta,
+                ctx: &goscript_vm::ffi::FfiCallCtx,
+            ) -> goscript_vm::value::RuntimeResult<goscript_vm::value::GosValue> {
+                let s = goscript_vm::bind::BoundStruct::zero(meta, ctx, #name_str)?;
+                #( s.set(#field_names, #go_names, &self.#idents)?; )*
+                Ok(s.into_value())
+            }
+        }
+    };
+    proc_macro::TokenStream::from(expanded)
+}
+
+/// The name given by `#[gos(name = "...")]`
+fn go_name(field: &Field) -> syn::Result<Option<String>> {
+    for attr in field.attrs.iter().filter(|a| a.path.is_ident("gos")) {
+        if let Meta::List(list) = attr.parse_meta()? {
+            for nested in list.nested.iter() {
+                match nested {
+                    NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("name") => {
+                        if let Lit::Str(s) = &nv.lit {
+                            return Ok(Some(s.value()));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        return Err(syn::Error::new_spanned(
+            attr,
+            "expected #[gos(name = \"...\")]",
+        ));
+    }
+    Ok(None)
+}
@@ -4,6 +4,7 @@
 
 mod ffi;
 mod ffi_impl;
+mod gos_bind;
 mod unsafe_ptr;
 
 #[proc_macro_derive(Ffi)]
@@ -23,3 +24,8 @@ pub fn ffi_impl(
 pub fn derive_unsafe_ptr(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     unsafe_ptr::derive_unsafe_ptr_implement(input)
 }
+
+#[proc_macro_derive(GosBind, attributes(gos))]
+pub fn derive_gos_bind(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    gos_bind::derive_gos_bind_implement(input)
+}
@@ -10,5 +10,9 @@ package math
 //	Abs(±Inf) = +Inf
 //	Abs(NaN) = NaN
 func Abs(x float64) float64 {
+	return arch.abs(x)
+}
+
+func abs(x float64) float64 {
 	return Float64frombits(Float64bits(x) &^ (1 << 63))
 }
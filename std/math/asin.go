@@ -17,6 +17,10 @@ package math
 //	Asin(±0) = ±0
 //	Asin(x) = NaN if x < -1 or x > 1
 func Asin(x float64) float64 {
+	return arch.asin(x)
+}
+
+func asin(x float64) float64 {
 	if x == 0 {
 		return x // special case
 	}
@@ -46,7 +50,9 @@ func Asin(x float64) float64 {
 //
 // Special case is:
 //	Acos(x) = NaN if x < -1 or x > 1
-func Acos(x float64) float64
+func Acos(x float64) float64 {
+	return arch.acos(x)
+}
 
 func acos(x float64) float64 {
 	return Pi/2 - Asin(x)
@@ -93,6 +93,10 @@ func satan(x float64) float64 {
 //      Atan(±0) = ±0
 //      Atan(±Inf) = ±Pi/2
 func Atan(x float64) float64 {
+	return arch.atan(x)
+}
+
+func atan(x float64) float64 {
 	if x == 0 {
 		return x
 	}
@@ -27,6 +27,10 @@ package math
 //	Atan2(+Inf, x) = +Pi/2
 //	Atan2(-Inf, x) = -Pi/2
 func Atan2(y, x float64) float64 {
+	return arch.atan2(y, x)
+}
+
+func atan2(y, x float64) float64 {
 	// special cases
 	switch {
 	case IsNaN(y) || IsNaN(x):
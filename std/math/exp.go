@@ -88,6 +88,10 @@ package math
 // Very large values overflow to 0 or +Inf.
 // Very small values underflow to 1.
 func Exp(x float64) float64 {
+	return arch.exp(x)
+}
+
+func exp(x float64) float64 {
 	const (
 		Ln2Hi = 6.93147180369123816490e-01
 		Ln2Lo = 1.90821492927058770002e-10
@@ -131,6 +135,10 @@ func Exp(x float64) float64 {
 //
 // Special cases are the same as Exp.
 func Exp2(x float64) float64 {
+	return arch.exp2(x)
+}
+
+func exp2(x float64) float64 {
 	const (
 		Ln2Hi = 6.93147180369123816490e-01
 		Ln2Lo = 1.90821492927058770002e-10
@@ -11,6 +11,10 @@ package math
 //	Floor(±Inf) = ±Inf
 //	Floor(NaN) = NaN
 func Floor(x float64) float64 {
+	return arch.floor(x)
+}
+
+func floor(x float64) float64 {
 	if x == 0 || IsNaN(x) || IsInf(x, 0) {
 		return x
 	}
@@ -32,6 +36,10 @@ func Floor(x float64) float64 {
 //	Ceil(±Inf) = ±Inf
 //	Ceil(NaN) = NaN
 func Ceil(x float64) float64 {
+	return arch.ceil(x)
+}
+
+func ceil(x float64) float64 {
 	return -Floor(-x)
 }
 
@@ -42,6 +50,10 @@ func Ceil(x float64) float64 {
 //	Trunc(±Inf) = ±Inf
 //	Trunc(NaN) = NaN
 func Trunc(x float64) float64 {
+	return arch.trunc(x)
+}
+
+func trunc(x float64) float64 {
 	if x == 0 || IsNaN(x) || IsInf(x, 0) {
 		return x
 	}
@@ -56,6 +68,10 @@ func Trunc(x float64) float64 {
 //	Round(±Inf) = ±Inf
 //	Round(NaN) = NaN
 func Round(x float64) float64 {
+	return arch.round(x)
+}
+
+func round(x float64) float64 {
 	// Round is a faster implementation of:
 	//
 	// func Round(x float64) float64 {
@@ -17,6 +17,10 @@ package math
 //	Hypot(NaN, q) = NaN
 //	Hypot(p, NaN) = NaN
 func Hypot(p, q float64) float64 {
+	return arch.hypot(p, q)
+}
+
+func hypot(p, q float64) float64 {
 	// special cases
 	switch {
 	case IsInf(p, 0) || IsInf(q, 0):
@@ -0,0 +1,48 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+package math
+
+// The functions below are implemented natively with Rust's f64 methods, which
+// follow IEEE 754 and C99 for the special cases, just like Go. The Go
+// implementations are kept next to the exported functions, in lower case.
+
+var arch ffiMath
+
+func init() {
+	arch = ffi(ffiMath, "math")
+}
+
+type ffiMath interface {
+	sqrt(x float64) float64
+	pow(x, y float64) float64
+	floor(x float64) float64
+	ceil(x float64) float64
+	trunc(x float64) float64
+	round(x float64) float64
+	abs(x float64) float64
+	log(x float64) float64
+	log10(x float64) float64
+	log2(x float64) float64
+	log1p(x float64) float64
+	exp(x float64) float64
+	exp2(x float64) float64
+	expm1(x float64) float64
+	sin(x float64) float64
+	cos(x float64) float64
+	tan(x float64) float64
+	asin(x float64) float64
+	acos(x float64) float64
+	atan(x float64) float64
+	atan2(y, x float64) float64
+	sinh(x float64) float64
+	cosh(x float64) float64
+	tanh(x float64) float64
+	asinh(x float64) float64
+	acosh(x float64) float64
+	atanh(x float64) float64
+	cbrt(x float64) float64
+	hypot(p, q float64) float64
+	mod(x, y float64) float64
+}
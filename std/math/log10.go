@@ -7,12 +7,20 @@ package math
 // Log10 returns the decimal logarithm of x.
 // The special cases are the same as for Log.
 func Log10(x float64) float64 {
+	return arch.log10(x)
+}
+
+func log10(x float64) float64 {
 	return Log(x) * (1 / Ln10)
 }
 
 // Log2 returns the binary logarithm of x.
 // The special cases are the same as for Log.
 func Log2(x float64) float64 {
+	return arch.log2(x)
+}
+
+func log2(x float64) float64 {
 	frac, exp := Frexp(x)
 	// Make sure exact powers of two give an exact answer.
 	// Don't depend on Log(0.5)*(1/Ln2)+exp being exactly exp-1.
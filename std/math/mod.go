@@ -19,6 +19,10 @@ package math
 //	Mod(x, ±Inf) = x
 //	Mod(x, NaN) = NaN
 func Mod(x, y float64) float64 {
+	return arch.mod(x, y)
+}
+
+func mod(x, y float64) float64 {
 	if y == 0 || IsInf(x, 0) || IsNaN(x) || IsNaN(y) {
 		return NaN()
 	}
@@ -42,9 +42,7 @@ type Source64 interface {
 // Unlike the default Source used by top-level functions, this source is not
 // safe for concurrent use by multiple goroutines.
 func NewSource(seed int64) Source {
-	var rng rngSource
-	rng.Seed(seed)
-	return &rng
+	return newRngSource(seed)
 }
 
 // A Rand is a source of random numbers.
@@ -115,6 +115,10 @@ var _cos = [...]float64{
 //	Cos(±Inf) = NaN
 //	Cos(NaN) = NaN
 func Cos(x float64) float64 {
+	return arch.cos(x)
+}
+
+func cos(x float64) float64 {
 	const (
 		PI4A = 7.85398125648498535156e-1  // 0x3fe921fb40000000, Pi/4 split into three parts
 		PI4B = 3.77489470793079817668e-8  // 0x3e64442d00000000,
@@ -174,6 +178,10 @@ func Cos(x float64) float64 {
 //	Sin(±Inf) = NaN
 //	Sin(NaN) = NaN
 func Sin(x float64) float64 {
+	return arch.sin(x)
+}
+
+func sin(x float64) float64 {
 	const (
 		PI4A = 7.85398125648498535156e-1  // 0x3fe921fb40000000, Pi/4 split into three parts
 		PI4B = 3.77489470793079817668e-8  // 0x3e64442d00000000,
@@ -91,6 +91,10 @@ package math
 //	Sqrt(NaN) = NaN
 
 func Sqrt(x float64) float64 {
+	return arch.sqrt(x)
+}
+
+func sqrt(x float64) float64 {
 	// special cases
 	switch {
 	case x == 0 || IsNaN(x) || IsInf(x, 1):
@@ -80,6 +80,10 @@ var _tanQ = [...]float64{
 //	Tan(±Inf) = NaN
 //	Tan(NaN) = NaN
 func Tan(x float64) float64 {
+	return arch.tan(x)
+}
+
+func tan(x float64) float64 {
 	const (
 		PI4A = 7.85398125648498535156e-1  // 0x3fe921fb40000000, Pi/4 split into three parts
 		PI4B = 3.77489470793079817668e-8  // 0x3e64442d00000000,
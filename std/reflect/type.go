@@ -330,7 +330,8 @@ type StructTag string
 // returned by Get is unspecified. To determine whether a tag is
 // explicitly set to the empty string, use Lookup.
 func (tag StructTag) Get(key string) string {
-	panic("not implemented")
+	v, _ := tag.Lookup(key)
+	return v
 }
 
 // Lookup returns the value associated with key in the tag string.
@@ -340,7 +341,57 @@ func (tag StructTag) Get(key string) string {
 // the tag string. If the tag does not have the conventional format,
 // the value returned by Lookup is unspecified.
 func (tag StructTag) Lookup(key string) (value string, ok bool) {
-	panic("not implemented")
+	// When modifying this code, also update the validateStructTag code
+	// in cmd/vet/structtag.go.
+
+	for tag != "" {
+		// Skip leading space.
+		i := 0
+		for i < len(tag) && tag[i] == ' ' {
+			i++
+		}
+		tag = tag[i:]
+		if tag == "" {
+			break
+		}
+
+		// Scan to colon. A space, a quote or a control character is a syntax error.
+		// Strictly speaking, control chars include the range [0x7f, 0x9f], not just
+		// [0x00, 0x1f], but in practice, we ignore the multi-byte control characters
+		// as it is simpler to inspect the tag's bytes than the tag's runes.
+		i = 0
+		for i < len(tag) && tag[i] > ' ' && tag[i] != ':' && tag[i] != '"' && tag[i] != 0x7f {
+			i++
+		}
+		if i == 0 || i+1 >= len(tag) || tag[i] != ':' || tag[i+1] != '"' {
+			break
+		}
+		name := string(tag[:i])
+		tag = tag[i+1:]
+
+		// Scan quoted string to find value.
+		i = 1
+		for i < len(tag) && tag[i] != '"' {
+			if tag[i] == '\\' {
+				i++
+			}
+			i++
+		}
+		if i >= len(tag) {
+			break
+		}
+		qvalue := string(tag[:i+1])
+		tag = tag[i+1:]
+
+		if key == name {
+			value, err := strconv.Unquote(qvalue)
+			if err != nil {
+				break
+			}
+			return value, true
+		}
+	}
+	return "", false
 }
 
 type reflectType struct {
@@ -369,7 +420,7 @@ func (t reflectType) NumMethod() int {
 }
 
 func (t reflectType) Name() string {
-	panic("not implemented")
+	return native.type_name(t.typePtr)
 }
 
 func (t reflectType) PkgPath() string {
@@ -381,7 +432,7 @@ func (t reflectType) Size() uintptr {
 }
 
 func (t reflectType) String() string {
-	return t.kind.String()
+	return native.type_string(t.typePtr)
 }
 
 func (t reflectType) Kind() Kind {
@@ -417,11 +468,24 @@ func (t reflectType) IsVariadic() bool {
 }
 
 func (t reflectType) Elem() Type {
-	panic("not implemented")
+	p, kind := native.type_elem(t.typePtr)
+	return reflectType{typePtr: p, kind: Kind(kind)}
 }
 
 func (t reflectType) Field(i int) StructField {
-	panic("not implemented")
+	name, p, kind, exported, embedded, tag := native.type_field(t.typePtr, i)
+	f := StructField{
+		Name:      name,
+		Type:      reflectType{typePtr: p, kind: Kind(kind)},
+		Tag:       StructTag(tag),
+		Index:     []int{i},
+		Anonymous: embedded,
+	}
+	if !exported {
+		// Goscript: the package path is not known at runtime
+		f.PkgPath = "-"
+	}
+	return f
 }
 
 func (t reflectType) FieldByIndex(index []int) StructField {
@@ -429,7 +493,12 @@ func (t reflectType) FieldByIndex(index []int) StructField {
 }
 
 func (t reflectType) FieldByName(name string) (StructField, bool) {
-	panic("not implemented")
+	for i := 0; i < t.NumField(); i++ {
+		if f := t.Field(i); f.Name == name {
+			return f, true
+		}
+	}
+	return StructField{}, false
 }
 
 func (t reflectType) FieldByNameFunc(match func(string) bool) (StructField, bool) {
@@ -441,15 +510,16 @@ func (t reflectType) In(i int) Type {
 }
 
 func (t reflectType) Key() Type {
-	panic("not implemented")
+	p, kind := native.type_key(t.typePtr)
+	return reflectType{typePtr: p, kind: Kind(kind)}
 }
 
 func (t reflectType) Len() int {
-	panic("not implemented")
+	return native.type_len(t.typePtr)
 }
 
 func (t reflectType) NumField() int {
-	panic("not implemented")
+	return native.type_num_field(t.typePtr)
 }
 
 func (t reflectType) NumIn() int {
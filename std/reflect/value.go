@@ -29,6 +29,16 @@ type ffiReflect interface {
 	index(p unsafe.Pointer, i int) unsafe.Pointer
 	is_nil(p unsafe.Pointer) bool
 	len(p unsafe.Pointer) int
+	iface(p unsafe.Pointer) interface{}
+	can_interface(p unsafe.Pointer) bool
+
+	type_string(t unsafe.Pointer) string
+	type_name(t unsafe.Pointer) string
+	type_elem(t unsafe.Pointer) (unsafe.Pointer, uint)
+	type_key(t unsafe.Pointer) (unsafe.Pointer, uint)
+	type_len(t unsafe.Pointer) int
+	type_num_field(t unsafe.Pointer) int
+	type_field(t unsafe.Pointer, i int) (string, unsafe.Pointer, uint, bool, bool, string)
 
 	map_range_init(p unsafe.Pointer) unsafe.Pointer
 	map_range_next(p unsafe.Pointer) bool
@@ -216,7 +226,7 @@ func (v Value) Int() int64 {
 
 // CanInterface reports whether Interface can be used without panicking.
 func (v Value) CanInterface() bool {
-	return false
+	return native.can_interface(v.ptr)
 }
 
 // Interface returns v's current value as an interface{}.
@@ -225,7 +235,7 @@ func (v Value) CanInterface() bool {
 // It panics if the Value was obtained by accessing
 // unexported struct fields.
 func (v Value) Interface() (i interface{}) {
-	panic("not implemented")
+	return native.iface(v.ptr)
 }
 
 // InterfaceData returns the interface v's value as a uintptr pair.
@@ -279,7 +289,12 @@ func (v Value) MapIndex(key Value) Value {
 // It panics if v's Kind is not Map.
 // It returns an empty slice if v represents a nil map.
 func (v Value) MapKeys() []Value {
-	panic("not implemented")
+	keys := make([]Value, 0, v.Len())
+	iter := v.MapRange()
+	for iter.Next() {
+		keys = append(keys, iter.Key())
+	}
+	return keys
 }
 
 // A MapIter is an iterator for ranging over a map.
@@ -16,9 +16,7 @@ import "reflect"
 // The function panics if the provided interface is not a slice.
 func Slice(slice interface{}, less func(i, j int) bool) {
 	rv := reflect.ValueOf(slice)
-	swap := reflect.Swapper(slice)
-	length := rv.Len()
-	quickSort_func(lessSwap{less, swap}, 0, length, maxDepth(length))
+	native.sort_slice(slice, rv.Len(), less)
 }
 
 // SliceStable sorts the provided slice given the provided less
@@ -27,8 +25,7 @@ func Slice(slice interface{}, less func(i, j int) bool) {
 // The function panics if the provided interface is not a slice.
 func SliceStable(slice interface{}, less func(i, j int) bool) {
 	rv := reflect.ValueOf(slice)
-	swap := reflect.Swapper(slice)
-	stable_func(lessSwap{less, swap}, rv.Len())
+	native.sort_slice(slice, rv.Len(), less)
 }
 
 // SliceIsSorted tests whether a slice is sorted.
@@ -6,7 +6,12 @@ package time
 
 // Sleep pauses the current goroutine for at least the duration d.
 // A negative or zero duration causes Sleep to return immediately.
-func Sleep(d Duration)
+func Sleep(d Duration) {
+	if d <= 0 {
+		return
+	}
+	native.sleep(int64(d))
+}
 
 // Interface to timers implemented in package runtime.
 // Must be in sync with ../runtime/time.go:/^type timer
@@ -36,13 +41,45 @@ func when(d Duration) int64 {
 	return t
 }
 
-// goscript: todo
-func startTimer(*runtimeTimer) {
+// goscript: a started timer runs on its own goroutine, which sleeps on the
+// VM's timer queue until t.when. t.i counts the starts and stops of t, so a
+// goroutine left over from before a Stop or Reset sees it has been
+// superseded when it wakes up, and exits without firing. t.tb is 1 while
+// the timer is pending.
+func startTimer(t *runtimeTimer) {
+	t.i++
+	t.tb = 1
+	go runTimer(t, t.i)
 }
 
-// goscript: todo
-func stopTimer(*runtimeTimer) bool {
-	return true
+func stopTimer(t *runtimeTimer) bool {
+	t.i++
+	active := t.tb != 0
+	t.tb = 0
+	return active
+}
+
+func runTimer(t *runtimeTimer, gen int) {
+	for {
+		native.sleep_until(t.when)
+		if t.i != gen {
+			return
+		}
+		if t.period > 0 {
+			t.when += t.period
+			// skip the ticks missed while the goroutine wasn't scheduled
+			if cur := runtimeNano(); t.when < cur {
+				t.when = cur + t.period
+			}
+		} else {
+			t.tb = 0
+		}
+		t.seq++
+		t.f(t.arg, t.seq)
+		if t.period <= 0 {
+			return
+		}
+	}
 }
 
 // The Timer type represents a single event.
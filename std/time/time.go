@@ -1067,15 +1067,24 @@ func daysIn(m Month, year int) int {
 	return int(daysBefore[m] - daysBefore[m-1])
 }
 
-// goscript: todo
+var native ffiTime = ffi(ffiTime, "time")
+
+// ffiTime is implemented by the engine, the timers are driven by the VM's
+// executor so sleeping goroutines don't take any cpu time.
+type ffiTime interface {
+	now() (sec int64, nsec int32, mono int64)
+	runtime_nano() int64
+	sleep(d int64)
+	sleep_until(when int64)
+}
+
 func now() (sec int64, nsec int32, mono int64) {
-	return 0, 0, 0
+	return native.now()
 }
 
 // runtimeNano returns the current value of the runtime clock in nanoseconds.
-// goscript: todo
 func runtimeNano() int64 {
-	return 0
+	return native.runtime_nano()
 }
 
 // Monotonic times are reported as offsets from startNano.
@@ -1084,7 +1093,9 @@ func runtimeNano() int64 {
 // which appears to have a default resolution of 15ms),
 // we avoid ever reporting a monotonic time of 0.
 // (Callers may want to use 0 as "time not set".)
-var startNano int64 = runtimeNano() - 1
+// goscript: the runtime clock starts at 0 when the VM starts, and native is
+// not usable before the package is initialized.
+var startNano int64 = -1
 
 // Now returns the current local time.
 func Now() Time {
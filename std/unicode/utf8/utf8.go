@@ -7,6 +7,21 @@
 // See https://en.wikipedia.org/wiki/UTF-8
 package utf8
 
+var native ffiUtf8
+
+func init() {
+	native = ffi(ffiUtf8, "unicode.utf8")
+}
+
+// DecodeRune, RuneLen, RuneCountInString and ValidString are implemented
+// natively, they are called the most by programs processing text.
+type ffiUtf8 interface {
+	decode_rune(p []byte) (rune, int)
+	rune_len(r rune) int
+	rune_count_in_string(s string) int
+	valid_string(s string) bool
+}
+
 // The conditions RuneError==unicode.ReplacementChar and
 // MaxRune==unicode.MaxRune are verified in the tests.
 // Defining them locally avoids this package depending on package unicode.
@@ -149,43 +164,7 @@ func FullRuneInString(s string) bool {
 // out of range, or is not the shortest possible UTF-8 encoding for the
 // value. No other validation is performed.
 func DecodeRune(p []byte) (r rune, size int) {
-	n := len(p)
-	if n < 1 {
-		return RuneError, 0
-	}
-	p0 := p[0]
-	x := first[p0]
-	if x >= as {
-		// The following code simulates an additional check for x == xx and
-		// handling the ASCII and invalid cases accordingly. This mask-and-or
-		// approach prevents an additional branch.
-		mask := rune(x) << 31 >> 31 // Create 0x0000 or 0xFFFF.
-		return rune(p[0])&^mask | RuneError&mask, 1
-	}
-	sz := int(x & 7)
-	accept := acceptRanges[x>>4]
-	if n < sz {
-		return RuneError, 1
-	}
-	b1 := p[1]
-	if b1 < accept.lo || accept.hi < b1 {
-		return RuneError, 1
-	}
-	if sz <= 2 { // <= instead of == to help the compiler eliminate some bounds checks
-		return rune(p0&mask2)<<6 | rune(b1&maskx), 2
-	}
-	b2 := p[2]
-	if b2 < locb || hicb < b2 {
-		return RuneError, 1
-	}
-	if sz <= 3 {
-		return rune(p0&mask3)<<12 | rune(b1&maskx)<<6 | rune(b2&maskx), 3
-	}
-	b3 := p[3]
-	if b3 < locb || hicb < b3 {
-		return RuneError, 1
-	}
-	return rune(p0&mask4)<<18 | rune(b1&maskx)<<12 | rune(b2&maskx)<<6 | rune(b3&maskx), 4
+	return native.decode_rune(p)
 }
 
 // DecodeRuneInString is like DecodeRune but its input is a string. If s is
@@ -319,21 +298,7 @@ func DecodeLastRuneInString(s string) (r rune, size int) {
 // RuneLen returns the number of bytes required to encode the rune.
 // It returns -1 if the rune is not a valid value to encode in UTF-8.
 func RuneLen(r rune) int {
-	switch {
-	case r < 0:
-		return -1
-	case r <= rune1Max:
-		return 1
-	case r <= rune2Max:
-		return 2
-	case surrogateMin <= r && r <= surrogateMax:
-		return -1
-	case r <= rune3Max:
-		return 3
-	case r <= MaxRune:
-		return 4
-	}
-	return -1
+	return native.rune_len(r)
 }
 
 // EncodeRune writes into p (which must be large enough) the UTF-8 encoding of the rune.
@@ -408,37 +373,7 @@ func RuneCount(p []byte) int {
 
 // RuneCountInString is like RuneCount but its input is a string.
 func RuneCountInString(s string) (n int) {
-	ns := len(s)
-	for i := 0; i < ns; n++ {
-		c := s[i]
-		if c < RuneSelf {
-			// ASCII fast path
-			i++
-			continue
-		}
-		x := first[c]
-		if x == xx {
-			i++ // invalid.
-			continue
-		}
-		size := int(x & 7)
-		if i+size > ns {
-			i++ // Short or invalid.
-			continue
-		}
-		accept := acceptRanges[x>>4]
-		if c := s[i+1]; c < accept.lo || accept.hi < c {
-			size = 1
-		} else if size == 2 {
-		} else if c := s[i+2]; c < locb || hicb < c {
-			size = 1
-		} else if size == 3 {
-		} else if c := s[i+3]; c < locb || hicb < c {
-			size = 1
-		}
-		i += size
-	}
-	return n
+	return native.rune_count_in_string(s)
 }
 
 // RuneStart reports whether the byte could be the first byte of an encoded,
@@ -480,34 +415,7 @@ func Valid(p []byte) bool {
 
 // ValidString reports whether s consists entirely of valid UTF-8-encoded runes.
 func ValidString(s string) bool {
-	n := len(s)
-	for i := 0; i < n; {
-		si := s[i]
-		if si < RuneSelf {
-			i++
-			continue
-		}
-		x := first[si]
-		if x == xx {
-			return false // Illegal starter byte.
-		}
-		size := int(x & 7)
-		if i+size > n {
-			return false // Short or invalid.
-		}
-		accept := acceptRanges[x>>4]
-		if c := s[i+1]; c < accept.lo || accept.hi < c {
-			return false
-		} else if size == 2 {
-		} else if c := s[i+2]; c < locb || hicb < c {
-			return false
-		} else if size == 3 {
-		} else if c := s[i+3]; c < locb || hicb < c {
-			return false
-		}
-		i += size
-	}
-	return true
+	return native.valid_string(s)
 }
 
 // ValidRune reports whether r can be legally encoded as UTF-8.
@@ -145,6 +145,8 @@ impl<'a, S: SourceRead> Checker<'a, S> {
             if lhs.typ().is_none() {
                 lhs.set_type(Some(invalid_type));
             }
+            // avoid follow-on "declared and not used" errors
+            lhs.entity_type_mut().var_property_mut().used = true;
             return None;
         }
         // If the lhs doesn't have a type yet, use the type of x.
@@ -289,6 +291,8 @@ impl<'a, S: SourceRead> Checker<'a, S> {
                 if lobj.typ().is_none() {
                     lobj.set_type(Some(invalid_type));
                 }
+                // avoid follow-on "declared and not used" errors
+                lobj.entity_type_mut().var_property_mut().used = true;
             }
         };
 
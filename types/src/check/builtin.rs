@@ -355,7 +355,7 @@ impl<'a, S: SourceRead> Checker<'a, S> {
                 // if both arguments are constants, the result is a constant
                 match (&mut x.mode, &y.mode) {
                     (OperandMode::Constant(vx), OperandMode::Constant(vy)) => {
-                        *vx = Value::binary_op(vx, &Token::ADD, &vy.to_float().make_imag());
+                        *vx = Value::binary_op(vx, &Token::ADD, &vy.real().make_imag());
                     }
                     _ => {
                         x.mode = OperandMode::Value;
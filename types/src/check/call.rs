@@ -93,6 +93,7 @@ impl<'a, S: SourceRead> Checker<'a, S> {
                             let (count, _) = result.rhs_count();
                             let re = UnpackedResultLeftovers::new(&result, None);
                             self.arguments(x, e, sig_key, &re, count, fctx);
+                            self.printf(e, fctx);
                         }
                     }
 
@@ -263,7 +264,13 @@ impl<'a, S: SourceRead> Checker<'a, S> {
                     let lobj_pkg = lobj.pkg();
                     match lobj.entity_type_mut() {
                         EntityType::PkgName(imported, used) => {
-                            debug_assert_eq!(self.pkg, lobj_pkg.unwrap());
+                            // imported by this package, or by an outer one
+                            // if it is nested
+                            debug_assert!(
+                                self.pkg == lobj_pkg.unwrap()
+                                    || self.tc_objs.pkgs[self.pkg].scope()
+                                        != self.tc_objs.pkgs[lobj_pkg.unwrap()].scope()
+                            );
                             self.result.record_use(*ikey, okey);
                             *used = true;
                             let pkg = &self.tc_objs.pkgs[*imported];
@@ -343,9 +350,20 @@ impl<'a, S: SourceRead> Checker<'a, S> {
                             ed, sel_name, td, sel_name
                         )
                     }
+                    LookupResult::BadMethodReceiver if x.mode == OperandMode::TypeExpr => {
+                        let ed = self.new_dis(x.expr.as_ref().unwrap());
+                        let td = self.new_td_o(&x.typ);
+                        format!(
+                            "invalid method expression {}.{} (needs pointer receiver (*{}).{})",
+                            ed, sel_name, td, sel_name
+                        )
+                    }
+                    // the operand is not addressable, like a map element or
+                    // the result of a call, calling the method on a copy
+                    // would lose the changes
                     LookupResult::BadMethodReceiver => {
                         let td = self.new_td_o(&x.typ);
-                        format!("{} is not in method set of {}", sel_name, td)
+                        format!("cannot call pointer method {} on {}", sel_name, td)
                     }
                     LookupResult::Entry(_, _, _) => unreachable!(),
                 };
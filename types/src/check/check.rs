@@ -232,6 +232,8 @@ pub struct Checker<'a, S: SourceRead> {
     pub result: TypeInfo,
     // for debug
     pub indent: Rc<RefCell<usize>>,
+    // unused imports are not errors, for the snippets of a REPL
+    pub allow_unused_imports: bool,
 }
 
 impl ObjContext {
@@ -427,18 +429,25 @@ impl<'a, S: SourceRead> Checker<'a, S> {
             reader: reader,
             result: TypeInfo::new(),
             indent: Rc::new(RefCell::new(0)),
+            allow_unused_imports: false,
         }
     }
 
-    pub fn check(mut self, mut files: Vec<ast::File>) -> Result<PackageKey, ()> {
-        self.check_files_pkg_name(&files)?;
+    pub fn check(mut self, files: Vec<ast::File>) -> Result<PackageKey, ()> {
+        let mut files = self.check_files_pkg_name(files)?;
         let fctx = &mut FilesContext::new(&files);
         self.collect_objects(fctx);
         self.package_objects(fctx);
         fctx.process_delayed(0, &mut self);
         self.init_order();
-        self.unused_imports(fctx);
+        if !self.allow_unused_imports {
+            self.unused_imports(fctx);
+        }
         self.record_untyped(fctx);
+        if !self.errors.has_errors() {
+            self.check_pure_funcs(&files);
+            self.vet_error_vars(&files);
+        }
 
         std::mem::swap(&mut self.result.ast_files, &mut files);
         self.all_results.insert(self.pkg, self.result);
@@ -459,6 +468,12 @@ impl<'a, S: SourceRead> Checker<'a, S> {
         self.errors
     }
 
+    /// The results of the packages checked before this one
+    #[inline]
+    pub fn all_results(&self) -> &HashMap<PackageKey, TypeInfo> {
+        self.all_results
+    }
+
     #[inline]
     pub fn trace(&self) -> bool {
         self.trace_config.trace_checker
@@ -478,32 +493,37 @@ impl<'a, S: SourceRead> Checker<'a, S> {
         )
     }
 
-    /// check files' package name
-    fn check_files_pkg_name(&mut self, files: &Vec<ast::File>) -> Result<(), ()> {
+    /// check files' package name, files in a different package are reported and ignored
+    fn check_files_pkg_name(&mut self, files: Vec<ast::File>) -> Result<Vec<ast::File>, ()> {
         let mut pkg_name: Option<String> = None;
-        for f in files.iter() {
+        let mut result = Vec::with_capacity(files.len());
+        for f in files.into_iter() {
             let ident = &self.ast_objs.idents[f.name];
-            if pkg_name.is_none() {
-                if ident.name == "_" {
-                    self.error(ident.pos, "invalid package name _".to_owned());
-                    return Err(());
-                } else {
-                    pkg_name = Some(ident.name.clone());
+            match &pkg_name {
+                None => {
+                    if ident.name == "_" {
+                        self.error(ident.pos, "invalid package name _".to_owned());
+                    } else {
+                        pkg_name = Some(ident.name.clone());
+                    }
+                    result.push(f);
+                }
+                Some(name) if name == &ident.name => result.push(f),
+                Some(name) => {
+                    self.error(
+                        f.package,
+                        format!("package {}; expected {}", ident.name, name),
+                    );
                 }
-            } else if &ident.name != pkg_name.as_ref().unwrap() {
-                self.error(
-                    f.package,
-                    format!(
-                        "package {}; expected {}",
-                        ident.name,
-                        pkg_name.as_ref().unwrap()
-                    ),
-                );
-                return Err(());
             }
         }
-        self.tc_objs.pkgs[self.pkg].set_name(pkg_name.unwrap());
-        Ok(())
+        match pkg_name {
+            Some(name) => {
+                self.tc_objs.pkgs[self.pkg].set_name(name);
+                Ok(result)
+            }
+            None => Err(()),
+        }
     }
 
     pub fn error(&self, pos: Pos, err: String) {
@@ -522,6 +542,12 @@ impl<'a, S: SourceRead> Checker<'a, S> {
         self.error_impl(pos, err.to_string(), true);
     }
 
+    /// Reports a problem that doesn't stop the compilation
+    pub fn warning(&self, pos: Pos, msg: String) {
+        let file = self.fset.file(pos).unwrap();
+        FilePosErrors::new(file, self.errors).add_warning(pos, msg);
+    }
+
     fn error_impl(&self, pos: Pos, err: String, soft: bool) {
         let file = self.fset.file(pos).unwrap();
         FilePosErrors::new(file, self.errors).add(pos, err, soft);
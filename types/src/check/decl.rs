@@ -288,9 +288,10 @@ impl<'a, S: SourceRead> Checker<'a, S> {
             if o == self.tc_objs.universe().indir() {
                 continue;
             }
-            self.error(pos, format!("\t{} refers to", self.lobj(*o).name()));
+            let oval = self.lobj(*o);
+            self.error(oval.pos(), format!("\t{} refers to", oval.name()));
         }
-        self.error(pos, format!("\t{} refers to", lobj.name()));
+        self.error(pos, format!("\t{}", lobj.name()));
 
         true
     }
@@ -478,6 +479,32 @@ impl<'a, S: SourceRead> Checker<'a, S> {
             // ok to continue
         }
 
+        let memoize = fdecl
+            .pragmas
+            .iter()
+            .any(|p| matches!(p, ast::Pragma::Memoize(_)));
+        if memoize {
+            let pos = fdecl.pos(self.ast_objs);
+            let f = move |checker: &mut Checker<S>, _: &mut FilesContext<S>| {
+                checker.memoize_sig(pos, sig_key);
+            };
+            fctx.later(Box::new(f));
+        }
+        if fdecl.pragmas.contains(&ast::Pragma::Pure) {
+            let pos = fdecl.pos(self.ast_objs);
+            if fdecl.recv.is_some() {
+                self.error_str(pos, "//gos:pure can't be used on methods");
+            } else {
+                let f = move |checker: &mut Checker<S>, _: &mut FilesContext<S>| {
+                    let sig = checker.otype(sig_key).try_as_signature().unwrap();
+                    if sig.results_count(checker.tc_objs) == 0 {
+                        checker.error_str(pos, "//gos:pure function has no results");
+                    }
+                };
+                fctx.later(Box::new(f));
+            }
+        }
+
         if let Some(_) = &fdecl.body {
             let name = lobj.name().clone();
             let body = BodyContainer::FuncDecl(fdecl_key);
@@ -488,6 +515,35 @@ impl<'a, S: SourceRead> Checker<'a, S> {
         }
     }
 
+    /// Checks that a `//gos:memoize` function has results to cache, and
+    /// parameters that can be the keys of the cache.
+    fn memoize_sig(&mut self, pos: Pos, sig_key: TypeKey) {
+        let sig = self.otype(sig_key).try_as_signature().unwrap();
+        let params = self.otype(sig.params()).try_as_tuple().unwrap().vars();
+        let invalid: Vec<ObjKey> = sig
+            .recv()
+            .iter()
+            .chain(params.iter())
+            .filter(|&&p| !typ::comparable(self.lobj(p).typ().unwrap(), self.tc_objs))
+            .copied()
+            .collect();
+        if sig.results_count(self.tc_objs) == 0 {
+            self.error_str(pos, "//gos:memoize function has no results");
+        }
+        for p in invalid.into_iter() {
+            let lobj = self.lobj(p);
+            let (ppos, name, t) = (lobj.pos(), lobj.name().clone(), lobj.typ().unwrap());
+            let td = self.new_dis(&t);
+            self.error(
+                ppos,
+                format!(
+                    "//gos:memoize function parameter {} of non-comparable type {}",
+                    name, td
+                ),
+            );
+        }
+    }
+
     pub fn add_method_decls(&mut self, okey: ObjKey, fctx: &mut FilesContext<S>) {
         // get associated methods
         // (Checker.collect_objects only collects methods with non-blank names;
@@ -541,9 +597,9 @@ impl<'a, S: SourceRead> Checker<'a, S> {
                             self.error(
                                 mobj.pos(),
                                 format!(
-                                    "method {} already declared for {}",
-                                    mobj.name(),
-                                    self.new_dis(m)
+                                    "method {}.{} already declared",
+                                    self.lobj(okey).name(),
+                                    mobj.name()
                                 ),
                             );
                         }
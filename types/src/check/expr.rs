@@ -600,11 +600,11 @@ impl<'a, S: SourceRead> Checker<'a, S> {
             return;
         }
 
-        // spec: "The right operand in a shift expression must have unsigned
-        // integer type or be an untyped constant representable by a value of
-        // type uint."
+        // spec: "The right operand in a shift expression must have integer
+        // type or be an untyped constant representable by a value of type
+        // uint." A negative count panics at run time.
         let ytval = self.otype(y.typ.unwrap());
-        if ytval.is_unsigned(o) {
+        if ytval.is_integer(o) && !ytval.is_untyped(o) {
             //ok
         } else if ytval.is_untyped(o) {
             self.convert_untyped(y, self.basic_type(BasicType::Uint), fctx);
@@ -614,10 +614,7 @@ impl<'a, S: SourceRead> Checker<'a, S> {
             }
         } else {
             let yd = self.new_dis(y);
-            self.error(
-                yd.pos(),
-                format!("shift count {} must be unsigned integer", yd),
-            );
+            self.error(yd.pos(), format!("shift count {} must be integer", yd));
             x.mode = OperandMode::Invalid;
             return;
         }
@@ -628,10 +625,7 @@ impl<'a, S: SourceRead> Checker<'a, S> {
                 let yval = yv.to_int();
                 if !yval.is_int() {
                     let yd = self.new_dis(y);
-                    self.invalid_op(
-                        yd.pos(),
-                        &format!("shift count {} must be unsigned integer", yd),
-                    );
+                    self.invalid_op(yd.pos(), &format!("shift count {} must be integer", yd));
                     x.mode = OperandMode::Invalid;
                     return;
                 }
@@ -1151,7 +1145,7 @@ impl<'a, S: SourceRead> Checker<'a, S> {
                                         break; // cannot continue
                                     }
                                     let fld = self.lobj(fields[i]);
-                                    if !fld.exported() && fld.pkg() != Some(self.pkg) {
+                                    if !fld.same_id(Some(self.pkg), fld.name(), self.tc_objs) {
                                         let pos = x.pos(self.ast_objs);
                                         let (n, td) = (fld.name(), self.new_dis(&ty));
                                         let msg = format!(
@@ -1573,6 +1567,7 @@ impl<'a, S: SourceRead> Checker<'a, S> {
     }
 
     /// type_assertion checks that x.(T) is legal; xtyp must be the type of x.
+    /// pos is the position of the case if it's a case of a type switch.
     pub fn type_assertion(
         &mut self,
         pos: Option<Pos>,
@@ -1581,22 +1576,25 @@ impl<'a, S: SourceRead> Checker<'a, S> {
         t: TypeKey,
         fctx: &mut FilesContext<S>,
     ) {
-        if let Some((method, wrong_type)) = lookup::assertable_to(xtype, t, self, fctx) {
+        if let Some((method, _)) = lookup::assertable_to(xtype, t, self, fctx) {
+            let cause = lookup::missing_method_cause(t, method, self.tc_objs);
             let dx = self.new_dis(x);
-            self.error(
-                pos.unwrap_or_else(|| dx.pos()),
-                format!(
-                    "{} cannot have dynamic type {} ({} {})",
-                    dx,
-                    self.new_dis(&t),
-                    if wrong_type {
-                        "wrong type for method"
-                    } else {
-                        "missing method"
-                    },
-                    self.lobj(method).name()
+            let td = self.new_dis(&t);
+            let msg = match pos {
+                Some(_) => format!(
+                    "impossible type switch case: {}\n\t{} cannot have dynamic type {} {}",
+                    td, dx, td, cause
                 ),
-            );
+                None => format!(
+                    "impossible type assertion: {}.({})\n\t{} does not implement {} {}",
+                    self.new_dis(x.expr.as_ref().unwrap()),
+                    td,
+                    td,
+                    self.new_dis(x.typ.as_ref().unwrap()),
+                    cause
+                ),
+            };
+            self.error(pos.unwrap_or_else(|| dx.pos()), msg);
         }
     }
 
@@ -42,26 +42,24 @@ impl GraphEdges {
 
 impl<'a, S: SourceRead> Checker<'a, S> {
     pub fn init_order(&mut self) {
-        let (mut nodes, edges) = self.dependency_graph();
-        nodes.sort_by(|a, b| a.ndeps.cmp(&b.ndeps)); // sort by n_deps
-        let len = nodes.len();
-        let mut nodes = &mut nodes[0..len];
+        let (nodes, edges) = self.dependency_graph();
+        let mut ndeps: HashMap<ObjKey, usize> = nodes.iter().map(|n| (n.obj, n.ndeps)).collect();
+        let mut pending: Vec<ObjKey> = nodes.iter().map(|n| n.obj).collect();
         let mut order: Vec<ObjKey> = vec![];
         let mut emitted: HashSet<DeclInfoKey> = HashSet::new();
 
-        loop {
-            if nodes.len() == 0 {
-                break;
-            }
-            let mut first_dependant = nodes
+        // repeatedly take the earliest declared object that is ready for
+        // initialization, i.e. with no uninitialized dependencies left
+        while !pending.is_empty() {
+            let (i, _) = pending
                 .iter()
                 .enumerate()
-                .find(|(_, n)| n.ndeps > 0)
-                .map_or(nodes.len(), |(i, _)| i);
-            if first_dependant == 0 {
-                // we have a cycle with the first node
+                .min_by_key(|(_, o)| (ndeps[*o], self.lobj(**o).order()))
+                .unwrap();
+            let obj = pending.remove(i);
+            if ndeps[&obj] > 0 {
+                // we have a cycle with this node
                 let visited = &mut HashSet::new();
-                let obj = nodes[0].obj;
                 // If obj is not part of the cycle (e.g., obj->b->c->d->c),
                 // cycle will be nil. Don't report anything in that case since
                 // the cycle is reported when the algorithm gets to an object
@@ -76,31 +74,15 @@ impl<'a, S: SourceRead> Checker<'a, S> {
                 // Ok to continue, but the variable initialization order
                 // will be incorrect at this point since it assumes no
                 // cycle errors.
-                // set first_dependant to 1 to remove the first node,
-                first_dependant = 1;
             }
 
-            let mut indep: Vec<ObjKey> = nodes[0..first_dependant].iter().map(|n| n.obj).collect();
-            indep.sort_by(|a, b| self.lobj(*a).order().cmp(&self.lobj(*b).order()));
-            order.append(&mut indep);
-
             // reduce dependency count of all dependent nodes
-            let to_sub: HashMap<ObjKey, usize> =
-                nodes[0..first_dependant]
-                    .iter()
-                    .fold(HashMap::new(), |mut init, x| {
-                        for p in edges[&x.obj].pred.borrow().iter() {
-                            *init.entry(*p).or_insert(0) += 1;
-                        }
-                        init
-                    });
-            // remove resolved nodes
-            nodes = &mut nodes[first_dependant..];
-            for n in nodes.iter_mut() {
-                n.ndeps -= *to_sub.get(&n.obj).unwrap_or(&0);
+            for p in edges[&obj].pred.borrow().iter() {
+                if let Some(n) = ndeps.get_mut(p) {
+                    *n = n.saturating_sub(1);
+                }
             }
-            // sort nodes, shoud be fast as it's almost sorted
-            nodes.sort_by(|a, b| a.ndeps.cmp(&b.ndeps));
+            order.push(obj);
         }
 
         // record the init order for variables with initializers only
@@ -163,7 +145,7 @@ impl<'a, S: SourceRead> Checker<'a, S> {
         // which are permitted. Yet such cycles may incorrectly inflate the dependency
         // count for variables which in turn may not get scheduled for initialization
         // in correct order.)
-        let mut nodes: Vec<GraphNode> = map
+        let mut objs: Vec<ObjKey> = map
             .iter()
             .filter_map(|(o, node)| {
                 if self.lobj(*o).entity_type().is_func() {
@@ -181,21 +163,33 @@ impl<'a, S: SourceRead> Checker<'a, S> {
                     }
                     None
                 } else {
-                    Some(GraphNode {
-                        obj: *o,
-                        ndeps: node.succ.borrow().len(),
-                        pos: self.lobj(*o).pos(),
-                    })
+                    Some(*o)
                 }
             })
             .collect();
 
+        // count the dependencies once all the function nodes are removed
+        let mut nodes: Vec<GraphNode> = objs
+            .drain(..)
+            .map(|o| GraphNode {
+                obj: o,
+                ndeps: map[&o].succ.borrow().len(),
+                pos: self.lobj(o).pos(),
+            })
+            .collect();
+
         nodes.sort_by(|a, b| a.pos.cmp(&b.pos)); // sort by pos
         (nodes, map)
     }
 
     fn report_cycle(&self, cycle: &Vec<ObjKey>) {
         let o = self.lobj(cycle[0]);
+        // report a more concise error for self references
+        if cycle.len() == 1 {
+            let msg = format!("initialization cycle: {} refers to itself", o.name());
+            self.error(o.pos(), msg);
+            return;
+        }
         self.error(o.pos(), format!("initialization cycle for {}", o.name()));
         self.error(o.pos(), format!("\t{} refers to", o.name()));
         for okey in cycle[1..].iter().rev() {
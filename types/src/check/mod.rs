@@ -15,10 +15,13 @@ mod expr;
 mod initorder;
 mod interface;
 mod label;
+mod printf;
+mod pure;
 mod resolver;
 mod returns;
 mod stmt;
 mod typexpr;
+mod vet;
 
 pub use check::TypeInfo;
 pub use check::{Checker, FilesContext};
@@ -0,0 +1,441 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+//
+//
+// This code is adapted from the printf analyzer of go vet
+// with license as follows:
+// Copyright 2010 The Go Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+use crate::SourceRead;
+
+use super::super::constant::Value;
+use super::super::lookup::{self, LookupResult};
+use super::super::objects::TypeKey;
+use super::super::operand::OperandMode;
+use super::super::typ::{BasicInfo, BasicType, Type};
+use super::check::{Checker, FilesContext};
+use goscript_parser::ast::{CallExpr, Expr, Node};
+use std::collections::HashSet;
+
+const ARG_BOOL: u32 = 1;
+const ARG_INT: u32 = 1 << 1;
+const ARG_FLOAT: u32 = 1 << 2;
+const ARG_COMPLEX: u32 = 1 << 3;
+const ARG_STRING: u32 = 1 << 4;
+const ARG_POINTER: u32 = 1 << 5;
+const ARG_ERROR: u32 = 1 << 6;
+const ARG_ANY: u32 = !0;
+
+/// The argument types accepted by a verb, None for unknown verbs
+fn verb_flags(verb: char) -> Option<u32> {
+    match verb {
+        'b' => Some(ARG_INT | ARG_FLOAT | ARG_COMPLEX | ARG_POINTER),
+        'c' | 'U' => Some(ARG_INT),
+        'd' | 'o' | 'O' => Some(ARG_INT | ARG_POINTER),
+        'e' | 'E' | 'f' | 'F' | 'g' | 'G' => Some(ARG_FLOAT | ARG_COMPLEX),
+        'p' => Some(ARG_POINTER),
+        'q' => Some(ARG_INT | ARG_STRING),
+        's' => Some(ARG_STRING),
+        't' => Some(ARG_BOOL),
+        'T' | 'v' => Some(ARG_ANY),
+        'x' | 'X' => Some(ARG_INT | ARG_FLOAT | ARG_COMPLEX | ARG_STRING | ARG_POINTER),
+        'w' => Some(ARG_ERROR),
+        _ => None,
+    }
+}
+
+/// A parsed formatting directive like "%-8.*[2]d"
+struct Directive {
+    text: String,
+    verb: char,
+    /// the arguments consumed by '*' widths and precisions
+    stars: Vec<usize>,
+    /// the argument of the verb
+    arg: usize,
+}
+
+/// Parses an argument index like [2] at chars[*i], returns None if there is
+/// none, Some(None) if it's invalid.
+fn arg_index(chars: &[char], i: &mut usize) -> Option<Option<usize>> {
+    if *i >= chars.len() || chars[*i] != '[' {
+        return None;
+    }
+    let close = chars[*i..].iter().position(|c| *c == ']');
+    let n = close.and_then(|c| {
+        let digits: String = chars[*i + 1..*i + c].iter().collect();
+        digits.parse::<usize>().ok().filter(|n| *n > 0)
+    });
+    *i = close.map_or(chars.len(), |c| *i + c + 1);
+    Some(n)
+}
+
+/// Skips a width or precision, a '*' takes it from the next argument
+fn width_or_star(chars: &[char], i: &mut usize, arg_num: &mut usize, stars: &mut Vec<usize>) {
+    if *i < chars.len() && chars[*i] == '*' {
+        stars.push(*arg_num);
+        *arg_num += 1;
+        *i += 1;
+    } else {
+        while *i < chars.len() && chars[*i].is_ascii_digit() {
+            *i += 1;
+        }
+    }
+}
+
+/// The printf-like functions of package fmt, and the index of their format
+fn format_index(name: &str) -> Option<usize> {
+    match name {
+        "Printf" | "Sprintf" | "Errorf" => Some(0),
+        "Fprintf" => Some(1),
+        _ => None,
+    }
+}
+
+impl<'a, S: SourceRead> Checker<'a, S> {
+    /// printf checks calls to the printf-like functions of package fmt whose
+    /// format string is a constant, reporting verbs that don't match the type
+    /// of their argument, and arguments that are missing or left over.
+    pub fn printf(&mut self, call: &CallExpr, fctx: &FilesContext<S>) {
+        let fmt_index = match self.printf_format_index(call) {
+            Some(i) => i,
+            None => return,
+        };
+        // a slice passed with ... has an unknown length
+        if call.ellipsis.is_some() || call.args.len() <= fmt_index {
+            return;
+        }
+        let format = match self.printf_arg_type(&call.args[fmt_index], fctx) {
+            Some((_, Some(s))) => s,
+            _ => return,
+        };
+        let fname = self.new_dis(&call.func).to_string();
+        let first_arg = fmt_index + 1;
+        let nargs = call.args.len();
+        let reads = |n: usize| -> String {
+            match n {
+                1 => "1 arg".to_owned(),
+                _ => format!("{} args", n),
+            }
+        };
+
+        let mut arg_num = first_arg;
+        let mut max_arg_num = first_arg;
+        let mut any_index = false;
+        let chars: Vec<char> = format.chars().collect();
+        let mut i = 0;
+        while i < chars.len() {
+            if chars[i] != '%' {
+                i += 1;
+                continue;
+            }
+            let d =
+                match self.parse_directive(&chars, &mut i, &mut arg_num, first_arg, &mut any_index)
+                {
+                    Ok(d) => d,
+                    Err(msg) => {
+                        let pos = call.args[fmt_index].pos(self.ast_objs);
+                        self.warning(pos, format!("{} format {}", fname, msg));
+                        return;
+                    }
+                };
+            if d.verb != '%' {
+                max_arg_num = max_arg_num.max(arg_num);
+            }
+            if !self.printf_directive(call, fctx, &fname, &d, first_arg) {
+                return;
+            }
+        }
+
+        // if any formats are indexed, extra arguments are ignored.
+        if !any_index && max_arg_num < nargs {
+            let pos = call.args[max_arg_num].pos(self.ast_objs);
+            self.warning(
+                pos,
+                format!(
+                    "{} call needs {} but has {}",
+                    fname,
+                    reads(max_arg_num - first_arg),
+                    reads(nargs - first_arg)
+                ),
+            );
+        }
+    }
+
+    fn printf_format_index(&self, call: &CallExpr) -> Option<usize> {
+        let sel = match &call.func {
+            Expr::Selector(sel) => sel,
+            _ => return None,
+        };
+        let okey = self.result.uses.get(&sel.sel)?;
+        let lobj = self.lobj(*okey);
+        if !lobj.entity_type().is_func() {
+            return None;
+        }
+        let pkg = lobj.pkg()?;
+        if self.tc_objs.pkgs[pkg].path() != "fmt" {
+            return None;
+        }
+        format_index(lobj.name())
+    }
+
+    /// The type of an argument, and its value if it's a constant string
+    fn printf_arg_type(
+        &self,
+        e: &Expr,
+        fctx: &FilesContext<S>,
+    ) -> Option<(TypeKey, Option<String>)> {
+        let id = e.id();
+        let (typ, val) = match self.result.types.get(&id) {
+            Some(tv) => (tv.typ, tv.get_const_val()),
+            None => {
+                let info = fctx.untyped.get(&id)?;
+                let val = match &info.mode {
+                    OperandMode::Constant(v) => Some(v),
+                    _ => None,
+                };
+                (info.typ?, val)
+            }
+        };
+        let s = val.and_then(|v| match v {
+            Value::Str(_) => Some(v.str_as_string()),
+            _ => None,
+        });
+        Some((typ, s))
+    }
+
+    /// Parses the directive starting with the '%' at chars[*i], advancing
+    /// *i past it, and *arg_num past the arguments it consumes.
+    fn parse_directive(
+        &self,
+        chars: &[char],
+        i: &mut usize,
+        arg_num: &mut usize,
+        first_arg: usize,
+        any_index: &mut bool,
+    ) -> Result<Directive, String> {
+        let start = *i;
+        let text = |i: usize| chars[start..i].iter().collect::<String>();
+        let mut stars = vec![];
+        *i += 1;
+        while *i < chars.len() && "#0+- ".contains(chars[*i]) {
+            *i += 1;
+        }
+        // an explicit argument index like [2] may precede the width, the
+        // precision and the verb
+        let mut index = |i: &mut usize, arg_num: &mut usize| match arg_index(chars, i) {
+            Some(n) => {
+                *any_index = true;
+                match n {
+                    Some(n) => {
+                        *arg_num = first_arg + n - 1;
+                        Ok(())
+                    }
+                    None => Err(format!("{} has invalid argument index", text(*i))),
+                }
+            }
+            None => Ok(()),
+        };
+        index(i, arg_num)?;
+        width_or_star(chars, i, arg_num, &mut stars);
+        if *i < chars.len() && chars[*i] == '.' {
+            *i += 1;
+            index(i, arg_num)?;
+            width_or_star(chars, i, arg_num, &mut stars);
+        }
+        index(i, arg_num)?;
+
+        if *i >= chars.len() {
+            return Err(format!("{} is missing verb at end of string", text(*i)));
+        }
+        let verb = chars[*i];
+        *i += 1;
+        let arg = *arg_num;
+        if verb != '%' {
+            *arg_num += 1;
+        }
+        Ok(Directive {
+            text: text(*i),
+            verb: verb,
+            stars: stars,
+            arg: arg,
+        })
+    }
+
+    /// Checks a directive against the arguments of the call, returns false
+    /// if an error is reported.
+    fn printf_directive(
+        &self,
+        call: &CallExpr,
+        fctx: &FilesContext<S>,
+        fname: &str,
+        d: &Directive,
+        first_arg: usize,
+    ) -> bool {
+        let nargs = call.args.len();
+        let fmt_pos = call.args[first_arg - 1].pos(self.ast_objs);
+        let missing = |arg: usize| {
+            let msg = format!(
+                "{} format {} reads arg #{}, but call has {} arg{}",
+                fname,
+                d.text,
+                arg + 1 - first_arg,
+                nargs - first_arg,
+                if nargs - first_arg == 1 { "" } else { "s" }
+            );
+            self.warning(fmt_pos, msg);
+        };
+
+        for &star in d.stars.iter() {
+            if star >= nargs {
+                missing(star);
+                return false;
+            }
+            let arg = &call.args[star];
+            if let Some((t, _)) = self.printf_arg_type(arg, fctx) {
+                if !self.printf_type_ok(t, ARG_INT, true, &mut HashSet::new()) {
+                    let msg = format!(
+                        "{} format {} uses non-int {} as argument of *",
+                        fname,
+                        d.text,
+                        self.new_dis(arg)
+                    );
+                    self.warning(arg.pos(self.ast_objs), msg);
+                    return false;
+                }
+            }
+        }
+
+        if d.verb == '%' {
+            return true;
+        }
+        let flags = match verb_flags(d.verb) {
+            Some(f) => f,
+            None => {
+                let msg = format!("{} format {} has unknown verb {}", fname, d.text, d.verb);
+                self.warning(fmt_pos, msg);
+                return false;
+            }
+        };
+        if d.verb == 'w' && !fname.ends_with("Errorf") {
+            let msg = format!("{} does not support error-wrapping directive %w", fname);
+            self.warning(fmt_pos, msg);
+            return false;
+        }
+        if d.arg >= nargs {
+            missing(d.arg);
+            return false;
+        }
+        let arg = &call.args[d.arg];
+        if let Some((t, _)) = self.printf_arg_type(arg, fctx) {
+            let ok = match d.verb {
+                // any value is fine if it can print itself
+                's' | 'v' | 'q' | 'x' | 'X' if self.has_method(t, &["Error", "String"]) => true,
+                _ => self.printf_type_ok(t, flags, true, &mut HashSet::new()),
+            };
+            if !ok && !self.has_method(t, &["Format"]) {
+                let msg = format!(
+                    "{} format {} has arg {} of wrong type {}",
+                    fname,
+                    d.text,
+                    self.new_dis(arg),
+                    self.new_dis(&t)
+                );
+                self.warning(arg.pos(self.ast_objs), msg);
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Reports whether t has a method with one of the names
+    fn has_method(&self, t: TypeKey, names: &[&str]) -> bool {
+        names.iter().any(|name| {
+            match lookup::lookup_field_or_method(t, false, None, name, self.tc_objs) {
+                LookupResult::Entry(okey, _, _) => self.lobj(okey).entity_type().is_func(),
+                _ => false,
+            }
+        })
+    }
+
+    /// Reports whether a value of type t can be printed with a verb that
+    /// accepts the argument types in flags. top is false for the elements
+    /// and fields of composite values.
+    fn printf_type_ok(
+        &self,
+        t: TypeKey,
+        flags: u32,
+        top: bool,
+        in_progress: &mut HashSet<TypeKey>,
+    ) -> bool {
+        if flags == ARG_ANY {
+            return true;
+        }
+        if flags == ARG_ERROR {
+            return self.otype(t).is_interface(self.tc_objs) || self.has_method(t, &["Error"]);
+        }
+        // elements and fields print themselves as strings, as vet allows
+        if !top && flags & ARG_STRING != 0 && self.has_method(t, &["Error", "String"]) {
+            return true;
+        }
+        // a recursive type is fine if the rest of it is
+        if !in_progress.insert(t) {
+            return true;
+        }
+        let objs = &*self.tc_objs;
+        let is_byte = |t: TypeKey| match self.otype(t).underlying_val(objs) {
+            Type::Basic(b) => b.typ().real_type() == BasicType::Uint8,
+            _ => false,
+        };
+        match self.otype(t).underlying_val(objs) {
+            Type::Basic(b) => match b.typ() {
+                BasicType::UnsafePointer | BasicType::UntypedNil => flags & ARG_POINTER != 0,
+                _ => match b.info() {
+                    BasicInfo::IsBoolean => flags & ARG_BOOL != 0,
+                    BasicInfo::IsInteger => flags & ARG_INT != 0,
+                    BasicInfo::IsFloat => flags & ARG_FLOAT != 0,
+                    BasicInfo::IsComplex => flags & ARG_COMPLEX != 0,
+                    BasicInfo::IsString => flags & ARG_STRING != 0,
+                    BasicInfo::IsInvalid => true,
+                },
+            },
+            // the dynamic type is only known at runtime
+            Type::Interface(_) => true,
+            Type::Array(detail) => {
+                (is_byte(detail.elem()) && flags & ARG_STRING != 0)
+                    || self.printf_type_ok(detail.elem(), flags, false, in_progress)
+            }
+            Type::Slice(detail) => {
+                (is_byte(detail.elem()) && flags & ARG_STRING != 0)
+                    || (top && flags == ARG_POINTER)
+                    || self.printf_type_ok(detail.elem(), flags, false, in_progress)
+            }
+            Type::Map(detail) => {
+                (top && flags == ARG_POINTER)
+                    || (self.printf_type_ok(detail.key(), flags, false, in_progress)
+                        && self.printf_type_ok(detail.elem(), flags, false, in_progress))
+            }
+            Type::Pointer(detail) => {
+                if flags & ARG_POINTER != 0 {
+                    return true;
+                }
+                // a pointer to a composite value prints like the value: &{...}
+                let base = detail.base();
+                match self.otype(base).underlying_val(objs) {
+                    Type::Struct(_) | Type::Array(_) | Type::Slice(_) | Type::Map(_) if top => {
+                        self.printf_type_ok(base, flags, false, in_progress)
+                    }
+                    _ => false,
+                }
+            }
+            Type::Struct(detail) => detail.fields().iter().all(|f| {
+                let ft = self.lobj(*f).typ().unwrap();
+                self.printf_type_ok(ft, flags, false, in_progress)
+            }),
+            Type::Chan(_) | Type::Signature(_) => flags & ARG_POINTER != 0,
+            Type::Tuple(_) | Type::Named(_) => false,
+        }
+    }
+}
@@ -0,0 +1,322 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+use crate::SourceRead;
+
+use super::super::obj::EntityType;
+use super::super::objects::ObjKey;
+use super::super::operand::OperandMode;
+use super::super::selection::SelectionKind;
+use super::super::typ;
+use super::super::universe::Builtin;
+use super::check::Checker;
+use goscript_parser::ast::{self, Expr, Node, Stmt};
+use goscript_parser::objects::IdentKey;
+use goscript_parser::position::Pos;
+use goscript_parser::token::Token;
+use std::collections::{HashMap, HashSet};
+
+impl<'a, S: SourceRead> Checker<'a, S> {
+    /// Reports the side effects in the bodies of the `//gos:pure` functions,
+    /// as the code generator leaves out the calls of them whose results are
+    /// unused. The check is conservative: any write that is not to a local
+    /// variable, channel operations, goroutines and calls of anything but
+    /// other `//gos:pure` functions and side effect free builtins are errors.
+    pub fn check_pure_funcs(&self, files: &Vec<ast::File>) {
+        let pure = self.pure_funcs(files);
+        for f in files.iter() {
+            for d in f.decls.iter() {
+                if let ast::Decl::Func(fkey) = d {
+                    let fdecl = &self.ast_objs.fdecls[*fkey];
+                    if !fdecl.pragmas.contains(&ast::Pragma::Pure) {
+                        continue;
+                    }
+                    if let Some(body) = &fdecl.body {
+                        self.pure_stmts(&body.list, &pure);
+                    }
+                }
+            }
+        }
+    }
+
+    /// The `//gos:pure` functions of the package and the packages checked
+    /// before it
+    fn pure_funcs(&self, files: &Vec<ast::File>) -> HashSet<ObjKey> {
+        let objs = &self.ast_objs;
+        let mut pure = HashSet::new();
+        let mut add = |files: &Vec<ast::File>, defs: &HashMap<IdentKey, Option<ObjKey>>| {
+            for d in files.iter().flat_map(|f| f.decls.iter()) {
+                if let ast::Decl::Func(fkey) = d {
+                    let fdecl = &objs.fdecls[*fkey];
+                    if fdecl.recv.is_none() && fdecl.pragmas.contains(&ast::Pragma::Pure) {
+                        if let Some(Some(okey)) = defs.get(&fdecl.name) {
+                            pure.insert(*okey);
+                        }
+                    }
+                }
+            }
+        };
+        add(files, &self.result.defs);
+        for ti in self.all_results().values() {
+            add(&ti.ast_files, &ti.defs);
+        }
+        pure
+    }
+
+    fn impure(&self, pos: Pos, what: String) {
+        self.error(pos, format!("{} in //gos:pure function", what));
+    }
+
+    fn pure_stmts(&self, list: &[Stmt], pure: &HashSet<ObjKey>) {
+        for s in list.iter() {
+            self.pure_stmt(s, pure);
+        }
+    }
+
+    fn pure_opt_stmt(&self, stmt: &Option<Stmt>, pure: &HashSet<ObjKey>) {
+        if let Some(s) = stmt {
+            self.pure_stmt(s, pure);
+        }
+    }
+
+    fn pure_stmt(&self, stmt: &Stmt, pure: &HashSet<ObjKey>) {
+        let objs = &*self.ast_objs;
+        match stmt {
+            Stmt::Decl(d) => {
+                if let ast::Decl::Gen(gdecl) = d.as_ref() {
+                    for skey in gdecl.specs.iter() {
+                        if let ast::Spec::Value(vs) = &objs.specs[*skey] {
+                            self.pure_exprs(&vs.values, pure);
+                        }
+                    }
+                }
+            }
+            Stmt::Labeled(lkey) => self.pure_stmt(&objs.l_stmts[*lkey].stmt, pure),
+            Stmt::Expr(e) => self.pure_expr(e, pure),
+            Stmt::Send(s) => self.impure(s.chan.pos(objs), "channel send".to_owned()),
+            Stmt::IncDec(s) => {
+                self.pure_target(&s.expr);
+                self.pure_expr(&s.expr, pure);
+            }
+            Stmt::Assign(akey) => {
+                let astmt = &objs.a_stmts[*akey];
+                if astmt.token != Token::DEFINE {
+                    for e in astmt.lhs.iter() {
+                        self.pure_target(e);
+                    }
+                }
+                self.pure_exprs(&astmt.lhs, pure);
+                self.pure_exprs(&astmt.rhs, pure);
+            }
+            Stmt::Go(s) => self.impure(s.go, "go statement".to_owned()),
+            Stmt::Defer(s) => self.pure_expr(&s.call, pure),
+            Stmt::Return(s) => self.pure_exprs(&s.results, pure),
+            Stmt::Block(s) => self.pure_stmts(&s.list, pure),
+            Stmt::If(s) => {
+                self.pure_opt_stmt(&s.init, pure);
+                self.pure_expr(&s.cond, pure);
+                self.pure_stmts(&s.body.list, pure);
+                self.pure_opt_stmt(&s.els, pure);
+            }
+            Stmt::Case(s) => {
+                if let Some(l) = &s.list {
+                    self.pure_exprs(l, pure);
+                }
+                self.pure_stmts(&s.body, pure);
+            }
+            Stmt::Switch(s) => {
+                self.pure_opt_stmt(&s.init, pure);
+                if let Some(tag) = &s.tag {
+                    self.pure_expr(tag, pure);
+                }
+                self.pure_stmts(&s.body.list, pure);
+            }
+            Stmt::TypeSwitch(s) => {
+                self.pure_opt_stmt(&s.init, pure);
+                self.pure_stmt(&s.assign, pure);
+                self.pure_stmts(&s.body.list, pure);
+            }
+            Stmt::Select(s) => self.impure(s.select, "select statement".to_owned()),
+            Stmt::Comm(s) => self.pure_stmts(&s.body, pure),
+            Stmt::For(s) => {
+                self.pure_opt_stmt(&s.init, pure);
+                if let Some(cond) = &s.cond {
+                    self.pure_expr(cond, pure);
+                }
+                self.pure_opt_stmt(&s.post, pure);
+                self.pure_stmts(&s.body.list, pure);
+            }
+            Stmt::Range(s) => {
+                let over_chan = self.result.types.get(&s.expr.id()).is_some_and(|tv| {
+                    let t = typ::underlying_type(tv.typ, self.tc_objs);
+                    self.otype(t).try_as_chan().is_some()
+                });
+                if over_chan {
+                    self.impure(s.expr.pos(objs), "range over a channel".to_owned());
+                }
+                if s.token == Token::ASSIGN {
+                    for e in s.key.iter().chain(s.val.iter()) {
+                        self.pure_target(e);
+                    }
+                }
+                self.pure_expr(&s.expr, pure);
+                self.pure_stmts(&s.body.list, pure);
+            }
+            Stmt::Bad(_) | Stmt::Empty(_) | Stmt::Branch(_) => {}
+        }
+    }
+
+    /// Reports an assigned expression that is not a local variable, or a
+    /// field or element of an array or struct held by one.
+    fn pure_target(&self, expr: &Expr) {
+        let objs = &*self.ast_objs;
+        let expr = Checker::<S>::unparen(expr);
+        let pos = expr.pos(objs);
+        match expr {
+            Expr::Ident(ikey) => {
+                if let Some(okey) = self.result.uses.get(ikey) {
+                    if self.is_pkg_var(*okey) {
+                        let name = self.lobj(*okey).name();
+                        self.impure(pos, format!("assignment to package variable {}", name));
+                    }
+                }
+            }
+            Expr::Selector(sel) => match self.result.selections.get(&expr.id()) {
+                Some(s) if !*s.indirect() => self.pure_target(&sel.expr),
+                Some(_) => self.impure(pos, "write through a pointer".to_owned()),
+                None => {
+                    let name = &self.ast_ident(sel.sel).name;
+                    self.impure(pos, format!("assignment to package variable {}", name));
+                }
+            },
+            Expr::Index(ind) => {
+                let base = self.result.types.get(&ind.expr.id()).map(|tv| {
+                    let t = typ::underlying_type(tv.typ, self.tc_objs);
+                    self.otype(t)
+                });
+                match base {
+                    Some(t) if t.try_as_array().is_some() => self.pure_target(&ind.expr),
+                    Some(t) if t.try_as_map().is_some() => {
+                        self.impure(pos, "write to a map".to_owned())
+                    }
+                    _ => self.impure(pos, "write through a slice or pointer".to_owned()),
+                }
+            }
+            Expr::Star(_) => self.impure(pos, "write through a pointer".to_owned()),
+            _ => {}
+        }
+    }
+
+    fn is_pkg_var(&self, okey: ObjKey) -> bool {
+        let lobj = self.lobj(okey);
+        match lobj.entity_type() {
+            EntityType::Var(_) => {
+                lobj.parent().is_some()
+                    && lobj.pkg().map(|p| *self.package(p).scope()) == lobj.parent()
+            }
+            _ => false,
+        }
+    }
+
+    fn pure_exprs(&self, exprs: &[Expr], pure: &HashSet<ObjKey>) {
+        for e in exprs.iter() {
+            self.pure_expr(e, pure);
+        }
+    }
+
+    fn pure_expr(&self, expr: &Expr, pure: &HashSet<ObjKey>) {
+        match expr {
+            Expr::FuncLit(flit) => self.pure_stmts(&flit.body.list, pure),
+            Expr::CompositeLit(clit) => self.pure_exprs(&clit.elts, pure),
+            Expr::Paren(e) => self.pure_expr(&e.expr, pure),
+            Expr::Selector(e) => self.pure_expr(&e.expr, pure),
+            Expr::Index(e) => {
+                self.pure_expr(&e.expr, pure);
+                self.pure_expr(&e.index, pure);
+            }
+            Expr::Slice(e) => {
+                self.pure_expr(&e.expr, pure);
+                for x in [&e.low, &e.high, &e.max].into_iter().flatten() {
+                    self.pure_expr(x, pure);
+                }
+            }
+            Expr::TypeAssert(e) => self.pure_expr(&e.expr, pure),
+            Expr::Call(e) => {
+                self.pure_call(e, pure);
+                self.pure_expr(&e.func, pure);
+                self.pure_exprs(&e.args, pure);
+            }
+            Expr::Star(e) => self.pure_expr(&e.expr, pure),
+            Expr::Unary(e) => {
+                if e.op == Token::ARROW {
+                    self.impure(e.op_pos, "channel receive".to_owned());
+                }
+                self.pure_expr(&e.expr, pure);
+            }
+            Expr::Binary(e) => {
+                self.pure_expr(&e.expr_a, pure);
+                self.pure_expr(&e.expr_b, pure);
+            }
+            Expr::KeyValue(e) => {
+                self.pure_expr(&e.key, pure);
+                self.pure_expr(&e.val, pure);
+            }
+            _ => {}
+        }
+    }
+
+    /// Reports a call that is not a conversion, a call of a function literal
+    /// or a side effect free builtin, or a call of a `//gos:pure` function.
+    fn pure_call(&self, call: &ast::CallExpr, pure: &HashSet<ObjKey>) {
+        let func = Checker::<S>::unparen(&call.func);
+        let pos = func.pos(self.ast_objs);
+        let is_type = self
+            .result
+            .types
+            .get(&func.id())
+            .map_or(false, |tv| tv.mode == OperandMode::TypeExpr);
+        if is_type {
+            return;
+        }
+        let ident = match func {
+            Expr::Ident(ikey) => *ikey,
+            Expr::Selector(sel) => match self.result.selections.get(&func.id()).map(|s| s.kind()) {
+                Some(SelectionKind::FieldVal) => {
+                    return self.impure(pos, "call of a function value".to_owned())
+                }
+                Some(_) => return self.impure(pos, "method call".to_owned()),
+                None => sel.sel,
+            },
+            Expr::FuncLit(_) => return,
+            _ => return self.impure(pos, "call of a function value".to_owned()),
+        };
+        let okey = match self.result.uses.get(&ident) {
+            Some(okey) => *okey,
+            None => return,
+        };
+        let lobj = self.lobj(okey);
+        match lobj.entity_type() {
+            EntityType::TypeName => {}
+            EntityType::Builtin(b) => match b {
+                Builtin::Cap
+                | Builtin::Complex
+                | Builtin::Imag
+                | Builtin::Len
+                | Builtin::Make
+                | Builtin::New
+                | Builtin::Real
+                | Builtin::Alignof
+                | Builtin::Offsetof
+                | Builtin::Sizeof => {}
+                _ => self.impure(pos, format!("call of builtin {}", lobj.name())),
+            },
+            EntityType::Func(_) if !pure.contains(&okey) => self.impure(
+                pos,
+                format!("call of {}, which is not //gos:pure,", lobj.name()),
+            ),
+            EntityType::Func(_) => {}
+            _ => self.impure(pos, "call of a function value".to_owned()),
+        }
+    }
+}
@@ -275,7 +275,7 @@ impl<'a, S: SourceRead> Checker<'a, S> {
                                             self.declare(file_scope, None, elem, 0);
                                         }
                                         // add position to set of dot-import positions for this file
-                                        // (this is only needed for "imported but not used" errors)
+                                        // (this is only needed for "imported and not used" errors)
                                         fctx.add_unused_dot_import(&file_scope, &imp, spec_pos);
                                     } else {
                                         // declare imported package object in file scope
@@ -551,18 +551,19 @@ impl<'a, S: SourceRead> Checker<'a, S> {
                 match obj_val.entity_type() {
                     EntityType::PkgName(pkey, used) => {
                         if !*used {
-                            let (path, base) = self.pkg_path_and_name(*pkey);
-                            if obj_val.name() == base {
-                                self.soft_error(
-                                    obj_val.pos(),
-                                    format!("{} imported but not used", path),
-                                );
-                            } else {
-                                self.soft_error(
-                                    obj_val.pos(),
-                                    format!("{} imported but not used as {}", path, base),
-                                );
-                            }
+                            let pkg_val = self.package(*pkey);
+                            let path = pkg_val.path();
+                            let msg = match pkg_val.name() {
+                                Some(n) if n != obj_val.name() => {
+                                    format!(
+                                        "{:?} imported as {} and not used",
+                                        path,
+                                        obj_val.name()
+                                    )
+                                }
+                                _ => format!("{:?} imported and not used", path),
+                            };
+                            self.soft_error(obj_val.pos(), msg);
                         }
                     }
                     _ => {}
@@ -574,7 +575,7 @@ impl<'a, S: SourceRead> Checker<'a, S> {
             for (pkey, pos) in imports.iter() {
                 self.soft_error(
                     *pos,
-                    format!("{} imported but not used", self.package(*pkey).path()),
+                    format!("{:?} imported and not used", self.package(*pkey).path()),
                 );
             }
         }
@@ -748,7 +749,7 @@ impl<'a, S: SourceRead> Checker<'a, S> {
                 name = &name[0..name.len() - 1];
             }
             if let Some(i) = name.rfind('/') {
-                name = &name[i..name.len()]
+                name = &name[i + 1..]
             }
             let pkg = self.tc_objs.new_package(path.clone());
             self.package_mut(pkg).mark_fake_with_name(name.to_owned());
@@ -758,18 +759,6 @@ impl<'a, S: SourceRead> Checker<'a, S> {
         imported.unwrap()
     }
 
-    // pkg_name returns the package's path and name (last element) of a PkgName obj.
-    fn pkg_path_and_name(&self, pkey: PackageKey) -> (&str, &str) {
-        let pkg_val = self.package(pkey);
-        let path = pkg_val.path();
-        if let Some((i, _)) = path.match_indices('/').next() {
-            if i > 0 {
-                return (&path, &path[i + 1..]);
-            }
-        }
-        (&path, &path)
-    }
-
     /// dir makes a good-faith attempt to return the directory
     /// portion of path. If path is empty, the result is ".".
     fn file_dir(&self, file: &ast::File) -> String {
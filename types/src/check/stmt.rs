@@ -69,7 +69,7 @@ impl GoVal {
                     _ => GoVal::Invalid,
                 },
             },
-            Value::Float(_) => match v.num_as_f64() {
+            Value::Rat(_) | Value::Float(_) => match v.num_as_f64() {
                 (f, true) => GoVal::Float64(f),
                 _ => GoVal::Invalid,
             },
@@ -181,7 +181,7 @@ impl<'a, S: SourceRead> Checker<'a, S> {
         unused.sort_by(|a, b| a.pos().cmp(&b.pos()));
 
         for lo in unused.iter() {
-            self.soft_error(lo.pos(), format!("{} declared but not used", lo.name()));
+            self.soft_error(lo.pos(), format!("declared and not used: {}", lo.name()));
         }
         for skey in sval.children().iter() {
             // Don't go inside function literal scopes a second time;
@@ -727,7 +727,7 @@ impl<'a, S: SourceRead> Checker<'a, S> {
                                     ident.pos,
                                     "no new variable on left side of :=",
                                 );
-                                None // avoid declared but not used error below
+                                None // avoid declared and not used error below
                             } else {
                                 self.result.record_def(ikey, None);
                                 Some(ikey)
@@ -803,7 +803,7 @@ impl<'a, S: SourceRead> Checker<'a, S> {
                             });
                         self.declare(self.octx.scope.unwrap(), None, okey, scope_pos);
                         self.result.record_implicit(s, okey);
-                        // For the "declared but not used" error, all lhs variables act as
+                        // For the "declared and not used" error, all lhs variables act as
                         // one; i.e., if any one of them is 'used', all of them are 'used'.
                         // Collect them for later analysis.
                         lhs_vars.push(okey);
@@ -823,7 +823,7 @@ impl<'a, S: SourceRead> Checker<'a, S> {
                     if !used {
                         let ident = self.ast_ident(lhs.unwrap());
                         let (pos, name) = (ident.pos, &ident.name);
-                        self.soft_error(pos, format!("{} declared but not used", name));
+                        self.soft_error(pos, format!("declared and not used: {}", name));
                     }
                 }
 
@@ -902,7 +902,7 @@ impl<'a, S: SourceRead> Checker<'a, S> {
                             );
                             // Don't call useLHS here because we want to use the lhs in
                             // this erroneous statement so that we don't get errors about
-                            // these lhs variables being declared but not used.
+                            // these lhs variables being declared and not used.
                             let lhs = assign.lhs.clone();
                             self.use_exprs(&lhs, fctx); // avoid follow-up errors
                         }
@@ -73,10 +73,10 @@ impl<'a, S: SourceRead> Checker<'a, S> {
             // (This code is only needed for dot-imports. Without them,
             // we only have to mark variables, see Var case below).
             if pkg.is_some() && pkg != Some(self.pkg) {
-                fctx.unused_dot_imports
-                    .get_mut(&skey)
-                    .unwrap()
-                    .remove(&pkg.unwrap());
+                // not in a file scope if found in the scope of an outer package
+                if let Some(imports) = fctx.unused_dot_imports.get_mut(&skey) {
+                    imports.remove(&pkg.unwrap());
+                }
             }
 
             let lobj = self.lobj(okey);
@@ -800,7 +800,8 @@ impl<'a, S: SourceRead> Checker<'a, S> {
         pos: Pos,
     ) {
         if tag.is_some() && tags.is_none() {
-            *tags = Some(vec![]);
+            // the fields before the first tagged one have no tags
+            *tags = Some(vec![None; fields.len()]);
         }
         if tags.is_some() {
             tags.as_mut().unwrap().push(tag);
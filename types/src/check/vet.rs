@@ -0,0 +1,408 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+//
+//
+// The shadowing check is adapted from the shadow analyzer of go vet
+// with license as follows:
+// Copyright 2013 The Go Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+use crate::SourceRead;
+
+use super::super::obj::EntityType;
+use super::super::objects::{ObjKey, ScopeKey};
+use super::check::Checker;
+use goscript_parser::ast::{self, BlockStmt, Expr, Node, Stmt};
+use goscript_parser::objects::{FuncTypeKey, IdentKey, Objects as AstObjects};
+use goscript_parser::position::Pos;
+use goscript_parser::token::Token;
+use std::collections::{HashMap, HashSet};
+
+/// An assignment to a variable
+struct Write {
+    ident: IdentKey,
+    /// end of the assigning statement
+    end: Pos,
+    /// the statement list and the index in it, if the assignment is a
+    /// statement of a list
+    slot: Option<(usize, usize)>,
+}
+
+/// Collects the assignments, loops and named results of function bodies
+struct BodyWalker<'a> {
+    objs: &'a AstObjects,
+    writes: Vec<Write>,
+    loops: Vec<(Pos, Pos)>,
+    /// bodies of functions with labels, gotos make the order of their
+    /// statements meaningless
+    labeled: Vec<(Pos, Pos)>,
+    results: HashSet<IdentKey>,
+    body: (Pos, Pos),
+    list_count: usize,
+}
+
+impl<'a> BodyWalker<'a> {
+    fn new(objs: &'a AstObjects) -> BodyWalker<'a> {
+        BodyWalker {
+            objs: objs,
+            writes: vec![],
+            loops: vec![],
+            labeled: vec![],
+            results: HashSet::new(),
+            body: (0, 0),
+            list_count: 0,
+        }
+    }
+
+    fn func(&mut self, typ: FuncTypeKey, body: &BlockStmt) {
+        if let Some(results) = &self.objs.ftypes[typ].results {
+            for f in results.list.iter() {
+                self.results.extend(self.objs.fields[*f].names.iter());
+            }
+        }
+        let outer = self.body;
+        self.body = (body.l_brace, body.r_brace);
+        self.list(&body.list);
+        self.body = outer;
+    }
+
+    fn list(&mut self, list: &[Stmt]) {
+        let id = self.list_count;
+        self.list_count += 1;
+        for (i, s) in list.iter().enumerate() {
+            self.stmt(s, Some((id, i)));
+        }
+    }
+
+    fn opt_stmt(&mut self, stmt: &Option<Stmt>) {
+        if let Some(s) = stmt {
+            self.stmt(s, None);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt, slot: Option<(usize, usize)>) {
+        let objs = self.objs;
+        match stmt {
+            Stmt::Decl(d) => {
+                if let ast::Decl::Gen(gdecl) = d.as_ref() {
+                    for skey in gdecl.specs.iter() {
+                        if let ast::Spec::Value(vs) = &objs.specs[*skey] {
+                            if !vs.values.is_empty() {
+                                let end = stmt.end(objs);
+                                for ikey in vs.names.iter() {
+                                    self.write(*ikey, end, slot);
+                                }
+                            }
+                            self.exprs(&vs.values);
+                        }
+                    }
+                }
+            }
+            Stmt::Labeled(lkey) => {
+                self.labeled.push(self.body);
+                self.stmt(&objs.l_stmts[*lkey].stmt, slot);
+            }
+            Stmt::Expr(e) => self.expr(e),
+            Stmt::Send(s) => {
+                self.expr(&s.chan);
+                self.expr(&s.val);
+            }
+            Stmt::IncDec(s) => self.expr(&s.expr),
+            Stmt::Assign(akey) => {
+                let astmt = &objs.a_stmts[*akey];
+                if astmt.token == Token::ASSIGN || astmt.token == Token::DEFINE {
+                    let end = stmt.end(objs);
+                    for e in astmt.lhs.iter() {
+                        if let Expr::Ident(ikey) = e {
+                            self.write(*ikey, end, slot);
+                        }
+                    }
+                }
+                self.exprs(&astmt.lhs);
+                self.exprs(&astmt.rhs);
+            }
+            Stmt::Go(s) => self.expr(&s.call),
+            Stmt::Defer(s) => self.expr(&s.call),
+            Stmt::Return(s) => self.exprs(&s.results),
+            Stmt::Block(s) => self.list(&s.list),
+            Stmt::If(s) => {
+                self.opt_stmt(&s.init);
+                self.expr(&s.cond);
+                self.list(&s.body.list);
+                self.opt_stmt(&s.els);
+            }
+            Stmt::Case(s) => {
+                if let Some(l) = &s.list {
+                    self.exprs(l);
+                }
+                self.list(&s.body);
+            }
+            Stmt::Switch(s) => {
+                self.opt_stmt(&s.init);
+                if let Some(tag) = &s.tag {
+                    self.expr(tag);
+                }
+                self.list(&s.body.list);
+            }
+            Stmt::TypeSwitch(s) => {
+                self.opt_stmt(&s.init);
+                self.stmt(&s.assign, None);
+                self.list(&s.body.list);
+            }
+            Stmt::Comm(s) => {
+                self.opt_stmt(&s.comm);
+                self.list(&s.body);
+            }
+            Stmt::Select(s) => self.list(&s.body.list),
+            Stmt::For(s) => {
+                self.loops.push((s.for_pos, s.body.r_brace));
+                self.opt_stmt(&s.init);
+                if let Some(cond) = &s.cond {
+                    self.expr(cond);
+                }
+                self.opt_stmt(&s.post);
+                self.list(&s.body.list);
+            }
+            Stmt::Range(s) => {
+                self.loops.push((s.for_pos, s.body.r_brace));
+                self.expr(&s.expr);
+                self.list(&s.body.list);
+            }
+            Stmt::Bad(_) | Stmt::Empty(_) | Stmt::Branch(_) => {}
+        }
+    }
+
+    fn write(&mut self, ident: IdentKey, end: Pos, slot: Option<(usize, usize)>) {
+        self.writes.push(Write {
+            ident: ident,
+            end: end,
+            slot: slot,
+        });
+    }
+
+    fn exprs(&mut self, exprs: &[Expr]) {
+        for e in exprs.iter() {
+            self.expr(e);
+        }
+    }
+
+    /// Only looks for function literals in expressions
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::FuncLit(flit) => self.func(flit.typ, &flit.body),
+            Expr::CompositeLit(clit) => self.exprs(&clit.elts),
+            Expr::Paren(e) => self.expr(&e.expr),
+            Expr::Selector(e) => self.expr(&e.expr),
+            Expr::Index(e) => {
+                self.expr(&e.expr);
+                self.expr(&e.index);
+            }
+            Expr::Slice(e) => {
+                self.expr(&e.expr);
+                for x in [&e.low, &e.high, &e.max].iter() {
+                    if let Some(x) = x {
+                        self.expr(x);
+                    }
+                }
+            }
+            Expr::TypeAssert(e) => self.expr(&e.expr),
+            Expr::Call(e) => {
+                self.expr(&e.func);
+                self.exprs(&e.args);
+            }
+            Expr::Star(e) => self.expr(&e.expr),
+            Expr::Unary(e) => self.expr(&e.expr),
+            Expr::Binary(e) => {
+                self.expr(&e.expr_a);
+                self.expr(&e.expr_b);
+            }
+            Expr::KeyValue(e) => {
+                self.expr(&e.key);
+                self.expr(&e.val);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl<'a, S: SourceRead> Checker<'a, S> {
+    /// Reports local error variables that shadow another error variable
+    /// which is still used afterwards, and values assigned to local error
+    /// variables that are never checked, as warnings.
+    pub fn vet_error_vars(&self, files: &Vec<ast::File>) {
+        let univ = self.tc_objs.universe().scope();
+        let err_type = match self.tc_objs.scopes[*univ].lookup("error") {
+            Some(okey) => self.lobj(*okey).typ(),
+            None => return,
+        };
+        let pkg_scope = *self.package(self.pkg).scope();
+        let is_err_var = |okey: ObjKey| {
+            let lobj = self.lobj(okey);
+            match lobj.entity_type() {
+                EntityType::Var(prop) => {
+                    !prop.is_field
+                        && lobj.typ() == err_type
+                        && lobj.parent().map_or(false, |s| s != pkg_scope)
+                }
+                _ => false,
+            }
+        };
+
+        let mut mentions: HashMap<ObjKey, Vec<IdentKey>> = HashMap::new();
+        for (ikey, okey) in self.result.uses.iter() {
+            if is_err_var(*okey) {
+                mentions.entry(*okey).or_default().push(*ikey);
+            }
+        }
+
+        let mut warnings = vec![];
+        self.vet_shadowing(&is_err_var, &mentions, &mut warnings);
+        self.vet_unused_values(files, &is_err_var, &mentions, &mut warnings);
+        warnings.sort_by_key(|(pos, _)| *pos);
+        for (pos, msg) in warnings.into_iter() {
+            self.warning(pos, msg);
+        }
+    }
+
+    /// Checks for the `if err := f(); err != nil` pitfall, where the outer
+    /// err is checked later but never sees the result of f.
+    fn vet_shadowing(
+        &self,
+        is_err_var: &dyn Fn(ObjKey) -> bool,
+        mentions: &HashMap<ObjKey, Vec<IdentKey>>,
+        warnings: &mut Vec<(Pos, String)>,
+    ) {
+        let pkg_scope = *self.package(self.pkg).scope();
+        for (ikey, okey) in self.result.defs.iter() {
+            let okey = match okey {
+                Some(o) if is_err_var(*o) => *o,
+                _ => continue,
+            };
+            let lobj = self.lobj(okey);
+            let pos = self.ast_ident(*ikey).pos;
+            let mut skey = *self.tc_objs.scopes[lobj.parent().unwrap()].parent();
+            while let Some(s) = skey {
+                if s == pkg_scope {
+                    break;
+                }
+                let scope = &self.tc_objs.scopes[s];
+                if let Some(outer) = scope.lookup(lobj.name()) {
+                    let used_after = mentions
+                        .get(outer)
+                        .map_or(false, |m| m.iter().any(|i| self.ast_ident(*i).pos > pos));
+                    if is_err_var(*outer) && self.lobj(*outer).pos() < pos && used_after {
+                        let line = self.fset.position(self.lobj(*outer).pos()).unwrap().line;
+                        let msg = format!(
+                            "declaration of \"{}\" shadows declaration at line {}",
+                            lobj.name(),
+                            line
+                        );
+                        warnings.push((pos, msg));
+                    }
+                    break;
+                }
+                skey = *scope.parent();
+            }
+        }
+    }
+
+    /// Checks for values assigned to error variables that are overwritten
+    /// or go out of scope before being read.
+    fn vet_unused_values(
+        &self,
+        files: &Vec<ast::File>,
+        is_err_var: &dyn Fn(ObjKey) -> bool,
+        mentions: &HashMap<ObjKey, Vec<IdentKey>>,
+        warnings: &mut Vec<(Pos, String)>,
+    ) {
+        let mut walker = BodyWalker::new(&*self.ast_objs);
+        for f in files.iter() {
+            for d in f.decls.iter() {
+                if let ast::Decl::Func(fkey) = d {
+                    let fdecl = &self.ast_objs.fdecls[*fkey];
+                    if let Some(body) = &fdecl.body {
+                        walker.func(fdecl.typ, body);
+                    }
+                }
+            }
+        }
+
+        let obj_of = |ikey: &IdentKey| match self.result.defs.get(ikey) {
+            Some(okey) => *okey,
+            None => self.result.uses.get(ikey).copied(),
+        };
+        let results: HashSet<ObjKey> = walker.results.iter().filter_map(obj_of).collect();
+        let written: HashSet<IdentKey> = walker.writes.iter().map(|w| w.ident).collect();
+        let within =
+            |ranges: &[(Pos, Pos)], pos: Pos| ranges.iter().any(|r| r.0 < pos && pos < r.1);
+
+        for (i, w) in walker.writes.iter().enumerate() {
+            let okey = match obj_of(&w.ident) {
+                Some(o) if is_err_var(o) && !results.contains(&o) => o,
+                _ => continue,
+            };
+            let pos = self.ast_ident(w.ident).pos;
+            if within(&walker.labeled, pos) || self.captured(okey, mentions) {
+                continue;
+            }
+            let reads: Vec<Pos> = mentions.get(&okey).map_or(vec![], |m| {
+                m.iter()
+                    .filter(|i| !written.contains(i))
+                    .map(|i| self.ast_ident(*i).pos)
+                    .collect()
+            });
+            // the next assignment in the same statement list
+            let next = w.slot.and_then(|(list, index)| {
+                walker.writes[i + 1..].iter().find(|x| {
+                    x.slot.map_or(false, |(l, j)| l == list && j > index)
+                        && obj_of(&x.ident) == Some(okey)
+                })
+            });
+            let unused = match next {
+                Some(n) => !reads.iter().any(|r| w.end < *r && *r < n.end),
+                None => {
+                    !reads.iter().any(|r| w.end < *r)
+                        && !walker.loops.iter().any(|l| {
+                            l.0 < pos && pos < l.1 && reads.iter().any(|r| within(&[*l], *r))
+                        })
+                }
+            };
+            if unused {
+                let msg = format!("this value of {} is never used", self.lobj(okey).name());
+                warnings.push((pos, msg));
+            }
+        }
+    }
+
+    /// Returns true if the variable is mentioned in a function literal
+    /// other than the one it is declared in.
+    fn captured(&self, okey: ObjKey, mentions: &HashMap<ObjKey, Vec<IdentKey>>) -> bool {
+        let scopes = &self.tc_objs.scopes;
+        let mut func_scope: ScopeKey = self.lobj(okey).parent().unwrap();
+        while !scopes[func_scope].is_func() {
+            match scopes[func_scope].parent() {
+                Some(s) => func_scope = *s,
+                None => return false,
+            }
+        }
+        mentions.get(&okey).map_or(false, |m| {
+            m.iter().any(|ikey| {
+                let pos = self.ast_ident(*ikey).pos;
+                let mut skey = func_scope;
+                while let Some(child) = scopes[skey]
+                    .children()
+                    .iter()
+                    .find(|c| scopes[**c].contains(pos))
+                {
+                    if scopes[*child].is_func() {
+                        return true;
+                    }
+                    skey = *child;
+                }
+                false
+            })
+        })
+    }
+}
@@ -16,7 +16,7 @@ use num_rational::BigRational;
 use num_traits::cast::FromPrimitive;
 use num_traits::cast::ToPrimitive;
 use num_traits::sign::Signed;
-use num_traits::Num;
+use num_traits::{Num, Zero};
 use ordered_float;
 use std::borrow::Borrow;
 use std::borrow::Cow;
@@ -33,10 +33,9 @@ type F64 = ordered_float::OrderedFloat<f64>;
 /// values produce unknown values unless specified
 /// otherwise.
 ///
-/// Because BigFloat library is not available at the moment(2020/5)
-/// float numbers arbitrary precision is not supported for now
-/// float numbers is simply represented as f64
-/// todo: This is against the Go specs.
+/// Float literals are exact rationals, as are the results of operations on
+/// them, so 0.1+0.2 == 0.3 and 1e400/1e300 == 1e100. Float values only come
+/// from typed constants, rounded to float32 or float64.
 
 /// All the values involved in the evaluation
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
@@ -71,11 +70,14 @@ impl fmt::Display for Value {
             }
             Value::Rat(r) => {
                 //f.write_str("rat: ")?;
-                r.fmt(f)
+                match rat_to_f64(r) {
+                    Some(x) => fmt_float(x, f),
+                    None => fmt_big_float(&r.to_integer(), f),
+                }
             }
             Value::Float(s) => {
                 //f.write_str("float: ")?;
-                s.fmt(f)
+                fmt_float(**s, f)
             }
             Value::Complex(r, i) => {
                 //f.write_str("complex: ")?;
@@ -113,8 +115,8 @@ impl Value {
             Token::IMAG(imlit) => {
                 let s = imlit.as_str();
                 let v = float_from_literal(&s[..(s.len() - 1)]);
-                if let Value::Float(_) = &v {
-                    Value::Complex(Box::new(Value::with_f64(0.0)), Box::new(v))
+                if let Value::Rat(_) | Value::Float(_) = &v {
+                    Value::Complex(Box::new(Value::with_i64(0)), Box::new(v))
                 } else {
                     Value::Unknown
                 }
@@ -146,20 +148,25 @@ impl Value {
 
         let float_representable =
             |val: &Value, btype: BasicType, rounded: Option<&mut Value>| -> bool {
+                if btype == BasicType::UntypedFloat {
+                    // of any magnitude, and kept exact
+                    return matches!(val, Value::Int(_) | Value::Rat(_) | Value::Float(_));
+                }
                 match val.to_float() {
-                    Value::Float(f) => match btype {
-                        BasicType::Float64 => true,
-                        BasicType::Float32 => {
-                            let f32_ = *f as f32;
-                            let ok = !f32_.is_infinite();
-                            if let Some(r) = rounded {
-                                *r = Value::Float(((*f as f32) as f64).into());
+                    Value::Float(f) => {
+                        let (f, ok) = match btype {
+                            BasicType::Float64 => (*f, f.is_finite()),
+                            BasicType::Float32 => {
+                                let f32_ = *f as f32;
+                                (f32_ as f64, f32_.is_finite())
                             }
-                            ok
+                            _ => unreachable!(),
+                        };
+                        if let (Some(r), true) = (rounded, ok) {
+                            *r = Value::Float(f.into());
                         }
-                        BasicType::UntypedFloat => true,
-                        _ => unreachable!(),
-                    },
+                        ok
+                    }
                     _ => false,
                 }
             };
@@ -197,23 +204,12 @@ impl Value {
                 };
                 match self.to_complex() {
                     Value::Complex(r, i) => {
-                        let (rrounded, irounded): (Option<&mut Value>, Option<&mut Value>) =
-                            match rounded {
-                                Some(val) => {
-                                    *val = Value::Complex(
-                                        Box::new(Value::with_f64(0.0)),
-                                        Box::new(Value::with_f64(0.0)),
-                                    );
-                                    if let Value::Complex(r, i) = &mut *val {
-                                        (Some(r.as_mut()), Some(i.as_mut()))
-                                    } else {
-                                        unreachable!()
-                                    }
-                                }
-                                None => (None, None),
-                            };
-                        let rok = float_representable(&r, ty, rrounded);
-                        let iok = float_representable(&i, ty, irounded);
+                        let (mut rrounded, mut irounded) = (*r.clone(), *i.clone());
+                        let rok = float_representable(&r, ty, Some(&mut rrounded));
+                        let iok = float_representable(&i, ty, Some(&mut irounded));
+                        if let (Some(val), true) = (rounded, rok && iok) {
+                            *val = Value::Complex(Box::new(rrounded), Box::new(irounded));
+                        }
                         rok && iok
                     }
                     _ => false,
@@ -268,7 +264,7 @@ impl Value {
 
     pub fn to_float(&self) -> Value {
         let v = match self {
-            Value::Int(i) => i.to_f64(),
+            Value::Int(i) => int_to_f64(i),
             Value::Rat(r) => rat_to_f64(r),
             Value::Float(f) => Some(**f),
             Value::Complex(r, i) => {
@@ -292,7 +288,7 @@ impl Value {
     pub fn to_complex(&self) -> Value {
         match self {
             Value::Int(_) | Value::Rat(_) | Value::Float(_) => {
-                Value::Complex(Box::new(self.clone()), Box::new(Value::with_f64(0.0)))
+                Value::Complex(Box::new(self.clone()), Box::new(Value::with_i64(0)))
             }
             Value::Complex(_, _) => self.clone(),
             _ => Value::Unknown,
@@ -305,7 +301,7 @@ impl Value {
     pub fn make_imag(&self) -> Value {
         match self {
             Value::Int(_) | Value::Float(_) | Value::Rat(_) => {
-                Value::Complex(Box::new(Value::with_f64(0.0)), Box::new(self.clone()))
+                Value::Complex(Box::new(Value::with_i64(0)), Box::new(self.clone()))
             }
             Value::Unknown => Value::Unknown,
             _ => panic!("{} not Int or Float", self),
@@ -326,7 +322,7 @@ impl Value {
     /// If x is Unknown, the result is Unknown.
     pub fn imag(&self) -> Value {
         match self {
-            Value::Int(_) | Value::Float(_) | Value::Rat(_) => Value::with_f64(0.0),
+            Value::Int(_) | Value::Float(_) | Value::Rat(_) => Value::with_i64(0),
             Value::Complex(_, i) => *i.clone(),
             Value::Unknown => Value::Unknown,
             _ => panic!("{} not numeric", self),
@@ -734,14 +730,14 @@ impl Value {
                     Cow::Owned(Value::Rat(BigRational::new(iv.clone(), 1.into()))),
                     y,
                 ),
-                Value::Float(_) => match iv.to_f64() {
+                Value::Float(_) => match int_to_f64(iv) {
                     Some(f) => (Cow::Owned(Value::Float(f.into())), y),
                     None => (Cow::Owned(Value::Unknown), Cow::Owned(Value::Unknown)),
                 },
                 Value::Complex(_, _) => (
                     Cow::Owned(Value::Complex(
                         Box::new(x.into_owned()),
-                        Box::new(Value::with_f64(0.0)),
+                        Box::new(Value::with_i64(0)),
                     )),
                     y,
                 ),
@@ -757,7 +753,7 @@ impl Value {
                 Value::Complex(_, _) => (
                     Cow::Owned(Value::Complex(
                         Box::new(x.into_owned()),
-                        Box::new(Value::with_f64(0.0)),
+                        Box::new(Value::with_i64(0)),
                     )),
                     y,
                 ),
@@ -769,7 +765,7 @@ impl Value {
                 Value::Complex(_, _) => (
                     Cow::Owned(Value::Complex(
                         Box::new(x.into_owned()),
-                        Box::new(Value::with_f64(0.0)),
+                        Box::new(Value::with_i64(0)),
                     )),
                     y,
                 ),
@@ -805,13 +801,81 @@ pub fn int_from_literal(lit: &str) -> Value {
     }
 }
 
+/// The largest decimal exponent a float literal is kept exact with, beyond
+/// it the literal is simply parsed as a f64
+const MAX_EXACT_EXP: i64 = 10000;
+
 pub fn float_from_literal(lit: &str) -> Value {
-    match lit.parse::<f64>() {
-        Ok(f) => Value::with_f64(f),
-        Err(_) => Value::Unknown,
+    let (mantissa, exp) = match lit.find(|c| c == 'e' || c == 'E') {
+        Some(i) => (&lit[..i], lit[i + 1..].parse::<i64>().ok()),
+        None => (lit, Some(0)),
+    };
+    let (int_part, frac_part) = match mantissa.find('.') {
+        Some(i) => (&mantissa[..i], &mantissa[i + 1..]),
+        None => (mantissa, ""),
+    };
+    let digits = BigInt::from_str_radix(&format!("{}{}", int_part, frac_part), 10);
+    match (digits, exp.map(|e| e - frac_part.len() as i64)) {
+        (Ok(n), Some(e)) if e.abs() <= MAX_EXACT_EXP => {
+            let p = num_traits::pow(BigInt::from(10), e.unsigned_abs() as usize);
+            if e >= 0 {
+                Value::Rat(BigRational::from_integer(n * p))
+            } else {
+                Value::Rat(BigRational::new(n, p))
+            }
+        }
+        _ => match lit.parse::<f64>() {
+            Ok(f) => Value::with_f64(f),
+            Err(_) => Value::Unknown,
+        },
     }
 }
 
+/// Formats x like Go's %.6g, which is how constants are shown in errors
+fn fmt_float(x: f64, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    if x == 0.0 || !x.is_finite() {
+        return write!(f, "{}", x);
+    }
+    let trim = |m: String| -> String {
+        if m.contains('.') {
+            m.trim_end_matches('0').trim_end_matches('.').to_owned()
+        } else {
+            m
+        }
+    };
+    let sci = format!("{:.5e}", x);
+    let (m, e) = sci.split_at(sci.find('e').unwrap());
+    let e: i32 = e[1..].parse().unwrap();
+    if e < -4 || e >= 6 {
+        let sign = if e < 0 { '-' } else { '+' };
+        write!(f, "{}e{}{:02}", trim(m.to_owned()), sign, e.abs())
+    } else {
+        write!(f, "{}", trim(format!("{:.*}", (5 - e) as usize, x)))
+    }
+}
+
+/// Formats i, too large for a f64, like fmt_float
+fn fmt_big_float(i: &BigInt, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let digits = i.abs().to_string();
+    let mut exp = digits.len() - 1;
+    let mut m = (digits[..7].parse::<u64>().unwrap() + 5) / 10;
+    if m == 1000000 {
+        m /= 10;
+        exp += 1;
+    }
+    let m = m.to_string();
+    let frac = m[1..].trim_end_matches('0');
+    let sign = if i.is_negative() { "-" } else { "" };
+    match frac.is_empty() {
+        true => write!(f, "{}{}e+{:02}", sign, &m[..1], exp),
+        false => write!(f, "{}{}.{}e+{:02}", sign, &m[..1], frac, exp),
+    }
+}
+
+fn int_to_f64(i: &BigInt) -> Option<f64> {
+    rat_to_f64(&BigRational::from_integer(i.clone()))
+}
+
 fn shorten_with_ellipsis(s: String, max: usize) -> String {
     if s.len() <= max {
         s
@@ -823,10 +887,37 @@ fn shorten_with_ellipsis(s: String, max: usize) -> String {
     }
 }
 
+/// Returns the nearest f64 of r, None if it overflows
 fn rat_to_f64(r: &BigRational) -> Option<f64> {
-    match (r.numer().to_f64(), r.denom().to_f64()) {
-        (Some(n), Some(d)) => Some(n / d),
-        _ => None,
+    let (n, d) = (r.numer().abs(), r.denom());
+    if n.is_zero() {
+        return Some(0.0);
+    }
+    // scale the quotient to 65 or 66 bits, with the remainder as a sticky
+    // bit, so that converting it to f64 rounds only once
+    let shift = 65 - (n.bits() as i64 - d.bits() as i64);
+    let (n, d) = if shift >= 0 {
+        (n << shift as usize, d.clone())
+    } else {
+        (n, d << (-shift) as usize)
+    };
+    let (q, rem) = (&n / &d, &n % &d);
+    let mut q = q.to_u128()?;
+    if !rem.is_zero() {
+        q |= 1;
+    }
+    let mut x = q as f64;
+    let mut e = -shift;
+    while e.abs() > 1000 {
+        let step = e.signum() * 1000;
+        x *= 2f64.powi(step as i32);
+        e -= step;
+    }
+    x *= 2f64.powi(e as i32);
+    match x.is_finite() {
+        true if r.is_negative() => Some(-x),
+        true => Some(x),
+        false => None,
     }
 }
 
@@ -10,17 +10,18 @@
 // license that can be found in the LICENSE file.
 
 use super::check::check::{Checker, TypeInfo};
-use super::objects::{PackageKey, TCObjects};
+use super::objects::{ObjKey, PackageKey, TCObjects};
+use super::scope::Scope;
 use goscript_parser::ast;
 use goscript_parser::errors::ErrorList;
 use goscript_parser::objects::Objects as AstObjects;
 use goscript_parser::position;
 use goscript_parser::{FileSet, Parser};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::env;
 use std::fs;
 use std::io;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 
 pub struct TraceConfig {
     //print debug info in parser
@@ -128,6 +129,128 @@ impl<'a> SourceRead for FsReader<'a> {
     }
 }
 
+/// A SourceRead of files kept in memory, for the embedders without a file
+/// system, like the engine compiled to wasm. The root of the map is the
+/// working directory, and paths are normalized without looking at any file
+/// system, so `./a/../b.gos` is `b.gos`.
+#[derive(Clone, Debug, Default)]
+pub struct MapReader {
+    files: BTreeMap<PathBuf, String>,
+    base_dir: Option<String>,
+}
+
+impl MapReader {
+    /// `base_dir` is the directory of the map non-local imports are under,
+    /// like `std/`
+    pub fn new(base_dir: Option<&str>) -> MapReader {
+        MapReader {
+            files: BTreeMap::new(),
+            base_dir: base_dir.map(|x| x.to_owned()),
+        }
+    }
+
+    pub fn set_base_dir(&mut self, base_dir: Option<&str>) {
+        self.base_dir = base_dir.map(|x| x.to_owned());
+    }
+
+    /// Adds a file, or replaces the one at the same path
+    pub fn add_file(&mut self, path: &str, source: String) {
+        self.files.insert(normalize_path(Path::new(path)), source);
+    }
+
+    pub fn remove_file(&mut self, path: &str) -> Option<String> {
+        self.files.remove(&normalize_path(Path::new(path)))
+    }
+
+    /// The paths of the files, normalized and sorted
+    pub fn paths(&self) -> impl Iterator<Item = &Path> {
+        self.files.keys().map(|x| x.as_path())
+    }
+}
+
+/// Collects the files of a map of paths to sources, without a base
+/// directory
+impl<P: AsRef<str>> FromIterator<(P, String)> for MapReader {
+    fn from_iter<I: IntoIterator<Item = (P, String)>>(iter: I) -> MapReader {
+        let mut reader = MapReader::new(None);
+        reader.extend(iter);
+        reader
+    }
+}
+
+impl<P: AsRef<str>> Extend<(P, String)> for MapReader {
+    fn extend<I: IntoIterator<Item = (P, String)>>(&mut self, iter: I) {
+        for (path, source) in iter {
+            self.add_file(path.as_ref(), source);
+        }
+    }
+}
+
+impl SourceRead for MapReader {
+    fn working_dir(&self) -> io::Result<PathBuf> {
+        Ok(PathBuf::new())
+    }
+
+    fn base_dir(&self) -> Option<&str> {
+        self.base_dir.as_deref()
+    }
+
+    fn read_file(&self, path: &Path) -> io::Result<String> {
+        self.files
+            .get(&normalize_path(path))
+            .cloned()
+            .ok_or(io::Error::from(io::ErrorKind::NotFound))
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        let dir = normalize_path(path);
+        let files: Vec<PathBuf> = self
+            .files
+            .keys()
+            .filter(|p| p.parent() == Some(dir.as_path()))
+            .cloned()
+            .collect();
+        if files.is_empty() {
+            Err(io::Error::from(io::ErrorKind::NotFound))
+        } else {
+            Ok(files)
+        }
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        self.files.contains_key(&normalize_path(path))
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        let dir = normalize_path(path);
+        self.files.keys().any(|p| p != &dir && p.starts_with(&dir))
+    }
+
+    fn canonicalize_path(&self, path: &PathBuf) -> io::Result<PathBuf> {
+        if self.is_file(path) || self.is_dir(path) {
+            Ok(normalize_path(path))
+        } else {
+            Err(io::Error::from(io::ErrorKind::NotFound))
+        }
+    }
+}
+
+/// Removes the `.` components of a path, and the `..` ones together with
+/// the components they cancel, `..` at the start are dropped.
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut result = PathBuf::new();
+    for c in path.components() {
+        match c {
+            Component::Normal(name) => result.push(name),
+            Component::ParentDir => {
+                result.pop();
+            }
+            Component::CurDir | Component::RootDir | Component::Prefix(_) => {}
+        }
+    }
+    result
+}
+
 /// ImportKey identifies an imported package by import path and source directory
 /// (directory containing the file containing the import). In practice, the directory
 /// may always be the same, or may not matter. Given an (import path, directory), an
@@ -153,6 +276,7 @@ pub struct Importer<'a, S: SourceRead> {
     trace_config: &'a TraceConfig,
     reader: &'a S,
     fset: &'a mut FileSet,
+    // the imported packages by directory
     pkgs: &'a mut HashMap<String, PackageKey>,
     all_results: &'a mut HashMap<PackageKey, TypeInfo>,
     ast_objs: &'a mut AstObjects,
@@ -193,11 +317,14 @@ impl<'a, S: SourceRead> Importer<'a, S> {
         let pb = self.canonicalize_import(key)?;
         let path = pb.0.as_path();
         let import_path = pb.1;
-        match self.pkgs.get(&import_path) {
+        // packages are told apart by their directories, as different local
+        // import paths can refer to the same one, like "./a" and "../a"
+        let dir = path.to_string_lossy().to_string();
+        match self.pkgs.get(&dir) {
             Some(key) => Ok(*key),
             None => {
-                let pkg = self.tc_objs.new_package(import_path.clone());
-                self.pkgs.insert(import_path, pkg);
+                let pkg = self.tc_objs.new_package(import_path);
+                self.pkgs.insert(dir, pkg);
                 let files = self.parse_path(path)?;
                 Checker::new(
                     self.tc_objs,
@@ -215,36 +342,49 @@ impl<'a, S: SourceRead> Importer<'a, S> {
         }
     }
 
-    fn canonicalize_import(&mut self, key: &'a ImportKey) -> Result<(PathBuf, String), ()> {
-        let mut import_path = key.path.clone();
-        let path = if is_local(&key.path) {
-            let working_dir = self.reader.working_dir();
-            if working_dir.is_err() {
-                return self.error(format!("failed to get working dir for: {}", key.path));
-            }
-            let mut wd = working_dir.unwrap();
-            wd.push(&key.dir);
-            wd.push(&key.path);
-            if let Some(base) = &self.reader.base_dir() {
-                if let Ok(rel) = wd.as_path().strip_prefix(base) {
-                    import_path = rel.to_string_lossy().to_string()
-                }
-            }
-            wd
-        } else {
-            if let Some(base) = &self.reader.base_dir() {
-                let mut p = PathBuf::new();
-                p.push(base);
-                p.push(&key.path);
-                p
-            } else {
-                return self.error(format!("base dir required for path: {}", key.path));
-            }
+    /// Checks `files`, parsed with the objects of the importer, as a package
+    /// nested in `outer`, for a REPL, where each snippet sees what the ones
+    /// before it declared and imported. Unused imports are not errors as the
+    /// following snippets may use them.
+    pub fn check_nested(
+        &mut self,
+        path: &str,
+        files: Vec<ast::File>,
+        outer: Option<PackageKey>,
+    ) -> Result<PackageKey, ()> {
+        let pkg = match outer {
+            Some(o) => self.tc_objs.new_nested_package(path.to_owned(), o),
+            None => self.tc_objs.new_package(path.to_owned()),
         };
-        match self.reader.canonicalize_path(&path) {
-            Ok(p) => Ok((p, import_path)),
-            Err(e) => self.error(format!("{} {}", e, key.path)),
+        let mut checker = Checker::new(
+            self.tc_objs,
+            self.ast_objs,
+            self.fset,
+            self.errors,
+            self.pkgs,
+            self.all_results,
+            pkg,
+            self.trace_config,
+            self.reader,
+        );
+        checker.allow_unused_imports = true;
+        checker.check(files)?;
+        // the imports are in the file scopes, they are made visible to the
+        // nested packages too
+        let scope = *self.tc_objs.pkgs[pkg].scope();
+        let imported: Vec<ObjKey> = self.tc_objs.scopes[scope]
+            .children()
+            .iter()
+            .flat_map(|s| self.tc_objs.scopes[*s].elems().values().copied())
+            .collect();
+        for okey in imported {
+            Scope::insert(scope, okey, self.tc_objs);
         }
+        Ok(pkg)
+    }
+
+    fn canonicalize_import(&mut self, key: &'a ImportKey) -> Result<(PathBuf, String), ()> {
+        resolve_import(self.reader, key).or_else(|e| self.error(e))
     }
 
     fn parse_path(&mut self, path: &Path) -> Result<Vec<ast::File>, ()> {
@@ -268,15 +408,18 @@ impl<'a, S: SourceRead> Importer<'a, S> {
                             self.trace_config.trace_parser,
                         )
                         .parse_file();
-                        if afile.is_none() {
-                            // parse error, the details should be in the errorlist already.
-                            // give up
-                            return Err(());
-                        } else {
-                            afiles.push(afile.unwrap());
+                        // None means the package clause failed to parse, the details
+                        // should be in the errorlist already. Skip the file and go on
+                        // with the rest so that their errors get reported too.
+                        if let Some(f) = afile {
+                            afiles.push(f);
                         }
                     }
-                    Ok(afiles)
+                    if afiles.is_empty() {
+                        Err(())
+                    } else {
+                        Ok(afiles)
+                    }
                 }
             }
             Err(e) => self.error(format!(
@@ -294,7 +437,45 @@ impl<'a, S: SourceRead> Importer<'a, S> {
     }
 }
 
-fn read_content(p: &Path, reader: &dyn SourceRead) -> io::Result<Vec<(String, String)>> {
+/// Returns the directory or file an import refers to, and the import path
+/// the package is known by, which is relative to the base dir for local
+/// imports under it.
+pub fn resolve_import<S: SourceRead>(
+    reader: &S,
+    key: &ImportKey,
+) -> Result<(PathBuf, String), String> {
+    let mut import_path = key.path.clone();
+    let path = if is_local(&key.path) {
+        let mut wd = reader
+            .working_dir()
+            .map_err(|_| format!("failed to get working dir for: {}", key.path))?;
+        wd.push(&key.dir);
+        wd.push(&key.path);
+        if let Some(base) = &reader.base_dir() {
+            if let Ok(rel) = wd.as_path().strip_prefix(base) {
+                import_path = rel.to_string_lossy().to_string()
+            }
+        }
+        wd
+    } else {
+        if let Some(base) = &reader.base_dir() {
+            let mut p = PathBuf::new();
+            p.push(base);
+            p.push(&key.path);
+            p
+        } else {
+            return Err(format!("base dir required for path: {}", key.path));
+        }
+    };
+    match reader.canonicalize_path(&path) {
+        Ok(p) => Ok((p, import_path)),
+        Err(e) => Err(format!("{} {}", e, key.path)),
+    }
+}
+
+/// Reads the source files of the package at `p`, which is a directory or
+/// a single file, returns their display names and contents.
+pub fn read_content(p: &Path, reader: &dyn SourceRead) -> io::Result<Vec<(String, String)>> {
     let working_dir = reader
         .working_dir()
         .ok()
@@ -0,0 +1,176 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Checking a program again and again while its files are edited, like an
+//! editor does, without parsing and checking the packages that did not change.
+
+use super::check::TypeInfo;
+use super::importer::{read_content, ImportKey, Importer, SourceRead, TraceConfig};
+use super::objects::{PackageKey, TCObjects};
+use goscript_parser::errors::ErrorList;
+use goscript_parser::objects::Objects as AstObjects;
+use goscript_parser::FileSet;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+
+/// Checks a program keeping the results of its packages between checks. A
+/// package is parsed and checked again only if the content of its files
+/// changed, or if a package it imports is checked again. The packages of a
+/// check with errors are all checked again the next time, for their errors to
+/// be reported again.
+///
+/// The objects of the packages that are checked again are not freed, the
+/// arenas grow with each edit.
+pub struct IncrementalChecker {
+    fs: FileSet,
+    asto: AstObjects,
+    tco: TCObjects,
+    results: HashMap<PackageKey, TypeInfo>,
+    /// the packages by directory, it is the cache of the importer
+    pkgs: HashMap<String, PackageKey>,
+    /// the hashes of the contents of the packages in `pkgs` by directory
+    hashes: HashMap<String, u64>,
+    /// the directories of the packages checked by the last check
+    last_checked: Vec<String>,
+}
+
+impl IncrementalChecker {
+    pub fn new() -> IncrementalChecker {
+        IncrementalChecker {
+            fs: FileSet::new(),
+            asto: AstObjects::new(),
+            tco: TCObjects::new(),
+            results: HashMap::new(),
+            pkgs: HashMap::new(),
+            hashes: HashMap::new(),
+            last_checked: vec![],
+        }
+    }
+
+    /// Checks the program at `path`, with the packages that did not change
+    /// since the last check taken from it. Returns the main package and the
+    /// errors of the check.
+    pub fn check<S: SourceRead>(
+        &mut self,
+        reader: &S,
+        path: &str,
+    ) -> (Result<PackageKey, ()>, ErrorList) {
+        self.invalidate(reader);
+        let cached: HashSet<PackageKey> = self.pkgs.values().copied().collect();
+        let cfg = TraceConfig {
+            trace_parser: false,
+            trace_checker: false,
+        };
+        let el = ErrorList::new();
+        let key = ImportKey::new(path, "./");
+        let pkg = Importer::new(
+            &cfg,
+            reader,
+            &mut self.fs,
+            &mut self.pkgs,
+            &mut self.results,
+            &mut self.asto,
+            &mut self.tco,
+            &el,
+            0,
+        )
+        .import(&key);
+
+        let mut checked: Vec<String> = self
+            .pkgs
+            .iter()
+            .filter(|(_, k)| !cached.contains(k))
+            .map(|(dir, _)| dir.clone())
+            .collect();
+        checked.sort();
+        if !el.has_errors() {
+            for dir in checked.iter() {
+                if let Some(h) = content_hash(reader, dir) {
+                    self.hashes.insert(dir.clone(), h);
+                }
+            }
+        }
+        self.last_checked = checked;
+        (pkg, el)
+    }
+
+    /// The directories of the packages parsed and checked by the last check,
+    /// sorted
+    pub fn last_checked(&self) -> &[String] {
+        &self.last_checked
+    }
+
+    pub fn results(&self) -> &HashMap<PackageKey, TypeInfo> {
+        &self.results
+    }
+
+    pub fn tc_objects(&self) -> &TCObjects {
+        &self.tco
+    }
+
+    pub fn ast_objects(&self) -> &AstObjects {
+        &self.asto
+    }
+
+    pub fn file_set(&self) -> &FileSet {
+        &self.fs
+    }
+
+    /// Removes the packages to check again from the cache: the ones whose
+    /// files changed or that are not known to be fine, and their importers
+    fn invalidate<S: SourceRead>(&mut self, reader: &S) {
+        let mut stale: HashSet<String> = self
+            .pkgs
+            .keys()
+            .filter(|dir| {
+                let old = self.hashes.get(*dir);
+                old.is_none() || old.copied() != content_hash(reader, dir)
+            })
+            .cloned()
+            .collect();
+        let dirs: HashMap<PackageKey, String> =
+            self.pkgs.iter().map(|(d, k)| (*k, d.clone())).collect();
+        loop {
+            let importers: Vec<String> = self
+                .pkgs
+                .iter()
+                .filter(|(dir, key)| {
+                    !stale.contains(*dir)
+                        && self.tco.pkgs[**key]
+                            .imports()
+                            .iter()
+                            .any(|i| dirs.get(i).map_or(false, |d| stale.contains(d)))
+                })
+                .map(|(dir, _)| dir.clone())
+                .collect();
+            if importers.is_empty() {
+                break;
+            }
+            stale.extend(importers);
+        }
+        for dir in stale.iter() {
+            if let Some(key) = self.pkgs.remove(dir) {
+                self.results.remove(&key);
+            }
+            self.hashes.remove(dir);
+        }
+    }
+}
+
+impl Default for IncrementalChecker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The hash of the names and contents of the files of the package at `dir`
+fn content_hash<S: SourceRead>(reader: &S, dir: &str) -> Option<u64> {
+    read_content(Path::new(dir), reader).ok().map(|files| {
+        let mut hasher = DefaultHasher::new();
+        files.hash(&mut hasher);
+        hasher.finish()
+    })
+}
@@ -30,12 +30,22 @@ mod check;
 
 mod importer;
 
+mod incremental;
+
+mod workspace;
+
 pub use check::{DeclInfo, TypeInfo};
 pub use constant::Value as ConstValue;
 pub use display::Displayer;
-pub use importer::{FsReader, ImportKey, Importer, SourceRead, TraceConfig};
+pub use importer::{
+    read_content, resolve_import, FsReader, ImportKey, Importer, MapReader, SourceRead, TraceConfig,
+};
+pub use incremental::IncrementalChecker;
+pub use lookup::{lookup_field_or_method, LookupResult};
 pub use obj::EntityType;
 pub use objects::{DeclInfoKey, ObjKey, PackageKey, ScopeKey, TCObjects, TypeKey};
 pub use operand::OperandMode;
+pub use selection::SelectionKind;
 pub use typ::{identical, identical_ignore_tags, BasicType, ChanDir, Type};
 pub use universe::{Builtin, Universe};
+pub use workspace::{IdentInfo, Location, Workspace};
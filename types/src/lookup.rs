@@ -13,6 +13,7 @@
 use crate::SourceRead;
 
 use super::check::{Checker, FilesContext};
+use super::display::type_str;
 use super::obj;
 use super::objects::{ObjKey, PackageKey, TCObjects, TypeKey};
 use super::selection::*;
@@ -402,6 +403,60 @@ pub fn missing_method<S: SourceRead>(
     None
 }
 
+/// missing_method_cause explains why 't' does not implement an interface
+/// whose method 'm' missing_method reported, like gc does:
+/// "(missing method M)", "(method M has pointer receiver)" or
+/// "(wrong type for method M)" followed by the signatures found and
+/// wanted.
+pub fn missing_method_cause(t: TypeKey, m: ObjKey, objs: &mut TCObjects) -> String {
+    let func_str = |okey: ObjKey, objs: &TCObjects| {
+        let lobj = &objs.lobjs[okey];
+        let sig = type_str(&lobj.typ().unwrap(), objs);
+        format!(
+            "{}{}",
+            lobj.name(),
+            sig.strip_prefix("func").unwrap_or(&sig)
+        )
+    };
+    let (pkg, name) = (objs.lobjs[m].pkg(), objs.lobjs[m].name().clone());
+    // addressable, so that methods with pointer receivers are found too
+    if let LookupResult::Entry(alt, _, _) = lookup_field_or_method(t, true, pkg, &name, objs) {
+        if !objs.lobjs[alt].entity_type().is_func() {
+            return format!("({}.{} is a field, not a method)", type_str(&t, objs), name);
+        }
+        if !typ::identical_o(objs.lobjs[m].typ(), objs.lobjs[alt].typ(), objs) {
+            return format!(
+                "(wrong type for method {})\n\t\thave {}\n\t\twant {}",
+                name,
+                func_str(alt, objs),
+                func_str(m, objs)
+            );
+        }
+        if lookup_field_or_method(t, false, pkg, &name, objs) == LookupResult::BadMethodReceiver {
+            return format!("(method {} has pointer receiver)", name);
+        }
+    }
+    // a method whose name only differs in case
+    let mset_type = match try_deref(t, objs) {
+        (_, false) if !typ::is_interface(t, objs) => objs.new_t_pointer(t),
+        _ => t,
+    };
+    let mset = MethodSet::new(&mset_type, objs);
+    let alt = mset.list().iter().map(|x| x.obj()).find(|&okey| {
+        let alt_name = objs.lobjs[okey].name();
+        alt_name != &name && alt_name.eq_ignore_ascii_case(&name)
+    });
+    match alt {
+        Some(alt) => format!(
+            "(missing method {})\n\t\thave {}\n\t\twant {}",
+            name,
+            func_str(alt, objs),
+            func_str(m, objs)
+        ),
+        None => format!("(missing method {})", name),
+    }
+}
+
 fn lookup_field_or_method_impl(
     tkey: TypeKey,
     addressable: bool,
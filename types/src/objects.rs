@@ -117,6 +117,15 @@ impl TCObjects {
         self.pkgs.insert(pkg)
     }
 
+    /// Returns a package whose scope is nested in the one of `outer`, so
+    /// that the declarations of `outer` are visible in it
+    pub fn new_nested_package(&mut self, path: String, outer: PackageKey) -> PackageKey {
+        let parent = *self.pkgs[outer].scope();
+        let skey = self.new_scope(Some(parent), 0, 0, format!("package {}", path), false);
+        let pkg = Package::new(path, None, skey);
+        self.pkgs.insert(pkg)
+    }
+
     pub fn new_pkg_name(
         &mut self,
         pos: position::Pos,
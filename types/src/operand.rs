@@ -14,7 +14,8 @@ use crate::SourceRead;
 
 use super::check::{Checker, FilesContext};
 use super::constant;
-use super::lookup::missing_method;
+use super::display::type_str;
+use super::lookup::{missing_method, missing_method_cause};
 use super::objects::{TCObjects, TypeKey};
 use super::typ;
 use super::typ::{fmt_type, BasicType, Type};
@@ -195,15 +196,12 @@ impl Operand {
 
         // 'left' is an interface and 'right' implements 'left'
         if ut_left.try_as_interface().is_some() {
-            if let Some((m, wrong_type)) = missing_method(k_right, ut_key_left, true, checker, fctx)
-            {
+            if let Some((m, _)) = missing_method(k_right, ut_key_left, true, checker, fctx) {
                 if let Some(re) = reason {
-                    let msg = if wrong_type {
-                        "wrong type for method"
-                    } else {
-                        "missing method"
-                    };
-                    *re = format!("{} {}", msg, checker.tc_objs.lobjs[m].name());
+                    let cause = missing_method_cause(k_right, m, checker.tc_objs);
+                    let objs = &checker.tc_objs;
+                    let (tr, tl) = (type_str(&k_right, objs), type_str(&k_left, objs));
+                    *re = format!("{} does not implement {} {}", tr, tl, cause);
                 }
                 return false;
             }
@@ -554,17 +552,19 @@ impl<'a, 'b> ExprFormater<'a, 'b> {
         self.fmt_fields(&sig.params, ", ", false)?;
         self.f.write_char(')')?;
         if let Some(re) = &sig.results {
+            if re.list.len() == 0 {
+                return Ok(());
+            }
             self.f.write_char(' ')?;
             if re.list.len() == 1 {
                 let field = &self.ast_objs.fields[re.list[0]];
                 if field.names.len() == 0 {
-                    self.visit_expr(&field.typ)?;
+                    return self.visit_expr(&field.typ);
                 }
-            } else {
-                self.f.write_char('(')?;
-                self.fmt_fields(&re, ", ", false)?;
-                self.f.write_char(')')?;
             }
+            self.f.write_char('(')?;
+            self.fmt_fields(&re, ", ", false)?;
+            self.f.write_char(')')?;
         }
         Ok(())
     }
@@ -578,24 +578,19 @@ impl<'a, 'b> ExprFormater<'a, 'b> {
             for (i, name) in field.names.iter().enumerate() {
                 if i > 0 {
                     self.f.write_str(", ")?;
-                    self.fmt_ident(name)?;
                 }
+                self.fmt_ident(name)?;
             }
             // types of interface methods consist of signatures only
-            if iface {
-                match &field.typ {
-                    Expr::Func(sig) => {
-                        self.fmt_sig(&self.ast_objs.ftypes[*sig])?;
-                    }
-                    _ => {}
-                }
-            } else {
-                // named fields are separated with a blank from the field type
-                if field.names.len() > 0 {
-                    self.f.write_char(' ')?;
-                }
-                self.visit_expr(&field.typ)?;
+            if let (true, Expr::Func(sig)) = (iface, &field.typ) {
+                self.fmt_sig(&self.ast_objs.ftypes[*sig])?;
+                continue;
+            }
+            // named fields are separated with a blank from the field type
+            if field.names.len() > 0 {
+                self.f.write_char(' ')?;
             }
+            self.visit_expr(&field.typ)?;
         }
         Ok(())
     }
@@ -105,7 +105,12 @@ impl Package {
         f: &mut fmt::Formatter<'_>,
         qf: &dyn Fn(&Package) -> Cow<str>,
     ) -> fmt::Result {
-        write!(f, "{}.", qf(self))
+        let q = qf(self);
+        if q.is_empty() {
+            Ok(())
+        } else {
+            write!(f, "{}.", q)
+        }
     }
 }
 
@@ -0,0 +1,203 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! The check results of a program in a form that can be queried by position,
+//! for language servers and other editor tools. Lines and columns start at 1
+//! and columns are in chars, like the ones of the positions in error messages.
+
+use super::check::TypeInfo;
+use super::display::Displayer;
+use super::importer::{ImportKey, Importer, SourceRead, TraceConfig};
+use super::objects::{ObjKey, PackageKey, TCObjects};
+use super::typ::BasicType;
+use goscript_parser::errors::ErrorList;
+use goscript_parser::objects::{IdentKey, Objects as AstObjects};
+use goscript_parser::{FilePos, FileSet, Pos};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// A position in a file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// An identifier of a file that defines or refers to an object
+#[derive(Debug, Clone)]
+pub struct IdentInfo {
+    pub ident: IdentKey,
+    pub name: String,
+    pub line: usize,
+    pub column: usize,
+    pub is_def: bool,
+    pub obj: ObjKey,
+    pub pkg: PackageKey,
+}
+
+/// A type-checked program, with the identifiers of each of its files
+pub struct Workspace {
+    fs: FileSet,
+    asto: AstObjects,
+    tco: TCObjects,
+    infos: HashMap<PackageKey, TypeInfo>,
+    errors: ErrorList,
+    main_pkg: Option<PackageKey>,
+    /// the identifiers of the files sorted by position
+    files: HashMap<String, Vec<IdentInfo>>,
+}
+
+impl Workspace {
+    /// Type-checks the program at `path`, the results of the packages that
+    /// were checked are kept even if there are errors
+    pub fn check<S: SourceRead>(reader: &S, path: &str) -> Workspace {
+        let cfg = TraceConfig {
+            trace_parser: false,
+            trace_checker: false,
+        };
+        let mut fs = FileSet::new();
+        let mut asto = AstObjects::new();
+        let mut tco = TCObjects::new();
+        let mut infos = HashMap::new();
+        let mut pkgs = HashMap::new();
+        let errors = ErrorList::new();
+        let key = ImportKey::new(path, "./");
+        let main_pkg = Importer::new(
+            &cfg, reader, &mut fs, &mut pkgs, &mut infos, &mut asto, &mut tco, &errors, 0,
+        )
+        .import(&key)
+        .ok();
+
+        // types of the main package are not qualified, the others are by package name
+        let own = main_pkg.map(|p| tco.pkgs[p].path().clone());
+        tco.fmt_qualifier = Box::new(move |p| {
+            if Some(p.path()) == own.as_ref() {
+                Cow::Borrowed("")
+            } else {
+                Cow::Owned(p.name().clone().unwrap_or_default())
+            }
+        });
+
+        let mut files: HashMap<String, Vec<IdentInfo>> = HashMap::new();
+        for (pkg, info) in infos.iter() {
+            let defs = info
+                .defs
+                .iter()
+                .filter_map(|(i, o)| o.map(|o| (*i, o, true)));
+            let uses = info.uses.iter().map(|(i, o)| (*i, *o, false));
+            for (ikey, okey, is_def) in defs.chain(uses) {
+                let ident = &asto.idents[ikey];
+                if let Some(p) = fs.position(ident.pos) {
+                    files
+                        .entry(p.filename.to_string())
+                        .or_default()
+                        .push(IdentInfo {
+                            ident: ikey,
+                            name: ident.name.clone(),
+                            line: p.line,
+                            column: p.column,
+                            is_def: is_def,
+                            obj: okey,
+                            pkg: *pkg,
+                        });
+                }
+            }
+        }
+        for idents in files.values_mut() {
+            idents.sort_by_key(|i| (i.line, i.column));
+        }
+
+        Workspace {
+            fs: fs,
+            asto: asto,
+            tco: tco,
+            infos: infos,
+            errors: errors,
+            main_pkg: main_pkg,
+            files: files,
+        }
+    }
+
+    /// The errors of the check, the results are incomplete if there are any
+    pub fn errors(&self) -> &ErrorList {
+        &self.errors
+    }
+
+    pub fn main_pkg(&self) -> Option<PackageKey> {
+        self.main_pkg
+    }
+
+    pub fn tc_objects(&self) -> &TCObjects {
+        &self.tco
+    }
+
+    pub fn ast_objects(&self) -> &AstObjects {
+        &self.asto
+    }
+
+    pub fn type_info(&self, pkg: PackageKey) -> Option<&TypeInfo> {
+        self.infos.get(&pkg)
+    }
+
+    /// The names of the files with identifiers, sorted
+    pub fn files(&self) -> Vec<&str> {
+        let mut files: Vec<&str> = self.files.keys().map(|f| &f[..]).collect();
+        files.sort();
+        files
+    }
+
+    /// The identifiers of `file` defining or referring to objects, sorted by
+    /// position. `file` may be a suffix of the name, like `a/b.gos`.
+    pub fn file_idents(&self, file: &str) -> Option<&[IdentInfo]> {
+        let path = Path::new(file.trim_start_matches("./"));
+        self.files
+            .iter()
+            .find(|(name, _)| Path::new(name).ends_with(path))
+            .map(|(_, idents)| &idents[..])
+    }
+
+    /// The identifier at `line` and `column` of `file`
+    pub fn ident_at(&self, file: &str, line: usize, column: usize) -> Option<&IdentInfo> {
+        self.file_idents(file)?.iter().find(|i| {
+            i.line == line && i.column <= column && column < i.column + i.name.chars().count()
+        })
+    }
+
+    /// Where the object denoted by the identifier at `line` and `column` of
+    /// `file` is declared, None for the predeclared ones
+    pub fn lookup_definition(&self, file: &str, line: usize, column: usize) -> Option<Location> {
+        let ident = self.ident_at(file, line, column)?;
+        let obj = &self.tco.lobjs[ident.obj];
+        obj.pkg()?;
+        self.location(obj.pos())
+    }
+
+    /// The type of the object denoted by the identifier at `line` and
+    /// `column` of `file`, None for package names
+    pub fn hover_type(&self, file: &str, line: usize, column: usize) -> Option<String> {
+        let ident = self.ident_at(file, line, column)?;
+        let typ = self.tco.lobjs[ident.obj].typ()?;
+        if typ == self.tco.universe().types()[&BasicType::Invalid] {
+            return None;
+        }
+        Some(Displayer::new(&typ, None, Some(&self.tco)).to_string())
+    }
+
+    /// The declaration of the object denoted by the identifier at `line` and
+    /// `column` of `file`, like `var x int` or `func f(a string) error`
+    pub fn hover_object(&self, file: &str, line: usize, column: usize) -> Option<String> {
+        let ident = self.ident_at(file, line, column)?;
+        Some(Displayer::new(&ident.obj, None, Some(&self.tco)).to_string())
+    }
+
+    fn location(&self, pos: Pos) -> Option<Location> {
+        self.fs.position(pos).map(|p: FilePos| Location {
+            file: p.filename.to_string(),
+            line: p.line,
+            column: p.column,
+        })
+    }
+}
@@ -71,7 +71,7 @@ fn test_file(path: &str, trace: bool) {
         trace_parser: trace,
         trace_checker: trace,
     };
-    let reader = FsReader::new(Some("./"), None, None);
+    let reader = FsReader::new(Some("./"), Some("../std/"), None);
     let fs = &mut fe::FileSet::new();
     let asto = &mut fe::objects::Objects::new();
     let el = &mut fe::errors::ErrorList::new();
@@ -201,7 +201,160 @@ fn test_auto() {
     test_file("./tests/data/vardecl.src", trace);
 }
 
+#[test]
+fn test_init_cycles() {
+    test_file("./tests/data/init0.src", false);
+    test_file("./tests/data/init1.src", false);
+    test_file("./tests/data/init2.src", false);
+    test_file("./tests/data/init3.src", false);
+}
+
+#[test]
+fn test_recover() {
+    test_file("./tests/data/recover", false);
+}
+
+#[test]
+fn test_printf() {
+    test_file("./tests/data/printf.src", false);
+}
+
+#[test]
+fn test_vet() {
+    test_file("./tests/data/vet.src", false);
+}
+
+#[test]
+fn test_memoize() {
+    test_file("./tests/data/memoize.src", false);
+}
+
+#[test]
+fn test_pragmas() {
+    test_file("./tests/data/pragmas.src", false);
+}
+
+#[test]
+fn test_pure() {
+    test_file("./tests/data/pure.src", false);
+}
+
 #[test]
 fn test_temp() {
     test_file("./tests/data/temp.gos", true);
 }
+
+#[test]
+fn test_workspace() {
+    let source = r#"package main
+
+import "strings"
+
+type Point struct{ X, Y int }
+
+func (p *Point) Move(dx int) { p.X += dx }
+
+func main() {
+	p := &Point{1, 2}
+	p.Move(3)
+	s := strings.Repeat("a", p.X)
+	println(len(s))
+}
+"#;
+    let reader = FsReader::new(Some("./"), Some("../std/"), Some(source));
+    let file = FsReader::temp_file_path();
+    let ws = types::Workspace::check(&reader, file);
+    assert!(!ws.errors().has_errors());
+    assert!(ws.files().iter().any(|f| f.ends_with(file)));
+    assert!(ws.file_idents(file).unwrap().iter().any(|i| i.is_def && i.name == "Move"));
+
+    // p in p.Move is defined by p := ...
+    let def = ws.lookup_definition(file, 11, 2).unwrap();
+    assert!(def.file.ends_with(file));
+    assert_eq!((def.line, def.column), (10, 2));
+    assert_eq!(ws.hover_type(file, 11, 2).unwrap(), "*Point");
+    // the method, the field and the package
+    assert_eq!(ws.lookup_definition(file, 11, 5).unwrap().line, 7);
+    assert_eq!(ws.hover_type(file, 11, 5).unwrap(), "func(dx int)");
+    assert_eq!(ws.hover_type(file, 12, 29).unwrap(), "int");
+    assert_eq!(ws.lookup_definition(file, 12, 29).unwrap().line, 5);
+    assert_eq!(ws.hover_type(file, 12, 7), None);
+    assert_eq!(ws.lookup_definition(file, 12, 7).unwrap().line, 3);
+    assert_eq!(
+        ws.hover_object(file, 12, 7).unwrap(),
+        "package strings"
+    );
+    // a function of another package
+    let def = ws.lookup_definition(file, 12, 15).unwrap();
+    assert!(def.file.ends_with("strings/strings.go"), "{}", def.file);
+    assert_eq!(
+        ws.hover_type(file, 12, 15).unwrap(),
+        "func(s string, count int) string"
+    );
+    // predeclared objects have no definition
+    assert_eq!(ws.lookup_definition(file, 13, 10), None);
+    assert_eq!(ws.hover_object(file, 13, 10).unwrap(), "builtin len");
+    // nothing at the position
+    assert!(ws.lookup_definition(file, 12, 4).is_none());
+}
+
+#[test]
+fn test_incremental() {
+    let mut reader = types::MapReader::new(None);
+    reader.add_file(
+        "app/main.gos",
+        "package main\n\nimport \"../a\"\n\nfunc main() { _ = a.F() }\n".to_owned(),
+    );
+    reader.add_file(
+        "a/a.gos",
+        "package a\n\nimport \"../b\"\n\nfunc F() int { return b.G() }\n".to_owned(),
+    );
+    reader.add_file("b/b.gos", "package b\n\nfunc G() int { return 1 }\n".to_owned());
+    reader.add_file("c/c.gos", "package c\n".to_owned());
+    let mut checker = types::IncrementalChecker::new();
+    let (pkg, el) = checker.check(&reader, "./app");
+    assert!(pkg.is_ok() && !el.has_errors());
+    assert_eq!(checker.last_checked(), ["a", "app", "b"]);
+
+    // nothing changed
+    let (pkg2, el) = checker.check(&reader, "./app");
+    assert!(!el.has_errors());
+    assert_eq!(pkg2, pkg);
+    assert!(checker.last_checked().is_empty());
+
+    // only the edited package is checked again
+    reader.add_file(
+        "app/main.gos",
+        "package main\n\nimport \"../a\"\n\nfunc main() { println(a.F()) }\n".to_owned(),
+    );
+    let (pkg, el) = checker.check(&reader, "./app");
+    assert!(pkg.is_ok() && !el.has_errors());
+    assert_eq!(checker.last_checked(), ["app"]);
+
+    // the importers of an edited package are checked again too
+    reader.add_file("b/b.gos", "package b\n\nfunc G() string { return \"\" }\n".to_owned());
+    let (_, el) = checker.check(&reader, "./app");
+    assert!(el.has_errors());
+    assert!(el.borrow()[0].msg.contains("cannot use"), "{}", el);
+    assert_eq!(checker.last_checked(), ["a", "app", "b"]);
+    assert_eq!(checker.results().len(), 3);
+
+    // a check with errors is not cached, the errors are reported again
+    let (_, el) = checker.check(&reader, "./app");
+    assert!(el.has_errors());
+    assert_eq!(checker.last_checked(), ["a", "app", "b"]);
+
+    // a new file of a package
+    reader.add_file("b/b2.gos", "package b\n\nfunc H() int { return 2 }\n".to_owned());
+    reader.add_file("b/b.gos", "package b\n\nfunc G() int { return H() }\n".to_owned());
+    let (_, el) = checker.check(&reader, "./app");
+    assert!(!el.has_errors(), "{}", el);
+    assert_eq!(checker.last_checked(), ["a", "app", "b"]);
+    reader.add_file(
+        "a/a.gos",
+        "package a\n\nimport \"../b\"\n\nfunc F() int { return b.G() + 1 }\n".to_owned(),
+    );
+    let (_, el) = checker.check(&reader, "./app");
+    assert!(!el.has_errors());
+    assert_eq!(checker.last_checked(), ["a", "app"]);
+}
@@ -0,0 +1,107 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Counting the conversions a program executes, by the CAST instructions
+//! that run them, to find the accidental interface boxing and numeric
+//! conversions in hot paths.
+//!
+//! Auditing costs a hash map update per conversion, without it the cost is
+//! one branch per conversion.
+
+use super::instruction::{Opcode, ValueType};
+use super::value::{FunctionKey, VMObjects};
+use super::vm::StackFrame;
+use goscript_parser::FileSet;
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum CastKind {
+    /// a value put in an interface, implicitly or not
+    Boxing,
+    /// between integer, float and complex types
+    Numeric,
+    /// to or from strings, like `string(bytes)` or `[]rune(s)`
+    String,
+    /// between unsafe pointers, pointers and uintptrs
+    Pointer,
+    /// the others, like between function values of different types
+    Other,
+}
+
+impl CastKind {
+    pub fn new(from: ValueType, to: ValueType) -> CastKind {
+        let numeric = |t: ValueType| {
+            (t >= ValueType::Int && t <= ValueType::Complex64) || t == ValueType::Complex128
+        };
+        let pointer = |t: ValueType| t == ValueType::Pointer || t == ValueType::UnsafePtr;
+        if to == ValueType::Interface {
+            CastKind::Boxing
+        } else if from == ValueType::String || to == ValueType::String {
+            CastKind::String
+        } else if pointer(from) || pointer(to) {
+            CastKind::Pointer
+        } else if numeric(from) && numeric(to) {
+            CastKind::Numeric
+        } else {
+            CastKind::Other
+        }
+    }
+}
+
+/// The conversions of a CAST instruction
+#[derive(Debug, Clone)]
+pub struct CastRecord {
+    pub kind: CastKind,
+    pub from: ValueType,
+    pub to: ValueType,
+    /// the function and the position of the conversion, None for the
+    /// functions the compiler adds if there is no debug info
+    pub site: Option<StackFrame>,
+    /// how many times it ran
+    pub count: u64,
+}
+
+/// The counts of the CAST instructions by function and index
+#[derive(Default)]
+pub struct CastAudit {
+    counts: RefCell<HashMap<(FunctionKey, usize), u64>>,
+}
+
+impl CastAudit {
+    pub fn new() -> CastAudit {
+        CastAudit::default()
+    }
+
+    #[inline]
+    pub(crate) fn record(&self, func: FunctionKey, pc: usize) {
+        *self.counts.borrow_mut().entry((func, pc)).or_insert(0) += 1;
+    }
+
+    /// The conversions run so far, the most frequent first
+    pub fn report(&self, objs: &VMObjects, fs: Option<&FileSet>) -> Vec<CastRecord> {
+        let mut records: Vec<CastRecord> = self
+            .counts
+            .borrow()
+            .iter()
+            .filter_map(|(&(func, pc), &count)| {
+                let inst = objs.functions.get(func)?.code()[pc];
+                debug_assert!(inst.op() == Opcode::CAST);
+                let (from, to) = (inst.t1(), inst.t0());
+                Some(CastRecord {
+                    kind: CastKind::new(from, to),
+                    from: from,
+                    to: to,
+                    site: StackFrame::new(func, pc, objs, fs),
+                    count: count,
+                })
+            })
+            .collect();
+        records.sort_by(|a, b| {
+            let pos = |r: &CastRecord| r.site.as_ref().map(|s| (s.file.clone(), s.line, s.column));
+            b.count.cmp(&a.count).then_with(|| pos(a).cmp(&pos(b)))
+        });
+        records
+    }
+}
@@ -0,0 +1,290 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Binds Rust types to Go types, for embedders passing typed data to the
+//! scripts and reading typed results back. Unlike `FromGos` and `IntoGos`
+//! the conversions know the Go type, so a Rust struct deriving `GosBind`
+//! can be converted to and from a Go struct, field by field by name.
+//!
+//! Go values of struct types only have their metadata in interfaces, an FFI
+//! function gets them as `interface{}` and converts them with
+//! `FfiCallCtx::bind_from`, and stores results through pointers with
+//! `FfiCallCtx::bind_into`.
+
+use super::ffi::{FfiCallCtx, FromGos};
+use super::instruction::ValueType;
+use super::metadata::{Meta, MetadataType};
+use super::value::{GosValue, InterfaceObj, RuntimeResult};
+
+/// A Rust type with a Go counterpart, converted with the metadata of the Go
+/// type. Implemented for bool, the integer and float types, `String`,
+/// `Vec<T>` as slices, and structs with `#[derive(GosBind)]`.
+pub trait GosBind: Sized {
+    fn from_gos_meta(v: &GosValue, meta: Meta, ctx: &FfiCallCtx) -> RuntimeResult<Self>;
+
+    fn to_gos_meta(&self, meta: Meta, ctx: &FfiCallCtx) -> RuntimeResult<GosValue>;
+}
+
+impl<'a> FfiCallCtx<'a> {
+    /// Converts the value in the interface `iface`, like an `interface{}`
+    /// argument, to a Rust value
+    pub fn bind_from<T: GosBind>(&self, iface: &GosValue) -> RuntimeResult<T> {
+        let (v, meta) = iface_value(iface)?;
+        T::from_gos_meta(&v, meta, self)
+    }
+
+    /// Stores `val` where the pointer in the interface `target` points to,
+    /// like a `*Config` passed as `interface{}`
+    pub fn bind_into<T: GosBind>(&mut self, target: &GosValue, val: &T) -> RuntimeResult<()> {
+        let (ptr, meta) = iface_value(target)?;
+        if meta.ptr_depth == 0 || ptr.is_nil() {
+            return Err("bind: the target is not a non-nil pointer".to_owned());
+        }
+        let v = val.to_gos_meta(meta.unptr_to(), self)?;
+        ptr.as_some_pointer()?
+            .set_pointee(&v, self.stack, &self.vm_objs.packages, self.gcv)
+    }
+
+    fn value_type_of(&self, meta: Meta) -> ValueType {
+        meta.value_type(&self.vm_objs.metas)
+    }
+}
+
+fn iface_value(iface: &GosValue) -> RuntimeResult<(GosValue, Meta)> {
+    let iface = match iface.typ() {
+        ValueType::Interface => iface.as_interface(),
+        _ => None,
+    };
+    match iface.map(|x| x as &InterfaceObj) {
+        Some(InterfaceObj::Gos(v, Some((meta, _)))) => Ok((v.clone(), *meta)),
+        _ => Err("bind: expected a non-nil interface holding a Go value".to_owned()),
+    }
+}
+
+fn mismatch<T>(rust: &str, meta: Meta, ctx: &FfiCallCtx) -> RuntimeResult<T> {
+    Err(format!(
+        "bind: cannot convert between {} and {:?}",
+        rust,
+        ctx.value_type_of(meta)
+    ))
+}
+
+impl GosBind for bool {
+    fn from_gos_meta(v: &GosValue, _: Meta, _: &FfiCallCtx) -> RuntimeResult<bool> {
+        bool::from_gos(v)
+    }
+
+    fn to_gos_meta(&self, meta: Meta, ctx: &FfiCallCtx) -> RuntimeResult<GosValue> {
+        match ctx.value_type_of(meta) {
+            ValueType::Bool => Ok(GosValue::new_bool(*self)),
+            _ => mismatch("bool", meta, ctx),
+        }
+    }
+}
+
+/// Returns the integer i as a value of type t, None if it's out of range
+fn int_into_gos(i: i128, t: ValueType) -> Option<GosValue> {
+    Some(match t {
+        ValueType::Int => GosValue::new_int(i.try_into().ok()?),
+        ValueType::Int8 => GosValue::new_int8(i.try_into().ok()?),
+        ValueType::Int16 => GosValue::new_int16(i.try_into().ok()?),
+        ValueType::Int32 => GosValue::new_int32(i.try_into().ok()?),
+        ValueType::Int64 => GosValue::new_int64(i.try_into().ok()?),
+        ValueType::Uint => GosValue::new_uint(i.try_into().ok()?),
+        ValueType::UintPtr => GosValue::new_uint_ptr(i.try_into().ok()?),
+        ValueType::Uint8 => GosValue::new_uint8(i.try_into().ok()?),
+        ValueType::Uint16 => GosValue::new_uint16(i.try_into().ok()?),
+        ValueType::Uint32 => GosValue::new_uint32(i.try_into().ok()?),
+        ValueType::Uint64 => GosValue::new_uint64(i.try_into().ok()?),
+        _ => return None,
+    })
+}
+
+macro_rules! int_binds {
+    ($($t:ty),*) => {
+        $(
+            /// Any Go integer type, as long as the value fits
+            impl GosBind for $t {
+                fn from_gos_meta(v: &GosValue, _: Meta, _: &FfiCallCtx) -> RuntimeResult<$t> {
+                    <$t>::from_gos(v)
+                }
+
+                fn to_gos_meta(&self, meta: Meta, ctx: &FfiCallCtx) -> RuntimeResult<GosValue> {
+                    let t = ctx.value_type_of(meta);
+                    match int_into_gos(*self as i128, t) {
+                        Some(v) => Ok(v),
+                        None => Err(format!("bind: {} out of range of {:?}", self, t)),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+int_binds!(isize, i8, i16, i32, i64, usize, u8, u16, u32, u64);
+
+impl GosBind for f64 {
+    fn from_gos_meta(v: &GosValue, _: Meta, _: &FfiCallCtx) -> RuntimeResult<f64> {
+        f64::from_gos(v)
+    }
+
+    fn to_gos_meta(&self, meta: Meta, ctx: &FfiCallCtx) -> RuntimeResult<GosValue> {
+        match ctx.value_type_of(meta) {
+            ValueType::Float64 => Ok(GosValue::new_float64((*self).into())),
+            ValueType::Float32 => Ok(GosValue::new_float32((*self as f32).into())),
+            _ => mismatch("f64", meta, ctx),
+        }
+    }
+}
+
+impl GosBind for f32 {
+    fn from_gos_meta(v: &GosValue, _: Meta, _: &FfiCallCtx) -> RuntimeResult<f32> {
+        f32::from_gos(v)
+    }
+
+    fn to_gos_meta(&self, meta: Meta, ctx: &FfiCallCtx) -> RuntimeResult<GosValue> {
+        (*self as f64).to_gos_meta(meta, ctx)
+    }
+}
+
+impl GosBind for String {
+    fn from_gos_meta(v: &GosValue, _: Meta, _: &FfiCallCtx) -> RuntimeResult<String> {
+        String::from_gos(v)
+    }
+
+    fn to_gos_meta(&self, meta: Meta, ctx: &FfiCallCtx) -> RuntimeResult<GosValue> {
+        match ctx.value_type_of(meta) {
+            ValueType::String => Ok(GosValue::with_str(self)),
+            _ => mismatch("String", meta, ctx),
+        }
+    }
+}
+
+/// A slice, nil is empty
+impl<T: GosBind> GosBind for Vec<T> {
+    fn from_gos_meta(v: &GosValue, meta: Meta, ctx: &FfiCallCtx) -> RuntimeResult<Vec<T>> {
+        let elem = slice_elem(meta, ctx)?;
+        if v.is_nil() {
+            return Ok(vec![]);
+        }
+        (0..v.len())
+            .map(|i| T::from_gos_meta(&v.dispatcher_a_s().slice_get(v, i)?, elem, ctx))
+            .collect()
+    }
+
+    fn to_gos_meta(&self, meta: Meta, ctx: &FfiCallCtx) -> RuntimeResult<GosValue> {
+        let elem = slice_elem(meta, ctx)?;
+        let data = self
+            .iter()
+            .map(|x| x.to_gos_meta(elem, ctx))
+            .collect::<RuntimeResult<Vec<GosValue>>>()?;
+        Ok(GosValue::slice_with_data(
+            data,
+            ctx.value_type_of(elem),
+            ctx.gcv,
+        ))
+    }
+}
+
+fn slice_elem(meta: Meta, ctx: &FfiCallCtx) -> RuntimeResult<Meta> {
+    let metas = &ctx.vm_objs.metas;
+    match &metas[meta.underlying(metas).key] {
+        MetadataType::Slice(elem) if meta.ptr_depth == 0 => Ok(*elem),
+        _ => mismatch("Vec", meta, ctx),
+    }
+}
+
+/// A Go struct seen by the code `#[derive(GosBind)]` generates
+#[doc(hidden)]
+pub struct BoundStruct<'a, 'b> {
+    ctx: &'a FfiCallCtx<'b>,
+    value: GosValue,
+    /// name and type of the fields of the Go struct
+    fields: Vec<(String, Meta)>,
+    /// name of the Rust struct, for errors
+    name: &'static str,
+}
+
+impl<'a, 'b> BoundStruct<'a, 'b> {
+    /// Sees v, a struct of type meta
+    pub fn new(
+        v: &GosValue,
+        meta: Meta,
+        ctx: &'a FfiCallCtx<'b>,
+        name: &'static str,
+    ) -> RuntimeResult<BoundStruct<'a, 'b>> {
+        let metas = &ctx.vm_objs.metas;
+        let fields = match &metas[meta.underlying(metas).key] {
+            MetadataType::Struct(fields, _) if meta.ptr_depth == 0 => fields
+                .all()
+                .iter()
+                .map(|f| (f.name.clone(), f.meta))
+                .collect(),
+            _ => return mismatch(name, meta, ctx),
+        };
+        Ok(BoundStruct {
+            ctx: ctx,
+            value: v.clone(),
+            fields: fields,
+            name: name,
+        })
+    }
+
+    /// Sees a new zero struct of type meta
+    pub fn zero(
+        meta: Meta,
+        ctx: &'a FfiCallCtx<'b>,
+        name: &'static str,
+    ) -> RuntimeResult<BoundStruct<'a, 'b>> {
+        let zero = meta.zero(&ctx.vm_objs.metas, ctx.gcv);
+        BoundStruct::new(&zero, meta, ctx, name)
+    }
+
+    /// The Go field of the Rust field `field`, the one named `go_name` if
+    /// given, or else the one with the same name ignoring case and
+    /// underscores, so that `max_retries` binds to `MaxRetries`
+    fn field(&self, field: &str, go_name: Option<&str>) -> RuntimeResult<(usize, Meta)> {
+        let normalize = |s: &str| s.replace('_', "").to_lowercase();
+        let found = match go_name {
+            Some(n) => self.fields.iter().position(|f| f.0 == n),
+            None => self.fields.iter().position(|f| f.0 == field).or_else(|| {
+                self.fields
+                    .iter()
+                    .position(|f| normalize(&f.0) == normalize(field))
+            }),
+        };
+        match found {
+            Some(i) => Ok((i, self.fields[i].1)),
+            None => Err(format!(
+                "bind: no Go field for {}.{}",
+                self.name,
+                go_name.unwrap_or(field)
+            )),
+        }
+    }
+
+    pub fn get<T: GosBind>(&self, field: &str, go_name: Option<&str>) -> RuntimeResult<T> {
+        let (i, meta) = self.field(field, go_name)?;
+        let v = self.value.as_struct().0.borrow_fields()[i].clone();
+        T::from_gos_meta(&v, meta, self.ctx).map_err(|e| format!("{}.{}: {}", self.name, field, e))
+    }
+
+    pub fn set<T: GosBind>(
+        &self,
+        field: &str,
+        go_name: Option<&str>,
+        val: &T,
+    ) -> RuntimeResult<()> {
+        let (i, meta) = self.field(field, go_name)?;
+        let v = val
+            .to_gos_meta(meta, self.ctx)
+            .map_err(|e| format!("{}.{}: {}", self.name, field, e))?;
+        self.value.as_struct().0.borrow_fields_mut()[i] = v;
+        Ok(())
+    }
+
+    pub fn into_value(self) -> GosValue {
+        self.value
+    }
+}
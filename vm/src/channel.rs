@@ -3,17 +3,22 @@
 // license that can be found in the LICENSE file.
 
 use super::instruction::*;
+use super::rng;
 use super::value::*;
 use futures_lite::future;
 use rand::prelude::*;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::mem;
 use std::rc::Rc;
+use std::task::{Poll, Waker};
 
 #[derive(Clone, Debug)]
 pub enum RendezvousState {
     Empty,
     Full(GosValue),
+    // closed after a send that has not been received yet, which is still
+    // delivered, as the sender went on believing it was
+    ClosedFull(GosValue),
     Closed,
 }
 
@@ -22,81 +27,180 @@ pub enum Channel {
     Bounded(
         async_channel::Sender<GosValue>,
         async_channel::Receiver<GosValue>,
+        Watchers,
     ),
-    Rendezvous(Rc<RefCell<RendezvousState>>),
+    // the count of the receivers waiting on it, so that a send in select
+    // proceeds only when there is someone to receive
+    Rendezvous(
+        Rc<RefCell<RendezvousState>>,
+        Rc<Cell<usize>>,
+        Rc<Tickets>,
+        Watchers,
+    ),
+}
+
+/// The counts of the values put in and taken out of a rendezvous channel. A
+/// send gets the count after its value is put in as its ticket, and the value
+/// is received once as many are taken out. The state of the slot can't tell,
+/// as it is full again as soon as the receiver sends another value.
+#[derive(Debug, Default)]
+pub struct Tickets {
+    sent: Cell<u64>,
+    received: Cell<u64>,
+}
+
+/// The wakers of the fibers blocked on a channel, woken whenever its state
+/// changes so that they try again. The bounded channels wake their own
+/// senders and receivers, only the selects on them watch.
+#[derive(Clone, Debug, Default)]
+pub struct Watchers(Rc<RefCell<Vec<Waker>>>);
+
+impl Watchers {
+    fn watch(&self, waker: &Waker) {
+        let mut wakers = self.0.borrow_mut();
+        if !wakers.iter().any(|w| w.will_wake(waker)) {
+            wakers.push(waker.clone());
+        }
+    }
+
+    fn notify(&self) {
+        // wake without holding the borrow
+        let wakers = mem::take(&mut *self.0.borrow_mut());
+        wakers.into_iter().for_each(|w| w.wake());
+    }
+}
+
+/// Counts a receiver as waiting on a rendezvous channel as long as it lives
+struct WaitingReceiver(Rc<Cell<usize>>);
+
+impl WaitingReceiver {
+    fn new(count: &Rc<Cell<usize>>, watchers: &Watchers) -> WaitingReceiver {
+        count.set(count.get() + 1);
+        // a send in select may proceed now
+        watchers.notify();
+        WaitingReceiver(count.clone())
+    }
+}
+
+impl Drop for WaitingReceiver {
+    fn drop(&mut self) {
+        self.0.set(self.0.get() - 1);
+    }
 }
 
 impl Channel {
     pub fn new(cap: usize) -> Channel {
         if cap == 0 {
-            Channel::Rendezvous(Rc::new(RefCell::new(RendezvousState::Empty)))
+            Channel::Rendezvous(
+                Rc::new(RefCell::new(RendezvousState::Empty)),
+                Rc::new(Cell::new(0)),
+                Rc::new(Tickets::default()),
+                Watchers::default(),
+            )
         } else {
             let (s, r) = async_channel::bounded(cap);
-            Channel::Bounded(s, r)
+            Channel::Bounded(s, r, Watchers::default())
+        }
+    }
+
+    fn watchers(&self) -> &Watchers {
+        match self {
+            Channel::Bounded(_, _, w) => w,
+            Channel::Rendezvous(_, _, _, w) => w,
         }
     }
 
     #[inline]
     pub fn len(&self) -> usize {
         match self {
-            Channel::Bounded(s, _) => s.len(),
-            Channel::Rendezvous(_) => 0,
+            Channel::Bounded(s, _, _) => s.len(),
+            Channel::Rendezvous(_, _, _, _) => 0,
         }
     }
 
     #[inline]
     pub fn cap(&self) -> usize {
         match self {
-            Channel::Bounded(s, _) => s.capacity().unwrap(),
-            Channel::Rendezvous(_) => 0,
+            Channel::Bounded(s, _, _) => s.capacity().unwrap(),
+            Channel::Rendezvous(_, _, _, _) => 0,
         }
     }
 
     #[inline]
     pub fn close(&self) {
         match self {
-            Channel::Bounded(s, _) => {
+            Channel::Bounded(s, _, _) => {
                 s.close();
             }
-            Channel::Rendezvous(state) => *state.borrow_mut() = RendezvousState::Closed,
+            Channel::Rendezvous(state, _, _, _) => {
+                let cur_state: &mut RendezvousState = &mut state.borrow_mut();
+                *cur_state = match mem::replace(cur_state, RendezvousState::Closed) {
+                    RendezvousState::Full(v) => RendezvousState::ClosedFull(v),
+                    other @ RendezvousState::ClosedFull(_) => other,
+                    _ => RendezvousState::Closed,
+                };
+            }
         }
+        self.watchers().notify();
     }
 
     pub fn try_send(&self, v: GosValue) -> Result<(), async_channel::TrySendError<GosValue>> {
+        let re = self.try_send_inner(v);
+        if re.is_ok() {
+            self.watchers().notify();
+        }
+        re
+    }
+
+    fn try_send_inner(&self, v: GosValue) -> Result<(), async_channel::TrySendError<GosValue>> {
         match self {
-            Channel::Bounded(s, _) => s.try_send(v),
-            Channel::Rendezvous(state) => {
+            Channel::Bounded(s, _, _) => s.try_send(v),
+            Channel::Rendezvous(state, _, tickets, _) => {
                 let state_ref = state.borrow();
                 let s: &RendezvousState = &state_ref;
                 match s {
                     RendezvousState::Empty => {
                         drop(state_ref);
                         *state.borrow_mut() = RendezvousState::Full(v);
+                        tickets.sent.set(tickets.sent.get() + 1);
                         Ok(())
                     }
                     RendezvousState::Full(_) => Err(async_channel::TrySendError::Full(v)),
-                    RendezvousState::Closed => Err(async_channel::TrySendError::Closed(v)),
+                    RendezvousState::ClosedFull(_) | RendezvousState::Closed => {
+                        Err(async_channel::TrySendError::Closed(v))
+                    }
                 }
             }
         }
     }
 
     pub fn try_recv(&self) -> Result<GosValue, async_channel::TryRecvError> {
+        let re = self.try_recv_inner();
+        if re.is_ok() {
+            self.watchers().notify();
+        }
+        re
+    }
+
+    fn try_recv_inner(&self) -> Result<GosValue, async_channel::TryRecvError> {
         match self {
-            Channel::Bounded(_, r) => r.try_recv(),
-            Channel::Rendezvous(state) => {
+            Channel::Bounded(_, r, _) => r.try_recv(),
+            Channel::Rendezvous(state, _, tickets, _) => {
                 let state_ref = state.borrow();
                 let s: &RendezvousState = &state_ref;
                 match s {
                     RendezvousState::Empty => Err(async_channel::TryRecvError::Empty),
-                    RendezvousState::Full(_) => {
+                    RendezvousState::Full(_) | RendezvousState::ClosedFull(_) => {
                         drop(state_ref);
+                        tickets.received.set(tickets.received.get() + 1);
                         let cur_state: &mut RendezvousState = &mut state.borrow_mut();
-                        let full = mem::replace(cur_state, RendezvousState::Empty);
-                        if let RendezvousState::Full(v) = full {
-                            Ok(v)
-                        } else {
-                            unreachable!()
+                        match mem::replace(cur_state, RendezvousState::Empty) {
+                            RendezvousState::Full(v) => Ok(v),
+                            RendezvousState::ClosedFull(v) => {
+                                *cur_state = RendezvousState::Closed;
+                                Ok(v)
+                            }
+                            _ => unreachable!(),
                         }
                     }
                     RendezvousState::Closed => Err(async_channel::TryRecvError::Closed),
@@ -105,32 +209,93 @@ impl Channel {
         }
     }
 
+    /// try_send for a send case of select, which can't proceed on an unbuffered
+    /// channel without a receiver
+    pub fn try_select_send(
+        &self,
+        v: GosValue,
+    ) -> Result<(), async_channel::TrySendError<GosValue>> {
+        match self {
+            Channel::Rendezvous(state, waiting, _, _) => match &*state.borrow() {
+                RendezvousState::Empty if waiting.get() == 0 => {
+                    return Err(async_channel::TrySendError::Full(v))
+                }
+                _ => {}
+            },
+            Channel::Bounded(_, _, _) => {}
+        }
+        self.try_send(v)
+    }
+
+    /// An unbuffered send completes when the value with its ticket is received
+    async fn wait_received(&self, ticket: u64) {
+        if let Channel::Rendezvous(_, _, tickets, watchers) = self {
+            future::poll_fn(|cx| {
+                if tickets.received.get() >= ticket {
+                    Poll::Ready(())
+                } else {
+                    watchers.watch(cx.waker());
+                    Poll::Pending
+                }
+            })
+            .await
+        }
+    }
+
+    fn wait_to_recv(&self) -> Option<WaitingReceiver> {
+        match self {
+            Channel::Rendezvous(_, waiting, _, watchers) => {
+                Some(WaitingReceiver::new(waiting, watchers))
+            }
+            Channel::Bounded(_, _, _) => None,
+        }
+    }
+
     pub async fn send(&self, v: &GosValue) -> RuntimeResult<()> {
-        loop {
-            match self.try_send(v.clone()) {
-                Ok(()) => return Ok(()),
-                Err(e) => match e {
-                    async_channel::TrySendError::Full(_) => {
-                        future::yield_now().await;
+        match self {
+            Channel::Bounded(s, _, watchers) => {
+                s.send(v.clone())
+                    .await
+                    .map_err(|_| "channel closed!".to_owned())?;
+                watchers.notify();
+                Ok(())
+            }
+            Channel::Rendezvous(_, _, tickets, watchers) => {
+                let ticket = future::poll_fn(|cx| match self.try_send(v.clone()) {
+                    Ok(()) => Poll::Ready(Ok(tickets.sent.get())),
+                    Err(async_channel::TrySendError::Full(_)) => {
+                        watchers.watch(cx.waker());
+                        Poll::Pending
                     }
-                    async_channel::TrySendError::Closed(_) => {
-                        return Err("channel closed!".to_owned());
+                    Err(async_channel::TrySendError::Closed(_)) => {
+                        Poll::Ready(Err("channel closed!".to_owned()))
                     }
-                },
+                })
+                .await?;
+                self.wait_received(ticket).await;
+                Ok(())
             }
         }
     }
 
     pub async fn recv(&self) -> Option<GosValue> {
-        loop {
-            match self.try_recv() {
-                Ok(v) => return Some(v),
-                Err(e) => match e {
-                    async_channel::TryRecvError::Empty => {
-                        future::yield_now().await;
+        match self {
+            Channel::Bounded(_, r, watchers) => {
+                let v = r.recv().await.ok()?;
+                watchers.notify();
+                Some(v)
+            }
+            Channel::Rendezvous(_, _, _, watchers) => {
+                let _waiting = self.wait_to_recv();
+                future::poll_fn(|cx| match self.try_recv() {
+                    Ok(v) => Poll::Ready(Some(v)),
+                    Err(async_channel::TryRecvError::Empty) => {
+                        watchers.watch(cx.waker());
+                        Poll::Pending
                     }
-                    async_channel::TryRecvError::Closed => return None,
-                },
+                    Err(async_channel::TryRecvError::Closed) => Poll::Ready(None),
+                })
+                .await
             }
         }
     }
@@ -154,42 +319,153 @@ impl Selector {
         }
     }
 
+    /// Waits for one of the cases to proceed, trying them in a random order.
+    /// A select with no case ready parks until one of its channels changes.
     pub async fn select(&self) -> RuntimeResult<(usize, Option<GosValue>)> {
+        let mut waiting = vec![];
+        future::poll_fn(|cx| {
+            if let Some(re) = self.try_select() {
+                return Poll::Ready(re);
+            }
+            if self.default_offset.is_some() {
+                return Poll::Ready(Ok((self.comms.len(), None)));
+            }
+            if waiting.is_empty() {
+                // wait on the unbuffered channels, for the senders in other selects
+                waiting = self
+                    .comms
+                    .iter()
+                    .filter_map(|c| match c {
+                        SelectComm::Recv(c, _, _) => c.as_channel()?.chan.wait_to_recv(),
+                        SelectComm::Send(_, _, _) => None,
+                    })
+                    .collect();
+            }
+            for c in self.comms.iter() {
+                let (SelectComm::Send(c, _, _) | SelectComm::Recv(c, _, _)) = c;
+                if let Some(chan) = c.as_channel() {
+                    chan.chan.watchers().watch(cx.waker());
+                }
+            }
+            Poll::Pending
+        })
+        .await
+    }
+
+    /// Tries the cases once in a random order, None if none can proceed
+    fn try_select(&self) -> Option<RuntimeResult<(usize, Option<GosValue>)>> {
         let count = self.comms.len();
-        let mut rng = rand::thread_rng();
-        loop {
-            for (i, entry) in self
-                .comms
-                .iter()
-                .enumerate()
-                .choose_multiple(&mut rng, count)
-            {
-                match entry {
-                    SelectComm::Send(c, val, _) => {
-                        match c.as_some_channel()?.chan.try_send(val.clone()) {
-                            Ok(_) => return Ok((i, None)),
-                            Err(e) => match e {
-                                async_channel::TrySendError::Full(_) => {}
-                                async_channel::TrySendError::Closed(_) => {
-                                    return Err("channel closed!".to_owned());
-                                }
-                            },
-                        }
-                    }
-                    SelectComm::Recv(c, _, _) => match c.as_some_channel()?.chan.try_recv() {
-                        Ok(v) => return Ok((i, Some(v))),
+        let order = rng::with_rng(|rng| self.comms.iter().enumerate().choose_multiple(rng, count));
+        for (i, entry) in order {
+            match entry {
+                // communication on nil channels can never proceed
+                SelectComm::Send(c, val, _) => match c.as_channel() {
+                    Some(chan) => match chan.chan.try_select_send(val.clone()) {
+                        Ok(_) => return Some(Ok((i, None))),
+                        Err(e) => match e {
+                            async_channel::TrySendError::Full(_) => {}
+                            async_channel::TrySendError::Closed(_) => {
+                                return Some(Err("channel closed!".to_owned()));
+                            }
+                        },
+                    },
+                    None => {}
+                },
+                SelectComm::Recv(c, _, _) => match c.as_channel() {
+                    Some(chan) => match chan.chan.try_recv() {
+                        Ok(v) => return Some(Ok((i, Some(v)))),
                         Err(e) => match e {
                             async_channel::TryRecvError::Empty => {}
-                            async_channel::TryRecvError::Closed => return Ok((i, None)),
+                            async_channel::TryRecvError::Closed => return Some(Ok((i, None))),
                         },
                     },
-                }
+                    None => {}
+                },
             }
+        }
+        None
+    }
+}
 
-            if let Some(_) = self.default_offset {
-                return Ok((self.comms.len(), None));
-            }
-            future::yield_now().await;
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::future::Future;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::task::{Context, Wake};
+
+    struct CountingWaker(AtomicUsize);
+
+    impl Wake for CountingWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn test_select_parks() {
+        let new_chan = |cap| GosValue::new_channel(ChannelObj::new(cap, GosValue::new_int(0)));
+        let (a, b) = (new_chan(1), new_chan(0));
+        let selector = Selector::new(
+            vec![
+                SelectComm::Recv(a.clone(), ValueType::Int, 0),
+                SelectComm::Recv(b.clone(), ValueType::Int, 0),
+            ],
+            None,
+        );
+        let counter = Arc::new(CountingWaker(AtomicUsize::new(0)));
+        let waker = Waker::from(counter.clone());
+        let mut cx = Context::from_waker(&waker);
+        let mut fut = Box::pin(selector.select());
+
+        // nothing to receive, it waits without asking to be polled again
+        assert!(fut.as_mut().poll(&mut cx).is_pending());
+        assert!(fut.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(counter.0.load(Ordering::SeqCst), 0);
+
+        let chan = &a.as_channel().unwrap().chan;
+        chan.try_send(GosValue::new_int(7)).unwrap();
+        assert_eq!(counter.0.load(Ordering::SeqCst), 1);
+        match fut.as_mut().poll(&mut cx) {
+            Poll::Ready(Ok((0, Some(v)))) => assert_eq!(*v.as_int(), 7),
+            _ => panic!("select should receive from the first channel"),
+        }
+    }
+
+    #[test]
+    fn test_send_recv_park() {
+        let counter = Arc::new(CountingWaker(AtomicUsize::new(0)));
+        let waker = Waker::from(counter.clone());
+        let mut cx = Context::from_waker(&waker);
+        let woken = || counter.0.load(Ordering::SeqCst);
+
+        // an unbuffered send waits for the value to be received
+        let chan = Channel::new(0);
+        let val = GosValue::new_int(7);
+        let mut send = Box::pin(chan.send(&val));
+        assert!(send.as_mut().poll(&mut cx).is_pending());
+        assert!(send.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(woken(), 0);
+        assert_eq!(*chan.try_recv().unwrap().as_int(), 7);
+        assert_eq!(woken(), 1);
+        assert!(matches!(send.as_mut().poll(&mut cx), Poll::Ready(Ok(()))));
+
+        let mut recv = Box::pin(chan.recv());
+        assert!(recv.as_mut().poll(&mut cx).is_pending());
+        chan.close();
+        assert_eq!(woken(), 2);
+        assert!(matches!(recv.as_mut().poll(&mut cx), Poll::Ready(None)));
+
+        let chan = Channel::new(1);
+        let mut recv = Box::pin(chan.recv());
+        assert!(recv.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(woken(), 2);
+        chan.try_send(GosValue::new_int(8)).unwrap();
+        assert_eq!(woken(), 3);
+        match recv.as_mut().poll(&mut cx) {
+            Poll::Ready(Some(v)) => assert_eq!(*v.as_int(), 8),
+            _ => panic!("recv should get the value sent"),
         }
     }
 }
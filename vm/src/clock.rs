@@ -0,0 +1,97 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! The clocks of the VM, the ones of the OS on most targets.
+//!
+//! wasm32-unknown-unknown has no clock and can't block the thread, so with
+//! the `wasm` feature the time is virtual, like on the Go playground: it
+//! starts at the time set with `set_unix_time`, the UNIX epoch if not set,
+//! and only moves forward when the VM sleeps until a timer is due. Sleeping
+//! returns right away and time budgets never run out.
+
+#[cfg(not(feature = "wasm"))]
+use std::time::Duration;
+#[cfg(not(feature = "wasm"))]
+pub use std::time::Instant;
+
+/// The time since the UNIX epoch
+#[cfg(not(feature = "wasm"))]
+pub fn unix_now() -> Duration {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO)
+}
+
+/// Blocks the thread for `d`
+#[cfg(not(feature = "wasm"))]
+pub fn sleep(d: Duration) {
+    std::thread::sleep(d)
+}
+
+#[cfg(feature = "wasm")]
+pub use virtual_clock::*;
+
+#[cfg(feature = "wasm")]
+mod virtual_clock {
+    use std::cell::Cell;
+    use std::ops::{Add, Sub};
+    use std::time::Duration;
+
+    thread_local! {
+        // the time since the UNIX epoch
+        static NOW: Cell<Duration> = Cell::new(Duration::ZERO);
+    }
+
+    /// A point of the virtual time, with the API of `std::time::Instant`
+    /// the VM uses
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    pub struct Instant(Duration);
+
+    impl Instant {
+        pub fn now() -> Instant {
+            Instant(unix_now())
+        }
+
+        pub fn duration_since(&self, earlier: Instant) -> Duration {
+            self.0.saturating_sub(earlier.0)
+        }
+
+        pub fn elapsed(&self) -> Duration {
+            Instant::now().duration_since(*self)
+        }
+    }
+
+    impl Add<Duration> for Instant {
+        type Output = Instant;
+
+        fn add(self, d: Duration) -> Instant {
+            Instant(self.0 + d)
+        }
+    }
+
+    impl Sub<Instant> for Instant {
+        type Output = Duration;
+
+        fn sub(self, earlier: Instant) -> Duration {
+            self.duration_since(earlier)
+        }
+    }
+
+    /// Sets the virtual time, the embedder usually starts it at the time of
+    /// the host, like `Date.now()` in a browser. It never goes backwards.
+    pub fn set_unix_time(since_epoch: Duration) {
+        NOW.with(|now| now.set(now.get().max(since_epoch)))
+    }
+
+    /// The time since the UNIX epoch
+    pub fn unix_now() -> Duration {
+        NOW.with(|now| now.get())
+    }
+
+    /// Moves the virtual time forward by `d`
+    pub fn sleep(d: Duration) {
+        NOW.with(|now| now.set(now.get() + d))
+    }
+}
@@ -0,0 +1,318 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Handlers of the opcodes that only work on the stack and the pc of the
+//! current frame: they don't call, return, yield or allocate goroutines, and
+//! make up most of the executed instructions. `main_loop` hands the first one
+//! of a sequence of them to `run`, which executes the rest back to back in a
+//! loop of its own. A handler can fail with a runtime error, which
+//! `main_loop` turns into a Go panic.
+
+use super::gc::GcoVec;
+use super::instruction::{Instruction, Opcode};
+use super::objects::VMObjects;
+use super::stack::Stack;
+use super::value::{GosValue, RuntimeResult, ValueType};
+use super::vm::{check_comparable, check_divisor, check_hashable, load_index};
+
+/// The state of the current frame a handler can read, or change for jumps
+pub(crate) struct Regs<'a> {
+    pub pc: &'a mut usize,
+    pub stack_base: usize,
+    pub consts: &'a [GosValue],
+    pub code: &'a [Instruction],
+    pub objs: &'a VMObjects,
+    pub gcv: &'a GcoVec,
+}
+
+/// The return type of a handler, which may or may not fail
+trait Outcome {
+    fn into_result(self) -> RuntimeResult<()>;
+}
+
+impl Outcome for () {
+    #[inline(always)]
+    fn into_result(self) -> RuntimeResult<()> {
+        Ok(())
+    }
+}
+
+impl Outcome for RuntimeResult<()> {
+    #[inline(always)]
+    fn into_result(self) -> RuntimeResult<()> {
+        self
+    }
+}
+
+macro_rules! handlers {
+    ($($op:ident($stack:ident, $inst:ident, $regs:ident) $(-> $ret:ty)? $body:block)*) => {
+        $(
+            #[allow(non_snake_case, unused_variables)]
+            #[inline(always)]
+            fn $op($stack: &mut Stack, $inst: Instruction, $regs: &mut Regs) $(-> $ret)? $body
+        )*
+
+        /// Runs `inst`, which must have a handler, and the instructions
+        /// after it as long as they have one, `limit` instructions at most.
+        /// Returns the number of instructions run, and the runtime error the
+        /// last one failed with, the pc is past it then.
+        #[inline(always)]
+        pub(crate) fn run(
+            stack: &mut Stack,
+            inst: Instruction,
+            regs: &mut Regs,
+            limit: usize,
+        ) -> (usize, Option<String>) {
+            let mut inst = inst;
+            let mut n = 1;
+            loop {
+                let result = match inst.op() {
+                    $(Opcode::$op => $op(stack, inst, regs).into_result(),)*
+                    _ => unreachable!(),
+                };
+                if let Err(e) = result {
+                    return (n, Some(e));
+                }
+                if n == limit {
+                    return (n, None);
+                }
+                let next = regs.code[*regs.pc];
+                if !matches!(next.op(), $(Opcode::$op)|*) {
+                    return (n, None);
+                }
+                *regs.pc += 1;
+                n += 1;
+                inst = next;
+            }
+        }
+    };
+}
+
+/// Fails if the op-assign of a store divides an integer by zero, e.g.
+/// `x /= 0`, the store is skipped then
+#[inline(always)]
+fn check_op_assign(stack: &Stack, rhs_index: i32, t: ValueType) -> RuntimeResult<()> {
+    match rhs_index >= 0 {
+        true => check_divisor(stack, Instruction::index2code(rhs_index), t),
+        false => Ok(()),
+    }
+}
+
+handlers! {
+    PUSH_CONST(stack, inst, regs) {
+        stack.push(regs.consts[inst.imm() as usize].clone());
+    }
+    PUSH_NIL(stack, inst, regs) {
+        stack.push_nil(inst.t0());
+    }
+    PUSH_FALSE(stack, inst, regs) {
+        stack.push_bool(false);
+    }
+    PUSH_TRUE(stack, inst, regs) {
+        stack.push_bool(true);
+    }
+    PUSH_IMM(stack, inst, regs) {
+        stack.push_int32_as(inst.imm(), inst.t0());
+    }
+    POP(stack, inst, regs) {
+        // this looks weired because it used to require ValueType for every pop
+        // and we may change it back in the future.
+        match inst.imm() {
+            1 => {
+                stack.pop_value();
+            }
+            2 => {
+                stack.pop_value();
+                stack.pop_value();
+            }
+            3 => {
+                stack.pop_value();
+                stack.pop_value();
+                stack.pop_value();
+            }
+            _ => unreachable!(),
+        }
+    }
+    PUSH_ZERO_VALUE(stack, inst, regs) {
+        let meta = regs.consts[inst.imm() as usize].as_metadata();
+        stack.push(meta.zero(&regs.objs.metas, regs.gcv));
+    }
+    LOAD_LOCAL(stack, inst, regs) {
+        stack.push_from_index(Stack::offset(regs.stack_base, inst.imm()));
+    }
+    STORE_LOCAL(stack, inst, regs) -> RuntimeResult<()> {
+        let (rhs_index, index) = inst.imm824();
+        check_op_assign(stack, rhs_index, inst.t0())?;
+        let s_index = Stack::offset(regs.stack_base, index);
+        stack.store_local(s_index, rhs_index, inst.t0(), regs.gcv);
+        Ok(())
+    }
+    STORE_LOCAL_POP(stack, inst, regs) -> RuntimeResult<()> {
+        let next = regs.code[*regs.pc];
+        *regs.pc += 1;
+        let (rhs_index, index) = inst.imm824();
+        check_op_assign(stack, rhs_index, inst.t0())?;
+        let s_index = Stack::offset(regs.stack_base, index);
+        stack.store_local(s_index, rhs_index, inst.t0(), regs.gcv);
+        for _ in 0..next.imm() {
+            stack.pop_value();
+        }
+        Ok(())
+    }
+    LOAD_INDEX(stack, inst, regs) -> RuntimeResult<()> {
+        let ind = stack.pop_value();
+        let val = &stack.pop_value();
+        load_index(stack, val, &ind, inst, regs.consts, regs.objs, regs.gcv)
+    }
+    LOAD_INDEX_IMM(stack, inst, regs) -> RuntimeResult<()> {
+        let val = &stack.pop_value();
+        let index = inst.imm() as usize;
+        if inst.t2_as_index() == 0 {
+            val.load_index_int(index, regs.gcv).map(|v| stack.push(v))
+        } else {
+            stack.push_index_comma_ok(val, &GosValue::new_int(index as isize), regs.gcv)
+        }
+    }
+    LOAD_LOCAL_INDEX(stack, inst, regs) -> RuntimeResult<()> {
+        let next = regs.code[*regs.pc];
+        *regs.pc += 1;
+        let ind = stack.get(Stack::offset(regs.stack_base, inst.imm())).clone();
+        let val = &stack.pop_value();
+        load_index(stack, val, &ind, next, regs.consts, regs.objs, regs.gcv)
+    }
+    STORE_INDEX(stack, inst, regs) -> RuntimeResult<()> {
+        let (rhs_index, index) = inst.imm824();
+        check_op_assign(stack, rhs_index, inst.t0())?;
+        let s_index = Stack::offset(stack.len(), index);
+        let key = stack.get(s_index + 1);
+        let target = &stack.get(s_index);
+        if target.typ() == ValueType::Map {
+            check_hashable(key, stack, regs.objs)?;
+        }
+        stack.store_index(target, key, rhs_index, inst.t0(), regs.gcv)
+    }
+    STORE_INDEX_IMM(stack, inst, regs) -> RuntimeResult<()> {
+        // the only place we can store the immediate index is t2
+        let (rhs_index, imm) = inst.imm824();
+        check_op_assign(stack, rhs_index, inst.t0())?;
+        let s_index = Stack::offset(stack.len(), inst.t2_as_index());
+        let target = &stack.get(s_index);
+        stack.store_index_int(target, imm, rhs_index, inst.t0(), regs.gcv)
+    }
+    LOAD_INDEX_UNCHECKED(stack, inst, regs) -> RuntimeResult<()> {
+        let ind = stack.pop_value();
+        let val = &stack.pop_value();
+        val.load_index_unchecked(&ind, regs.gcv).map(|v| stack.push(v))
+    }
+    STORE_INDEX_UNCHECKED(stack, inst, regs) -> RuntimeResult<()> {
+        let (rhs_index, index) = inst.imm824();
+        check_op_assign(stack, rhs_index, inst.t0())?;
+        let s_index = Stack::offset(stack.len(), index);
+        let key = stack.get(s_index + 1);
+        let target = &stack.get(s_index);
+        stack.store_index_unchecked(target, key, rhs_index, inst.t0(), regs.gcv)
+    }
+    ADD(stack, inst, regs) {
+        stack.add(inst.t0());
+    }
+    SUB(stack, inst, regs) {
+        stack.sub(inst.t0());
+    }
+    MUL(stack, inst, regs) {
+        stack.mul(inst.t0());
+    }
+    QUO(stack, inst, regs) -> RuntimeResult<()> {
+        check_divisor(stack, Opcode::QUO, inst.t0())?;
+        stack.quo(inst.t0());
+        Ok(())
+    }
+    REM(stack, inst, regs) -> RuntimeResult<()> {
+        check_divisor(stack, Opcode::REM, inst.t0())?;
+        stack.rem(inst.t0());
+        Ok(())
+    }
+    AND(stack, inst, regs) {
+        stack.and(inst.t0());
+    }
+    OR(stack, inst, regs) {
+        stack.or(inst.t0());
+    }
+    XOR(stack, inst, regs) {
+        stack.xor(inst.t0());
+    }
+    AND_NOT(stack, inst, regs) {
+        stack.and_not(inst.t0());
+    }
+    SHL(stack, inst, regs) -> RuntimeResult<()> {
+        stack.shl(inst.t0(), inst.t1())
+    }
+    SHR(stack, inst, regs) -> RuntimeResult<()> {
+        stack.shr(inst.t0(), inst.t1())
+    }
+    UNARY_ADD(stack, inst, regs) {}
+    UNARY_SUB(stack, inst, regs) {
+        stack.unary_negate(inst.t0());
+    }
+    UNARY_XOR(stack, inst, regs) {
+        stack.unary_xor(inst.t0());
+    }
+    NOT(stack, inst, regs) {
+        stack.logical_not(inst.t0());
+    }
+    EQL(stack, inst, regs) -> RuntimeResult<()> {
+        check_comparable(stack, inst.t0(), regs.objs)?;
+        stack.compare_eql(inst.t0(), inst.t1());
+        Ok(())
+    }
+    NEQ(stack, inst, regs) -> RuntimeResult<()> {
+        check_comparable(stack, inst.t0(), regs.objs)?;
+        stack.compare_neq(inst.t0());
+        Ok(())
+    }
+    LSS(stack, inst, regs) {
+        stack.compare_lss(inst.t0());
+    }
+    GTR(stack, inst, regs) {
+        stack.compare_gtr(inst.t0());
+    }
+    LEQ(stack, inst, regs) {
+        stack.compare_leq(inst.t0());
+    }
+    GEQ(stack, inst, regs) {
+        stack.compare_geq(inst.t0());
+    }
+    PUSH_IMM_ADD(stack, inst, regs) {
+        let next = regs.code[*regs.pc];
+        *regs.pc += 1;
+        stack.push_int32_as(inst.imm(), inst.t0());
+        stack.add(next.t0());
+    }
+    JUMP(stack, inst, regs) {
+        *regs.pc = Stack::offset(*regs.pc, inst.imm());
+    }
+    JUMP_IF(stack, inst, regs) {
+        if stack.pop_bool() {
+            *regs.pc = Stack::offset(*regs.pc, inst.imm());
+        }
+    }
+    JUMP_IF_NOT(stack, inst, regs) {
+        if !stack.pop_bool() {
+            *regs.pc = Stack::offset(*regs.pc, inst.imm());
+        }
+    }
+    SHORT_CIRCUIT_OR(stack, inst, regs) {
+        if *stack.get_data(stack.len() - 1).as_bool() {
+            *regs.pc = Stack::offset(*regs.pc, inst.imm());
+        } else {
+            stack.pop_discard_copyable();
+        }
+    }
+    SHORT_CIRCUIT_AND(stack, inst, regs) {
+        if !*stack.get_data(stack.len() - 1).as_bool() {
+            *regs.pc = Stack::offset(*regs.pc, inst.imm());
+        } else {
+            stack.pop_discard_copyable();
+        }
+    }
+}
@@ -3,10 +3,15 @@
 // license that can be found in the LICENSE file.
 
 use super::gc::GcoVec;
-use super::objects::VMObjects;
+use super::instruction::ValueType;
+use super::metadata::Meta;
+use super::objects::{ArrayObj, StrUtil, UnderlyingFfi, VMObjects};
 use super::stack::Stack;
-use super::value::{GosValue, RuntimeResult};
+use super::timer::Timers;
+use super::value::{Elem8, GosElem, GosValue, InterfaceObj, RuntimeResult};
+use super::vm::StackFrame;
 use std::any::Any;
+use std::cell::{Cell, OnceCell};
 use std::collections::HashMap;
 use std::future::Future;
 use std::pin::Pin;
@@ -23,6 +28,304 @@ pub struct FfiCallCtx<'a> {
     pub stack: &'a mut Stack,
     pub gcv: &'a GcoVec,
     pub statics: &'a dyn FfiStatics,
+    pub timers: &'a Timers,
+    // the code the program exits with, set by exit
+    pub(crate) exit_code: &'a Cell<Option<i32>>,
+    pub(crate) backtrace: &'a dyn Fn() -> Vec<StackFrame>,
+    pub(crate) callbacks: &'a dyn ClosureCaller,
+}
+
+/// Runs the Go closures FFI functions call back
+pub(crate) trait ClosureCaller {
+    fn call(&self, f: &GosValue, args: Vec<GosValue>) -> RuntimeResult<Vec<GosValue>>;
+
+    fn spawn(&self, f: &GosValue, args: Vec<GosValue>) -> RuntimeResult<()>;
+}
+
+impl<'a> FfiCallCtx<'a> {
+    /// Ends the program with code once the call returns, like os.Exit: the
+    /// goroutines are stopped where they are and deferred calls are not run
+    pub fn exit(&self, code: i32) {
+        self.exit_code.set(Some(code));
+    }
+
+    /// Returns the calls of the goroutine making this call, innermost
+    /// first, like runtime/debug.Stack
+    pub fn stack_trace(&self) -> Vec<StackFrame> {
+        (self.backtrace)()
+    }
+
+    /// Calls the Go function f with args and returns its results, once it
+    /// returns, the other goroutines run meanwhile. The arguments of a
+    /// variadic function end with a slice. A panic of f is an Err with its
+    /// message, returning it with `?` panics again in the caller.
+    pub fn call_closure(&self, f: &GosValue, args: Vec<GosValue>) -> RuntimeResult<Vec<GosValue>> {
+        self.callbacks.call(f, args)
+    }
+
+    /// Starts a goroutine calling the Go function f with args, like `go f(args)`
+    pub fn spawn_closure(&self, f: &GosValue, args: Vec<GosValue>) -> RuntimeResult<()> {
+        self.callbacks.spawn(f, args)
+    }
+
+    /// Returns an `error` whose Error() is msg, Goscript code can handle it
+    /// like any other error, e.g. with errors.Is and errors.As
+    pub fn new_error(&self, msg: &str) -> GosValue {
+        FfiError::new_error(msg, self.vm_objs.s_meta.error)
+    }
+
+    /// Returns a nil `error` if the result is Ok, or an `error` with the
+    /// message of Err
+    pub fn result_to_error<T, E: ToString>(&self, result: &Result<T, E>) -> GosValue {
+        match result {
+            Ok(_) => GosValue::new_nil(ValueType::Interface),
+            Err(e) => self.new_error(&e.to_string()),
+        }
+    }
+}
+
+/// Converts a Goscript argument to the Rust type of a parameter of a typed
+/// FFI function, see `ffi_impl`. `&str` and `&[u8]` parameters are
+/// converted to `String` and `Vec<u8>` and passed by reference.
+pub trait FromGos: Sized {
+    fn from_gos(v: &GosValue) -> RuntimeResult<Self>;
+}
+
+/// Converts a result of a typed FFI function to Goscript, the Rust type
+/// decides the Go type: `isize` is `int`, `i64` is `int64`, `String` is
+/// `string`, `GoError` is `error` and so on.
+pub trait IntoGos {
+    fn into_gos(self, ctx: &FfiCallCtx) -> GosValue;
+}
+
+fn type_mismatch<T>(expected: &str, v: &GosValue) -> RuntimeResult<T> {
+    Err(format!("expected {}, found {:?}", expected, v.typ()))
+}
+
+impl FromGos for GosValue {
+    fn from_gos(v: &GosValue) -> RuntimeResult<GosValue> {
+        Ok(v.clone())
+    }
+}
+
+impl IntoGos for GosValue {
+    fn into_gos(self, _ctx: &FfiCallCtx) -> GosValue {
+        self
+    }
+}
+
+impl FromGos for bool {
+    fn from_gos(v: &GosValue) -> RuntimeResult<bool> {
+        match v.typ() {
+            ValueType::Bool => Ok(*v.as_bool()),
+            _ => type_mismatch("bool", v),
+        }
+    }
+}
+
+impl IntoGos for bool {
+    fn into_gos(self, _ctx: &FfiCallCtx) -> GosValue {
+        GosValue::new_bool(self)
+    }
+}
+
+/// Reads an integer of any size, so that an `int` can be passed to an
+/// `i64` parameter as long as it fits
+fn int_from_gos(v: &GosValue) -> Option<i128> {
+    Some(match v.typ() {
+        ValueType::Int => *v.as_int() as i128,
+        ValueType::Int8 => *v.as_int8() as i128,
+        ValueType::Int16 => *v.as_int16() as i128,
+        ValueType::Int32 => *v.as_int32() as i128,
+        ValueType::Int64 => *v.as_int64() as i128,
+        ValueType::Uint => *v.as_uint() as i128,
+        ValueType::UintPtr => *v.as_uint_ptr() as i128,
+        ValueType::Uint8 => *v.as_uint8() as i128,
+        ValueType::Uint16 => *v.as_uint16() as i128,
+        ValueType::Uint32 => *v.as_uint32() as i128,
+        ValueType::Uint64 => *v.as_uint64() as i128,
+        _ => return None,
+    })
+}
+
+macro_rules! int_conversions {
+    ($($t:ty => $new:ident),*) => {
+        $(
+            impl FromGos for $t {
+                fn from_gos(v: &GosValue) -> RuntimeResult<$t> {
+                    match int_from_gos(v) {
+                        Some(i) => <$t>::try_from(i)
+                            .map_err(|_| format!("{} out of range of {}", i, stringify!($t))),
+                        None => type_mismatch("integer", v),
+                    }
+                }
+            }
+
+            impl IntoGos for $t {
+                fn into_gos(self, _ctx: &FfiCallCtx) -> GosValue {
+                    GosValue::$new(self)
+                }
+            }
+        )*
+    };
+}
+
+int_conversions!(
+    isize => new_int, i8 => new_int8, i16 => new_int16, i32 => new_int32, i64 => new_int64,
+    usize => new_uint, u8 => new_uint8, u16 => new_uint16, u32 => new_uint32, u64 => new_uint64
+);
+
+impl FromGos for f64 {
+    fn from_gos(v: &GosValue) -> RuntimeResult<f64> {
+        match v.typ() {
+            ValueType::Float64 => Ok(v.as_float64().into_inner()),
+            ValueType::Float32 => Ok(v.as_float32().into_inner() as f64),
+            _ => type_mismatch("float", v),
+        }
+    }
+}
+
+impl IntoGos for f64 {
+    fn into_gos(self, _ctx: &FfiCallCtx) -> GosValue {
+        GosValue::new_float64(self.into())
+    }
+}
+
+impl FromGos for f32 {
+    fn from_gos(v: &GosValue) -> RuntimeResult<f32> {
+        match v.typ() {
+            ValueType::Float32 => Ok(v.as_float32().into_inner()),
+            _ => type_mismatch("float32", v),
+        }
+    }
+}
+
+impl IntoGos for f32 {
+    fn into_gos(self, _ctx: &FfiCallCtx) -> GosValue {
+        GosValue::new_float32(self.into())
+    }
+}
+
+impl FromGos for String {
+    fn from_gos(v: &GosValue) -> RuntimeResult<String> {
+        match v.typ() {
+            ValueType::String => Ok(StrUtil::as_str(v.as_string()).to_owned()),
+            _ => type_mismatch("string", v),
+        }
+    }
+}
+
+impl IntoGos for String {
+    fn into_gos(self, _ctx: &FfiCallCtx) -> GosValue {
+        GosValue::with_str(&self)
+    }
+}
+
+impl IntoGos for &str {
+    fn into_gos(self, _ctx: &FfiCallCtx) -> GosValue {
+        GosValue::with_str(self)
+    }
+}
+
+/// A `[]byte`, copied, nil is empty
+impl FromGos for Vec<u8> {
+    fn from_gos(v: &GosValue) -> RuntimeResult<Vec<u8>> {
+        match (v.typ(), v.t_elem()) {
+            (ValueType::Slice, ValueType::Uint8) => Ok(match v.as_slice::<Elem8>() {
+                Some(s) => unsafe { s.0.as_raw_slice::<u8>() }.to_vec(),
+                None => vec![],
+            }),
+            // an untyped nil
+            (ValueType::Void, _) => Ok(vec![]),
+            _ => type_mismatch("[]byte", v),
+        }
+    }
+}
+
+impl IntoGos for Vec<u8> {
+    fn into_gos(self, _ctx: &FfiCallCtx) -> GosValue {
+        let buf: Vec<Elem8> = unsafe { std::mem::transmute(self) };
+        let arr = GosValue::new_non_gc_array(ArrayObj::with_raw_data(buf), ValueType::Uint8);
+        GosValue::slice_array(arr, 0, -1, ValueType::Uint8).unwrap()
+    }
+}
+
+impl FromGos for Vec<String> {
+    fn from_gos(v: &GosValue) -> RuntimeResult<Vec<String>> {
+        match (v.typ(), v.t_elem()) {
+            (ValueType::Slice, ValueType::String) => match v.as_slice::<GosElem>() {
+                Some(s) => {
+                    s.0.get_vec(ValueType::String)
+                        .iter()
+                        .map(String::from_gos)
+                        .collect()
+                }
+                None => Ok(vec![]),
+            },
+            // an untyped nil
+            (ValueType::Void, _) => Ok(vec![]),
+            _ => type_mismatch("[]string", v),
+        }
+    }
+}
+
+impl IntoGos for Vec<String> {
+    fn into_gos(self, ctx: &FfiCallCtx) -> GosValue {
+        let data = self.iter().map(|s| GosValue::with_str(s)).collect();
+        GosValue::slice_with_data(data, ValueType::String, ctx.gcv)
+    }
+}
+
+/// A Go `error` returned by a typed FFI function, nil or with a message
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GoError(Option<String>);
+
+impl GoError {
+    pub fn nil() -> GoError {
+        GoError(None)
+    }
+
+    pub fn new(msg: &str) -> GoError {
+        GoError(Some(msg.to_owned()))
+    }
+
+    /// nil if the result is Ok, or the message of Err
+    pub fn from_result<T, E: ToString>(result: &Result<T, E>) -> GoError {
+        GoError(result.as_ref().err().map(|e| e.to_string()))
+    }
+
+    pub fn is_nil(&self) -> bool {
+        self.0.is_none()
+    }
+}
+
+impl IntoGos for GoError {
+    fn into_gos(self, ctx: &FfiCallCtx) -> GosValue {
+        match self.0 {
+            Some(msg) => ctx.new_error(&msg),
+            None => GosValue::new_nil(ValueType::Interface),
+        }
+    }
+}
+
+/// Used by the wrappers `ffi_impl` generates for typed FFI functions
+#[doc(hidden)]
+pub fn check_arg_count(ctx: &FfiCallCtx, args: &[GosValue], count: usize) -> RuntimeResult<()> {
+    match args.len() == count {
+        true => Ok(()),
+        false => Err(format!(
+            "{}: expected {} arguments, found {}",
+            ctx.func_name,
+            count,
+            args.len()
+        )),
+    }
+}
+
+/// Used by the wrappers `ffi_impl` generates for typed FFI functions
+#[doc(hidden)]
+pub fn arg_from_gos<T: FromGos>(ctx: &FfiCallCtx, args: &[GosValue], i: usize) -> RuntimeResult<T> {
+    T::from_gos(&args[i]).map_err(|e| format!("{}: argument {}: {}", ctx.func_name, i, e))
 }
 
 /// A FFI Object implemented in Rust for Goscript to call
@@ -32,6 +335,12 @@ pub trait Ffi {
         ctx: &mut FfiCallCtx,
         params: Vec<GosValue>,
     ) -> Pin<Box<dyn Future<Output = RuntimeResult<Vec<GosValue>>> + '_>>;
+
+    /// The message of an object that is an `error`, which is what printing
+    /// it shows
+    fn error_msg(&self) -> Option<&str> {
+        None
+    }
 }
 
 impl std::fmt::Debug for dyn Ffi {
@@ -40,8 +349,105 @@ impl std::fmt::Debug for dyn Ffi {
     }
 }
 
+/// The error created by FfiCallCtx::new_error, and the value of the
+/// panics the vm raises
+pub(crate) struct FfiError(String);
+
+impl FfiError {
+    /// Returns an `error` whose Error() is msg, `error_meta` is the Meta of
+    /// the `error` interface
+    pub(crate) fn new_error(msg: &str, error_meta: Meta) -> GosValue {
+        let ffi = UnderlyingFfi::new(Rc::new(FfiError(msg.to_owned())), error_meta);
+        GosValue::new_interface(InterfaceObj::Ffi(ffi))
+    }
+}
+
+impl Ffi for FfiError {
+    fn call(
+        &self,
+        ctx: &mut FfiCallCtx,
+        _params: Vec<GosValue>,
+    ) -> Pin<Box<dyn Future<Output = RuntimeResult<Vec<GosValue>>> + '_>> {
+        let result = match ctx.func_name {
+            "Error" => Ok(vec![GosValue::with_str(&self.0)]),
+            name => Err(format!("error has no method {}", name)),
+        };
+        Box::pin(async move { result })
+    }
+
+    fn error_msg(&self) -> Option<&str> {
+        Some(&self.0)
+    }
+}
+
+/// The FFI objects a program creates with the `ffi` builtin, see
+/// `ByteCode::ffi_deps`
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct FfiDeps {
+    /// Names of the FFI objects, sorted
+    pub names: Vec<String>,
+    /// Number of `ffi` calls with a name that is not a constant, which can
+    /// only be known when they are run
+    pub dynamic: usize,
+}
+
+/// How a registered FFI object is created
+enum FfiEntry {
+    Proto(Rc<dyn Ffi>),
+    /// created by the function the first time a program asks for it, and
+    /// shared afterwards
+    Lazy(Box<dyn Fn() -> Rc<dyn Ffi>>, OnceCell<Rc<dyn Ffi>>),
+}
+
+impl FfiEntry {
+    fn get(&self) -> Rc<dyn Ffi> {
+        match self {
+            FfiEntry::Proto(proto) => proto.clone(),
+            FfiEntry::Lazy(new, cell) => cell.get_or_init(new).clone(),
+        }
+    }
+}
+
+/// A group of FFI objects registered under a common prefix with
+/// `FfiFactory::register_module`, a program gets the one named `x` in the
+/// module `lib` with `ffi(T, "lib.x")`.
+#[derive(Default)]
+pub struct FfiModule {
+    entries: Vec<(String, FfiEntry)>,
+}
+
+impl FfiModule {
+    pub fn new() -> FfiModule {
+        FfiModule::default()
+    }
+
+    pub fn with(mut self, name: &str, proto: Rc<dyn Ffi>) -> FfiModule {
+        self.entries.push((name.to_owned(), FfiEntry::Proto(proto)));
+        self
+    }
+
+    /// Adds an object that is only created if a program uses it, like a
+    /// connection pool that is expensive to set up
+    pub fn with_lazy<F>(mut self, name: &str, new: F) -> FfiModule
+    where
+        F: Fn() -> Rc<dyn Ffi> + 'static,
+    {
+        let entry = FfiEntry::Lazy(Box::new(new), OnceCell::new());
+        self.entries.push((name.to_owned(), entry));
+        self
+    }
+
+    /// Adds the objects of `sub` with the prefix `name`
+    pub fn with_module(mut self, name: &str, sub: FfiModule) -> FfiModule {
+        let entries = sub.entries.into_iter();
+        self.entries
+            .extend(entries.map(|(n, e)| (format!("{}.{}", name, n), e)));
+        self
+    }
+}
+
 pub struct FfiFactory {
-    registry: HashMap<&'static str, Rc<dyn Ffi>>,
+    registry: HashMap<String, FfiEntry>,
     statics: Box<dyn FfiStatics>,
 }
 
@@ -53,17 +459,82 @@ impl FfiFactory {
         }
     }
 
-    pub fn register(&mut self, name: &'static str, proto: Rc<dyn Ffi>) {
-        assert!(self.registry.insert(name, proto).is_none());
+    /// Panics if the name is taken, see `try_register`
+    pub fn register(&mut self, name: &str, proto: Rc<dyn Ffi>) {
+        if let Err(e) = self.try_register(name, proto) {
+            panic!("{}", e)
+        }
+    }
+
+    pub fn try_register(&mut self, name: &str, proto: Rc<dyn Ffi>) -> RuntimeResult<()> {
+        self.register_module("", FfiModule::new().with(name, proto))
+    }
+
+    /// Registers an object that is created the first time a program asks
+    /// for it, panics if the name is taken
+    pub fn register_lazy<F>(&mut self, name: &str, new: F)
+    where
+        F: Fn() -> Rc<dyn Ffi> + 'static,
+    {
+        if let Err(e) = self.register_module("", FfiModule::new().with_lazy(name, new)) {
+            panic!("{}", e)
+        }
+    }
+
+    /// Registers the objects of the module with the prefix `name`, or with
+    /// their own names if `name` is empty. Nothing is registered if one of
+    /// the names is taken.
+    pub fn register_module(&mut self, name: &str, module: FfiModule) -> RuntimeResult<()> {
+        let entries: Vec<(String, FfiEntry)> = match name.is_empty() {
+            true => module.entries,
+            false => FfiModule::new().with_module(name, module).entries,
+        };
+        let mut names: Vec<&str> = entries.iter().map(|(n, _)| n.as_str()).collect();
+        names.sort_unstable();
+        let dup = names
+            .windows(2)
+            .find(|w| w[0] == w[1])
+            .map(|w| w[0])
+            .or_else(|| {
+                names
+                    .iter()
+                    .find(|n| self.registry.contains_key(**n))
+                    .copied()
+            });
+        if let Some(dup) = dup {
+            return Err(format!("FFI named {} is already registered", dup));
+        }
+        self.registry.extend(entries);
+        Ok(())
+    }
+
+    /// Whether an object with the name is registered, without creating it
+    pub fn contains(&self, name: &str) -> bool {
+        self.registry.contains_key(name)
     }
 
     pub fn create_by_name(&self, name: &str) -> RuntimeResult<Rc<dyn Ffi>> {
         match self.registry.get(name) {
-            Some(proto) => Ok(proto.clone()),
+            Some(entry) => Ok(entry.get()),
             None => Err(format!("FFI named {} not found", name)),
         }
     }
 
+    /// Checks that all the FFI objects in `deps` can be created, so that a
+    /// program doesn't fail halfway because one of them is not registered.
+    pub fn check_deps(&self, deps: &FfiDeps) -> RuntimeResult<()> {
+        let missing: Vec<&str> = deps
+            .names
+            .iter()
+            .filter(|x| !self.contains(x))
+            .map(|x| x.as_str())
+            .collect();
+        match missing.is_empty() {
+            true => Ok(()),
+            false => Err(format!("FFI named {} not found", missing.join(", "))),
+        }
+    }
+
     /// Get a reference to the ffi factory's statics.
     pub fn statics(&self) -> &dyn FfiStatics {
         self.statics.as_ref()
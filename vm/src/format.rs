@@ -0,0 +1,1570 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! A Go compatible value formatter, it implements the printing rules of
+//! Go's fmt package over GosValues and their metadata, so that it can be
+//! shared by the fmt package and any FFI that prints Goscript values.
+
+use super::instruction::ValueType;
+use super::metadata::*;
+use super::objects::*;
+use super::stack::Stack;
+use super::value::*;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+
+const LDIGITS: &[u8; 16] = b"0123456789abcdef";
+const UDIGITS: &[u8; 16] = b"0123456789ABCDEF";
+
+/// The results of the Error() or String() methods of an operand, or of
+/// those of the values nested in it, as the formatter itself cannot call
+/// back into Goscript.
+#[derive(Clone, Debug, Default)]
+pub enum MethodText {
+    #[default]
+    None,
+    Text(String),
+    /// One entry per value returned by `ValueFormatter::elems`
+    Nested(Vec<MethodText>),
+}
+
+static NO_TEXT: MethodText = MethodText::None;
+
+impl MethodText {
+    fn elem(&self, i: usize) -> &MethodText {
+        match self {
+            MethodText::Nested(texts) => texts.get(i).unwrap_or(&NO_TEXT),
+            _ => &NO_TEXT,
+        }
+    }
+}
+
+/// An argument to be formatted.
+/// `val` is the argument boxed in an interface, `text` holds what its
+/// methods and those of its elements returned.
+pub struct Operand {
+    pub val: GosValue,
+    pub text: MethodText,
+}
+
+impl Operand {
+    pub fn new(val: GosValue, text: MethodText) -> Operand {
+        Operand {
+            val: val,
+            text: text,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct Flags {
+    sharp: bool,
+    zero: bool,
+    plus: bool,
+    minus: bool,
+    space: bool,
+    sharp_v: bool,
+    plus_v: bool,
+    wid: Option<usize>,
+    prec: Option<usize>,
+}
+
+pub struct ValueFormatter<'a> {
+    objs: &'a VMObjects,
+    stack: &'a Stack,
+    buf: String,
+    flags: Flags,
+    reordered: bool,
+    good_arg_num: bool,
+    wrapped: Option<usize>,
+}
+
+impl<'a> ValueFormatter<'a> {
+    pub fn new(objs: &'a VMObjects, stack: &'a Stack) -> ValueFormatter<'a> {
+        ValueFormatter {
+            objs: objs,
+            stack: stack,
+            buf: String::new(),
+            flags: Flags::default(),
+            reordered: false,
+            good_arg_num: true,
+            wrapped: None,
+        }
+    }
+
+    /// Formats using the default formats, like Go's fmt.Sprint.
+    /// Spaces are added between operands when neither is a string.
+    pub fn sprint(mut self, args: &[Operand]) -> String {
+        let mut prev_string = false;
+        for (i, arg) in args.iter().enumerate() {
+            let is_string = match Self::iface_inner(&arg.val) {
+                Some((v, _)) => v.typ() == ValueType::String,
+                None => false,
+            };
+            if i > 0 && !is_string && !prev_string {
+                self.buf.push(' ');
+            }
+            self.print_arg(arg, 'v');
+            prev_string = is_string;
+        }
+        self.buf
+    }
+
+    /// Formats using the default formats, like Go's fmt.Sprintln.
+    /// Spaces are always added between operands and a newline is appended.
+    pub fn sprintln(mut self, args: &[Operand]) -> String {
+        for (i, arg) in args.iter().enumerate() {
+            if i > 0 {
+                self.buf.push(' ');
+            }
+            self.print_arg(arg, 'v');
+        }
+        self.buf.push('\n');
+        self.buf
+    }
+
+    /// Formats according to a format specifier, like Go's fmt.Sprintf.
+    /// It also returns the index of the operand consumed by a %w verb,
+    /// which is what fmt.Errorf wraps.
+    pub fn sprintf(mut self, format: &str, args: &[Operand]) -> (String, Option<usize>) {
+        let fmt: Vec<char> = format.chars().collect();
+        let end = fmt.len();
+        let mut arg_num = 0;
+        let mut i = 0;
+        'outer: while i < end {
+            self.good_arg_num = true;
+            let lasti = i;
+            while i < end && fmt[i] != '%' {
+                i += 1;
+            }
+            self.buf.extend(&fmt[lasti..i]);
+            if i >= end {
+                break;
+            }
+            // skip the '%'
+            i += 1;
+
+            self.flags = Flags::default();
+            while i < end {
+                match fmt[i] {
+                    '#' => self.flags.sharp = true,
+                    '0' => self.flags.zero = !self.flags.minus,
+                    '+' => self.flags.plus = true,
+                    '-' => {
+                        self.flags.minus = true;
+                        self.flags.zero = false;
+                    }
+                    ' ' => self.flags.space = true,
+                    c => {
+                        // fast path for the common case of a simple verb
+                        if c.is_ascii_lowercase() && arg_num < args.len() {
+                            let verb = self.prepare_verb(c);
+                            self.print_verb_arg(&args[arg_num], arg_num, verb);
+                            arg_num += 1;
+                            i += 1;
+                            continue 'outer;
+                        }
+                        break;
+                    }
+                }
+                i += 1;
+            }
+
+            // argument index
+            let (n, ni, mut after_index) = self.arg_number(arg_num, &fmt, i, args.len());
+            arg_num = n;
+            i = ni;
+
+            // width
+            if i < end && fmt[i] == '*' {
+                i += 1;
+                let (wid, present, n) = Self::int_from_arg(args, arg_num);
+                arg_num = n;
+                if !present {
+                    self.buf.push_str("%!(BADWIDTH)");
+                } else if wid < 0 {
+                    self.flags.wid = Some(-wid as usize);
+                    self.flags.minus = true;
+                    self.flags.zero = false;
+                } else {
+                    self.flags.wid = Some(wid as usize);
+                }
+                after_index = false;
+            } else {
+                let (wid, ni) = Self::parse_num(&fmt, i, end);
+                self.flags.wid = wid;
+                i = ni;
+                if after_index && wid.is_some() {
+                    self.good_arg_num = false;
+                }
+            }
+
+            // precision
+            if i + 1 < end && fmt[i] == '.' {
+                i += 1;
+                if after_index {
+                    self.good_arg_num = false;
+                }
+                let (n, ni, ai) = self.arg_number(arg_num, &fmt, i, args.len());
+                arg_num = n;
+                i = ni;
+                after_index = ai;
+                if i < end && fmt[i] == '*' {
+                    i += 1;
+                    let (prec, present, n) = Self::int_from_arg(args, arg_num);
+                    arg_num = n;
+                    self.flags.prec = if present && prec >= 0 {
+                        Some(prec as usize)
+                    } else {
+                        None
+                    };
+                    if !present {
+                        self.buf.push_str("%!(BADPREC)");
+                    }
+                    after_index = false;
+                } else {
+                    let (prec, ni) = Self::parse_num(&fmt, i, end);
+                    self.flags.prec = Some(prec.unwrap_or(0));
+                    i = ni;
+                }
+            }
+
+            if !after_index {
+                let (n, ni, _) = self.arg_number(arg_num, &fmt, i, args.len());
+                arg_num = n;
+                i = ni;
+            }
+
+            if i >= end {
+                self.buf.push_str("%!(NOVERB)");
+                break;
+            }
+            let verb = fmt[i];
+            i += 1;
+            if verb == '%' {
+                self.buf.push('%');
+            } else if !self.good_arg_num {
+                self.buf.push_str(&format!("%!{}(BADINDEX)", verb));
+            } else if arg_num >= args.len() {
+                self.buf.push_str(&format!("%!{}(MISSING)", verb));
+            } else {
+                let verb = self.prepare_verb(verb);
+                self.print_verb_arg(&args[arg_num], arg_num, verb);
+                arg_num += 1;
+            }
+        }
+
+        if !self.reordered && arg_num < args.len() {
+            self.flags = Flags::default();
+            self.buf.push_str("%!(EXTRA ");
+            for (i, arg) in args[arg_num..].iter().enumerate() {
+                if i > 0 {
+                    self.buf.push_str(", ");
+                }
+                match Self::iface_inner(&arg.val) {
+                    Some((v, meta)) => {
+                        let t = self.value_type_string(&v, meta);
+                        self.buf.push_str(&t);
+                        self.buf.push('=');
+                        self.print_arg(arg, 'v');
+                    }
+                    None => self.buf.push_str("<nil>"),
+                }
+            }
+            self.buf.push(')');
+        }
+        (self.buf, self.wrapped)
+    }
+
+    /// Returns the values nested in arg that get printed, boxed in interfaces
+    /// so that fmt can call their Error() or String() methods: the elements
+    /// of arrays and slices, the keys and values of maps, the fields of
+    /// structs, and the value a pointer points to if `top`. The ones that
+    /// cannot have such methods are nil. None if none of them can.
+    pub fn elems(&self, arg: &GosValue, top: bool) -> Option<Vec<GosValue>> {
+        let (val, meta) = Self::iface_inner(arg)?;
+        let meta = meta?;
+        let metas = &self.objs.metas;
+        // the values with their types, and whether their methods may be called
+        let nested: Vec<(GosValue, Meta, bool)> = match val.typ() {
+            ValueType::Slice | ValueType::Array if meta.ptr_depth == 0 => {
+                let elem_meta = match meta.mtype_unwraped(metas) {
+                    MetadataType::Slice(e) => *e,
+                    MetadataType::Array(e, _) => *e,
+                    _ => return None,
+                };
+                (0..val.len())
+                    .filter_map(|i| {
+                        let r = match val.typ() {
+                            ValueType::Slice => val.dispatcher_a_s().slice_get(&val, i),
+                            _ => val.dispatcher_a_s().array_get(&val, i),
+                        };
+                        r.ok().map(|v| (v, elem_meta, true))
+                    })
+                    .collect()
+            }
+            ValueType::Map if meta.ptr_depth == 0 => {
+                let (kmeta, vmeta) = match meta.mtype_unwraped(metas) {
+                    MetadataType::Map(k, v) => (*k, *v),
+                    _ => return None,
+                };
+                Self::map_entries(&val)
+                    .into_iter()
+                    .flat_map(|(k, v)| [(k, kmeta, true), (v, vmeta, true)])
+                    .collect()
+            }
+            ValueType::Struct if meta.ptr_depth == 0 => {
+                let fields = match meta.mtype_unwraped(metas) {
+                    MetadataType::Struct(fields, _) => fields.all(),
+                    _ => return None,
+                };
+                // like Go, no methods are called on unexported fields
+                val.as_struct()
+                    .0
+                    .borrow_fields()
+                    .iter()
+                    .zip(fields.iter())
+                    .map(|(v, f)| (v.clone(), f.meta, f.exported))
+                    .collect()
+            }
+            ValueType::Pointer if top && meta.ptr_depth > 0 => {
+                let pointee = val.as_pointer()?.deref(self.stack, &self.objs.packages).ok()?;
+                vec![(pointee, meta.unptr_to(), true)]
+            }
+            _ => return None,
+        };
+        let mut cache = HashMap::new();
+        let boxed: Vec<Option<GosValue>> = nested
+            .into_iter()
+            .map(|(v, m, callable)| {
+                let may = *cache
+                    .entry((m.key, m.ptr_depth))
+                    .or_insert_with(|| self.may_have_text(m, &mut HashSet::new()));
+                (callable && may).then(|| {
+                    match m.underlying(metas).value_type(metas) {
+                        ValueType::Interface => v,
+                        _ => GosValue::new_interface(InterfaceObj::with_value(v, Some((m, vec![])))),
+                    }
+                })
+            })
+            .collect();
+        boxed.iter().any(|x| x.is_some()).then(|| {
+            boxed
+                .into_iter()
+                .map(|x| x.unwrap_or_else(|| GosValue::new_nil(ValueType::Interface)))
+                .collect()
+        })
+    }
+
+    /// Whether a value of the type, or one nested in it, may have an Error()
+    /// or String() method to print it. `seen` cuts recursive types short.
+    fn may_have_text(&self, meta: Meta, seen: &mut HashSet<(MetadataKey, u8)>) -> bool {
+        if !seen.insert((meta.key, meta.ptr_depth)) {
+            return false;
+        }
+        let metas = &self.objs.metas;
+        match &metas[meta.key] {
+            MetadataType::Named(methods, _, _) if !methods.mapping.is_empty() => true,
+            // nested pointers print as addresses
+            _ if meta.ptr_depth > 0 => false,
+            MetadataType::Named(_, underlying, _) => self.may_have_text(*underlying, seen),
+            MetadataType::Interface(_) => true,
+            MetadataType::Slice(e) | MetadataType::Array(e, _) => self.may_have_text(*e, seen),
+            MetadataType::Map(k, v) => {
+                self.may_have_text(*k, seen) || self.may_have_text(*v, seen)
+            }
+            MetadataType::Struct(fields, _) => fields
+                .all()
+                .iter()
+                .any(|f| f.exported && self.may_have_text(f.meta, seen)),
+            _ => false,
+        }
+    }
+
+    /// The Go representation of a type, as printed by %T.
+    pub fn type_string(&self, meta: &Meta) -> String {
+        let mut s = "*".repeat(meta.ptr_depth as usize);
+        s.push_str(&self.mtype_string(&self.objs.metas[meta.key]));
+        s
+    }
+
+    fn mtype_string(&self, mt: &MetadataType) -> String {
+        match mt {
+            MetadataType::Bool => "bool".to_owned(),
+            MetadataType::Int => "int".to_owned(),
+            MetadataType::Int8 => "int8".to_owned(),
+            MetadataType::Int16 => "int16".to_owned(),
+            MetadataType::Int32 => "int32".to_owned(),
+            MetadataType::Int64 => "int64".to_owned(),
+            MetadataType::Uint => "uint".to_owned(),
+            MetadataType::UintPtr => "uintptr".to_owned(),
+            MetadataType::Uint8 => "uint8".to_owned(),
+            MetadataType::Uint16 => "uint16".to_owned(),
+            MetadataType::Uint32 => "uint32".to_owned(),
+            MetadataType::Uint64 => "uint64".to_owned(),
+            MetadataType::Float32 => "float32".to_owned(),
+            MetadataType::Float64 => "float64".to_owned(),
+            MetadataType::Complex64 => "complex64".to_owned(),
+            MetadataType::Complex128 => "complex128".to_owned(),
+            MetadataType::UnsafePtr => "unsafe.Pointer".to_owned(),
+            MetadataType::Str(_) => "string".to_owned(),
+            MetadataType::Array(m, size) => format!("[{}]{}", size, self.type_string(m)),
+            MetadataType::Slice(m) => format!("[]{}", self.type_string(m)),
+            MetadataType::Struct(fields, _) => {
+                let fields: Vec<String> = fields
+                    .all()
+                    .iter()
+                    .map(|f| match f.embedded {
+                        true => self.type_string(&f.meta),
+                        false => format!("{} {}", f.name, self.type_string(&f.meta)),
+                    })
+                    .collect();
+                match fields.is_empty() {
+                    true => "struct {}".to_owned(),
+                    false => format!("struct {{ {} }}", fields.join("; ")),
+                }
+            }
+            MetadataType::Signature(sig) => format!("func{}", self.sig_string(sig)),
+            MetadataType::Map(k, v) => {
+                format!("map[{}]{}", self.type_string(k), self.type_string(v))
+            }
+            MetadataType::Interface(fields) => {
+                let mut methods = fields.iface_methods_info();
+                methods.sort_by(|a, b| a.0.cmp(&b.0));
+                let methods: Vec<String> = methods
+                    .iter()
+                    .map(|(name, m)| match &self.objs.metas[m.key] {
+                        MetadataType::Signature(sig) => {
+                            format!("{}{}", name, self.sig_string(sig))
+                        }
+                        _ => name.clone(),
+                    })
+                    .collect();
+                match methods.is_empty() {
+                    true => "interface {}".to_owned(),
+                    false => format!("interface {{ {} }}", methods.join("; ")),
+                }
+            }
+            MetadataType::Channel(typ, m) => {
+                let prefix = match typ {
+                    ChannelType::SendRecv => "chan ",
+                    ChannelType::Send => "chan<- ",
+                    ChannelType::Recv => "<-chan ",
+                };
+                format!("{}{}", prefix, self.type_string(m))
+            }
+            MetadataType::Named(_, _, name) => name.clone(),
+            MetadataType::None => "<nil>".to_owned(),
+        }
+    }
+
+    fn sig_string(&self, sig: &SigMetadata) -> String {
+        let mut params: Vec<String> = sig.params.iter().map(|p| self.type_string(p)).collect();
+        if let Some((_, elem)) = &sig.variadic {
+            if let Some(last) = params.last_mut() {
+                *last = format!("...{}", self.type_string(elem));
+            }
+        }
+        let results: Vec<String> = sig.results.iter().map(|r| self.type_string(r)).collect();
+        match results.len() {
+            0 => format!("({})", params.join(", ")),
+            1 => format!("({}) {}", params.join(", "), results[0]),
+            _ => format!("({}) ({})", params.join(", "), results.join(", ")),
+        }
+    }
+
+    fn value_type_string(&self, val: &GosValue, meta: Option<Meta>) -> String {
+        match meta {
+            Some(m) => self.type_string(&m),
+            None => match val.typ() {
+                ValueType::Bool => "bool",
+                ValueType::Int => "int",
+                ValueType::Int8 => "int8",
+                ValueType::Int16 => "int16",
+                ValueType::Int32 => "int32",
+                ValueType::Int64 => "int64",
+                ValueType::Uint => "uint",
+                ValueType::UintPtr => "uintptr",
+                ValueType::Uint8 => "uint8",
+                ValueType::Uint16 => "uint16",
+                ValueType::Uint32 => "uint32",
+                ValueType::Uint64 => "uint64",
+                ValueType::Float32 => "float32",
+                ValueType::Float64 => "float64",
+                ValueType::Complex64 => "complex64",
+                ValueType::Complex128 => "complex128",
+                ValueType::String => "string",
+                ValueType::UnsafePtr => "unsafe.Pointer",
+                _ => "?",
+            }
+            .to_owned(),
+        }
+    }
+
+    /// Returns the dynamic value and type of an interface, None if it's nil
+    fn iface_inner(val: &GosValue) -> Option<(GosValue, Option<Meta>)> {
+        match val.typ() {
+            ValueType::Interface => {}
+            ValueType::Void => return None,
+            _ => return Some((val.clone(), None)),
+        }
+        match val.as_interface() {
+            Some(InterfaceObj::Gos(v, meta)) => Some((v.clone(), meta.as_ref().map(|x| x.0))),
+            Some(InterfaceObj::Ffi(_)) => Some((val.clone(), None)),
+            None => None,
+        }
+    }
+
+    /// Resolves the "v" verb modifiers the way Go does
+    fn prepare_verb(&mut self, verb: char) -> char {
+        match verb {
+            'v' | 'w' => {
+                self.flags.sharp_v = self.flags.sharp;
+                self.flags.sharp = false;
+                self.flags.plus_v = self.flags.plus;
+                self.flags.plus = false;
+                verb
+            }
+            _ => verb,
+        }
+    }
+
+    fn print_verb_arg(&mut self, arg: &Operand, index: usize, verb: char) {
+        match verb {
+            'w' => {
+                if matches!(arg.text, MethodText::Text(_)) && self.wrapped.is_none() {
+                    self.wrapped = Some(index);
+                    self.print_arg(arg, 'v');
+                } else {
+                    self.print_arg(arg, 'w');
+                }
+            }
+            _ => self.print_arg(arg, verb),
+        }
+    }
+
+    fn arg_number(
+        &mut self,
+        arg_num: usize,
+        fmt: &[char],
+        i: usize,
+        num_args: usize,
+    ) -> (usize, usize, bool) {
+        if fmt.len() <= i || fmt[i] != '[' {
+            return (arg_num, i, false);
+        }
+        self.reordered = true;
+        let (index, wid, ok) = Self::parse_arg_number(&fmt[i..]);
+        if ok && index < num_args {
+            return (index, i + wid, true);
+        }
+        self.good_arg_num = false;
+        (arg_num, i + wid, ok)
+    }
+
+    fn parse_arg_number(fmt: &[char]) -> (usize, usize, bool) {
+        if fmt.len() < 3 {
+            return (0, 1, false);
+        }
+        for i in 1..fmt.len() {
+            if fmt[i] == ']' {
+                return match Self::parse_num(fmt, 1, i) {
+                    (Some(n), ni) if ni == i && n > 0 => (n - 1, i + 1, true),
+                    _ => (0, i + 1, false),
+                };
+            }
+        }
+        (0, 1, false)
+    }
+
+    fn parse_num(fmt: &[char], start: usize, end: usize) -> (Option<usize>, usize) {
+        let mut num: Option<usize> = None;
+        let mut i = start;
+        while i < end && fmt[i].is_ascii_digit() {
+            let n = num.unwrap_or(0);
+            if n > 1_000_000 {
+                return (None, end);
+            }
+            num = Some(n * 10 + fmt[i].to_digit(10).unwrap() as usize);
+            i += 1;
+        }
+        (num, i)
+    }
+
+    fn int_from_arg(args: &[Operand], arg_num: usize) -> (isize, bool, usize) {
+        if arg_num >= args.len() {
+            return (0, false, arg_num);
+        }
+        let n = match Self::iface_inner(&args[arg_num].val) {
+            Some((v, _)) => match Self::int_parts(&v) {
+                Some((u, true)) => Some(u as i64),
+                Some((u, false)) if u <= i64::MAX as u64 => Some(u as i64),
+                _ => None,
+            },
+            None => None,
+        }
+        .filter(|n| n.abs() <= 1_000_000)
+        .map(|n| n as isize);
+        match n {
+            Some(n) => (n, true, arg_num + 1),
+            None => (0, false, arg_num + 1),
+        }
+    }
+
+    fn print_arg(&mut self, arg: &Operand, verb: char) {
+        let (val, meta) = match Self::iface_inner(&arg.val) {
+            Some(inner) => inner,
+            None => {
+                match verb {
+                    'T' | 'v' => self.pad("<nil>"),
+                    _ => self.bad_verb(verb, None),
+                }
+                return;
+            }
+        };
+        match verb {
+            'T' => {
+                let t = self.value_type_string(&val, meta);
+                self.fmt_s(&t);
+                return;
+            }
+            'p' => {
+                self.fmt_pointer(&val, meta, 'p');
+                return;
+            }
+            _ => {}
+        }
+        if let MethodText::Text(text) = &arg.text {
+            if self.print_text(&val, text, verb) {
+                return;
+            }
+        }
+        self.print_value(&val, meta, verb, 0, &arg.text);
+    }
+
+    /// Prints what the Error() or String() method of val returned, if the
+    /// verb takes it. A method that panicked prints the panic.
+    fn print_text(&mut self, val: &GosValue, text: &str, verb: char) -> bool {
+        if let Some(panic) = text.strip_prefix("%!v(PANIC=") {
+            // a method called on a nil pointer receiver prints <nil>
+            match val.typ() == ValueType::Pointer && val.is_nil() {
+                true => self.pad("<nil>"),
+                false => self.buf.push_str(&format!("%!{}(PANIC={}", verb, panic)),
+            }
+            return true;
+        }
+        if !self.flags.sharp_v && matches!(verb, 'v' | 's' | 'x' | 'X' | 'q') {
+            self.fmt_string(text, verb);
+            return true;
+        }
+        false
+    }
+
+    fn print_value(
+        &mut self,
+        val: &GosValue,
+        meta: Option<Meta>,
+        verb: char,
+        depth: usize,
+        text: &MethodText,
+    ) {
+        if let MethodText::Text(t) = text {
+            if depth > 0 && self.print_text(val, t, verb) {
+                return;
+            }
+        }
+        match val.typ() {
+            ValueType::Bool => match verb {
+                't' | 'v' => self.pad(if *val.as_bool() { "true" } else { "false" }),
+                _ => self.bad_verb(verb, Some((val, meta))),
+            },
+            ValueType::Int
+            | ValueType::Int8
+            | ValueType::Int16
+            | ValueType::Int32
+            | ValueType::Int64
+            | ValueType::Uint
+            | ValueType::UintPtr
+            | ValueType::Uint8
+            | ValueType::Uint16
+            | ValueType::Uint32
+            | ValueType::Uint64 => {
+                let (u, signed) = Self::int_parts(val).unwrap();
+                self.fmt_int(u, signed, verb, Some((val, meta)));
+            }
+            ValueType::Float32 => self.fmt_float(
+                val.as_float32().into_inner() as f64,
+                32,
+                verb,
+                Some((val, meta)),
+            ),
+            ValueType::Float64 => {
+                self.fmt_float(val.as_float64().into_inner(), 64, verb, Some((val, meta)))
+            }
+            ValueType::Complex64 => {
+                let c = val.as_complex64();
+                let (r, i) = (c.r.into_inner() as f64, c.i.into_inner() as f64);
+                self.fmt_complex(r, i, 64, verb, Some((val, meta)));
+            }
+            ValueType::Complex128 => {
+                let c = val.as_complex128();
+                let (r, i) = (c.r.into_inner(), c.i.into_inner());
+                self.fmt_complex(r, i, 128, verb, Some((val, meta)));
+            }
+            ValueType::String => {
+                let s = StrUtil::as_str(val.as_string()).to_string();
+                match verb {
+                    'v' | 's' | 'x' | 'X' | 'q' => self.fmt_string(&s, verb),
+                    _ => self.bad_verb(verb, Some((val, meta))),
+                }
+            }
+            ValueType::Slice | ValueType::Array => self.print_list(val, meta, verb, depth, text),
+            ValueType::Map => self.print_map(val, meta, verb, depth, text),
+            ValueType::Struct => self.print_struct(val, meta, verb, depth, text),
+            ValueType::Interface | ValueType::Void => match Self::iface_inner(val) {
+                Some((inner, _)) if inner.typ() == ValueType::Interface => self.pad("<ffi>"),
+                Some((inner, inner_meta)) => {
+                    self.print_value(&inner, inner_meta, verb, depth + 1, text)
+                }
+                None => match self.flags.sharp_v {
+                    true => {
+                        let t = self.value_type_string(val, meta);
+                        self.buf.push_str(&t);
+                        self.buf.push_str("(nil)");
+                    }
+                    false => self.buf.push_str("<nil>"),
+                },
+            },
+            ValueType::Pointer => {
+                if depth == 0 {
+                    if let Some(p) = val.as_pointer() {
+                        if let Ok(pointee) = p.deref(self.stack, &self.objs.packages) {
+                            if matches!(
+                                pointee.typ(),
+                                ValueType::Array
+                                    | ValueType::Slice
+                                    | ValueType::Struct
+                                    | ValueType::Map
+                            ) {
+                                let pointee_meta =
+                                    meta.filter(|m| m.ptr_depth > 0).map(|m| m.unptr_to());
+                                self.buf.push('&');
+                                return self.print_value(
+                                    &pointee,
+                                    pointee_meta,
+                                    verb,
+                                    depth + 1,
+                                    text.elem(0),
+                                );
+                            }
+                        }
+                    }
+                }
+                self.fmt_pointer(val, meta, verb);
+            }
+            ValueType::Closure
+            | ValueType::Function
+            | ValueType::Channel
+            | ValueType::UnsafePtr => self.fmt_pointer(val, meta, verb),
+            _ => self.buf.push('?'),
+        }
+    }
+
+    fn print_list(
+        &mut self,
+        val: &GosValue,
+        meta: Option<Meta>,
+        verb: char,
+        depth: usize,
+        text: &MethodText,
+    ) {
+        let is_slice = val.typ() == ValueType::Slice;
+        let elem_meta = meta.filter(|m| m.ptr_depth == 0).and_then(|m| {
+            match m.mtype_unwraped(&self.objs.metas) {
+                MetadataType::Slice(e) => Some(*e),
+                MetadataType::Array(e, _) => Some(*e),
+                _ => None,
+            }
+        });
+        let is_bytes = match elem_meta {
+            Some(e) => e.ptr_depth == 0 && e.value_type(&self.objs.metas) == ValueType::Uint8,
+            None => val.t_elem() == ValueType::Uint8,
+        };
+        let len = val.len();
+        let get = |i: usize| -> Option<GosValue> {
+            let r = match is_slice {
+                true => val.dispatcher_a_s().slice_get(val, i),
+                false => val.dispatcher_a_s().array_get(val, i),
+            };
+            r.ok()
+        };
+
+        if is_bytes && matches!(verb, 's' | 'q' | 'x' | 'X') {
+            let bytes: Vec<u8> = (0..len)
+                .filter_map(|i| get(i).map(|v| *v.as_uint8()))
+                .collect();
+            match verb {
+                's' => self.fmt_s(&String::from_utf8_lossy(&bytes)),
+                'x' => self.fmt_sx(&bytes, LDIGITS),
+                'X' => self.fmt_sx(&bytes, UDIGITS),
+                _ => self.fmt_q(&String::from_utf8_lossy(&bytes)),
+            }
+            return;
+        }
+
+        if self.flags.sharp_v {
+            let t = self.value_type_string(val, meta);
+            self.buf.push_str(&t);
+            if is_slice && val.is_nil() {
+                self.buf.push_str("(nil)");
+                return;
+            }
+            self.buf.push('{');
+            for i in 0..len {
+                if i > 0 {
+                    self.buf.push_str(", ");
+                }
+                if let Some(v) = get(i) {
+                    self.print_value(&v, elem_meta, verb, depth + 1, text.elem(i));
+                }
+            }
+            self.buf.push('}');
+        } else {
+            self.buf.push('[');
+            for i in 0..len {
+                if i > 0 {
+                    self.buf.push(' ');
+                }
+                if let Some(v) = get(i) {
+                    self.print_value(&v, elem_meta, verb, depth + 1, text.elem(i));
+                }
+            }
+            self.buf.push(']');
+        }
+    }
+
+    fn print_map(
+        &mut self,
+        val: &GosValue,
+        meta: Option<Meta>,
+        verb: char,
+        depth: usize,
+        text: &MethodText,
+    ) {
+        let (kmeta, vmeta) = match meta.filter(|m| m.ptr_depth == 0) {
+            Some(m) => match m.mtype_unwraped(&self.objs.metas) {
+                MetadataType::Map(k, v) => (Some(*k), Some(*v)),
+                _ => (None, None),
+            },
+            None => (None, None),
+        };
+        if self.flags.sharp_v {
+            let t = self.value_type_string(val, meta);
+            self.buf.push_str(&t);
+            if val.is_nil() {
+                self.buf.push_str("(nil)");
+                return;
+            }
+            self.buf.push('{');
+        } else {
+            self.buf.push_str("map[");
+        }
+        for (i, (k, v)) in Self::map_entries(val).iter().enumerate() {
+            if i > 0 {
+                match self.flags.sharp_v {
+                    true => self.buf.push_str(", "),
+                    false => self.buf.push(' '),
+                }
+            }
+            self.print_value(k, kmeta, verb, depth + 1, text.elem(2 * i));
+            self.buf.push(':');
+            self.print_value(v, vmeta, verb, depth + 1, text.elem(2 * i + 1));
+        }
+        match self.flags.sharp_v {
+            true => self.buf.push('}'),
+            false => self.buf.push(']'),
+        }
+    }
+
+    /// The entries of a map in the order they are printed
+    fn map_entries(val: &GosValue) -> Vec<(GosValue, GosValue)> {
+        let mut entries: Vec<(GosValue, GosValue)> = match val.as_map() {
+            Some(m) => {
+                m.0.borrow_data()
+                    .iter()
+                    .map(|(k, v)| (k.clone(), v.clone()))
+                    .collect()
+            }
+            None => vec![],
+        };
+        entries.sort_by(|a, b| Self::compare_keys(&a.0, &b.0));
+        entries
+    }
+
+    /// Orders map keys for printing, keys of different or uncomparable
+    /// types keep their original order
+    fn compare_keys(a: &GosValue, b: &GosValue) -> Ordering {
+        let (a, b) = match (Self::iface_inner(a), Self::iface_inner(b)) {
+            (Some((a, _)), Some((b, _))) => (a, b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (None, None) => return Ordering::Equal,
+        };
+        if a.typ() != b.typ() {
+            return Ordering::Equal;
+        }
+        match a.typ() {
+            ValueType::Bool
+            | ValueType::Int
+            | ValueType::Int8
+            | ValueType::Int16
+            | ValueType::Int32
+            | ValueType::Int64
+            | ValueType::Uint
+            | ValueType::UintPtr
+            | ValueType::Uint8
+            | ValueType::Uint16
+            | ValueType::Uint32
+            | ValueType::Uint64
+            | ValueType::Float32
+            | ValueType::Float64
+            | ValueType::String => a.cmp(&b),
+            _ => Ordering::Equal,
+        }
+    }
+
+    fn print_struct(
+        &mut self,
+        val: &GosValue,
+        meta: Option<Meta>,
+        verb: char,
+        depth: usize,
+        text: &MethodText,
+    ) {
+        let fields_meta: Option<Vec<(String, Meta)>> =
+            meta.filter(|m| m.ptr_depth == 0).and_then(|m| {
+                match m.mtype_unwraped(&self.objs.metas) {
+                    MetadataType::Struct(fields, _) => Some(
+                        fields
+                            .all()
+                            .iter()
+                            .map(|f| (f.name.clone(), f.meta))
+                            .collect(),
+                    ),
+                    _ => None,
+                }
+            });
+        if self.flags.sharp_v {
+            let t = self.value_type_string(val, meta);
+            self.buf.push_str(&t);
+        }
+        self.buf.push('{');
+        let fields: Vec<GosValue> = val.as_struct().0.borrow_fields().clone();
+        for (i, f) in fields.iter().enumerate() {
+            if i > 0 {
+                match self.flags.sharp_v {
+                    true => self.buf.push_str(", "),
+                    false => self.buf.push(' '),
+                }
+            }
+            let field_meta = fields_meta.as_ref().and_then(|x| x.get(i));
+            if self.flags.plus_v || self.flags.sharp_v {
+                if let Some((name, _)) = field_meta {
+                    if !name.is_empty() {
+                        self.buf.push_str(name);
+                        self.buf.push(':');
+                    }
+                }
+            }
+            self.print_value(f, field_meta.map(|x| x.1), verb, depth + 1, text.elem(i));
+        }
+        self.buf.push('}');
+    }
+
+    fn fmt_pointer(&mut self, val: &GosValue, meta: Option<Meta>, verb: char) {
+        let u = match val.typ() {
+            ValueType::Pointer
+            | ValueType::UnsafePtr
+            | ValueType::Closure
+            | ValueType::Channel
+            | ValueType::Slice
+            | ValueType::Map
+            | ValueType::Function => match val.is_nil() {
+                true => 0,
+                false => val.as_addr() as u64,
+            },
+            _ => return self.bad_verb(verb, Some((val, meta))),
+        };
+        match verb {
+            'v' => {
+                if self.flags.sharp_v {
+                    let t = self.value_type_string(val, meta);
+                    self.buf.push('(');
+                    self.buf.push_str(&t);
+                    self.buf.push_str(")(");
+                    match u {
+                        0 => self.buf.push_str("nil"),
+                        _ => self.fmt_0x64(u, true),
+                    }
+                    self.buf.push(')');
+                } else {
+                    match u {
+                        0 => self.pad("<nil>"),
+                        _ => self.fmt_0x64(u, !self.flags.sharp),
+                    }
+                }
+            }
+            'p' => self.fmt_0x64(u, !self.flags.sharp),
+            'b' | 'o' | 'd' | 'x' | 'X' => self.fmt_int(u, false, verb, None),
+            _ => self.bad_verb(verb, Some((val, meta))),
+        }
+    }
+
+    fn bad_verb(&mut self, verb: char, val: Option<(&GosValue, Option<Meta>)>) {
+        self.buf.push_str("%!");
+        self.buf.push(verb);
+        self.buf.push('(');
+        match val {
+            Some((v, meta)) => {
+                let t = self.value_type_string(v, meta);
+                self.buf.push_str(&t);
+                self.buf.push('=');
+                self.print_value(v, meta, 'v', 0, &NO_TEXT);
+            }
+            None => self.buf.push_str("<nil>"),
+        }
+        self.buf.push(')');
+    }
+
+    /// Returns the bits of an integer and whether it's signed
+    fn int_parts(val: &GosValue) -> Option<(u64, bool)> {
+        let parts = match val.typ() {
+            ValueType::Int => (*val.as_int() as i64 as u64, true),
+            ValueType::Int8 => (*val.as_int8() as i64 as u64, true),
+            ValueType::Int16 => (*val.as_int16() as i64 as u64, true),
+            ValueType::Int32 => (*val.as_int32() as i64 as u64, true),
+            ValueType::Int64 => (*val.as_int64() as u64, true),
+            ValueType::Uint => (*val.as_uint() as u64, false),
+            ValueType::UintPtr => (*val.as_uint_ptr() as u64, false),
+            ValueType::Uint8 => (*val.as_uint8() as u64, false),
+            ValueType::Uint16 => (*val.as_uint16() as u64, false),
+            ValueType::Uint32 => (*val.as_uint32() as u64, false),
+            ValueType::Uint64 => (*val.as_uint64(), false),
+            _ => return None,
+        };
+        Some(parts)
+    }
+
+    fn fmt_int(
+        &mut self,
+        u: u64,
+        signed: bool,
+        verb: char,
+        val: Option<(&GosValue, Option<Meta>)>,
+    ) {
+        match verb {
+            'v' => match self.flags.sharp_v && !signed {
+                true => self.fmt_0x64(u, true),
+                false => self.fmt_integer(u, signed, 10, verb, LDIGITS),
+            },
+            'd' => self.fmt_integer(u, signed, 10, verb, LDIGITS),
+            'b' => self.fmt_integer(u, signed, 2, verb, LDIGITS),
+            'o' | 'O' => self.fmt_integer(u, signed, 8, verb, LDIGITS),
+            'x' => self.fmt_integer(u, signed, 16, verb, LDIGITS),
+            'X' => self.fmt_integer(u, signed, 16, verb, UDIGITS),
+            'c' => self.fmt_c(u),
+            'q' => self.fmt_qc(u),
+            'U' => self.fmt_unicode(u),
+            _ => match val {
+                Some((v, meta)) => self.bad_verb(verb, Some((v, meta))),
+                None => self.bad_verb(verb, None),
+            },
+        }
+    }
+
+    fn fmt_0x64(&mut self, u: u64, leading_0x: bool) {
+        let sharp = self.flags.sharp;
+        self.flags.sharp = leading_0x;
+        self.fmt_integer(u, false, 16, 'v', LDIGITS);
+        self.flags.sharp = sharp;
+    }
+
+    fn fmt_integer(&mut self, u: u64, signed: bool, base: u64, verb: char, digits: &[u8; 16]) {
+        let negative = signed && (u as i64) < 0;
+        let mut u = match negative {
+            true => (u as i64).unsigned_abs(),
+            false => u,
+        };
+
+        let mut prec = 0;
+        if let Some(p) = self.flags.prec {
+            prec = p;
+            if prec == 0 && u == 0 {
+                let zero = self.flags.zero;
+                self.flags.zero = false;
+                self.write_padding(self.flags.wid.unwrap_or(0));
+                self.flags.zero = zero;
+                return;
+            }
+        } else if let (true, Some(wid)) = (self.flags.zero, self.flags.wid) {
+            prec = wid;
+            if negative || self.flags.plus || self.flags.space {
+                prec = prec.saturating_sub(1);
+            }
+        }
+
+        // digits are collected backwards
+        let mut buf: Vec<u8> = vec![];
+        loop {
+            buf.push(digits[(u % base) as usize]);
+            u /= base;
+            if u == 0 {
+                break;
+            }
+        }
+        while buf.len() < prec {
+            buf.push(b'0');
+        }
+        if self.flags.sharp {
+            match base {
+                2 => buf.extend_from_slice(b"b0"),
+                8 => {
+                    if *buf.last().unwrap() != b'0' {
+                        buf.push(b'0');
+                    }
+                }
+                16 => {
+                    buf.push(if digits[10] == b'a' { b'x' } else { b'X' });
+                    buf.push(b'0');
+                }
+                _ => {}
+            }
+        }
+        if verb == 'O' {
+            buf.extend_from_slice(b"o0");
+        }
+        if negative {
+            buf.push(b'-');
+        } else if self.flags.plus {
+            buf.push(b'+');
+        } else if self.flags.space {
+            buf.push(b' ');
+        }
+        buf.reverse();
+
+        let zero = self.flags.zero;
+        self.flags.zero = false;
+        self.pad(&String::from_utf8(buf).unwrap());
+        self.flags.zero = zero;
+    }
+
+    fn fmt_c(&mut self, u: u64) {
+        let c = Self::to_char(u);
+        self.pad(&c.to_string());
+    }
+
+    fn fmt_qc(&mut self, u: u64) {
+        let c = Self::to_char(u);
+        let s = match self.flags.sharp && c != '`' && Self::is_print(c) {
+            true => format!("'{}'", c),
+            false => Self::quote_with(&c.to_string(), '\'', self.flags.plus),
+        };
+        self.pad(&s);
+    }
+
+    fn fmt_unicode(&mut self, u: u64) {
+        let prec = self.flags.prec.unwrap_or(4).max(4);
+        let mut s = format!("U+{:0width$X}", u, width = prec);
+        if self.flags.sharp && u <= 0x10FFFF {
+            if let Some(c) = std::char::from_u32(u as u32) {
+                if Self::is_print(c) {
+                    s.push_str(&format!(" '{}'", c));
+                }
+            }
+        }
+        let zero = self.flags.zero;
+        self.flags.zero = false;
+        self.pad(&s);
+        self.flags.zero = zero;
+    }
+
+    fn to_char(u: u64) -> char {
+        match u <= 0x10FFFF {
+            true => std::char::from_u32(u as u32).unwrap_or('\u{FFFD}'),
+            false => '\u{FFFD}',
+        }
+    }
+
+    fn fmt_float(&mut self, v: f64, size: u32, verb: char, val: Option<(&GosValue, Option<Meta>)>) {
+        match verb {
+            'v' => self.fmt_float_impl(v, size, 'g', -1),
+            'g' | 'G' => self.fmt_float_impl(v, size, verb, -1),
+            'f' | 'F' | 'e' | 'E' => self.fmt_float_impl(v, size, verb, 6),
+            _ => match val {
+                Some((v, meta)) => self.bad_verb(verb, Some((v, meta))),
+                None => self.bad_verb(verb, None),
+            },
+        }
+    }
+
+    fn fmt_complex(
+        &mut self,
+        r: f64,
+        i: f64,
+        size: u32,
+        verb: char,
+        val: Option<(&GosValue, Option<Meta>)>,
+    ) {
+        match verb {
+            'v' | 'g' | 'G' | 'f' | 'F' | 'e' | 'E' => {
+                let plus = self.flags.plus;
+                self.buf.push('(');
+                self.fmt_float(r, size / 2, verb, None);
+                // the imaginary part always has a sign
+                self.flags.plus = true;
+                self.fmt_float(i, size / 2, verb, None);
+                self.buf.push_str("i)");
+                self.flags.plus = plus;
+            }
+            _ => match val {
+                Some((v, meta)) => self.bad_verb(verb, Some((v, meta))),
+                None => self.bad_verb(verb, None),
+            },
+        }
+    }
+
+    fn fmt_float_impl(&mut self, v: f64, size: u32, verb: char, prec: i32) {
+        let prec = self.flags.prec.map_or(prec, |p| p as i32);
+        let mut num = format_float(v, size, verb, prec);
+        if !num.starts_with('-') && !num.starts_with('+') {
+            num.insert(0, '+');
+        }
+        if self.flags.space && num.starts_with('+') && !self.flags.plus {
+            num.replace_range(0..1, " ");
+        }
+        if num[1..].starts_with('I') || num[1..].starts_with('N') {
+            let zero = self.flags.zero;
+            self.flags.zero = false;
+            if num[1..].starts_with('N') && !self.flags.space && !self.flags.plus {
+                num.remove(0);
+            }
+            self.pad(&num);
+            self.flags.zero = zero;
+            return;
+        }
+        if self.flags.plus || !num.starts_with('+') {
+            let len = num.chars().count();
+            match (self.flags.zero, self.flags.wid) {
+                (true, Some(wid)) if wid > len => {
+                    self.buf.push_str(&num[..1]);
+                    self.write_padding(wid - len);
+                    self.buf.push_str(&num[1..]);
+                }
+                _ => self.pad(&num),
+            }
+            return;
+        }
+        self.pad(&num[1..]);
+    }
+
+    fn fmt_string(&mut self, s: &str, verb: char) {
+        match verb {
+            'v' => match self.flags.sharp_v {
+                true => self.fmt_q(s),
+                false => self.fmt_s(s),
+            },
+            's' => self.fmt_s(s),
+            'x' => self.fmt_sx(s.as_bytes(), LDIGITS),
+            'X' => self.fmt_sx(s.as_bytes(), UDIGITS),
+            'q' => self.fmt_q(s),
+            _ => {
+                let v = GosValue::with_str(s);
+                self.bad_verb(verb, Some((&v, None)));
+            }
+        }
+    }
+
+    fn truncate<'b>(&self, s: &'b str) -> &'b str {
+        match self.flags.prec {
+            Some(p) => match s.char_indices().nth(p) {
+                Some((i, _)) => &s[..i],
+                None => s,
+            },
+            None => s,
+        }
+    }
+
+    fn fmt_s(&mut self, s: &str) {
+        let s = self.truncate(s);
+        self.pad(s);
+    }
+
+    fn fmt_sx(&mut self, b: &[u8], digits: &[u8; 16]) {
+        let length = match self.flags.prec {
+            Some(p) if p < b.len() => p,
+            _ => b.len(),
+        };
+        let mut width = 2 * length;
+        if width > 0 {
+            if self.flags.space {
+                if self.flags.sharp {
+                    width *= 2;
+                }
+                width += length - 1;
+            } else if self.flags.sharp {
+                width += 2;
+            }
+        } else {
+            if let Some(wid) = self.flags.wid {
+                self.write_padding(wid);
+            }
+            return;
+        }
+        let wid = self.flags.wid.unwrap_or(0);
+        if wid > width && !self.flags.minus {
+            self.write_padding(wid - width);
+        }
+        let x = if digits[10] == b'a' { 'x' } else { 'X' };
+        if self.flags.sharp {
+            self.buf.push('0');
+            self.buf.push(x);
+        }
+        for (i, c) in b[..length].iter().enumerate() {
+            if self.flags.space && i > 0 {
+                self.buf.push(' ');
+                if self.flags.sharp {
+                    self.buf.push('0');
+                    self.buf.push(x);
+                }
+            }
+            self.buf.push(digits[(c >> 4) as usize] as char);
+            self.buf.push(digits[(c & 0xF) as usize] as char);
+        }
+        if wid > width && self.flags.minus {
+            self.write_padding(wid - width);
+        }
+    }
+
+    fn fmt_q(&mut self, s: &str) {
+        let s = self.truncate(s);
+        let can_backquote = s
+            .chars()
+            .all(|c| c != '`' && c != '\u{FEFF}' && (c == '\t' || !c.is_control()));
+        let q = match self.flags.sharp && can_backquote {
+            true => format!("`{}`", s),
+            false => Self::quote_with(s, '"', self.flags.plus),
+        };
+        self.pad(&q);
+    }
+
+    /// Quotes a string with Go escapes, like strconv.Quote.
+    fn quote_with(s: &str, quote: char, ascii_only: bool) -> String {
+        let mut q = String::with_capacity(s.len() + 2);
+        q.push(quote);
+        for c in s.chars() {
+            match c {
+                _ if c == quote || c == '\\' => {
+                    q.push('\\');
+                    q.push(c);
+                }
+                '\u{7}' => q.push_str("\\a"),
+                '\u{8}' => q.push_str("\\b"),
+                '\u{c}' => q.push_str("\\f"),
+                '\n' => q.push_str("\\n"),
+                '\r' => q.push_str("\\r"),
+                '\t' => q.push_str("\\t"),
+                '\u{b}' => q.push_str("\\v"),
+                _ if (c as u32) < 0x80 && Self::is_print(c) => q.push(c),
+                _ if (c as u32) < 0x80 => q.push_str(&format!("\\x{:02x}", c as u32)),
+                _ if Self::is_print(c) && !ascii_only => q.push(c),
+                _ if (c as u32) < 0x10000 => q.push_str(&format!("\\u{:04x}", c as u32)),
+                _ => q.push_str(&format!("\\U{:08x}", c as u32)),
+            }
+        }
+        q.push(quote);
+        q
+    }
+
+    fn is_print(c: char) -> bool {
+        !c.is_control() && c != '\u{FFFD}' && !matches!(c, '\u{2028}' | '\u{2029}' | '\u{FEFF}')
+    }
+
+    fn pad(&mut self, s: &str) {
+        let wid = match self.flags.wid {
+            Some(w) if w > 0 => w,
+            _ => {
+                self.buf.push_str(s);
+                return;
+            }
+        };
+        let width = wid.saturating_sub(s.chars().count());
+        if !self.flags.minus {
+            self.write_padding(width);
+            self.buf.push_str(s);
+        } else {
+            self.buf.push_str(s);
+            self.write_padding(width);
+        }
+    }
+
+    fn write_padding(&mut self, n: usize) {
+        let c = if self.flags.zero { '0' } else { ' ' };
+        self.buf.extend(std::iter::repeat(c).take(n));
+    }
+}
+
+/// Formats values that are not on the stack of a goroutine, like the
+/// members of packages, the way Go's fmt.Sprintln does without the newline
+pub fn sprint_values(objs: &VMObjects, vals: &[GosValue]) -> String {
+    let stack = Stack::new();
+    let operands: Vec<Operand> = vals.iter().map(|v| Operand::new(v.clone(), MethodText::None)).collect();
+    let mut s = ValueFormatter::new(objs, &stack).sprintln(&operands);
+    s.pop();
+    s
+}
+
+/// Formats a float the way Go's strconv.FormatFloat does,
+/// a negative precision means the shortest representation that round-trips.
+pub fn format_float(v: f64, size: u32, verb: char, prec: i32) -> String {
+    if v.is_nan() {
+        return "NaN".to_owned();
+    }
+    if v.is_infinite() {
+        return if v > 0.0 { "+Inf" } else { "-Inf" }.to_owned();
+    }
+    let neg = v.is_sign_negative();
+    let abs = v.abs();
+    let shortest = prec < 0;
+    let mut s = String::new();
+    if neg {
+        s.push('-');
+    }
+    match verb {
+        'e' | 'E' => {
+            let (digs, dp) = float_digits(abs, size, if shortest { 0 } else { prec + 1 });
+            let prec = if shortest {
+                digs.len().saturating_sub(1)
+            } else {
+                prec as usize
+            };
+            fmt_e(&mut s, &digs, dp, prec, verb);
+        }
+        'f' | 'F' => {
+            if shortest {
+                let (digs, dp) = float_digits(abs, size, 0);
+                fmt_f(&mut s, &digs, dp, (digs.len() as i32 - dp).max(0) as usize);
+            } else {
+                s.push_str(&format!("{:.*}", prec as usize, abs));
+            }
+        }
+        _ => {
+            let upper = verb == 'G';
+            let mut prec = prec;
+            if !shortest && prec == 0 {
+                prec = 1;
+            }
+            let (digs, dp) = float_digits(abs, size, if shortest { 0 } else { prec });
+            let nd = digs.len() as i32;
+            if shortest {
+                prec = nd;
+            }
+            let mut eprec = prec;
+            if eprec > nd && nd >= dp {
+                eprec = nd;
+            }
+            if shortest {
+                eprec = 6;
+            }
+            let exp = dp - 1;
+            if exp < -4 || exp >= eprec {
+                if prec > nd {
+                    prec = nd;
+                }
+                let e = if upper { 'E' } else { 'e' };
+                fmt_e(&mut s, &digs, dp, (prec - 1).max(0) as usize, e);
+            } else {
+                if prec > dp {
+                    prec = nd;
+                }
+                fmt_f(&mut s, &digs, dp, (prec - dp).max(0) as usize);
+            }
+        }
+    }
+    s
+}
+
+/// Decimal digits of a non-negative float without trailing zeros, and the
+/// position of the decimal point. A zero `digits` means the shortest
+/// representation, otherwise the value is rounded to that many digits.
+fn float_digits(v: f64, size: u32, digits: i32) -> (Vec<u8>, i32) {
+    let s = match (digits, size) {
+        (0, 32) => format!("{:e}", v as f32),
+        (0, _) => format!("{:e}", v),
+        (n, _) => format!("{:.*e}", (n - 1) as usize, v),
+    };
+    let (mantissa, exp) = s.split_at(s.find('e').unwrap());
+    let exp: i32 = exp[1..].parse().unwrap();
+    let mut digs: Vec<u8> = mantissa.bytes().filter(|b| *b != b'.').collect();
+    while digs.last() == Some(&b'0') {
+        digs.pop();
+    }
+    match digs.is_empty() {
+        true => (digs, 0),
+        false => (digs, exp + 1),
+    }
+}
+
+fn fmt_e(s: &mut String, digs: &[u8], dp: i32, prec: usize, verb: char) {
+    s.push(*digs.first().unwrap_or(&b'0') as char);
+    if prec > 0 {
+        s.push('.');
+        for i in 1..=prec {
+            s.push(*digs.get(i).unwrap_or(&b'0') as char);
+        }
+    }
+    s.push(verb);
+    let exp = if digs.is_empty() { 0 } else { dp - 1 };
+    s.push(if exp < 0 { '-' } else { '+' });
+    s.push_str(&format!("{:02}", exp.abs()));
+}
+
+fn fmt_f(s: &mut String, digs: &[u8], dp: i32, prec: usize) {
+    if dp > 0 {
+        for i in 0..dp as usize {
+            s.push(*digs.get(i).unwrap_or(&b'0') as char);
+        }
+    } else {
+        s.push('0');
+    }
+    if prec > 0 {
+        s.push('.');
+        for i in 1..=prec as i32 {
+            let j = dp + i - 1;
+            let c = match j >= 0 && (j as usize) < digs.len() {
+                true => digs[j as usize],
+                false => b'0',
+            };
+            s.push(c as char);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_format_float() {
+        assert_eq!(format_float(0.0, 64, 'g', -1), "0");
+        assert_eq!(format_float(1e6, 64, 'g', -1), "1e+06");
+        assert_eq!(format_float(123456.0, 64, 'g', -1), "123456");
+        assert_eq!(format_float(0.1, 32, 'g', -1), "0.1");
+        assert_eq!(format_float(0.000012, 64, 'g', -1), "1.2e-05");
+        assert_eq!(format_float(-2.5, 64, 'f', 3), "-2.500");
+        assert_eq!(format_float(1234.5678, 64, 'e', 6), "1.234568e+03");
+        assert_eq!(format_float(1234.5678, 64, 'g', 3), "1.23e+03");
+        assert_eq!(format_float(100.0, 64, 'g', 5), "100");
+        assert_eq!(format_float(f64::INFINITY, 64, 'g', -1), "+Inf");
+    }
+}
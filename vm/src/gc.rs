@@ -2,25 +2,109 @@
 // Use of this source code is governed by a BSD-style
 // license that can be found in the LICENSE file.
 
+use super::clock::Instant;
 use super::instruction::ValueType;
 use super::objects::*;
+use super::profile::{AllocKind, AllocProfile};
 use super::value::{GosValue, RCQueue, RCount, IRC};
-use std::cell::Ref;
-use std::cell::RefCell;
+use std::cell::{Cell, Ref, RefCell};
+use std::collections::HashMap;
 use std::convert::TryFrom;
+use std::mem::ManuallyDrop;
 use std::rc::{Rc, Weak};
+use std::time::Duration;
+
+/// Controls when collections are triggered, they can only happen at the
+/// yield points of fibers.
+#[derive(Clone, Copy, Debug)]
+pub struct GcConfig {
+    /// A collection is due when the number of tracked objects reaches this and
+    /// has grown by `growth_factor` since the last collection.
+    pub min_objects: usize,
+    pub growth_factor: usize,
+    /// A collection is also due after this many instructions, if anything has
+    /// been allocated since the last collection.
+    pub instruction_interval: usize,
+}
+
+impl Default for GcConfig {
+    fn default() -> GcConfig {
+        GcConfig {
+            min_objects: 4096,
+            growth_factor: 2,
+            instruction_interval: 1 << 22,
+        }
+    }
+}
+
+/// Statistics of the collections run by a VM.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct GcStats {
+    /// Number of collections run
+    pub collections: usize,
+    /// Number of tracked objects found freed by the collections, be it by
+    /// breaking cycles or by reference counting in between
+    pub freed: usize,
+    /// Number of tracked objects alive after the last collection
+    pub live: usize,
+    /// Total time spent collecting
+    pub time: Duration,
+}
 
 pub struct GcoVec {
     inner: Rc<RefCell<Vec<GcWeak>>>,
+    config: Cell<GcConfig>,
+    stats: Cell<GcStats>,
+    // instructions executed since the last collection
+    insts: Cell<usize>,
+    profile: RefCell<Option<Rc<AllocProfile>>>,
 }
 
 impl GcoVec {
     pub fn new() -> GcoVec {
+        GcoVec::with_config(GcConfig::default())
+    }
+
+    pub fn with_config(config: GcConfig) -> GcoVec {
         GcoVec {
             inner: Rc::new(RefCell::new(Vec::new())),
+            config: Cell::new(config),
+            stats: Cell::new(GcStats::default()),
+            insts: Cell::new(0),
+            profile: RefCell::new(None),
         }
     }
 
+    pub fn config(&self) -> GcConfig {
+        self.config.get()
+    }
+
+    pub fn set_config(&self, config: GcConfig) {
+        self.config.set(config);
+    }
+
+    pub fn stats(&self) -> GcStats {
+        self.stats.get()
+    }
+
+    /// Number of tracked objects, some of which may have been freed since
+    /// the last collection
+    pub fn count(&self) -> usize {
+        self.inner.borrow().len()
+    }
+
+    /// Called by fibers at yield points with the number of instructions they
+    /// have just run, returns true if it's time to collect.
+    pub fn tick(&self, insts: usize) -> bool {
+        let insts = self.insts.get() + insts;
+        self.insts.set(insts);
+        let count = self.inner.borrow().len();
+        let live = self.stats.get().live;
+        let cfg = self.config.get();
+        (count >= cfg.min_objects && count >= live.saturating_mul(cfg.growth_factor))
+            || (insts >= cfg.instruction_interval && count > live)
+    }
+
     pub fn add_array(&self, arr: &Rc<(GosArrayObj, RCount)>) {
         self.add_weak(GcWeak::new_array(arr))
     }
@@ -37,14 +121,43 @@ impl GcoVec {
         self.add_weak(GcWeak::new_struct(s))
     }
 
+    /// Sets the profile the allocations are counted in, None to stop counting
+    pub fn set_alloc_profile(&self, profile: Option<Rc<AllocProfile>>) {
+        *self.profile.borrow_mut() = profile;
+    }
+
     #[inline]
     pub fn add_weak(&self, w: GcWeak) {
+        self.count_alloc(w.alloc_kind());
         self.inner.borrow_mut().push(w);
     }
 
+    /// Counts an allocation in the profile if there is one, the objects not
+    /// tracked are counted too
+    #[inline]
+    pub(crate) fn count_alloc(&self, kind: AllocKind) {
+        if let Some(p) = self.profile.borrow().as_ref() {
+            p.record(kind);
+        }
+    }
+
     fn borrow_data(&self) -> Ref<Vec<GcWeak>> {
         self.inner.borrow()
     }
+
+    /// Drops the entries of freed objects and updates the stats
+    fn compact(&self, time: Duration) {
+        let mut inner = self.inner.borrow_mut();
+        let before = inner.len();
+        inner.retain(|w| w.is_alive());
+        let mut stats = self.stats.get();
+        stats.collections += 1;
+        stats.freed += before - inner.len();
+        stats.live = inner.len();
+        stats.time += time;
+        self.stats.set(stats);
+        self.insts.set(0);
+    }
 }
 
 #[derive(Clone)]
@@ -79,6 +192,24 @@ impl GcWeak {
         GcWeak::Struct(Rc::downgrade(s))
     }
 
+    fn alloc_kind(&self) -> AllocKind {
+        match &self {
+            GcWeak::Array(_) => AllocKind::Array,
+            GcWeak::Closure(_) => AllocKind::Closure,
+            GcWeak::Map(_) => AllocKind::Map,
+            GcWeak::Struct(_) => AllocKind::Struct,
+        }
+    }
+
+    fn is_alive(&self) -> bool {
+        match &self {
+            GcWeak::Array(w) => w.strong_count() > 0,
+            GcWeak::Closure(w) => w.strong_count() > 0,
+            GcWeak::Map(w) => w.strong_count() > 0,
+            GcWeak::Struct(w) => w.strong_count() > 0,
+        }
+    }
+
     fn to_gosv(&self) -> Option<GosValue> {
         match &self {
             GcWeak::Array(w) => w.upgrade().map(|v| {
@@ -101,22 +232,128 @@ impl GcWeak {
     }
 }
 
-fn children_ref_sub_one(val: &GosValue) {
+/// Slices, interfaces and upvalues are shared by all the copies of a value,
+/// but they are not tracked by GcoVec. Such an object passes a reference on to
+/// what it holds only when all of its own references come from the objects
+/// being scanned, otherwise it's held from outside (e.g. the stack) and
+/// so is everything it holds.
+enum Shared {
+    Slice(GosValue),
+    Interface(GosValue),
+    UpValue(UpValue),
+}
+
+#[derive(Default)]
+struct SharedRefs {
+    // the number of times each shared object has been reached
+    visits: HashMap<usize, usize>,
+    // shared objects whose references all come from the scanned objects
+    ready: Vec<Shared>,
+}
+
+impl SharedRefs {
+    fn visit(&mut self, addr: usize, strong_count: usize, shared: impl FnOnce() -> Shared) {
+        let visits = self.visits.entry(addr).or_insert(0);
+        *visits += 1;
+        if *visits == strong_count {
+            self.ready.push(shared());
+        }
+    }
+
+    fn visit_up_value(&mut self, uv: &UpValue) {
+        let addr = Rc::as_ptr(&uv.inner) as usize;
+        self.visit(addr, Rc::strong_count(&uv.inner), || {
+            Shared::UpValue(uv.clone())
+        });
+    }
+
+    fn release_ready(&mut self) {
+        while let Some(shared) = self.ready.pop() {
+            match shared {
+                Shared::Slice(s) => ref_sub_one(s.as_gos_slice().unwrap().0.array(), self),
+                Shared::Interface(i) => {
+                    if let Some(v) = i.as_interface().unwrap().underlying_value() {
+                        ref_sub_one(v, self);
+                    }
+                }
+                Shared::UpValue(uv) => {
+                    if let UpValueState::Closed(v) = &uv.inner.borrow() as &UpValueState {
+                        ref_sub_one(v, self);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// returns the strong count of the Rc that r was turned into with Rc::into_raw
+fn strong_count_of_raw<T>(r: &T) -> usize {
+    let rc = ManuallyDrop::new(unsafe { Rc::from_raw(r as *const T) });
+    Rc::strong_count(&rc)
+}
+
+/// ref_sub_one removes the reference held by a scanned object from what val
+/// refers to.
+fn ref_sub_one(val: &GosValue, shared: &mut SharedRefs) {
+    match val.typ() {
+        ValueType::Array | ValueType::Struct | ValueType::Closure | ValueType::Map => {
+            if !val.is_nil() {
+                val.set_rc(val.rc() - 1);
+            }
+        }
+        ValueType::Slice => {
+            if let Some(s) = val.as_gos_slice() {
+                shared.visit(s as *const _ as usize, strong_count_of_raw(s), || {
+                    Shared::Slice(val.clone())
+                });
+            }
+        }
+        ValueType::Interface => {
+            if let Some(i) = val.as_interface() {
+                shared.visit(i as *const _ as usize, strong_count_of_raw(i), || {
+                    Shared::Interface(val.clone())
+                });
+            }
+        }
+        ValueType::Pointer => match val.as_pointer() {
+            Some(PointerObj::UpVal(uv)) => shared.visit_up_value(uv),
+            Some(PointerObj::SliceMember(s, _)) | Some(PointerObj::StructField(s, _)) => {
+                ref_sub_one(s, shared)
+            }
+            _ => {}
+        },
+        ValueType::UnsafePtr => {
+            val.as_unsafe_ptr().map(|p| p.ptr().ref_sub_one());
+        }
+        _ => {}
+    }
+}
+
+fn children_ref_sub_one(val: &GosValue, shared: &mut SharedRefs) {
     match val.typ() {
         ValueType::Array => val
             .as_gos_array()
             .0
             .borrow_data_mut()
             .iter()
-            .for_each(|obj| obj.ref_sub_one()),
+            .for_each(|obj| ref_sub_one(&obj.borrow(), shared)),
         ValueType::Closure => match val.as_closure() {
-            Some(cls) => cls.0.ref_sub_one(),
+            Some(cls) => {
+                if let ClosureObj::Gos(obj) = &cls.0 {
+                    if let Some(uvs) = &obj.uvs {
+                        uvs.values().for_each(|uv| shared.visit_up_value(uv));
+                    }
+                    if let Some(recv) = &obj.recv {
+                        ref_sub_one(recv, shared);
+                    }
+                }
+            }
             None => {}
         },
         ValueType::Map => match val.as_map() {
             Some(m) => m.0.borrow_data().iter().for_each(|(k, v)| {
-                k.ref_sub_one();
-                v.ref_sub_one();
+                ref_sub_one(k, shared);
+                ref_sub_one(v, shared);
             }),
             None => {}
         },
@@ -125,7 +362,7 @@ fn children_ref_sub_one(val: &GosValue) {
             .0
             .borrow_fields()
             .iter()
-            .for_each(|obj| obj.ref_sub_one()),
+            .for_each(|obj| ref_sub_one(obj, shared)),
         _ => unreachable!(),
     };
 }
@@ -175,37 +412,30 @@ fn break_cycle(val: &GosValue) {
 
 /// put the non-zero-rc on the left, and the others on the right
 fn partition_to_scan(to_scan: &mut Vec<GosValue>) -> usize {
-    let len = to_scan.len();
-    if len == 0 {
-        return 0;
-    }
-    let mut p0 = 0;
-    let mut p1 = len - 1;
-    loop {
-        while p0 < len - 1 && to_scan[p0].rc() > 0 {
-            p0 += 1;
-        }
-        while p1 > 1 && to_scan[p1].rc() <= 0 {
-            p1 -= 1;
-        }
-        if p0 >= p1 {
-            break;
+    let mut boundary = 0;
+    for i in 0..to_scan.len() {
+        if to_scan[i].rc() > 0 {
+            to_scan.swap(boundary, i);
+            boundary += 1;
         }
-        to_scan.swap(p0, p1);
     }
-    p0
+    boundary
 }
 
 pub fn gc(objs: &GcoVec) {
+    let start = Instant::now();
     let mut to_scan: Vec<GosValue> = objs
         .borrow_data()
         .iter()
         .filter_map(|o| o.to_gosv())
         .collect();
     //print!("objs before GC: {}\n", to_scan.len());
+    let mut shared = SharedRefs::default();
     for v in to_scan.iter() {
-        children_ref_sub_one(v);
+        children_ref_sub_one(v, &mut shared);
     }
+    shared.release_ready();
+    drop(shared);
 
     let boundary = partition_to_scan(&mut to_scan);
     for i in boundary..to_scan.len() {
@@ -233,10 +463,6 @@ pub fn gc(objs: &GcoVec) {
         }
     }
 
-    let _result: Vec<GosValue> = objs
-        .borrow_data()
-        .iter()
-        .filter_map(|o| o.to_gosv())
-        .collect();
-    //print!("objs left after GC: {}\n", result.len());
+    objs.compact(start.elapsed());
+    //print!("objs left after GC: {}\n", objs.stats().live);
 }
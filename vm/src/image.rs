@@ -0,0 +1,1505 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Bytecode images, the generated code serialized so that it can be cached
+//! or shipped without the source and run later.
+//!
+//! An image is laid out as:
+//!
+//! | magic "GOSB" | version u16 | flags u16 | section count u32 |
+//! | section id u8 | stored size u64 | size u64 | data |, once per section
+//! | CRC-32 of all the above u32 | signature size u32 | signature |
+//!
+//! The sections are the metadata, the code of the functions with the
+//! interface bindings, the consts and package members, and optionally the
+//! debug info: the positions of the instructions and the named locals of the
+//! functions that were not stripped, and the source files. Loading the debug
+//! info can be put off until it's needed with `read_image_without_debug`.
+//! With the `zstd` feature the sections are compressed. Object keys are
+//! stored as indices, the loader maps them to the keys of the objects it
+//! creates.
+//!
+//! The checksum only catches corrupted images, the loader doesn't check the
+//! instructions beyond their opcodes. Images from untrusted sources should
+//! be signed with an `ImageSigner` and checked with an `ImageVerifier`.
+
+use super::gc::GcoVec;
+use super::instruction::{Instruction, OpIndex, Opcode, ValueType};
+use super::metadata::*;
+use super::value::*;
+use super::vm::ByteCode;
+use goscript_parser::FileSet;
+use slotmap::Key;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// Images of other versions are rejected by `read_image`
+pub const FORMAT_VERSION: u16 = 8;
+
+const MAGIC: &[u8; 4] = b"GOSB";
+const FLAG_COMPRESSED: u16 = 1;
+
+// stands for null keys, like the package of the entry function
+const NULL_INDEX: u32 = u32::MAX;
+
+const SECTION_METADATA: u8 = 1;
+const SECTION_CODE: u8 = 2;
+const SECTION_CONSTS: u8 = 3;
+const SECTION_DEBUG: u8 = 4;
+
+// the largest a section may be once decompressed
+const MAX_SECTION_SIZE: usize = 1 << 30;
+
+/// Signs the images as they are written.
+pub trait ImageSigner {
+    /// Returns the signature of `payload`, the image up to and including
+    /// its checksum.
+    fn sign(&self, payload: &[u8]) -> Vec<u8>;
+}
+
+/// Checks the signatures of the images before they are loaded.
+pub trait ImageVerifier {
+    fn verify(&self, payload: &[u8], signature: &[u8]) -> bool;
+}
+
+/// Serializes `code`, the debug section is written if `fs`, the files the
+/// code is generated from, is given.
+pub fn write_image(
+    code: &ByteCode,
+    fs: Option<&FileSet>,
+    signer: Option<&dyn ImageSigner>,
+) -> RuntimeResult<Vec<u8>> {
+    let mut w = Writer::new(code);
+    let mut sections = vec![];
+    w.write_metadata()?;
+    sections.push((SECTION_METADATA, w.take()));
+    w.write_code()?;
+    sections.push((SECTION_CODE, w.take()));
+    w.write_consts()?;
+    sections.push((SECTION_CONSTS, w.take()));
+    if let Some(fs) = fs {
+        w.write_debug(fs);
+        sections.push((SECTION_DEBUG, w.take()));
+    }
+
+    let compressed = cfg!(feature = "zstd");
+    let mut image = MAGIC.to_vec();
+    image.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    let flags = if compressed { FLAG_COMPRESSED } else { 0 };
+    image.extend_from_slice(&flags.to_le_bytes());
+    image.extend_from_slice(&(sections.len() as u32).to_le_bytes());
+    for (id, data) in sections.into_iter() {
+        let stored = if compressed {
+            compress(&data)?
+        } else {
+            data.clone()
+        };
+        image.push(id);
+        image.extend_from_slice(&(stored.len() as u64).to_le_bytes());
+        image.extend_from_slice(&(data.len() as u64).to_le_bytes());
+        image.extend_from_slice(&stored);
+    }
+    let checksum = crc32(&image);
+    image.extend_from_slice(&checksum.to_le_bytes());
+    let signature = signer.map_or(vec![], |s| s.sign(&image));
+    image.extend_from_slice(&(signature.len() as u32).to_le_bytes());
+    image.extend_from_slice(&signature);
+    Ok(image)
+}
+
+/// Loads an image written by `write_image`, with the files of the debug
+/// section if it has one. If `verifier` is given, images without a valid
+/// signature are rejected.
+///
+/// Only the opcodes of the instructions are checked, not the indices of the
+/// consts, functions and other objects in their operands: running a tampered
+/// image can panic the host. Only images that are signed and verified, or
+/// come from a trusted source, are safe to load.
+pub fn read_image(
+    image: &[u8],
+    verifier: Option<&dyn ImageVerifier>,
+) -> RuntimeResult<(ByteCode, Option<FileSet>)> {
+    load_image(image, verifier, true)
+}
+
+/// Loads an image like `read_image` but leaves its debug section out, for
+/// `read_image_debug` to load it only if it's needed. The same as for
+/// `read_image`, only signed or trusted images are safe to load.
+pub fn read_image_without_debug(
+    image: &[u8],
+    verifier: Option<&dyn ImageVerifier>,
+) -> RuntimeResult<ByteCode> {
+    load_image(image, verifier, false).map(|(code, _)| code)
+}
+
+/// Loads the debug section of `image` into `code`, loaded from it by
+/// `read_image_without_debug`. Returns the files of the section, None if
+/// the image has none.
+pub fn read_image_debug(image: &[u8], code: &mut ByteCode) -> RuntimeResult<Option<FileSet>> {
+    let mut sections = Sections::read(image, None)?;
+    let debug = match sections.get(SECTION_DEBUG)? {
+        Some(d) => d,
+        None => return Ok(None),
+    };
+    let funcs = &mut code.objects.functions;
+    let lens: Vec<usize> = funcs.values().map(|f| f.code().len()).collect();
+    let (infos, fs) = read_debug(&mut Cursor::new(&debug), &lens)?;
+    // the functions are in the order they are loaded in
+    for ((_, f), info) in funcs.iter_mut().zip(infos.into_iter()) {
+        if let Some(info) = info {
+            f.set_debug(info);
+        }
+    }
+    Ok(Some(fs))
+}
+
+fn load_image(
+    image: &[u8],
+    verifier: Option<&dyn ImageVerifier>,
+    with_debug: bool,
+) -> RuntimeResult<(ByteCode, Option<FileSet>)> {
+    let mut sections = Sections::read(image, verifier)?;
+    let mut required = |id: u8| {
+        sections
+            .get(id)?
+            .ok_or_else(|| format!("bytecode image section {} is missing", id))
+    };
+    let metadata = required(SECTION_METADATA)?;
+    let code = required(SECTION_CODE)?;
+    let consts = required(SECTION_CONSTS)?;
+    let debug = match with_debug {
+        true => sections.get(SECTION_DEBUG)?,
+        false => None,
+    };
+
+    let mut l = Loader::new();
+    let s_meta = l.read_metadata(&mut Cursor::new(&metadata))?;
+    let (packages, ifaces, entry) = l.read_code(&mut Cursor::new(&code))?;
+    l.read_consts(&mut Cursor::new(&consts))?;
+    let (infos, fs) = match debug {
+        Some(d) => {
+            let lens: Vec<usize> = l.code.iter().map(|(code, _)| code.len()).collect();
+            let (infos, fs) = read_debug(&mut Cursor::new(&d), &lens)?;
+            (infos, Some(fs))
+        }
+        None => (vec![], None),
+    };
+    for (method, index) in std::mem::take(&mut l.method_fixups).into_iter() {
+        method.borrow_mut().func = Some(*l.func(index)?);
+    }
+    let mut infos = infos.into_iter();
+    let code = std::mem::take(&mut l.code);
+    for (key, (code, local_alloc)) in l.func_keys.iter().zip(code.into_iter()) {
+        let info = infos.next().flatten();
+        l.functions[*key].set_code(code, info, local_alloc);
+    }
+    let objects = VMObjects {
+        metas: l.metas,
+        functions: l.functions,
+        packages: l.packages,
+        s_meta: s_meta,
+    };
+    let code = ByteCode {
+        objects: Box::pin(objects),
+        packages: packages,
+        ifaces: ifaces,
+        entry: entry,
+    };
+    Ok((code, fs))
+}
+
+/// The sections of an image, checked against its checksum and signature
+struct Sections<'a> {
+    flags: u16,
+    data: HashMap<u8, (&'a [u8], usize)>,
+}
+
+impl<'a> Sections<'a> {
+    fn read(image: &'a [u8], verifier: Option<&dyn ImageVerifier>) -> RuntimeResult<Sections<'a>> {
+        let mut c = Cursor::new(image);
+        if c.bytes(MAGIC.len())? != MAGIC {
+            return Err("not a bytecode image".to_owned());
+        }
+        let version = c.u16()?;
+        if version != FORMAT_VERSION {
+            return Err(format!(
+                "bytecode image version {} is not supported, expected {}",
+                version, FORMAT_VERSION
+            ));
+        }
+        let flags = c.u16()?;
+        if flags & !FLAG_COMPRESSED != 0 {
+            return Err(format!("unknown bytecode image flags: {:x}", flags));
+        }
+        let count = c.u32()?;
+        let mut data = HashMap::new();
+        for _ in 0..count {
+            let id = c.u8()?;
+            let stored = c.usize()?;
+            let size = c.usize()?;
+            if size > MAX_SECTION_SIZE {
+                return Err(format!("bytecode image section {} is too large", id));
+            }
+            data.insert(id, (c.bytes(stored)?, size));
+        }
+        let checksum = crc32(&image[..c.pos]);
+        if c.u32()? != checksum {
+            return Err("bytecode image checksum mismatch".to_owned());
+        }
+        let payload = &image[..c.pos];
+        let sig_len = c.u32()? as usize;
+        let signature = c.bytes(sig_len)?;
+        if c.pos != image.len() {
+            return Err("unexpected data after the bytecode image".to_owned());
+        }
+        if let Some(v) = verifier {
+            if !v.verify(payload, signature) {
+                return Err("bytecode image signature rejected".to_owned());
+            }
+        }
+        Ok(Sections {
+            flags: flags,
+            data: data,
+        })
+    }
+
+    /// Takes the data of the section `id`, decompressed
+    fn get(&mut self, id: u8) -> RuntimeResult<Option<Vec<u8>>> {
+        match self.data.remove(&id) {
+            Some((data, size)) => {
+                let data = if self.flags & FLAG_COMPRESSED != 0 {
+                    decompress(data, size)?
+                } else {
+                    data.to_vec()
+                };
+                match data.len() == size {
+                    true => Ok(Some(data)),
+                    false => Err(format!("bytecode image section {} has a wrong size", id)),
+                }
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+/// Reads the debug section, the debug info of the functions whose code
+/// have the lengths `lens`, and the files
+fn read_debug(c: &mut Cursor, lens: &[usize]) -> RuntimeResult<(Vec<Option<DebugInfo>>, FileSet)> {
+    if c.len()? != lens.len() {
+        return Err("the debug info doesn't match the functions".to_owned());
+    }
+    let mut infos = Vec::with_capacity(lens.len());
+    for &code_len in lens.iter() {
+        let info = c.option(|c| {
+            let len = c.len()?;
+            if len != code_len {
+                return Err("the positions don't match the code".to_owned());
+            }
+            let mut pos = Vec::with_capacity(len);
+            for _ in 0..len {
+                pos.push(c.u64()?.checked_sub(1).map(|x| x as usize));
+            }
+            let mut locals = vec![];
+            for _ in 0..c.len()? {
+                locals.push(LocalVar {
+                    name: c.str()?,
+                    index: c.i32()?,
+                    start: c.usize()?,
+                    end: c.usize()?,
+                });
+            }
+            Ok(DebugInfo {
+                pos: pos,
+                locals: locals,
+            })
+        })?;
+        infos.push(info);
+    }
+    let mut fs = FileSet::new();
+    for _ in 0..c.len()? {
+        let name = c.str()?;
+        let base = c.usize()?;
+        let size = c.usize()?;
+        let mut lines = vec![];
+        for _ in 0..c.len()? {
+            lines.push(c.usize()?);
+        }
+        if base < fs.base() || base.checked_add(size).is_none() {
+            return Err(format!("bad file base in bytecode image: {}", name));
+        }
+        let file = fs.add_file(name, Some(base), size);
+        // a new file already has the first line
+        if lines != [0] && !file.set_lines(lines) {
+            return Err(format!("bad line table in bytecode image: {}", file.name()));
+        }
+    }
+    Ok((infos, fs))
+}
+
+type IfaceBindings = (Meta, Vec<Binding4Runtime>);
+
+/// The raw word after an instruction that is an object key
+enum KeyWord {
+    Package,
+    Function,
+}
+
+impl KeyWord {
+    fn after(op: Opcode) -> Option<KeyWord> {
+        match op {
+            Opcode::LOAD_PKG_FIELD
+            | Opcode::STORE_PKG_FIELD
+            | Opcode::REF_PKG_MEMBER
+            | Opcode::LOAD_PKG_INIT => Some(KeyWord::Package),
+            Opcode::BIND_METHOD => Some(KeyWord::Function),
+            _ => None,
+        }
+    }
+}
+
+struct Writer<'a> {
+    objs: &'a VMObjects,
+    code: &'a ByteCode,
+    buf: Vec<u8>,
+    metas: HashMap<MetadataKey, u32>,
+    functions: HashMap<FunctionKey, u32>,
+    packages: HashMap<PackageKey, u32>,
+}
+
+impl<'a> Writer<'a> {
+    fn new(code: &'a ByteCode) -> Writer<'a> {
+        let objs: &VMObjects = &code.objects;
+        fn indices<K: Copy + std::hash::Hash + Eq>(
+            keys: impl Iterator<Item = K>,
+        ) -> HashMap<K, u32> {
+            keys.enumerate().map(|(i, k)| (k, i as u32)).collect()
+        }
+        Writer {
+            objs: objs,
+            code: code,
+            buf: vec![],
+            metas: indices(objs.metas.keys()),
+            functions: indices(objs.functions.keys()),
+            packages: indices(objs.packages.keys()),
+        }
+    }
+
+    fn take(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.buf)
+    }
+
+    fn write_metadata(&mut self) -> RuntimeResult<()> {
+        let objs = self.objs;
+        self.len(objs.metas.len());
+        for (_, mt) in objs.metas.iter() {
+            self.mtype(mt)?;
+        }
+        let s = &objs.s_meta;
+        for m in [
+            &s.mbool,
+            &s.mint,
+            &s.mint8,
+            &s.mint16,
+            &s.mint32,
+            &s.mint64,
+            &s.muint,
+            &s.muint_ptr,
+            &s.muint8,
+            &s.muint16,
+            &s.muint32,
+            &s.muint64,
+            &s.mfloat32,
+            &s.mfloat64,
+            &s.mcomplex64,
+            &s.mcomplex128,
+            &s.mstr,
+            &s.unsafe_ptr,
+            &s.default_sig,
+            &s.empty_iface,
+            &s.empty_iface_slice,
+            &s.empty_iface_map,
+            &s.none,
+            &s.error,
+        ] {
+            self.meta(m)?;
+        }
+        Ok(())
+    }
+
+    fn write_code(&mut self) -> RuntimeResult<()> {
+        let objs = self.objs;
+        self.len(objs.packages.len());
+        // all the functions are created before their code is loaded, as it
+        // refers to them
+        self.len(objs.functions.len());
+        for (_, f) in objs.functions.iter() {
+            self.package(f.package)?;
+            self.meta(&f.meta)?;
+            self.u8(match f.flag {
+                FuncFlag::Default => 0,
+                FuncFlag::PkgCtor => 1,
+                FuncFlag::HasDefer => 2,
+            });
+            self.str(&f.name);
+        }
+        for (_, f) in objs.functions.iter() {
+            self.i32(f.local_alloc());
+            let code = f.code();
+            self.len(code.len());
+            let mut key = None;
+            for inst in code.iter() {
+                let word = match key.take() {
+                    Some(KeyWord::Package) => {
+                        self.package_index(u64_to_key(inst.get_u64()))? as u64
+                    }
+                    Some(KeyWord::Function) => {
+                        self.function_index(u64_to_key(inst.get_u64()))? as u64
+                    }
+                    None => {
+                        key = KeyWord::after(inst.op());
+                        inst.get_u64()
+                    }
+                };
+                self.u64(word);
+            }
+            self.len(f.stack_temp_types.len());
+            for t in f.stack_temp_types.iter() {
+                self.u8(*t as u8);
+            }
+            self.len(f.up_ptrs.len());
+            for d in f.up_ptrs.iter() {
+                self.function(d.func)?;
+                self.i32(d.index);
+                self.u8(d.typ as u8);
+                self.bool(d.is_up_value);
+            }
+        }
+
+        let code = self.code;
+        self.len(code.ifaces.len());
+        for (m, bindings) in code.ifaces.iter() {
+            self.meta(m)?;
+            self.len(bindings.len());
+            for b in bindings.iter() {
+                let indices = match b {
+                    Binding4Runtime::Struct(f, pointer_recv, indices) => {
+                        self.u8(0);
+                        self.function(*f)?;
+                        self.bool(*pointer_recv);
+                        indices
+                    }
+                    Binding4Runtime::Iface(i, indices) => {
+                        self.u8(1);
+                        self.len(*i);
+                        indices
+                    }
+                };
+                self.option(indices.as_ref(), |w, x| {
+                    w.indices(x);
+                    Ok(())
+                })?;
+            }
+        }
+        self.function(code.entry)?;
+        self.len(code.packages.len());
+        for p in code.packages.iter() {
+            self.package(*p)?;
+        }
+        Ok(())
+    }
+
+    fn write_consts(&mut self) -> RuntimeResult<()> {
+        let objs = self.objs;
+        for (_, f) in objs.functions.iter() {
+            self.values(&f.consts)?;
+            self.values(&f.ret_zeros)?;
+            self.values(&f.local_zeros)?;
+        }
+        for (_, p) in objs.packages.iter() {
+            self.len(p.member_count());
+            for i in 0..p.member_count() as OpIndex {
+                self.value(&p.member(i))?;
+                self.u8(p.member_type(i) as u8);
+            }
+            let mut names: Vec<(&String, &OpIndex)> = p.member_indices().iter().collect();
+            names.sort_by_key(|x| *x.1);
+            self.len(names.len());
+            for (name, i) in names.into_iter() {
+                self.str(name);
+                self.i32(*i);
+            }
+            self.values(p.init_funcs())?;
+        }
+        Ok(())
+    }
+
+    fn write_debug(&mut self, fs: &FileSet) {
+        let objs = self.objs;
+        self.len(objs.functions.len());
+        for (_, f) in objs.functions.iter() {
+            match f.debug() {
+                Some(d) => {
+                    self.bool(true);
+                    self.len(d.pos.len());
+                    for p in d.pos.iter() {
+                        self.u64(p.map_or(0, |x| x as u64 + 1));
+                    }
+                    self.len(d.locals.len());
+                    for l in d.locals.iter() {
+                        self.str(&l.name);
+                        self.i32(l.index);
+                        self.len(l.start);
+                        self.len(l.end);
+                    }
+                }
+                None => self.bool(false),
+            }
+        }
+        let files: Vec<_> = fs.iter().collect();
+        self.len(files.len());
+        for f in files.into_iter() {
+            self.str(f.name());
+            self.len(f.base());
+            self.len(f.size());
+            self.len(f.lines().len());
+            for l in f.lines().iter() {
+                self.len(*l);
+            }
+        }
+    }
+
+    fn mtype(&mut self, mt: &MetadataType) -> RuntimeResult<()> {
+        match mt {
+            MetadataType::Bool => self.u8(0),
+            MetadataType::Int => self.u8(1),
+            MetadataType::Int8 => self.u8(2),
+            MetadataType::Int16 => self.u8(3),
+            MetadataType::Int32 => self.u8(4),
+            MetadataType::Int64 => self.u8(5),
+            MetadataType::Uint => self.u8(6),
+            MetadataType::UintPtr => self.u8(7),
+            MetadataType::Uint8 => self.u8(8),
+            MetadataType::Uint16 => self.u8(9),
+            MetadataType::Uint32 => self.u8(10),
+            MetadataType::Uint64 => self.u8(11),
+            MetadataType::Float32 => self.u8(12),
+            MetadataType::Float64 => self.u8(13),
+            MetadataType::Complex64 => self.u8(14),
+            MetadataType::Complex128 => self.u8(15),
+            MetadataType::UnsafePtr => self.u8(16),
+            MetadataType::Str(zero) => {
+                self.u8(17);
+                self.value(zero)?;
+            }
+            MetadataType::Array(m, size) => {
+                self.u8(18);
+                self.meta(m)?;
+                self.len(*size);
+            }
+            MetadataType::Slice(m) => {
+                self.u8(19);
+                self.meta(m)?;
+            }
+            MetadataType::Struct(fields, zero) => {
+                self.u8(20);
+                self.fields(fields)?;
+                self.values(&zero.borrow_fields())?;
+            }
+            MetadataType::Signature(sig) => {
+                self.u8(21);
+                self.option(sig.recv.as_ref(), |w, m| w.meta(m))?;
+                self.metas(&sig.params)?;
+                self.metas(&sig.results)?;
+                self.option(sig.variadic.as_ref(), |w, (a, b)| {
+                    w.meta(a)?;
+                    w.meta(b)
+                })?;
+                self.len(sig.params_type.len());
+                for t in sig.params_type.iter() {
+                    self.u8(*t as u8);
+                }
+            }
+            MetadataType::Map(k, v) => {
+                self.u8(22);
+                self.meta(k)?;
+                self.meta(v)?;
+            }
+            MetadataType::Interface(fields) => {
+                self.u8(23);
+                self.fields(fields)?;
+            }
+            MetadataType::Channel(t, m) => {
+                self.u8(24);
+                self.u8(match t {
+                    ChannelType::Send => 0,
+                    ChannelType::Recv => 1,
+                    ChannelType::SendRecv => 2,
+                });
+                self.meta(m)?;
+            }
+            MetadataType::Named(methods, underlying, name) => {
+                self.u8(25);
+                self.len(methods.members.len());
+                for m in methods.members.iter() {
+                    let m = m.borrow();
+                    self.bool(m.pointer_recv);
+                    self.option(m.func.as_ref(), |w, f| w.function(*f))?;
+                }
+                let mut mapping: Vec<(&String, &OpIndex)> = methods.mapping.iter().collect();
+                mapping.sort();
+                self.len(mapping.len());
+                for (name, i) in mapping.into_iter() {
+                    self.str(name);
+                    self.i32(*i);
+                }
+                self.meta(underlying)?;
+                self.str(name);
+            }
+            MetadataType::None => self.u8(26),
+        }
+        Ok(())
+    }
+
+    fn fields(&mut self, fields: &Fields) -> RuntimeResult<()> {
+        self.len(fields.all().len());
+        for f in fields.all().iter() {
+            self.meta(&f.meta)?;
+            self.str(&f.name);
+            self.bool(f.exported);
+            self.bool(f.embedded);
+            self.option(f.tag.as_ref(), |w, t| {
+                w.str(t);
+                Ok(())
+            })?;
+        }
+        let mut mapping: Vec<(&String, &Vec<usize>)> = fields.mapping().iter().collect();
+        mapping.sort();
+        self.len(mapping.len());
+        for (name, indices) in mapping.into_iter() {
+            self.str(name);
+            self.indices(indices);
+        }
+        Ok(())
+    }
+
+    fn values(&mut self, vals: &[GosValue]) -> RuntimeResult<()> {
+        self.len(vals.len());
+        for v in vals.iter() {
+            self.value(v)?;
+        }
+        Ok(())
+    }
+
+    fn value(&mut self, val: &GosValue) -> RuntimeResult<()> {
+        let t = val.typ();
+        self.u8(t as u8);
+        self.u8(val.t_elem() as u8);
+        match t {
+            ValueType::Void => {}
+            ValueType::Bool => self.bool(*val.as_bool()),
+            ValueType::Int => self.u64(*val.as_int() as u64),
+            ValueType::Int8 => self.u64(*val.as_int8() as u64),
+            ValueType::Int16 => self.u64(*val.as_int16() as u64),
+            ValueType::Int32 => self.u64(*val.as_int32() as u64),
+            ValueType::Int64 => self.u64(*val.as_int64() as u64),
+            ValueType::Uint => self.u64(*val.as_uint() as u64),
+            ValueType::UintPtr => self.u64(*val.as_uint_ptr() as u64),
+            ValueType::Uint8 => self.u64(*val.as_uint8() as u64),
+            ValueType::Uint16 => self.u64(*val.as_uint16() as u64),
+            ValueType::Uint32 => self.u64(*val.as_uint32() as u64),
+            ValueType::Uint64 => self.u64(*val.as_uint64()),
+            ValueType::Float32 => self.u64(val.as_float32().into_inner().to_bits() as u64),
+            ValueType::Float64 => self.u64(val.as_float64().into_inner().to_bits()),
+            ValueType::Complex64 => {
+                let c = val.as_complex64();
+                self.u64(c.r.into_inner().to_bits() as u64);
+                self.u64(c.i.into_inner().to_bits() as u64);
+            }
+            ValueType::Complex128 => {
+                let c = val.as_complex128();
+                self.u64(c.r.into_inner().to_bits());
+                self.u64(c.i.into_inner().to_bits());
+            }
+            ValueType::Function => self.function(*val.as_function())?,
+            ValueType::Package => self.package(*val.as_package())?,
+            ValueType::Metadata => self.meta(val.as_metadata())?,
+            ValueType::String => self.str(&StrUtil::as_str(val.as_string())),
+            ValueType::Array => {
+                let len = val.len();
+                self.len(len);
+                for i in 0..len {
+                    self.value(&val.dispatcher_a_s().array_get(val, i)?)?;
+                }
+            }
+            ValueType::Struct => self.values(&val.as_struct().0.borrow_fields())?,
+            ValueType::Pointer
+            | ValueType::UnsafePtr
+            | ValueType::Closure
+            | ValueType::Slice
+            | ValueType::Map
+            | ValueType::Interface
+            | ValueType::Channel
+                if val.is_nil() =>
+            {
+                self.bool(false)
+            }
+            // maps of constant literals
+            ValueType::Map => {
+                let map = &val.as_map().unwrap().0;
+                self.bool(true);
+                self.value(map.zero_val())?;
+                let data = map.borrow_data();
+                let mut entries: Vec<(&GosValue, &GosValue)> = data.iter().collect();
+                entries.sort();
+                self.len(entries.len());
+                for (k, v) in entries.into_iter() {
+                    self.value(k)?;
+                    self.value(v)?;
+                }
+            }
+            // closures of functions without receivers, which are the only
+            // ones codegen creates
+            ValueType::Closure => match val.as_closure() {
+                Some((ClosureObj::Gos(c), _)) if c.recv.is_none() => {
+                    self.bool(true);
+                    self.function(c.func)?;
+                }
+                _ => return Err("can not serialize closures with receivers".to_owned()),
+            },
+            _ => return Err(format!("can not serialize non-nil values of type {:?}", t)),
+        }
+        Ok(())
+    }
+
+    fn metas(&mut self, metas: &[Meta]) -> RuntimeResult<()> {
+        self.len(metas.len());
+        for m in metas.iter() {
+            self.meta(m)?;
+        }
+        Ok(())
+    }
+
+    fn meta(&mut self, m: &Meta) -> RuntimeResult<()> {
+        let i = *self.metas.get(&m.key).ok_or("metadata not found")?;
+        self.u32(i);
+        self.u8(m.ptr_depth);
+        self.bool(m.is_type);
+        Ok(())
+    }
+
+    fn function(&mut self, key: FunctionKey) -> RuntimeResult<()> {
+        let i = self.function_index(key)?;
+        self.u32(i);
+        Ok(())
+    }
+
+    fn package(&mut self, key: PackageKey) -> RuntimeResult<()> {
+        let i = self.package_index(key)?;
+        self.u32(i);
+        Ok(())
+    }
+
+    fn function_index(&self, key: FunctionKey) -> RuntimeResult<u32> {
+        self.functions
+            .get(&key)
+            .copied()
+            .ok_or_else(|| "function not found".to_owned())
+    }
+
+    fn package_index(&self, key: PackageKey) -> RuntimeResult<u32> {
+        if key.is_null() {
+            return Ok(NULL_INDEX);
+        }
+        self.packages
+            .get(&key)
+            .copied()
+            .ok_or_else(|| "package not found".to_owned())
+    }
+
+    fn option<T>(
+        &mut self,
+        val: Option<T>,
+        f: impl FnOnce(&mut Self, T) -> RuntimeResult<()>,
+    ) -> RuntimeResult<()> {
+        self.bool(val.is_some());
+        match val {
+            Some(v) => f(self, v),
+            None => Ok(()),
+        }
+    }
+
+    fn indices(&mut self, indices: &[usize]) {
+        self.len(indices.len());
+        for i in indices.iter() {
+            self.len(*i);
+        }
+    }
+
+    fn str(&mut self, s: &str) {
+        self.len(s.len());
+        self.buf.extend_from_slice(s.as_bytes());
+    }
+
+    fn len(&mut self, n: usize) {
+        self.u64(n as u64)
+    }
+
+    fn bool(&mut self, b: bool) {
+        self.u8(b as u8)
+    }
+
+    fn i32(&mut self, i: i32) {
+        self.u32(i as u32)
+    }
+
+    fn u8(&mut self, u: u8) {
+        self.buf.push(u);
+    }
+
+    fn u32(&mut self, u: u32) {
+        self.buf.extend_from_slice(&u.to_le_bytes());
+    }
+
+    fn u64(&mut self, u: u64) {
+        self.buf.extend_from_slice(&u.to_le_bytes());
+    }
+}
+
+struct Loader {
+    metas: MetadataObjs,
+    functions: FunctionObjs,
+    packages: PackageObjs,
+    meta_keys: Vec<MetadataKey>,
+    func_keys: Vec<FunctionKey>,
+    pkg_keys: Vec<PackageKey>,
+    // methods and the indices of their functions, which are created after
+    // the metadata
+    method_fixups: Vec<(Rc<RefCell<MethodDesc>>, usize)>,
+    // the code and the local count of the functions, set once all are read
+    code: Vec<(Vec<Instruction>, OpIndex)>,
+    // for the zero values created while loading, like codegen's
+    gcv: GcoVec,
+}
+
+impl Loader {
+    fn new() -> Loader {
+        Loader {
+            metas: MetadataObjs::with_key(),
+            functions: FunctionObjs::with_key(),
+            packages: PackageObjs::with_key(),
+            meta_keys: vec![],
+            func_keys: vec![],
+            pkg_keys: vec![],
+            method_fixups: vec![],
+            code: vec![],
+            gcv: GcoVec::new(),
+        }
+    }
+
+    fn read_metadata(&mut self, c: &mut Cursor) -> RuntimeResult<StaticMeta> {
+        let count = c.len()?;
+        for _ in 0..count {
+            self.meta_keys.push(self.metas.insert(MetadataType::None));
+        }
+        for i in 0..count {
+            let mt = self.mtype(c)?;
+            self.metas[self.meta_keys[i]] = mt;
+        }
+        Ok(StaticMeta {
+            mbool: self.meta(c)?,
+            mint: self.meta(c)?,
+            mint8: self.meta(c)?,
+            mint16: self.meta(c)?,
+            mint32: self.meta(c)?,
+            mint64: self.meta(c)?,
+            muint: self.meta(c)?,
+            muint_ptr: self.meta(c)?,
+            muint8: self.meta(c)?,
+            muint16: self.meta(c)?,
+            muint32: self.meta(c)?,
+            muint64: self.meta(c)?,
+            mfloat32: self.meta(c)?,
+            mfloat64: self.meta(c)?,
+            mcomplex64: self.meta(c)?,
+            mcomplex128: self.meta(c)?,
+            mstr: self.meta(c)?,
+            unsafe_ptr: self.meta(c)?,
+            default_sig: self.meta(c)?,
+            empty_iface: self.meta(c)?,
+            empty_iface_slice: self.meta(c)?,
+            empty_iface_map: self.meta(c)?,
+            none: self.meta(c)?,
+            error: self.meta(c)?,
+        })
+    }
+
+    fn read_code(
+        &mut self,
+        c: &mut Cursor,
+    ) -> RuntimeResult<(Vec<PackageKey>, Vec<IfaceBindings>, FunctionKey)> {
+        for _ in 0..c.len()? {
+            self.pkg_keys.push(self.packages.insert(PackageVal::new()));
+        }
+        for _ in 0..c.len()? {
+            let package = self.package(c.len32()?)?;
+            let meta = self.meta(c)?;
+            let flag = match c.u8()? {
+                0 => FuncFlag::Default,
+                1 => FuncFlag::PkgCtor,
+                2 => FuncFlag::HasDefer,
+                x => return Err(format!("bad function flag {}", x)),
+            };
+            let name = c.str()?;
+            if !matches!(&self.metas[meta.key], MetadataType::Signature(_)) {
+                return Err("function metadata is not a signature".to_owned());
+            }
+            let mut func = FunctionVal::new(package, meta, &self.metas, &self.gcv, flag);
+            func.name = name;
+            self.func_keys.push(self.functions.insert(func));
+        }
+        for i in 0..self.func_keys.len() {
+            let local_alloc = c.i32()?;
+            let len = c.len()?;
+            let mut code = Vec::with_capacity(len);
+            let mut key = None;
+            for _ in 0..len {
+                let word = c.u64()?;
+                let inst = match key.take() {
+                    Some(KeyWord::Package) => {
+                        Instruction::from_u64(key_to_u64(self.package(word as usize)?))
+                    }
+                    Some(KeyWord::Function) => {
+                        Instruction::from_u64(key_to_u64(*self.func(word as usize)?))
+                    }
+                    None => {
+                        // the opcode and the types are transmuted from the
+                        // top bytes
+                        if (word >> 56) as u8 >= Opcode::COUNT {
+                            return Err(format!("bad opcode in bytecode image: {}", word >> 56));
+                        }
+                        let inst = Instruction::from_u64(word);
+                        let types = if inst.op().t2_is_index() { 2 } else { 3 };
+                        for i in 0..types {
+                            value_type((word >> (48 - 8 * i)) as u8)?;
+                        }
+                        key = KeyWord::after(inst.op());
+                        inst
+                    }
+                };
+                code.push(inst);
+            }
+            let mut temp_types = vec![];
+            for _ in 0..c.len()? {
+                temp_types.push(value_type(c.u8()?)?);
+            }
+            let mut up_ptrs = vec![];
+            for _ in 0..c.len()? {
+                let func = *self.func(c.len32()?)?;
+                let index = c.i32()?;
+                let typ = value_type(c.u8()?)?;
+                up_ptrs.push(ValueDesc::new(func, index, typ, c.bool()?));
+            }
+            let f = &mut self.functions[self.func_keys[i]];
+            f.stack_temp_types = temp_types;
+            f.up_ptrs = up_ptrs;
+            self.code.push((code, local_alloc));
+        }
+
+        let mut ifaces = vec![];
+        for _ in 0..c.len()? {
+            let meta = self.meta(c)?;
+            let mut bindings = vec![];
+            for _ in 0..c.len()? {
+                let b = match c.u8()? {
+                    0 => {
+                        let f = *self.func(c.len32()?)?;
+                        let pointer_recv = c.bool()?;
+                        Binding4Runtime::Struct(f, pointer_recv, c.option(|c| c.indices())?)
+                    }
+                    1 => {
+                        let i = c.usize()?;
+                        Binding4Runtime::Iface(i, c.option(|c| c.indices())?)
+                    }
+                    x => return Err(format!("bad interface binding {}", x)),
+                };
+                bindings.push(b);
+            }
+            ifaces.push((meta, bindings));
+        }
+        let entry = *self.func(c.len32()?)?;
+        let mut packages = vec![];
+        for _ in 0..c.len()? {
+            packages.push(self.package(c.len32()?)?);
+        }
+        Ok((packages, ifaces, entry))
+    }
+
+    fn read_consts(&mut self, c: &mut Cursor) -> RuntimeResult<()> {
+        for i in 0..self.func_keys.len() {
+            let consts = self.values(c)?;
+            let ret_zeros = self.values(c)?;
+            let local_zeros = self.values(c)?;
+            let f = &mut self.functions[self.func_keys[i]];
+            f.consts = consts;
+            f.ret_zeros = ret_zeros;
+            f.local_zeros = local_zeros;
+        }
+        for i in 0..self.pkg_keys.len() {
+            let mut members = vec![];
+            let mut member_types = vec![];
+            for _ in 0..c.len()? {
+                members.push(self.value(c)?);
+                member_types.push(value_type(c.u8()?)?);
+            }
+            let mut member_indices = HashMap::new();
+            for _ in 0..c.len()? {
+                let name = c.str()?;
+                let index = c.i32()?;
+                if index < 0 || index as usize >= members.len() {
+                    return Err(format!("bad package member index {}", index));
+                }
+                member_indices.insert(name, index);
+            }
+            let init_funcs = self.values(c)?;
+            self.packages[self.pkg_keys[i]] =
+                PackageVal::with_parts(members, member_types, member_indices, init_funcs);
+        }
+        Ok(())
+    }
+
+    fn mtype(&mut self, c: &mut Cursor) -> RuntimeResult<MetadataType> {
+        let mt = match c.u8()? {
+            0 => MetadataType::Bool,
+            1 => MetadataType::Int,
+            2 => MetadataType::Int8,
+            3 => MetadataType::Int16,
+            4 => MetadataType::Int32,
+            5 => MetadataType::Int64,
+            6 => MetadataType::Uint,
+            7 => MetadataType::UintPtr,
+            8 => MetadataType::Uint8,
+            9 => MetadataType::Uint16,
+            10 => MetadataType::Uint32,
+            11 => MetadataType::Uint64,
+            12 => MetadataType::Float32,
+            13 => MetadataType::Float64,
+            14 => MetadataType::Complex64,
+            15 => MetadataType::Complex128,
+            16 => MetadataType::UnsafePtr,
+            17 => MetadataType::Str(self.value(c)?),
+            18 => {
+                let elem = self.meta(c)?;
+                match c.usize()? {
+                    n if n <= MAX_SLICE_LEN => MetadataType::Array(elem, n),
+                    n => return Err(format!("bad array length in bytecode image: {}", n)),
+                }
+            }
+            19 => MetadataType::Slice(self.meta(c)?),
+            20 => {
+                let fields = self.fields(c)?;
+                MetadataType::Struct(fields, StructObj::new(self.values(c)?))
+            }
+            21 => {
+                let recv = c.option(|c| self.meta(c))?;
+                let params = self.metas(c)?;
+                let results = self.metas(c)?;
+                let variadic = c.option(|c| Ok((self.meta(c)?, self.meta(c)?)))?;
+                let mut params_type = vec![];
+                for _ in 0..c.len()? {
+                    params_type.push(value_type(c.u8()?)?);
+                }
+                MetadataType::Signature(SigMetadata {
+                    recv: recv,
+                    params: params,
+                    results: results,
+                    variadic: variadic,
+                    params_type: params_type,
+                })
+            }
+            22 => MetadataType::Map(self.meta(c)?, self.meta(c)?),
+            23 => MetadataType::Interface(self.fields(c)?),
+            24 => {
+                let t = match c.u8()? {
+                    0 => ChannelType::Send,
+                    1 => ChannelType::Recv,
+                    2 => ChannelType::SendRecv,
+                    x => return Err(format!("bad channel type {}", x)),
+                };
+                MetadataType::Channel(t, self.meta(c)?)
+            }
+            25 => {
+                let mut methods = Methods::new();
+                for _ in 0..c.len()? {
+                    let pointer_recv = c.bool()?;
+                    let func = c.option(|c| c.len32())?;
+                    let desc = Rc::new(RefCell::new(MethodDesc {
+                        pointer_recv: pointer_recv,
+                        func: None,
+                    }));
+                    if let Some(i) = func {
+                        self.method_fixups.push((desc.clone(), i));
+                    }
+                    methods.members.push(desc);
+                }
+                for _ in 0..c.len()? {
+                    let name = c.str()?;
+                    methods.mapping.insert(name, c.i32()?);
+                }
+                let underlying = self.meta(c)?;
+                MetadataType::Named(methods, underlying, c.str()?)
+            }
+            26 => MetadataType::None,
+            x => return Err(format!("bad metadata type {}", x)),
+        };
+        Ok(mt)
+    }
+
+    fn fields(&mut self, c: &mut Cursor) -> RuntimeResult<Fields> {
+        let mut fields = vec![];
+        for _ in 0..c.len()? {
+            fields.push(FieldInfo {
+                meta: self.meta(c)?,
+                name: c.str()?,
+                exported: c.bool()?,
+                embedded: c.bool()?,
+                tag: c.option(|c| c.str())?,
+            });
+        }
+        let mut mapping = HashMap::new();
+        for _ in 0..c.len()? {
+            let name = c.str()?;
+            mapping.insert(name, c.indices()?);
+        }
+        Ok(Fields::new(fields, mapping))
+    }
+
+    fn values(&mut self, c: &mut Cursor) -> RuntimeResult<Vec<GosValue>> {
+        let mut vals = vec![];
+        for _ in 0..c.len()? {
+            vals.push(self.value(c)?);
+        }
+        Ok(vals)
+    }
+
+    fn value(&mut self, c: &mut Cursor) -> RuntimeResult<GosValue> {
+        let t = value_type(c.u8()?)?;
+        let t_elem = value_type(c.u8()?)?;
+        let val = match t {
+            ValueType::Void => GosValue::new_nil(ValueType::Void),
+            ValueType::Bool => GosValue::new_bool(c.bool()?),
+            ValueType::Int => GosValue::new_int(c.u64()? as isize),
+            ValueType::Int8 => GosValue::new_int8(c.u64()? as i8),
+            ValueType::Int16 => GosValue::new_int16(c.u64()? as i16),
+            ValueType::Int32 => GosValue::new_int32(c.u64()? as i32),
+            ValueType::Int64 => GosValue::new_int64(c.u64()? as i64),
+            ValueType::Uint => GosValue::new_uint(c.u64()? as usize),
+            ValueType::UintPtr => GosValue::new_uint_ptr(c.u64()? as usize),
+            ValueType::Uint8 => GosValue::new_uint8(c.u64()? as u8),
+            ValueType::Uint16 => GosValue::new_uint16(c.u64()? as u16),
+            ValueType::Uint32 => GosValue::new_uint32(c.u64()? as u32),
+            ValueType::Uint64 => GosValue::new_uint64(c.u64()?),
+            ValueType::Float32 => GosValue::new_float32(f32::from_bits(c.u64()? as u32).into()),
+            ValueType::Float64 => GosValue::new_float64(f64::from_bits(c.u64()?).into()),
+            ValueType::Complex64 => {
+                let r = f32::from_bits(c.u64()? as u32);
+                let i = f32::from_bits(c.u64()? as u32);
+                GosValue::new_complex64(r.into(), i.into())
+            }
+            ValueType::Complex128 => {
+                let r = f64::from_bits(c.u64()?);
+                let i = f64::from_bits(c.u64()?);
+                GosValue::new_complex128(r.into(), i.into())
+            }
+            ValueType::Function => GosValue::new_function(*self.func(c.len32()?)?),
+            ValueType::Package => GosValue::new_package(self.package(c.len32()?)?),
+            ValueType::Metadata => GosValue::new_metadata(self.meta(c)?),
+            ValueType::String => GosValue::with_str(&c.str()?),
+            ValueType::Array => {
+                if t_elem == ValueType::Void {
+                    return Err("array without element type".to_owned());
+                }
+                let data = self.values(c)?;
+                GosValue::array_with_data(data, t_elem, &self.gcv)
+            }
+            ValueType::Struct => {
+                let fields = self.values(c)?;
+                GosValue::new_struct(StructObj::new(fields), &self.gcv)
+            }
+            ValueType::Pointer
+            | ValueType::UnsafePtr
+            | ValueType::Closure
+            | ValueType::Slice
+            | ValueType::Map
+            | ValueType::Interface
+            | ValueType::Channel => match (t, c.bool()?) {
+                (ValueType::Slice, false) => GosValue::new_slice_nil(t_elem),
+                (_, false) => GosValue::new_nil(t),
+                (ValueType::Map, true) => {
+                    let map = GosValue::map_with_default_val(self.value(c)?, &self.gcv);
+                    for _ in 0..c.len()? {
+                        let k = self.value(c)?;
+                        let v = self.value(c)?;
+                        map.as_map().unwrap().0.insert(k, v);
+                    }
+                    map
+                }
+                (ValueType::Closure, true) => {
+                    let f = *self.func(c.len32()?)?;
+                    GosValue::new_closure_static(f, &self.functions)
+                }
+                _ => return Err(format!("bad value of type {:?} in bytecode image", t)),
+            },
+            _ => return Err(format!("bad value of type {:?} in bytecode image", t)),
+        };
+        Ok(val)
+    }
+
+    fn metas(&mut self, c: &mut Cursor) -> RuntimeResult<Vec<Meta>> {
+        let mut metas = vec![];
+        for _ in 0..c.len()? {
+            metas.push(self.meta(c)?);
+        }
+        Ok(metas)
+    }
+
+    fn meta(&self, c: &mut Cursor) -> RuntimeResult<Meta> {
+        let i = c.u32()? as usize;
+        let key = *self.meta_keys.get(i).ok_or("metadata index out of range")?;
+        Ok(Meta::new(key, c.u8()?, c.bool()?))
+    }
+
+    fn func(&self, i: usize) -> RuntimeResult<&FunctionKey> {
+        self.func_keys
+            .get(i)
+            .ok_or_else(|| "function index out of range".to_owned())
+    }
+
+    fn package(&self, i: usize) -> RuntimeResult<PackageKey> {
+        if i == NULL_INDEX as usize {
+            return Ok(PackageKey::null());
+        }
+        self.pkg_keys
+            .get(i)
+            .copied()
+            .ok_or_else(|| "package index out of range".to_owned())
+    }
+}
+
+struct Cursor<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(data: &'a [u8]) -> Cursor<'a> {
+        Cursor { data: data, pos: 0 }
+    }
+
+    fn bytes(&mut self, n: usize) -> RuntimeResult<&'a [u8]> {
+        match self.pos.checked_add(n) {
+            Some(end) if end <= self.data.len() => {
+                let b = &self.data[self.pos..end];
+                self.pos = end;
+                Ok(b)
+            }
+            _ => Err("bytecode image is truncated".to_owned()),
+        }
+    }
+
+    fn u8(&mut self) -> RuntimeResult<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> RuntimeResult<u16> {
+        Ok(u16::from_le_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> RuntimeResult<u32> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> RuntimeResult<u64> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+
+    fn i32(&mut self) -> RuntimeResult<i32> {
+        Ok(self.u32()? as i32)
+    }
+
+    fn bool(&mut self) -> RuntimeResult<bool> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            x => Err(format!("bad bool in bytecode image: {}", x)),
+        }
+    }
+
+    /// A count of things in the image written as u64, each of them takes
+    /// a byte at least, so it can't be more than the bytes left
+    fn len(&mut self) -> RuntimeResult<usize> {
+        let n = self.usize()?;
+        match n <= self.data.len() - self.pos {
+            true => Ok(n),
+            false => Err(format!("bad length in bytecode image: {}", n)),
+        }
+    }
+
+    /// A size or an index written as u64
+    fn usize(&mut self) -> RuntimeResult<usize> {
+        let n = self.u64()?;
+        usize::try_from(n).map_err(|_| format!("bad number in bytecode image: {}", n))
+    }
+
+    /// An object index written as u32
+    fn len32(&mut self) -> RuntimeResult<usize> {
+        Ok(self.u32()? as usize)
+    }
+
+    fn str(&mut self) -> RuntimeResult<String> {
+        let n = self.len()?;
+        String::from_utf8(self.bytes(n)?.to_vec())
+            .map_err(|_| "bad string in bytecode image".to_owned())
+    }
+
+    fn indices(&mut self) -> RuntimeResult<Vec<usize>> {
+        let mut indices = vec![];
+        for _ in 0..self.len()? {
+            indices.push(self.usize()?);
+        }
+        Ok(indices)
+    }
+
+    fn option<T>(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> RuntimeResult<T>,
+    ) -> RuntimeResult<Option<T>> {
+        match self.bool()? {
+            true => Ok(Some(f(self)?)),
+            false => Ok(None),
+        }
+    }
+}
+
+fn value_type(b: u8) -> RuntimeResult<ValueType> {
+    if b > ValueType::FlagE as u8 {
+        return Err(format!("bad value type in bytecode image: {}", b));
+    }
+    Ok(unsafe { std::mem::transmute::<u8, ValueType>(b) })
+}
+
+/// CRC-32 (IEEE)
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &b in data.iter() {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb8_8320 & 0u32.wrapping_sub(crc & 1));
+        }
+    }
+    !crc
+}
+
+#[cfg(feature = "zstd")]
+fn compress(data: &[u8]) -> RuntimeResult<Vec<u8>> {
+    zstd::bulk::compress(data, 0).map_err(|e| e.to_string())
+}
+
+#[cfg(not(feature = "zstd"))]
+fn compress(_: &[u8]) -> RuntimeResult<Vec<u8>> {
+    unreachable!()
+}
+
+#[cfg(feature = "zstd")]
+fn decompress(data: &[u8], size: usize) -> RuntimeResult<Vec<u8>> {
+    zstd::bulk::decompress(data, size).map_err(|e| e.to_string())
+}
+
+#[cfg(not(feature = "zstd"))]
+fn decompress(_: &[u8], _: usize) -> RuntimeResult<Vec<u8>> {
+    Err("the bytecode image is compressed, which needs the zstd feature".to_owned())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    /// A loader with the metadata of a signature, and the code section of
+    /// a function of it whose code is `words`
+    fn with_code(words: &[u64]) -> (Loader, Vec<u8>) {
+        let mut meta = vec![];
+        meta.extend_from_slice(&1u64.to_le_bytes());
+        // func(), without receiver, params, results or variadic
+        meta.extend_from_slice(&[21, 0]);
+        meta.extend_from_slice(&[0; 16]);
+        meta.extend_from_slice(&[0; 9]);
+        for _ in 0..24 {
+            meta.extend_from_slice(&[0; 6]);
+        }
+        let mut l = Loader::new();
+        l.read_metadata(&mut Cursor::new(&meta)).unwrap();
+
+        let mut code = vec![];
+        code.extend_from_slice(&0u64.to_le_bytes());
+        code.extend_from_slice(&1u64.to_le_bytes());
+        code.extend_from_slice(&NULL_INDEX.to_le_bytes());
+        code.extend_from_slice(&[0; 6]);
+        code.extend_from_slice(&[0]);
+        code.extend_from_slice(&1u64.to_le_bytes());
+        code.extend_from_slice(b"f");
+        code.extend_from_slice(&0i32.to_le_bytes());
+        code.extend_from_slice(&(words.len() as u64).to_le_bytes());
+        for w in words.iter() {
+            code.extend_from_slice(&w.to_le_bytes());
+        }
+        code.extend_from_slice(&[0; 16]);
+        code.extend_from_slice(&[0; 8]);
+        code.extend_from_slice(&0u32.to_le_bytes());
+        code.extend_from_slice(&[0; 8]);
+        (l, code)
+    }
+
+    #[test]
+    fn test_tampered_code() {
+        let ret = Instruction::new(Opcode::RETURN, None, None, None, None).get_u64();
+        let (mut l, code) = with_code(&[ret]);
+        assert!(l.read_code(&mut Cursor::new(&code)).is_ok());
+
+        let op = (Opcode::COUNT as u64) << 56;
+        let (mut l, code) = with_code(&[op]);
+        let err = l.read_code(&mut Cursor::new(&code)).unwrap_err();
+        assert!(err.starts_with("bad opcode"), "{}", err);
+
+        for shift in [48, 40, 32] {
+            let (mut l, code) = with_code(&[ret | 0xff << shift]);
+            let err = l.read_code(&mut Cursor::new(&code)).unwrap_err();
+            assert!(err.starts_with("bad value type"), "{}", err);
+        }
+        // the byte of t2 is an index for some instructions
+        let load = Instruction::new(Opcode::LOAD_INDEX_IMM, None, None, None, None).get_u64();
+        let (mut l, code) = with_code(&[load | 0xff << 32, ret]);
+        assert!(l.read_code(&mut Cursor::new(&code)).is_ok());
+
+        // the length of the code is more than the bytes left
+        let (mut l, mut code) = with_code(&[ret]);
+        code[40..48].copy_from_slice(&(1u64 << 40).to_le_bytes());
+        let err = l.read_code(&mut Cursor::new(&code)).unwrap_err();
+        assert!(err.starts_with("bad length"), "{}", err);
+    }
+
+    #[test]
+    fn test_cursor_len() {
+        let data = [3u64.to_le_bytes(), [0; 8]].concat();
+        assert_eq!(Cursor::new(&data).len(), Ok(3));
+        let data = (1u64 << 40).to_le_bytes();
+        assert!(Cursor::new(&data).len().is_err());
+        assert_eq!(Cursor::new(&data).usize(), Ok(1 << 40));
+    }
+}
@@ -8,9 +8,24 @@ use std::fmt;
 
 pub type OpIndex = i32;
 
-#[repr(u8)]
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
-pub enum Opcode {
+/// Declares the opcodes, and their count for telling bytes that are not
+/// opcodes apart
+macro_rules! opcodes {
+    ($($name:ident $(= $val:expr)?,)*) => {
+        #[repr(u8)]
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+        pub enum Opcode {
+            $($name $(= $val)?,)*
+        }
+
+        impl Opcode {
+            /// The number of opcodes, the bytes from it up are not opcodes
+            pub const COUNT: u8 = [$(Opcode::$name),*].len() as u8;
+        }
+    };
+}
+
+opcodes! {
     VOID = 0, //place holder
     // push pop load store
     PUSH_CONST,
@@ -28,6 +43,10 @@ pub enum Opcode {
     STORE_INDEX,
     LOAD_INDEX_IMM,
     STORE_INDEX_IMM,
+    // LOAD_INDEX and STORE_INDEX of slice elements by indices proved in
+    // range, for //gos:nobounds
+    LOAD_INDEX_UNCHECKED,
+    STORE_INDEX_UNCHECKED,
     LOAD_STRUCT_FIELD,
     STORE_STRUCT_FIELD,
     LOAD_PKG_FIELD,
@@ -55,6 +74,7 @@ pub enum Opcode {
     //REF,       // &
     REF,
     REF_UPVALUE,
+    REF_LOCAL, // & of a local the pointer of which doesn't outlive the frame
     REF_SLICE_MEMBER,
     REF_STRUCT_FIELD,
     REF_PKG_MEMBER,
@@ -73,6 +93,8 @@ pub enum Opcode {
     PRE_CALL,
     CALL,
     RETURN,
+    BUDGET,  // starts the time budget of a function call
+    MEMOIZE, // returns the cached results of a function call, if any
 
     // jump
     JUMP,
@@ -109,14 +131,51 @@ pub enum Opcode {
     RECOVER,    // for built-in function recover
     ASSERT,     // for built-in function assert
     FFI,        // for built-in function native
+
+    // superinstructions, each does the work of a pair of instructions. The
+    // second one is left in place and skipped, so that jumping to it still
+    // works.
+    LOAD_LOCAL_INDEX, // LOAD_LOCAL + LOAD_INDEX
+    LOAD_LOCAL_FIELD, // LOAD_LOCAL + LOAD_STRUCT_FIELD
+    STORE_LOCAL_POP,  // STORE_LOCAL + POP
+    PUSH_IMM_ADD,     // PUSH_IMM + ADD
 }
 
 impl Opcode {
+    /// Whether the instructions keep an index in the space of t2 instead
+    /// of a type
+    #[inline]
+    pub fn t2_is_index(&self) -> bool {
+        matches!(
+            self,
+            Opcode::LOAD_INDEX
+                | Opcode::LOAD_INDEX_IMM
+                | Opcode::STORE_INDEX
+                | Opcode::STORE_INDEX_IMM
+                | Opcode::LOAD_INDEX_UNCHECKED
+                | Opcode::STORE_INDEX_UNCHECKED
+                | Opcode::STORE_STRUCT_FIELD
+                | Opcode::TYPE_ASSERT
+        )
+    }
+
     #[inline]
     pub fn offset(&self, base: Opcode) -> OpIndex {
         (*self as i16 - base as i16) as OpIndex
     }
 
+    /// The superinstruction of `self` followed by `next`, if any
+    #[inline]
+    pub fn fused_with(&self, next: Opcode) -> Option<Opcode> {
+        match (self, next) {
+            (Opcode::LOAD_LOCAL, Opcode::LOAD_INDEX) => Some(Opcode::LOAD_LOCAL_INDEX),
+            (Opcode::LOAD_LOCAL, Opcode::LOAD_STRUCT_FIELD) => Some(Opcode::LOAD_LOCAL_FIELD),
+            (Opcode::STORE_LOCAL, Opcode::POP) => Some(Opcode::STORE_LOCAL_POP),
+            (Opcode::PUSH_IMM, Opcode::ADD) => Some(Opcode::PUSH_IMM_ADD),
+            _ => None,
+        }
+    }
+
     pub fn property(&self) -> (&str, i8) {
         match self {
             Opcode::VOID => ("VOID (place holder)", 0),
@@ -129,12 +188,14 @@ impl Opcode {
             Opcode::POP => ("POP", -1),
             Opcode::LOAD_LOCAL => ("LOAD_LOCAL", 1),
             Opcode::STORE_LOCAL => ("STORE_LOCAL", 0),
-            Opcode::LOAD_UPVALUE => ("LOAD_LOCAL", 1),
+            Opcode::LOAD_UPVALUE => ("LOAD_UPVALUE", 1),
             Opcode::STORE_UPVALUE => ("STORE_UPVALUE", 0),
             Opcode::LOAD_INDEX => ("LOAD_INDEX", -1),
             Opcode::STORE_INDEX => ("STORE_INDEX", 0),
             Opcode::LOAD_INDEX_IMM => ("LOAD_INDEX_IMM", 0),
             Opcode::STORE_INDEX_IMM => ("STORE_INDEX_IMM", 0),
+            Opcode::LOAD_INDEX_UNCHECKED => ("LOAD_INDEX_UNCHECKED", -1),
+            Opcode::STORE_INDEX_UNCHECKED => ("STORE_INDEX_UNCHECKED", 0),
             Opcode::LOAD_STRUCT_FIELD => ("LOAD_STRUCT_FIELD", 0),
             Opcode::STORE_STRUCT_FIELD => ("STORE_STRUCT_FIELD", 0),
             Opcode::LOAD_PKG_FIELD => ("LOAD_PKG_FIELD", 1),
@@ -161,6 +222,7 @@ impl Opcode {
             Opcode::UNARY_XOR => ("UNARY_XOR", 0),
             Opcode::REF => ("REF", 0),
             Opcode::REF_UPVALUE => ("REF_UPVALUE", 0),
+            Opcode::REF_LOCAL => ("REF_LOCAL", 1),
             Opcode::REF_SLICE_MEMBER => ("REF_SLICE_MEMBER", 0),
             Opcode::REF_STRUCT_FIELD => ("REF_STRUCT_FIELD", 0),
             Opcode::REF_PKG_MEMBER => ("REF_PKG_MEMBER", 0),
@@ -178,6 +240,8 @@ impl Opcode {
             Opcode::PRE_CALL => ("PRE_CALL", -128),
             Opcode::CALL => ("CALL", -128),
             Opcode::RETURN => ("RETURN", -128),
+            Opcode::BUDGET => ("BUDGET", 0),
+            Opcode::MEMOIZE => ("MEMOIZE", 0),
 
             Opcode::JUMP => ("JUMP", 0),
             Opcode::JUMP_IF => ("JUMP_IF", -1),
@@ -211,6 +275,11 @@ impl Opcode {
             Opcode::RECOVER => ("RECOVER", 1),
             Opcode::ASSERT => ("ASSERT", 0),
             Opcode::FFI => ("FFI", 0),
+
+            Opcode::LOAD_LOCAL_INDEX => ("LOAD_LOCAL_INDEX", 0),
+            Opcode::LOAD_LOCAL_FIELD => ("LOAD_LOCAL_FIELD", 1),
+            Opcode::STORE_LOCAL_POP => ("STORE_LOCAL_POP", -128),
+            Opcode::PUSH_IMM_ADD => ("PUSH_IMM_ADD", 0),
         }
     }
 
@@ -320,6 +389,11 @@ impl Instruction {
         Instruction { val: v }
     }
 
+    #[inline]
+    pub fn set_op(&mut self, op: Opcode) {
+        self.val = (self.val & 0x00ff_ffff_ffff_ffff) | (op as u64) << (8 * 3 + 32);
+    }
+
     #[inline]
     pub fn set_imm(&mut self, imm: OpIndex) {
         let uv: u32 = unsafe { std::mem::transmute(imm) };
@@ -425,6 +499,7 @@ impl fmt::Debug for Instruction {
         let op = self.op();
         match op {
             Opcode::STORE_LOCAL
+            | Opcode::STORE_LOCAL_POP
             | Opcode::STORE_UPVALUE
             | Opcode::STORE_STRUCT_FIELD
             | Opcode::STORE_PKG_FIELD
@@ -488,4 +563,15 @@ mod test {
         assert_eq!(i.imm824().0, 127);
         assert_eq!(i.imm824().1, 1 << 23 - 1);
     }
+
+    #[test]
+    fn test_fuse() {
+        let mut i = Instruction::new(Opcode::PUSH_IMM, Some(ValueType::Int), None, None, Some(-7));
+        let op = i.op().fused_with(Opcode::ADD).unwrap();
+        i.set_op(op);
+        assert_eq!(i.op(), Opcode::PUSH_IMM_ADD);
+        assert_eq!(i.t0(), ValueType::Int);
+        assert_eq!(i.imm(), -7);
+        assert_eq!(Opcode::ADD.fused_with(Opcode::PUSH_IMM), None);
+    }
 }
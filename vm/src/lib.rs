@@ -13,11 +13,37 @@ pub mod objects;
 
 pub mod ffi;
 
+pub mod bind;
+
 pub mod value;
 
 mod stack;
 
+mod dispatch;
+
 #[macro_use]
 pub mod vm;
 
 pub mod gc;
+
+pub mod timer;
+
+pub mod limits;
+
+pub mod clock;
+
+pub mod rng;
+
+pub mod step;
+
+pub mod audit;
+
+pub mod profile;
+
+pub mod memo;
+
+pub mod format;
+
+pub mod opbench;
+
+pub mod image;
@@ -0,0 +1,113 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Limits on what a program may use, for running untrusted scripts.
+//!
+//! The fibers account for their instructions every time they yield, so a
+//! limit stops the program within a thousand or so instructions of running
+//! out.
+//!
+//! There is no limit on the memory in bytes. `max_objects` bounds the
+//! number of objects the collector tracks, the structs, arrays, slices, maps
+//! and closures, whatever their sizes: a single huge slice counts as one
+//! object, and strings are not counted at all. It keeps a script from
+//! piling up objects, it doesn't cap the memory it uses.
+
+use super::clock::Instant;
+use super::gc::{gc, GcoVec};
+use std::cell::Cell;
+use std::fmt;
+use std::time::Duration;
+
+/// The limits of a run, none if not set
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Limits {
+    /// the number of instructions all the goroutines may execute
+    pub max_instructions: Option<u64>,
+    /// the wall-clock time the program may run for, sleeping included
+    pub max_time: Option<Duration>,
+    /// the number of objects that may be alive at once, after a collection.
+    /// This is an object count, not a memory limit, see the module doc
+    pub max_objects: Option<usize>,
+}
+
+/// A limit a program has run out of
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Limit {
+    Instructions(u64),
+    Time(Duration),
+    /// the number of live objects, not their size
+    Objects(usize),
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Limit::Instructions(n) => write!(f, "instruction limit of {} exceeded", n),
+            Limit::Time(d) => write!(f, "time limit of {:?} exceeded", d),
+            Limit::Objects(n) => write!(f, "object limit of {} exceeded", n),
+        }
+    }
+}
+
+/// Measures a run against its limits
+pub(crate) struct Meter {
+    limits: Limits,
+    deadline: Option<Instant>,
+    instructions: Cell<u64>,
+    exceeded: Cell<Option<Limit>>,
+}
+
+impl Meter {
+    pub fn new(limits: Limits) -> Meter {
+        Meter {
+            limits: limits,
+            deadline: limits.max_time.map(|d| Instant::now() + d),
+            instructions: Cell::new(0),
+            exceeded: Cell::new(None),
+        }
+    }
+
+    /// When the time runs out
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    pub fn exceeded(&self) -> Option<Limit> {
+        self.exceeded.get()
+    }
+
+    /// Accounts for `insts` more instructions, returns true if a limit has
+    /// been exceeded. Collects the garbage if there may be too many objects.
+    pub fn charge(&self, insts: usize, gcv: &GcoVec) -> bool {
+        if let Some(max) = self.limits.max_instructions {
+            let count = self.instructions.get() + insts as u64;
+            self.instructions.set(count);
+            if count > max {
+                self.exceeded.set(Some(Limit::Instructions(max)));
+                return true;
+            }
+        }
+        if let Some(max) = self.limits.max_objects {
+            if gcv.count() > max {
+                gc(gcv);
+                if gcv.count() > max {
+                    self.exceeded.set(Some(Limit::Objects(max)));
+                    return true;
+                }
+            }
+        }
+        self.check_time()
+    }
+
+    /// Returns true if a limit has been exceeded, the time is checked
+    pub fn check_time(&self) -> bool {
+        if let (Some(deadline), Some(max)) = (self.deadline, self.limits.max_time) {
+            if Instant::now() >= deadline {
+                self.exceeded.set(Some(Limit::Time(max)));
+            }
+        }
+        self.exceeded.get().is_some()
+    }
+}
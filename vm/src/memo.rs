@@ -0,0 +1,95 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+use super::objects::FunctionKey;
+use super::value::GosValue;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
+
+/// The result caches of the `//gos:memoize` functions of a VM, shared by all
+/// its fibers.
+///
+/// The keys are the arguments of the calls, which the type checker makes
+/// sure are comparable. Values are stored and handed out as copies, so that
+/// neither the callee nor the callers can change what's cached; what
+/// pointers, slices or maps in the results point to is shared though.
+#[derive(Clone)]
+pub struct Memos {
+    inner: Rc<RefCell<HashMap<FunctionKey, MemoCache>>>,
+}
+
+impl Memos {
+    pub fn new() -> Memos {
+        Memos {
+            inner: Rc::new(RefCell::new(HashMap::new())),
+        }
+    }
+
+    /// Returns the cached results of `func` called with `args`, creating the
+    /// cache of `func` with `capacity` if it's the first call.
+    pub fn get(
+        &self,
+        func: FunctionKey,
+        capacity: usize,
+        args: &[GosValue],
+    ) -> Option<Vec<GosValue>> {
+        self.inner
+            .borrow_mut()
+            .entry(func)
+            .or_insert_with(|| MemoCache::new(capacity))
+            .get(args)
+            .cloned()
+    }
+
+    pub fn insert(&self, func: FunctionKey, args: Vec<GosValue>, results: Vec<GosValue>) {
+        if let Some(cache) = self.inner.borrow_mut().get_mut(&func) {
+            cache.insert(args, results);
+        }
+    }
+}
+
+/// A least recently used cache of the results of a function
+struct MemoCache {
+    capacity: usize,
+    // bumped by every access, orders the entries by their last use
+    tick: u64,
+    entries: HashMap<Vec<GosValue>, (Vec<GosValue>, u64)>,
+    lru: BTreeMap<u64, Vec<GosValue>>,
+}
+
+impl MemoCache {
+    fn new(capacity: usize) -> MemoCache {
+        MemoCache {
+            capacity: capacity,
+            tick: 0,
+            entries: HashMap::new(),
+            lru: BTreeMap::new(),
+        }
+    }
+
+    fn get(&mut self, args: &[GosValue]) -> Option<&Vec<GosValue>> {
+        let tick = self.tick;
+        let (results, used) = self.entries.get_mut(args)?;
+        let key = self.lru.remove(used).unwrap();
+        *used = tick;
+        self.lru.insert(tick, key);
+        self.tick += 1;
+        Some(results)
+    }
+
+    fn insert(&mut self, args: Vec<GosValue>, results: Vec<GosValue>) {
+        if let Some((_, used)) = self.entries.remove(&args) {
+            // a recursive call got here first
+            self.lru.remove(&used);
+        } else if self.entries.len() >= self.capacity {
+            if let Some((_, oldest)) = self.lru.pop_first() {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.lru.insert(self.tick, args.clone());
+        self.entries.insert(args, (results, self.tick));
+        self.tick += 1;
+    }
+}
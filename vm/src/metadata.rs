@@ -39,11 +39,31 @@ pub struct StaticMeta {
     pub unsafe_ptr: Meta,
     pub default_sig: Meta,
     pub empty_iface: Meta,
+    /// []interface{} and map[string]interface{}, for FFI code decoding
+    /// values of unknown types
+    pub empty_iface_slice: Meta,
+    pub empty_iface_map: Meta,
     pub none: Meta,
+    /// interface{ Error() string }, the type of errors created by FFI
+    pub error: Meta,
 }
 
 impl StaticMeta {
     pub fn new(objs: &mut MetadataObjs) -> StaticMeta {
+        let mstr = Meta::with_type(MetadataType::Str(GosValue::with_str("")), objs);
+        let error_sig = Meta::new_sig(None, vec![], vec![mstr], None, objs);
+        let error_method = FieldInfo {
+            meta: error_sig,
+            name: "Error".to_owned(),
+            exported: true,
+            embedded: false,
+            tag: None,
+        };
+        let error_mapping = HashMap::from([("Error".to_owned(), vec![0])]);
+        let empty_iface = Meta::with_type(
+            MetadataType::Interface(Fields::new(vec![], HashMap::new())),
+            objs,
+        );
         StaticMeta {
             mbool: Meta::with_type(MetadataType::Bool, objs),
             mint: Meta::with_type(MetadataType::Int, objs),
@@ -61,14 +81,14 @@ impl StaticMeta {
             mfloat64: Meta::with_type(MetadataType::Float64, objs),
             mcomplex64: Meta::with_type(MetadataType::Complex64, objs),
             mcomplex128: Meta::with_type(MetadataType::Complex128, objs),
-            mstr: Meta::with_type(MetadataType::Str(GosValue::with_str("")), objs),
+            mstr: mstr,
             unsafe_ptr: Meta::with_type(MetadataType::UnsafePtr, objs),
             default_sig: Meta::with_type(MetadataType::Signature(SigMetadata::default()), objs),
-            empty_iface: Meta::with_type(
-                MetadataType::Interface(Fields::new(vec![], HashMap::new())),
-                objs,
-            ),
+            empty_iface: empty_iface,
+            empty_iface_slice: Meta::new_slice(empty_iface, objs),
+            empty_iface_map: Meta::new_map(mstr, empty_iface, objs),
             none: Meta::with_type(MetadataType::None, objs),
+            error: Meta::new_interface(Fields::new(vec![error_method], error_mapping), objs),
         }
     }
 }
@@ -155,9 +175,10 @@ impl Meta {
         Meta::with_type(t, metas)
     }
 
-    pub fn new_named(underlying: Meta, metas: &mut MetadataObjs) -> Meta {
+    /// name is the qualified name of the type, e.g. "main.Point"
+    pub fn new_named(name: String, underlying: Meta, metas: &mut MetadataObjs) -> Meta {
         //debug_assert!(underlying.value_type(metas) != ValueType::Named);
-        Meta::with_type(MetadataType::Named(Methods::new(), underlying), metas)
+        Meta::with_type(MetadataType::Named(Methods::new(), underlying, name), metas)
     }
 
     #[inline]
@@ -222,7 +243,7 @@ impl Meta {
                     MetadataType::Map(_, _) => ValueType::Map,
                     MetadataType::Interface(_) => ValueType::Interface,
                     MetadataType::Channel(_, _) => ValueType::Channel,
-                    MetadataType::Named(_, m) => m.value_type(metas),
+                    MetadataType::Named(_, m, _) => m.value_type(metas),
                     MetadataType::None => ValueType::Void,
                 },
                 _ => ValueType::Pointer,
@@ -259,12 +280,12 @@ impl Meta {
                     GosValue::array_with_size(*size, *size, &val, t, gcv)
                 }
                 MetadataType::Slice(m) => GosValue::new_slice_nil(m.value_type(mobjs)),
-                MetadataType::Struct(_, s) => GosValue::new_struct(s.clone(), gcv),
+                MetadataType::Struct(_, s) => GosValue::new_struct(s.copy_semantic(gcv), gcv),
                 MetadataType::Signature(_) => GosValue::new_nil(ValueType::Closure),
                 MetadataType::Map(_, _) => GosValue::new_nil(ValueType::Map),
                 MetadataType::Interface(_) => GosValue::new_nil(ValueType::Interface),
                 MetadataType::Channel(_, _) => GosValue::new_nil(ValueType::Channel),
-                MetadataType::Named(_, gm) => gm.zero(mobjs, gcv),
+                MetadataType::Named(_, gm, _) => gm.zero(mobjs, gcv),
                 MetadataType::None => unreachable!(),
             },
             _ => GosValue::new_nil(ValueType::Pointer),
@@ -283,7 +304,7 @@ impl Meta {
     #[inline]
     pub fn underlying(&self, metas: &MetadataObjs) -> Meta {
         match &metas[self.key] {
-            MetadataType::Named(_, u) => *u,
+            MetadataType::Named(_, u, _) => *u,
             _ => *self,
         }
     }
@@ -297,7 +318,7 @@ impl Meta {
     pub fn add_method(&self, name: String, pointer_recv: bool, metas: &mut MetadataObjs) {
         let k = self.recv_meta_key();
         match &mut metas[k] {
-            MetadataType::Named(m, _) => {
+            MetadataType::Named(m, _, _) => {
                 m.members.push(Rc::new(RefCell::new(MethodDesc {
                     pointer_recv: pointer_recv,
                     func: None,
@@ -311,7 +332,7 @@ impl Meta {
     pub fn set_method_code(&self, name: &String, func: FunctionKey, metas: &mut MetadataObjs) {
         let k = self.recv_meta_key();
         match &mut metas[k] {
-            MetadataType::Named(m, _) => {
+            MetadataType::Named(m, _, _) => {
                 let index = m.mapping[name] as usize;
                 m.members[index].borrow_mut().func = Some(func);
             }
@@ -319,44 +340,79 @@ impl Meta {
         }
     }
 
-    /// Depth-first search for method by name
+    /// Finds the method `name` of the type, or the one promoted from the
+    /// shallowest embedded field that has it, as the selector x.name does.
+    /// None if there is none, if a field of that name is shallower, or if
+    /// there are several at the same depth.
     pub fn get_iface_binding(&self, name: &String, metas: &MetadataObjs) -> Option<IfaceBinding> {
-        match &metas[self.key] {
-            MetadataType::Named(m, underlying) => match m.mapping.get(name) {
-                Some(&i) => Some(IfaceBinding::Struct(m.members[i as usize].clone(), None)),
-                None => underlying.get_iface_binding(name, metas),
-            },
-            MetadataType::Interface(fields) => fields
-                .mapping
-                .get(name)
-                .map(|x| IfaceBinding::Iface(x[0], None)),
-            MetadataType::Struct(fields, _) => {
-                for (i, f) in fields.fields.iter().enumerate() {
-                    if let Some(mut re) = f.meta.get_iface_binding(name, metas) {
-                        let indices = match &mut re {
-                            IfaceBinding::Struct(_, indices) | IfaceBinding::Iface(_, indices) => {
-                                indices
+        // the types at the current depth, with the indices of the embedded
+        // fields leading to them
+        let mut level: Vec<(Meta, Vec<usize>)> = vec![(*self, vec![])];
+        while !level.is_empty() {
+            let mut found = vec![];
+            let mut next = vec![];
+            for (meta, indices) in level.into_iter() {
+                let path = (!indices.is_empty()).then(|| indices.clone());
+                let mut typ = &metas[meta.key];
+                if let MetadataType::Named(m, underlying, _) = typ {
+                    if let Some(&i) = m.mapping.get(name) {
+                        let method = m.members[i as usize].clone();
+                        found.push(Some(IfaceBinding::Struct(method, path)));
+                        continue;
+                    }
+                    typ = &metas[underlying.key];
+                }
+                match typ {
+                    MetadataType::Interface(fields) => {
+                        if let Some(x) = fields.mapping.get(name) {
+                            found.push(Some(IfaceBinding::Iface(x[0], path)));
+                        }
+                    }
+                    MetadataType::Struct(fields, _) => {
+                        for (i, f) in fields.all().iter().enumerate() {
+                            if &f.name == name {
+                                found.push(None);
+                            } else if f.embedded {
+                                let mut indices = indices.clone();
+                                indices.push(i);
+                                next.push((f.meta, indices));
                             }
-                        };
-                        if let Some(x) = indices {
-                            x.push(i)
-                        } else {
-                            *indices = Some(vec![i]);
                         }
-                        return Some(re);
                     }
+                    _ => {}
                 }
-                None
             }
-            _ => None,
+            match found.len() {
+                0 => level = next,
+                1 => return found.pop().unwrap(),
+                _ => return None,
+            }
+        }
+        None
+    }
+
+    /// Whether one of the embedded fields at `indices` is a pointer, the
+    /// methods with pointer receivers of the types embedded through it are
+    /// then in the method set of the values of the type too
+    pub fn embeds_pointer(&self, indices: &[usize], metas: &MetadataObjs) -> bool {
+        let mut meta = *self;
+        for &i in indices.iter() {
+            meta = match &metas[meta.underlying(metas).key] {
+                MetadataType::Struct(fields, _) => fields.all()[i].meta,
+                _ => unreachable!(),
+            };
+            if meta.ptr_depth > 0 {
+                return true;
+            }
         }
+        false
     }
 
     #[inline]
     pub fn get_method(&self, index: OpIndex, metas: &MetadataObjs) -> Rc<RefCell<MethodDesc>> {
         let k = self.recv_meta_key();
         let m = match &metas[k] {
-            MetadataType::Named(methods, _) => methods,
+            MetadataType::Named(methods, _, _) => methods,
             _ => unreachable!(),
         };
         m.members[index as usize].clone()
@@ -373,6 +429,69 @@ pub struct FieldInfo {
     pub name: String,
     pub exported: bool,
     pub embedded: bool,
+    /// The raw tag string, like `json:"name,omitempty"`
+    pub tag: Option<String>,
+}
+
+impl FieldInfo {
+    /// Returns the value associated with key in the tag string, following
+    /// the conventions of Go's StructTag.Lookup.
+    pub fn lookup_tag(&self, key: &str) -> Option<String> {
+        let mut tag = self.tag.as_deref()?;
+        loop {
+            tag = tag.trim_start_matches(' ');
+            if tag.is_empty() {
+                return None;
+            }
+            // a name is a non-empty run of non-control characters other than
+            // space, quote and colon
+            let name_len = tag
+                .find(|c: char| c <= ' ' || c == ':' || c == '"' || c == '\u{7f}')
+                .unwrap_or(tag.len());
+            if name_len == 0 || !tag[name_len..].starts_with(":\"") {
+                return None;
+            }
+            let name = &tag[..name_len];
+            tag = &tag[name_len + 1..];
+
+            // scan the quoted value
+            let bytes = tag.as_bytes();
+            let mut i = 1;
+            while i < bytes.len() && bytes[i] != b'"' {
+                if bytes[i] == b'\\' {
+                    i += 1;
+                }
+                i += 1;
+            }
+            if i >= bytes.len() {
+                return None;
+            }
+            let quoted = &tag[1..i];
+            tag = &tag[i + 1..];
+            if name == key {
+                return unquote_tag_value(quoted);
+            }
+        }
+    }
+}
+
+fn unquote_tag_value(s: &str) -> Option<String> {
+    let mut result = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next()? {
+            'n' => result.push('\n'),
+            't' => result.push('\t'),
+            'r' => result.push('\r'),
+            c @ ('\\' | '"' | '\'') => result.push(c),
+            _ => return None,
+        }
+    }
+    Some(result)
 }
 
 #[derive(Debug, Clone)]
@@ -567,7 +686,7 @@ pub enum MetadataType {
     Map(Meta, Meta),
     Interface(Fields),
     Channel(ChannelType, Meta),
-    Named(Methods, Meta),
+    Named(Methods, Meta, String),
     None,
 }
 
@@ -631,7 +750,7 @@ impl MetadataType {
     #[inline]
     pub fn as_named_mut(&mut self) -> (&mut Methods, &mut Meta) {
         match self {
-            Self::Named(meth, meta) => (meth, meta),
+            Self::Named(meth, meta, _) => (meth, meta),
             _ => unreachable!(),
         }
     }
@@ -639,7 +758,7 @@ impl MetadataType {
     #[inline]
     pub fn unwrap_named<'a, 'b: 'a>(&'a self, metas: &'b MetadataObjs) -> &'a Self {
         match self {
-            Self::Named(_, meta) => &metas[meta.key],
+            Self::Named(_, meta, _) => &metas[meta.key],
             _ => self,
         }
     }
@@ -674,7 +793,7 @@ impl MetadataType {
             (Self::Channel(at, avt), Self::Channel(bt, bvt)) => {
                 at == bt && avt.identical(bvt, metas)
             }
-            (Self::Named(_, a), Self::Named(_, b)) => a.identical(b, metas),
+            (Self::Named(_, a, _), Self::Named(_, b, _)) => a.identical(b, metas),
             _ => false,
         }
     }
@@ -87,17 +87,19 @@ pub type GosHashMap = HashMap<GosValue, GosValue>;
 
 pub type GosHashMapIter<'a> = std::collections::hash_map::Iter<'a, GosValue, GosValue>;
 
+/// The entries are shared by the clones of a map until either of them is
+/// written, like the evaluations of a map literal of constants.
 #[derive(Debug)]
 pub struct MapObj {
     zero_val: GosValue,
-    map: RefCell<GosHashMap>,
+    map: RefCell<Rc<GosHashMap>>,
 }
 
 impl MapObj {
     pub fn new(zero_val: GosValue) -> MapObj {
         MapObj {
             zero_val: zero_val,
-            map: RefCell::new(HashMap::new()),
+            map: RefCell::new(Rc::new(HashMap::new())),
         }
     }
 
@@ -106,6 +108,11 @@ impl MapObj {
         self.borrow_data_mut().insert(key, val)
     }
 
+    #[inline]
+    pub fn zero_val(&self) -> &GosValue {
+        &self.zero_val
+    }
+
     #[inline]
     pub fn new_default_val(&self, gcv: &GcoVec) -> GosValue {
         self.zero_val.copy_semantic(gcv)
@@ -132,7 +139,7 @@ impl MapObj {
     pub fn touch_key(&self, key: &GosValue, gcv: &GcoVec) {
         if self.borrow_data().get(&key).is_none() {
             self.borrow_data_mut()
-                .insert(key.clone(), self.new_default_val(gcv));
+                .insert(key.copy_semantic(gcv), self.new_default_val(gcv));
         }
     }
 
@@ -141,19 +148,28 @@ impl MapObj {
         self.borrow_data().len()
     }
 
+    /// Copies the entries if they are shared with another map
+    #[inline]
+    pub fn unshare(&self) {
+        if Rc::strong_count(&self.map.borrow()) > 1 {
+            self.borrow_data_mut();
+        }
+    }
+
+    /// Copies the entries first if they are shared with another map
     #[inline]
     pub fn borrow_data_mut(&self) -> RefMut<GosHashMap> {
-        self.map.borrow_mut()
+        RefMut::map(self.map.borrow_mut(), |x| Rc::make_mut(x))
     }
 
     #[inline]
     pub fn borrow_data(&self) -> Ref<GosHashMap> {
-        self.map.borrow()
+        Ref::map(self.map.borrow(), |x| &**x)
     }
 
     #[inline]
     pub fn clone_inner(&self) -> RefCell<GosHashMap> {
-        self.map.clone()
+        RefCell::new(self.borrow_data().clone())
     }
 }
 
@@ -191,8 +207,17 @@ impl Display for MapObj {
 // ----------------------------------------------------------------------------
 // ArrayObj
 
+/// The message of indexing out of range, in Go's format. A negative index
+/// shows up as a huge usize, which is printed as the negative number.
+pub fn index_out_of_range(i: usize, len: usize) -> String {
+    format!("index out of range [{}] with length {}", i as isize, len)
+}
+
+/// The data is shared by the arrays made by `share` until either of them is
+/// written, so that a literal of constants is only copied if it's modified.
+/// Elements are written through `set` or `borrow_data_mut`, which copy it then.
 pub struct ArrayObj<T> {
-    vec: RefCell<Vec<T>>,
+    vec: RefCell<Rc<Vec<T>>>,
 }
 
 pub type GosArrayObj = ArrayObj<GosElem>;
@@ -207,20 +232,45 @@ where
         for _ in 0..size {
             v.push(T::from_value(val.copy_semantic(gcos)))
         }
-        ArrayObj {
-            vec: RefCell::new(v),
-        }
+        ArrayObj::with_raw_data(v)
     }
 
     pub fn with_data(data: Vec<GosValue>) -> ArrayObj<T> {
+        ArrayObj::with_raw_data(data.into_iter().map(|x| T::from_value(x)).collect())
+    }
+
+    /// A copy of the array, the elements that are structs or arrays are
+    /// copied too
+    pub fn copy_semantic(&self, gcv: &GcoVec) -> ArrayObj<T> {
+        ArrayObj::with_raw_data(
+            self.borrow_data()
+                .iter()
+                .map(|x| x.copy_semantic(gcv))
+                .collect(),
+        )
+    }
+
+    /// An array with the same data, which gets copied when either of them
+    /// is written. The elements must not be structs or arrays, which are
+    /// written in place.
+    pub fn share(&self) -> ArrayObj<T> {
         ArrayObj {
-            vec: RefCell::new(data.into_iter().map(|x| T::from_value(x)).collect()),
+            vec: RefCell::new(self.vec.borrow().clone()),
         }
     }
 
     pub fn with_raw_data(data: Vec<T>) -> ArrayObj<T> {
         ArrayObj {
-            vec: RefCell::new(data),
+            vec: RefCell::new(Rc::new(data)),
+        }
+    }
+
+    /// Copies the data if it's shared with another array, before it's written
+    /// through a shared borrow
+    #[inline(always)]
+    pub fn unshare(&self) {
+        if Rc::strong_count(&self.vec.borrow()) > 1 {
+            self.borrow_data_mut();
         }
     }
 
@@ -229,14 +279,15 @@ where
         self.borrow_data().len()
     }
 
+    /// Copies the data first if it's shared with another array
     #[inline(always)]
     pub fn borrow_data_mut(&self) -> std::cell::RefMut<Vec<T>> {
-        self.vec.borrow_mut()
+        RefMut::map(self.vec.borrow_mut(), |x| Rc::make_mut(x))
     }
 
     #[inline(always)]
     pub fn borrow_data(&self) -> std::cell::Ref<Vec<T>> {
-        self.vec.borrow()
+        Ref::map(self.vec.borrow(), |x| &**x)
     }
 
     #[inline]
@@ -257,7 +308,7 @@ where
     #[inline(always)]
     pub fn get(&self, i: usize, t: ValueType) -> RuntimeResult<GosValue> {
         if i >= self.len() {
-            return Err(format!("index {} out of range", i).to_owned());
+            return Err(index_out_of_range(i, self.len()));
         }
         Ok(self.borrow_data()[i].clone().into_value(t))
     }
@@ -265,8 +316,9 @@ where
     #[inline(always)]
     pub fn set(&self, i: usize, val: &GosValue) -> RuntimeResult<()> {
         if i >= self.len() {
-            return Err(format!("index {} out of range", i).to_owned());
+            return Err(index_out_of_range(i, self.len()));
         }
+        self.unshare();
         Ok(self.borrow_data()[i].set_value(&val))
     }
 
@@ -334,15 +386,17 @@ where
     T: Element + PartialEq,
 {
     fn clone(&self) -> Self {
-        ArrayObj {
-            vec: RefCell::new(self.borrow_data().iter().map(|x| x.clone()).collect()),
-        }
+        ArrayObj::with_raw_data(self.borrow_data().iter().map(|x| x.clone()).collect())
     }
 }
 
 // ----------------------------------------------------------------------------
 // SliceObj
 
+/// The longest slice make may create, the array is allocated when the slice
+/// is made
+pub const MAX_SLICE_LEN: usize = isize::MAX as usize / std::mem::size_of::<GosValue>();
+
 #[derive(Clone)]
 pub struct SliceObj<T> {
     array: GosValue,
@@ -359,9 +413,21 @@ impl<T> SliceObj<T>
 where
     T: Element,
 {
+    /// Slices the array like `arr[begin:end]`, a negative end is the length
     pub fn with_array(arr: GosValue, begin: isize, end: isize) -> RuntimeResult<SliceObj<T>> {
+        let end = if end >= 0 { Some(end) } else { None };
+        SliceObj::with_array_bounds(arr, begin, end, None)
+    }
+
+    /// Slices the array like `arr[begin:end:max]`
+    pub fn with_array_bounds(
+        arr: GosValue,
+        begin: isize,
+        end: Option<isize>,
+        max: Option<isize>,
+    ) -> RuntimeResult<SliceObj<T>> {
         let len = arr.as_array::<T>().0.len();
-        let (bi, ei, cap) = SliceObj::<T>::check_indices(0, len, len, begin, end, -1)?;
+        let (bi, ei, cap) = SliceObj::<T>::check_indices(0, len, len, begin, end, max, "length")?;
         Ok(SliceObj {
             begin: Cell::from(bi),
             end: Cell::from(ei),
@@ -452,55 +518,80 @@ where
 
     #[inline(always)]
     pub fn get(&self, i: usize, t: ValueType) -> RuntimeResult<GosValue> {
+        if i >= self.len() {
+            return Err(index_out_of_range(i, self.len()));
+        }
         self.array_obj().get(self.begin() + i, t)
     }
 
     #[inline(always)]
     pub fn set(&self, i: usize, val: &GosValue) -> RuntimeResult<()> {
-        self.array_obj().set(i, val)
-    }
-
-    #[inline]
-    pub fn push(&mut self, val: GosValue) {
-        let mut data = self.borrow_all_data_mut();
-        if data.len() == self.len() {
-            data.push(T::from_value(val))
-        } else {
-            data[self.end()] = T::from_value(val);
-        }
-        drop(data);
-        *self.end.get_mut() += 1;
-        if self.cap_end.get() < self.end.get() {
-            *self.cap_end.get_mut() += 1;
+        if i >= self.len() {
+            return Err(index_out_of_range(i, self.len()));
+        }
+        self.array_obj().set(self.begin() + i, val)
+    }
+
+    /// Appends the elements of `other` like Go does: into the array, where
+    /// the other slices sharing it see them, if they fit in the capacity,
+    /// or else into a new array with room to grow.
+    pub fn append(&self, other: &SliceObj<T>, t_elem: ValueType, gcv: &GcoVec) -> SliceObj<T> {
+        let len = self.len() + other.len();
+        if len <= self.cap() {
+            let cloned;
+            let other_data;
+            let src: &[T] = match self.sharing_with(other) {
+                true => {
+                    cloned = other.as_rust_slice().to_vec();
+                    &cloned
+                }
+                false => {
+                    other_data = other.as_rust_slice();
+                    &other_data
+                }
+            };
+            let end = self.end();
+            T::copy_or_clone_slice(&mut self.borrow_all_data_mut()[end..end + src.len()], src);
+            return SliceObj {
+                begin: self.begin.clone(),
+                end: Cell::from(end + src.len()),
+                cap_end: self.cap_end.clone(),
+                array: self.array.clone(),
+                phantom: PhantomData,
+            };
+        }
+        let cap = SliceObj::<T>::grown_cap(self.cap(), len);
+        let mut data = Vec::with_capacity(cap);
+        data.extend_from_slice(&self.as_rust_slice());
+        data.extend_from_slice(&other.as_rust_slice());
+        // the rest of the capacity, which reslicing can reach
+        let zero = data[0].zero_like(gcv);
+        data.resize(cap, zero);
+        let arr = GosValue::new_array(ArrayObj::with_raw_data(data), t_elem, gcv);
+        SliceObj {
+            begin: Cell::from(0),
+            end: Cell::from(len),
+            cap_end: Cell::from(cap),
+            array: arr,
+            phantom: PhantomData,
         }
     }
 
-    #[inline]
-    pub fn append(&mut self, other: &SliceObj<T>) {
-        let mut data = self.borrow_all_data_mut();
-        let new_end = self.end() + other.len();
-        let after_end_len = data.len() - self.end();
-        let sharing = self.sharing_with(other);
-        if after_end_len <= other.len() {
-            data.truncate(self.end());
-            if !sharing {
-                data.extend_from_slice(&other.as_rust_slice());
-            } else {
-                data.extend_from_within(other.range());
-            }
-        } else {
-            if !sharing {
-                T::copy_or_clone_slice(&mut data[self.end()..new_end], &other.as_rust_slice());
-            } else {
-                let cloned = data[other.range()].to_vec();
-                T::copy_or_clone_slice(&mut data[self.end()..new_end], &cloned);
-            }
+    /// The capacity of a slice of capacity `cap` grown to hold `len`
+    /// elements: doubled while small, then grown by a quarter or so
+    fn grown_cap(cap: usize, len: usize) -> usize {
+        const THRESHOLD: usize = 256;
+        if len > cap * 2 {
+            return len;
+        }
+        if cap < THRESHOLD {
+            return cap * 2;
         }
-        drop(data);
-        *self.end.get_mut() = new_end;
-        if self.cap_end.get() < self.end.get() {
-            *self.cap_end.get_mut() = self.end.get();
+        let mut new_cap = cap;
+        while new_cap < len {
+            new_cap += (new_cap + 3 * THRESHOLD) / 4;
         }
+        new_cap
     }
 
     #[inline]
@@ -526,14 +617,34 @@ where
     }
 
     #[inline]
-    pub fn slice(&self, begin: isize, end: isize, max: isize) -> RuntimeResult<SliceObj<T>> {
+    pub fn slice(
+        &self,
+        begin: isize,
+        end: Option<isize>,
+        max: Option<isize>,
+    ) -> RuntimeResult<SliceObj<T>> {
+        self.slice_within(begin, end, max, self.cap_end.get(), "capacity")
+    }
+
+    /// Slices with `cap_end` as the upper bound, `bound` names the bound in
+    /// error messages
+    #[inline]
+    fn slice_within(
+        &self,
+        begin: isize,
+        end: Option<isize>,
+        max: Option<isize>,
+        cap_end: usize,
+        bound: &str,
+    ) -> RuntimeResult<SliceObj<T>> {
         let (bi, ei, cap) = SliceObj::<T>::check_indices(
             self.begin(),
             self.len(),
-            self.cap_end.get(),
+            cap_end,
             begin,
             end,
             max,
+            bound,
         )?;
         Ok(SliceObj {
             begin: Cell::from(bi),
@@ -556,9 +667,9 @@ where
     pub fn swap(&self, i: usize, j: usize) -> RuntimeResult<()> {
         let len = self.len();
         if i >= len {
-            Err(format!("index {} out of range", i))
+            Err(index_out_of_range(i, len))
         } else if j >= len {
-            Err(format!("index {} out of range", j))
+            Err(index_out_of_range(j, len))
         } else {
             self.borrow_all_data_mut()
                 .swap(i + self.begin(), j + self.begin());
@@ -576,29 +687,53 @@ where
         self.array_obj().borrow_data()
     }
 
+    /// Checks the bounds of [begin:end:max] in the order Go does, and returns
+    /// them as indices of the array. A missing end is the length and a
+    /// missing max the capacity, like in `s[begin:]` and `s[begin:end]`.
     #[inline]
-    fn check_indices(
+    pub(crate) fn check_indices(
         this_begin: usize,
         this_len: usize,
         this_cap: usize,
         begin: isize,
-        end: isize,
-        max: isize,
+        end: Option<isize>,
+        max: Option<isize>,
+        bound: &str,
     ) -> RuntimeResult<(usize, usize, usize)> {
-        let bi = this_begin + begin as usize;
-        if bi > this_cap {
-            return Err(format!("index {} out of range", begin).to_owned());
-        }
-        let this_len_p1 = this_len as isize + 1;
-        let ei = this_begin + ((this_len_p1 + end) % this_len_p1) as usize;
-        if ei < bi {
-            return Err(format!("index {} out of range", end).to_owned());
-        }
-        let cap = if max < 0 { this_cap } else { max as usize };
-        if cap > this_cap {
-            return Err(format!("index {} out of range", max).to_owned());
+        let cap = (this_cap - this_begin) as isize;
+        // negative indices are out of range, like the huge unsigned ones
+        // they are in Go
+        let full = max.is_some();
+        let max = max.unwrap_or(cap);
+        if max < 0 || max > cap {
+            return Err(format!(
+                "slice bounds out of range [::{}] with {} {}",
+                max, bound, cap
+            ));
+        }
+        let end = end.unwrap_or(this_len as isize);
+        if end < 0 || end > max {
+            return Err(if full {
+                format!("slice bounds out of range [:{}:{}]", end, max)
+            } else {
+                format!(
+                    "slice bounds out of range [:{}] with {} {}",
+                    end, bound, cap
+                )
+            });
+        }
+        if begin < 0 || begin > end {
+            return Err(if full {
+                format!("slice bounds out of range [{}:{}:]", begin, end)
+            } else {
+                format!("slice bounds out of range [{}:{}]", begin, end)
+            });
         }
-        Ok((bi, ei, cap))
+        Ok((
+            this_begin + begin as usize,
+            this_begin + end as usize,
+            this_begin + max as usize,
+        ))
     }
 
     pub fn display_fmt(&self, t: ValueType, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -639,9 +774,36 @@ pub type SliceEnumIter<'a, T> = std::iter::Enumerate<SliceIter<'a, T>>;
 // ----------------------------------------------------------------------------
 // StringObj
 
-pub type StringIter<'a> = std::str::Chars<'a>;
+/// Iterates over the runes of a string with their byte offsets, like range
+/// in Go. The bytes don't have to be valid UTF-8.
+pub struct StringEnumIter<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
 
-pub type StringEnumIter<'a> = std::iter::Enumerate<StringIter<'a>>;
+impl<'a> StringEnumIter<'a> {
+    pub fn new(bytes: &'a [u8]) -> StringEnumIter<'a> {
+        StringEnumIter {
+            bytes: bytes,
+            pos: 0,
+        }
+    }
+}
+
+impl<'a> Iterator for StringEnumIter<'a> {
+    type Item = (usize, char);
+
+    fn next(&mut self) -> Option<(usize, char)> {
+        let rest = &self.bytes[self.pos..];
+        if rest.is_empty() {
+            return None;
+        }
+        let (r, size) = StrUtil::decode_rune(rest);
+        let pos = self.pos;
+        self.pos += size;
+        Some((pos, r))
+    }
+}
 
 pub type StringObj = SliceObj<Elem8>;
 
@@ -654,6 +816,13 @@ impl StrUtil {
         StrUtil::buf_into_string(buf)
     }
 
+    /// Go strings are bytes, they don't have to be valid UTF-8
+    #[inline]
+    pub fn with_bytes(b: Vec<u8>) -> StringObj {
+        let buf: Vec<Elem8> = unsafe { std::mem::transmute(b) };
+        StrUtil::buf_into_string(buf)
+    }
+
     /// It's safe because strings are readonly
     /// https://stackoverflow.com/questions/50431702/is-it-safe-and-defined-behavior-to-transmute-between-a-t-and-an-unsafecellt
     /// https://doc.rust-lang.org/src/core/str/converts.rs.html#173
@@ -662,11 +831,32 @@ impl StrUtil {
         unsafe { std::mem::transmute(this.as_rust_slice()) }
     }
 
+    /// Decodes the rune `b` starts with and returns it with its size in
+    /// bytes. Like in Go an invalid encoding is U+FFFD, one byte long.
+    pub fn decode_rune(b: &[u8]) -> (char, usize) {
+        let head = &b[..b.len().min(4)];
+        let valid = match std::str::from_utf8(head) {
+            Ok(s) => s,
+            // the prefix is valid UTF-8
+            Err(e) => unsafe { std::str::from_utf8_unchecked(&head[..e.valid_up_to()]) },
+        };
+        match valid.chars().next() {
+            Some(c) => (c, c.len_utf8()),
+            None => (char::REPLACEMENT_CHARACTER, 1),
+        }
+    }
+
     #[inline]
     pub fn index(this: &StringObj, i: usize) -> RuntimeResult<GosValue> {
         this.get(i, ValueType::Uint8)
     }
 
+    /// Strings can't be sliced beyond their length like slices can
+    #[inline]
+    pub fn slice(this: &StringObj, begin: isize, end: Option<isize>) -> RuntimeResult<StringObj> {
+        this.slice_within(begin, end, None, this.end(), "length")
+    }
+
     #[inline]
     pub fn index_elem(this: &StringObj, i: usize) -> u8 {
         this.index_elem(i).into_inner()
@@ -710,6 +900,17 @@ impl StructObj {
     pub fn borrow_fields_mut(&self) -> RefMut<Vec<GosValue>> {
         self.fields.borrow_mut()
     }
+
+    /// A copy of the struct, the fields that are structs or arrays are
+    /// copied too
+    pub fn copy_semantic(&self, gcv: &GcoVec) -> StructObj {
+        StructObj::new(
+            self.borrow_fields()
+                .iter()
+                .map(|x| x.copy_semantic(gcv))
+                .collect(),
+        )
+    }
 }
 
 impl Eq for StructObj {}
@@ -847,7 +1048,9 @@ impl PartialEq for InterfaceObj {
     #[inline]
     fn eq(&self, other: &InterfaceObj) -> bool {
         match (self, other) {
-            (Self::Gos(x, _), Self::Gos(y, _)) => x == y,
+            // values of different dynamic types are never equal
+            (Self::Gos(x, Some((mx, _))), Self::Gos(y, Some((my, _)))) => mx == my && x == y,
+            (Self::Gos(x, _), Self::Gos(y, _)) => x.typ() == y.typ() && x == y,
             (Self::Ffi(x), Self::Ffi(y)) => Rc::ptr_eq(&x.ffi_obj, &y.ffi_obj),
             _ => false,
         }
@@ -868,7 +1071,10 @@ impl Display for InterfaceObj {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Self::Gos(v, _) => write!(f, "{}", v),
-            Self::Ffi(ffi) => write!(f, "<ffi>{:?}", ffi.ffi_obj),
+            Self::Ffi(ffi) => match ffi.ffi_obj.error_msg() {
+                Some(msg) => f.write_str(msg),
+                None => write!(f, "<ffi>{:?}", ffi.ffi_obj),
+            },
         }
     }
 }
@@ -883,6 +1089,10 @@ pub struct ChannelObj {
 }
 
 impl ChannelObj {
+    /// The largest buffer a channel may have, the buffer is allocated when
+    /// the channel is made
+    pub const MAX_CAP: usize = isize::MAX as usize / std::mem::size_of::<GosValue>();
+
     pub fn new(cap: usize, recv_zero: GosValue) -> ChannelObj {
         ChannelObj {
             chan: Channel::new(cap),
@@ -1205,6 +1415,11 @@ impl UnsafePtrObj {
         &*self.ptr
     }
 
+    /// The address of the pointer, what converting it to a uintptr gives
+    pub fn addr(&self) -> usize {
+        Rc::as_ptr(&self.ptr) as *const () as usize
+    }
+
     pub fn downcast_ref<T: Any>(&self) -> RuntimeResult<&T> {
         self.ptr
             .as_any()
@@ -1513,7 +1728,9 @@ impl ClosureObj {
         match self {
             Self::Gos(g) => {
                 let sig = &metas[g.meta.key].as_signature();
-                sig.params.len() + sig.results.len()
+                // the receiver is the first parameter, bound or not
+                let recv = sig.recv.is_some() as usize;
+                sig.params.len() + sig.results.len() + recv
             }
             Self::Ffi(f) => {
                 let sig = &metas[f.meta.key].as_signature();
@@ -1568,8 +1785,6 @@ pub struct PackageVal {
     member_types: Vec<ValueType>,
     member_indices: HashMap<String, OpIndex>,
     init_funcs: Vec<GosValue>,
-    // maps func_member_index of the constructor to pkg_member_index
-    var_mapping: Option<HashMap<OpIndex, OpIndex>>,
 }
 
 impl PackageVal {
@@ -1579,7 +1794,24 @@ impl PackageVal {
             member_types: vec![],
             member_indices: HashMap::new(),
             init_funcs: vec![],
-            var_mapping: Some(HashMap::new()),
+        }
+    }
+
+    /// Creates a package from the parts of one that has been serialized
+    pub fn with_parts(
+        members: Vec<GosValue>,
+        member_types: Vec<ValueType>,
+        member_indices: HashMap<String, OpIndex>,
+        init_funcs: Vec<GosValue>,
+    ) -> PackageVal {
+        PackageVal {
+            members: members
+                .into_iter()
+                .map(|x| Rc::new(RefCell::new(x)))
+                .collect(),
+            member_types: member_types,
+            member_indices: member_indices,
+            init_funcs: init_funcs,
         }
     }
 
@@ -1591,12 +1823,6 @@ impl PackageVal {
         index as OpIndex
     }
 
-    pub fn add_var_mapping(&mut self, name: String, fn_index: OpIndex) -> OpIndex {
-        let index = *self.get_member_index(&name).unwrap();
-        self.var_mapping.as_mut().unwrap().insert(fn_index, index);
-        index
-    }
-
     pub fn add_init_func(&mut self, func: GosValue) {
         self.init_funcs.push(func);
     }
@@ -1605,12 +1831,9 @@ impl PackageVal {
         self.member_indices.get(name)
     }
 
-    pub fn inited(&self) -> bool {
-        self.var_mapping.is_none()
-    }
-
-    pub fn set_inited(&mut self) {
-        self.var_mapping = None
+    #[inline]
+    pub fn member_count(&self) -> usize {
+        self.members.len()
     }
 
     #[inline]
@@ -1629,13 +1852,18 @@ impl PackageVal {
     }
 
     #[inline]
-    pub fn init_vars(&self, stack: &mut Stack) {
-        let mapping = self.var_mapping.as_ref().unwrap();
-        let count = mapping.len();
-        for i in 0..count {
-            let vi = mapping[&((count - 1 - i) as OpIndex)];
-            *self.member_mut(vi) = stack.pop_value();
-        }
+    pub fn member_type(&self, i: OpIndex) -> ValueType {
+        self.member_types[i as usize]
+    }
+
+    #[inline]
+    pub fn member_indices(&self) -> &HashMap<String, OpIndex> {
+        &self.member_indices
+    }
+
+    #[inline]
+    pub fn init_funcs(&self) -> &[GosValue] {
+        &self.init_funcs
     }
 }
 
@@ -1675,13 +1903,65 @@ pub enum FuncFlag {
     HasDefer,
 }
 
+/// What a function keeps for debugging and stack traces, which running it
+/// doesn't need, so that it can be stripped from release code
+#[derive(Clone, Debug, Default)]
+pub struct DebugInfo {
+    /// the positions of the instructions, one per instruction
+    pub pos: Vec<Option<usize>>,
+    /// the named locals, parameters and results
+    pub locals: Vec<LocalVar>,
+}
+
+impl DebugInfo {
+    /// The locals in scope at the instruction `pc`, by slot. Of the ones
+    /// with the same name, the innermost shadows the others.
+    pub fn locals_at(&self, pc: usize) -> Vec<&LocalVar> {
+        let pos = match self.pos.get(pc).copied().flatten() {
+            Some(p) => p,
+            None => return vec![],
+        };
+        let mut visible: HashMap<&str, &LocalVar> = HashMap::new();
+        for l in self
+            .locals
+            .iter()
+            .filter(|l| l.start <= pos && pos <= l.end)
+        {
+            match visible.get(&l.name[..]) {
+                Some(other) if other.start >= l.start => {}
+                _ => {
+                    visible.insert(&l.name, l);
+                }
+            }
+        }
+        let mut locals: Vec<&LocalVar> = visible.into_values().collect();
+        locals.sort_by_key(|l| l.index);
+        locals
+    }
+}
+
+/// A named local of a function
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LocalVar {
+    pub name: String,
+    /// its slot, from the start of the frame
+    pub index: OpIndex,
+    /// the positions in the source it's in scope between, both included
+    pub start: usize,
+    pub end: usize,
+}
+
 /// FunctionVal is the direct container of the Opcode.
 #[derive(Clone, Debug)]
 pub struct FunctionVal {
     pub package: PackageKey,
     pub meta: Meta,
+    /// the name in stack traces, like `main.main`, `main.(*T).M` or
+    /// `main.main.func1`, empty for the functions the compiler adds
+    pub name: String,
     code: Vec<Instruction>,
-    pos: Vec<Option<usize>>,
+    /// None once stripped
+    debug: Option<DebugInfo>,
     pub consts: Vec<GosValue>,
     pub up_ptrs: Vec<ValueDesc>,
 
@@ -1689,10 +1969,16 @@ pub struct FunctionVal {
     pub ret_zeros: Vec<GosValue>,
     pub local_zeros: Vec<GosValue>,
     pub flag: FuncFlag,
+    /// the code is kept as generated, without superinstructions, and the
+    /// passes over the code leave it alone, for `//gos:noopt`
+    pub keep_code: bool,
 
     entities: HashMap<KeyData, EntIndex>,
     uv_entities: HashMap<KeyData, EntIndex>,
     local_alloc: OpIndex,
+    // the index of the last instruction emitted, for it to be fused with the
+    // next one. Raw data in the code doesn't count.
+    last_inst: Option<usize>,
 }
 
 impl FunctionVal {
@@ -1711,17 +1997,20 @@ impl FunctionVal {
         FunctionVal {
             package: package,
             meta: meta,
+            name: String::new(),
             code: Vec::new(),
-            pos: Vec::new(),
+            debug: Some(DebugInfo::default()),
             consts: Vec::new(),
             up_ptrs: Vec::new(),
             stack_temp_types: p_types,
             ret_zeros: returns,
             local_zeros: Vec::new(),
             flag: flag,
+            keep_code: false,
             entities: HashMap::new(),
             uv_entities: HashMap::new(),
             local_alloc: 0,
+            last_inst: None,
         }
     }
 
@@ -1735,9 +2024,44 @@ impl FunctionVal {
         self.code.get_mut(i).unwrap()
     }
 
+    /// The positions of the instructions, empty without debug info
+    #[inline]
+    pub fn pos(&self) -> &[Option<usize>] {
+        self.debug.as_ref().map_or(&[], |d| &d.pos)
+    }
+
+    #[inline]
+    pub fn debug(&self) -> Option<&DebugInfo> {
+        self.debug.as_ref()
+    }
+
+    /// Removes the debug info, code emitted afterwards has none either
+    pub fn take_debug(&mut self) -> Option<DebugInfo> {
+        self.debug.take()
+    }
+
+    /// Sets the debug info taken by `take_debug`, fails if it doesn't match
+    /// the code
+    pub fn set_debug(&mut self, debug: DebugInfo) -> bool {
+        if debug.pos.len() != self.code.len() {
+            return false;
+        }
+        self.debug = Some(debug);
+        true
+    }
+
+    /// Records the name and the scope of a local for debugging
+    pub fn add_local_var(&mut self, var: LocalVar) {
+        if let Some(d) = &mut self.debug {
+            d.locals.push(var);
+        }
+    }
+
     #[inline]
-    pub fn pos(&self) -> &Vec<Option<usize>> {
-        &self.pos
+    fn push_pos(&mut self, pos: Option<usize>) {
+        if let Some(d) = &mut self.debug {
+            d.pos.push(pos);
+        }
     }
 
     #[inline]
@@ -1760,6 +2084,11 @@ impl FunctionVal {
         self.flag == FuncFlag::PkgCtor
     }
 
+    #[inline]
+    pub fn local_alloc(&self) -> OpIndex {
+        self.local_alloc
+    }
+
     #[inline]
     pub fn local_count(&self) -> usize {
         self.local_alloc as usize - self.param_count() - self.ret_count()
@@ -1788,8 +2117,10 @@ impl FunctionVal {
 
     #[inline]
     pub fn push_inst_pos(&mut self, i: Instruction, pos: Option<usize>) {
+        self.fuse_last(i.op());
+        self.last_inst = Some(self.code.len());
         self.code.push(i);
-        self.pos.push(pos);
+        self.push_pos(pos);
     }
 
     #[inline]
@@ -1801,14 +2132,53 @@ impl FunctionVal {
         pos: Option<usize>,
     ) {
         let i = Instruction::new(op, types[0], types[1], types[2], imm);
+        self.push_inst_pos(i, pos);
+    }
+
+    /// Appends `i` as it is, without fusing it with its neighbours
+    pub fn push_inst_unfused(&mut self, i: Instruction, pos: Option<usize>) {
+        self.last_inst = None;
         self.code.push(i);
-        self.pos.push(pos);
+        self.push_pos(pos);
+    }
+
+    /// Replaces the code of a function loaded from a bytecode image, the
+    /// positions of `debug` are as many as the instructions of `code`.
+    pub fn set_code(
+        &mut self,
+        code: Vec<Instruction>,
+        debug: Option<DebugInfo>,
+        local_alloc: OpIndex,
+    ) {
+        debug_assert!(debug.as_ref().map_or(true, |d| d.pos.len() == code.len()));
+        self.code = code;
+        self.debug = debug;
+        self.local_alloc = local_alloc;
+        self.last_inst = None;
     }
 
     pub fn emit_raw_inst(&mut self, u: u64, pos: Option<usize>) {
         let i = Instruction::from_u64(u);
+        // passes over the code rely on raw data not looking like instructions
+        debug_assert!(i.op() == Opcode::VOID);
         self.code.push(i);
-        self.pos.push(pos);
+        self.push_pos(pos);
+    }
+
+    /// Turns the last instruction into a superinstruction if it can be fused
+    /// with the next one, `next` is still emitted after it.
+    #[inline]
+    fn fuse_last(&mut self, next: Opcode) {
+        if self.keep_code {
+            return;
+        }
+        if let Some(last) = self.last_inst {
+            if last + 1 == self.code.len() {
+                if let Some(op) = self.code[last].op().fused_with(next) {
+                    self.code[last].set_op(op);
+                }
+            }
+        }
     }
 
     pub fn emit_code_with_type(&mut self, code: Opcode, t: ValueType, pos: Option<usize>) {
@@ -1850,7 +2220,7 @@ impl FunctionVal {
         let flag = if comma_ok { 1 } else { 0 };
         inst.set_t2_with_index(flag);
         self.code.push(inst);
-        self.pos.push(pos);
+        self.push_pos(pos);
     }
 
     pub fn emit_code(&mut self, code: Opcode, pos: Option<usize>) {
@@ -0,0 +1,401 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Microbenchmarks of single opcodes.
+//!
+//! An [`OpCase`] is a hand written instruction sequence, it's run by the VM in
+//! a counted loop without going through the parser, the checker or codegen.
+//! The cost of the loop itself is measured with an empty body and subtracted,
+//! what's left is the cost of one pass over the sequence. Timings can be
+//! saved as a baseline and compared by a [`RegressionGate`], so that a slower
+//! dispatcher or value op shows up before it's buried in a whole program.
+
+use super::ffi::{FfiFactory, FfiStatics};
+use super::gc::GcoVec;
+use super::instruction::{Instruction, OpIndex, Opcode, ValueType};
+use super::objects::{FuncFlag, FunctionVal, VMObjects};
+use super::value::GosValue;
+use super::vm::{ByteCode, GosVM};
+use std::any::Any;
+use std::time::{Duration, Instant};
+
+/// An instruction sequence to be measured
+#[derive(Clone, Debug)]
+pub struct OpCase {
+    pub name: String,
+    /// The initial values of the locals the body may use, at index 0, 1, ...
+    pub locals: Vec<GosValue>,
+    pub consts: Vec<GosValue>,
+    /// Run once per iteration, must leave the stack as it found it.
+    /// It's emitted as it is, superinstructions have to be spelled out.
+    pub body: Vec<Instruction>,
+}
+
+impl OpCase {
+    pub fn new(name: &str, body: Vec<Instruction>) -> OpCase {
+        OpCase {
+            name: name.to_owned(),
+            locals: vec![],
+            consts: vec![],
+            body: body,
+        }
+    }
+
+    pub fn with_locals(mut self, locals: Vec<GosValue>) -> OpCase {
+        self.locals = locals;
+        self
+    }
+
+    pub fn with_consts(mut self, consts: Vec<GosValue>) -> OpCase {
+        self.consts = consts;
+        self
+    }
+
+    /// Builds the entry function that runs the body `iterations` times:
+    ///
+    /// ```text
+    /// loop: <body>
+    ///       counter = counter + 1
+    ///       if counter < iterations { goto loop }
+    ///       return
+    /// ```
+    fn build(&self, iterations: usize) -> ByteCode {
+        let iterations = OpIndex::try_from(iterations).expect("too many iterations");
+        let mut objs = VMObjects::new();
+        let gcv = GcoVec::new();
+        let mut func = FunctionVal::new(
+            slotmap::Key::null(),
+            objs.s_meta.default_sig.clone(),
+            &objs.metas,
+            &gcv,
+            FuncFlag::Default,
+        );
+        func.consts = self.consts.clone();
+        for v in self.locals.iter() {
+            func.add_local(None);
+            func.add_local_zero(v.clone(), v.typ());
+        }
+        func.add_local(None);
+        func.add_local_zero(GosValue::new_int(0), ValueType::Int);
+        let counter = self.locals.len() as OpIndex;
+
+        let int = Some(ValueType::Int);
+        let mut store = Instruction::new(Opcode::STORE_LOCAL, int, None, None, None);
+        store.set_imm824(-1, counter);
+        let code = self.body.iter().cloned().chain([
+            Instruction::new(Opcode::LOAD_LOCAL, None, None, None, Some(counter)),
+            Instruction::new(Opcode::PUSH_IMM, int, None, None, Some(1)),
+            Instruction::new(Opcode::ADD, int, None, None, None),
+            store,
+            Instruction::new(Opcode::POP, None, None, None, Some(1)),
+            Instruction::new(Opcode::LOAD_LOCAL, None, None, None, Some(counter)),
+            Instruction::new(Opcode::PUSH_IMM, int, None, None, Some(iterations)),
+            Instruction::new(Opcode::LSS, int, None, None, None),
+        ]);
+        for inst in code {
+            func.push_inst_unfused(inst, None);
+        }
+        let back = -(func.next_code_index() as OpIndex + 1);
+        let jump = Instruction::new(Opcode::JUMP_IF, None, None, None, Some(back));
+        func.push_inst_unfused(jump, None);
+        let ret = Instruction::new(Opcode::RETURN, Some(ValueType::Void), None, None, None);
+        func.push_inst_unfused(ret, None);
+
+        let entry = objs.functions.insert(func);
+        ByteCode::new(Box::pin(objs), vec![], vec![], entry)
+    }
+}
+
+/// The measured cost of an [`OpCase`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct OpTiming {
+    pub name: String,
+    /// Nanoseconds per pass over the body, loop overhead excluded
+    pub nanos: f64,
+}
+
+/// Runs [`OpCase`]s and reports the fastest of a number of rounds, which is
+/// the least disturbed by whatever else the machine is doing.
+pub struct OpBench {
+    iterations: usize,
+    rounds: usize,
+    ffi: FfiFactory,
+}
+
+impl OpBench {
+    pub fn new(iterations: usize, rounds: usize) -> OpBench {
+        assert!(iterations > 0 && rounds > 0);
+        OpBench {
+            iterations: iterations,
+            rounds: rounds,
+            ffi: FfiFactory::new(Box::new(NoStatics)),
+        }
+    }
+
+    pub fn run(&self, case: &OpCase) -> OpTiming {
+        self.run_all(std::slice::from_ref(case)).pop().unwrap()
+    }
+
+    pub fn run_all(&self, cases: &[OpCase]) -> Vec<OpTiming> {
+        let overhead = self.fastest(&OpCase::new("", vec![]));
+        cases
+            .iter()
+            .map(|c| {
+                let total = self.fastest(c);
+                OpTiming {
+                    name: c.name.clone(),
+                    nanos: total.saturating_sub(overhead).as_nanos() as f64
+                        / self.iterations as f64,
+                }
+            })
+            .collect()
+    }
+
+    fn fastest(&self, case: &OpCase) -> Duration {
+        (0..self.rounds)
+            .map(|_| {
+                // building the code is not part of the measurement
+                let vm = GosVM::new(case.build(self.iterations), &self.ffi, None);
+                let start = Instant::now();
+                vm.run();
+                start.elapsed()
+            })
+            .min()
+            .unwrap()
+    }
+}
+
+struct NoStatics;
+
+impl FfiStatics for NoStatics {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Cases covering the hottest opcodes: stack traffic, locals, int arithmetic
+/// and comparison, slice indexing and a superinstruction next to the pair it
+/// replaces.
+pub fn standard_cases() -> Vec<OpCase> {
+    let int = Some(ValueType::Int);
+    let inst = |op, t0, imm| Instruction::new(op, t0, None, None, imm);
+    let pop = inst(Opcode::POP, None, Some(1));
+    let two_ints = vec![GosValue::new_int(3), GosValue::new_int(4)];
+    let gcv = GcoVec::new();
+    let slice = GosValue::slice_with_data(
+        (0..8).map(|i| GosValue::new_int(i)).collect(),
+        ValueType::Int,
+        &gcv,
+    );
+    let mut store = inst(Opcode::STORE_LOCAL, int, None);
+    store.set_imm824(-1, 1);
+    vec![
+        OpCase::new(
+            "push_imm_pop",
+            vec![inst(Opcode::PUSH_IMM, int, Some(1)), pop],
+        ),
+        OpCase::new(
+            "push_const_pop",
+            vec![inst(Opcode::PUSH_CONST, None, Some(0)), pop],
+        )
+        .with_consts(vec![GosValue::new_int(42)]),
+        OpCase::new(
+            "load_local_pop",
+            vec![inst(Opcode::LOAD_LOCAL, None, Some(0)), pop],
+        )
+        .with_locals(two_ints.clone()),
+        OpCase::new(
+            "store_local",
+            vec![inst(Opcode::LOAD_LOCAL, None, Some(0)), store, pop],
+        )
+        .with_locals(two_ints.clone()),
+        OpCase::new(
+            "add_int",
+            vec![
+                inst(Opcode::LOAD_LOCAL, None, Some(0)),
+                inst(Opcode::LOAD_LOCAL, None, Some(1)),
+                inst(Opcode::ADD, int, None),
+                pop,
+            ],
+        )
+        .with_locals(two_ints.clone()),
+        OpCase::new(
+            "lss_int",
+            vec![
+                inst(Opcode::LOAD_LOCAL, None, Some(0)),
+                inst(Opcode::LOAD_LOCAL, None, Some(1)),
+                inst(Opcode::LSS, int, None),
+                pop,
+            ],
+        )
+        .with_locals(two_ints.clone()),
+        OpCase::new(
+            "add_imm",
+            vec![
+                inst(Opcode::LOAD_LOCAL, None, Some(0)),
+                inst(Opcode::PUSH_IMM, int, Some(1)),
+                inst(Opcode::ADD, int, None),
+                pop,
+            ],
+        )
+        .with_locals(two_ints.clone()),
+        OpCase::new(
+            "add_imm_fused",
+            vec![
+                inst(Opcode::LOAD_LOCAL, None, Some(0)),
+                inst(Opcode::PUSH_IMM_ADD, int, Some(1)),
+                inst(Opcode::ADD, int, None),
+                pop,
+            ],
+        )
+        .with_locals(two_ints),
+        OpCase::new(
+            "load_index_imm",
+            vec![
+                inst(Opcode::LOAD_LOCAL, None, Some(0)),
+                inst(Opcode::LOAD_INDEX_IMM, Some(ValueType::Slice), Some(3)),
+                pop,
+            ],
+        )
+        .with_locals(vec![slice]),
+        OpCase::new("jump", vec![inst(Opcode::JUMP, None, Some(0))]),
+    ]
+}
+
+/// A case that got slower than its baseline allows
+#[derive(Clone, Debug, PartialEq)]
+pub struct Regression {
+    pub name: String,
+    pub baseline: f64,
+    pub current: f64,
+}
+
+/// Decides whether new timings are acceptable given a baseline
+#[derive(Clone, Copy, Debug)]
+pub struct RegressionGate {
+    /// The allowed slowdown as a ratio, 0.25 lets a case get 25% slower
+    pub tolerance: f64,
+    /// Slowdowns smaller than this many nanoseconds are taken as noise
+    pub min_delta: f64,
+}
+
+impl Default for RegressionGate {
+    fn default() -> RegressionGate {
+        RegressionGate {
+            tolerance: 0.25,
+            min_delta: 1.0,
+        }
+    }
+}
+
+impl RegressionGate {
+    /// Returns the cases in `current` that regressed, cases missing from
+    /// either side are not compared.
+    pub fn check(&self, baseline: &[OpTiming], current: &[OpTiming]) -> Vec<Regression> {
+        current
+            .iter()
+            .filter_map(|cur| {
+                let base = baseline.iter().find(|b| b.name == cur.name)?;
+                let slower = cur.nanos - base.nanos;
+                (slower > self.min_delta && cur.nanos > base.nanos * (1.0 + self.tolerance)).then(
+                    || Regression {
+                        name: cur.name.clone(),
+                        baseline: base.nanos,
+                        current: cur.nanos,
+                    },
+                )
+            })
+            .collect()
+    }
+}
+
+/// Formats timings as lines of `name nanos`, to be saved as a baseline
+pub fn to_baseline(timings: &[OpTiming]) -> String {
+    timings
+        .iter()
+        .map(|t| format!("{} {:.3}\n", t.name, t.nanos))
+        .collect()
+}
+
+/// Parses what [`to_baseline`] produced, blank lines and lines starting with
+/// `#` are skipped.
+pub fn parse_baseline(s: &str) -> Result<Vec<OpTiming>, String> {
+    s.lines()
+        .enumerate()
+        .map(|(i, l)| (i, l.trim()))
+        .filter(|(_, l)| !l.is_empty() && !l.starts_with('#'))
+        .map(|(i, l)| {
+            let mut parts = l.split_whitespace();
+            match (parts.next(), parts.next().map(|n| n.parse()), parts.next()) {
+                (Some(name), Some(Ok(nanos)), None) => Ok(OpTiming {
+                    name: name.to_owned(),
+                    nanos: nanos,
+                }),
+                _ => Err(format!("invalid baseline at line {}: {}", i + 1, l)),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_build() {
+        let case = OpCase::new("push_imm_pop", standard_cases()[0].body.clone());
+        let bc = case.build(10);
+        let code = bc.objects.functions[bc.entry].code();
+        // the body isn't fused and the loop jumps back to the first instruction
+        assert_eq!(code[0].op(), Opcode::PUSH_IMM);
+        assert_eq!(code[1].op(), Opcode::POP);
+        let jump = code.len() - 2;
+        assert_eq!(code[jump].op(), Opcode::JUMP_IF);
+        assert_eq!(jump as OpIndex + 1 + code[jump].imm(), 0);
+    }
+
+    #[test]
+    fn test_run_standard_cases() {
+        let cases = standard_cases();
+        let timings = OpBench::new(1000, 2).run_all(&cases);
+        assert_eq!(timings.len(), cases.len());
+        for (c, t) in cases.iter().zip(timings.iter()) {
+            assert_eq!(c.name, t.name);
+            assert!(t.nanos >= 0.0);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "checked_stack")]
+    #[should_panic(expected = "pop_bool expects a Bool operand, found a Int")]
+    fn test_checked_stack() {
+        let body = vec![
+            Instruction::new(Opcode::PUSH_IMM, Some(ValueType::Int), None, None, Some(1)),
+            Instruction::new(Opcode::JUMP_IF, None, None, None, Some(0)),
+        ];
+        OpBench::new(10, 1).run(&OpCase::new("bad_jump_if", body));
+    }
+
+    #[test]
+    fn test_gate() {
+        let t = |name: &str, nanos| OpTiming {
+            name: name.to_owned(),
+            nanos: nanos,
+        };
+        let baseline = parse_baseline("# ns per op\nadd 10.0\nlss 1.0\n\npop 2.0\n").unwrap();
+        assert_eq!(parse_baseline(&to_baseline(&baseline)).unwrap(), baseline);
+        assert!(parse_baseline("add ten").is_err());
+
+        let current = vec![t("add", 20.0), t("lss", 1.9), t("pop", 2.2), t("new", 99.0)];
+        let regressions = RegressionGate::default().check(&baseline, &current);
+        assert_eq!(
+            regressions,
+            vec![Regression {
+                name: "add".to_owned(),
+                baseline: 10.0,
+                current: 20.0,
+            }]
+        );
+    }
+}
@@ -0,0 +1,108 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Counting the objects a program allocates, by kind and by the
+//! instructions that allocate them, to find the allocation hotspots.
+//!
+//! The byte arrays made for the native functions, like the ones of strings
+//! converted to byte slices, are not counted. Profiling costs a store per
+//! instruction and a hash map update per allocation, without it the cost is
+//! one branch per instruction and per allocation.
+
+use super::value::{FunctionKey, VMObjects};
+use super::vm::StackFrame;
+use goscript_parser::FileSet;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum AllocKind {
+    /// arrays, and the arrays under slices
+    Array,
+    Closure,
+    Map,
+    Struct,
+}
+
+/// The allocations of a kind by an instruction
+#[derive(Debug, Clone)]
+pub struct AllocRecord {
+    pub kind: AllocKind,
+    /// the function and the position of the instruction, None for the
+    /// functions the compiler adds if there is no debug info, and for the
+    /// allocations made before the first instruction runs
+    pub site: Option<StackFrame>,
+    pub count: u64,
+}
+
+/// The allocations of a run
+#[derive(Debug, Clone, Default)]
+pub struct AllocReport {
+    /// the allocations by kind, the kinds with none are left out
+    pub kinds: Vec<(AllocKind, u64)>,
+    /// the allocations by kind and instruction, the most frequent first
+    pub sites: Vec<AllocRecord>,
+}
+
+/// The counts of the allocations by kind and by the instruction running
+#[derive(Default)]
+pub struct AllocProfile {
+    site: Cell<Option<(FunctionKey, usize)>>,
+    counts: RefCell<HashMap<(AllocKind, Option<(FunctionKey, usize)>), u64>>,
+}
+
+impl AllocProfile {
+    pub fn new() -> AllocProfile {
+        AllocProfile::default()
+    }
+
+    /// Sets the instruction the following allocations are made by
+    #[inline]
+    pub(crate) fn set_site(&self, func: FunctionKey, pc: usize) {
+        self.site.set(Some((func, pc)));
+    }
+
+    #[inline]
+    pub(crate) fn record(&self, kind: AllocKind) {
+        *self
+            .counts
+            .borrow_mut()
+            .entry((kind, self.site.get()))
+            .or_insert(0) += 1;
+    }
+
+    /// The allocations made so far
+    pub fn report(&self, objs: &VMObjects, fs: Option<&FileSet>) -> AllocReport {
+        let counts = self.counts.borrow();
+        let mut kinds: HashMap<AllocKind, u64> = HashMap::new();
+        for (&(kind, _), &count) in counts.iter() {
+            *kinds.entry(kind).or_insert(0) += count;
+        }
+        let mut kinds: Vec<(AllocKind, u64)> = kinds.into_iter().collect();
+        kinds.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+        let mut sites: Vec<AllocRecord> = counts
+            .iter()
+            .map(|(&(kind, site), &count)| AllocRecord {
+                kind: kind,
+                site: site.and_then(|(func, pc)| {
+                    objs.functions.get(func)?;
+                    StackFrame::new(func, pc, objs, fs)
+                }),
+                count: count,
+            })
+            .collect();
+        sites.sort_by(|a, b| {
+            let pos = |r: &AllocRecord| r.site.as_ref().map(|s| (s.file.clone(), s.line, s.column));
+            b.count
+                .cmp(&a.count)
+                .then_with(|| pos(a).cmp(&pos(b)))
+                .then(a.kind.cmp(&b.kind))
+        });
+        AllocReport {
+            kinds: kinds,
+            sites: sites,
+        }
+    }
+}
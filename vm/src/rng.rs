@@ -0,0 +1,26 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! The random numbers of the VM and the std library, like the order select
+//! tries its cases in. They are not for cryptography.
+//!
+//! The generator is seeded by the hasher of std rather than the OS, so it
+//! works on targets without an entropy source like wasm32-unknown-unknown.
+
+use rand::rngs::SmallRng;
+use rand::SeedableRng;
+use std::cell::RefCell;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+
+thread_local! {
+    static RNG: RefCell<SmallRng> = RefCell::new(SmallRng::seed_from_u64(
+        RandomState::new().build_hasher().finish(),
+    ));
+}
+
+/// Calls `f` with the generator of the thread
+pub fn with_rng<T>(f: impl FnOnce(&mut SmallRng) -> T) -> T {
+    RNG.with(|rng| f(&mut rng.borrow_mut()))
+}
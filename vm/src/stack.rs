@@ -16,6 +16,7 @@ const DEFAULT_CAPACITY: usize = 1024;
 
 macro_rules! stack_binary_op {
     ($stack:ident, $op:tt, $t:ident) => {{
+        $stack.check_top(2, $t, stringify!($op));
         let len = $stack.len();
         let a = $stack.get_data(len - 2);
         let b = $stack.get_data(len - 1);
@@ -26,20 +27,24 @@ macro_rules! stack_binary_op {
 
 macro_rules! stack_binary_op_shift {
     ($stack:ident, $op:tt, $t0:ident, $t1:ident) => {{
-        let mut right = $stack.pop_value();
-        right.cast_copyable($t1, ValueType::Uint32);
-        $stack
-            .get_data_mut($stack.len() - 1)
-            .$op(right.as_uint32(), $t0);
+        $stack.check_top(1, $t1, stringify!($op));
+        let right = $stack.pop_value();
+        $stack.check_top(1, $t0, stringify!($op));
+        let count = right.data().as_shift_count($t1)?;
+        $stack.get_data_mut($stack.len() - 1).$op(&count, $t0);
+        Ok(())
     }};
 }
 
 macro_rules! stack_cmp_op {
     ($stack:ident, $op:tt, $t:ident) => {{
+        $stack.check_top(2, $t, stringify!($op));
         let len = $stack.len();
         let a = $stack.get_data(len - 2);
         let b = $stack.get_data(len - 1);
-        *$stack.get_data_mut(len - 2) = ValueData::new_bool(ValueData::$op(a, b, $t));
+        // the result is a bool whatever the operands are, so replace the type
+        // tag along with the data
+        *$stack.get_mut(len - 2) = GosValue::new_bool(ValueData::$op(a, b, $t));
         $stack.pop_discard_copyable();
     }};
 }
@@ -88,6 +93,7 @@ macro_rules! store_local_val {
             //dbg!($r_index, $stack.copy_semantic(ri, $gcos));
             $to.set($s_index, $stack.copy_semantic(ri, $gcos));
         } else {
+            check_value_type($to.get($s_index), $t, "op-assign");
             $to.set(
                 $s_index,
                 $stack
@@ -98,6 +104,31 @@ macro_rules! store_local_val {
     }};
 }
 
+#[inline(always)]
+fn check_value_type(v: &GosValue, t: ValueType, op: &str) {
+    if cfg!(feature = "checked_stack") && v.typ() != t {
+        panic!(
+            "checked stack: {} expects a {:?} operand, found a {:?}",
+            op,
+            t,
+            v.typ()
+        );
+    }
+}
+
+#[cold]
+fn out_of_range(index: usize, len: usize) -> ! {
+    panic!(
+        "checked stack: index {} out of range, stack has {}",
+        index, len
+    );
+}
+
+/// The operand stack of a fiber.
+///
+/// Operations take the types of their operands from the instructions rather
+/// than the values. Building with the `checked_stack` feature verifies them
+/// against the values' own type tags, along with every index into the stack.
 pub struct Stack {
     vec: Vec<GosValue>,
 }
@@ -112,7 +143,14 @@ impl Stack {
 
     #[inline]
     pub fn get(&self, index: usize) -> &GosValue {
-        unsafe { self.vec.get_unchecked(index) }
+        if cfg!(feature = "checked_stack") {
+            let len = self.len();
+            self.vec
+                .get(index)
+                .unwrap_or_else(|| out_of_range(index, len))
+        } else {
+            unsafe { self.vec.get_unchecked(index) }
+        }
     }
 
     #[inline]
@@ -122,7 +160,14 @@ impl Stack {
 
     #[inline]
     pub fn get_mut(&mut self, index: usize) -> &mut GosValue {
-        unsafe { self.vec.get_unchecked_mut(index) }
+        if cfg!(feature = "checked_stack") {
+            let len = self.len();
+            self.vec
+                .get_mut(index)
+                .unwrap_or_else(|| out_of_range(index, len))
+        } else {
+            unsafe { self.vec.get_unchecked_mut(index) }
+        }
     }
 
     #[inline]
@@ -179,42 +224,48 @@ impl Stack {
 
     #[inline]
     pub fn pop_bool(&mut self) -> bool {
-        *self.pop_value().as_bool()
+        *self.pop_typed(ValueType::Bool, "pop_bool").as_bool()
     }
 
     #[inline]
     pub fn pop_int(&mut self) -> isize {
-        *self.pop_value().as_int()
+        *self.pop_typed(ValueType::Int, "pop_int").as_int()
     }
 
     #[inline]
     pub fn pop_int32(&mut self) -> i32 {
-        *self.pop_value().as_int32()
+        *self.pop_typed(ValueType::Int32, "pop_int32").as_int32()
     }
 
     #[inline]
     pub fn pop_uint(&mut self) -> usize {
-        *self.pop_value().as_uint()
+        *self.pop_typed(ValueType::Uint, "pop_uint").as_uint()
     }
 
     #[inline]
     pub fn pop_uint32(&mut self) -> u32 {
-        *self.pop_value().as_uint32()
+        *self.pop_typed(ValueType::Uint32, "pop_uint32").as_uint32()
     }
 
     #[inline]
     pub fn pop_float32(&mut self) -> F32 {
-        *self.pop_value().as_float32()
+        *self
+            .pop_typed(ValueType::Float32, "pop_float32")
+            .as_float32()
     }
 
     #[inline]
     pub fn pop_float64(&mut self) -> F64 {
-        *self.pop_value().as_float64()
+        *self
+            .pop_typed(ValueType::Float64, "pop_float64")
+            .as_float64()
     }
 
     #[inline]
     pub fn pop_complex64(&mut self) -> Complex64 {
-        *self.pop_value().as_complex64()
+        *self
+            .pop_typed(ValueType::Complex64, "pop_complex64")
+            .as_complex64()
     }
 
     #[inline]
@@ -277,6 +328,13 @@ impl Stack {
         self.pop_value().into_closure()
     }
 
+    /// Pops a value the caller is going to read as a `t`
+    #[inline]
+    fn pop_typed(&mut self, t: ValueType, op: &str) -> GosValue {
+        self.check_top(1, t, op);
+        self.pop_value()
+    }
+
     #[inline]
     pub fn pop_discard_copyable(&mut self) {
         self.pop_value();
@@ -326,6 +384,10 @@ impl Stack {
     fn read_with_ops(&self, lhs: &ValueData, r_index: OpIndex, t: ValueType) -> ValueData {
         let ri = Stack::offset(self.len(), -1);
         let op = Instruction::index2code(r_index);
+        match op {
+            Opcode::UNARY_ADD | Opcode::UNARY_SUB | Opcode::SHL | Opcode::SHR => {}
+            _ => self.check_top(1, t, op.text()),
+        }
         read_with_ops!(op, lhs, self.get_data(ri), self.get_data(ri), t)
     }
 
@@ -335,6 +397,7 @@ impl Stack {
             let i = Stack::offset(self.len(), r_index);
             self.copy_semantic(i, gcos)
         } else {
+            check_value_type(target, t, "op-assign");
             self.read_with_ops(target.data(), r_index, t).into_value(t)
         };
     }
@@ -393,6 +456,29 @@ impl Stack {
         }
     }
 
+    /// Like store_index, but the index of a slice element is not checked
+    /// against the length, the code generator proved it in range, for
+    /// `//gos:nobounds`
+    #[inline]
+    pub fn store_index_unchecked(
+        &self,
+        target: &GosValue,
+        key: &GosValue,
+        r_index: OpIndex,
+        t: ValueType,
+        gcv: &GcoVec,
+    ) -> RuntimeResult<()> {
+        if target.typ() != ValueType::Slice {
+            return self.store_index(target, key, r_index, t, gcv);
+        }
+        let index = *key.as_uint();
+        let slice = &target
+            .as_slice::<AnyElem>()
+            .ok_or_else(|| index_out_of_range(index, 0))?
+            .0;
+        self.store_array_entry(slice.array(), slice.begin() + index, r_index, t, gcv)
+    }
+
     #[inline]
     pub fn store_index_int(
         &self,
@@ -486,6 +572,7 @@ impl Stack {
     #[inline]
     pub fn switch_cmp(&mut self, t: ValueType, objs: &VMObjects) -> bool {
         if t.copyable() {
+            self.check_top(2, t, "switch_cmp");
             let len = self.len();
             let a = self.get_data(len - 2);
             let b = self.get_data(len - 1);
@@ -541,12 +628,12 @@ impl Stack {
     }
 
     #[inline]
-    pub fn shl(&mut self, t0: ValueType, t1: ValueType) {
+    pub fn shl(&mut self, t0: ValueType, t1: ValueType) -> RuntimeResult<()> {
         stack_binary_op_shift!(self, binary_op_shl, t0, t1)
     }
 
     #[inline]
-    pub fn shr(&mut self, t0: ValueType, t1: ValueType) {
+    pub fn shr(&mut self, t0: ValueType, t1: ValueType) -> RuntimeResult<()> {
         stack_binary_op_shift!(self, binary_op_shr, t0, t1)
     }
 
@@ -557,16 +644,19 @@ impl Stack {
 
     #[inline]
     pub fn unary_negate(&mut self, t: ValueType) {
+        self.check_top(1, t, "unary_negate");
         self.get_data_mut(self.len() - 1).unary_negate(t);
     }
 
     #[inline]
     pub fn unary_xor(&mut self, t: ValueType) {
+        self.check_top(1, t, "unary_xor");
         self.get_data_mut(self.len() - 1).unary_xor(t);
     }
 
     #[inline]
     pub fn logical_not(&mut self, t: ValueType) {
+        self.check_top(1, t, "logical_not");
         self.get_data_mut(self.len() - 1).unary_not(t);
     }
 
@@ -640,6 +730,25 @@ impl Stack {
         (base as isize + offset as isize) as usize
     }
 
+    /// With the `checked_stack` feature, panics unless the top `n` values are
+    /// `t`s. Otherwise the type encoded in the instruction is trusted, and a
+    /// mismatch reads the wrong field of the value's data without notice.
+    #[inline(always)]
+    fn check_top(&self, n: usize, t: ValueType, op: &str) {
+        if cfg!(feature = "checked_stack") {
+            let len = self.len();
+            if len < n {
+                panic!(
+                    "checked stack: {} needs {} operands, stack has {}",
+                    op, n, len
+                );
+            }
+            for v in self.vec[len - n..].iter() {
+                check_value_type(v, t, op);
+            }
+        }
+    }
+
     #[inline]
     fn get_data_mut(&mut self, index: usize) -> &mut ValueData {
         unsafe { self.get_mut(index).data_mut() }
@@ -678,10 +787,14 @@ impl Stack {
         t: ValueType,
         gcv: &GcoVec,
     ) -> RuntimeResult<()> {
-        let (array, index) = target
-            .as_some_slice::<AnyElem>()?
-            .0
-            .get_array_equivalent(index);
+        let slice = &target
+            .as_slice::<AnyElem>()
+            .ok_or_else(|| index_out_of_range(index, 0))?
+            .0;
+        if index >= slice.len() {
+            return Err(index_out_of_range(index, slice.len()));
+        }
+        let (array, index) = slice.get_array_equivalent(index);
         self.store_array_entry(array, index, r_index, t, gcv)
     }
 
@@ -695,7 +808,9 @@ impl Stack {
         t: ValueType,
         gcv: &GcoVec,
     ) -> RuntimeResult<()> {
-        let map = target.as_some_map()?;
+        let map = target
+            .as_map()
+            .ok_or_else(|| "assignment to entry in nil map".to_owned())?;
         map.0.touch_key(&key, gcv);
         let mut borrowed = map.0.borrow_data_mut();
         let mut target_cell = borrowed.get_mut(&key).unwrap();
@@ -720,20 +835,32 @@ impl fmt::Debug for Stack {
     }
 }
 
+enum RangeIter {
+    Map(GosHashMapIter<'static>),
+    Slice(SliceEnumIter<'static, AnyElem>),
+    String(StringEnumIter<'static>),
+    // received from by Opcode::RANGE itself, because receiving is async
+    Channel,
+    // a nil map or slice has nothing to range over
+    Nil,
+}
+
 /// store iterators for Opcode::RANGE
+///
+/// Every iterator is tagged with the loop that owns it, which is the stack_base
+/// of the frame and the pc of the RANGE instruction. A loop can be left without
+/// running out its iterator(break, continue and goto to an outer label, return),
+/// such iterators are dropped when an enclosing loop or a new run of the same
+/// loop finds them above its own.
+/// The container is kept alongside its iterator, because the iterator borrows it
+/// and a temporary(e.g. a slice literal) has no other reference after RANGE_INIT.
 pub struct RangeStack {
-    maps: Vec<GosHashMapIter<'static>>,
-    slices: Vec<SliceEnumIter<'static, AnyElem>>,
-    strings: Vec<StringEnumIter<'static>>,
+    iters: Vec<((usize, usize), RangeIter, GosValue)>,
 }
 
 impl RangeStack {
     pub fn new() -> RangeStack {
-        RangeStack {
-            maps: vec![],
-            slices: vec![],
-            strings: vec![],
-        }
+        RangeStack { iters: vec![] }
     }
 
     /// range_init creates iters and transmute them to 'static, then save them on stacks.
@@ -748,66 +875,115 @@ impl RangeStack {
         target: &GosValue,
         typ: ValueType,
         t_elem: ValueType,
+        owner: (usize, usize),
     ) -> RuntimeResult<()> {
-        match typ {
+        // iterators of returned frames, or left behind by the last run of this loop
+        match self.iters.iter().position(|(o, _, _)| *o == owner) {
+            Some(i) => self.iters.truncate(i),
+            None => {
+                while self.iters.last().map_or(false, |(o, _, _)| o.0 > owner.0) {
+                    self.iters.pop();
+                }
+            }
+        }
+        let iter = match typ {
+            ValueType::Channel => RangeIter::Channel,
+            _ if target.is_nil() => RangeIter::Nil,
+            // the iterators read the data of the containers, which must not
+            // be left behind by a copy on write while ranging
             ValueType::Map => {
-                let map = target.as_some_map()?.0.borrow_data();
-                let iter = unsafe { mem::transmute(map.iter()) };
-                self.maps.push(iter);
+                let map = &target.as_some_map()?.0;
+                map.unshare();
+                let map = map.borrow_data();
+                RangeIter::Map(unsafe { mem::transmute(map.iter()) })
             }
             ValueType::Array | ValueType::Slice => {
-                let iter = dispatcher_a_s_for(t_elem).array_slice_iter(&target)?;
-                self.slices.push(iter);
+                RangeIter::Slice(dispatcher_a_s_for(t_elem).array_slice_iter(&target)?)
             }
             ValueType::String => {
-                let iter = unsafe {
-                    mem::transmute(StrUtil::as_str(target.as_string()).chars().enumerate())
-                };
-                self.strings.push(iter);
+                let bytes = unsafe { target.as_string().as_raw_slice::<u8>() };
+                RangeIter::String(unsafe { mem::transmute(StringEnumIter::new(&bytes)) })
             }
             _ => unreachable!(),
-        }
+        };
+        self.iters.push((owner, iter, target.clone()));
         Ok(())
     }
 
-    pub fn range_body(&mut self, typ: ValueType, t_elem: ValueType, stack: &mut Stack) -> bool {
-        match typ {
-            ValueType::Map => match self.maps.last_mut().unwrap().next() {
+    pub fn range_body(
+        &mut self,
+        t_elem: ValueType,
+        stack: &mut Stack,
+        owner: (usize, usize),
+    ) -> bool {
+        self.drop_left_loops(owner);
+        let done = match &mut self.iters.last_mut().unwrap().1 {
+            RangeIter::Map(iter) => match iter.next() {
                 Some((k, v)) => {
                     stack.push(k.clone());
                     stack.push(v.clone());
                     false
                 }
-                None => {
-                    self.maps.pop();
-                    true
-                }
+                None => true,
             },
-            ValueType::Array | ValueType::Slice => {
-                match dispatcher_a_s_for(t_elem).array_slice_next(self.slices.last_mut().unwrap()) {
-                    Some((k, v)) => {
-                        stack.push_int(k as isize);
-                        stack.push(v);
-                        false
-                    }
-                    None => {
-                        self.slices.pop();
-                        true
-                    }
-                }
-            }
-            ValueType::String => match self.strings.last_mut().unwrap().next() {
+            RangeIter::Slice(iter) => match dispatcher_a_s_for(t_elem).array_slice_next(iter) {
                 Some((k, v)) => {
                     stack.push_int(k as isize);
-                    stack.push_int(v as isize);
+                    stack.push(v);
                     false
                 }
-                None => {
-                    self.strings.pop();
-                    true
+                None => true,
+            },
+            RangeIter::String(iter) => match iter.next() {
+                Some((k, v)) => {
+                    stack.push_int(k as isize);
+                    stack.push(GosValue::new_int32(v as i32));
+                    false
                 }
+                None => true,
             },
-            _ => unreachable!(),
+            RangeIter::Channel => unreachable!(),
+            RangeIter::Nil => true,
+        };
+        if done {
+            self.iters.pop();
+        }
+        done
+    }
+
+    /// The channel a loop over a channel receives from, nil or not
+    pub fn range_channel(&mut self, owner: (usize, usize)) -> GosValue {
+        self.drop_left_loops(owner);
+        let (_, iter, target) = self.iters.last().unwrap();
+        debug_assert!(matches!(iter, RangeIter::Channel));
+        target.clone()
+    }
+
+    /// Ends the loop on the top, for the loops that detect the end themselves
+    pub fn range_done(&mut self) {
+        self.iters.pop();
+    }
+
+    // the iterators above this loop's own are from loops that have been left
+    fn drop_left_loops(&mut self, owner: (usize, usize)) {
+        while self.iters.last().unwrap().0 != owner {
+            self.iters.pop();
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_compare_result_type() {
+        let mut s = Stack::new();
+        s.push_int(1);
+        s.push_int(2);
+        s.compare_lss(ValueType::Int);
+        assert_eq!(s.len(), 1);
+        assert_eq!(s.get(0).typ(), ValueType::Bool);
+        assert!(s.pop_bool());
+    }
+}
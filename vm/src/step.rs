@@ -0,0 +1,277 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Observing a program instruction by instruction, the "teaching mode" of
+//! the VM. Visual debuggers and teaching tools register a `StepObserver`
+//! to animate how Go code executes on the VM.
+//!
+//! Observing formats the value stack of the goroutine after every
+//! instruction, so it is meant for small programs. Without an observer
+//! the cost is one branch per instruction.
+
+use super::instruction::{Instruction, OpIndex, Opcode, ValueType};
+use super::objects::FunctionKey;
+use super::stack::Stack;
+use goscript_parser::FilePos;
+use std::fmt;
+use std::rc::Rc;
+
+pub trait StepObserver {
+    /// Called after every instruction a goroutine executes
+    fn on_step(&self, step: &Step);
+}
+
+/// An instruction a goroutine executed, with the state of the goroutine
+#[derive(Debug, Clone)]
+pub struct Step {
+    /// the id of the goroutine, main is 0
+    pub fiber: usize,
+    pub instruction: DecodedInstruction,
+    /// the calls of the goroutine when the instruction started, outermost
+    /// first, the last one is running the instruction
+    pub frames: Vec<StepFrame>,
+    /// how the value stack of the goroutine changed
+    pub stack: StackDiff,
+}
+
+#[derive(Debug, Clone)]
+pub struct StepFrame {
+    pub func: FunctionKey,
+    /// the index of the instruction running, the call for the callers
+    pub pc: usize,
+    /// where the locals of the function start in the value stack
+    pub stack_base: usize,
+    pub pos: Option<FilePos>,
+    /// the names of the locals in scope and their indices in the value
+    /// stack, empty without debug info
+    pub locals: Vec<(String, usize)>,
+}
+
+/// The change of the value stack by an instruction, the values are
+/// formatted like `fmt.Print` would do
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StackDiff {
+    /// the number of values at the bottom left untouched
+    pub kept: usize,
+    /// the number of values above them that were removed or changed
+    pub popped: usize,
+    /// the values above them now
+    pub pushed: Vec<String>,
+}
+
+impl StackDiff {
+    /// Applies the change to the stack before the instruction, the stack
+    /// of a goroutine is empty before its first step
+    pub fn apply(&self, snapshot: &mut Vec<String>) {
+        snapshot.truncate(self.kept);
+        snapshot.extend(self.pushed.iter().cloned());
+    }
+}
+
+/// An instruction with its operands named after their use
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedInstruction {
+    pub op: Opcode,
+    /// the types the instruction works on, the unused ones are left out
+    pub types: Vec<ValueType>,
+    pub operands: Vec<(&'static str, OpIndex)>,
+    /// the operator of a compound assignment like `+=`
+    pub assign_op: Option<Opcode>,
+}
+
+impl DecodedInstruction {
+    pub fn new(inst: Instruction) -> DecodedInstruction {
+        let op = inst.op();
+        let t2_index = op.t2_is_index();
+        let raw = inst.get_u64();
+        let bytes = [(raw >> 48) as u8, (raw >> 40) as u8, (raw >> 32) as u8];
+        let types = bytes[..if t2_index { 2 } else { 3 }]
+            .iter()
+            .filter_map(|b| value_type(*b))
+            .collect();
+
+        let mut assign_op = None;
+        let mut operands = vec![];
+        let imm = |name| vec![(name, inst.imm())];
+        let mut imm824 = |name| {
+            let (rhs, index) = inst.imm824();
+            if rhs >= 0 {
+                assign_op = Some(Instruction::index2code(rhs));
+                vec![(name, index)]
+            } else {
+                vec![("rhs", rhs), (name, index)]
+            }
+        };
+        operands.extend(match op {
+            Opcode::PUSH_CONST
+            | Opcode::PUSH_ZERO_VALUE
+            | Opcode::LITERAL
+            | Opcode::TYPE_ASSERT => imm("const"),
+            Opcode::PUSH_IMM | Opcode::PUSH_IMM_ADD => imm("value"),
+            Opcode::POP => imm("count"),
+            Opcode::LOAD_LOCAL
+            | Opcode::LOAD_LOCAL_INDEX
+            | Opcode::LOAD_LOCAL_FIELD
+            | Opcode::REF_LOCAL => imm("local"),
+            Opcode::LOAD_UPVALUE => imm("upvalue"),
+            Opcode::LOAD_INDEX_IMM => imm("index"),
+            Opcode::LOAD_STRUCT_FIELD => imm("field"),
+            Opcode::LOAD_PKG_FIELD => imm("member"),
+            Opcode::JUMP
+            | Opcode::JUMP_IF
+            | Opcode::JUMP_IF_NOT
+            | Opcode::SHORT_CIRCUIT_OR
+            | Opcode::SHORT_CIRCUIT_AND
+            | Opcode::SWITCH => imm("offset"),
+            Opcode::STORE_LOCAL | Opcode::STORE_LOCAL_POP => imm824("local"),
+            Opcode::STORE_UPVALUE => imm824("upvalue"),
+            Opcode::STORE_INDEX_IMM => imm824("index"),
+            Opcode::STORE_STRUCT_FIELD => imm824("field"),
+            Opcode::STORE_PKG_FIELD => imm824("member"),
+            Opcode::STORE_DEREF => imm824("pointer"),
+            _ if inst.imm() != 0 => imm("imm"),
+            _ => vec![],
+        });
+        if t2_index {
+            let name = match op {
+                Opcode::LOAD_INDEX
+                | Opcode::LOAD_INDEX_IMM
+                | Opcode::LOAD_INDEX_UNCHECKED
+                | Opcode::TYPE_ASSERT => "comma_ok",
+                _ => "target",
+            };
+            operands.push((name, inst.t2_as_index()));
+        }
+        DecodedInstruction {
+            op: op,
+            types: types,
+            operands: operands,
+            assign_op: assign_op,
+        }
+    }
+}
+
+impl fmt::Display for DecodedInstruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.op.text())?;
+        if let Some(op) = self.assign_op {
+            write!(f, "({})", op.text())?;
+        }
+        for t in self.types.iter() {
+            write!(f, " {:?}", t)?;
+        }
+        for (name, val) in self.operands.iter() {
+            write!(f, " {}={}", name, val)?;
+        }
+        Ok(())
+    }
+}
+
+fn value_type(b: u8) -> Option<ValueType> {
+    if b == ValueType::Void as u8 || b > ValueType::FlagE as u8 {
+        None
+    } else {
+        Some(unsafe { std::mem::transmute::<u8, ValueType>(b) })
+    }
+}
+
+/// Reports the steps of a goroutine, a step is complete when the next one
+/// starts, or when the goroutine ends
+pub(crate) struct StepRecorder {
+    observer: Rc<dyn StepObserver>,
+    fiber: usize,
+    stack: Vec<String>,
+    pending: Option<(DecodedInstruction, Vec<StepFrame>)>,
+}
+
+impl StepRecorder {
+    pub fn new(observer: Rc<dyn StepObserver>, fiber: usize) -> StepRecorder {
+        StepRecorder {
+            observer: observer,
+            fiber: fiber,
+            // the first step reports the whole stack
+            stack: vec![],
+            pending: None,
+        }
+    }
+
+    /// Starts a step with the instruction about to run
+    pub fn step(&mut self, inst: Instruction, frames: Vec<StepFrame>, stack: &Stack) {
+        self.finish(stack);
+        self.pending = Some((DecodedInstruction::new(inst), frames));
+    }
+
+    /// Completes the last step with the stack it left
+    pub fn finish(&mut self, stack: &Stack) {
+        if let Some((inst, frames)) = self.pending.take() {
+            let now = snapshot(stack);
+            let kept = self
+                .stack
+                .iter()
+                .zip(now.iter())
+                .take_while(|(a, b)| a == b)
+                .count();
+            let diff = StackDiff {
+                kept: kept,
+                popped: self.stack.len() - kept,
+                pushed: now[kept..].to_vec(),
+            };
+            self.stack = now;
+            self.observer.on_step(&Step {
+                fiber: self.fiber,
+                instruction: inst,
+                frames: frames,
+                stack: diff,
+            });
+        }
+    }
+}
+
+fn snapshot(stack: &Stack) -> Vec<String> {
+    (0..stack.len()).map(|i| stack.get(i).to_string()).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        let mut i = Instruction::new(Opcode::STORE_LOCAL, Some(ValueType::Int), None, None, None);
+        i.set_imm824(-1, 2);
+        let d = DecodedInstruction::new(i);
+        assert_eq!(d.operands, vec![("rhs", -1), ("local", 2)]);
+        assert_eq!(d.to_string(), "STORE_LOCAL Int rhs=-1 local=2");
+
+        i.set_imm824(Instruction::code2index(Opcode::ADD), 0);
+        assert_eq!(
+            DecodedInstruction::new(i).to_string(),
+            "STORE_LOCAL(ADD) Int local=0"
+        );
+
+        let mut i = Instruction::new(
+            Opcode::LOAD_INDEX,
+            Some(ValueType::Map),
+            None,
+            None,
+            Some(3),
+        );
+        i.set_t2_with_index(1);
+        let d = DecodedInstruction::new(i);
+        assert_eq!(d.types, vec![ValueType::Map]);
+        assert_eq!(d.operands, vec![("imm", 3), ("comma_ok", 1)]);
+    }
+
+    #[test]
+    fn test_stack_diff() {
+        let diff = StackDiff {
+            kept: 1,
+            popped: 2,
+            pushed: vec!["3".to_owned()],
+        };
+        let mut snapshot = vec!["1".to_owned(), "2".to_owned(), "2".to_owned()];
+        diff.apply(&mut snapshot);
+        assert_eq!(snapshot, vec!["1", "3"]);
+    }
+}
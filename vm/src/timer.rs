@@ -0,0 +1,282 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+use super::clock::{self, Instant};
+use std::cell::RefCell;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+use std::time::Duration;
+
+/// The timers of a VM, a queue of deadlines with the wakers of the fibers
+/// sleeping on them.
+///
+/// The executor loop fires the due ones between ticks, and when there is
+/// nothing else to run it blocks the thread until the next deadline, so a
+/// sleeping fiber costs nothing until it's woken. The I/O waited on by the
+/// fibers wakes the thread too.
+#[derive(Clone)]
+pub struct Timers {
+    inner: Rc<RefCell<TimerQueue>>,
+    epoch: Instant,
+    io: IoWaits,
+}
+
+struct TimerQueue {
+    heap: BinaryHeap<Reverse<(Instant, u64)>>,
+    // entries cancelled before they fire are dropped from here, and skipped
+    // when they reach the top of the heap
+    wakers: HashMap<u64, Waker>,
+    next_id: u64,
+}
+
+impl Timers {
+    pub fn new() -> Timers {
+        Timers {
+            inner: Rc::new(RefCell::new(TimerQueue {
+                heap: BinaryHeap::new(),
+                wakers: HashMap::new(),
+                next_id: 0,
+            })),
+            epoch: Instant::now(),
+            io: IoWaits::default(),
+        }
+    }
+
+    /// The I/O the fibers wait on
+    pub fn io(&self) -> &IoWaits {
+        &self.io
+    }
+
+    /// Nanoseconds since the VM started, the monotonic clock of the scripts.
+    pub fn now_nanos(&self) -> i64 {
+        self.epoch.elapsed().as_nanos() as i64
+    }
+
+    /// The instant of the monotonic time `nanos`
+    pub fn instant_of(&self, nanos: i64) -> Instant {
+        self.epoch + Duration::from_nanos(nanos.max(0) as u64)
+    }
+
+    /// Returns a future that's ready at `deadline`
+    pub fn sleep_until(&self, deadline: Instant) -> Sleep {
+        Sleep {
+            timers: self.clone(),
+            deadline: deadline,
+            id: None,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.borrow().wakers.is_empty()
+    }
+
+    /// The earliest deadline that's still waited on
+    pub fn next_deadline(&self) -> Option<Instant> {
+        let mut q = self.inner.borrow_mut();
+        while let Some(Reverse((deadline, id))) = q.heap.peek() {
+            if q.wakers.contains_key(id) {
+                return Some(*deadline);
+            }
+            q.heap.pop();
+        }
+        None
+    }
+
+    /// Wakes the fibers whose deadlines have passed, returns how many
+    pub fn fire_due(&self) -> usize {
+        if self.is_empty() {
+            return 0;
+        }
+        let now = Instant::now();
+        let mut wakers = vec![];
+        {
+            let mut q = self.inner.borrow_mut();
+            while let Some(Reverse((deadline, id))) = q.heap.peek() {
+                if *deadline > now {
+                    break;
+                }
+                let id = *id;
+                q.heap.pop();
+                if let Some(w) = q.wakers.remove(&id) {
+                    wakers.push(w);
+                }
+            }
+        }
+        // wake without holding the borrow
+        let count = wakers.len();
+        wakers.into_iter().for_each(|w| w.wake());
+        count
+    }
+
+    /// Blocks the thread until the next deadline and fires it, returns false
+    /// if there is nothing to wait for.
+    pub fn park(&self) -> bool {
+        self.park_until(None)
+    }
+
+    /// Like `park`, but wakes up at `limit` at the latest. While there is
+    /// pending I/O it also wakes up when some of it is ready.
+    pub fn park_until(&self, limit: Option<Instant>) -> bool {
+        let next = self.next_deadline();
+        let deadline = match (next, limit) {
+            (Some(d), Some(l)) => Some(d.min(l)),
+            (Some(d), None) => Some(d),
+            (None, _) if self.io.is_pending() => limit,
+            (None, _) => return false,
+        };
+        let timeout = deadline.map(|d| {
+            let now = Instant::now();
+            if d > now {
+                d - now
+            } else {
+                Duration::ZERO
+            }
+        });
+        if self.io.is_pending() {
+            self.io.park(timeout);
+        } else if let Some(t) = timeout {
+            clock::sleep(t);
+        }
+        self.fire_due();
+        true
+    }
+}
+
+/// The I/O the fibers of a VM wait on, which other threads report ready.
+/// While some is pending, the executor loop blocks until it's woken by one
+/// of the waits, instead of reporting a deadlock.
+#[derive(Clone, Default)]
+pub struct IoWaits {
+    signal: Arc<IoSignal>,
+}
+
+#[derive(Default)]
+struct IoSignal {
+    pending: AtomicUsize,
+    woken: Mutex<bool>,
+    cond: Condvar,
+}
+
+impl IoWaits {
+    /// Starts a wait of the fiber `task` wakes, it's pending until dropped
+    pub fn wait(&self, task: &Waker) -> IoWait {
+        self.signal.pending.fetch_add(1, Ordering::SeqCst);
+        let waker = Arc::new(IoWaker {
+            task: task.clone(),
+            signal: self.signal.clone(),
+        });
+        IoWait {
+            waker: Waker::from(waker),
+            signal: self.signal.clone(),
+        }
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.signal.pending.load(Ordering::SeqCst) > 0
+    }
+
+    /// Blocks the thread until a wait is woken, or `timeout` has passed
+    fn park(&self, timeout: Option<Duration>) {
+        let s = &self.signal;
+        let woken = s.woken.lock().unwrap_or_else(|e| e.into_inner());
+        let mut woken = match timeout {
+            Some(t) => {
+                s.cond
+                    .wait_timeout_while(woken, t, |w| !*w)
+                    .unwrap_or_else(|e| e.into_inner())
+                    .0
+            }
+            None => s
+                .cond
+                .wait_while(woken, |w| !*w)
+                .unwrap_or_else(|e| e.into_inner()),
+        };
+        *woken = false;
+    }
+}
+
+/// A pending wait of a fiber, its waker can be called from any thread
+pub struct IoWait {
+    waker: Waker,
+    signal: Arc<IoSignal>,
+}
+
+impl IoWait {
+    /// Wakes the fiber and the executor loop
+    pub fn waker(&self) -> &Waker {
+        &self.waker
+    }
+}
+
+impl Drop for IoWait {
+    fn drop(&mut self) {
+        self.signal.pending.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+struct IoWaker {
+    task: Waker,
+    signal: Arc<IoSignal>,
+}
+
+impl Wake for IoWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.task.wake_by_ref();
+        let mut woken = self.signal.woken.lock().unwrap_or_else(|e| e.into_inner());
+        *woken = true;
+        self.signal.cond.notify_all();
+    }
+}
+
+pub struct Sleep {
+    timers: Timers,
+    deadline: Instant,
+    id: Option<u64>,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if Instant::now() >= self.deadline {
+            if let Some(id) = self.id.take() {
+                self.timers.inner.borrow_mut().wakers.remove(&id);
+            }
+            return Poll::Ready(());
+        }
+        let mut q = self.timers.inner.borrow_mut();
+        match self.id {
+            Some(id) => {
+                q.wakers.insert(id, cx.waker().clone());
+            }
+            None => {
+                let id = q.next_id;
+                q.next_id += 1;
+                q.heap.push(Reverse((self.deadline, id)));
+                q.wakers.insert(id, cx.waker().clone());
+                drop(q);
+                self.id = Some(id);
+            }
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        if let Some(id) = self.id {
+            self.timers.inner.borrow_mut().wakers.remove(&id);
+        }
+    }
+}
@@ -6,9 +6,10 @@ use super::gc::GcoVec;
 pub use super::instruction::{OpIndex, ValueType};
 use super::metadata::*;
 pub use super::objects::*;
+use super::profile::AllocKind;
 use crate::channel::Channel;
 use ordered_float;
-use std::cell::{Cell, RefCell};
+use std::cell::{Cell, Ref, RefCell};
 use std::cmp::Ordering;
 use std::collections::VecDeque;
 use std::fmt::Debug;
@@ -78,6 +79,14 @@ macro_rules! union_cmp {
     };
 }
 
+// floats are compared as IEEE 754 says, not as OrderedFloat orders them,
+// so NaN is unequal to everything, itself included
+macro_rules! union_cmp_float {
+    ($a:ident, $b:ident, $name:tt, $op:tt) => {
+        $a.$name.into_inner() $op $b.$name.into_inner()
+    };
+}
+
 macro_rules! binary_op_int_float_str {
     ($t:ident, $a:ident, $b:ident, $op:tt) => {
         match $t {
@@ -100,6 +109,25 @@ macro_rules! binary_op_int_float_str {
     };
 }
 
+macro_rules! binary_op_int_wrap {
+    ($t:ident, $a:ident, $b:ident, $op:tt) => {
+        match $t {
+            ValueType::Int => union_op_wrap!($a, $b, int, $op),
+            ValueType::Int8 => union_op_wrap!($a, $b, int8, $op),
+            ValueType::Int16 => union_op_wrap!($a, $b, int16, $op),
+            ValueType::Int32 => union_op_wrap!($a, $b, int32, $op),
+            ValueType::Int64 => union_op_wrap!($a, $b, int64, $op),
+            ValueType::Uint => union_op_wrap!($a, $b, uint, $op),
+            ValueType::UintPtr => union_op_wrap!($a, $b, uint_ptr, $op),
+            ValueType::Uint8 => union_op_wrap!($a, $b, uint8, $op),
+            ValueType::Uint16 => union_op_wrap!($a, $b, uint16, $op),
+            ValueType::Uint32 => union_op_wrap!($a, $b, uint32, $op),
+            ValueType::Uint64 => union_op_wrap!($a, $b, uint64, $op),
+            _ => unreachable!(),
+        }
+    };
+}
+
 macro_rules! binary_op_int_no_wrap {
     ($t:ident, $a:ident, $b:ident, $op:tt) => {
         match $t {
@@ -134,8 +162,8 @@ macro_rules! cmp_bool_int_float {
             ValueType::Uint16 => union_cmp!($a, $b, uint16, $op),
             ValueType::Uint32 => union_cmp!($a, $b, uint32, $op),
             ValueType::Uint64 => union_cmp!($a, $b, uint64, $op),
-            ValueType::Float32 => union_cmp!($a, $b, float32, $op),
-            ValueType::Float64 => union_cmp!($a, $b, float64, $op),
+            ValueType::Float32 => union_cmp_float!($a, $b, float32, $op),
+            ValueType::Float64 => union_cmp_float!($a, $b, float64, $op),
             _ => unreachable!(),
         }
     };
@@ -155,8 +183,8 @@ macro_rules! cmp_int_float {
             ValueType::Uint16 => union_cmp!($a, $b, uint16, $op),
             ValueType::Uint32 => union_cmp!($a, $b, uint32, $op),
             ValueType::Uint64 => union_cmp!($a, $b, uint64, $op),
-            ValueType::Float32 => union_cmp!($a, $b, float32, $op),
-            ValueType::Float64 => union_cmp!($a, $b, float64, $op),
+            ValueType::Float32 => union_cmp_float!($a, $b, float32, $op),
+            ValueType::Float64 => union_cmp_float!($a, $b, float64, $op),
             _ => unreachable!(),
         }
     };
@@ -606,6 +634,7 @@ impl ValueData {
             ValueType::Int32 => *self.as_int32() as usize,
             ValueType::Int64 => *self.as_int64() as usize,
             ValueType::Uint => *self.as_uint() as usize,
+            ValueType::UintPtr => *self.as_uint_ptr() as usize,
             ValueType::Uint8 => *self.as_uint8() as usize,
             ValueType::Uint16 => *self.as_uint16() as usize,
             ValueType::Uint32 => *self.as_uint32() as usize,
@@ -643,18 +672,19 @@ impl ValueData {
     #[inline]
     pub fn unary_negate(&mut self, t: ValueType) {
         match t {
-            ValueType::Int => self.int = -unsafe { self.int },
-            ValueType::Int8 => self.int8 = -unsafe { self.int8 },
-            ValueType::Int16 => self.int16 = -unsafe { self.int16 },
-            ValueType::Int32 => self.int32 = -unsafe { self.int32 },
-            ValueType::Int64 => self.int64 = -unsafe { self.int64 },
+            ValueType::Int => self.int = unsafe { self.int }.wrapping_neg(),
+            ValueType::Int8 => self.int8 = unsafe { self.int8 }.wrapping_neg(),
+            ValueType::Int16 => self.int16 = unsafe { self.int16 }.wrapping_neg(),
+            ValueType::Int32 => self.int32 = unsafe { self.int32 }.wrapping_neg(),
+            ValueType::Int64 => self.int64 = unsafe { self.int64 }.wrapping_neg(),
             ValueType::Float32 => self.float32 = -unsafe { self.float32 },
             ValueType::Float64 => self.float64 = -unsafe { self.float64 },
-            ValueType::Uint => self.uint = unsafe { (!0) ^ self.uint } + 1,
-            ValueType::Uint8 => self.uint8 = unsafe { (!0) ^ self.uint8 } + 1,
-            ValueType::Uint16 => self.uint16 = unsafe { (!0) ^ self.uint16 } + 1,
-            ValueType::Uint32 => self.uint32 = unsafe { (!0) ^ self.uint32 } + 1,
-            ValueType::Uint64 => self.uint64 = unsafe { (!0) ^ self.uint64 } + 1,
+            ValueType::Uint => self.uint = unsafe { self.uint }.wrapping_neg(),
+            ValueType::Uint8 => self.uint8 = unsafe { self.uint8 }.wrapping_neg(),
+            ValueType::Uint16 => self.uint16 = unsafe { self.uint16 }.wrapping_neg(),
+            ValueType::Uint32 => self.uint32 = unsafe { self.uint32 }.wrapping_neg(),
+            ValueType::Uint64 => self.uint64 = unsafe { self.uint64 }.wrapping_neg(),
+            ValueType::UintPtr => self.uint_ptr = unsafe { self.uint_ptr }.wrapping_neg(),
             _ => unreachable!(),
         }
     }
@@ -667,6 +697,7 @@ impl ValueData {
             ValueType::Uint16 => self.uint16 = unsafe { (!0) ^ self.uint16 },
             ValueType::Uint32 => self.uint32 = unsafe { (!0) ^ self.uint32 },
             ValueType::Uint64 => self.uint64 = unsafe { (!0) ^ self.uint64 },
+            ValueType::UintPtr => self.uint_ptr = unsafe { (!0) ^ self.uint_ptr },
             ValueType::Int => self.int = unsafe { -1 ^ self.int },
             ValueType::Int8 => self.int8 = unsafe { -1 ^ self.int8 },
             ValueType::Int16 => self.int16 = unsafe { -1 ^ self.int16 },
@@ -685,18 +716,19 @@ impl ValueData {
     #[inline]
     pub fn inc(&mut self, t: ValueType) {
         match t {
-            ValueType::Int => self.int = unsafe { self.int } + 1,
-            ValueType::Int8 => self.int8 = unsafe { self.int8 } + 1,
-            ValueType::Int16 => self.int16 = unsafe { self.int16 } + 1,
-            ValueType::Int32 => self.int32 = unsafe { self.int32 } + 1,
-            ValueType::Int64 => self.int64 = unsafe { self.int64 } + 1,
+            ValueType::Int => self.int = unsafe { self.int }.wrapping_add(1),
+            ValueType::Int8 => self.int8 = unsafe { self.int8 }.wrapping_add(1),
+            ValueType::Int16 => self.int16 = unsafe { self.int16 }.wrapping_add(1),
+            ValueType::Int32 => self.int32 = unsafe { self.int32 }.wrapping_add(1),
+            ValueType::Int64 => self.int64 = unsafe { self.int64 }.wrapping_add(1),
             ValueType::Float32 => self.float32 = unsafe { self.float32 } + 1.0,
             ValueType::Float64 => self.float64 = unsafe { self.float64 } + 1.0,
-            ValueType::Uint => self.uint = unsafe { self.uint } + 1,
-            ValueType::Uint8 => self.uint8 = unsafe { self.uint8 } + 1,
-            ValueType::Uint16 => self.uint16 = unsafe { self.uint16 } + 1,
-            ValueType::Uint32 => self.uint32 = unsafe { self.uint32 } + 1,
-            ValueType::Uint64 => self.uint64 = unsafe { self.uint64 } + 1,
+            ValueType::Uint => self.uint = unsafe { self.uint }.wrapping_add(1),
+            ValueType::Uint8 => self.uint8 = unsafe { self.uint8 }.wrapping_add(1),
+            ValueType::Uint16 => self.uint16 = unsafe { self.uint16 }.wrapping_add(1),
+            ValueType::Uint32 => self.uint32 = unsafe { self.uint32 }.wrapping_add(1),
+            ValueType::Uint64 => self.uint64 = unsafe { self.uint64 }.wrapping_add(1),
+            ValueType::UintPtr => self.uint_ptr = unsafe { self.uint_ptr }.wrapping_add(1),
             _ => unreachable!(),
         }
     }
@@ -704,18 +736,19 @@ impl ValueData {
     #[inline]
     pub fn dec(&mut self, t: ValueType) {
         match t {
-            ValueType::Int => self.int = unsafe { self.int } - 1,
-            ValueType::Int8 => self.int8 = unsafe { self.int8 } - 1,
-            ValueType::Int16 => self.int16 = unsafe { self.int16 } - 1,
-            ValueType::Int32 => self.int32 = unsafe { self.int32 } - 1,
-            ValueType::Int64 => self.int64 = unsafe { self.int64 } - 1,
+            ValueType::Int => self.int = unsafe { self.int }.wrapping_sub(1),
+            ValueType::Int8 => self.int8 = unsafe { self.int8 }.wrapping_sub(1),
+            ValueType::Int16 => self.int16 = unsafe { self.int16 }.wrapping_sub(1),
+            ValueType::Int32 => self.int32 = unsafe { self.int32 }.wrapping_sub(1),
+            ValueType::Int64 => self.int64 = unsafe { self.int64 }.wrapping_sub(1),
             ValueType::Float32 => self.float32 = unsafe { self.float32 } - 1.0,
             ValueType::Float64 => self.float64 = unsafe { self.float64 } - 1.0,
-            ValueType::Uint => self.uint = unsafe { self.uint } - 1,
-            ValueType::Uint8 => self.uint8 = unsafe { self.uint8 } - 1,
-            ValueType::Uint16 => self.uint16 = unsafe { self.uint16 } - 1,
-            ValueType::Uint32 => self.uint32 = unsafe { self.uint32 } - 1,
-            ValueType::Uint64 => self.uint64 = unsafe { self.uint64 } - 1,
+            ValueType::Uint => self.uint = unsafe { self.uint }.wrapping_sub(1),
+            ValueType::Uint8 => self.uint8 = unsafe { self.uint8 }.wrapping_sub(1),
+            ValueType::Uint16 => self.uint16 = unsafe { self.uint16 }.wrapping_sub(1),
+            ValueType::Uint32 => self.uint32 = unsafe { self.uint32 }.wrapping_sub(1),
+            ValueType::Uint64 => self.uint64 = unsafe { self.uint64 }.wrapping_sub(1),
+            ValueType::UintPtr => self.uint_ptr = unsafe { self.uint_ptr }.wrapping_sub(1),
             _ => unreachable!(),
         }
     }
@@ -742,7 +775,8 @@ impl ValueData {
 
     #[inline]
     pub fn binary_op_rem(&self, b: &ValueData, t: ValueType) -> ValueData {
-        unsafe { binary_op_int_no_wrap!(t, self, b, %) }
+        // wraps like Go, the remainder of MIN % -1 is 0
+        unsafe { binary_op_int_wrap!(t, self, b, %) }
     }
 
     #[inline]
@@ -767,9 +801,54 @@ impl ValueData {
 
     #[inline]
     pub fn binary_op_shr(&mut self, b: &u32, t: ValueType) {
+        // shifted right by its width or more, a signed integer is 0 or -1
+        let b = &match t {
+            ValueType::Int => (*b).min(isize::BITS - 1),
+            ValueType::Int8 => (*b).min(i8::BITS - 1),
+            ValueType::Int16 => (*b).min(i16::BITS - 1),
+            ValueType::Int32 => (*b).min(i32::BITS - 1),
+            ValueType::Int64 => (*b).min(i64::BITS - 1),
+            _ => *b,
+        };
         unsafe { shift_int!(t, self, b, checked_shr) }
     }
 
+    /// The integer of type t, widened so that any of them fits
+    #[inline]
+    pub fn as_i128(&self, t: ValueType) -> i128 {
+        unsafe {
+            match t {
+                ValueType::Int => self.int as i128,
+                ValueType::Int8 => self.int8 as i128,
+                ValueType::Int16 => self.int16 as i128,
+                ValueType::Int32 => self.int32 as i128,
+                ValueType::Int64 => self.int64 as i128,
+                ValueType::Uint => self.uint as i128,
+                ValueType::UintPtr => self.uint_ptr as i128,
+                ValueType::Uint8 => self.uint8 as i128,
+                ValueType::Uint16 => self.uint16 as i128,
+                ValueType::Uint32 => self.uint32 as i128,
+                ValueType::Uint64 => self.uint64 as i128,
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    /// The shift count of type t, saturated to u32::MAX, as shifting by
+    /// that many bits or more gives the same result
+    #[inline]
+    pub fn as_shift_count(&self, t: ValueType) -> RuntimeResult<u32> {
+        let count = match t {
+            ValueType::Float32 => unsafe { self.float32.into_inner() as i128 },
+            ValueType::Float64 => unsafe { self.float64.into_inner() as i128 },
+            _ => self.as_i128(t),
+        };
+        match count {
+            c if c < 0 => Err("negative shift amount".to_owned()),
+            c => Ok(c.min(u32::MAX as i128) as u32),
+        }
+    }
+
     #[inline]
     pub fn binary_op_and_not(&self, b: &ValueData, t: ValueType) -> ValueData {
         unsafe {
@@ -1013,6 +1092,8 @@ impl ValueData {
         let rc = Rc::new((arr, Cell::new(0)));
         if T::need_gc() {
             gcv.add_array(&ValueData::from_array(rc.clone()).into_array::<GosElem>());
+        } else {
+            gcv.count_alloc(AllocKind::Array);
         }
         ValueData::from_array(rc)
     }
@@ -1204,20 +1285,13 @@ impl ValueData {
     #[inline]
     fn copy_semantic(&self, t: ValueType, t_elem: ValueType, gcv: &GcoVec) -> ValueData {
         match t {
-            _ if t != ValueType::Array
-                && t != ValueType::Struct
-                && t != ValueType::Slice
-                && t != ValueType::Map =>
-            {
+            // a copied map is the same map
+            _ if t != ValueType::Array && t != ValueType::Struct && t != ValueType::Slice => {
                 self.clone(t)
             }
             ValueType::Array => dispatcher_a_s_for(t_elem).array_copy_semantic(self, gcv),
-            ValueType::Struct => ValueData::new_struct(StructObj::clone(&self.as_struct().0), gcv),
+            ValueType::Struct => ValueData::new_struct(self.as_struct().0.copy_semantic(gcv), gcv),
             ValueType::Slice => dispatcher_a_s_for(t_elem).slice_copy_semantic(self),
-            ValueType::Map => match self.as_map() {
-                Some(m) => ValueData::new_map(m.0.clone(), gcv),
-                None => ValueData::new_nil(t),
-            },
             _ => unreachable!(),
         }
     }
@@ -1317,6 +1391,47 @@ impl GosValue {
         GosValue::new(t, ValueData::new_nil(t))
     }
 
+    /// The zero value of the type of self, made without the metadata
+    pub fn zero_like(&self, gcv: &GcoVec) -> GosValue {
+        match self.typ {
+            ValueType::Bool => GosValue::new_bool(false),
+            ValueType::Int => GosValue::new_int(0),
+            ValueType::Int8 => GosValue::new_int8(0),
+            ValueType::Int16 => GosValue::new_int16(0),
+            ValueType::Int32 => GosValue::new_int32(0),
+            ValueType::Int64 => GosValue::new_int64(0),
+            ValueType::Uint => GosValue::new_uint(0),
+            ValueType::UintPtr => GosValue::new_uint_ptr(0),
+            ValueType::Uint8 => GosValue::new_uint8(0),
+            ValueType::Uint16 => GosValue::new_uint16(0),
+            ValueType::Uint32 => GosValue::new_uint32(0),
+            ValueType::Uint64 => GosValue::new_uint64(0),
+            ValueType::Float32 => GosValue::new_float32(0.0.into()),
+            ValueType::Float64 => GosValue::new_float64(0.0.into()),
+            ValueType::Complex64 => GosValue::new_complex64(0.0.into(), 0.0.into()),
+            ValueType::Complex128 => GosValue::new_complex128(0.0.into(), 0.0.into()),
+            ValueType::String => GosValue::with_str(""),
+            ValueType::Array => {
+                let len = self.len();
+                match len {
+                    0 => GosValue::array_with_data(vec![], self.t_elem, gcv),
+                    _ => {
+                        let zero = self.dispatcher_a_s().array_get(self, 0).unwrap();
+                        let zero = zero.zero_like(gcv);
+                        GosValue::array_with_size(len, len, &zero, self.t_elem, gcv)
+                    }
+                }
+            }
+            ValueType::Struct => {
+                let fields = self.as_struct().0.borrow_fields();
+                let zeros = fields.iter().map(|f| f.zero_like(gcv)).collect();
+                GosValue::new_struct(StructObj::new(zeros), gcv)
+            }
+            ValueType::Slice => GosValue::new_slice_nil(self.t_elem),
+            t => GosValue::new_nil(t),
+        }
+    }
+
     #[inline]
     pub fn new_slice_nil(t_elem: ValueType) -> GosValue {
         GosValue::with_elem_type(
@@ -1465,6 +1580,14 @@ impl GosValue {
         GosValue::new(ValueType::UnsafePtr, ValueData::new_unsafe_ptr(p))
     }
 
+    #[inline]
+    pub fn with_unsafe_ptr_obj(p: UnsafePtrObj) -> GosValue {
+        GosValue::new(
+            ValueType::UnsafePtr,
+            ValueData::from_unsafe_ptr(Some(Box::new(p))),
+        )
+    }
+
     #[inline]
     pub fn new_closure(obj: ClosureObj, gcv: &GcoVec) -> GosValue {
         let data = ValueData::new_closure(obj, gcv);
@@ -1532,7 +1655,7 @@ impl GosValue {
         if t_elem == ValueType::Void {
             panic!("qqq");
         }
-        let arr = GosValue::array_with_size(size, cap, val, t_elem, gcv);
+        let arr = GosValue::array_with_size(cap, cap, val, t_elem, gcv);
         GosValue::slice_array(arr, 0, size as isize, t_elem).unwrap()
     }
 
@@ -1553,7 +1676,8 @@ impl GosValue {
         end: isize,
         t_elem: ValueType,
     ) -> RuntimeResult<GosValue> {
-        dispatcher_a_s_for(t_elem).slice_array(arr, begin, end)
+        let end = if end >= 0 { Some(end) } else { None };
+        dispatcher_a_s_for(t_elem).slice_array(arr, begin, end, None)
     }
 
     #[inline]
@@ -1993,6 +2117,56 @@ impl GosValue {
         }
     }
 
+    /// Returns the dynamic type of the interface, self or one in its fields
+    /// or elements, that holds a slice, a map or a function, which makes
+    /// comparing or hashing self a runtime panic
+    pub fn uncomparable_iface(&self) -> Option<Meta> {
+        match self.typ {
+            ValueType::Interface => match self.as_interface()? {
+                InterfaceObj::Gos(v, Some((meta, _))) => match v.holds_uncomparable() {
+                    true => Some(*meta),
+                    false => v.uncomparable_iface(),
+                },
+                _ => None,
+            },
+            ValueType::Struct => self
+                .as_struct()
+                .0
+                .borrow_fields()
+                .iter()
+                .find_map(|f| f.uncomparable_iface()),
+            ValueType::Array => self.array_elems()?.find_map(|e| e.uncomparable_iface()),
+            _ => None,
+        }
+    }
+
+    /// Whether self is, or has a field or element that is, a slice, a map or
+    /// a function, not looking into interfaces
+    fn holds_uncomparable(&self) -> bool {
+        match self.typ {
+            ValueType::Slice | ValueType::Map | ValueType::Closure => true,
+            ValueType::Struct => self
+                .as_struct()
+                .0
+                .borrow_fields()
+                .iter()
+                .any(|f| f.holds_uncomparable()),
+            ValueType::Array => self
+                .array_elems()
+                .map_or(false, |mut it| it.any(|e| e.holds_uncomparable())),
+            _ => false,
+        }
+    }
+
+    /// The elements of an array, None if they are numbers or strings
+    fn array_elems(&self) -> Option<impl Iterator<Item = GosValue> + '_> {
+        if self.t_elem.copyable() || self.t_elem == ValueType::String {
+            return None;
+        }
+        let dispatcher = self.dispatcher_a_s();
+        Some((0..self.len()).filter_map(move |i| dispatcher.array_get(self, i).ok()))
+    }
+
     #[inline]
     pub fn copy_semantic(&self, gcv: &GcoVec) -> GosValue {
         if self.typ.copyable() {
@@ -2014,10 +2188,29 @@ impl GosValue {
         }
     }
 
+    /// Converts a complex number of type from to the complex type to
+    pub fn cast_complex(&self, from: ValueType, to: ValueType) -> GosValue {
+        let (r, i) = match from {
+            ValueType::Complex64 => {
+                let c = self.as_complex64();
+                (c.r.into_inner() as f64, c.i.into_inner() as f64)
+            }
+            _ => {
+                let c = self.as_complex128();
+                (c.r.into_inner(), c.i.into_inner())
+            }
+        };
+        match to {
+            ValueType::Complex64 => GosValue::new_complex64((r as f32).into(), (i as f32).into()),
+            _ => GosValue::new_complex128(r.into(), i.into()),
+        }
+    }
+
     #[inline]
     pub fn cast_copyable(&mut self, from: ValueType, to: ValueType) {
         assert!(from.copyable());
-        self.data.cast_copyable(from, to)
+        self.data.cast_copyable(from, to);
+        self.typ = to;
     }
 
     #[inline]
@@ -2038,13 +2231,12 @@ impl GosValue {
     }
 
     #[inline]
-    pub fn slice_string(
-        s: &GosValue,
-        begin: isize,
-        end: isize,
-        max: isize,
-    ) -> RuntimeResult<GosValue> {
-        Ok(GosValue::new_string(s.as_string().slice(begin, end, max)?))
+    pub fn slice_string(s: &GosValue, begin: isize, end: Option<isize>) -> RuntimeResult<GosValue> {
+        Ok(GosValue::new_string(StrUtil::slice(
+            s.as_string(),
+            begin,
+            end,
+        )?))
     }
 
     #[inline]
@@ -2072,6 +2264,23 @@ impl GosValue {
         }
     }
 
+    /// Like load_index, but the index of a slice element is not checked
+    /// against the length, the code generator proved it in range, for
+    /// `//gos:nobounds`
+    #[inline]
+    pub fn load_index_unchecked(&self, ind: &GosValue, gcv: &GcoVec) -> RuntimeResult<GosValue> {
+        if self.typ != ValueType::Slice {
+            return self.load_index(ind, gcv);
+        }
+        let index = ind.as_index();
+        let slice = &self
+            .as_slice::<AnyElem>()
+            .ok_or_else(|| index_out_of_range(index, 0))?
+            .0;
+        self.dispatcher_a_s()
+            .array_get(slice.array(), slice.begin() + index)
+    }
+
     #[inline]
     pub fn load_index_int(&self, i: usize, gcv: &GcoVec) -> RuntimeResult<GosValue> {
         match self.typ {
@@ -2249,6 +2458,9 @@ impl PartialEq for GosValue {
     #[inline]
     fn eq(&self, b: &GosValue) -> bool {
         match (self.typ, b.typ) {
+            // an untyped nil, e.g. passed as an interface, is copyable too
+            (ValueType::Void, _) => b.is_nil(),
+            (_, ValueType::Void) => self.is_nil(),
             _ if self.typ.copyable() => self.as_uint() == b.as_uint(), //todo: does this work ok with float?
             (ValueType::Metadata, ValueType::Metadata) => self.as_metadata() == b.as_metadata(),
             (ValueType::Complex128, ValueType::Complex128) => {
@@ -2282,8 +2494,6 @@ impl PartialEq for GosValue {
                     _ => false,
                 }
             }
-            (_, ValueType::Void) => self.is_nil(),
-            (ValueType::Void, _) => b.is_nil(),
             (ValueType::Interface, _) => self
                 .as_interface()
                 .map_or(b.is_nil(), |x| x.equals_value(b)),
@@ -2435,6 +2645,14 @@ pub trait Element: Clone + Hash + Debug {
 
     fn set_value(&self, val: &GosValue);
 
+    /// The zero value of the type of self
+    fn zero_like(&self, gcv: &GcoVec) -> Self;
+
+    /// A copy of self, deep if it's a struct or an array
+    fn copy_semantic(&self, _gcv: &GcoVec) -> Self {
+        self.clone()
+    }
+
     fn need_gc() -> bool {
         false
     }
@@ -2450,6 +2668,11 @@ pub struct GosElem {
 }
 
 impl GosElem {
+    /// for gc
+    pub fn borrow(&self) -> Ref<'_, GosValue> {
+        self.cell.borrow()
+    }
+
     /// for gc
     pub fn ref_sub_one(&self) {
         self.cell.borrow().ref_sub_one();
@@ -2491,6 +2714,16 @@ impl Element for GosElem {
         self.cell.replace(val.clone());
     }
 
+    #[inline]
+    fn zero_like(&self, gcv: &GcoVec) -> Self {
+        GosElem::from_value(self.borrow().zero_like(gcv))
+    }
+
+    #[inline]
+    fn copy_semantic(&self, gcv: &GcoVec) -> Self {
+        GosElem::from_value(self.borrow().copy_semantic(gcv))
+    }
+
     #[inline]
     fn need_gc() -> bool {
         true
@@ -2559,6 +2792,11 @@ impl Element for CellElem<u8> {
         self.cell.set(*val.as_uint8());
     }
 
+    #[inline]
+    fn zero_like(&self, _: &GcoVec) -> Self {
+        CellElem { cell: Cell::new(0) }
+    }
+
     #[inline]
     fn copy_or_clone_slice(dst: &mut [Self], src: &[Self]) {
         CellElem::<u8>::clone_slice(dst, src)
@@ -2584,6 +2822,11 @@ impl Element for Elem16 {
         self.cell.set(*val.as_uint16());
     }
 
+    #[inline]
+    fn zero_like(&self, _: &GcoVec) -> Self {
+        CellElem { cell: Cell::new(0) }
+    }
+
     #[inline]
     fn copy_or_clone_slice(dst: &mut [Self], src: &[Self]) {
         CellElem::<u16>::clone_slice(dst, src)
@@ -2609,6 +2852,11 @@ impl Element for Elem32 {
         self.cell.set(*val.as_uint32());
     }
 
+    #[inline]
+    fn zero_like(&self, _: &GcoVec) -> Self {
+        CellElem { cell: Cell::new(0) }
+    }
+
     #[inline]
     fn copy_or_clone_slice(dst: &mut [Self], src: &[Self]) {
         CellElem::<u32>::clone_slice(dst, src)
@@ -2634,6 +2882,11 @@ impl Element for Elem64 {
         self.cell.set(*val.as_uint64());
     }
 
+    #[inline]
+    fn zero_like(&self, _: &GcoVec) -> Self {
+        CellElem { cell: Cell::new(0) }
+    }
+
     #[inline]
     fn copy_or_clone_slice(dst: &mut [Self], src: &[Self]) {
         CellElem::<u64>::clone_slice(dst, src)
@@ -2648,6 +2901,8 @@ pub trait Dispatcher {
 
     fn array_copy_semantic(&self, vdata: &ValueData, gcv: &GcoVec) -> ValueData;
 
+    fn array_share(&self, val: &GosValue, gcv: &GcoVec) -> GosValue;
+
     fn slice_copy_semantic(&self, vdata: &ValueData) -> ValueData;
 
     fn array_drop_data(&self, vdata: &ValueData);
@@ -2673,11 +2928,17 @@ pub trait Dispatcher {
         &self,
         slice: &GosValue,
         begin: isize,
-        end: isize,
-        max: isize,
+        end: Option<isize>,
+        max: Option<isize>,
     ) -> RuntimeResult<GosValue>;
 
-    fn slice_array(&self, arr: GosValue, begin: isize, end: isize) -> RuntimeResult<GosValue>;
+    fn slice_array(
+        &self,
+        arr: GosValue,
+        begin: isize,
+        end: Option<isize>,
+        max: Option<isize>,
+    ) -> RuntimeResult<GosValue>;
 
     fn slice_append(
         &self,
@@ -2745,7 +3006,12 @@ macro_rules! define_dispatcher {
 
             #[inline]
             fn array_copy_semantic(&self, vdata: &ValueData, gcv: &GcoVec) -> ValueData {
-                ValueData::new_array::<$elem>(vdata.as_array::<$elem>().0.clone(), gcv)
+                ValueData::new_array::<$elem>(vdata.as_array::<$elem>().0.copy_semantic(gcv), gcv)
+            }
+
+            #[inline]
+            fn array_share(&self, val: &GosValue, gcv: &GcoVec) -> GosValue {
+                GosValue::new_array(val.as_array::<$elem>().0.share(), self.typ, gcv)
             }
 
             #[inline]
@@ -2808,13 +3074,18 @@ macro_rules! define_dispatcher {
                 &self,
                 slice: &GosValue,
                 begin: isize,
-                end: isize,
-                max: isize,
+                end: Option<isize>,
+                max: Option<isize>,
             ) -> RuntimeResult<GosValue> {
-                Ok(GosValue::new_slice(
-                    slice.as_some_slice::<$elem>()?.0.slice(begin, end, max)?,
-                    slice.t_elem,
-                ))
+                match slice.as_slice::<$elem>() {
+                    Some(s) => Ok(GosValue::new_slice(
+                        s.0.slice(begin, end, max)?,
+                        slice.t_elem,
+                    )),
+                    // a nil slice is empty, slicing it yields itself
+                    None => SliceObj::<$elem>::check_indices(0, 0, 0, begin, end, max, "capacity")
+                        .map(|_| slice.clone()),
+                }
             }
 
             #[inline]
@@ -2822,10 +3093,11 @@ macro_rules! define_dispatcher {
                 &self,
                 arr: GosValue,
                 begin: isize,
-                end: isize,
+                end: Option<isize>,
+                max: Option<isize>,
             ) -> RuntimeResult<GosValue> {
                 Ok(GosValue::new_slice::<$elem>(
-                    SliceObj::with_array(arr, begin, end)?,
+                    SliceObj::with_array_bounds(arr, begin, end, max)?,
                     self.typ,
                 ))
             }
@@ -2842,8 +3114,7 @@ macro_rules! define_dispatcher {
                 match b {
                     Some(y) => match a {
                         Some(x) => {
-                            let mut to = x.0.clone();
-                            to.append(&y.0);
+                            let to = x.0.append(&y.0, other.t_elem(), gcv);
                             Ok(GosValue::new_slice(to, other.t_elem()))
                         }
                         None => {
@@ -2883,12 +3154,18 @@ macro_rules! define_dispatcher {
 
             #[inline]
             fn slice_get(&self, from: &GosValue, i: usize) -> RuntimeResult<GosValue> {
-                from.as_some_slice::<$elem>()?.0.get(i, self.typ)
+                match from.as_slice::<$elem>() {
+                    Some(s) => s.0.get(i, self.typ),
+                    None => Err(index_out_of_range(i, 0)),
+                }
             }
 
             #[inline]
             fn slice_set(&self, to: &GosValue, val: &GosValue, i: usize) -> RuntimeResult<()> {
-                to.as_some_slice::<$elem>()?.0.set(i, val)
+                match to.as_slice::<$elem>() {
+                    Some(s) => s.0.set(i, val),
+                    None => Err(index_out_of_range(i, 0)),
+                }
             }
 
             #[inline]
@@ -2897,8 +3174,16 @@ macro_rules! define_dispatcher {
                 val: &GosValue,
             ) -> RuntimeResult<SliceEnumIter<'static, AnyElem>> {
                 let rust_slice = match val.typ() {
-                    ValueType::Slice => val.as_some_slice::<$elem>()?.0.as_rust_slice(),
-                    ValueType::Array => val.as_array::<$elem>().0.as_rust_slice(),
+                    ValueType::Slice => {
+                        let slice = &val.as_some_slice::<$elem>()?.0;
+                        slice.array_obj().unshare();
+                        slice.as_rust_slice()
+                    }
+                    ValueType::Array => {
+                        let arr = &val.as_array::<$elem>().0;
+                        arr.unshare();
+                        arr.as_rust_slice()
+                    }
                     _ => unreachable!(),
                 };
                 Ok(unsafe { std::mem::transmute(rust_slice.iter().enumerate()) })
@@ -3039,6 +3324,7 @@ pub fn dispatcher_a_s_for(t: ValueType) -> &'static Box<dyn Dispatcher> {
 
 #[cfg(test)]
 mod test {
+    use super::super::gc::GcoVec;
     use super::super::value::*;
     use std::cell::RefCell;
     use std::collections::HashMap;
@@ -3047,6 +3333,14 @@ mod test {
     #[test]
     fn test_container() {}
 
+    #[test]
+    fn test_cast() {
+        let mut v = GosValue::new_uint8(200);
+        v.cast_copyable(ValueType::Uint8, ValueType::Int32);
+        assert_eq!(v.typ(), ValueType::Int32);
+        assert_eq!(*v.as_int32(), 200);
+    }
+
     #[test]
     fn test_size() {
         dbg!(mem::size_of::<HashMap<GosValue, GosValue>>());
@@ -3057,6 +3351,9 @@ mod test {
         dbg!(mem::size_of::<RefCell<GosValue>>());
         dbg!(mem::size_of::<GosValue>());
         dbg!(mem::size_of::<ValueData>());
+        // two type tags and the 8-byte payload, the stack is a Vec of these
+        assert_eq!(mem::size_of::<ValueData>(), 8);
+        assert_eq!(mem::size_of::<GosValue>(), 16);
         dbg!(mem::size_of::<Meta>());
         dbg!(mem::size_of::<Box<Meta>>());
         dbg!(mem::size_of::<OptionBox<Meta>>());
@@ -3072,4 +3369,20 @@ mod test {
         //let s2 = s.clone().into_string();
         //dbg!(s2);
     }
+
+    #[test]
+    fn test_array_share() {
+        let gcv = GcoVec::new();
+        let data = vec![GosValue::new_int(1), GosValue::new_int(2)];
+        let a = GosValue::array_with_data(data, ValueType::Int, &gcv);
+        let b = a.dispatcher_a_s().array_share(&a, &gcv);
+        let ptr = |v: &GosValue| v.as_array::<AnyElem>().0.borrow_data().as_ptr();
+        assert_eq!(ptr(&a), ptr(&b));
+        b.dispatcher_a_s()
+            .array_set(&b, &GosValue::new_int(9), 0)
+            .unwrap();
+        assert_ne!(ptr(&a), ptr(&b));
+        assert_eq!(*a.dispatcher_a_s().array_get(&a, 0).unwrap().as_int(), 1);
+        assert_eq!(*b.dispatcher_a_s().array_get(&b, 0).unwrap().as_int(), 9);
+    }
 }
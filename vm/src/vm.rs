@@ -3,21 +3,35 @@
 // license that can be found in the LICENSE file.
 
 #![allow(dead_code)]
+use super::audit::{CastAudit, CastRecord};
 use super::channel;
-use super::ffi::{FfiCallCtx, FfiFactory};
-use super::gc::{gc, GcoVec};
+use super::clock::Instant;
+use super::dispatch;
+use super::ffi::{ClosureCaller, FfiCallCtx, FfiDeps, FfiError, FfiFactory};
+use super::format::ValueFormatter;
+use super::gc::{gc, GcConfig, GcStats, GcoVec};
 use super::instruction::*;
+use super::limits::{Limit, Limits, Meter};
+use super::memo::Memos;
 use super::metadata::*;
-use super::objects::{u64_to_key, ClosureObj};
+use super::objects::{u64_to_key, ClosureObj, GosClosureObj, MAX_SLICE_LEN};
+use super::profile::{AllocProfile, AllocReport};
 use super::stack::{RangeStack, Stack};
+use super::step::{StepFrame, StepObserver, StepRecorder};
+use super::timer::Timers;
 use super::value::*;
 use async_executor::LocalExecutor;
-use futures_lite::future;
-use goscript_parser::{FilePos, FileSet};
+use futures_lite::future::{self, FutureExt};
+use goscript_parser::FileSet;
+use std::any::Any;
 use std::cell::{Cell, RefCell};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
 use std::pin::Pin;
 use std::rc::Rc;
+use std::time::Duration;
 
 // restore stack_ref after drop to allow code in block call yield
 macro_rules! restore_stack_ref {
@@ -36,11 +50,11 @@ macro_rules! go_panic {
     }};
 }
 
+// like Go's runtime errors, the recover()ing code gets an error whose
+// Error() returns the message
 macro_rules! go_panic_str {
-    ($panic:ident, $msg:expr, $frame:ident, $code:ident) => {{
-        let str_val = GosValue::with_str($msg);
-        let iface = GosValue::empty_iface_with_val(str_val);
-        let mut data = PanicData::new(iface);
+    ($panic:ident, $s_meta:ident, $msg:expr, $frame:ident, $code:ident) => {{
+        let mut data = PanicData::new(FfiError::new_error($msg, $s_meta.error));
         data.call_stack.push(($frame.func(), $frame.pc - 1));
         $panic = Some(data);
         $frame.pc = $code.len() - 1;
@@ -48,9 +62,23 @@ macro_rules! go_panic_str {
 }
 
 macro_rules! panic_if_err {
-    ($result:expr, $panic:ident, $frame:ident, $code:ident) => {{
+    ($result:expr, $panic:ident, $s_meta:ident, $frame:ident, $code:ident) => {{
         if let Err(e) = $result {
-            go_panic_str!($panic, &e, $frame, $code);
+            go_panic_str!($panic, $s_meta, &e, $frame, $code);
+        }
+    }};
+}
+
+/// Panics if the op-assign of a store divides an integer by zero, e.g.
+/// `x /= 0`, the store is skipped then
+macro_rules! check_op_assign {
+    ($stack:ident, $rhs_index:ident, $t:expr, $panic:ident, $s_meta:ident, $frame:ident, $code:ident) => {{
+        if $rhs_index >= 0 {
+            let op = Instruction::index2code($rhs_index);
+            if let Err(e) = check_divisor($stack, op, $t) {
+                go_panic_str!($panic, $s_meta, &e, $frame, $code);
+                continue;
+            }
         }
     }};
 }
@@ -101,6 +129,73 @@ impl ByteCode {
             entry: entry,
         }
     }
+
+    /// Adds the interface bindings generated after the code was created
+    pub fn add_ifaces(&mut self, ifaces: Vec<(Meta, Vec<IfaceBinding>)>) {
+        self.ifaces.extend(
+            ifaces
+                .into_iter()
+                .map(|(ms, binding)| (ms, binding.into_iter().map(|x| x.into()).collect())),
+        );
+    }
+
+    /// Removes the debug info of the functions and returns it, for release
+    /// code or for it to be kept apart and attached again by `attach_debug`.
+    /// The stack traces of stripped code have the names of the functions
+    /// only.
+    pub fn strip_debug(&mut self) -> HashMap<FunctionKey, DebugInfo> {
+        self.objects
+            .functions
+            .iter_mut()
+            .filter_map(|(k, f)| f.take_debug().map(|d| (k, d)))
+            .collect()
+    }
+
+    /// Sets the debug info returned by `strip_debug`, the one of functions
+    /// that are gone or whose code changed is dropped
+    pub fn attach_debug(&mut self, debug: HashMap<FunctionKey, DebugInfo>) {
+        for (k, d) in debug.into_iter() {
+            if let Some(f) = self.objects.functions.get_mut(k) {
+                f.set_debug(d);
+            }
+        }
+    }
+
+    /// Returns the FFI objects the code creates, for the embedder to check
+    /// that they are all registered before running it.
+    pub fn ffi_deps(&self) -> FfiDeps {
+        let mut names = BTreeSet::new();
+        let mut dynamic = 0;
+        for (_, func) in self.objects.functions.iter() {
+            let code = func.code();
+            for (i, inst) in code.iter().enumerate() {
+                if inst.op() != Opcode::FFI {
+                    continue;
+                }
+                // the name is loaded right before the signature of the call
+                let name = match i.checked_sub(2).map(|x| &code[x]) {
+                    Some(load) if load.op() == Opcode::PUSH_CONST => {
+                        let val = func.const_val(load.imm());
+                        match val.typ() {
+                            ValueType::String => Some(StrUtil::as_str(val.as_string()).to_string()),
+                            _ => None,
+                        }
+                    }
+                    _ => None,
+                };
+                match name {
+                    Some(n) => {
+                        names.insert(n);
+                    }
+                    None => dynamic += 1,
+                }
+            }
+        }
+        FfiDeps {
+            names: names.into_iter().collect(),
+            dynamic: dynamic,
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -109,6 +204,8 @@ struct Referers {
     weaks: Vec<WeakUpValue>,
 }
 
+/// Frames are moved around on every call, so the fields that are rarely
+/// used are boxed to keep them small.
 #[derive(Clone, Debug)]
 struct CallFrame {
     closure: ClosureObj,
@@ -116,11 +213,19 @@ struct CallFrame {
     stack_base: usize,
     var_ptrs: Option<Vec<UpValue>>,
     // closures that have upvalues pointing to this frame
-    referred_by: Option<HashMap<OpIndex, Referers>>,
+    referred_by: Option<Box<HashMap<OpIndex, Referers>>>,
 
-    defer_stack: Option<Vec<DeferredCall>>,
+    defer_stack: Option<Box<Vec<DeferredCall>>>,
+    // the deadline and the budget set by a //gos:timeout pragma
+    budget: Option<Box<(Instant, Duration)>>,
+    // the arguments of a //gos:memoize call that missed the cache, the
+    // results are cached under them on return
+    memo_key: Option<Box<Vec<GosValue>>>,
 }
 
+// a field added inline grows every call, the boxed ones take 8 bytes
+const _: () = assert!(std::mem::size_of::<CallFrame>() <= 160);
+
 impl CallFrame {
     fn with_closure(c: ClosureObj, sbase: usize) -> CallFrame {
         CallFrame {
@@ -130,12 +235,52 @@ impl CallFrame {
             var_ptrs: None,
             referred_by: None,
             defer_stack: None,
+            budget: None,
+            memo_key: None,
+        }
+    }
+
+    /// Takes the budget if it has run out, so that it panics only once
+    #[inline]
+    fn take_expired_budget(&mut self, now: Instant) -> Option<Duration> {
+        match self.budget.as_deref() {
+            Some(&(deadline, budget)) if now >= deadline => {
+                self.budget = None;
+                Some(budget)
+            }
+            _ => None,
+        }
+    }
+
+    /// Points the frame to the upvalues of its closure and to its own
+    /// variables the closures it creates capture
+    fn set_var_ptrs(
+        &mut self,
+        gosc: &GosClosureObj,
+        func: &FunctionVal,
+        stack: &Rc<RefCell<Stack>>,
+    ) {
+        if let Some(uvs) = &gosc.uvs {
+            let mut ptrs: Vec<UpValue> = Vec::with_capacity(func.up_ptrs.len());
+            for (i, p) in func.up_ptrs.iter().enumerate() {
+                ptrs.push(if p.is_up_value {
+                    uvs[&i].clone()
+                } else {
+                    // local pointers
+                    let uv = UpValue::new(
+                        p.clone_with_stack(Rc::downgrade(stack), self.stack_base as OpIndex),
+                    );
+                    self.add_referred_by(p.index, p.typ, &uv);
+                    uv
+                });
+            }
+            self.var_ptrs = Some(ptrs);
         }
     }
 
     fn add_referred_by(&mut self, index: OpIndex, typ: ValueType, uv: &UpValue) {
         if self.referred_by.is_none() {
-            self.referred_by = Some(HashMap::new());
+            self.referred_by = Some(Box::new(HashMap::new()));
         }
         let map = self.referred_by.as_mut().unwrap();
         let weak = uv.downgrade();
@@ -175,7 +320,7 @@ impl CallFrame {
     #[inline]
     fn on_drop(&mut self, stack: &Stack) {
         if let Some(referred) = &self.referred_by {
-            for (ind, referrers) in referred {
+            for (ind, referrers) in referred.iter() {
                 if referrers.weaks.len() == 0 {
                     continue;
                 }
@@ -190,6 +335,22 @@ impl CallFrame {
     }
 }
 
+/// Takes the first budget of the frames that has run out
+fn take_expired_budget(frames: &mut [CallFrame]) -> Option<Duration> {
+    if frames.iter().all(|f| f.budget.is_none()) {
+        return None;
+    }
+    let now = Instant::now();
+    frames.iter_mut().find_map(|f| f.take_expired_budget(now))
+}
+
+/// The value of the panic raised when a budget runs out, an error that can
+/// be type-asserted by the recover()ing code
+fn budget_exceeded(budget: Duration, s_meta: &StaticMeta) -> GosValue {
+    let msg = format!("time budget of {:?} exceeded", budget);
+    FfiError::new_error(&msg, s_meta.error)
+}
+
 #[derive(Clone, Debug)]
 struct DeferredCall {
     frame: CallFrame,
@@ -202,10 +363,178 @@ enum Result {
     End,
 }
 
+/// How a run of a program ended
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RunResult {
+    /// main returned
+    Done,
+    /// the program exited with a code, by os.Exit or FfiCallCtx::exit
+    Exit(i32),
+    /// a goroutine panicked and did not recover
+    Panic(PanicInfo),
+    /// all the goroutines were blocked with no timer to wake them, like Go's
+    /// fatal "all goroutines are asleep" error
+    Deadlock(Vec<BlockedGoroutine>),
+    /// the program was stopped for running out of a limit set with
+    /// `GosVM::set_limits`
+    LimitExceeded(Limit),
+}
+
+impl RunResult {
+    /// The exit status of a Go program ending this way, panics exit with 2
+    pub fn code(&self) -> i32 {
+        match self {
+            RunResult::Done => 0,
+            RunResult::Exit(code) => *code,
+            RunResult::Panic(_) | RunResult::Deadlock(_) | RunResult::LimitExceeded(_) => 2,
+        }
+    }
+}
+
+/// An unrecovered panic
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PanicInfo {
+    /// the value passed to panic, formatted
+    pub message: String,
+    /// the calls the panic went through, innermost first
+    pub stack: Vec<StackFrame>,
+}
+
+impl fmt::Display for PanicInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "panic: {}", self.message)?;
+        for frame in self.stack.iter() {
+            write!(f, "\n{}", frame)?;
+        }
+        Ok(())
+    }
+}
+
+/// A goroutine blocked forever, in the report of a deadlock
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockedGoroutine {
+    /// 1 for the main goroutine, the others are numbered as they are started
+    pub id: usize,
+    /// what it waits for, like "chan receive" or "select"
+    pub wait: String,
+    /// the calls it is blocked in, innermost first
+    pub stack: Vec<StackFrame>,
+}
+
+impl fmt::Display for BlockedGoroutine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "goroutine {} [{}]:", self.id, self.wait)?;
+        for frame in self.stack.iter() {
+            write!(f, "\n{}", frame)?;
+        }
+        Ok(())
+    }
+}
+
+/// Prints a deadlock like the Go runtime does
+fn print_deadlock(goroutines: &[BlockedGoroutine]) {
+    println!("fatal error: all goroutines are asleep - deadlock!");
+    for g in goroutines.iter() {
+        println!("\n{}", g);
+    }
+}
+
+/// A fiber waiting in an await that has not completed, where it is and
+/// what for
+struct BlockedFiber {
+    wait: String,
+    calls: Vec<(FunctionKey, usize)>,
+}
+
+/// Keeps a fiber recorded as blocked as long as it lives
+struct BlockedGuard {
+    blocked: Rc<RefCell<BTreeMap<usize, BlockedFiber>>>,
+    id: usize,
+}
+
+impl Drop for BlockedGuard {
+    fn drop(&mut self) {
+        self.blocked.borrow_mut().remove(&self.id);
+    }
+}
+
+/// A call in a stack trace
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StackFrame {
+    /// see FunctionVal::name
+    pub function: String,
+    /// the position of the call, or of the panic in the innermost frame,
+    /// empty and 0 without debug info
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    /// the locals in scope and their values, for the frames a panic went
+    /// through, empty without debug info
+    pub locals: Vec<(String, String)>,
+}
+
+impl StackFrame {
+    /// The frame of the function at the instruction `pc`, None for the
+    /// functions the compiler adds if there is no debug info
+    pub(crate) fn new(
+        func: FunctionKey,
+        pc: usize,
+        objs: &VMObjects,
+        fs: Option<&FileSet>,
+    ) -> Option<StackFrame> {
+        let f = &objs.functions[func];
+        let pos = f.pos().get(pc).copied().flatten();
+        let pos = fs.zip(pos).and_then(|(fs, p)| fs.position(p));
+        if f.name.is_empty() && pos.is_none() {
+            return None;
+        }
+        let (file, line, column) = pos.map_or((String::new(), 0, 0), |p| {
+            (p.filename.to_string(), p.line, p.column)
+        });
+        Some(StackFrame {
+            function: f.name.clone(),
+            file: file,
+            line: line,
+            column: column,
+            locals: vec![],
+        })
+    }
+}
+
+impl fmt::Display for StackFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self.function.as_str() {
+            "" => "<unknown>",
+            n => n,
+        };
+        if self.line > 0 {
+            write!(f, "{}\n\t{}:{}:{}", name, self.file, self.line, self.column)?;
+        } else {
+            write!(f, "{}\n\t<no debug info available>", name)?;
+        }
+        for (name, val) in self.locals.iter() {
+            write!(f, "\n\t\t{} = {}", name, val)?;
+        }
+        Ok(())
+    }
+}
+
+/// The stack trace of a goroutine with `frames`, all of them past a call
+fn backtrace(frames: &[CallFrame], objs: &VMObjects, fs: Option<&FileSet>) -> Vec<StackFrame> {
+    frames
+        .iter()
+        .rev()
+        .filter_map(|f| StackFrame::new(f.func(), f.pc - 1, objs, fs))
+        .collect()
+}
+
 #[derive(Debug)]
 struct PanicData {
     msg: GosValue,
     call_stack: Vec<(FunctionKey, usize)>,
+    /// the locals of the frames of call_stack by index, kept as they are
+    /// unwound
+    locals: HashMap<usize, Vec<(String, String)>>,
 }
 
 impl PanicData {
@@ -213,10 +542,54 @@ impl PanicData {
         PanicData {
             msg: m,
             call_stack: vec![],
+            locals: HashMap::new(),
+        }
+    }
+
+    /// Keeps the values of the locals of the frame of `func` being unwound,
+    /// if it's the last one the panic went through
+    fn keep_locals(&mut self, func: FunctionKey, stack: &Stack, base: usize, objs: &VMObjects) {
+        let index = match self.call_stack.last() {
+            Some((f, _)) if *f == func => self.call_stack.len() - 1,
+            _ => return,
+        };
+        if self.locals.contains_key(&index) {
+            return;
         }
+        let pc = self.call_stack[index].1;
+        let locals = objs.functions[func].debug().map_or(vec![], |d| {
+            d.locals_at(pc)
+                .into_iter()
+                .map(|l| {
+                    let mut val = stack.get(base + l.index as usize).to_string();
+                    if val.chars().count() > MAX_LOCAL_LEN {
+                        val = val.chars().take(MAX_LOCAL_LEN).collect::<String>() + "...";
+                    }
+                    (l.name.clone(), val)
+                })
+                .collect()
+        });
+        self.locals.insert(index, locals);
+    }
+
+    /// The stack trace, innermost first
+    fn stack_trace(&mut self, objs: &VMObjects, fs: Option<&FileSet>) -> Vec<StackFrame> {
+        let locals = &mut self.locals;
+        self.call_stack
+            .iter()
+            .enumerate()
+            .filter_map(|(i, (f, pc))| {
+                let mut frame = StackFrame::new(*f, *pc, objs, fs)?;
+                frame.locals = locals.remove(&i).unwrap_or_default();
+                Some(frame)
+            })
+            .collect()
     }
 }
 
+/// The longest value of a local in a stack trace, in chars
+const MAX_LOCAL_LEN: usize = 64;
+
 #[derive(Clone)]
 struct Context<'a> {
     exec: Rc<LocalExecutor<'a>>,
@@ -224,7 +597,31 @@ struct Context<'a> {
     gcv: &'a GcoVec,
     ffi_factory: &'a FfiFactory,
     fs: Option<&'a FileSet>,
-    next_id: Cell<usize>,
+    timers: Timers,
+    memos: Memos,
+    // shared by the clones, so that each fiber gets an id of its own
+    next_id: Rc<Cell<usize>>,
+    // a Rust panic in a fiber, kept for GosVM::run to raise again because
+    // the executor swallows the panics of detached tasks
+    crash: Rc<Cell<Option<Box<dyn Any + Send>>>>,
+    // set when the program exits with a code, see FfiCallCtx::exit
+    exit_code: Rc<Cell<Option<i32>>>,
+    // set when a fiber ends with a panic
+    panicked: Rc<RefCell<Option<PanicInfo>>>,
+    // the value of a panic that ended a closure called back by an FFI
+    // function, the panic goes on with it in the fiber calling the function
+    callback_panic: Rc<Cell<Option<GosValue>>>,
+    // the fibers pending in an await by id, for the report of a deadlock
+    blocked: Rc<RefCell<BTreeMap<usize, BlockedFiber>>>,
+    meter: Rc<Meter>,
+    observer: Option<Rc<dyn StepObserver>>,
+    cast_audit: Option<Rc<CastAudit>>,
+    alloc_profile: Option<Rc<AllocProfile>>,
+    // the unsafe pointers converted to uintptrs by address, for converting
+    // them back; a uintptr can't be traced so they are kept for the run
+    unsafe_addrs: Rc<RefCell<HashMap<usize, UnsafePtrObj>>>,
+    // the packages initialized or being initialized
+    inited_pkgs: Rc<RefCell<HashSet<PackageKey>>>,
 }
 
 impl<'a> Context<'a> {
@@ -234,6 +631,7 @@ impl<'a> Context<'a> {
         gcv: &'a GcoVec,
         ffi_factory: &'a FfiFactory,
         fs: Option<&'a FileSet>,
+        timers: Timers,
     ) -> Context<'a> {
         Context {
             exec: exec,
@@ -241,25 +639,236 @@ impl<'a> Context<'a> {
             gcv: gcv,
             ffi_factory: ffi_factory,
             fs: fs,
-            next_id: Cell::new(0),
+            timers: timers,
+            memos: Memos::new(),
+            next_id: Rc::new(Cell::new(0)),
+            crash: Rc::new(Cell::new(None)),
+            exit_code: Rc::new(Cell::new(None)),
+            panicked: Rc::new(RefCell::new(None)),
+            callback_panic: Rc::new(Cell::new(None)),
+            blocked: Rc::new(RefCell::new(BTreeMap::new())),
+            meter: Rc::new(Meter::new(Limits::default())),
+            observer: None,
+            cast_audit: None,
+            alloc_profile: None,
+            unsafe_addrs: Rc::new(RefCell::new(HashMap::new())),
+            inited_pkgs: Rc::new(RefCell::new(HashSet::new())),
         }
     }
 
+    /// Converts p to a uintptr, its address
+    fn unsafe_ptr_addr(&self, p: &UnsafePtrObj) -> usize {
+        let addr = p.addr();
+        self.unsafe_addrs.borrow_mut().insert(addr, p.clone());
+        addr
+    }
+
+    /// Converts the uintptr addr back to the unsafe pointer it was
+    fn unsafe_ptr_at(&self, addr: usize) -> RuntimeResult<GosValue> {
+        if addr == 0 {
+            return Ok(GosValue::new_nil(ValueType::UnsafePtr));
+        }
+        self.unsafe_addrs
+            .borrow()
+            .get(&addr)
+            .cloned()
+            .map(GosValue::with_unsafe_ptr_obj)
+            .ok_or_else(|| format!("invalid uintptr {:#x} converted to unsafe.Pointer", addr))
+    }
+
     fn new_entry_frame(&self, entry: FunctionKey) -> CallFrame {
         let cls = GosValue::new_closure_static(entry, &self.code.objects.functions);
         CallFrame::with_closure(cls.as_closure().unwrap().0.clone(), 0)
     }
 
     fn spawn_fiber(&self, stack: Stack, first_frame: CallFrame) {
-        let mut f = Fiber::new(self.clone(), stack, first_frame);
+        self.spawn(Fiber::new(self.clone(), stack, first_frame));
+    }
+
+    fn spawn(&self, f: Fiber<'a>) {
+        let crash = self.crash.clone();
         self.exec
             .spawn(async move {
                 // let parent fiber go first
                 future::yield_now().await;
-                f.main_loop().await;
+                Context::run_fiber(f, crash).await;
             })
             .detach();
     }
+
+    /// Awaits `fut` for the fiber `id` with `frames`, which is recorded as
+    /// blocked on `wait` while `fut` is pending. Nothing is recorded if it's
+    /// ready at once.
+    async fn blocking<T>(
+        &self,
+        id: usize,
+        wait: &str,
+        frames: &[CallFrame],
+        fut: impl Future<Output = T>,
+    ) -> T {
+        futures_lite::pin!(fut);
+        let mut guard = None;
+        future::poll_fn(|cx| {
+            let poll = fut.as_mut().poll(cx);
+            if poll.is_pending() && guard.is_none() {
+                let calls = frames.iter().rev().map(|f| (f.func(), f.pc - 1)).collect();
+                self.blocked.borrow_mut().insert(
+                    id,
+                    BlockedFiber {
+                        wait: wait.to_owned(),
+                        calls: calls,
+                    },
+                );
+                guard = Some(BlockedGuard {
+                    blocked: self.blocked.clone(),
+                    id: id,
+                });
+            }
+            poll
+        })
+        .await
+    }
+
+    /// The fibers blocked, for the report of a deadlock
+    fn blocked_goroutines(&self) -> Vec<BlockedGoroutine> {
+        let objs = &self.code.objects;
+        self.blocked
+            .borrow()
+            .iter()
+            .map(|(id, b)| BlockedGoroutine {
+                id: id + 1,
+                wait: b.wait.clone(),
+                stack: b
+                    .calls
+                    .iter()
+                    .filter_map(|(f, pc)| StackFrame::new(*f, *pc, objs, self.fs))
+                    .collect(),
+            })
+            .collect()
+    }
+
+    async fn run_fiber(mut f: Fiber<'a>, crash: Rc<Cell<Option<Box<dyn Any + Send>>>>) {
+        if let Err(e) = AssertUnwindSafe(f.main_loop()).catch_unwind().await {
+            crash.set(Some(e));
+        }
+    }
+
+    /// Pushes a call of the Go closure f with args on stack, like PRE_CALL
+    /// and CALL do, and returns its frame and how many results it has
+    fn push_closure_call(
+        &self,
+        f: &GosValue,
+        args: Vec<GosValue>,
+        stack: &Rc<RefCell<Stack>>,
+    ) -> RuntimeResult<(CallFrame, usize)> {
+        if f.typ() != ValueType::Closure {
+            return Err(format!("cannot call non-function {}", f));
+        }
+        let gosc = match f.as_closure() {
+            Some((ClosureObj::Gos(gosc), _)) => gosc,
+            Some((ClosureObj::Ffi(_), _)) => {
+                return Err("cannot call an FFI function back".to_owned())
+            }
+            None => return Err("call of nil function".to_owned()),
+        };
+        let func = &self.code.objects.functions[gosc.func];
+        let recv = gosc.recv.is_some() as usize;
+        if args.len() + recv != func.param_count() {
+            return Err(format!(
+                "function takes {} arguments, found {}",
+                func.param_count() - recv,
+                args.len()
+            ));
+        }
+        let mut s = stack.borrow_mut();
+        let base = s.len();
+        s.append_vec(func.ret_zeros.clone());
+        if let Some(r) = &gosc.recv {
+            s.push(r.clone());
+        }
+        s.append_vec(args);
+        drop(s);
+        let mut frame = CallFrame::with_closure(ClosureObj::Gos(gosc.clone()), base);
+        frame.set_var_ptrs(gosc, func, stack);
+        Ok((frame, func.ret_count()))
+    }
+
+    /// Runs the Go closure f to its end for the FFI function the fiber of
+    /// stack is calling, the other goroutines run meanwhile. The closure
+    /// may use the variables of the caller, so it runs on top of its stack.
+    fn call_closure(
+        &self,
+        stack: &Rc<RefCell<Stack>>,
+        f: &GosValue,
+        args: Vec<GosValue>,
+    ) -> RuntimeResult<Vec<GosValue>> {
+        let (frame, ret_count) = self.push_closure_call(f, args, stack)?;
+        let base = frame.stack_base;
+        let mut fiber = Fiber::with_stack(self.clone(), stack.clone(), frame);
+        let end = Rc::new(Cell::new(None));
+        fiber.callback_end = Some(end.clone());
+        let task = self
+            .exec
+            .spawn(Context::run_fiber(fiber, self.crash.clone()));
+        let result = loop {
+            self.timers.fire_due();
+            let ticked = self.exec.try_tick();
+            if self.exit_code.get().is_some() {
+                break Err("the program exited".to_owned());
+            }
+            if let Some(r) = end.take() {
+                break r;
+            }
+            if let Some(e) = self.crash.take() {
+                // for GosVM::run to raise again
+                self.crash.set(Some(e));
+                break Err("a goroutine crashed".to_owned());
+            }
+            if self.panicked.borrow().is_some() {
+                break Err("a goroutine panicked".to_owned());
+            }
+            if let Some(limit) = self.meter.exceeded() {
+                break Err(limit.to_string());
+            }
+            if !ticked && !self.timers.park_until(self.meter.deadline()) {
+                break Err("all goroutines are asleep - deadlock!".to_owned());
+            }
+        };
+        // stops the closure if it has not returned
+        drop(task);
+        let mut s = stack.borrow_mut();
+        let len = s.len();
+        let mut rets = s.pop_value_n(len - base);
+        result.map(|_| {
+            rets.truncate(ret_count);
+            rets
+        })
+    }
+
+    /// Starts a goroutine running the Go closure f with args
+    fn spawn_closure(&self, f: &GosValue, args: Vec<GosValue>) -> RuntimeResult<()> {
+        let stack = Rc::new(RefCell::new(Stack::new()));
+        let (frame, _) = self.push_closure_call(f, args, &stack)?;
+        self.spawn(Fiber::with_stack(self.clone(), stack, frame));
+        Ok(())
+    }
+}
+
+/// Calls Go closures back for an FFI function, see FfiCallCtx::call_closure
+struct FiberCallbacks<'a, 'b> {
+    context: &'b Context<'a>,
+    // the stack of the fiber calling the FFI function
+    stack: &'b Rc<RefCell<Stack>>,
+}
+
+impl<'a, 'b> ClosureCaller for FiberCallbacks<'a, 'b> {
+    fn call(&self, f: &GosValue, args: Vec<GosValue>) -> RuntimeResult<Vec<GosValue>> {
+        self.context.call_closure(self.stack, f, args)
+    }
+
+    fn spawn(&self, f: &GosValue, args: Vec<GosValue>) -> RuntimeResult<()> {
+        self.context.spawn_closure(f, args)
+    }
 }
 
 pub struct Fiber<'a> {
@@ -269,19 +878,27 @@ pub struct Fiber<'a> {
     next_frames: Vec<CallFrame>,
     context: Context<'a>,
     id: usize,
+    // set if the fiber runs a closure called back by an FFI function, which
+    // gets how it ended instead of the program
+    callback_end: Option<Rc<Cell<Option<RuntimeResult<()>>>>>,
 }
 
 impl<'a> Fiber<'a> {
     fn new(c: Context<'a>, stack: Stack, first_frame: CallFrame) -> Fiber<'a> {
+        Fiber::with_stack(c, Rc::new(RefCell::new(stack)), first_frame)
+    }
+
+    fn with_stack(c: Context<'a>, stack: Rc<RefCell<Stack>>, first_frame: CallFrame) -> Fiber<'a> {
         let id = c.next_id.get();
         c.next_id.set(id + 1);
         Fiber {
-            stack: Rc::new(RefCell::new(stack)),
+            stack: stack,
             rstack: RangeStack::new(),
             frames: vec![first_frame],
             next_frames: Vec::new(),
             context: c,
             id: id,
+            callback_end: None,
         }
     }
 
@@ -305,14 +922,37 @@ impl<'a> Fiber<'a> {
         let mut stack_base = frame.stack_base;
         let mut frame_height = self.frames.len();
 
+        let mut recorder = ctx
+            .observer
+            .as_ref()
+            .map(|o| StepRecorder::new(o.clone(), self.id));
+        let cast_audit = ctx.cast_audit.as_deref();
+        let alloc_profile = ctx.alloc_profile.as_deref();
+
         let mut total_inst = 0;
         //let mut stats: HashMap<Opcode, usize> = HashMap::new();
+        let mut panic: Option<PanicData> = None;
         loop {
+            let expired = match panic {
+                None => take_expired_budget(&mut self.frames),
+                Some(_) => None,
+            };
             let mut frame = self.frames.last_mut().unwrap();
+            if let Some(budget) = expired {
+                go_panic!(panic, budget_exceeded(budget, s_meta), frame, code);
+            }
             let mut result: Result = Result::Continue;
-            let mut panic: Option<PanicData> = None;
             let yield_unit = 1024;
-            for _ in 0..yield_unit {
+            let batch_begin = total_inst;
+            while total_inst - batch_begin < yield_unit {
+                if let Some(r) = recorder.as_mut() {
+                    let frames = step_frames(&self.frames, objs, ctx.fs);
+                    r.step(code[frames.last().unwrap().pc], frames, stack);
+                    frame = self.frames.last_mut().unwrap();
+                }
+                if let Some(p) = alloc_profile {
+                    p.set_site(frame.func(), frame.pc);
+                }
                 let inst = code[frame.pc];
                 let inst_op = inst.op();
                 total_inst += 1;
@@ -320,44 +960,6 @@ impl<'a> Fiber<'a> {
                 frame.pc += 1;
                 //dbg!(inst_op);
                 match inst_op {
-                    Opcode::PUSH_CONST => {
-                        let index = inst.imm();
-                        stack.push(consts[index as usize].clone());
-                    }
-                    Opcode::PUSH_NIL => stack.push_nil(inst.t0()),
-                    Opcode::PUSH_FALSE => stack.push_bool(false),
-                    Opcode::PUSH_TRUE => stack.push_bool(true),
-                    Opcode::PUSH_IMM => stack.push_int32_as(inst.imm(), inst.t0()),
-                    Opcode::PUSH_ZERO_VALUE => {
-                        let meta = consts[inst.imm() as usize].as_metadata();
-                        stack.push(meta.zero(&objs.metas, gcv));
-                    }
-                    Opcode::POP => match inst.imm() {
-                        // this looks weired because it used to require ValueType for every pop
-                        // and we may change it back in the future.
-                        1 => {
-                            stack.pop_value();
-                        }
-                        2 => {
-                            stack.pop_value();
-                            stack.pop_value();
-                        }
-                        3 => {
-                            stack.pop_value();
-                            stack.pop_value();
-                            stack.pop_value();
-                        }
-                        _ => unreachable!(),
-                    },
-                    Opcode::LOAD_LOCAL => {
-                        let index = Stack::offset(stack_base, inst.imm());
-                        stack.push_from_index(index); // (index![stack, index]);
-                    }
-                    Opcode::STORE_LOCAL => {
-                        let (rhs_index, index) = inst.imm824();
-                        let s_index = Stack::offset(stack_base, index);
-                        stack.store_local(s_index, rhs_index, inst.t0(), gcv);
-                    }
                     Opcode::LOAD_UPVALUE => {
                         let index = inst.imm();
                         let upvalue = frame.var_ptrs.as_ref().unwrap()[index as usize].clone();
@@ -366,48 +968,11 @@ impl<'a> Fiber<'a> {
                     }
                     Opcode::STORE_UPVALUE => {
                         let (rhs_index, index) = inst.imm824();
+                        check_op_assign!(stack, rhs_index, inst.t0(), panic, s_meta, frame, code);
                         let upvalue = frame.var_ptrs.as_ref().unwrap()[index as usize].clone();
                         stack.store_up_value(&upvalue, rhs_index, inst.t0(), gcv);
                         frame = self.frames.last_mut().unwrap();
                     }
-                    Opcode::LOAD_INDEX => {
-                        let ind = stack.pop_value();
-                        let val = &stack.pop_value();
-                        let result = if inst.t2_as_index() == 0 {
-                            val.load_index(&ind, gcv).and_then(|v| Ok(stack.push(v)))
-                        } else {
-                            stack.push_index_comma_ok(val, &ind, gcv)
-                        };
-                        panic_if_err!(result, panic, frame, code);
-                    }
-                    Opcode::LOAD_INDEX_IMM => {
-                        let val = &stack.pop_value();
-                        let index = inst.imm() as usize;
-                        let result = if inst.t2_as_index() == 0 {
-                            val.load_index_int(index, gcv)
-                                .and_then(|v| Ok(stack.push(v)))
-                        } else {
-                            stack.push_index_comma_ok(val, &GosValue::new_int(index as isize), gcv)
-                        };
-                        panic_if_err!(result, panic, frame, code);
-                    }
-                    Opcode::STORE_INDEX => {
-                        let (rhs_index, index) = inst.imm824();
-                        let s_index = Stack::offset(stack.len(), index);
-                        let key = stack.get(s_index + 1);
-                        let target = &stack.get(s_index);
-                        let result = stack.store_index(target, &key, rhs_index, inst.t0(), gcv);
-                        panic_if_err!(result, panic, frame, code);
-                    }
-                    Opcode::STORE_INDEX_IMM => {
-                        // the only place we can store the immediate index is t2
-                        let (rhs_index, imm) = inst.imm824();
-                        let index = inst.t2_as_index();
-                        let s_index = Stack::offset(stack.len(), index);
-                        let target = &stack.get(s_index);
-                        let result = stack.store_index_int(target, imm, rhs_index, inst.t0(), gcv);
-                        panic_if_err!(result, panic, frame, code);
-                    }
                     Opcode::LOAD_STRUCT_FIELD => {
                         let (struct_, index) = get_struct_and_index(
                             inst.imm(),
@@ -421,7 +986,7 @@ impl<'a> Fiber<'a> {
                             Ok(t) => {
                                 stack.push(t.as_struct().0.borrow_fields()[index].clone());
                             }
-                            Err(e) => go_panic_str!(panic, &e, frame, code),
+                            Err(e) => go_panic_str!(panic, s_meta, &e, frame, code),
                         }
                     }
                     Opcode::BIND_METHOD => {
@@ -437,17 +1002,20 @@ impl<'a> Fiber<'a> {
                         ));
                     }
                     Opcode::BIND_INTERFACE_METHOD => {
-                        let val = stack.pop_interface().unwrap();
                         let index = inst.imm() as usize;
-                        match bind_method(&val, index, stack, objs, gcv) {
+                        let result = stack
+                            .pop_some_interface()
+                            .and_then(|val| bind_method(&val, index, stack, objs, gcv));
+                        match result {
                             Ok(cls) => stack.push(cls),
                             Err(e) => {
-                                go_panic_str!(panic, &e, frame, code);
+                                go_panic_str!(panic, s_meta, &e, frame, code);
                             }
                         }
                     }
                     Opcode::STORE_STRUCT_FIELD => {
                         let (rhs_index, imm) = inst.imm824();
+                        check_op_assign!(stack, rhs_index, inst.t0(), panic, s_meta, frame, code);
                         let index = inst.t2_as_index();
                         let s_index = Stack::offset(stack.len(), index);
                         let (struct_, index) = get_struct_and_index(
@@ -463,7 +1031,7 @@ impl<'a> Fiber<'a> {
                                 let field = &mut target.as_struct().0.borrow_fields_mut()[index];
                                 stack.store_val(field, rhs_index, inst.t0(), gcv);
                             }
-                            Err(e) => go_panic_str!(panic, &e, frame, code),
+                            Err(e) => go_panic_str!(panic, s_meta, &e, frame, code),
                         }
                     }
                     Opcode::LOAD_PKG_FIELD => {
@@ -487,36 +1055,62 @@ impl<'a> Fiber<'a> {
                     }
                     Opcode::STORE_PKG_FIELD => {
                         let (rhs_index, imm) = inst.imm824();
+                        check_op_assign!(stack, rhs_index, inst.t0(), panic, s_meta, frame, code);
                         let pkg = &objs.packages[read_imm_key!(code, frame, objs)];
                         stack.store_val(&mut pkg.member_mut(imm), rhs_index, inst.t0(), gcv);
                     }
                     Opcode::STORE_DEREF => {
                         let (rhs_index, index) = inst.imm824();
+                        check_op_assign!(stack, rhs_index, inst.t0(), panic, s_meta, frame, code);
                         let s_index = Stack::offset(stack.len(), index);
                         let p = stack.get(s_index).clone();
                         let result = p.as_some_pointer().and_then(|p| {
                             stack.store_to_pointer(p, rhs_index, inst.t0(), &objs.packages, gcv)
                         });
-                        panic_if_err!(result, panic, frame, code);
+                        panic_if_err!(result, panic, s_meta, frame, code);
                     }
                     Opcode::CAST => {
+                        if let Some(audit) = cast_audit {
+                            audit.record(frame.func(), frame.pc - 1);
+                        }
                         let (target, mapping) = inst.imm824();
                         let index = Stack::offset(stack.len(), target);
                         let from_type = inst.t1();
                         let to_type = inst.t0();
                         match to_type {
+                            // the count of an op-assign shift
+                            ValueType::Uint32 if inst.t2() == ValueType::FlagA => {
+                                let v = stack.get_mut(index);
+                                match v.data().as_shift_count(from_type) {
+                                    Ok(count) => *v = GosValue::new_uint32(count),
+                                    Err(e) => go_panic_str!(panic, s_meta, &e, frame, code),
+                                }
+                            }
                             ValueType::UintPtr => match from_type {
                                 ValueType::UnsafePtr => {
                                     let up = stack.pop_unsafe_ptr();
-                                    stack.push(GosValue::new_uint_ptr(
-                                        up.map_or(0, |x| x.as_rust_ptr() as *const () as usize),
-                                    ));
+                                    let addr = up.map_or(0, |x| ctx.unsafe_ptr_addr(&x));
+                                    stack.push(GosValue::new_uint_ptr(addr));
                                 }
                                 _ => stack.get_mut(index).cast_copyable(from_type, to_type),
                             },
+                            ValueType::Complex64 | ValueType::Complex128 => {
+                                let v = stack.get(index).cast_complex(from_type, to_type);
+                                stack.set(index, v);
+                            }
                             _ if to_type.copyable() => {
                                 stack.get_mut(index).cast_copyable(from_type, to_type);
                             }
+                            ValueType::Interface if from_type == ValueType::Interface => {
+                                let want = &ifaces[mapping as usize].0;
+                                let from = stack.get(index).clone();
+                                // a nil interface stays nil
+                                match type_assert(&from, want, objs, gcv) {
+                                    Ok(Some(val)) => stack.set(index, val),
+                                    Ok(None) => {}
+                                    Err(e) => go_panic_str!(panic, s_meta, &e, frame, code),
+                                }
+                            }
                             ValueType::Interface => {
                                 let binding = ifaces[mapping as usize].clone();
                                 let under = stack.copy_semantic(index, gcv);
@@ -535,7 +1129,11 @@ impl<'a> Fiber<'a> {
                                                     .0
                                                     .as_rust_slice()
                                                     .iter()
-                                                    .map(|x| char_from_i32(x.cell.get() as i32))
+                                                    .map(|x| {
+                                                        char_or_replacement(
+                                                            x.cell.get() as i32 as i128
+                                                        )
+                                                    })
                                                     .collect(),
                                                 None => "".to_owned(),
                                             };
@@ -548,11 +1146,8 @@ impl<'a> Fiber<'a> {
                                         _ => unreachable!(),
                                     },
                                     _ => {
-                                        let val = stack.get_mut(index);
-                                        val.cast_copyable(from_type, ValueType::Uint32);
-                                        GosValue::with_str(
-                                            &char_from_u32(*val.as_uint32()).to_string(),
-                                        )
+                                        let i = stack.get(index).data().as_i128(from_type);
+                                        GosValue::with_str(&char_or_replacement(i).to_string())
                                     }
                                 };
                                 stack.set(index, result);
@@ -561,9 +1156,9 @@ impl<'a> Fiber<'a> {
                                 let from = stack.get_string(index);
                                 let result = match inst.t2() {
                                     ValueType::Int32 => {
-                                        let data = StrUtil::as_str(from)
-                                            .chars()
-                                            .map(|x| GosValue::new_int32(x as i32))
+                                        let bytes = unsafe { from.as_raw_slice::<u8>() };
+                                        let data = StringEnumIter::new(&bytes)
+                                            .map(|(_, r)| GosValue::new_int32(r as i32))
                                             .collect();
                                         GosValue::slice_with_data(data, inst.t2(), gcv)
                                     }
@@ -589,12 +1184,14 @@ impl<'a> Fiber<'a> {
                                                         Ok(p) => stack
                                                             .set(index, GosValue::new_pointer(p)),
                                                         Err(e) => {
-                                                            go_panic_str!(panic, &e, frame, code)
+                                                            go_panic_str!(
+                                                                panic, s_meta, &e, frame, code
+                                                            )
                                                         }
                                                     };
                                                 }
                                                 None => {
-                                                    go_panic_str!(panic, "only a unsafe-pointer cast from a pointer can be cast back to a pointer", frame, code);
+                                                    go_panic_str!(panic, s_meta, "only a unsafe-pointer cast from a pointer can be cast back to a pointer", frame, code);
                                                 }
                                             }
                                         }
@@ -603,60 +1200,62 @@ impl<'a> Fiber<'a> {
                                         }
                                     };
                                 }
-                                _ => unimplemented!(),
+                                _ => {
+                                    let e = cast_err(from_type, to_type);
+                                    go_panic_str!(panic, s_meta, &e, frame, code);
+                                }
                             },
                             ValueType::UnsafePtr => match from_type {
                                 ValueType::Pointer => {
                                     let h = PointerHandle::new(stack.get(index));
                                     stack.set(index, h);
                                 }
-                                _ => unimplemented!(),
+                                ValueType::UintPtr => {
+                                    let addr = *stack.get(index).as_uint_ptr();
+                                    match ctx.unsafe_ptr_at(addr) {
+                                        Ok(p) => stack.set(index, p),
+                                        Err(e) => go_panic_str!(panic, s_meta, &e, frame, code),
+                                    }
+                                }
+                                _ => {
+                                    let e = cast_err(from_type, to_type);
+                                    go_panic_str!(panic, s_meta, &e, frame, code);
+                                }
                             },
                             _ => {
-                                dbg!(to_type);
-                                unimplemented!()
+                                let e = cast_err(from_type, to_type);
+                                go_panic_str!(panic, s_meta, &e, frame, code);
                             }
                         }
                     }
-                    Opcode::ADD => stack.add(inst.t0()),
-                    Opcode::SUB => stack.sub(inst.t0()),
-                    Opcode::MUL => stack.mul(inst.t0()),
-                    Opcode::QUO => stack.quo(inst.t0()),
-                    Opcode::REM => stack.rem(inst.t0()),
-                    Opcode::AND => stack.and(inst.t0()),
-                    Opcode::OR => stack.or(inst.t0()),
-                    Opcode::XOR => stack.xor(inst.t0()),
-                    Opcode::AND_NOT => stack.and_not(inst.t0()),
-                    Opcode::SHL => stack.shl(inst.t0(), inst.t1()),
-                    Opcode::SHR => stack.shr(inst.t0(), inst.t1()),
-                    Opcode::UNARY_ADD => {}
-                    Opcode::UNARY_SUB => stack.unary_negate(inst.t0()),
-                    Opcode::UNARY_XOR => stack.unary_xor(inst.t0()),
-                    Opcode::NOT => stack.logical_not(inst.t0()),
-                    Opcode::EQL => stack.compare_eql(inst.t0(), inst.t1()),
-                    Opcode::LSS => stack.compare_lss(inst.t0()),
-                    Opcode::GTR => stack.compare_gtr(inst.t0()),
-                    Opcode::NEQ => stack.compare_neq(inst.t0()),
-                    Opcode::LEQ => stack.compare_leq(inst.t0()),
-                    Opcode::GEQ => stack.compare_geq(inst.t0()),
                     Opcode::SEND => {
                         let val = stack.pop_value();
                         let chan = stack.pop_channel();
                         drop(stack_mut_ref);
                         let re = match chan {
-                            Some(c) => c.send(&val).await,
-                            None => loop {
-                                future::yield_now().await;
-                            },
+                            Some(c) => {
+                                ctx.blocking(self.id, "chan send", &self.frames, c.send(&val))
+                                    .await
+                            }
+                            // a nil channel blocks forever
+                            None => {
+                                let wait = "chan send (nil chan)";
+                                ctx.blocking(self.id, wait, &self.frames, future::pending())
+                                    .await
+                            }
                         };
+                        frame = self.frames.last_mut().unwrap();
                         restore_stack_ref!(self, stack, stack_mut_ref);
-                        panic_if_err!(re, panic, frame, code);
+                        panic_if_err!(re, panic, s_meta, frame, code);
                     }
                     Opcode::RECV => {
                         match stack.pop_channel() {
                             Some(chan) => {
                                 drop(stack_mut_ref);
-                                let val = chan.recv().await;
+                                let val = ctx
+                                    .blocking(self.id, "chan receive", &self.frames, chan.recv())
+                                    .await;
+                                frame = self.frames.last_mut().unwrap();
                                 restore_stack_ref!(self, stack, stack_mut_ref);
                                 let (unwrapped, ok) = unwrap_recv_val!(chan, val, gcv);
                                 stack.push(unwrapped);
@@ -664,9 +1263,14 @@ impl<'a> Fiber<'a> {
                                     stack.push(GosValue::new_bool(ok));
                                 }
                             }
-                            None => loop {
-                                future::yield_now().await;
-                            },
+                            None => {
+                                drop(stack_mut_ref);
+                                let wait = "chan receive (nil chan)";
+                                ctx.blocking(self.id, wait, &self.frames, future::pending::<()>())
+                                    .await;
+                                frame = self.frames.last_mut().unwrap();
+                                restore_stack_ref!(self, stack, stack_mut_ref);
+                            }
                         };
                     }
                     Opcode::REF => {
@@ -679,6 +1283,13 @@ impl<'a> Fiber<'a> {
                         let upvalue = frame.var_ptrs.as_ref().unwrap()[index as usize].clone();
                         stack.push(GosValue::new_pointer(PointerObj::UpVal(upvalue.clone())));
                     }
+                    Opcode::REF_LOCAL => {
+                        // not registered with the frame, the pointer is gone
+                        // before the frame is
+                        let desc = ValueDesc::new(frame.func(), inst.imm(), inst.t0(), false)
+                            .clone_with_stack(Rc::downgrade(&self.stack), stack_base as OpIndex);
+                        stack.push(GosValue::new_pointer(PointerObj::UpVal(UpValue::new(desc))));
+                    }
                     Opcode::REF_SLICE_MEMBER => {
                         let i = stack.pop_int() as OpIndex;
                         let typ = inst.t0();
@@ -686,7 +1297,7 @@ impl<'a> Fiber<'a> {
                         match PointerObj::new_slice_member(arr_or_slice, i, typ, inst.t2()) {
                             Ok(p) => stack.push(GosValue::new_pointer(p)),
                             Err(e) => {
-                                go_panic_str!(panic, &e, frame, code)
+                                go_panic_str!(panic, s_meta, &e, frame, code)
                             }
                         }
                     }
@@ -706,7 +1317,7 @@ impl<'a> Fiber<'a> {
                                     index as OpIndex,
                                 )));
                             }
-                            Err(e) => go_panic_str!(panic, &e, frame, code),
+                            Err(e) => go_panic_str!(panic, s_meta, &e, frame, code),
                         }
                     }
                     Opcode::REF_PKG_MEMBER => {
@@ -719,7 +1330,7 @@ impl<'a> Fiber<'a> {
                     Opcode::DEREF => {
                         let boxed = stack.pop_value();
                         let re = deref_value(&boxed, stack, objs).and_then(|v| Ok(stack.push(v)));
-                        panic_if_err!(re, panic, frame, code);
+                        panic_if_err!(re, panic, s_meta, frame, code);
                     }
                     Opcode::PRE_CALL => {
                         let cls = &stack.pop_closure().unwrap().0;
@@ -751,24 +1362,7 @@ impl<'a> Fiber<'a> {
                         match cls {
                             ClosureObj::Gos(gosc) => {
                                 let nfunc = &objs.functions[gosc.func];
-                                if let Some(uvs) = &gosc.uvs {
-                                    let mut ptrs: Vec<UpValue> =
-                                        Vec::with_capacity(nfunc.up_ptrs.len());
-                                    for (i, p) in nfunc.up_ptrs.iter().enumerate() {
-                                        ptrs.push(if p.is_up_value {
-                                            uvs[&i].clone()
-                                        } else {
-                                            // local pointers
-                                            let uv = UpValue::new(p.clone_with_stack(
-                                                Rc::downgrade(&self.stack),
-                                                nframe.stack_base as OpIndex,
-                                            ));
-                                            nframe.add_referred_by(p.index, p.typ, &uv);
-                                            uv
-                                        });
-                                    }
-                                    nframe.var_ptrs = Some(ptrs);
-                                }
+                                nframe.set_var_ptrs(&gosc, nfunc, &self.stack);
                                 match call_style {
                                     ValueType::Void => {
                                         // default call
@@ -799,7 +1393,10 @@ impl<'a> Fiber<'a> {
                                             frame: nframe,
                                             vec: v,
                                         };
-                                        frame.defer_stack.get_or_insert(vec![]).push(deferred);
+                                        frame
+                                            .defer_stack
+                                            .get_or_insert_with(Default::default)
+                                            .push(deferred);
                                     }
                                     _ => unreachable!(),
                                 }
@@ -807,30 +1404,59 @@ impl<'a> Fiber<'a> {
                             ClosureObj::Ffi(ffic) => {
                                 let ptypes = &objs.metas[ffic.meta.key].as_signature().params_type;
                                 let params = stack.pop_value_n(ptypes.len());
-                                // release stack so that code in ffi can yield
+                                // release stack so that code in ffi can yield and
+                                // closures called back can run on top of it, the
+                                // pointers to it are loaded through the RefCell
                                 drop(stack_mut_ref);
+                                let mut ffi_stack = Stack::new();
                                 let returns = {
+                                    let frames = &self.frames;
+                                    let trace = || backtrace(frames, objs, ctx.fs);
+                                    let callbacks = FiberCallbacks {
+                                        context: &self.context,
+                                        stack: &self.stack,
+                                    };
                                     let mut ctx = FfiCallCtx {
                                         func_name: &ffic.func_name,
                                         vm_objs: objs,
-                                        stack: &mut self.stack.borrow_mut(),
+                                        stack: &mut ffi_stack,
                                         gcv: gcv,
                                         statics: self.context.ffi_factory.statics(),
+                                        timers: &self.context.timers,
+                                        exit_code: &self.context.exit_code,
+                                        backtrace: &trace,
+                                        callbacks: &callbacks,
                                     };
                                     let fut = ffic.ffi.call(&mut ctx, params);
-                                    fut.await
+                                    let wait = &ffic.func_name;
+                                    self.context.blocking(self.id, wait, frames, fut).await
                                 };
+                                frame = self.frames.last_mut().unwrap();
                                 restore_stack_ref!(self, stack, stack_mut_ref);
-                                match returns {
-                                    Ok(result) => stack.append_vec(result),
-                                    Err(e) => {
-                                        go_panic_str!(panic, &e, frame, code);
+                                if self.context.exit_code.get().is_some() {
+                                    result = Result::End;
+                                    break;
+                                }
+                                let callback_panic = self.context.callback_panic.take();
+                                match (returns, callback_panic) {
+                                    (Ok(result), _) => stack.append_vec(result),
+                                    (Err(_), Some(val)) => {
+                                        go_panic!(panic, val, frame, code);
+                                    }
+                                    (Err(e), None) => {
+                                        go_panic_str!(panic, s_meta, &e, frame, code);
                                     }
                                 }
                             }
                         }
                     }
                     Opcode::RETURN => {
+                        if panic.is_none() && frame.budget.is_some() {
+                            if let Some(budget) = frame.take_expired_budget(Instant::now()) {
+                                go_panic!(panic, budget_exceeded(budget, s_meta), frame, code);
+                                continue;
+                            }
+                        }
                         //dbg!(stack.len());
                         //for s in stack.iter() {
                         //    dbg!(GosValueDebug::new(&s, &objs));
@@ -839,17 +1465,23 @@ impl<'a> Fiber<'a> {
                         let clear_stack = match inst.t0() {
                             // default case
                             ValueType::Void => true,
-                            // init_package func
-                            ValueType::FlagA => {
-                                let index = inst.imm() as usize;
-                                let pkey = pkgs[index];
-                                let pkg = &objs.packages[pkey];
-                                // the var values left on the stack are for pkg members
-                                pkg.init_vars(stack);
-                                false
-                            }
+                            // init_package func, the vars are already stored in
+                            // the package
+                            ValueType::FlagA => true,
                             // func with deferred calls
                             ValueType::FlagB => {
+                                // discard what the last deferred call returned, the
+                                // pending ones have their return slots on the stack
+                                let pending: usize = frame.defer_stack.as_ref().map_or(0, |x| {
+                                    x.iter().map(|c| c.frame.func_val(objs).ret_count()).sum()
+                                });
+                                let expected = stack_base
+                                    + func.ret_count()
+                                    + func.stack_temp_types.len()
+                                    + pending;
+                                if stack.len() > expected {
+                                    stack.discard_n(stack.len() - expected);
+                                }
                                 if let Some(call) =
                                     frame.defer_stack.as_mut().map(|x| x.pop()).flatten()
                                 {
@@ -878,6 +1510,9 @@ impl<'a> Fiber<'a> {
 
                         let panicking = panic.is_some();
                         if clear_stack {
+                            if let Some(p) = &mut panic {
+                                p.keep_locals(frame.func(), stack, stack_base, objs);
+                            }
                             // println!(
                             //     "current line: {}",
                             //     self.context.fs.unwrap().position(
@@ -885,10 +1520,32 @@ impl<'a> Fiber<'a> {
                             //     )
                             // );
 
+                            if let Some(key) = frame.memo_key.take() {
+                                if !panicking {
+                                    let results = (0..func.ret_count())
+                                        .map(|i| stack.get(stack_base + i).copy_semantic(gcv))
+                                        .collect();
+                                    ctx.memos.insert(frame.func(), *key, results);
+                                }
+                            }
                             frame.on_drop(&stack);
                             if !panicking {
                                 stack.pop_value_n(frame.func_val(objs).stack_temp_types.len());
                             } else {
+                                // the panic may have interrupted a call being set up,
+                                // drop the frame and the values it left behind
+                                while self
+                                    .next_frames
+                                    .last()
+                                    .map_or(false, |f| f.stack_base >= stack_base)
+                                {
+                                    self.next_frames.pop();
+                                }
+                                let expected =
+                                    stack_base + func.ret_count() + func.stack_temp_types.len();
+                                if stack.len() > expected {
+                                    stack.discard_n(stack.len() - expected);
+                                }
                                 stack.discard_n(frame.func_val(objs).stack_temp_types.len());
                             }
                         }
@@ -897,8 +1554,6 @@ impl<'a> Fiber<'a> {
                         self.frames.pop();
                         frame_height -= 1;
                         if self.frames.is_empty() {
-                            dbg!(total_inst);
-
                             result = Result::End;
                             break;
                         }
@@ -915,35 +1570,46 @@ impl<'a> Fiber<'a> {
                         }
                     }
 
-                    Opcode::JUMP => {
-                        frame.pc = Stack::offset(frame.pc, inst.imm());
+                    Opcode::BUDGET => {
+                        let nanos = *consts[inst.imm() as usize].as_int64();
+                        let budget = Duration::from_nanos(nanos as u64);
+                        frame.budget = Some(Box::new((Instant::now() + budget, budget)));
                     }
-                    Opcode::JUMP_IF => {
-                        if stack.pop_bool() {
-                            frame.pc = Stack::offset(frame.pc, inst.imm());
-                        }
-                    }
-                    Opcode::JUMP_IF_NOT => {
-                        if !stack.pop_bool() {
-                            frame.pc = Stack::offset(frame.pc, inst.imm());
-                        }
-                    }
-                    Opcode::SHORT_CIRCUIT_OR => {
-                        if *stack.get_data(stack.len() - 1).as_bool() {
-                            frame.pc = Stack::offset(frame.pc, inst.imm());
-                        } else {
-                            stack.pop_discard_copyable();
+                    Opcode::MEMOIZE => {
+                        let begin = stack_base + func.ret_count();
+                        let args: Vec<GosValue> = (begin..begin + func.param_count())
+                            .map(|i| stack.get(i).copy_semantic(gcv))
+                            .collect();
+                        let capacity = inst.imm() as usize;
+                        match ctx.memos.get(frame.func(), capacity, &args) {
+                            Some(results) => {
+                                for (i, v) in results.iter().enumerate() {
+                                    stack.set(stack_base + i, v.copy_semantic(gcv));
+                                }
+                                // the last instruction is always a RETURN
+                                frame.pc = code.len() - 1;
+                            }
+                            None => frame.memo_key = Some(Box::new(args)),
                         }
                     }
-                    Opcode::SHORT_CIRCUIT_AND => {
-                        if !*stack.get_data(stack.len() - 1).as_bool() {
-                            frame.pc = Stack::offset(frame.pc, inst.imm());
-                        } else {
-                            stack.pop_discard_copyable();
+
+                    Opcode::LOAD_LOCAL_FIELD => {
+                        let next = code[frame.pc];
+                        frame.pc += 1;
+                        let val = stack.get(Stack::offset(stack_base, inst.imm())).clone();
+                        let (struct_, index) =
+                            get_struct_and_index(next.imm(), val, stack, code, frame, objs);
+                        match struct_ {
+                            Ok(t) => {
+                                stack.push(t.as_struct().0.borrow_fields()[index].clone());
+                            }
+                            Err(e) => go_panic_str!(panic, s_meta, &e, frame, code),
                         }
                     }
                     Opcode::SWITCH => {
-                        if stack.switch_cmp(inst.t0(), objs) {
+                        if let Err(e) = check_comparable(stack, inst.t0(), objs) {
+                            go_panic_str!(panic, s_meta, &e, frame, code);
+                        } else if stack.switch_cmp(inst.t0(), objs) {
                             stack.pop_value();
                             frame.pc = Stack::offset(frame.pc, inst.imm());
                         }
@@ -951,40 +1617,38 @@ impl<'a> Fiber<'a> {
                     Opcode::SELECT => {
                         let blocks = inst.imm();
                         let begin = frame.pc - 1;
-                        let mut end = begin + blocks as usize;
-                        let end_code = &code[end - 1];
-                        let default_offset = match end_code.t0() {
-                            ValueType::FlagE => {
-                                end -= 1;
-                                Some(end_code.imm())
-                            }
-                            _ => None,
-                        };
-                        let comms = code[begin..end]
-                            .iter()
-                            .enumerate()
-                            .rev()
-                            .map(|(i, sel_code)| {
-                                let offset = if i == 0 { 0 } else { sel_code.imm() };
-                                let flag = sel_code.t0();
-                                match &flag {
-                                    ValueType::FlagA => {
-                                        let val = stack.pop_value();
-                                        let chan = stack.pop_value();
-                                        channel::SelectComm::Send(chan, val, offset)
-                                    }
-                                    ValueType::FlagB | ValueType::FlagC | ValueType::FlagD => {
-                                        let chan = stack.pop_value();
-                                        channel::SelectComm::Recv(chan, flag, offset)
-                                    }
-                                    _ => unreachable!(),
+                        let end = begin + blocks as usize;
+                        // the default clause can be anywhere and has nothing on the stack
+                        let mut default_offset = None;
+                        let mut comms = Vec::with_capacity(blocks as usize);
+                        for (i, sel_code) in code[begin..end].iter().enumerate().rev() {
+                            let offset = if i == 0 { 0 } else { sel_code.imm() };
+                            let flag = sel_code.t0();
+                            match &flag {
+                                ValueType::FlagA => {
+                                    let val = stack.pop_value();
+                                    let chan = stack.pop_value();
+                                    comms.push(channel::SelectComm::Send(chan, val, offset));
                                 }
-                            })
-                            .collect();
+                                ValueType::FlagB | ValueType::FlagC | ValueType::FlagD => {
+                                    let chan = stack.pop_value();
+                                    comms.push(channel::SelectComm::Recv(chan, flag, offset));
+                                }
+                                ValueType::FlagE => default_offset = Some(offset),
+                                _ => unreachable!(),
+                            }
+                        }
                         let selector = channel::Selector::new(comms, default_offset);
 
                         drop(stack_mut_ref);
-                        let re = selector.select().await;
+                        let wait = match selector.comms.len() {
+                            0 => "select (no cases)",
+                            _ => "select",
+                        };
+                        let re = ctx
+                            .blocking(self.id, wait, &self.frames, selector.select())
+                            .await;
+                        frame = self.frames.last_mut().unwrap();
                         restore_stack_ref!(self, stack, stack_mut_ref);
 
                         match re {
@@ -1018,7 +1682,7 @@ impl<'a> Fiber<'a> {
                                 frame.pc = Stack::offset(frame.pc, (blocks - 1) + block_offset);
                             }
                             Err(e) => {
-                                go_panic_str!(panic, &e, frame, code);
+                                go_panic_str!(panic, s_meta, &e, frame, code);
                             }
                         }
                     }
@@ -1027,13 +1691,47 @@ impl<'a> Fiber<'a> {
                         let target = stack.get(len - 1);
                         let re = self
                             .rstack
-                            .range_init(target, inst.t0(), inst.t2())
+                            .range_init(target, inst.t0(), inst.t2(), (stack_base, frame.pc))
                             .and_then(|_| Ok(stack.pop_value()));
-                        panic_if_err!(re, panic, frame, code);
+                        panic_if_err!(re, panic, s_meta, frame, code);
+                    }
+                    Opcode::RANGE if inst.t0() == ValueType::Channel => {
+                        let owner = (stack_base, frame.pc - 1);
+                        let chan_val = self.rstack.range_channel(owner);
+                        match chan_val.as_channel() {
+                            Some(chan) => {
+                                drop(stack_mut_ref);
+                                let val = ctx
+                                    .blocking(self.id, "chan receive", &self.frames, chan.recv())
+                                    .await;
+                                frame = self.frames.last_mut().unwrap();
+                                restore_stack_ref!(self, stack, stack_mut_ref);
+                                match val {
+                                    Some(v) => {
+                                        stack.push(v);
+                                        // there is no second iteration value
+                                        stack.push_nil(ValueType::Void);
+                                    }
+                                    None => {
+                                        self.rstack.range_done();
+                                        frame.pc = Stack::offset(frame.pc, inst.imm());
+                                    }
+                                }
+                            }
+                            None => {
+                                drop(stack_mut_ref);
+                                let wait = "chan receive (nil chan)";
+                                ctx.blocking(self.id, wait, &self.frames, future::pending::<()>())
+                                    .await;
+                                frame = self.frames.last_mut().unwrap();
+                                restore_stack_ref!(self, stack, stack_mut_ref);
+                            }
+                        }
                     }
                     Opcode::RANGE => {
                         let offset = inst.imm();
-                        if self.rstack.range_body(inst.t0(), inst.t2(), stack) {
+                        let owner = (stack_base, frame.pc - 1);
+                        if self.rstack.range_body(inst.t2(), stack, owner) {
                             frame.pc = Stack::offset(frame.pc, offset);
                         }
                     }
@@ -1041,25 +1739,16 @@ impl<'a> Fiber<'a> {
                     Opcode::TYPE_ASSERT => {
                         let val = stack.pop_value();
                         let do_try = inst.t2_as_index() > 0;
-                        let result = match val.as_some_interface() {
-                            Ok(iface) => match &iface as &InterfaceObj {
-                                InterfaceObj::Gos(v, b) => {
-                                    let meta = b.as_ref().unwrap().0;
-                                    let want_meta = consts[inst.imm() as usize].as_metadata();
-                                    if *want_meta == meta {
-                                        Ok((v.copy_semantic(gcv), true))
-                                    } else {
-                                        if do_try {
-                                            Ok((want_meta.zero(&objs.metas, gcv), false))
-                                        } else {
-                                            Err("interface conversion: wrong type".to_owned())
-                                        }
-                                    }
-                                }
-                                InterfaceObj::Ffi(_) => {
-                                    Err("FFI interface do not support type assertion".to_owned())
+                        let want_meta = consts[inst.imm() as usize].as_metadata();
+                        let result = match type_assert(&val, want_meta, objs, gcv) {
+                            Ok(Some(v)) => Ok((v, true)),
+                            Ok(None) => {
+                                if do_try {
+                                    Ok((want_meta.zero(&objs.metas, gcv), false))
+                                } else {
+                                    Err("interface conversion: wrong type".to_owned())
                                 }
-                            },
+                            }
                             Err(e) => Err(e),
                         };
                         match result {
@@ -1069,66 +1758,55 @@ impl<'a> Fiber<'a> {
                                     stack.push_bool(ok);
                                 }
                             }
-                            Err(e) => go_panic_str!(panic, &e, frame, code),
+                            Err(e) => go_panic_str!(panic, s_meta, &e, frame, code),
                         }
                     }
                     Opcode::TYPE => {
-                        let iface_value = stack.pop_value();
-                        let (val, meta) = match iface_value.as_interface() {
-                            Some(iface) => match &iface as &InterfaceObj {
-                                InterfaceObj::Gos(v, b) => {
-                                    (v.copy_semantic(gcv), b.as_ref().unwrap().0)
-                                }
-                                _ => (iface_value.clone(), s_meta.none),
-                            },
-                            _ => (iface_value, s_meta.none),
-                        };
-                        let typ = meta.value_type(&objs.metas);
-                        stack.push(GosValue::new_metadata(meta));
-                        let option_count = inst.imm() as usize;
-                        if option_count > 0 {
-                            let mut index = None;
-                            for i in 0..option_count {
-                                let inst_data = code[frame.pc + i];
-                                if inst_data.t0() == typ {
-                                    index = Some(inst_data.imm());
-                                }
-                            }
-                            let s_index = Stack::offset(stack_base, index.unwrap());
-                            stack.set(s_index, val);
-
-                            frame.pc += option_count;
+                        let val = stack.pop_value();
+                        let want_meta = consts[inst.imm() as usize].as_metadata();
+                        match type_assert(&val, want_meta, objs, gcv) {
+                            Ok(v) => stack.push_bool(v.is_some()),
+                            Err(e) => go_panic_str!(panic, s_meta, &e, frame, code),
                         }
                     }
                     Opcode::IMPORT => {
                         let pkey = pkgs[inst.imm() as usize];
-                        stack.push(GosValue::new_bool(!objs.packages[pkey].inited()));
+                        // a package is initialized once, however many import it
+                        let first = ctx.inited_pkgs.borrow_mut().insert(pkey);
+                        stack.push(GosValue::new_bool(first));
                     }
                     Opcode::SLICE | Opcode::SLICE_FULL => {
-                        let max = if inst_op == Opcode::SLICE_FULL {
-                            stack.pop_int()
-                        } else {
-                            -1
+                        // the indices may be of any integer type
+                        let max = match inst_op {
+                            Opcode::SLICE_FULL => Some(stack.pop_value().as_index() as isize),
+                            _ => None,
+                        };
+                        let end = stack.pop_value().as_index() as isize;
+                        let end = match inst.t2() {
+                            ValueType::FlagA => None,
+                            _ => Some(end),
                         };
-                        let end = stack.pop_int();
-                        let begin = stack.pop_int();
+                        let begin = stack.pop_value().as_index() as isize;
                         let result = match inst.t0() {
                             ValueType::Slice => {
                                 let s = stack.pop_value();
                                 s.dispatcher_a_s().slice_slice(&s, begin, end, max)
                             }
                             ValueType::String => {
-                                GosValue::slice_string(&stack.pop_value(), begin, end, max)
-                            }
-                            ValueType::Array => {
-                                GosValue::slice_array(stack.pop_value(), begin, end, inst.t1())
+                                GosValue::slice_string(&stack.pop_value(), begin, end)
                             }
+                            ValueType::Array => dispatcher_a_s_for(inst.t1()).slice_array(
+                                stack.pop_value(),
+                                begin,
+                                end,
+                                max,
+                            ),
                             _ => unreachable!(),
                         };
 
                         match result {
                             Ok(v) => stack.push(v),
-                            Err(e) => go_panic_str!(panic, &e, frame, code),
+                            Err(e) => go_panic_str!(panic, s_meta, &e, frame, code),
                         }
                     }
                     Opcode::LITERAL => {
@@ -1205,8 +1883,9 @@ impl<'a> Fiber<'a> {
                                         let (val, typ) = build_val(m);
                                         GosValue::slice_with_data(val, typ, gcv)
                                     }
-                                    MetadataType::Array(m, _) => {
-                                        let (val, typ) = build_val(m);
+                                    MetadataType::Array(m, size) => {
+                                        let (mut val, typ) = build_val(m);
+                                        val.resize(*size, m.zero(&objs.metas, gcv));
                                         GosValue::array_with_data(val, typ, gcv)
                                     }
                                     MetadataType::Map(_, vm) => {
@@ -1218,7 +1897,14 @@ impl<'a> Fiber<'a> {
                                         for _ in 0..count {
                                             let k = stack.pop_value();
                                             let v = stack.pop_value();
-                                            map.0.insert(k, v);
+                                            match check_hashable(&k, stack, objs) {
+                                                Ok(()) => {
+                                                    map.0.insert(k.copy_semantic(gcv), v);
+                                                }
+                                                Err(e) => {
+                                                    go_panic_str!(panic, s_meta, &e, frame, code)
+                                                }
+                                            }
                                         }
                                         map_val
                                     }
@@ -1237,6 +1923,24 @@ impl<'a> Fiber<'a> {
                                     _ => unreachable!(),
                                 }
                             }
+                            ValueType::Array | ValueType::Map => {
+                                // a literal of constants prebuilt by codegen,
+                                // every evaluation shares its data until it's
+                                // written
+                                let val = match arg.typ() {
+                                    ValueType::Map => {
+                                        GosValue::new_map(arg.as_map().unwrap().0.clone(), gcv)
+                                    }
+                                    _ => arg.dispatcher_a_s().array_share(arg, gcv),
+                                };
+                                match inst.t1() {
+                                    ValueType::Slice => {
+                                        let len = val.len() as isize;
+                                        GosValue::slice_array(val, 0, len, arg.t_elem()).unwrap()
+                                    }
+                                    _ => val,
+                                }
+                            }
                             _ => unimplemented!(),
                         };
                         stack.push(new_val);
@@ -1253,36 +1957,54 @@ impl<'a> Fiber<'a> {
                         let i = Stack::offset(stack.len(), index - 1);
                         let meta_val = stack.get(i);
                         let md = meta_val.as_metadata();
-                        let val = match md.mtype_unwraped(&objs.metas) {
+                        let result = match md.mtype_unwraped(&objs.metas) {
                             MetadataType::Slice(vmeta) => {
                                 let (cap, len) = match index {
-                                    -2 => (stack.pop_int() as usize, stack.pop_int() as usize),
+                                    -2 => (stack.pop_int(), stack.pop_int()),
                                     -1 => {
-                                        let len = stack.pop_int() as usize;
+                                        let len = stack.pop_int();
                                         (len, len)
                                     }
                                     _ => unreachable!(),
                                 };
-                                let zero = vmeta.zero(&objs.metas, gcv);
-                                GosValue::slice_with_size(len, cap, &zero, zero.typ(), gcv)
+                                if len < 0 || len as usize > MAX_SLICE_LEN {
+                                    Err("makeslice: len out of range")
+                                } else if cap < len || cap as usize > MAX_SLICE_LEN {
+                                    Err("makeslice: cap out of range")
+                                } else {
+                                    let (len, cap) = (len as usize, cap as usize);
+                                    let zero = vmeta.zero(&objs.metas, gcv);
+                                    Ok(GosValue::slice_with_size(len, cap, &zero, zero.typ(), gcv))
+                                }
                             }
                             MetadataType::Map(_, v) => {
                                 let default = v.zero(&objs.metas, gcv);
-                                GosValue::map_with_default_val(default, gcv)
+                                Ok(GosValue::map_with_default_val(default, gcv))
                             }
                             MetadataType::Channel(_, val_meta) => {
-                                let cap = match index {
-                                    -1 => stack.pop_int() as usize,
+                                let size = match index {
+                                    -1 => stack.pop_int(),
                                     0 => 0,
                                     _ => unreachable!(),
                                 };
-                                let zero = val_meta.zero(&objs.metas, gcv);
-                                GosValue::new_channel(ChannelObj::new(cap, zero))
+                                match size >= 0 && size as usize <= ChannelObj::MAX_CAP {
+                                    true => {
+                                        let zero = val_meta.zero(&objs.metas, gcv);
+                                        let chan = ChannelObj::new(size as usize, zero);
+                                        Ok(GosValue::new_channel(chan))
+                                    }
+                                    false => Err("makechan: size out of range"),
+                                }
                             }
                             _ => unreachable!(),
                         };
-                        stack.pop_value();
-                        stack.push(val);
+                        match result {
+                            Ok(val) => {
+                                stack.pop_value();
+                                stack.push(val);
+                            }
+                            Err(e) => go_panic_str!(panic, s_meta, e, frame, code),
+                        }
                     }
                     Opcode::COMPLEX => {
                         // for the specs: For complex, the two arguments must be of the same
@@ -1357,7 +2079,7 @@ impl<'a> Fiber<'a> {
                         let a = stack.pop_value();
                         match dispatcher_a_s_for(inst.t0()).slice_append(a, b, gcv) {
                             Ok(slice) => stack.push(slice),
-                            Err(e) => go_panic_str!(panic, &e, frame, code),
+                            Err(e) => go_panic_str!(panic, s_meta, &e, frame, code),
                         };
                     }
                     Opcode::COPY => {
@@ -1382,10 +2104,12 @@ impl<'a> Fiber<'a> {
                     }
                     Opcode::DELETE => {
                         let key = &stack.pop_value();
+                        let result = check_hashable(key, stack, objs);
                         match stack.pop_map() {
-                            Some(m) => m.0.delete(key),
-                            None => {}
+                            Some(m) if result.is_ok() => m.0.delete(key),
+                            _ => {}
                         }
+                        panic_if_err!(result, panic, s_meta, frame, code);
                     }
                     Opcode::CLOSE => match stack.pop_channel() {
                         Some(c) => c.close(),
@@ -1403,7 +2127,7 @@ impl<'a> Fiber<'a> {
                     Opcode::ASSERT => {
                         let ok = stack.pop_bool();
                         if !ok {
-                            go_panic_str!(panic, "Opcode::ASSERT: not true!", frame, code);
+                            go_panic_str!(panic, s_meta, "Opcode::ASSERT: not true!", frame, code);
                         }
                     }
                     Opcode::FFI => {
@@ -1428,7 +2152,7 @@ impl<'a> Fiber<'a> {
                                 )))
                             }
                             Err(e) => {
-                                go_panic_str!(panic, &e, frame, code);
+                                go_panic_str!(panic, s_meta, &e, frame, code);
                                 continue;
                             }
                         };
@@ -1437,30 +2161,57 @@ impl<'a> Fiber<'a> {
                         stack.push(v);
                     }
                     Opcode::VOID => unreachable!(),
+                    _ => {
+                        // the observer and the profile see every instruction
+                        let limit = match (&recorder, alloc_profile) {
+                            (None, None) => yield_unit - (total_inst - batch_begin) + 1,
+                            _ => 1,
+                        };
+                        let mut regs = dispatch::Regs {
+                            pc: &mut frame.pc,
+                            stack_base,
+                            consts,
+                            code,
+                            objs,
+                            gcv,
+                        };
+                        let (ran, err) = dispatch::run(stack, inst, &mut regs, limit);
+                        total_inst += ran - 1;
+                        if let Some(e) = err {
+                            go_panic_str!(panic, s_meta, &e, frame, code);
+                        }
+                    }
                 };
                 //dbg!(inst_op, stack.len());
             } //yield unit
             match result {
                 Result::End => {
-                    if let Some(p) = panic {
-                        println!("panic: {}", p.msg);
-                        if let Some(files) = self.context.fs {
-                            for (fkey, pc) in p.call_stack.iter() {
-                                let func = &objs.functions[*fkey];
-                                if let Some(p) = func.pos()[*pc] {
-                                    println!("{}", files.position(p).unwrap_or(FilePos::null()));
-                                } else {
-                                    println!("<no debug info available>");
-                                }
+                    if let Some(r) = recorder.as_mut() {
+                        r.finish(stack);
+                    }
+                    if let Some(end) = &self.callback_end {
+                        // the panic goes on in the goroutine calling back
+                        end.set(Some(match panic.take() {
+                            Some(p) => {
+                                self.context.callback_panic.set(Some(p.msg.clone()));
+                                Err(p.msg.to_string())
                             }
+                            None => Ok(()),
+                        }));
+                    } else if let Some(mut p) = panic.take() {
+                        // the first unrecovered panic ends the program
+                        let mut panicked = self.context.panicked.borrow_mut();
+                        if panicked.is_none() {
+                            let stack = p.stack_trace(objs, ctx.fs);
+                            *panicked = Some(PanicInfo {
+                                message: p.msg.to_string(),
+                                stack: stack,
+                            });
                         }
+                        drop(panicked);
 
                         // a hack to make the test case fail
-                        let iface = p.msg.as_interface().unwrap();
-                        let val = iface.underlying_value().unwrap();
-                        if val.typ() == ValueType::String
-                            && StrUtil::as_str(val.as_string()).starts_with("Opcode::ASSERT")
-                        {
+                        if p.msg.to_string().starts_with("Opcode::ASSERT") {
                             panic!("ASSERT");
                         }
                     }
@@ -1468,6 +2219,14 @@ impl<'a> Fiber<'a> {
                 }
                 Result::Continue => {
                     drop(stack_mut_ref);
+                    // everything alive is held by the stacks and the objects now,
+                    // so it's safe to collect
+                    if ctx.meter.charge(total_inst - batch_begin, gcv) {
+                        break;
+                    }
+                    if gcv.tick(total_inst - batch_begin) {
+                        gc(gcv);
+                    }
                     future::yield_now().await;
                     restore_stack_ref!(self, stack, stack_mut_ref);
                 }
@@ -1483,45 +2242,322 @@ pub struct GosVM<'a> {
     gcv: GcoVec,
     ffi: &'a FfiFactory,
     fs: Option<&'a FileSet>,
+    observer: Option<Rc<dyn StepObserver>>,
+    cast_audit: Option<Rc<CastAudit>>,
+    alloc_profile: Option<Rc<AllocProfile>>,
+    limits: Limits,
 }
 
 impl<'a> GosVM<'a> {
     pub fn new(bc: ByteCode, ffi: &'a FfiFactory, fs: Option<&'a FileSet>) -> GosVM<'a> {
+        GosVM::with_gcv(bc, GcoVec::new(), ffi, fs)
+    }
+
+    /// Returns a VM running code taken back from another one with
+    /// `into_parts`, the objects of which are collected by `gcv`
+    pub fn with_gcv(
+        bc: ByteCode,
+        gcv: GcoVec,
+        ffi: &'a FfiFactory,
+        fs: Option<&'a FileSet>,
+    ) -> GosVM<'a> {
         GosVM {
             code: bc,
-            gcv: GcoVec::new(),
+            gcv: gcv,
             ffi: ffi,
             fs: fs,
+            observer: None,
+            cast_audit: None,
+            alloc_profile: None,
+            limits: Limits::default(),
         }
     }
 
-    pub fn run(&self) {
+    /// Stops the program when it runs out of one of `limits`
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /// Reports every instruction the program executes to `observer`, see
+    /// the step module
+    pub fn set_step_observer(&mut self, observer: Rc<dyn StepObserver>) {
+        self.observer = Some(observer);
+    }
+
+    /// Counts the conversions the program runs, for `cast_report`
+    pub fn audit_casts(&mut self) {
+        self.cast_audit = Some(Rc::new(CastAudit::new()));
+    }
+
+    /// The conversions run so far if they are audited, the most frequent
+    /// first
+    pub fn cast_report(&self) -> Vec<CastRecord> {
+        self.cast_audit
+            .as_ref()
+            .map_or(vec![], |audit| audit.report(&self.code.objects, self.fs))
+    }
+
+    /// Counts the objects the program allocates, for `alloc_report`
+    pub fn profile_allocs(&mut self) {
+        let profile = Rc::new(AllocProfile::new());
+        self.gcv.set_alloc_profile(Some(profile.clone()));
+        self.alloc_profile = Some(profile);
+    }
+
+    /// The allocations made so far if they are profiled
+    pub fn alloc_report(&self) -> AllocReport {
+        self.alloc_profile
+            .as_ref()
+            .map_or(AllocReport::default(), |p| {
+                p.report(&self.code.objects, self.fs)
+            })
+    }
+
+    /// Takes the code back with the values of its packages, for more code
+    /// to be added to it and run by another VM
+    pub fn into_parts(self) -> (ByteCode, GcoVec) {
+        self.gcv.set_alloc_profile(None);
+        (self.code, self.gcv)
+    }
+
+    pub fn set_gc_config(&self, config: GcConfig) {
+        self.gcv.set_config(config);
+    }
+
+    /// Statistics of the collections run so far
+    pub fn gc_stats(&self) -> GcStats {
+        self.gcv.stats()
+    }
+
+    /// Runs the program, until main returns, the program exits, a
+    /// goroutine panics or all of them are blocked
+    pub fn run(&self) -> RunResult {
         // Init array/slice dispatcher
         dispatcher_a_s_for(ValueType::Uint);
 
         let exec = Rc::new(LocalExecutor::new());
-        let ctx = Context::new(exec.clone(), &self.code, &self.gcv, self.ffi, self.fs);
+        let timers = Timers::new();
+        let mut ctx = Context::new(
+            exec.clone(),
+            &self.code,
+            &self.gcv,
+            self.ffi,
+            self.fs,
+            timers.clone(),
+        );
+        ctx.observer = self.observer.clone();
+        ctx.cast_audit = self.cast_audit.clone();
+        ctx.alloc_profile = self.alloc_profile.clone();
+        ctx.meter = Rc::new(Meter::new(self.limits));
         let entry = ctx.new_entry_frame(self.code.entry);
-        ctx.spawn_fiber(Stack::new(), entry);
+        let main = Fiber::new(ctx.clone(), Stack::new(), entry);
+        let main_done = Rc::new(Cell::new(false));
+        let done = main_done.clone();
+        let crash = ctx.crash.clone();
+        exec.spawn(async move {
+            Context::run_fiber(main, crash).await;
+            done.set(true);
+        })
+        .detach();
 
+        let mut deadlock = None;
         future::block_on(async {
             loop {
-                if !exec.try_tick() {
+                timers.fire_due();
+                let ticked = exec.try_tick();
+                if let Some(e) = ctx.crash.take() {
+                    // the panic of a failed assert is raised again here
+                    if let Some(info) = ctx.panicked.borrow().as_ref() {
+                        println!("{}", info);
+                    }
+                    panic::resume_unwind(e);
+                }
+                if ctx.exit_code.get().is_some() || ctx.panicked.borrow().is_some() {
+                    break;
+                }
+                if ctx.meter.check_time() {
+                    break;
+                }
+                // Once main has returned, neither running goroutines nor
+                // pending timers keep the VM alive.
+                if main_done.get() {
+                    break;
+                }
+                // Nothing is runnable, wait for the next timer if any. If
+                // there is none, main and the goroutines left wait forever.
+                if !ticked && !timers.park_until(ctx.meter.deadline()) {
+                    deadlock = Some(ctx.blocked_goroutines());
                     break;
                 }
             }
         });
+        // the panic is printed like Go does, the embedder gets it too
+        if let Some(info) = ctx.panicked.borrow().as_ref() {
+            println!("{}", info);
+        }
+        if let Some(goroutines) = deadlock.as_ref() {
+            print_deadlock(goroutines);
+        }
+        if let Some(code) = ctx.exit_code.get() {
+            return RunResult::Exit(code);
+        }
+        if let Some(limit) = ctx.meter.exceeded() {
+            return RunResult::LimitExceeded(limit);
+        }
+        match (ctx.panicked.take(), deadlock) {
+            (Some(info), _) => RunResult::Panic(info),
+            (None, Some(goroutines)) => RunResult::Deadlock(goroutines),
+            (None, None) => RunResult::Done,
+        }
     }
 }
 
+/// The frames of a goroutine as a step observer sees them, the pc of the
+/// callers is past the call
+fn step_frames(frames: &[CallFrame], objs: &VMObjects, fs: Option<&FileSet>) -> Vec<StepFrame> {
+    let last = frames.len() - 1;
+    frames
+        .iter()
+        .enumerate()
+        .map(|(i, f)| {
+            let pc = if i == last { f.pc } else { f.pc - 1 };
+            let func = f.func();
+            let fval = &objs.functions[func];
+            let pos = fval.pos().get(pc).copied().flatten();
+            let locals = fval.debug().map_or(vec![], |d| {
+                d.locals_at(pc)
+                    .into_iter()
+                    .map(|l| (l.name.clone(), f.stack_base + l.index as usize))
+                    .collect()
+            });
+            StepFrame {
+                func: func,
+                pc: pc,
+                stack_base: f.stack_base,
+                pos: fs.zip(pos).and_then(|(fs, p)| fs.position(p)),
+                locals: locals,
+            }
+        })
+        .collect()
+}
+
+/// Returns the code point i, or "\uFFFD" if it's not a valid one
 #[inline]
-fn char_from_u32(u: u32) -> char {
-    unsafe { char::from_u32_unchecked(u) }
+fn char_or_replacement(i: i128) -> char {
+    u32::try_from(i)
+        .ok()
+        .and_then(char::from_u32)
+        .unwrap_or(char::REPLACEMENT_CHARACTER)
 }
 
 #[inline]
-fn char_from_i32(i: i32) -> char {
-    unsafe { char::from_u32_unchecked(i as u32) }
+fn cast_err(from: ValueType, to: ValueType) -> String {
+    format!("cannot convert a value of {:?} to {:?}", from, to)
+}
+
+/// Returns the runtime error of dividing by the integer of type t on top of
+/// the stack with op, QUO or REM, if it's zero
+#[inline]
+pub(crate) fn check_divisor(stack: &Stack, op: Opcode, t: ValueType) -> RuntimeResult<()> {
+    let int = matches!(
+        t,
+        ValueType::Int
+            | ValueType::Int8
+            | ValueType::Int16
+            | ValueType::Int32
+            | ValueType::Int64
+            | ValueType::Uint
+            | ValueType::UintPtr
+            | ValueType::Uint8
+            | ValueType::Uint16
+            | ValueType::Uint32
+            | ValueType::Uint64
+    );
+    match (op, int) {
+        (Opcode::QUO | Opcode::REM, true) if stack.get_data(stack.len() - 1).as_index(t) == 0 => {
+            Err("integer divide by zero".to_owned())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Returns the runtime error of comparing the two values of type t on top of
+/// the stack, if they are, or hold, interfaces with the same dynamic type
+/// that is not comparable, e.g. a slice
+#[inline]
+pub(crate) fn check_comparable(stack: &Stack, t: ValueType, objs: &VMObjects) -> RuntimeResult<()> {
+    if !matches!(
+        t,
+        ValueType::Interface | ValueType::Struct | ValueType::Array
+    ) {
+        return Ok(());
+    }
+    let a = stack.get(stack.len() - 2);
+    let b = stack.get(stack.len() - 1);
+    if t == ValueType::Interface {
+        // interfaces of different dynamic types are just not equal
+        let dyn_meta = |v: &GosValue| match v.typ() {
+            ValueType::Interface => match v.as_interface() {
+                Some(InterfaceObj::Gos(_, Some((meta, _)))) => Some(*meta),
+                _ => None,
+            },
+            _ => None,
+        };
+        if dyn_meta(&a).is_none() || dyn_meta(&a) != dyn_meta(&b) {
+            return Ok(());
+        }
+    }
+    match a.uncomparable_iface().or_else(|| b.uncomparable_iface()) {
+        Some(meta) => Err(format!(
+            "comparing uncomparable type {}",
+            ValueFormatter::new(objs, stack).type_string(&meta)
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Returns the runtime error of using key as a map key, if it is, or holds,
+/// an interface with a dynamic type that is not comparable
+#[inline]
+pub(crate) fn check_hashable(key: &GosValue, stack: &Stack, objs: &VMObjects) -> RuntimeResult<()> {
+    match key.uncomparable_iface() {
+        Some(meta) => Err(format!(
+            "hash of unhashable type {}",
+            ValueFormatter::new(objs, stack).type_string(&meta)
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Pushes val[ind] of a LOAD_INDEX, and the comma-ok flag if asked for.
+/// Reading a nil map yields the zero value of its element type, of which the
+/// metadata is the const at the imm of LOAD_INDEX.
+#[inline]
+pub(crate) fn load_index(
+    stack: &mut Stack,
+    val: &GosValue,
+    ind: &GosValue,
+    inst: Instruction,
+    consts: &[GosValue],
+    objs: &VMObjects,
+    gcv: &GcoVec,
+) -> RuntimeResult<()> {
+    let comma_ok = inst.t2_as_index() != 0;
+    if val.typ() == ValueType::Map {
+        check_hashable(ind, stack, objs)?;
+    }
+    if val.typ() == ValueType::Map && val.as_map().is_none() {
+        let elem_meta = consts[inst.imm() as usize].as_metadata();
+        stack.push(elem_meta.zero(&objs.metas, gcv));
+        if comma_ok {
+            stack.push_bool(false);
+        }
+        Ok(())
+    } else if comma_ok {
+        stack.push_index_comma_ok(val, ind, gcv)
+    } else {
+        val.load_index(ind, gcv).map(|v| stack.push(v))
+    }
 }
 
 #[inline]
@@ -1564,18 +2600,19 @@ fn get_struct_and_index(
 }
 
 #[inline]
+/// Returns the embedded field at `indices`, the pointers on the way are
+/// dereferenced
 pub fn get_embeded(
     val: GosValue,
-    indices: &Vec<usize>,
+    indices: &[usize],
     stack: &Stack,
     pkgs: &PackageObjs,
 ) -> RuntimeResult<GosValue> {
-    let typ = val.typ();
     let mut cur_val: GosValue = val;
-    if typ == ValueType::Pointer {
-        cur_val = cur_val.as_some_pointer()?.deref(stack, pkgs)?;
-    }
     for &i in indices.iter() {
+        if cur_val.typ() == ValueType::Pointer {
+            cur_val = cur_val.as_some_pointer()?.deref(stack, pkgs)?;
+        }
         let s = &cur_val.as_struct().0;
         let v = s.borrow_fields()[i].clone();
         cur_val = v;
@@ -1602,6 +2639,115 @@ fn cast_receiver(
     }
 }
 
+/// The receiver of a method promoted from the embedded field at `indices`
+/// of `val`, with a pointer receiver it points to the field in place
+fn embedded_receiver(
+    val: &GosValue,
+    indices: &[usize],
+    ptr_recv: bool,
+    stack: &Stack,
+    objs: &VMObjects,
+    gcv: &GcoVec,
+) -> RuntimeResult<GosValue> {
+    let (&last, path) = indices.split_last().unwrap();
+    let parent = get_embeded(val.clone(), path, stack, &objs.packages)?;
+    let parent = match parent.typ() {
+        ValueType::Pointer => deref_value(&parent, stack, objs)?,
+        _ => parent,
+    };
+    let field = parent.as_struct().0.borrow_fields()[last].clone();
+    match (ptr_recv, field.typ() == ValueType::Pointer) {
+        (true, true) => Ok(field),
+        (true, false) => Ok(GosValue::new_pointer(PointerObj::StructField(
+            parent,
+            last as OpIndex,
+        ))),
+        (false, true) => deref_value(&field, stack, objs).map(|x| x.copy_semantic(gcv)),
+        (false, false) => Ok(field.copy_semantic(gcv)),
+    }
+}
+
+/// Returns the value held by `iface` if its dynamic type is `want`, or None
+/// otherwise. If `want` is an interface type, the value is re-wrapped with the
+/// method bindings of `want` as long as the dynamic type implements it.
+pub fn type_assert(
+    iface: &GosValue,
+    want: &Meta,
+    objs: &VMObjects,
+    gcv: &GcoVec,
+) -> RuntimeResult<Option<GosValue>> {
+    // an untyped nil passed where an interface is expected is not boxed
+    if iface.typ() == ValueType::Void {
+        return Ok(None);
+    }
+    let want_iface = match want.ptr_depth {
+        0 => match &objs.metas[want.underlying(&objs.metas).key] {
+            MetadataType::Interface(fields) => Some(fields),
+            _ => None,
+        },
+        _ => None,
+    };
+    let (v, b) = match iface.as_interface() {
+        Some(i) => match i as &InterfaceObj {
+            InterfaceObj::Gos(v, b) => (v, b),
+            InterfaceObj::Ffi(ffi) => {
+                // FFI objects have no concrete type, but they can be asserted to
+                // interfaces whose methods they have
+                let has = objs.metas[ffi.meta.key].as_interface().mapping();
+                return Ok(match want_iface {
+                    Some(fields) if fields.all().is_empty() => Some(iface.clone()),
+                    Some(fields) if fields.all().iter().all(|f| has.contains_key(&f.name)) => {
+                        let meta = want.underlying(&objs.metas);
+                        let ffi = UnderlyingFfi::new(ffi.ffi_obj.clone(), meta);
+                        Some(GosValue::new_interface(InterfaceObj::Ffi(ffi)))
+                    }
+                    _ => None,
+                });
+            }
+        },
+        None => return Ok(None),
+    };
+    match (want_iface, b) {
+        (Some(fields), _) if fields.all().is_empty() => Ok(Some(iface.clone())),
+        (Some(fields), Some((meta, _))) => {
+            let bindings: Option<Vec<Binding4Runtime>> = fields
+                .all()
+                .iter()
+                .map(|f| {
+                    // the methods with pointer receivers are not in the
+                    // method set of a value
+                    meta.get_iface_binding(&f.name, &objs.metas)
+                        .filter(|x| match x {
+                            IfaceBinding::Struct(m, path) => {
+                                meta.ptr_depth > 0
+                                    || !m.borrow().pointer_recv
+                                    || path
+                                        .as_ref()
+                                        .is_some_and(|p| meta.embeds_pointer(p, &objs.metas))
+                            }
+                            _ => true,
+                        })
+                        .map(|x| x.into())
+                })
+                .collect();
+            Ok(bindings.map(|x| {
+                GosValue::new_interface(InterfaceObj::with_value(v.clone(), Some((*meta, x))))
+            }))
+        }
+        (None, Some((meta, _))) => {
+            // unnamed types, e.g. two interface{} literals,
+            // may have been given different keys
+            let same = want == meta
+                || (want.ptr_depth == meta.ptr_depth
+                    && want.is_type == meta.is_type
+                    && !matches!(&objs.metas[meta.key], MetadataType::Named(..))
+                    && want.identical(meta, &objs.metas));
+            Ok(same.then(|| v.copy_semantic(gcv)))
+        }
+        (_, None) => Ok(None),
+    }
+}
+
 pub fn bind_method(
     iface: &InterfaceObj,
     index: usize,
@@ -1615,11 +2761,9 @@ pub fn bind_method(
             match binding {
                 Binding4Runtime::Struct(func, ptr_recv, indices) => {
                     let obj = match indices {
-                        None => obj.copy_semantic(gcv),
-                        Some(inds) => get_embeded(obj.clone(), inds, stack, &objs.packages)?
-                            .copy_semantic(gcv),
+                        None => cast_receiver(obj.copy_semantic(gcv), *ptr_recv, stack, objs)?,
+                        Some(inds) => embedded_receiver(obj, inds, *ptr_recv, stack, objs, gcv)?,
                     };
-                    let obj = cast_receiver(obj, *ptr_recv, stack, objs)?;
                     let cls = ClosureObj::new_gos(*func, &objs.functions, Some(obj));
                     Ok(GosValue::new_closure(cls, gcv))
                 }